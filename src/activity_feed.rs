@@ -0,0 +1,157 @@
+use crate::config;
+use crate::local_storage::activity_feed::activity_feed_local_storage::ActivityFeedLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::event_log::event_log_local_storage::Provenance;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Entities that show up in the feed, with the fields copied into an entry
+/// so the app can render "user X shipped 12.5 m³" without loading the entity.
+const FEED_ENTITIES: &[(&str, &str, &[&str])] = &[
+    ("location_update", "location", &["partieNr", "contractId"]),
+    (
+        "shipment_update",
+        "shipment",
+        &["quantity", "locationId", "sawmillId"],
+    ),
+    ("contract_update", "contract", &["title"]),
+    ("note_update", "note", &[]),
+    ("photo_update", "photo", &["locationId"]),
+    ("tour_update", "tour", &["date", "driverId"]),
+];
+
+static LAST_PRUNE: LazyLock<Mutex<HashMap<String, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Derives a feed entry from an accepted update. Updates only privileged
+/// users receive are left out, so the feed can be synced to everyone. An
+/// entity's first entry is "created", later ones "updated" or "deleted".
+pub fn record(
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    msg_type: &str,
+    data: &Value,
+    min_role: i64,
+    provenance: &Provenance,
+) {
+    if !config::get().activity_feed.enabled || min_role > 0 {
+        return;
+    }
+    let Some((_, entity_type, fields)) = FEED_ENTITIES
+        .iter()
+        .find(|(feed_type, _, _)| *feed_type == msg_type)
+    else {
+        return;
+    };
+    let Some(entity_id) = data["id"].as_str() else {
+        return;
+    };
+
+    let result = ActivityFeedLocalStorage::new(core_storage).and_then(|storage| {
+        let action = if data["deleted"].as_i64() == Some(1) {
+            "deleted"
+        } else if storage.has_entity(entity_type, entity_id)? {
+            "updated"
+        } else {
+            "created"
+        };
+        let details: serde_json::Map<String, Value> = fields
+            .iter()
+            .filter_map(|field| Some((field.to_string(), data.get(*field)?.clone())))
+            .collect();
+
+        storage.save_activity(&json!({
+            "id": Uuid::new_v4().to_string(),
+            "lastEdit": chrono::Utc::now().timestamp_millis(),
+            "userId": provenance.user_id,
+            "action": action,
+            "entityType": entity_type,
+            "entityId": entity_id,
+            "details": details
+        }))?;
+        prune_if_due(tenant, &storage)
+    });
+    if let Err(e) = result {
+        println!(
+            "Failed to record activity of {} {} in tenant {}: {:?}",
+            entity_type, entity_id, tenant, e
+        );
+    }
+}
+
+fn prune_if_due(tenant: &str, storage: &ActivityFeedLocalStorage) -> rusqlite::Result<()> {
+    let due = LAST_PRUNE.lock().is_ok_and(|mut last_prune| {
+        let due = last_prune
+            .get(tenant)
+            .is_none_or(|pruned| pruned.elapsed() >= PRUNE_INTERVAL);
+        if due {
+            last_prune.insert(tenant.to_string(), Instant::now());
+        }
+        due
+    });
+    if !due {
+        return Ok(());
+    }
+
+    let settings = &config::get().activity_feed;
+    let cutoff =
+        chrono::Utc::now().timestamp_millis() - settings.retention_days as i64 * DAY_MILLIS;
+    let pruned = storage.prune(cutoff, settings.max_entries)?;
+    if pruned > 0 {
+        println!("Pruned {} activity entries of tenant {}", pruned, tenant);
+    }
+    Ok(())
+}
+
+/// Answers activity_fetch: a page of the feed, newest first. The next page
+/// starts at `nextBefore`/`nextBeforeId`, which are null on the last page.
+pub fn fetch(
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let invalid = |message: String| ("VALIDATION_FAILED", message);
+    let before = match data.get("before") {
+        None | Some(Value::Null) => None,
+        Some(before) => Some(
+            before
+                .as_i64()
+                .ok_or_else(|| invalid("before must be a timestamp".to_string()))?,
+        ),
+    };
+    let before_id = data["beforeId"].as_str().unwrap_or("\u{10FFFF}");
+    let limit = match data.get("limit") {
+        None | Some(Value::Null) => DEFAULT_PAGE_SIZE,
+        Some(limit) => limit
+            .as_u64()
+            .filter(|limit| (1..=MAX_PAGE_SIZE as u64).contains(limit))
+            .ok_or_else(|| invalid(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)))?
+            as usize,
+    };
+
+    let mut entries = ActivityFeedLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_page(before, before_id, data["userId"].as_str(), limit + 1))
+        .map_err(|e| {
+            (
+                "ACTIVITY_FAILED",
+                format!("Failed to load activity: {:?}", e),
+            )
+        })?;
+    let has_more = entries.len() > limit;
+    entries.truncate(limit);
+    let last = entries.last().filter(|_| has_more);
+
+    Ok(json!({
+        "entries": entries,
+        "nextBefore": last.map(|entry| entry["lastEdit"].clone()),
+        "nextBeforeId": last.map(|entry| entry["id"].clone()),
+        "retentionDays": config::get().activity_feed.retention_days
+    }))
+}
@@ -0,0 +1,281 @@
+use crate::config;
+use crate::instance_lease;
+use crate::local_storage::activity_report::activity_report_local_storage::ActivityReportLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use crate::timezone::{self, TenantTimezone};
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use serde_json::{Value, json};
+use std::fs;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use warp::http::StatusCode;
+use warp::reply::WithHeader;
+use warp::{Filter, Rejection, Reply};
+
+struct Period {
+    month: String,
+    start: i64,
+    end: i64,
+}
+
+impl Period {
+    fn of(first_day: NaiveDate, timezone: &TenantTimezone) -> Option<Self> {
+        let next = first_day.checked_add_months(Months::new(1))?;
+        Some(Period {
+            month: first_day.format("%Y-%m").to_string(),
+            start: timezone.start_of_day(first_day),
+            end: timezone.start_of_day(next),
+        })
+    }
+
+    fn parse(month: &str, timezone: &TenantTimezone) -> Option<Self> {
+        let first_day = NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").ok()?;
+        Period::of(first_day, timezone)
+    }
+
+    fn previous(timezone: &TenantTimezone) -> Option<Self> {
+        let today = timezone.to_local(Utc::now()).date_naive();
+        let first_day = today.with_day(1)?.checked_sub_months(Months::new(1))?;
+        Period::of(first_day, timezone)
+    }
+}
+
+fn current_month(timezone: &TenantTimezone) -> String {
+    timezone
+        .to_local(Utc::now())
+        .date_naive()
+        .format("%Y-%m")
+        .to_string()
+}
+
+pub fn record_login(core_storage: Arc<CoreLocalStorage>, user_id: &str) {
+    let month = current_month(&timezone::for_tenant(core_storage.clone()));
+    if let Err(e) = ActivityReportLocalStorage::new(core_storage).and_then(|storage| {
+        storage.record_user_activity(user_id, &month, Utc::now().timestamp_millis())
+    }) {
+        println!("Failed to record activity of user {}: {:?}", user_id, e);
+    }
+}
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    let settings = &config::get().activity_reports;
+    if !settings.enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.check_interval_secs);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            if !instance_lease::is_leader() {
+                continue;
+            }
+            if let Err(e) = tokio::task::spawn_blocking(generate_due_reports).await {
+                eprintln!("Activity report run failed: {:?}", e);
+            }
+        }
+    }))
+}
+
+fn generate_due_reports() {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            continue;
+        }
+
+        let core_storage = match path
+            .to_str()
+            .ok_or(rusqlite::Error::InvalidPath(path.clone()))
+            .and_then(CoreLocalStorage::new)
+            .and_then(|core_storage| {
+                schema::migrate(&*core_storage.get_connection()?)?;
+                Ok(Arc::new(core_storage))
+            }) {
+            Ok(core_storage) => core_storage,
+            Err(e) => {
+                eprintln!(
+                    "Failed to open tenant {} for activity reports: {:?}",
+                    tenant, e
+                );
+                continue;
+            }
+        };
+
+        let timezone = timezone::for_tenant(core_storage.clone());
+        let Some(period) = Period::previous(&timezone) else {
+            continue;
+        };
+        let exists = ActivityReportLocalStorage::new(core_storage.clone())
+            .and_then(|storage| storage.has_report(&period.month));
+        match exists {
+            Ok(true) => {}
+            Ok(false) => match generate(tenant, core_storage, &period, &timezone) {
+                Ok(_) => println!(
+                    "Generated activity report {} of tenant {}",
+                    period.month, tenant
+                ),
+                Err(e) => eprintln!(
+                    "Failed to generate activity report {} of tenant {}: {:?}",
+                    period.month, tenant, e
+                ),
+            },
+            Err(e) => eprintln!(
+                "Failed to look up activity reports of tenant {}: {:?}",
+                tenant, e
+            ),
+        }
+    }
+}
+
+fn generate(
+    tenant: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    period: &Period,
+    timezone: &TenantTimezone,
+) -> rusqlite::Result<Value> {
+    let storage = ActivityReportLocalStorage::new(core_storage)?;
+    let mut report = storage.collect_activity(&period.month, period.start, period.end)?;
+
+    let database_bytes = report["storage"]["databaseBytes"].as_i64().unwrap_or(0);
+    let growth = storage
+        .get_previous_report(&period.month)?
+        .and_then(|previous| previous["storage"]["databaseBytes"].as_i64())
+        .map(|previous| database_bytes - previous);
+    report["storage"]["growthBytes"] = json!(growth);
+    report["tenant"] = json!(tenant);
+    report["month"] = json!(period.month);
+    report["timezone"] = json!(timezone.name());
+    report["periodStart"] = json!(period.start);
+    report["periodEnd"] = json!(period.end);
+    report["generatedAt"] = json!(Utc::now().timestamp_millis());
+
+    storage.save_report(&period.month, period.start, period.end, &report)?;
+    metrics::increment("activity_reports_generated_total");
+    Ok(report)
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list = warp::path!("admin" / "reports")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_reports(authorization, &db_pools))
+        });
+
+    let download = warp::path!("admin" / "reports" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|month: String, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(download_report(month, authorization, &db_pools))
+        });
+
+    let regenerate = warp::path!("admin" / "reports" / String)
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|month: String, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(regenerate_report(month, authorization, &db_pools))
+        });
+
+    list.or(download).unify().or(regenerate).unify()
+}
+
+fn list_reports(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let reports = rest::open_storage(&user.tenant)
+        .and_then(ActivityReportLocalStorage::new)
+        .and_then(|storage| storage.list_reports())
+        .map_err(|e| rest::internal_error("Failed to list activity reports", e))?;
+
+    Ok(rest::json_reply(&json!({ "reports": reports })))
+}
+
+fn download_report(
+    month: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<WithHeader<ErrorReply>, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let report = rest::open_storage(&user.tenant)
+        .and_then(ActivityReportLocalStorage::new)
+        .and_then(|storage| storage.get_report(&month))
+        .map_err(|e| rest::internal_error("Failed to load activity report", e))?
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                "Activity report not found",
+            )
+        })?;
+
+    Ok(warp::reply::with_header(
+        rest::json_reply(&report),
+        "Content-Disposition",
+        format!(
+            "attachment; filename=\"activity-report-{}-{}.json\"",
+            user.tenant, month
+        ),
+    ))
+}
+
+fn regenerate_report(
+    month: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let timezone = timezone::for_tenant(core_storage.clone());
+
+    let period = Period::parse(&month, &timezone)
+        .filter(|period| period.month < current_month(&timezone))
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "month must be a completed month formatted as YYYY-MM",
+            )
+        })?;
+
+    let report = generate(&user.tenant, core_storage, &period, &timezone)
+        .map_err(|e| rest::internal_error("Failed to generate activity report", e))?;
+    println!(
+        "Admin {} regenerated activity report {} of tenant {}",
+        user.user_id, period.month, user.tenant
+    );
+
+    Ok(rest::json_reply(&report))
+}
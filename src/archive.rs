@@ -0,0 +1,82 @@
+use crate::config;
+use crate::instance_lease;
+use crate::local_storage::archive::archive_local_storage::ArchiveLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use std::fs;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    let settings = &config::get().archive;
+    if !settings.enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.interval_hours * 60 * 60);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            if !instance_lease::is_leader() {
+                continue;
+            }
+            if let Err(e) = tokio::task::spawn_blocking(archive_all_tenants).await {
+                eprintln!("Archive run failed: {:?}", e);
+            }
+        }
+    }))
+}
+
+fn archive_all_tenants() {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    let min_age_ms = (config::get().archive.min_age_days * 24 * 60 * 60 * 1000) as i64;
+    let cutoff = chrono::Utc::now().timestamp_millis() - min_age_ms;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            continue;
+        }
+
+        let result = path
+            .to_str()
+            .ok_or(rusqlite::Error::InvalidPath(path.clone()))
+            .and_then(CoreLocalStorage::new)
+            .and_then(|core_storage| {
+                schema::migrate(&*core_storage.get_connection()?)?;
+                ArchiveLocalStorage::new(Arc::new(core_storage))
+            })
+            .and_then(|archive_storage| archive_storage.archive_done_locations(cutoff));
+
+        match result {
+            Ok(0) => {}
+            Ok(archived) => {
+                metrics::add("archived_locations_total", archived as u64);
+                println!(
+                    "Archived {} done location(s) of tenant {}",
+                    archived, tenant
+                );
+            }
+            Err(e) => eprintln!("Failed to archive locations of tenant {}: {:?}", tenant, e),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::local_storage::assortment::assortment_local_storage::AssortmentLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::sawmill::sawmill_local_storage::SawmillLocalStorage;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const MAX_CODE_LEN: usize = 32;
+const MIN_DENSITY: f64 = 100.0;
+const MAX_DENSITY: f64 = 2000.0;
+
+pub fn validate_assortment(
+    assortment: &mut Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<(), String> {
+    let id = assortment["id"].as_str().unwrap_or_default().to_string();
+    let code = assortment["code"]
+        .as_str()
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .ok_or_else(|| "code is required".to_string())?
+        .to_string();
+    if code.chars().count() > MAX_CODE_LEN {
+        return Err(format!("code must not exceed {} characters", MAX_CODE_LEN));
+    }
+
+    let existing = AssortmentLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_assortment_by_code(&code))
+        .map_err(|e| format!("Failed to check assortment code: {}", e))?;
+    if existing.is_some_and(|existing| existing["id"].as_str() != Some(id.as_str())) {
+        return Err(format!("Assortment code {} is already in use", code));
+    }
+    assortment["code"] = json!(code);
+
+    match assortment.get("densityKgPerCubicMeter") {
+        None | Some(Value::Null) => {}
+        Some(density) => {
+            density
+                .as_f64()
+                .filter(|density| (MIN_DENSITY..=MAX_DENSITY).contains(density))
+                .ok_or_else(|| {
+                    format!(
+                        "densityKgPerCubicMeter must be between {} and {}",
+                        MIN_DENSITY, MAX_DENSITY
+                    )
+                })?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn validate_location(
+    location: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<(), String> {
+    match location.get("assortmentId") {
+        None | Some(Value::Null) => Ok(()),
+        Some(Value::String(assortment_id)) => {
+            load_assortment(assortment_id, core_storage).map(|_| ())
+        }
+        Some(_) => Err("assortmentId must be a string".to_string()),
+    }
+}
+
+pub fn check_shipment(
+    shipment: &mut Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<(), String> {
+    let assortment_id = match shipment.get("assortmentId") {
+        None | Some(Value::Null) => {
+            let location_id = shipment["locationId"].as_str().unwrap_or_default();
+            core_storage
+                .get_columns_by_id("locations", location_id, &["assortmentId"])
+                .map_err(|e| format!("Failed to load location: {}", e))?
+                .first()
+                .and_then(|location| location["assortmentId"].as_str())
+                .map(str::to_string)
+        }
+        Some(Value::String(assortment_id)) => Some(assortment_id.clone()),
+        Some(_) => return Err("assortmentId must be a string".to_string()),
+    };
+    let Some(assortment_id) = assortment_id else {
+        return Ok(());
+    };
+
+    let assortment = load_assortment(&assortment_id, core_storage.clone())?;
+    shipment["assortmentId"] = json!(assortment_id);
+
+    let sawmill_id = shipment["sawmillId"].as_str().unwrap_or_default();
+    let accepted = SawmillLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_assortment_ids(sawmill_id))
+        .map_err(|e| format!("Failed to load accepted assortments: {}", e))?;
+    if !accepted.is_empty() && !accepted.contains(&assortment_id) {
+        return Err(format!(
+            "Sawmill {} does not accept assortment {}",
+            sawmill_id,
+            assortment["code"].as_str().unwrap_or(&assortment_id)
+        ));
+    }
+
+    Ok(())
+}
+
+fn load_assortment(
+    assortment_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, String> {
+    AssortmentLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_assortment_by_id(assortment_id))
+        .map_err(|e| format!("Failed to load assortment: {}", e))?
+        .ok_or_else(|| format!("Unknown assortment: {}", assortment_id))
+}
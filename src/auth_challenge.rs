@@ -0,0 +1,169 @@
+use crate::config;
+use crate::metrics;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub const CHALLENGE_PROTOCOL_VERSION: i64 = 3;
+
+struct Challenge {
+    nonce: String,
+    issued: Instant,
+}
+
+static CHALLENGES: LazyLock<Mutex<HashMap<String, Challenge>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn issue(client_id: &str) -> Value {
+    let nonce = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    if let Ok(mut challenges) = CHALLENGES.lock() {
+        challenges.insert(
+            client_id.to_string(),
+            Challenge {
+                nonce: nonce.clone(),
+                issued: Instant::now(),
+            },
+        );
+    }
+
+    json!({
+        "type": "authentication_challenge",
+        "data": {
+            "nonce": nonce,
+            "expiresInSecs": config::get().auth.challenge_ttl_secs,
+            "protocolVersion": CHALLENGE_PROTOCOL_VERSION
+        },
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    })
+}
+
+pub fn discard(client_id: &str) {
+    if let Ok(mut challenges) = CHALLENGES.lock() {
+        challenges.remove(client_id);
+    }
+}
+
+/// Checks the challenge answer in an authentication_request. `key` is the
+/// device secret of an enrolled device, or the API key while a device
+/// enrols; the key itself is never sent.
+pub fn verify(client_id: &str, key: &str, data: &Value) -> Result<(), &'static str> {
+    let challenge = CHALLENGES
+        .lock()
+        .ok()
+        .and_then(|mut challenges| challenges.remove(client_id));
+
+    let result = match challenge {
+        None => Err("No challenge issued"),
+        Some(challenge)
+            if challenge.issued.elapsed()
+                > Duration::from_secs(config::get().auth.challenge_ttl_secs) =>
+        {
+            Err("Challenge expired")
+        }
+        Some(challenge) if data["challenge"]["nonce"].as_str() != Some(&challenge.nonce) => {
+            Err("Challenge mismatch")
+        }
+        Some(challenge) => {
+            let expected = hex(&hmac_sha256(key.as_bytes(), challenge.nonce.as_bytes()));
+            let signature = data["challenge"]["signature"]
+                .as_str()
+                .unwrap_or_default()
+                .to_lowercase();
+            if constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+                Ok(())
+            } else {
+                Err("Invalid challenge signature")
+            }
+        }
+    };
+
+    if result.is_err() {
+        metrics::increment("auth_challenge_rejected_total");
+    }
+    result
+}
+
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.map(|byte| byte ^ 0x36));
+    inner.update(message);
+
+    let mut outer = Sha256::new();
+    outer.update(block.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().into()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4231 test cases 1-4, 6 and 7; case 5 covers truncated output.
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        let key_4: Vec<u8> = (0x01..=0x19).collect();
+        let cases: [(&[u8], &[u8], &str); 6] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                &key_4,
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &[0xaa; 131],
+                b"This is a test using a larger than block-size key and a larger than \
+                  block-size data. The key needs to be hashed before being used by the \
+                  HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+
+        for (key, message, expected) in cases {
+            assert_eq!(hex(&hmac_sha256(key, message)), expected);
+        }
+    }
+
+    #[test]
+    fn constant_time_eq_compares_length_and_content() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::config;
+use crate::metrics;
+use crate::wire_format::{self, Shared};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::time::{Duration, Instant, timeout_at};
+use warp::ws::Message;
+
+pub const BATCH_PROTOCOL_VERSION: i64 = 2;
+
+pub fn spawn(
+    sender: UnboundedSender<Message>,
+    pending: Arc<AtomicUsize>,
+    msgpack: Arc<AtomicBool>,
+) -> UnboundedSender<Arc<Shared>> {
+    let (batch_sender, mut batch_receiver) = mpsc::unbounded_channel::<Arc<Shared>>();
+    let window = Duration::from_millis(config::get().broadcast.batch_window_ms);
+    let max_batch_size = config::get().broadcast.max_batch_size;
+
+    tokio::task::spawn(async move {
+        while let Some(first) = batch_receiver.recv().await {
+            let mut batch = vec![first];
+            let deadline = Instant::now() + window;
+
+            while batch.len() < max_batch_size {
+                pending.store(batch.len() + batch_receiver.len(), Ordering::Relaxed);
+                match timeout_at(deadline, batch_receiver.recv()).await {
+                    Ok(Some(msg)) => batch.push(msg),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            pending.store(batch_receiver.len(), Ordering::Relaxed);
+            if sender.send(render(batch, &msgpack)).is_err() {
+                break;
+            }
+        }
+    });
+
+    batch_sender
+}
+
+fn render(batch: Vec<Arc<Shared>>, msgpack: &AtomicBool) -> Message {
+    if let [msg] = batch.as_slice() {
+        return msg.frame(msgpack);
+    }
+
+    metrics::increment("broadcast_batches_total");
+    metrics::add("broadcast_batched_messages_total", batch.len() as u64);
+
+    let batch = json!({
+        "type": "batch",
+        "data": batch.iter().map(|msg| msg.value()).collect::<Vec<&Value>>(),
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+    wire_format::frame(&batch, msgpack)
+}
@@ -0,0 +1,63 @@
+use crate::Client;
+use crate::config;
+use crate::metrics;
+use crate::wire_format::Shared;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use warp::ws::Message;
+
+const CLOSE_CODE_QUARANTINED: u16 = 4002;
+
+pub fn send(client_id: &str, client: &Client, msg: &Arc<Shared>) {
+    match client.send_broadcast(msg) {
+        Ok(()) => {
+            if client.broadcast_failures.swap(0, Ordering::Relaxed) > 0 {
+                metrics::add_gauge(&failing_gauge(&client.db_name), -1);
+            }
+        }
+        Err(e) => {
+            let failures = client.broadcast_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            metrics::increment(&format!(
+                "ws_broadcast_failures_total{{tenant=\"{}\"}}",
+                client.db_name
+            ));
+            if failures == 1 {
+                metrics::add_gauge(&failing_gauge(&client.db_name), 1);
+            }
+            println!(
+                "Error sending message to client {} ({} consecutive failures): {:?}",
+                client_id, failures, e
+            );
+
+            if failures == config::get().broadcast.quarantine_after_failures {
+                quarantine(client_id, client, failures);
+            }
+        }
+    }
+}
+
+pub fn forget(client: &Client) {
+    if client.broadcast_failures.load(Ordering::Relaxed) > 0 {
+        metrics::add_gauge(&failing_gauge(&client.db_name), -1);
+    }
+}
+
+fn quarantine(client_id: &str, client: &Client, failures: usize) {
+    let _ = client.sender.send(Message::close_with(
+        CLOSE_CODE_QUARANTINED,
+        "Too many failed broadcasts",
+    ));
+    client.disconnect.notify_one();
+    metrics::increment(&format!(
+        "ws_clients_quarantined_total{{tenant=\"{}\"}}",
+        client.db_name
+    ));
+    eprintln!(
+        "ALERT: quarantined client {} of tenant {} ({}) after {} consecutive broadcast failures",
+        client_id, client.db_name, client.user_id, failures
+    );
+}
+
+fn failing_gauge(tenant: &str) -> String {
+    format!("ws_broadcast_failing_clients{{tenant=\"{}\"}}", tenant)
+}
@@ -0,0 +1,256 @@
+use crate::config;
+use crate::local_storage::schema;
+use crate::rest::{self, ErrorReply};
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use crate::{Clients, with_clients};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const HOUR_MS: i64 = 60 * 60 * 1000;
+
+static SNAPSHOT: LazyLock<Mutex<Option<Value>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn spawn(clients: Clients) -> Option<JoinHandle<()>> {
+    let settings = &config::get().capacity;
+    if !settings.enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.refresh_interval_secs);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            let connected = connected_clients(&clients);
+            match tokio::task::spawn_blocking(move || collect(&connected)).await {
+                Ok(snapshot) => {
+                    if let Ok(mut cached) = SNAPSHOT.lock() {
+                        *cached = Some(snapshot);
+                    }
+                }
+                Err(e) => eprintln!("Capacity stats run failed: {:?}", e),
+            }
+        }
+    }))
+}
+
+fn connected_clients(clients: &Clients) -> HashMap<String, usize> {
+    let mut connected = HashMap::new();
+    if let Ok(clients_lock) = clients.lock() {
+        for client in clients_lock
+            .values()
+            .filter(|client| !client.db_name.is_empty())
+        {
+            *connected.entry(client.db_name.clone()).or_insert(0) += 1;
+        }
+    }
+    connected
+}
+
+fn collect(connected: &HashMap<String, usize>) -> Value {
+    let started = Instant::now();
+    let computed_at = chrono::Utc::now().timestamp_millis();
+
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return json!({ "computedAt": computed_at, "tenants": [], "totals": {} });
+        }
+    };
+
+    let mut tenants = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+
+        let mut stats = json!({
+            "tenant": tenant,
+            "dbBytes": file_bytes(&path),
+            "clientsConnected": connected.get(tenant).copied().unwrap_or(0),
+            "draining": tenant_drain::is_draining(tenant),
+            "hibernatedSince": tenant_hibernation::hibernated_since(tenant)
+        });
+        if tenant_hibernation::is_hibernating(tenant) {
+            tenants.push(stats);
+            continue;
+        }
+        if let Err(e) = tenant_stats(&path, computed_at, &mut stats) {
+            eprintln!(
+                "Failed to collect capacity stats of tenant {}: {:?}",
+                tenant, e
+            );
+            stats["error"] = json!(e.to_string());
+        }
+        tenants.push(stats);
+    }
+    tenants.sort_by(|a, b| a["tenant"].as_str().cmp(&b["tenant"].as_str()));
+
+    let total = |field: &str| -> i64 {
+        tenants
+            .iter()
+            .filter_map(|stats| stats[field].as_i64())
+            .sum()
+    };
+    let totals = json!({
+        "tenants": tenants.len(),
+        "dbBytes": total("dbBytes"),
+        "photoBytes": total("photoBytes"),
+        "clientsConnected": total("clientsConnected"),
+        "updatesLastHour": total("updatesLastHour"),
+        "hibernated": tenants
+            .iter()
+            .filter(|stats| !stats["hibernatedSince"].is_null())
+            .count()
+    });
+
+    json!({
+        "computedAt": computed_at,
+        "durationMs": started.elapsed().as_millis() as u64,
+        "totals": totals,
+        "tenants": tenants
+    })
+}
+
+fn file_bytes(path: &Path) -> u64 {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    [path.as_os_str().to_owned(), wal]
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn tenant_stats(path: &Path, now: i64, stats: &mut Value) -> rusqlite::Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let tx = conn.unchecked_transaction()?;
+
+    let mut rows = Map::new();
+    let mut photo_bytes = 0;
+    let mut updates_last_hour = 0;
+    for table in schema::TABLES {
+        let exists = tx
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+                [table.name],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if !exists {
+            continue;
+        }
+
+        let count: i64 = tx.query_row(
+            &format!("SELECT COUNT(*) FROM \"{}\"", table.name),
+            [],
+            |row| row.get(0),
+        )?;
+        rows.insert(table.name.to_string(), json!(count));
+
+        if table.column("photoFile").is_some() {
+            photo_bytes += tx.query_row(
+                &format!(
+                    "SELECT COALESCE(SUM(length(photoFile)), 0) FROM \"{}\"",
+                    table.name
+                ),
+                [],
+                |row| row.get::<_, i64>(0),
+            )?;
+        }
+        if table.column("arrivalAtServer").is_some() {
+            updates_last_hour += tx.query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM \"{}\" WHERE arrivalAtServer > ?",
+                    table.name
+                ),
+                [now - HOUR_MS],
+                |row| row.get::<_, i64>(0),
+            )?;
+        }
+    }
+    tx.finish()?;
+
+    stats["rows"] = Value::Object(rows);
+    stats["photoBytes"] = json!(photo_bytes);
+    stats["updatesLastHour"] = json!(updates_last_hour);
+    Ok(())
+}
+
+pub fn route(
+    clients: Clients,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("admin" / "capacity")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_clients(clients))
+        .map(|authorization, clients: Clients| {
+            rest::into_reply(capacity_stats(authorization, &clients))
+        })
+}
+
+fn capacity_stats(
+    authorization: Option<String>,
+    clients: &Clients,
+) -> Result<ErrorReply, ErrorReply> {
+    let settings = &config::get().capacity;
+    if !settings.enabled {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Capacity stats are disabled",
+        ));
+    }
+    let api_key = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "));
+    if api_key != Some(settings.api_key.as_str()) {
+        return Err(rest::error_reply(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Missing or invalid API key",
+        ));
+    }
+
+    let snapshot = SNAPSHOT.lock().ok().and_then(|cached| cached.clone());
+    let Some(mut snapshot) = snapshot else {
+        return Err(rest::error_reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "NOT_READY",
+            "Capacity stats have not been computed yet",
+        ));
+    };
+
+    let connected = connected_clients(clients);
+    snapshot["clientsConnectedNow"] = json!(connected.values().sum::<usize>());
+    let mut hibernated = 0;
+    if let Some(tenants) = snapshot["tenants"].as_array_mut() {
+        for stats in tenants {
+            let hibernated_since = stats["tenant"]
+                .as_str()
+                .and_then(tenant_hibernation::hibernated_since);
+            hibernated += hibernated_since.is_some() as usize;
+            stats["hibernatedSince"] = json!(hibernated_since);
+        }
+    }
+    snapshot["totals"]["hibernated"] = json!(hibernated);
+    Ok(rest::json_reply(&snapshot))
+}
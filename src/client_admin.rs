@@ -0,0 +1,380 @@
+use crate::local_storage::audit_log::audit_log_local_storage::AuditLogLocalStorage;
+use crate::metrics;
+use crate::rest::{self, ErrorReply, RestUser};
+use crate::wire_format;
+use crate::{Clients, DbPoolMap, ROLE_ADMIN, with_clients, with_db_pools};
+use serde_json::{Value, json};
+use std::sync::atomic::Ordering;
+use std::sync::{LazyLock, Mutex};
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::ws::Message;
+use warp::{Filter, Rejection, Reply};
+
+const MAX_BAN_MINUTES: i64 = 7 * 24 * 60;
+const AUDIT_LOG_LIMIT: i64 = 500;
+const CLOSE_CODE_KICKED: u16 = 4001;
+
+struct Ban {
+    id: String,
+    tenant: String,
+    target: &'static str,
+    value: String,
+    reason: String,
+    created_by: String,
+    expires_at: i64,
+}
+
+impl Ban {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "target": self.target,
+            "value": self.value,
+            "reason": self.reason,
+            "createdBy": self.created_by,
+            "expiresAt": self.expires_at
+        })
+    }
+}
+
+static BANS: LazyLock<Mutex<Vec<Ban>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+pub fn active_ban(tenant: &str, api_key: &str, ip: Option<&str>) -> Option<Value> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut bans = BANS.lock().ok()?;
+    bans.retain(|ban| ban.expires_at > now);
+
+    bans.iter()
+        .find(|ban| {
+            ban.tenant == tenant
+                && match ban.target {
+                    "apiKey" => ban.value == api_key,
+                    _ => ip == Some(ban.value.as_str()),
+                }
+        })
+        .map(Ban::to_json)
+}
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list_connections = warp::path!("admin" / "connections")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_clients(clients.clone()))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, clients: Clients, db_pools: DbPoolMap| {
+            rest::into_reply(list_connections(authorization, &clients, &db_pools))
+        });
+
+    let disconnect = warp::path!("admin" / "connections" / String / "disconnect")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools.clone()))
+        .map(
+            |client_id, authorization, body, clients: Clients, db_pools: DbPoolMap| {
+                rest::into_reply(disconnect_client(
+                    client_id,
+                    authorization,
+                    body,
+                    &clients,
+                    &db_pools,
+                ))
+            },
+        );
+
+    let list_bans = warp::path!("admin" / "bans")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_bans(authorization, &db_pools))
+        });
+
+    let lift_ban = warp::path!("admin" / "bans" / String)
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|ban_id, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(lift_ban(ban_id, authorization, &db_pools))
+        });
+
+    let audit_log = warp::path!("admin" / "audit-log")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_audit_log(authorization, &db_pools))
+        });
+
+    list_connections
+        .or(disconnect)
+        .unify()
+        .or(list_bans)
+        .unify()
+        .or(lift_ban)
+        .unify()
+        .or(audit_log)
+        .unify()
+}
+
+fn list_connections(
+    authorization: Option<String>,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let clients_lock = clients.lock().map_err(|e| {
+        println!("Failed to lock clients: {:?}", e);
+        rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Failed to list connections",
+        )
+    })?;
+
+    let connections: Vec<Value> = clients_lock
+        .iter()
+        .filter(|(_, client)| client.db_name == user.tenant && client.authenticated_at > 0)
+        .map(|(client_id, client)| {
+            json!({
+                "clientId": client_id,
+                "userId": client.user_id,
+                "role": client.role,
+                "remoteAddr": client.remote_addr,
+                "authenticatedAt": client.authenticated_at,
+                "lastMessageAt": (client.last_message_at > 0).then_some(client.last_message_at),
+                "lastMessageType": client.last_message_type,
+                "syncCompleted": client.sync_completed,
+                "broadcastFailures": client.broadcast_failures.load(Ordering::Relaxed)
+            })
+        })
+        .collect();
+
+    Ok(rest::json_reply(&json!({ "connections": connections })))
+}
+
+fn disconnect_client(
+    client_id: String,
+    authorization: Option<String>,
+    body: Value,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let reason = body["reason"]
+        .as_str()
+        .filter(|reason| !reason.trim().is_empty())
+        .unwrap_or("Disconnected by an administrator")
+        .to_string();
+
+    let ban_request = match body.get("ban").filter(|ban| !ban.is_null()) {
+        Some(ban) => {
+            let target = match ban["target"].as_str() {
+                Some("apiKey") => "apiKey",
+                Some("ip") => "ip",
+                _ => {
+                    return Err(rest::error_reply(
+                        StatusCode::BAD_REQUEST,
+                        "VALIDATION_FAILED",
+                        "ban.target must be apiKey or ip",
+                    ));
+                }
+            };
+            let minutes = ban["durationMinutes"].as_i64().unwrap_or(60);
+            if !(1..=MAX_BAN_MINUTES).contains(&minutes) {
+                return Err(rest::error_reply(
+                    StatusCode::BAD_REQUEST,
+                    "VALIDATION_FAILED",
+                    &format!(
+                        "ban.durationMinutes must be between 1 and {}",
+                        MAX_BAN_MINUTES
+                    ),
+                ));
+            }
+            Some((target, minutes))
+        }
+        None => None,
+    };
+
+    let (kicked_user, api_key, remote_addr) = {
+        let clients_lock = clients.lock().map_err(|e| {
+            println!("Failed to lock clients: {:?}", e);
+            rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to disconnect client",
+            )
+        })?;
+        let client = clients_lock
+            .get(&client_id)
+            .filter(|client| client.db_name == user.tenant)
+            .ok_or_else(|| {
+                rest::error_reply(StatusCode::NOT_FOUND, "NOT_FOUND", "Connection not found")
+            })?;
+
+        let frame = json!({
+            "type": "disconnect",
+            "data": { "reason": reason },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        let _ = client
+            .sender
+            .send(wire_format::frame(&frame, &client.msgpack));
+        let _ = client
+            .sender
+            .send(Message::close_with(CLOSE_CODE_KICKED, reason.clone()));
+        client.disconnect.notify_one();
+
+        (
+            client.user_id.clone(),
+            client.api_key.clone(),
+            client.remote_addr.clone(),
+        )
+    };
+    metrics::increment("ws_clients_kicked_total");
+    println!(
+        "Admin {} disconnected client {} ({}): {}",
+        user.user_id, client_id, kicked_user, reason
+    );
+
+    let ban = match ban_request {
+        Some((target, minutes)) => {
+            let value = match target {
+                "apiKey" => Some(api_key),
+                _ => remote_addr,
+            };
+            let value = value.ok_or_else(|| {
+                rest::error_reply(
+                    StatusCode::CONFLICT,
+                    "BAN_FAILED",
+                    "Client was disconnected, but its address is unknown",
+                )
+            })?;
+            Some(add_ban(&user, target, value, &reason, minutes))
+        }
+        None => None,
+    };
+
+    audit(
+        &user,
+        "client_disconnect",
+        "client",
+        &client_id,
+        &json!({ "userId": kicked_user, "reason": reason, "ban": ban }),
+    );
+
+    Ok(rest::json_reply(&json!({
+        "clientId": client_id,
+        "disconnected": true,
+        "ban": ban
+    })))
+}
+
+fn add_ban(
+    user: &RestUser,
+    target: &'static str,
+    value: String,
+    reason: &str,
+    minutes: i64,
+) -> Value {
+    let ban = Ban {
+        id: Uuid::new_v4().to_string(),
+        tenant: user.tenant.clone(),
+        target,
+        value,
+        reason: reason.to_string(),
+        created_by: user.user_id.clone(),
+        expires_at: chrono::Utc::now().timestamp_millis() + minutes * 60 * 1000,
+    };
+    let ban_json = ban.to_json();
+
+    match BANS.lock() {
+        Ok(mut bans) => bans.push(ban),
+        Err(e) => println!("Failed to lock bans: {:?}", e),
+    }
+
+    ban_json
+}
+
+fn list_bans(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let bans: Vec<Value> = match BANS.lock() {
+        Ok(mut bans) => {
+            bans.retain(|ban| ban.expires_at > now);
+            bans.iter()
+                .filter(|ban| ban.tenant == user.tenant)
+                .map(Ban::to_json)
+                .collect()
+        }
+        Err(e) => {
+            println!("Failed to lock bans: {:?}", e);
+            Vec::new()
+        }
+    };
+
+    Ok(rest::json_reply(&json!({ "bans": bans })))
+}
+
+fn lift_ban(
+    ban_id: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+
+    let lifted = match BANS.lock() {
+        Ok(mut bans) => {
+            let position = bans
+                .iter()
+                .position(|ban| ban.id == ban_id && ban.tenant == user.tenant);
+            position.map(|position| bans.remove(position).to_json())
+        }
+        Err(e) => {
+            println!("Failed to lock bans: {:?}", e);
+            None
+        }
+    };
+
+    let Some(ban) = lifted else {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Ban not found",
+        ));
+    };
+    audit(&user, "ban_lift", "ban", &ban_id, &ban);
+
+    Ok(rest::json_reply(&json!({ "id": ban_id, "lifted": true })))
+}
+
+fn list_audit_log(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let entries = rest::open_storage(&user.tenant)
+        .and_then(AuditLogLocalStorage::new)
+        .and_then(|storage| storage.get_entries(AUDIT_LOG_LIMIT))
+        .map_err(|e| rest::internal_error("Failed to load audit log", e))?;
+
+    Ok(rest::json_reply(&json!({ "entries": entries })))
+}
+
+pub fn audit(user: &RestUser, action: &str, target_type: &str, target_id: &str, details: &Value) {
+    if let Err(e) = rest::open_storage(&user.tenant)
+        .and_then(AuditLogLocalStorage::new)
+        .and_then(|storage| storage.record(&user.user_id, action, target_type, target_id, details))
+    {
+        println!("Failed to write audit log: {:?}", e);
+    }
+}
@@ -0,0 +1,1712 @@
+use crate::rule_simulation::SimulationOptions;
+use crate::storage::MigrationOptions;
+use crate::synthetic::GenerateOptions;
+use crate::test_fixture;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub port: u16,
+    pub databases_dir: PathBuf,
+    pub photo_ingest: PhotoIngestConfig,
+    pub plausibility: PlausibilityConfig,
+    pub snapshots: SnapshotConfig,
+    pub portal: PortalConfig,
+    pub telemetry: TelemetryConfig,
+    pub broadcast: BroadcastConfig,
+    pub stuck_clients: StuckClientConfig,
+    pub schema: SchemaConfig,
+    pub sync_scheduler: SyncSchedulerConfig,
+    pub archive: ArchiveConfig,
+    pub coordinates: CoordinateConfig,
+    pub digests: DigestConfig,
+    pub shipment_documents: ShipmentDocumentConfig,
+    pub graphql: GraphqlConfig,
+    pub auth: AuthConfig,
+    pub scripting: ScriptingConfig,
+    pub capacity: CapacityConfig,
+    pub db_pool: DbPoolConfig,
+    pub watchdog: WatchdogConfig,
+    pub quota: QuotaConfig,
+    pub photo_transcode: PhotoTranscodeConfig,
+    pub activity_reports: ActivityReportConfig,
+    pub support_bundle: SupportBundleConfig,
+    pub photo_urls: PhotoUrlConfig,
+    pub selftest: SelftestConfig,
+    pub route_distance: RouteDistanceConfig,
+    pub event_stream: EventStreamConfig,
+    pub write_batch: WriteBatchConfig,
+    pub client_compat: ClientCompatConfig,
+    pub gate: GateConfig,
+    pub schema_validation: SchemaValidationConfig,
+    pub sla_stats: SlaStatsConfig,
+    pub write_verification: WriteVerificationConfig,
+    pub hibernation: HibernationConfig,
+    pub integrations: IntegrationConfig,
+    pub deprecations: DeprecationConfig,
+    pub storage: StorageConfig,
+    pub sync_batch: SyncBatchConfig,
+    pub event_bus: EventBusConfig,
+    pub sync_progress: SyncProgressConfig,
+    pub photo_chunks: PhotoChunkConfig,
+    pub status_page: StatusPageConfig,
+    pub photo_recompression: PhotoRecompressionConfig,
+    pub ws_compression: WsCompressionConfig,
+    pub wire_format: WireFormatConfig,
+    pub activity_feed: ActivityFeedConfig,
+    pub instance: InstanceConfig,
+    pub replication: ReplicationConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhotoIngestConfig {
+    pub max_concurrent: usize,
+    pub large_payload_bytes: usize,
+    pub spill_threshold_bytes: usize,
+    pub spill_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlausibilityConfig {
+    pub min_pieces_per_cubic_meter: f64,
+    pub max_pieces_per_cubic_meter: f64,
+    pub max_shipment_quantity: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub dir: PathBuf,
+    pub download_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PortalConfig {
+    pub rate_limit_per_minute: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub otlp_endpoint: String,
+    pub service_name: String,
+    pub sample_ratio: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BroadcastConfig {
+    pub batch_window_ms: u64,
+    pub max_batch_size: usize,
+    pub quarantine_after_failures: usize,
+    pub include_provenance: bool,
+    pub delta_updates: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StuckClientConfig {
+    pub threshold_minutes: u64,
+    pub check_interval_secs: u64,
+    pub disconnect: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchemaConfig {
+    pub check_on_startup: bool,
+    pub auto_repair: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncSchedulerConfig {
+    pub max_concurrent: usize,
+    pub max_per_tenant: usize,
+    pub priorities: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    pub enabled: bool,
+    pub min_age_days: u64,
+    pub interval_hours: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoordinateConfig {
+    pub allow_zero: bool,
+    pub round: bool,
+    pub decimal_places: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestConfig {
+    pub notification_url: String,
+    pub check_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShipmentDocumentConfig {
+    pub max_size_bytes: usize,
+    pub chunk_size_bytes: usize,
+    pub upload_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphqlConfig {
+    pub enabled: bool,
+    pub max_page_size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub challenge_ttl_secs: u64,
+    pub require_challenge: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptingConfig {
+    pub enabled: bool,
+    pub max_steps: usize,
+    pub timeout_ms: u64,
+    pub max_script_bytes: usize,
+    pub max_string_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CapacityConfig {
+    pub enabled: bool,
+    pub api_key: String,
+    pub refresh_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbPoolConfig {
+    pub max_size: u32,
+    pub acquire_timeout_ms: u64,
+    pub acquire_retries: u32,
+    pub retry_backoff_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    pub message_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    pub reservation_ttl_hours: u64,
+    pub expiry_check_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhotoTranscodeConfig {
+    pub command: String,
+    pub args: Vec<String>,
+    pub resize_args: Vec<String>,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivityReportConfig {
+    pub enabled: bool,
+    pub check_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupportBundleConfig {
+    pub dir: PathBuf,
+    pub download_ttl_secs: u64,
+    pub log_path: String,
+    pub log_tail_bytes: u64,
+    pub anonymize_user_names: bool,
+    pub coordinate_decimals: u32,
+    pub max_blob_bytes: usize,
+    pub scrub_columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhotoUrlConfig {
+    pub ttl_secs: u64,
+    pub signing_key: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelftestConfig {
+    pub on_startup: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteDistanceConfig {
+    pub routing_url: String,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventStreamConfig {
+    pub keep_alive_secs: u64,
+    pub channel_capacity: usize,
+    pub buffered_events: usize,
+    pub compaction_enabled: bool,
+    pub compaction_min_age_hours: u64,
+    pub compaction_interval_hours: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WriteBatchConfig {
+    pub enabled: bool,
+    pub burst_gap_ms: u64,
+    pub max_records: usize,
+    pub max_batch_ms: u64,
+    pub message_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientCompatConfig {
+    pub min_version: String,
+    pub legacy_protocol_sunset: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GateConfig {
+    pub default_density_kg_per_cubic_meter: f64,
+    pub discrepancy_threshold_percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchemaValidationConfig {
+    pub enabled: bool,
+    pub reject_unknown_fields: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlaStatsConfig {
+    pub enabled: bool,
+    pub flush_interval_secs: u64,
+    pub retention_months: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WriteVerificationConfig {
+    pub enabled: bool,
+    pub default_sample_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HibernationConfig {
+    pub enabled: bool,
+    pub idle_days: u64,
+    pub check_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntegrationConfig {
+    pub enabled: bool,
+    pub rate_limit_per_minute: u32,
+    pub max_lifetime_days: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeprecationConfig {
+    pub warn_clients: bool,
+    pub sunsets: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub photos_dir: PathBuf,
+    pub backups_dir: PathBuf,
+    pub exports_dir: PathBuf,
+    pub min_free_mb: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncBatchConfig {
+    pub enabled: bool,
+    pub max_batch_size: usize,
+    pub max_sync_limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventBusConfig {
+    pub channel_capacity: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncProgressConfig {
+    pub enabled: bool,
+    pub interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhotoChunkConfig {
+    pub max_size_bytes: usize,
+    pub chunk_size_bytes: usize,
+    pub upload_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusPageConfig {
+    pub enabled: bool,
+    pub api_key: String,
+    pub cache_secs: u64,
+    pub rate_limit_per_minute: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhotoRecompressionConfig {
+    pub enabled: bool,
+    pub interval_hours: u64,
+    pub batch_size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WsCompressionConfig {
+    pub enabled: bool,
+    pub min_bytes: usize,
+    pub level: u32,
+    pub max_inflated_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WireFormatConfig {
+    pub msgpack_enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivityFeedConfig {
+    pub enabled: bool,
+    pub retention_days: u64,
+    pub max_entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstanceConfig {
+    pub coordination_enabled: bool,
+    pub lease_secs: u64,
+    pub renew_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplicationConfig {
+    pub mode: String,
+    pub shared_secret: String,
+    pub primary_url: String,
+    pub poll_interval_secs: u64,
+    pub page_size: i64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 9090,
+            databases_dir: PathBuf::from("databases"),
+            photo_ingest: PhotoIngestConfig::default(),
+            plausibility: PlausibilityConfig::default(),
+            snapshots: SnapshotConfig::default(),
+            portal: PortalConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            broadcast: BroadcastConfig::default(),
+            stuck_clients: StuckClientConfig::default(),
+            schema: SchemaConfig::default(),
+            sync_scheduler: SyncSchedulerConfig::default(),
+            archive: ArchiveConfig::default(),
+            coordinates: CoordinateConfig::default(),
+            digests: DigestConfig::default(),
+            shipment_documents: ShipmentDocumentConfig::default(),
+            graphql: GraphqlConfig::default(),
+            auth: AuthConfig::default(),
+            scripting: ScriptingConfig::default(),
+            capacity: CapacityConfig::default(),
+            db_pool: DbPoolConfig::default(),
+            watchdog: WatchdogConfig::default(),
+            quota: QuotaConfig::default(),
+            photo_transcode: PhotoTranscodeConfig::default(),
+            activity_reports: ActivityReportConfig::default(),
+            support_bundle: SupportBundleConfig::default(),
+            photo_urls: PhotoUrlConfig::default(),
+            selftest: SelftestConfig::default(),
+            route_distance: RouteDistanceConfig::default(),
+            event_stream: EventStreamConfig::default(),
+            write_batch: WriteBatchConfig::default(),
+            client_compat: ClientCompatConfig::default(),
+            gate: GateConfig::default(),
+            schema_validation: SchemaValidationConfig::default(),
+            sla_stats: SlaStatsConfig::default(),
+            write_verification: WriteVerificationConfig::default(),
+            hibernation: HibernationConfig::default(),
+            integrations: IntegrationConfig::default(),
+            deprecations: DeprecationConfig::default(),
+            storage: StorageConfig::default(),
+            sync_batch: SyncBatchConfig::default(),
+            event_bus: EventBusConfig::default(),
+            sync_progress: SyncProgressConfig::default(),
+            photo_chunks: PhotoChunkConfig::default(),
+            status_page: StatusPageConfig::default(),
+            photo_recompression: PhotoRecompressionConfig::default(),
+            ws_compression: WsCompressionConfig::default(),
+            wire_format: WireFormatConfig::default(),
+            activity_feed: ActivityFeedConfig::default(),
+            instance: InstanceConfig::default(),
+            replication: ReplicationConfig::default(),
+        }
+    }
+}
+
+impl Default for PhotoIngestConfig {
+    fn default() -> Self {
+        PhotoIngestConfig {
+            max_concurrent: 2,
+            large_payload_bytes: 1024 * 1024,
+            spill_threshold_bytes: 8 * 1024 * 1024,
+            spill_dir: env::temp_dir(),
+        }
+    }
+}
+
+impl Default for PlausibilityConfig {
+    fn default() -> Self {
+        PlausibilityConfig {
+            min_pieces_per_cubic_meter: 0.1,
+            max_pieces_per_cubic_meter: 50.0,
+            max_shipment_quantity: 100.0,
+        }
+    }
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            dir: env::temp_dir(),
+            download_ttl_secs: 3600,
+        }
+    }
+}
+
+impl Default for PortalConfig {
+    fn default() -> Self {
+        PortalConfig {
+            rate_limit_per_minute: 60,
+        }
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            otlp_endpoint: String::new(),
+            service_name: "holz_logistik_server".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        BroadcastConfig {
+            batch_window_ms: 25,
+            max_batch_size: 200,
+            quarantine_after_failures: 20,
+            include_provenance: false,
+            delta_updates: true,
+        }
+    }
+}
+
+impl Default for StuckClientConfig {
+    fn default() -> Self {
+        StuckClientConfig {
+            threshold_minutes: 10,
+            check_interval_secs: 60,
+            disconnect: false,
+        }
+    }
+}
+
+impl Default for SchemaConfig {
+    fn default() -> Self {
+        SchemaConfig {
+            check_on_startup: true,
+            auto_repair: false,
+        }
+    }
+}
+
+impl Default for SyncSchedulerConfig {
+    fn default() -> Self {
+        SyncSchedulerConfig {
+            max_concurrent: 8,
+            max_per_tenant: 2,
+            priorities: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig {
+            enabled: false,
+            min_age_days: 365,
+            interval_hours: 24,
+        }
+    }
+}
+
+impl Default for CoordinateConfig {
+    fn default() -> Self {
+        CoordinateConfig {
+            allow_zero: false,
+            round: false,
+            decimal_places: 6,
+        }
+    }
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        DigestConfig {
+            notification_url: String::new(),
+            check_interval_secs: 300,
+        }
+    }
+}
+
+impl Default for ShipmentDocumentConfig {
+    fn default() -> Self {
+        ShipmentDocumentConfig {
+            max_size_bytes: 10 * 1024 * 1024,
+            chunk_size_bytes: 256 * 1024,
+            upload_timeout_secs: 600,
+        }
+    }
+}
+
+impl Default for GraphqlConfig {
+    fn default() -> Self {
+        GraphqlConfig {
+            enabled: false,
+            max_page_size: 500,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            challenge_ttl_secs: 30,
+            require_challenge: true,
+        }
+    }
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        ScriptingConfig {
+            enabled: false,
+            max_steps: 10_000,
+            timeout_ms: 50,
+            max_script_bytes: 16 * 1024,
+            max_string_bytes: 4096,
+        }
+    }
+}
+
+impl Default for CapacityConfig {
+    fn default() -> Self {
+        CapacityConfig {
+            enabled: false,
+            api_key: String::new(),
+            refresh_interval_secs: 300,
+        }
+    }
+}
+
+impl Default for DbPoolConfig {
+    fn default() -> Self {
+        DbPoolConfig {
+            max_size: 10,
+            acquire_timeout_ms: 2000,
+            acquire_retries: 2,
+            retry_backoff_ms: 100,
+        }
+    }
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            message_timeout_ms: 10_000,
+        }
+    }
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        QuotaConfig {
+            reservation_ttl_hours: 72,
+            expiry_check_interval_secs: 300,
+        }
+    }
+}
+
+impl Default for PhotoTranscodeConfig {
+    fn default() -> Self {
+        PhotoTranscodeConfig {
+            command: "magick".to_string(),
+            args: ["-", "-quality", "{quality}", "{format}:-"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+            resize_args: [
+                "-",
+                "-resize",
+                "{dimension}x{dimension}>",
+                "-quality",
+                "{quality}",
+                "{format}:-",
+            ]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect(),
+            timeout_secs: 30,
+        }
+    }
+}
+
+impl Default for ActivityReportConfig {
+    fn default() -> Self {
+        ActivityReportConfig {
+            enabled: true,
+            check_interval_secs: 3600,
+        }
+    }
+}
+
+impl Default for IntegrationConfig {
+    fn default() -> Self {
+        IntegrationConfig {
+            enabled: true,
+            rate_limit_per_minute: 120,
+            max_lifetime_days: 365,
+        }
+    }
+}
+
+impl Default for DeprecationConfig {
+    fn default() -> Self {
+        DeprecationConfig {
+            warn_clients: true,
+            sunsets: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            photos_dir: PathBuf::new(),
+            backups_dir: PathBuf::new(),
+            exports_dir: PathBuf::new(),
+            min_free_mb: 512,
+        }
+    }
+}
+
+impl Default for SyncBatchConfig {
+    fn default() -> Self {
+        SyncBatchConfig {
+            enabled: true,
+            max_batch_size: 500,
+            max_sync_limit: 10000,
+        }
+    }
+}
+
+impl Default for EventBusConfig {
+    fn default() -> Self {
+        EventBusConfig {
+            channel_capacity: 1024,
+        }
+    }
+}
+
+impl Default for SyncProgressConfig {
+    fn default() -> Self {
+        SyncProgressConfig {
+            enabled: true,
+            interval_ms: 1000,
+        }
+    }
+}
+
+impl Default for PhotoChunkConfig {
+    fn default() -> Self {
+        PhotoChunkConfig {
+            max_size_bytes: 26214400,
+            chunk_size_bytes: 262144,
+            upload_timeout_secs: 600,
+        }
+    }
+}
+
+impl Default for StatusPageConfig {
+    fn default() -> Self {
+        StatusPageConfig {
+            enabled: true,
+            api_key: String::new(),
+            cache_secs: 30,
+            rate_limit_per_minute: 30,
+        }
+    }
+}
+
+impl Default for PhotoRecompressionConfig {
+    fn default() -> Self {
+        PhotoRecompressionConfig {
+            enabled: false,
+            interval_hours: 24,
+            batch_size: 100,
+        }
+    }
+}
+
+impl Default for WsCompressionConfig {
+    fn default() -> Self {
+        WsCompressionConfig {
+            enabled: true,
+            min_bytes: 1024,
+            level: 6,
+            max_inflated_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for WireFormatConfig {
+    fn default() -> Self {
+        WireFormatConfig {
+            msgpack_enabled: true,
+        }
+    }
+}
+
+impl Default for ActivityFeedConfig {
+    fn default() -> Self {
+        ActivityFeedConfig {
+            enabled: true,
+            retention_days: 30,
+            max_entries: 10000,
+        }
+    }
+}
+
+impl Default for InstanceConfig {
+    fn default() -> Self {
+        InstanceConfig {
+            coordination_enabled: true,
+            lease_secs: 30,
+            renew_secs: 10,
+        }
+    }
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        ReplicationConfig {
+            mode: String::new(),
+            shared_secret: String::new(),
+            primary_url: String::new(),
+            poll_interval_secs: 2,
+            page_size: 500,
+        }
+    }
+}
+
+impl Default for SupportBundleConfig {
+    fn default() -> Self {
+        SupportBundleConfig {
+            dir: env::temp_dir(),
+            download_ttl_secs: 3600,
+            log_path: String::new(),
+            log_tail_bytes: 5 * 1024 * 1024,
+            anonymize_user_names: true,
+            coordinate_decimals: 2,
+            max_blob_bytes: 0,
+            scrub_columns: [
+                "locations.ownerInformation",
+                "locations.additionalInfo",
+                "contracts.additionalInfo",
+                "shipments.additionalInfo",
+                "notes.text",
+            ]
+            .iter()
+            .map(|column| column.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl Default for PhotoUrlConfig {
+    fn default() -> Self {
+        PhotoUrlConfig {
+            ttl_secs: 300,
+            signing_key: String::new(),
+        }
+    }
+}
+
+impl Default for RouteDistanceConfig {
+    fn default() -> Self {
+        RouteDistanceConfig {
+            routing_url: String::new(),
+            timeout_secs: 5,
+        }
+    }
+}
+
+impl Default for EventStreamConfig {
+    fn default() -> Self {
+        EventStreamConfig {
+            keep_alive_secs: 15,
+            channel_capacity: 1024,
+            buffered_events: 64,
+            compaction_enabled: false,
+            compaction_min_age_hours: 72,
+            compaction_interval_hours: 6,
+        }
+    }
+}
+
+impl Default for WriteBatchConfig {
+    fn default() -> Self {
+        WriteBatchConfig {
+            enabled: true,
+            burst_gap_ms: 50,
+            max_records: 250,
+            max_batch_ms: 1000,
+            message_types: [
+                "shipment_update",
+                "location_update",
+                "contract_update",
+                "note_update",
+                "sawmill_update",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+        }
+    }
+}
+
+impl Default for GateConfig {
+    fn default() -> Self {
+        GateConfig {
+            default_density_kg_per_cubic_meter: 900.0,
+            discrepancy_threshold_percent: 15.0,
+        }
+    }
+}
+
+impl Default for SchemaValidationConfig {
+    fn default() -> Self {
+        SchemaValidationConfig {
+            enabled: true,
+            reject_unknown_fields: false,
+        }
+    }
+}
+
+impl Default for SlaStatsConfig {
+    fn default() -> Self {
+        SlaStatsConfig {
+            enabled: true,
+            flush_interval_secs: 60,
+            retention_months: 13,
+        }
+    }
+}
+
+impl Default for WriteVerificationConfig {
+    fn default() -> Self {
+        WriteVerificationConfig {
+            enabled: true,
+            default_sample_rate: 1.0,
+        }
+    }
+}
+
+impl Default for HibernationConfig {
+    fn default() -> Self {
+        HibernationConfig {
+            enabled: true,
+            idle_days: 30,
+            check_interval_secs: 3600,
+        }
+    }
+}
+
+pub struct Options {
+    pub config_path: Option<PathBuf>,
+    pub print_config: bool,
+    pub selftest: bool,
+    pub test_fixture: bool,
+    pub generate_demo: Option<GenerateOptions>,
+    pub simulate_rules: Option<SimulationOptions>,
+    pub migrate_storage: Option<MigrationOptions>,
+}
+
+impl Options {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            config_path: env::var("HOLZ_CONFIG").ok().map(PathBuf::from),
+            print_config: false,
+            selftest: false,
+            test_fixture: false,
+            generate_demo: None,
+            simulate_rules: None,
+            migrate_storage: None,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let path = args.next().ok_or("--config requires a path")?;
+                    options.config_path = Some(PathBuf::from(path));
+                }
+                "--print-config" => options.print_config = true,
+                "--selftest" => options.selftest = true,
+                "--test-fixture" => options.test_fixture = true,
+                "--generate-demo" => {
+                    let tenant = args.next().ok_or("--generate-demo requires a tenant")?;
+                    options.generate_demo = Some(GenerateOptions::new(&tenant)?);
+                }
+                "--demo-append" => demo_options(&mut options)?.append = true,
+                "--simulate-rules" => {
+                    let tenant = args.next().ok_or("--simulate-rules requires a tenant")?;
+                    options.simulate_rules = Some(SimulationOptions::new(&tenant));
+                }
+                "--simulate-strict" => {
+                    options
+                        .simulate_rules
+                        .as_mut()
+                        .ok_or("--simulate-strict requires --simulate-rules <tenant> first")?
+                        .strict = true
+                }
+                "--migrate-storage" => {
+                    let from = args
+                        .next()
+                        .ok_or("--migrate-storage requires a directory")?;
+                    options.migrate_storage = Some(MigrationOptions::new(&from));
+                }
+                "--migrate-storage-dry-run" => {
+                    options
+                        .migrate_storage
+                        .as_mut()
+                        .ok_or("--migrate-storage-dry-run requires --migrate-storage <dir> first")?
+                        .dry_run = true
+                }
+                demo if demo.starts_with("--demo-") => {
+                    let value = args.next().ok_or(format!("{} requires a value", demo))?;
+                    demo_options(&mut options)?.set(demo, &value)?;
+                }
+                other => match other.strip_prefix("--config=") {
+                    Some(path) => options.config_path = Some(PathBuf::from(path)),
+                    None => return Err(format!("Unknown argument: {}", other)),
+                },
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn demo_options(options: &mut Options) -> Result<&mut GenerateOptions, String> {
+    options
+        .generate_demo
+        .as_mut()
+        .ok_or_else(|| "--demo-* options require --generate-demo <tenant> first".to_string())
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+impl Config {
+    pub fn load(config_path: Option<&Path>) -> Result<Self, String> {
+        let mut config = match config_path {
+            Some(path) => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                toml::from_str(&contents)
+                    .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?
+            }
+            None => Config::default(),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        env_override("PORT", &mut self.port)?;
+        env_override("DATABASES_DIR", &mut self.databases_dir)?;
+        env_override(
+            "PHOTO_INGEST_MAX_CONCURRENT",
+            &mut self.photo_ingest.max_concurrent,
+        )?;
+        env_override(
+            "PHOTO_INGEST_LARGE_PAYLOAD_BYTES",
+            &mut self.photo_ingest.large_payload_bytes,
+        )?;
+        env_override(
+            "PHOTO_SPILL_THRESHOLD_BYTES",
+            &mut self.photo_ingest.spill_threshold_bytes,
+        )?;
+        env_override("PHOTO_SPILL_DIR", &mut self.photo_ingest.spill_dir)?;
+        env_override(
+            "PLAUSIBILITY_MIN_PIECES_PER_CUBIC_METER",
+            &mut self.plausibility.min_pieces_per_cubic_meter,
+        )?;
+        env_override(
+            "PLAUSIBILITY_MAX_PIECES_PER_CUBIC_METER",
+            &mut self.plausibility.max_pieces_per_cubic_meter,
+        )?;
+        env_override(
+            "PLAUSIBILITY_MAX_SHIPMENT_QUANTITY",
+            &mut self.plausibility.max_shipment_quantity,
+        )?;
+        env_override("SNAPSHOT_DIR", &mut self.snapshots.dir)?;
+        env_override(
+            "SNAPSHOT_DOWNLOAD_TTL_SECS",
+            &mut self.snapshots.download_ttl_secs,
+        )?;
+        env_override(
+            "PORTAL_RATE_LIMIT_PER_MINUTE",
+            &mut self.portal.rate_limit_per_minute,
+        )?;
+        env_override(
+            "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+            &mut self.telemetry.otlp_endpoint,
+        )?;
+        env_override("OTEL_SERVICE_NAME", &mut self.telemetry.service_name)?;
+        env_override("TRACE_SAMPLE_RATIO", &mut self.telemetry.sample_ratio)?;
+        env_override(
+            "BROADCAST_BATCH_WINDOW_MS",
+            &mut self.broadcast.batch_window_ms,
+        )?;
+        env_override(
+            "BROADCAST_MAX_BATCH_SIZE",
+            &mut self.broadcast.max_batch_size,
+        )?;
+        env_override(
+            "BROADCAST_QUARANTINE_AFTER_FAILURES",
+            &mut self.broadcast.quarantine_after_failures,
+        )?;
+        env_override(
+            "BROADCAST_INCLUDE_PROVENANCE",
+            &mut self.broadcast.include_provenance,
+        )?;
+        env_override("BROADCAST_DELTA_UPDATES", &mut self.broadcast.delta_updates)?;
+        env_override(
+            "STUCK_CLIENT_THRESHOLD_MINUTES",
+            &mut self.stuck_clients.threshold_minutes,
+        )?;
+        env_override(
+            "STUCK_CLIENT_CHECK_INTERVAL_SECS",
+            &mut self.stuck_clients.check_interval_secs,
+        )?;
+        env_override(
+            "STUCK_CLIENT_DISCONNECT",
+            &mut self.stuck_clients.disconnect,
+        )?;
+        env_override("SCHEMA_CHECK_ON_STARTUP", &mut self.schema.check_on_startup)?;
+        env_override("SCHEMA_AUTO_REPAIR", &mut self.schema.auto_repair)?;
+        env_override(
+            "SYNC_MAX_CONCURRENT",
+            &mut self.sync_scheduler.max_concurrent,
+        )?;
+        env_override(
+            "SYNC_MAX_PER_TENANT",
+            &mut self.sync_scheduler.max_per_tenant,
+        )?;
+        env_override("ARCHIVE_ENABLED", &mut self.archive.enabled)?;
+        env_override("ARCHIVE_MIN_AGE_DAYS", &mut self.archive.min_age_days)?;
+        env_override("ARCHIVE_INTERVAL_HOURS", &mut self.archive.interval_hours)?;
+        env_override("COORDINATES_ALLOW_ZERO", &mut self.coordinates.allow_zero)?;
+        env_override("COORDINATES_ROUND", &mut self.coordinates.round)?;
+        env_override(
+            "COORDINATES_DECIMAL_PLACES",
+            &mut self.coordinates.decimal_places,
+        )?;
+        env_override(
+            "DIGEST_NOTIFICATION_URL",
+            &mut self.digests.notification_url,
+        )?;
+        env_override(
+            "DIGEST_CHECK_INTERVAL_SECS",
+            &mut self.digests.check_interval_secs,
+        )?;
+        env_override(
+            "SHIPMENT_DOCUMENT_MAX_SIZE_BYTES",
+            &mut self.shipment_documents.max_size_bytes,
+        )?;
+        env_override(
+            "SHIPMENT_DOCUMENT_CHUNK_SIZE_BYTES",
+            &mut self.shipment_documents.chunk_size_bytes,
+        )?;
+        env_override(
+            "SHIPMENT_DOCUMENT_UPLOAD_TIMEOUT_SECS",
+            &mut self.shipment_documents.upload_timeout_secs,
+        )?;
+        env_override("GRAPHQL_ENABLED", &mut self.graphql.enabled)?;
+        env_override("GRAPHQL_MAX_PAGE_SIZE", &mut self.graphql.max_page_size)?;
+        env_override("AUTH_CHALLENGE_TTL_SECS", &mut self.auth.challenge_ttl_secs)?;
+        env_override("AUTH_REQUIRE_CHALLENGE", &mut self.auth.require_challenge)?;
+        env_override("SCRIPTING_ENABLED", &mut self.scripting.enabled)?;
+        env_override("SCRIPTING_MAX_STEPS", &mut self.scripting.max_steps)?;
+        env_override("SCRIPTING_TIMEOUT_MS", &mut self.scripting.timeout_ms)?;
+        env_override(
+            "SCRIPTING_MAX_SCRIPT_BYTES",
+            &mut self.scripting.max_script_bytes,
+        )?;
+        env_override(
+            "SCRIPTING_MAX_STRING_BYTES",
+            &mut self.scripting.max_string_bytes,
+        )?;
+        env_override("CAPACITY_ENABLED", &mut self.capacity.enabled)?;
+        env_override("CAPACITY_API_KEY", &mut self.capacity.api_key)?;
+        env_override(
+            "CAPACITY_REFRESH_INTERVAL_SECS",
+            &mut self.capacity.refresh_interval_secs,
+        )?;
+        env_override("DB_POOL_MAX_SIZE", &mut self.db_pool.max_size)?;
+        env_override(
+            "DB_POOL_ACQUIRE_TIMEOUT_MS",
+            &mut self.db_pool.acquire_timeout_ms,
+        )?;
+        env_override("DB_POOL_ACQUIRE_RETRIES", &mut self.db_pool.acquire_retries)?;
+        env_override(
+            "DB_POOL_RETRY_BACKOFF_MS",
+            &mut self.db_pool.retry_backoff_ms,
+        )?;
+        env_override(
+            "WATCHDOG_MESSAGE_TIMEOUT_MS",
+            &mut self.watchdog.message_timeout_ms,
+        )?;
+        env_override(
+            "QUOTA_RESERVATION_TTL_HOURS",
+            &mut self.quota.reservation_ttl_hours,
+        )?;
+        env_override(
+            "QUOTA_EXPIRY_CHECK_INTERVAL_SECS",
+            &mut self.quota.expiry_check_interval_secs,
+        )?;
+        env_override("PHOTO_TRANSCODE_COMMAND", &mut self.photo_transcode.command)?;
+        env_override(
+            "PHOTO_TRANSCODE_TIMEOUT_SECS",
+            &mut self.photo_transcode.timeout_secs,
+        )?;
+        env_override(
+            "ACTIVITY_REPORTS_ENABLED",
+            &mut self.activity_reports.enabled,
+        )?;
+        env_override(
+            "ACTIVITY_REPORTS_CHECK_INTERVAL_SECS",
+            &mut self.activity_reports.check_interval_secs,
+        )?;
+        env_override("SUPPORT_BUNDLE_DIR", &mut self.support_bundle.dir)?;
+        env_override("SUPPORT_BUNDLE_LOG_PATH", &mut self.support_bundle.log_path)?;
+        env_override("PHOTO_URL_TTL_SECS", &mut self.photo_urls.ttl_secs)?;
+        env_override("PHOTO_URL_SIGNING_KEY", &mut self.photo_urls.signing_key)?;
+        env_override("SELFTEST_ON_STARTUP", &mut self.selftest.on_startup)?;
+        env_override(
+            "ROUTE_DISTANCE_ROUTING_URL",
+            &mut self.route_distance.routing_url,
+        )?;
+        env_override(
+            "ROUTE_DISTANCE_TIMEOUT_SECS",
+            &mut self.route_distance.timeout_secs,
+        )?;
+        env_override(
+            "EVENT_STREAM_KEEP_ALIVE_SECS",
+            &mut self.event_stream.keep_alive_secs,
+        )?;
+        env_override(
+            "EVENT_STREAM_COMPACTION_ENABLED",
+            &mut self.event_stream.compaction_enabled,
+        )?;
+        env_override("WRITE_BATCH_ENABLED", &mut self.write_batch.enabled)?;
+        env_override(
+            "WRITE_BATCH_BURST_GAP_MS",
+            &mut self.write_batch.burst_gap_ms,
+        )?;
+        env_override("WRITE_BATCH_MAX_RECORDS", &mut self.write_batch.max_records)?;
+        env_override("CLIENT_MIN_VERSION", &mut self.client_compat.min_version)?;
+        env_override(
+            "GATE_DISCREPANCY_THRESHOLD_PERCENT",
+            &mut self.gate.discrepancy_threshold_percent,
+        )?;
+        env_override(
+            "SCHEMA_VALIDATION_ENABLED",
+            &mut self.schema_validation.enabled,
+        )?;
+        env_override("SLA_STATS_ENABLED", &mut self.sla_stats.enabled)?;
+        env_override(
+            "WRITE_VERIFICATION_ENABLED",
+            &mut self.write_verification.enabled,
+        )?;
+        env_override("HIBERNATION_ENABLED", &mut self.hibernation.enabled)?;
+        env_override("HIBERNATION_IDLE_DAYS", &mut self.hibernation.idle_days)?;
+        env_override("INTEGRATIONS_ENABLED", &mut self.integrations.enabled)?;
+        env_override(
+            "INTEGRATIONS_RATE_LIMIT_PER_MINUTE",
+            &mut self.integrations.rate_limit_per_minute,
+        )?;
+        env_override(
+            "DEPRECATIONS_WARN_CLIENTS",
+            &mut self.deprecations.warn_clients,
+        )?;
+        env_override("STORAGE_PHOTOS_DIR", &mut self.storage.photos_dir)?;
+        env_override("STORAGE_BACKUPS_DIR", &mut self.storage.backups_dir)?;
+        env_override("STORAGE_EXPORTS_DIR", &mut self.storage.exports_dir)?;
+        env_override("STORAGE_MIN_FREE_MB", &mut self.storage.min_free_mb)?;
+        env_override("SYNC_BATCH_ENABLED", &mut self.sync_batch.enabled)?;
+        env_override(
+            "SYNC_BATCH_MAX_BATCH_SIZE",
+            &mut self.sync_batch.max_batch_size,
+        )?;
+        env_override(
+            "SYNC_BATCH_MAX_SYNC_LIMIT",
+            &mut self.sync_batch.max_sync_limit,
+        )?;
+        env_override(
+            "EVENT_BUS_CHANNEL_CAPACITY",
+            &mut self.event_bus.channel_capacity,
+        )?;
+        env_override("SYNC_PROGRESS_ENABLED", &mut self.sync_progress.enabled)?;
+        env_override(
+            "SYNC_PROGRESS_INTERVAL_MS",
+            &mut self.sync_progress.interval_ms,
+        )?;
+        env_override(
+            "PHOTO_CHUNK_MAX_SIZE_BYTES",
+            &mut self.photo_chunks.max_size_bytes,
+        )?;
+        env_override(
+            "PHOTO_CHUNK_SIZE_BYTES",
+            &mut self.photo_chunks.chunk_size_bytes,
+        )?;
+        env_override(
+            "PHOTO_CHUNK_UPLOAD_TIMEOUT_SECS",
+            &mut self.photo_chunks.upload_timeout_secs,
+        )?;
+        env_override("STATUS_PAGE_ENABLED", &mut self.status_page.enabled)?;
+        env_override("STATUS_PAGE_API_KEY", &mut self.status_page.api_key)?;
+        env_override("STATUS_PAGE_CACHE_SECS", &mut self.status_page.cache_secs)?;
+        env_override(
+            "STATUS_PAGE_RATE_LIMIT_PER_MINUTE",
+            &mut self.status_page.rate_limit_per_minute,
+        )?;
+        env_override(
+            "PHOTO_RECOMPRESSION_ENABLED",
+            &mut self.photo_recompression.enabled,
+        )?;
+        env_override(
+            "PHOTO_RECOMPRESSION_INTERVAL_HOURS",
+            &mut self.photo_recompression.interval_hours,
+        )?;
+        env_override(
+            "PHOTO_RECOMPRESSION_BATCH_SIZE",
+            &mut self.photo_recompression.batch_size,
+        )?;
+        env_override("WS_COMPRESSION_ENABLED", &mut self.ws_compression.enabled)?;
+        env_override(
+            "WS_COMPRESSION_MIN_BYTES",
+            &mut self.ws_compression.min_bytes,
+        )?;
+        env_override("WS_COMPRESSION_LEVEL", &mut self.ws_compression.level)?;
+        env_override(
+            "WS_COMPRESSION_MAX_INFLATED_BYTES",
+            &mut self.ws_compression.max_inflated_bytes,
+        )?;
+        env_override(
+            "WIRE_FORMAT_MSGPACK_ENABLED",
+            &mut self.wire_format.msgpack_enabled,
+        )?;
+        env_override("ACTIVITY_FEED_ENABLED", &mut self.activity_feed.enabled)?;
+        env_override(
+            "ACTIVITY_FEED_RETENTION_DAYS",
+            &mut self.activity_feed.retention_days,
+        )?;
+        env_override(
+            "ACTIVITY_FEED_MAX_ENTRIES",
+            &mut self.activity_feed.max_entries,
+        )?;
+        env_override(
+            "INSTANCE_COORDINATION_ENABLED",
+            &mut self.instance.coordination_enabled,
+        )?;
+        env_override("INSTANCE_LEASE_SECS", &mut self.instance.lease_secs)?;
+        env_override("INSTANCE_RENEW_SECS", &mut self.instance.renew_secs)?;
+        env_override("REPLICATION_MODE", &mut self.replication.mode)?;
+        env_override(
+            "REPLICATION_SHARED_SECRET",
+            &mut self.replication.shared_secret,
+        )?;
+        env_override("REPLICATION_PRIMARY_URL", &mut self.replication.primary_url)?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.photo_ingest.max_concurrent == 0 {
+            return Err("photo_ingest.max_concurrent must be at least 1".to_string());
+        }
+        if self.plausibility.min_pieces_per_cubic_meter
+            > self.plausibility.max_pieces_per_cubic_meter
+        {
+            return Err(
+                "plausibility.min_pieces_per_cubic_meter must not exceed max_pieces_per_cubic_meter"
+                    .to_string(),
+            );
+        }
+        if self.plausibility.max_shipment_quantity <= 0.0 {
+            return Err("plausibility.max_shipment_quantity must be positive".to_string());
+        }
+        if self.portal.rate_limit_per_minute == 0 {
+            return Err("portal.rate_limit_per_minute must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.telemetry.sample_ratio) {
+            return Err("telemetry.sample_ratio must be between 0 and 1".to_string());
+        }
+        if self.sync_batch.max_batch_size == 0 {
+            return Err("sync_batch.max_batch_size must be at least 1".to_string());
+        }
+        if self.sync_batch.max_sync_limit == 0 {
+            return Err("sync_batch.max_sync_limit must be at least 1".to_string());
+        }
+        if self.event_bus.channel_capacity == 0 {
+            return Err("event_bus.channel_capacity must be at least 1".to_string());
+        }
+        if self.photo_chunks.chunk_size_bytes == 0
+            || self.photo_chunks.chunk_size_bytes > self.photo_chunks.max_size_bytes
+        {
+            return Err(
+                "photo_chunks.chunk_size_bytes must be between 1 and max_size_bytes".to_string(),
+            );
+        }
+        if self.status_page.rate_limit_per_minute == 0 {
+            return Err("status_page.rate_limit_per_minute must be at least 1".to_string());
+        }
+        if !self.status_page.api_key.is_empty() && self.status_page.api_key.len() < 16 {
+            return Err("status_page.api_key must be at least 16 characters when set".to_string());
+        }
+        if self.photo_recompression.interval_hours == 0 || self.photo_recompression.batch_size == 0
+        {
+            return Err(
+                "photo_recompression.interval_hours and batch_size must be at least 1".to_string(),
+            );
+        }
+        if !(1..=9).contains(&self.ws_compression.level) {
+            return Err("ws_compression.level must be between 1 and 9".to_string());
+        }
+        if self.ws_compression.max_inflated_bytes == 0 {
+            return Err("ws_compression.max_inflated_bytes must be at least 1".to_string());
+        }
+        if self.activity_feed.retention_days == 0 || self.activity_feed.max_entries == 0 {
+            return Err(
+                "activity_feed.retention_days and max_entries must be at least 1".to_string(),
+            );
+        }
+        if self.instance.renew_secs == 0 || self.instance.lease_secs <= 2 * self.instance.renew_secs
+        {
+            return Err(
+                "instance.renew_secs must be at least 1 and lease_secs more than twice renew_secs"
+                    .to_string(),
+            );
+        }
+        match self.replication.mode.as_str() {
+            "" => {}
+            "primary" | "standby" if self.replication.shared_secret.len() < 32 => {
+                return Err("replication.shared_secret must be at least 32 characters".to_string());
+            }
+            "primary" => {}
+            "standby" if self.replication.primary_url.is_empty() => {
+                return Err("replication.primary_url is required on a standby".to_string());
+            }
+            "standby" => {}
+            other => {
+                return Err(format!(
+                    "replication.mode must be empty, primary or standby, not {}",
+                    other
+                ));
+            }
+        }
+        if self.replication.poll_interval_secs == 0 || self.replication.page_size <= 0 {
+            return Err(
+                "replication.poll_interval_secs and page_size must be at least 1".to_string(),
+            );
+        }
+        if self.broadcast.max_batch_size == 0 {
+            return Err("broadcast.max_batch_size must be at least 1".to_string());
+        }
+        if self.broadcast.quarantine_after_failures == 0 {
+            return Err("broadcast.quarantine_after_failures must be at least 1".to_string());
+        }
+        if self.sync_scheduler.max_concurrent == 0 || self.sync_scheduler.max_per_tenant == 0 {
+            return Err(
+                "sync_scheduler.max_concurrent and max_per_tenant must be at least 1".to_string(),
+            );
+        }
+        if self.stuck_clients.threshold_minutes == 0 || self.stuck_clients.check_interval_secs == 0
+        {
+            return Err(
+                "stuck_clients.threshold_minutes and check_interval_secs must be at least 1"
+                    .to_string(),
+            );
+        }
+        if self.archive.min_age_days == 0 || self.archive.interval_hours == 0 {
+            return Err("archive.min_age_days and interval_hours must be at least 1".to_string());
+        }
+        if self.coordinates.decimal_places > 10 {
+            return Err("coordinates.decimal_places must not exceed 10".to_string());
+        }
+        if self.digests.check_interval_secs == 0 {
+            return Err("digests.check_interval_secs must be at least 1".to_string());
+        }
+        if self.shipment_documents.chunk_size_bytes == 0
+            || self.shipment_documents.chunk_size_bytes > self.shipment_documents.max_size_bytes
+        {
+            return Err(
+                "shipment_documents.chunk_size_bytes must be between 1 and max_size_bytes"
+                    .to_string(),
+            );
+        }
+        if self.graphql.max_page_size < 1 {
+            return Err("graphql.max_page_size must be at least 1".to_string());
+        }
+        if self.auth.challenge_ttl_secs == 0 {
+            return Err("auth.challenge_ttl_secs must be at least 1".to_string());
+        }
+        if self.scripting.max_steps == 0 || self.scripting.timeout_ms == 0 {
+            return Err(
+                "scripting.max_steps and scripting.timeout_ms must be at least 1".to_string(),
+            );
+        }
+        if self.scripting.max_script_bytes == 0 || self.scripting.max_string_bytes == 0 {
+            return Err(
+                "scripting.max_script_bytes and scripting.max_string_bytes must be at least 1"
+                    .to_string(),
+            );
+        }
+        if self.capacity.refresh_interval_secs == 0 {
+            return Err("capacity.refresh_interval_secs must be at least 1".to_string());
+        }
+        if self.capacity.enabled && self.capacity.api_key.len() < 16 {
+            return Err("capacity.api_key must be at least 16 characters when enabled".to_string());
+        }
+        if self.db_pool.max_size == 0 || self.db_pool.acquire_timeout_ms == 0 {
+            return Err(
+                "db_pool.max_size and db_pool.acquire_timeout_ms must be at least 1".to_string(),
+            );
+        }
+        if self.watchdog.message_timeout_ms == 0 {
+            return Err("watchdog.message_timeout_ms must be at least 1".to_string());
+        }
+        if self.quota.reservation_ttl_hours == 0 || self.quota.expiry_check_interval_secs == 0 {
+            return Err(
+                "quota.reservation_ttl_hours and expiry_check_interval_secs must be at least 1"
+                    .to_string(),
+            );
+        }
+        if self.photo_transcode.command.trim().is_empty() || self.photo_transcode.timeout_secs == 0
+        {
+            return Err(
+                "photo_transcode.command must be set and timeout_secs must be at least 1"
+                    .to_string(),
+            );
+        }
+        if self.activity_reports.check_interval_secs == 0 {
+            return Err("activity_reports.check_interval_secs must be at least 1".to_string());
+        }
+        if self.support_bundle.download_ttl_secs == 0 {
+            return Err("support_bundle.download_ttl_secs must be at least 1".to_string());
+        }
+        if self.support_bundle.coordinate_decimals > 6 {
+            return Err("support_bundle.coordinate_decimals must not exceed 6".to_string());
+        }
+        if let Some(column) = self
+            .support_bundle
+            .scrub_columns
+            .iter()
+            .find(|column| column.split_once('.').is_none())
+        {
+            return Err(format!(
+                "support_bundle.scrub_columns entry {} must be table.column",
+                column
+            ));
+        }
+        if self.photo_urls.ttl_secs == 0 {
+            return Err("photo_urls.ttl_secs must be at least 1".to_string());
+        }
+        if !self.photo_urls.signing_key.is_empty() && self.photo_urls.signing_key.len() < 16 {
+            return Err("photo_urls.signing_key must be at least 16 characters".to_string());
+        }
+        if self.route_distance.timeout_secs == 0 {
+            return Err("route_distance.timeout_secs must be at least 1".to_string());
+        }
+        if self.event_stream.keep_alive_secs == 0
+            || self.event_stream.channel_capacity == 0
+            || self.event_stream.buffered_events == 0
+        {
+            return Err(
+                "event_stream.keep_alive_secs, channel_capacity and buffered_events must be at least 1"
+                    .to_string(),
+            );
+        }
+        if self.event_stream.compaction_interval_hours == 0 {
+            return Err("event_stream.compaction_interval_hours must be at least 1".to_string());
+        }
+        if self.write_batch.burst_gap_ms == 0
+            || self.write_batch.max_records == 0
+            || self.write_batch.max_batch_ms == 0
+        {
+            return Err(
+                "write_batch.burst_gap_ms, max_records and max_batch_ms must be at least 1"
+                    .to_string(),
+            );
+        }
+        if !self.client_compat.legacy_protocol_sunset.is_empty()
+            && chrono::NaiveDate::parse_from_str(
+                &self.client_compat.legacy_protocol_sunset,
+                "%Y-%m-%d",
+            )
+            .is_err()
+        {
+            return Err(
+                "client_compat.legacy_protocol_sunset must be a YYYY-MM-DD date".to_string(),
+            );
+        }
+        if self.gate.default_density_kg_per_cubic_meter <= 0.0 {
+            return Err("gate.default_density_kg_per_cubic_meter must be positive".to_string());
+        }
+        if self.gate.discrepancy_threshold_percent <= 0.0 {
+            return Err("gate.discrepancy_threshold_percent must be positive".to_string());
+        }
+        if self.sla_stats.flush_interval_secs == 0 || self.sla_stats.retention_months == 0 {
+            return Err(
+                "sla_stats.flush_interval_secs and retention_months must be at least 1".to_string(),
+            );
+        }
+        if self.write_verification.default_sample_rate <= 0.0
+            || self.write_verification.default_sample_rate > 1.0
+        {
+            return Err(
+                "write_verification.default_sample_rate must be above 0 and at most 1".to_string(),
+            );
+        }
+        if self.hibernation.check_interval_secs == 0 {
+            return Err("hibernation.check_interval_secs must be at least 1".to_string());
+        }
+        if self.integrations.rate_limit_per_minute == 0 {
+            return Err("integrations.rate_limit_per_minute must be at least 1".to_string());
+        }
+        if self.integrations.max_lifetime_days == 0 {
+            return Err("integrations.max_lifetime_days must be at least 1".to_string());
+        }
+        if let Some((id, sunset)) = self
+            .deprecations
+            .sunsets
+            .iter()
+            .find(|(_, sunset)| chrono::NaiveDate::parse_from_str(sunset, "%Y-%m-%d").is_err())
+        {
+            return Err(format!(
+                "deprecations.sunsets.{} must be a YYYY-MM-DD date, got {}",
+                id, sunset
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn render(&self) -> String {
+        toml::to_string_pretty(self).unwrap_or_else(|e| format!("# Failed to render config: {}", e))
+    }
+}
+
+pub fn init(options: &Options) -> Result<&'static Config, String> {
+    let mut config = Config::load(options.config_path.as_deref())?;
+    if options.test_fixture {
+        test_fixture::configure(&mut config);
+    }
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+pub fn init_with(
+    build: impl FnOnce() -> Result<Config, String>,
+) -> Result<&'static Config, String> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+    let config = build()?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(|| Config::load(None).unwrap_or_else(|e| panic!("{}", e)))
+}
+
+fn env_override<T: FromStr>(key: &str, target: &mut T) -> Result<(), String> {
+    if let Ok(value) = env::var(key) {
+        *target = value
+            .parse()
+            .map_err(|_| format!("{} has an invalid value: {}", key, value))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,403 @@
+use crate::ROLE_ADMIN;
+use crate::local_storage::conflict::conflict_local_storage::{
+    ConflictLocalStorage, STATUS_OPEN, STATUS_RESOLVED,
+};
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::delta;
+use crate::local_storage::event_log::event_log_local_storage::EventLogLocalStorage;
+use crate::update_schema;
+use serde_json::{Value, json};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const IGNORED_FIELDS: &[&str] = &["lastEdit", "arrivalAtServer", "synced"];
+const BASE_FIELD: &str = "baseLastEdit";
+const RESOLUTIONS: &[&str] = &["local", "server", "merged"];
+const DEFAULT_LIST_LIMIT: u64 = 50;
+const MAX_LIST_LIMIT: u64 = 200;
+
+/// Context for resolving a concurrent edit. The client sends its own version
+/// of the entity and the lastEdit it started editing from; the reply holds
+/// that common ancestor as recorded in the event log, the server's current
+/// version and a per-field three-way diff.
+pub fn detail(
+    data: &Value,
+    role: i64,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let msg_type = data["entity"].as_str().unwrap_or_default();
+    let table = update_schema::table_for(msg_type)
+        .ok_or(("VALIDATION_FAILED", format!("Unknown entity: {}", msg_type)))?;
+    let id = data["id"]
+        .as_str()
+        .ok_or(("VALIDATION_FAILED", "id is required".to_string()))?;
+    let local = &data["local"];
+    if !local.is_object() {
+        return Err(("VALIDATION_FAILED", "local must be an object".to_string()));
+    }
+    let base_last_edit = data["baseLastEdit"]
+        .as_i64()
+        .ok_or(("VALIDATION_FAILED", "baseLastEdit is required".to_string()))?;
+
+    let server = server_version(msg_type, table, id, core_storage.clone())
+        .map_err(|e| ("INTERNAL", format!("Failed to load {}: {:?}", id, e)))?
+        .ok_or(("NOT_FOUND", format!("{} {} not found", msg_type, id)))?;
+
+    let ancestor = ancestor(msg_type, id, base_last_edit, role, core_storage).map_err(|e| {
+        (
+            "INTERNAL",
+            format!("Failed to load history of {}: {:?}", id, e),
+        )
+    })?;
+
+    let fields = diff(
+        ancestor.as_ref().map(|version| &version["data"]),
+        local,
+        &server,
+    );
+
+    Ok(json!({
+        "entity": msg_type,
+        "id": id,
+        "ancestor": ancestor,
+        "local": local,
+        "server": server,
+        "fields": fields
+    }))
+}
+
+fn server_version(
+    msg_type: &str,
+    table: &str,
+    id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+) -> rusqlite::Result<Option<Value>> {
+    if delta::DELTA_TYPES.contains(&msg_type) {
+        delta::base_version(core_storage, msg_type, id)
+    } else {
+        core_storage
+            .get_by_id(table, id)
+            .map(|rows| rows.into_iter().next())
+    }
+}
+
+/// The version with lastEdit `base_last_edit` as recorded in the event log.
+/// Client updates are never compacted, so it is found however old it is.
+fn ancestor(
+    msg_type: &str,
+    id: &str,
+    base_last_edit: i64,
+    role: i64,
+    core_storage: Arc<CoreLocalStorage>,
+) -> rusqlite::Result<Option<Value>> {
+    EventLogLocalStorage::new(core_storage)?.get_entity_version(msg_type, id, role, base_last_edit)
+}
+
+/// Splits the lastEdit a client started editing from off an update, so it
+/// is neither validated nor stored as a column.
+pub fn split_base(msg_type: &str, data: &Value) -> Option<(Value, i64)> {
+    update_schema::table_for(msg_type)?;
+    let base_last_edit = data.get(BASE_FIELD)?.as_i64()?;
+
+    let mut data = data.clone();
+    data.as_object_mut()?.remove(BASE_FIELD);
+    Some((data, base_last_edit))
+}
+
+/// Detects an update that was made concurrently with another one: the
+/// server's version changed after `base_last_edit`. The later lastEdit
+/// still wins as before; a conflict is only returned when that loses
+/// changes of the other side. It is recorded once the update went through.
+pub fn detect(
+    msg_type: &str,
+    data: &Value,
+    base_last_edit: i64,
+    user_id: Option<&str>,
+    role: i64,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Option<Value> {
+    let table = update_schema::table_for(msg_type)?;
+    let id = data["id"].as_str()?;
+
+    let server = match server_version(msg_type, table, id, core_storage.clone()) {
+        Ok(server) => server?,
+        Err(e) => {
+            println!("Failed to load {} for conflict detection: {:?}", id, e);
+            return None;
+        }
+    };
+    let server_last_edit = server["lastEdit"].as_i64()?;
+    if server_last_edit <= base_last_edit {
+        return None;
+    }
+
+    let winner = if data["lastEdit"].as_i64().unwrap_or(0) > server_last_edit {
+        "local"
+    } else {
+        "server"
+    };
+    let ancestor = ancestor(msg_type, id, base_last_edit, role, core_storage).unwrap_or_else(|e| {
+        println!("Failed to load history of {}: {:?}", id, e);
+        None
+    });
+    let fields = diff(
+        ancestor.as_ref().map(|version| &version["data"]),
+        data,
+        &server,
+    );
+    let loser = if winner == "local" { "server" } else { "local" };
+    if !fields
+        .iter()
+        .any(|field| field["status"] == "conflict" || field["status"] == loser)
+    {
+        return None;
+    }
+
+    Some(json!({
+        "id": Uuid::new_v4().to_string(),
+        "lastEdit": chrono::Utc::now().timestamp_millis(),
+        "entityType": msg_type,
+        "entityId": id,
+        "userId": user_id,
+        "baseLastEdit": base_last_edit,
+        "localVersion": data,
+        "serverVersion": server,
+        "fields": fields,
+        "winner": winner,
+        "status": STATUS_OPEN
+    }))
+}
+
+pub fn record(conflict: &Value, core_storage: Arc<CoreLocalStorage>) -> rusqlite::Result<()> {
+    ConflictLocalStorage::new(core_storage)?.save_conflict(conflict)
+}
+
+/// Answers conflict_list. Admins see every conflict of the tenant, other
+/// users those recorded for their own updates.
+pub fn list(
+    data: &Value,
+    user_id: &str,
+    role: i64,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let status = match data.get("status") {
+        None | Some(Value::Null) => None,
+        Some(status) => Some(
+            status
+                .as_str()
+                .filter(|status| [STATUS_OPEN, STATUS_RESOLVED].contains(status))
+                .ok_or((
+                    "VALIDATION_FAILED",
+                    format!("status must be {} or {}", STATUS_OPEN, STATUS_RESOLVED),
+                ))?,
+        ),
+    };
+    let limit = match data.get("limit") {
+        None | Some(Value::Null) => DEFAULT_LIST_LIMIT,
+        Some(limit) => limit
+            .as_u64()
+            .filter(|limit| (1..=MAX_LIST_LIMIT).contains(limit))
+            .ok_or((
+                "VALIDATION_FAILED",
+                format!("limit must be between 1 and {}", MAX_LIST_LIMIT),
+            ))?,
+    };
+
+    let conflicts = ConflictLocalStorage::new(core_storage)
+        .and_then(|storage| {
+            storage.get_conflicts(
+                status,
+                (role < ROLE_ADMIN).then_some(user_id),
+                limit as usize,
+            )
+        })
+        .map_err(|e| ("INTERNAL", format!("Failed to load conflicts: {:?}", e)))?;
+
+    Ok(json!({ "conflicts": conflicts }))
+}
+
+/// Answers conflict_resolve. The user whose update caused the conflict or an
+/// admin marks it resolved; the entity itself is corrected with a regular
+/// update.
+pub fn resolve(
+    data: &Value,
+    user_id: &str,
+    role: i64,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let id = data["id"]
+        .as_str()
+        .ok_or(("VALIDATION_FAILED", "id is required".to_string()))?;
+    let resolution = data["resolution"]
+        .as_str()
+        .filter(|resolution| RESOLUTIONS.contains(resolution))
+        .ok_or((
+            "VALIDATION_FAILED",
+            format!("resolution must be one of {}", RESOLUTIONS.join(", ")),
+        ))?;
+
+    let internal = |e: rusqlite::Error| {
+        (
+            "INTERNAL",
+            format!("Failed to resolve conflict {}: {:?}", id, e),
+        )
+    };
+    let storage = ConflictLocalStorage::new(core_storage).map_err(internal)?;
+    let conflict = storage
+        .get_conflict(id)
+        .map_err(internal)?
+        .ok_or(("NOT_FOUND", format!("Conflict {} not found", id)))?;
+    if role < ROLE_ADMIN && conflict["userId"].as_str() != Some(user_id) {
+        return Err((
+            "PERMISSION_DENIED",
+            "Only admins may resolve conflicts of other users".to_string(),
+        ));
+    }
+    if !storage.resolve(id, resolution, user_id).map_err(internal)? {
+        return Err((
+            "ALREADY_RESOLVED",
+            format!("Conflict {} is already resolved", id),
+        ));
+    }
+
+    storage
+        .get_conflict(id)
+        .map_err(internal)?
+        .ok_or(("NOT_FOUND", format!("Conflict {} not found", id)))
+}
+
+/// Fields of the local version that differ between any of the versions.
+/// Partial updates only send the fields they change, so the others are left
+/// out. Without an ancestor every difference between local and server
+/// counts as a conflict.
+fn diff(ancestor: Option<&Value>, local: &Value, server: &Value) -> Vec<Value> {
+    let names: BTreeSet<&String> = local
+        .as_object()
+        .into_iter()
+        .flat_map(|version| version.keys())
+        .filter(|name| !IGNORED_FIELDS.contains(&name.as_str()))
+        .collect();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let local_value = &local[name];
+            let server_value = &server[name];
+            let status = match ancestor.map(|ancestor| &ancestor[name]) {
+                Some(base) => match (local_value != base, server_value != base) {
+                    (false, false) => return None,
+                    (true, false) => "local",
+                    (false, true) => "server",
+                    (true, true) if local_value == server_value => "same",
+                    (true, true) => "conflict",
+                },
+                None if local_value == server_value => return None,
+                None => "conflict",
+            };
+
+            Some(json!({
+                "field": name,
+                "status": status,
+                "ancestor": ancestor.map(|ancestor| &ancestor[name]),
+                "local": local_value,
+                "server": server_value
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_storage::event_log::event_log_local_storage::Provenance;
+    use crate::local_storage::schema;
+
+    fn storage() -> Arc<CoreLocalStorage> {
+        let core_storage = CoreLocalStorage::new(":memory:").unwrap();
+        schema::migrate(&core_storage.get_connection().unwrap()).unwrap();
+        Arc::new(core_storage)
+    }
+
+    fn log(core_storage: &Arc<CoreLocalStorage>, data: Value) {
+        let timestamp = data["lastEdit"].as_i64().unwrap();
+        EventLogLocalStorage::new(core_storage.clone())
+            .unwrap()
+            .append("note_update", 0, &data, timestamp, &Provenance::default())
+            .unwrap();
+    }
+
+    fn statuses(fields: &[Value]) -> Vec<(&str, &str)> {
+        fields
+            .iter()
+            .map(|field| {
+                (
+                    field["field"].as_str().unwrap(),
+                    field["status"].as_str().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn partial_updates_only_compare_the_fields_they_send() {
+        let ancestor = json!({"id": "n1", "text": "a", "entityId": "l1"});
+        let local = json!({"id": "n1", "text": "b"});
+        let server = json!({"id": "n1", "text": "a", "entityId": "l2"});
+
+        let fields = diff(Some(&ancestor), &local, &server);
+
+        assert_eq!(statuses(&fields), [("text", "local")]);
+    }
+
+    #[test]
+    fn ancestors_are_rebuilt_from_every_logged_version() {
+        let core_storage = storage();
+        log(
+            &core_storage,
+            json!({"id": "n1", "lastEdit": 100, "text": "a", "entityId": "l1"}),
+        );
+        for last_edit in 101..700 {
+            log(&core_storage, json!({"id": "n1", "lastEdit": last_edit}));
+        }
+        log(
+            &core_storage,
+            json!({"id": "n1", "lastEdit": 700, "text": "b"}),
+        );
+
+        let ancestor = ancestor("note_update", "n1", 101, 0, core_storage)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(ancestor["data"]["text"], "a");
+        assert_eq!(ancestor["data"]["entityId"], "l1");
+        assert_eq!(ancestor["data"]["lastEdit"], 101);
+    }
+
+    #[test]
+    fn missing_ancestors_only_flag_fields_the_update_sends() {
+        let core_storage = storage();
+        core_storage
+            .insert(
+                "notes",
+                &json!({
+                    "id": "n1",
+                    "lastEdit": 200,
+                    "text": "server",
+                    "userId": "u2",
+                    "arrivalAtServer": 200,
+                    "entityId": "l2"
+                }),
+            )
+            .unwrap();
+        let update = json!({"id": "n1", "lastEdit": 300, "text": "local"});
+
+        let conflict = detect("note_update", &update, 100, Some("u1"), 0, core_storage).unwrap();
+
+        assert_eq!(conflict["winner"], "local");
+        assert_eq!(
+            statuses(conflict["fields"].as_array().unwrap()),
+            [("text", "conflict")]
+        );
+    }
+}
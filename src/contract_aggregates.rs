@@ -0,0 +1,223 @@
+use crate::client_admin;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{Clients, DbPoolMap, ROLE_ADMIN, broadcast_to_tenant, with_clients, with_db_pools};
+use rusqlite::params;
+use serde_json::{Value, json};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const QUANTITY_EPSILON: f64 = 1e-6;
+
+struct Drift {
+    contract_id: String,
+    title: String,
+    stored_booked: f64,
+    computed_booked: f64,
+    stored_shipped: f64,
+    computed_shipped: f64,
+}
+
+impl Drift {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.contract_id,
+            "title": self.title,
+            "bookedQuantity": {
+                "stored": self.stored_booked,
+                "computed": self.computed_booked
+            },
+            "shippedQuantity": {
+                "stored": self.stored_shipped,
+                "computed": self.computed_shipped
+            }
+        })
+    }
+}
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("admin" / "contracts" / "recompute")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |authorization, body, clients: Clients, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    recompute(authorization, body, &clients, &db_pools).await,
+                ))
+            },
+        )
+}
+
+async fn recompute(
+    authorization: Option<String>,
+    body: Value,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let admin = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let contract_id = match &body["contractId"] {
+        Value::Null => None,
+        Value::String(id) => Some(id.as_str()),
+        _ => {
+            return Err(rest::error_reply(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "contractId must be a string",
+            ));
+        }
+    };
+    let dry_run = body["dryRun"].as_bool().unwrap_or(false);
+
+    let core_storage = rest::open_storage(&admin.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let (checked, drifts) = find_drift(&core_storage, contract_id)
+        .map_err(|e| rest::internal_error("Failed to recompute contract aggregates", e))?;
+    if let Some(contract_id) = contract_id
+        && checked == 0
+    {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            &format!("Contract {} not found", contract_id),
+        ));
+    }
+
+    let mut corrected = Vec::new();
+    if !dry_run && !drifts.is_empty() {
+        corrected = apply_corrections(&core_storage, &drifts)
+            .map_err(|e| rest::internal_error("Failed to correct contract aggregates", e))?;
+        for contract in &corrected {
+            broadcast_to_tenant(&admin.tenant, "contract_update", contract, 0, clients).await;
+        }
+
+        metrics::add(
+            "contract_aggregates_corrected_total",
+            corrected.len() as u64,
+        );
+        println!(
+            "Admin {} of tenant {} corrected the aggregates of {} contract(s)",
+            admin.user_id,
+            admin.tenant,
+            corrected.len()
+        );
+        client_admin::audit(
+            &admin,
+            "contract_recompute",
+            "contract",
+            contract_id.unwrap_or("*"),
+            &json!({ "drift": drifts.iter().map(Drift::to_json).collect::<Vec<_>>() }),
+        );
+    }
+
+    Ok(rest::json_reply(&json!({
+        "checked": checked,
+        "dryRun": dry_run,
+        "drift": drifts.iter().map(Drift::to_json).collect::<Vec<_>>(),
+        "corrected": corrected.len()
+    })))
+}
+
+/// Booked quantity is the initial quantity of every location of the contract
+/// plus active reservations whose location has not been saved yet; shipped
+/// quantity is the sum of its shipments.
+fn find_drift(
+    core_storage: &CoreLocalStorage,
+    contract_id: Option<&str>,
+) -> rusqlite::Result<(usize, Vec<Drift>)> {
+    let conn = core_storage.get_connection()?;
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.title, c.bookedQuantity, c.shippedQuantity,
+             (SELECT COALESCE(SUM(l.initialQuantity), 0) FROM locations l
+              WHERE l.contractId = c.id AND l.deleted = 0)
+             + (SELECT COALESCE(SUM(r.quantity), 0) FROM quotaReservations r
+                WHERE r.contractId = c.id AND r.status = 'active' AND r.deleted = 0
+                AND NOT EXISTS (
+                    SELECT 1 FROM locations l WHERE l.id = r.locationId AND l.deleted = 0
+                )),
+             (SELECT COALESCE(SUM(s.quantity), 0) FROM shipments s
+              WHERE s.contractId = c.id AND s.deleted = 0)
+         FROM contracts c
+         WHERE c.deleted = 0 AND (?1 IS NULL OR c.id = ?1)
+         ORDER BY c.id",
+    )?;
+    let rows = stmt.query_map(params![contract_id], |row| {
+        Ok(Drift {
+            contract_id: row.get(0)?,
+            title: row.get(1)?,
+            stored_booked: row.get(2)?,
+            stored_shipped: row.get(3)?,
+            computed_booked: row.get(4)?,
+            computed_shipped: row.get(5)?,
+        })
+    })?;
+
+    let mut checked = 0;
+    let mut drifts = Vec::new();
+    for row in rows {
+        let drift = row?;
+        checked += 1;
+        if (drift.stored_booked - drift.computed_booked).abs() > QUANTITY_EPSILON
+            || (drift.stored_shipped - drift.computed_shipped).abs() > QUANTITY_EPSILON
+        {
+            drifts.push(drift);
+        }
+    }
+
+    Ok((checked, drifts))
+}
+
+fn apply_corrections(
+    core_storage: &CoreLocalStorage,
+    drifts: &[Drift],
+) -> rusqlite::Result<Vec<Value>> {
+    core_storage.begin()?;
+
+    match correct_contracts(core_storage, drifts) {
+        Ok(corrected) => {
+            core_storage.commit()?;
+            Ok(corrected)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = core_storage.rollback() {
+                eprintln!(
+                    "Failed to roll back contract aggregate correction: {:?}",
+                    rollback_error
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+fn correct_contracts(
+    core_storage: &CoreLocalStorage,
+    drifts: &[Drift],
+) -> rusqlite::Result<Vec<Value>> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut corrected = Vec::new();
+    for drift in drifts {
+        let Some(mut contract) = core_storage
+            .get_existing_by_id("contracts", &drift.contract_id)?
+            .into_iter()
+            .next()
+        else {
+            continue;
+        };
+        contract["bookedQuantity"] = json!(drift.computed_booked);
+        contract["shippedQuantity"] = json!(drift.computed_shipped);
+        contract["lastEdit"] = json!(now);
+        contract["arrivalAtServer"] = json!(now);
+        core_storage.update("contracts", &contract)?;
+        corrected.push(contract);
+    }
+
+    Ok(corrected)
+}
@@ -0,0 +1,95 @@
+use crate::local_storage::contract::contract_local_storage::ContractLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::metrics;
+use serde_json::Value;
+use std::sync::Arc;
+
+const MODES: &[&str] = &["reject", "flag"];
+const DEFAULT_MODE: &str = "reject";
+
+pub enum Violation {
+    Reject(String),
+    Flag(String),
+}
+
+pub fn check_shipment(
+    shipment: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Option<Violation>, String> {
+    let contract_id = shipment["contractId"].as_str().unwrap_or_default();
+    let sawmill_id = shipment["sawmillId"].as_str().unwrap_or_default();
+
+    let (allowed, mode) = ContractLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_sawmill_allowlist(contract_id))
+        .map_err(|e| format!("Failed to load sawmill allowlist: {}", e))?;
+    if allowed.is_empty() || allowed.iter().any(|allowed| allowed == sawmill_id) {
+        return Ok(None);
+    }
+
+    let reason = format!(
+        "Sawmill {} is not allowed for contract {}",
+        sawmill_id, contract_id
+    );
+    metrics::increment("contract_allowlist_violations_total");
+    Ok(Some(match mode.as_deref().unwrap_or(DEFAULT_MODE) {
+        "flag" => Violation::Flag(reason),
+        _ => Violation::Reject(reason),
+    }))
+}
+
+pub fn update(
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let invalid = |message: String| ("VALIDATION_FAILED", message);
+
+    let contract_id = data["contractId"]
+        .as_str()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| invalid("contractId is required".to_string()))?;
+    let sawmill_ids = data["sawmillIds"]
+        .as_array()
+        .ok_or_else(|| invalid("sawmillIds must be an array".to_string()))?
+        .iter()
+        .map(|id| id.as_str().map(str::to_string))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| invalid("sawmillIds must only contain strings".to_string()))?;
+    let mode = match data.get("mode") {
+        None | Some(Value::Null) => DEFAULT_MODE,
+        Some(mode) => mode
+            .as_str()
+            .filter(|mode| MODES.contains(mode))
+            .ok_or_else(|| invalid(format!("mode must be one of {}", MODES.join(", "))))?,
+    };
+
+    let db_error = |e: rusqlite::Error| {
+        println!(
+            "Failed to update sawmill allowlist of {}: {:?}",
+            contract_id, e
+        );
+        ("INTERNAL", "Failed to update sawmill allowlist".to_string())
+    };
+    for sawmill_id in &sawmill_ids {
+        let exists = core_storage
+            .get_columns_by_id("sawmills", sawmill_id, &["deleted"])
+            .map_err(db_error)?
+            .first()
+            .is_some_and(|sawmill| sawmill["deleted"].as_i64().unwrap_or(0) == 0);
+        if !exists {
+            return Err(invalid(format!("Unknown sawmill: {}", sawmill_id)));
+        }
+    }
+
+    let storage = ContractLocalStorage::new(core_storage).map_err(db_error)?;
+    if !storage
+        .set_sawmill_allowlist(contract_id, &sawmill_ids, mode)
+        .map_err(db_error)?
+    {
+        return Err(("NOT_FOUND", format!("Contract {} not found", contract_id)));
+    }
+
+    storage
+        .get_contract_by_id(contract_id)
+        .map_err(db_error)?
+        .ok_or_else(|| ("NOT_FOUND", format!("Contract {} not found", contract_id)))
+}
@@ -0,0 +1,231 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use rusqlite::params;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const INHERITED_FIELDS: &[&str] = &[
+    "done",
+    "title",
+    "additionalInfo",
+    "startDate",
+    "endDate",
+    "pricePerCubicMeter",
+    "currency",
+    "vatRate",
+];
+
+pub fn split_contract(
+    core_storage: Arc<CoreLocalStorage>,
+    data: &Value,
+) -> Result<Vec<(&'static str, Value)>, String> {
+    core_storage
+        .begin()
+        .map_err(|e| format!("Failed to start transaction: {:?}", e))?;
+
+    match apply_split(core_storage.clone(), data) {
+        Ok(updates) => {
+            core_storage
+                .commit()
+                .map_err(|e| format!("Failed to commit contract split: {:?}", e))?;
+            Ok(updates)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = core_storage.rollback() {
+                eprintln!("Failed to roll back contract split: {:?}", rollback_error);
+            }
+            Err(e)
+        }
+    }
+}
+
+fn apply_split(
+    core_storage: Arc<CoreLocalStorage>,
+    data: &Value,
+) -> Result<Vec<(&'static str, Value)>, String> {
+    let db_error = |e: rusqlite::Error| format!("Database error: {:?}", e);
+
+    let contract_id = data["contractId"]
+        .as_str()
+        .ok_or("contractId is required")?;
+    let location_ids: Vec<&str> = data["locationIds"]
+        .as_array()
+        .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect())
+        .unwrap_or_default();
+    if location_ids.is_empty() {
+        return Err("locationIds must name at least one location".to_string());
+    }
+
+    let mut contract = core_storage
+        .get_existing_by_id("contracts", contract_id)
+        .map_err(db_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Contract {} not found", contract_id))?;
+
+    let contract_locations = contract_location_quantities(&core_storage, contract_id)?;
+    for location_id in &location_ids {
+        if !contract_locations.iter().any(|(id, _)| id == location_id) {
+            return Err(format!(
+                "Location {} does not belong to contract {}",
+                location_id, contract_id
+            ));
+        }
+    }
+
+    let total_quantity: f64 = contract_locations.iter().map(|(_, q)| q).sum();
+    let moved_quantity: f64 = contract_locations
+        .iter()
+        .filter(|(id, _)| location_ids.contains(&id.as_str()))
+        .map(|(_, q)| q)
+        .sum();
+    let ratio = match data["ratio"].as_f64() {
+        Some(ratio) if (0.0..=1.0).contains(&ratio) => ratio,
+        Some(_) => return Err("ratio must be between 0 and 1".to_string()),
+        None if total_quantity > 0.0 => moved_quantity / total_quantity,
+        None => location_ids.len() as f64 / contract_locations.len() as f64,
+    };
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let new_contract_id = data["newContract"]["id"]
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    if !core_storage
+        .get_by_id("contracts", &new_contract_id)
+        .map_err(db_error)?
+        .is_empty()
+    {
+        return Err(format!("Contract {} already exists", new_contract_id));
+    }
+
+    let mut shipments = Vec::new();
+    for location_id in &location_ids {
+        shipments.extend(
+            core_storage
+                .get_connection()
+                .and_then(|conn| {
+                    let mut stmt = conn.prepare(
+                        "SELECT id FROM shipments WHERE locationId = ? AND contractId = ? AND deleted = 0",
+                    )?;
+                    stmt.query_map(params![location_id, contract_id], |row| {
+                        row.get::<_, String>(0)
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+                })
+                .map_err(db_error)?,
+        );
+    }
+
+    let mut moved_shipped_quantity = 0.0;
+    let mut shipment_updates = Vec::new();
+    for shipment_id in &shipments {
+        if let Some(mut shipment) = core_storage
+            .get_existing_by_id("shipments", shipment_id)
+            .map_err(db_error)?
+            .into_iter()
+            .next()
+        {
+            moved_shipped_quantity += shipment["quantity"].as_f64().unwrap_or(0.0);
+            shipment["contractId"] = json!(new_contract_id);
+            shipment["lastEdit"] = json!(now);
+            shipment["arrivalAtServer"] = json!(now);
+            core_storage
+                .update("shipments", &shipment)
+                .map_err(db_error)?;
+            shipment_updates.push(shipment);
+        }
+    }
+
+    let available_quantity = contract["availableQuantity"].as_f64().unwrap_or(0.0);
+    let booked_quantity = contract["bookedQuantity"].as_f64().unwrap_or(0.0);
+    let shipped_quantity = contract["shippedQuantity"].as_f64().unwrap_or(0.0);
+    let moved_shipped_quantity = moved_shipped_quantity.min(shipped_quantity);
+
+    let mut new_contract = json!({
+        "id": new_contract_id,
+        "lastEdit": now,
+        "arrivalAtServer": now,
+        "deleted": 0,
+        "availableQuantity": available_quantity * ratio,
+        "bookedQuantity": booked_quantity * ratio,
+        "shippedQuantity": moved_shipped_quantity
+    });
+    for field in INHERITED_FIELDS {
+        let value = match data["newContract"].get(*field) {
+            Some(value) if !value.is_null() => value.clone(),
+            _ => contract[*field].clone(),
+        };
+        new_contract[*field] = value;
+    }
+    core_storage
+        .insert("contracts", &new_contract)
+        .map_err(db_error)?;
+
+    contract["availableQuantity"] = json!(available_quantity * (1.0 - ratio));
+    contract["bookedQuantity"] = json!(booked_quantity * (1.0 - ratio));
+    contract["shippedQuantity"] = json!(shipped_quantity - moved_shipped_quantity);
+    contract["lastEdit"] = json!(now);
+    contract["arrivalAtServer"] = json!(now);
+    core_storage
+        .update("contracts", &contract)
+        .map_err(db_error)?;
+
+    let location_storage = LocationLocalStorage::new(core_storage.clone()).map_err(db_error)?;
+    let mut location_updates = Vec::new();
+    for location_id in &location_ids {
+        let mut location = core_storage
+            .get_existing_by_id("locations", location_id)
+            .map_err(db_error)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("Location {} not found", location_id))?;
+        location["contractId"] = json!(new_contract_id);
+        location["lastEdit"] = json!(now);
+        location["arrivalAtServer"] = json!(now);
+        core_storage
+            .update("locations", &location)
+            .map_err(db_error)?;
+        location_updates.push(
+            location_storage
+                .get_location_by_id(location_id)
+                .map_err(db_error)?,
+        );
+    }
+
+    println!(
+        "Split contract {} into {}: {} locations, {} shipments, ratio {:.3}",
+        contract_id,
+        new_contract_id,
+        location_ids.len(),
+        shipment_updates.len(),
+        ratio
+    );
+
+    let mut updates = vec![
+        ("contract_update", contract),
+        ("contract_update", new_contract),
+    ];
+    updates.extend(location_updates.into_iter().map(|l| ("location_update", l)));
+    updates.extend(shipment_updates.into_iter().map(|s| ("shipment_update", s)));
+    Ok(updates)
+}
+
+fn contract_location_quantities(
+    core_storage: &CoreLocalStorage,
+    contract_id: &str,
+) -> Result<Vec<(String, f64)>, String> {
+    core_storage
+        .get_connection()
+        .and_then(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, initialQuantity FROM locations WHERE contractId = ? AND deleted = 0",
+            )?;
+            stmt.query_map(params![contract_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .map_err(|e| format!("Database error: {:?}", e))
+}
@@ -0,0 +1,136 @@
+use crate::config;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use warp::{Filter, Rejection};
+
+pub fn check_coordinates(latitude: f64, longitude: f64) -> Option<String> {
+    if !latitude.is_finite() || !(-90.0..=90.0).contains(&latitude) {
+        return Some(format!("Latitude {} is outside -90 to 90", latitude));
+    }
+    if !longitude.is_finite() || !(-180.0..=180.0).contains(&longitude) {
+        return Some(format!("Longitude {} is outside -180 to 180", longitude));
+    }
+    if latitude == 0.0 && longitude == 0.0 && !config::get().coordinates.allow_zero {
+        return Some("Coordinates 0,0 are not accepted".to_string());
+    }
+
+    None
+}
+
+pub fn normalize_location(location: &mut Value) -> Result<(), String> {
+    let latitude = location["latitude"]
+        .as_f64()
+        .ok_or("latitude must be a number")?;
+    let longitude = location["longitude"]
+        .as_f64()
+        .ok_or("longitude must be a number")?;
+
+    if let Some(reason) = check_coordinates(latitude, longitude) {
+        return Err(reason);
+    }
+
+    let settings = &config::get().coordinates;
+    if settings.round {
+        let factor = 10f64.powi(settings.decimal_places as i32);
+        location["latitude"] = json!((latitude * factor).round() / factor);
+        location["longitude"] = json!((longitude * factor).round() / factor);
+    }
+
+    Ok(())
+}
+
+pub fn check_sawmill(sawmill: &Value) -> Result<(), String> {
+    match (&sawmill["latitude"], &sawmill["longitude"]) {
+        (Value::Null, Value::Null) => Ok(()),
+        (latitude, longitude) => {
+            let latitude = latitude.as_f64().ok_or("latitude must be a number")?;
+            let longitude = longitude.as_f64().ok_or("longitude must be a number")?;
+            check_coordinates(latitude, longitude).map_or(Ok(()), Err)
+        }
+    }
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    warp::path!("data-quality" / "coordinates")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            coordinate_report(authorization, &db_pools).unwrap_or_else(|reply| reply)
+        })
+}
+
+fn coordinate_report(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let locations = core_storage
+        .get_connection()
+        .and_then(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, partieNr, contractId, latitude, longitude
+                 FROM locations WHERE deleted = 0 ORDER BY id",
+            )?;
+            stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, f64>(4)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .map_err(|e| rest::internal_error("Failed to load locations", e))?;
+
+    let mut positions: HashMap<(u64, u64), Vec<&str>> = HashMap::new();
+    for (id, _, _, latitude, longitude) in &locations {
+        positions
+            .entry((latitude.to_bits(), longitude.to_bits()))
+            .or_default()
+            .push(id);
+    }
+
+    let mut suspicious = Vec::new();
+    for (id, partie_nr, contract_id, latitude, longitude) in &locations {
+        let mut issues = Vec::new();
+        if let Some(reason) = check_coordinates(*latitude, *longitude) {
+            issues.push(reason);
+        } else if *latitude == 0.0 || *longitude == 0.0 {
+            issues.push("Latitude or longitude is exactly 0".to_string());
+        }
+        if let Some(ids) = positions.get(&(latitude.to_bits(), longitude.to_bits()))
+            && ids.len() > 1
+        {
+            issues.push(format!(
+                "Same coordinates as {} other location(s)",
+                ids.len() - 1
+            ));
+        }
+
+        if !issues.is_empty() {
+            suspicious.push(json!({
+                "id": id,
+                "partieNr": partie_nr,
+                "contractId": contract_id,
+                "latitude": latitude,
+                "longitude": longitude,
+                "issues": issues
+            }));
+        }
+    }
+
+    Ok(rest::json_reply(&json!({
+        "checked": locations.len(),
+        "locations": suspicious
+    })))
+}
@@ -0,0 +1,85 @@
+use crate::DbPool;
+use crate::config;
+use crate::metrics;
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const SERVER_BUSY: &str = "SERVER_BUSY";
+
+#[derive(Debug)]
+pub enum AcquireError {
+    Busy,
+    Failed(r2d2::Error),
+}
+
+pub fn build(manager: SqliteConnectionManager) -> Result<DbPool, r2d2::Error> {
+    Pool::builder()
+        .max_size(config::get().db_pool.max_size)
+        .build(manager)
+}
+
+pub fn retry_after_ms() -> u64 {
+    config::get().db_pool.acquire_timeout_ms
+}
+
+pub fn acquire(
+    tenant: &str,
+    pool: &DbPool,
+) -> Result<PooledConnection<SqliteConnectionManager>, AcquireError> {
+    let settings = &config::get().db_pool;
+    let timeout = Duration::from_millis(settings.acquire_timeout_ms);
+    let waiting_gauge = format!("db_pool_waiting{{tenant=\"{}\"}}", tenant);
+    let started = Instant::now();
+    let mut backoff = Duration::from_millis(settings.retry_backoff_ms);
+    let mut attempt = 0;
+
+    metrics::add_gauge(&waiting_gauge, 1);
+    let result = loop {
+        match pool.get_timeout(timeout) {
+            Ok(conn) => break Ok(conn),
+            Err(e) => {
+                let state = pool.state();
+                let saturated = state.idle_connections == 0 && state.connections >= pool.max_size();
+                if !saturated {
+                    break Err(AcquireError::Failed(e));
+                }
+                if attempt >= settings.acquire_retries {
+                    break Err(AcquireError::Busy);
+                }
+
+                attempt += 1;
+                metrics::increment("db_pool_acquire_retries_total");
+                println!(
+                    "Connection pool of tenant {} saturated ({} connections), retry {} of {} in {:?}",
+                    tenant, state.connections, attempt, settings.acquire_retries, backoff
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+        }
+    };
+    metrics::add_gauge(&waiting_gauge, -1);
+    metrics::add(
+        "db_pool_acquire_wait_ms_total",
+        started.elapsed().as_millis() as u64,
+    );
+
+    match &result {
+        Err(AcquireError::Busy) => {
+            metrics::increment("db_pool_busy_total");
+            println!(
+                "Connection pool of tenant {} stayed saturated for {:?}",
+                tenant,
+                started.elapsed()
+            );
+        }
+        Err(AcquireError::Failed(e)) => {
+            println!("Failed to get connection for tenant {}: {:?}", tenant, e)
+        }
+        Ok(_) => {}
+    }
+
+    result
+}
@@ -0,0 +1,202 @@
+use crate::config;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{Clients, DbPoolMap, ROLE_ADMIN, with_db_pools};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+pub const DELETED_IN_UPDATE: &str = "deletedInUpdate";
+pub const UNCHALLENGED_AUTHENTICATION: &str = "unchallengedAuthentication";
+
+struct DeprecatedForm {
+    id: &'static str,
+    message: &'static str,
+}
+
+const FORMS: &[DeprecatedForm] = &[
+    DeprecatedForm {
+        id: DELETED_IN_UPDATE,
+        message: "Deleting an entity by sending \"deleted\": 1 in an _update message is deprecated",
+    },
+    DeprecatedForm {
+        id: UNCHALLENGED_AUTHENTICATION,
+        message: "Authentication without a challenge is deprecated",
+    },
+];
+
+struct Usage {
+    count: u64,
+    first_seen: i64,
+    last_seen: i64,
+}
+
+/// Form id, tenant and client version.
+type UsageKey = (&'static str, String, String);
+
+static USAGE: LazyLock<Mutex<BTreeMap<UsageKey, Usage>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+fn form(id: &str) -> Option<&'static DeprecatedForm> {
+    FORMS.iter().find(|form| form.id == id)
+}
+
+pub fn sunset(id: &str) -> Option<String> {
+    let settings = config::get();
+    settings.deprecations.sunsets.get(id).cloned().or_else(|| {
+        (id == UNCHALLENGED_AUTHENTICATION
+            && !settings.client_compat.legacy_protocol_sunset.is_empty())
+        .then(|| settings.client_compat.legacy_protocol_sunset.clone())
+    })
+}
+
+fn detect(msg_type: &str, data: &Value) -> Vec<&'static str> {
+    let mut used = Vec::new();
+    if msg_type.ends_with("_update")
+        && data
+            .get("deleted")
+            .is_some_and(|deleted| deleted.as_i64() == Some(1) || deleted.as_bool() == Some(true))
+    {
+        used.push(DELETED_IN_UPDATE);
+    }
+
+    used
+}
+
+pub fn record(id: &'static str, tenant: &str, app_version: Option<&str>) {
+    let app_version = app_version.unwrap_or("unknown");
+    metrics::increment(&format!(
+        "deprecated_usage_total{{form=\"{}\",tenant=\"{}\",app_version=\"{}\"}}",
+        id, tenant, app_version
+    ));
+
+    let now = chrono::Utc::now().timestamp_millis();
+    if let Ok(mut usage) = USAGE.lock() {
+        let entry = usage
+            .entry((id, tenant.to_string(), app_version.to_string()))
+            .or_insert(Usage {
+                count: 0,
+                first_seen: now,
+                last_seen: now,
+            });
+        entry.count += 1;
+        entry.last_seen = now;
+    }
+}
+
+/// Records every deprecated form used by `data` and returns the
+/// deprecation_warning messages to send. Each form is announced once per
+/// connection; later uses are only counted.
+pub fn check_message(
+    client_id: &str,
+    msg_type: &str,
+    data: &Value,
+    clients: &Clients,
+) -> Vec<Value> {
+    let used = detect(msg_type, data);
+    if used.is_empty() {
+        return Vec::new();
+    }
+
+    let Ok(mut clients_lock) = clients.lock() else {
+        return Vec::new();
+    };
+    let Some(client) = clients_lock.get_mut(client_id) else {
+        return Vec::new();
+    };
+
+    let mut warnings = Vec::new();
+    for id in used {
+        record(id, &client.db_name, client.client_version.as_deref());
+        if !config::get().deprecations.warn_clients || !client.warned_deprecations.insert(id) {
+            continue;
+        }
+        let Some(form) = form(id) else {
+            continue;
+        };
+        warnings.push(json!({
+            "type": "deprecation_warning",
+            "data": {
+                "id": form.id,
+                "message": form.message,
+                "sunset": sunset(form.id),
+                "messageType": msg_type,
+                "entityId": data.get("id")
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        }));
+    }
+
+    warnings
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("admin" / "deprecations")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(usage_report(authorization, &db_pools))
+        })
+}
+
+fn usage_report(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let capacity = &config::get().capacity;
+    let is_operator = capacity.enabled
+        && !capacity.api_key.is_empty()
+        && authorization
+            .as_deref()
+            .and_then(|header| header.strip_prefix("Bearer "))
+            == Some(capacity.api_key.as_str());
+    let tenant = if is_operator {
+        None
+    } else {
+        Some(rest::authenticate(authorization, db_pools, ROLE_ADMIN)?.tenant)
+    };
+
+    let usage = USAGE.lock().map_err(|_| {
+        rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Deprecation usage unavailable",
+        )
+    })?;
+
+    let forms: Vec<Value> = FORMS
+        .iter()
+        .map(|form| {
+            let usage: Vec<Value> = usage
+                .iter()
+                .filter(|((id, usage_tenant, _), _)| {
+                    *id == form.id && tenant.as_ref().is_none_or(|tenant| tenant == usage_tenant)
+                })
+                .map(|((_, usage_tenant, app_version), usage)| {
+                    json!({
+                        "tenant": usage_tenant,
+                        "appVersion": app_version,
+                        "count": usage.count,
+                        "firstSeen": usage.first_seen,
+                        "lastSeen": usage.last_seen
+                    })
+                })
+                .collect();
+            json!({
+                "id": form.id,
+                "message": form.message,
+                "sunset": sunset(form.id),
+                "total": usage.iter().filter_map(|u| u["count"].as_u64()).sum::<u64>(),
+                "lastSeen": usage.iter().filter_map(|u| u["lastSeen"].as_i64()).max(),
+                "usage": usage
+            })
+        })
+        .collect();
+
+    Ok(rest::json_reply(&json!({ "forms": forms })))
+}
@@ -0,0 +1,462 @@
+use crate::config;
+use crate::instance_lease;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::settings::settings_local_storage::{
+    DIGEST_KEY, DIGEST_LAST_SENT_KEY, SettingsLocalStorage,
+};
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use crate::timezone::{self, TenantTimezone};
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use chrono::{DateTime, Datelike, FixedOffset, Timelike, Utc};
+use rusqlite::params;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use warp::{Filter, Rejection, Reply};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+const MAX_WARNINGS: i64 = 50;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct DigestSettings {
+    frequency: String,
+    #[serde(default = "default_hour")]
+    hour: u32,
+    #[serde(default = "default_weekday")]
+    weekday: u32,
+    #[serde(default)]
+    utc_offset_minutes: Option<i32>,
+    #[serde(default)]
+    subscribers: Vec<Subscriber>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Subscriber {
+    user_id: String,
+    email: String,
+}
+
+fn default_hour() -> u32 {
+    7
+}
+
+fn default_weekday() -> u32 {
+    1
+}
+
+impl DigestSettings {
+    fn period_ms(&self) -> i64 {
+        if self.frequency == "weekly" {
+            7 * DAY_MS
+        } else {
+            DAY_MS
+        }
+    }
+
+    fn timezone(&self, tenant_timezone: TenantTimezone) -> TenantTimezone {
+        self.utc_offset_minutes
+            .and_then(|minutes| FixedOffset::east_opt(minutes * 60))
+            .map(TenantTimezone::Fixed)
+            .unwrap_or(tenant_timezone)
+    }
+
+    fn due_date(
+        &self,
+        now: DateTime<Utc>,
+        last_sent: Option<&str>,
+        timezone: &TenantTimezone,
+    ) -> Option<String> {
+        let local = timezone.to_local(now);
+        let due = match self.frequency.as_str() {
+            "daily" => local.hour() >= self.hour,
+            "weekly" => {
+                local.weekday().number_from_monday() == self.weekday && local.hour() >= self.hour
+            }
+            _ => false,
+        };
+
+        let date = local.date_naive().to_string();
+        (due && last_sent != Some(date.as_str())).then_some(date)
+    }
+}
+
+pub fn validate_settings(value: &str) -> Result<(), String> {
+    let settings: DigestSettings =
+        serde_json::from_str(value).map_err(|e| format!("Invalid digest settings: {}", e))?;
+
+    if !["daily", "weekly", "off"].contains(&settings.frequency.as_str()) {
+        return Err("frequency must be daily, weekly or off".to_string());
+    }
+    if settings.hour > 23 {
+        return Err("hour must be between 0 and 23".to_string());
+    }
+    if !(1..=7).contains(&settings.weekday) {
+        return Err("weekday must be between 1 (Monday) and 7 (Sunday)".to_string());
+    }
+    if settings
+        .utc_offset_minutes
+        .is_some_and(|minutes| minutes.abs() > 14 * 60)
+    {
+        return Err("utcOffsetMinutes must be within ±14 hours".to_string());
+    }
+    if let Some(subscriber) = settings
+        .subscribers
+        .iter()
+        .find(|subscriber| !subscriber.email.contains('@'))
+    {
+        return Err(format!(
+            "Invalid email address for {}: {}",
+            subscriber.user_id, subscriber.email
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    let settings = &config::get().digests;
+    if settings.notification_url.is_empty() {
+        return None;
+    }
+
+    let check_interval = Duration::from_secs(settings.check_interval_secs);
+    Some(tokio::task::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut ticker = interval(check_interval);
+
+        loop {
+            ticker.tick().await;
+            if !instance_lease::is_leader() {
+                continue;
+            }
+            send_due_digests(&client).await;
+        }
+    }))
+}
+
+async fn send_due_digests(client: &reqwest::Client) {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    let now = Utc::now();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            continue;
+        }
+
+        let due = rest::open_storage(tenant).and_then(|core_storage| {
+            let settings_storage = SettingsLocalStorage::new(core_storage.clone())?;
+            let Some(settings) = settings_storage
+                .get_setting(DIGEST_KEY)?
+                .and_then(|value| serde_json::from_str::<DigestSettings>(&value).ok())
+            else {
+                return Ok(None);
+            };
+            if settings.subscribers.is_empty() {
+                return Ok(None);
+            }
+
+            let last_sent = settings_storage.get_setting(DIGEST_LAST_SENT_KEY)?;
+            let timezone = settings.timezone(timezone::for_tenant(core_storage.clone()));
+            let Some(date) = settings.due_date(now, last_sent.as_deref(), &timezone) else {
+                return Ok(None);
+            };
+
+            let (subject, html) = render_digest(tenant, &core_storage, &settings, &timezone, now)?;
+            Ok(Some((core_storage, settings, date, subject, html)))
+        });
+
+        let (core_storage, settings, date, subject, html) = match due {
+            Ok(Some(due)) => due,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Failed to prepare digest for tenant {}: {:?}", tenant, e);
+                continue;
+            }
+        };
+
+        let recipients: Vec<&str> = settings
+            .subscribers
+            .iter()
+            .map(|subscriber| subscriber.email.as_str())
+            .collect();
+        match deliver(client, tenant, &recipients, &subject, &html).await {
+            Ok(()) => {
+                metrics::increment("digests_sent_total");
+                println!(
+                    "Sent {} digest of tenant {} to {} subscriber(s)",
+                    settings.frequency,
+                    tenant,
+                    recipients.len()
+                );
+                if let Err(e) = SettingsLocalStorage::new(core_storage).and_then(|storage| {
+                    storage.save_setting(DIGEST_LAST_SENT_KEY, &date, now.timestamp_millis())
+                }) {
+                    eprintln!("Failed to record digest of tenant {}: {:?}", tenant, e);
+                }
+            }
+            Err(e) => {
+                metrics::increment("digests_failed_total");
+                eprintln!("Failed to send digest of tenant {}: {}", tenant, e);
+            }
+        }
+    }
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    tenant: &str,
+    recipients: &[&str],
+    subject: &str,
+    html: &str,
+) -> Result<(), String> {
+    let payload = json!({
+        "tenant": tenant,
+        "to": recipients,
+        "subject": subject,
+        "html": html
+    });
+
+    let response = client
+        .post(&config::get().digests.notification_url)
+        .header("content-type", "application/json")
+        .body(payload.to_string())
+        .send()
+        .await
+        .map_err(|e| format!("Notification service unreachable: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Notification service answered {}",
+            response.status()
+        ));
+    }
+
+    Ok(())
+}
+
+fn render_digest(
+    tenant: &str,
+    core_storage: &CoreLocalStorage,
+    settings: &DigestSettings,
+    timezone: &TenantTimezone,
+    now: DateTime<Utc>,
+) -> rusqlite::Result<(String, String)> {
+    let since = now.timestamp_millis() - settings.period_ms();
+    let conn = core_storage.get_connection()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(sawmills.name, shipments.sawmillId), COUNT(*),
+                SUM(shipments.quantity), SUM(shipments.oversizeQuantity), SUM(shipments.pieceCount)
+         FROM shipments LEFT JOIN sawmills ON sawmills.id = shipments.sawmillId
+         WHERE shipments.deleted = 0 AND shipments.lastEdit >= ?
+         GROUP BY shipments.sawmillId ORDER BY 1",
+    )?;
+    let shipments = stmt
+        .query_map(params![since], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, f64>(2)?,
+                row.get::<_, f64>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT title, availableQuantity, bookedQuantity, shippedQuantity
+         FROM contracts WHERE deleted = 0 AND done = 0 ORDER BY title",
+    )?;
+    let contracts = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, f64>(1)?,
+                row.get::<_, f64>(2)?,
+                row.get::<_, f64>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT entityType, entityId, reason FROM reviewItems
+         WHERE status = 'open' AND deleted = 0 ORDER BY lastEdit DESC LIMIT ?",
+    )?;
+    let warnings = stmt
+        .query_map(params![MAX_WARNINGS], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let local_now = timezone.to_local(now);
+    let local_since = timezone.to_local(now - chrono::Duration::milliseconds(settings.period_ms()));
+    let format = "%d.%m.%Y %H:%M";
+    let subject = format!(
+        "{} digest for {}: {} shipment(s)",
+        if settings.frequency == "weekly" {
+            "Weekly"
+        } else {
+            "Daily"
+        },
+        tenant,
+        shipments.iter().map(|(_, count, ..)| count).sum::<i64>()
+    );
+
+    let mut html = format!(
+        "<html><body><h1>{}</h1><p>{} – {} ({})</p>",
+        escape(&subject),
+        local_since.format(format),
+        local_now.format(format),
+        escape(&timezone.name())
+    );
+
+    html.push_str("<h2>Shipments</h2>");
+    if shipments.is_empty() {
+        html.push_str("<p>No shipments in this period.</p>");
+    } else {
+        html.push_str(
+            "<table><tr><th>Sawmill</th><th>Shipments</th><th>m³</th><th>Oversize m³</th><th>Pieces</th></tr>",
+        );
+        for (sawmill, count, quantity, oversize_quantity, pieces) in &shipments {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{:.2}</td><td>{:.2}</td><td>{}</td></tr>",
+                escape(sawmill),
+                count,
+                quantity,
+                oversize_quantity,
+                pieces
+            ));
+        }
+        html.push_str("</table>");
+    }
+
+    html.push_str("<h2>Contract progress</h2>");
+    if contracts.is_empty() {
+        html.push_str("<p>No open contracts.</p>");
+    } else {
+        html.push_str(
+            "<table><tr><th>Contract</th><th>Available m³</th><th>Booked m³</th><th>Shipped m³</th><th>Shipped</th></tr>",
+        );
+        for (title, available, booked, shipped) in &contracts {
+            let progress = if *available > 0.0 {
+                format!("{:.0}%", shipped / available * 100.0)
+            } else {
+                "–".to_string()
+            };
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{:.2}</td><td>{:.2}</td><td>{:.2}</td><td>{}</td></tr>",
+                escape(title),
+                available,
+                booked,
+                shipped,
+                progress
+            ));
+        }
+        html.push_str("</table>");
+    }
+
+    html.push_str("<h2>Warnings</h2>");
+    if warnings.is_empty() {
+        html.push_str("<p>No open review items.</p>");
+    } else {
+        html.push_str("<ul>");
+        for (entity_type, entity_id, reason) in &warnings {
+            html.push_str(&format!(
+                "<li>{} {}: {}</li>",
+                escape(entity_type),
+                escape(entity_id),
+                escape(reason)
+            ));
+        }
+        html.push_str("</ul>");
+    }
+    html.push_str("</body></html>");
+
+    Ok((subject, html))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("admin" / "digests" / "preview")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(|authorization, query, db_pools: DbPoolMap| {
+            match preview_digest(authorization, query, &db_pools) {
+                Ok(html) => Box::new(warp::reply::html(html)) as Box<dyn Reply>,
+                Err(reply) => Box::new(reply),
+            }
+        })
+}
+
+fn preview_digest(
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<String, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let core_storage: Arc<CoreLocalStorage> = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let stored = SettingsLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_setting(DIGEST_KEY))
+        .map_err(|e| rest::internal_error("Failed to load digest settings", e))?
+        .and_then(|value| serde_json::from_str::<DigestSettings>(&value).ok());
+    let mut settings = stored.unwrap_or(DigestSettings {
+        frequency: "daily".to_string(),
+        hour: default_hour(),
+        weekday: default_weekday(),
+        utc_offset_minutes: None,
+        subscribers: Vec::new(),
+    });
+    if let Some(frequency) = query.get("frequency") {
+        settings.frequency = frequency.clone();
+    }
+
+    let timezone = settings.timezone(timezone::for_tenant(core_storage.clone()));
+    render_digest(
+        &user.tenant,
+        &core_storage,
+        &settings,
+        &timezone,
+        Utc::now(),
+    )
+    .map(|(_, html)| html)
+    .map_err(|e| rest::internal_error("Failed to render digest", e))
+}
@@ -0,0 +1,293 @@
+use crate::activity_feed;
+use crate::activity_reports;
+use crate::config;
+use crate::event_stream;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::event_log::event_log_local_storage::Provenance;
+use crate::metrics;
+use crate::rest;
+use crate::sla_stats;
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+pub enum BusEvent {
+    /// An update that was accepted and broadcast to the tenant. Published
+    /// only once the write that produced it has committed.
+    UpdateAccepted {
+        tenant: String,
+        msg_type: String,
+        data: Value,
+        min_role: i64,
+        provenance: Provenance,
+    },
+    Authenticated {
+        tenant: String,
+        client_id: String,
+        user_id: String,
+        role: i64,
+    },
+    Disconnected {
+        tenant: String,
+        client_id: String,
+        user_id: String,
+    },
+}
+
+impl BusEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BusEvent::UpdateAccepted { .. } => "update_accepted",
+            BusEvent::Authenticated { .. } => "authenticated",
+            BusEvent::Disconnected { .. } => "disconnected",
+        }
+    }
+}
+
+struct Subscriber {
+    name: &'static str,
+    sender: mpsc::Sender<Arc<BusEvent>>,
+    lossless: bool,
+}
+
+/// How long a subscriber keeps a tenant's database open without events.
+const STORAGE_IDLE: Duration = Duration::from_secs(60);
+
+static SUBSCRIBERS: LazyLock<Mutex<Vec<Subscriber>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+static SPAWNING: Mutex<()> = Mutex::new(());
+
+/// Registers a subscriber with its own bounded channel. Lossy subscribers
+/// miss events while their channel is full; lossless ones make publishers
+/// wait instead.
+pub fn subscribe(name: &'static str, lossless: bool) -> mpsc::Receiver<Arc<BusEvent>> {
+    let (sender, receiver) = mpsc::channel(config::get().event_bus.channel_capacity);
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push(Subscriber {
+            name,
+            sender,
+            lossless,
+        });
+    }
+    receiver
+}
+
+pub async fn publish(event: BusEvent) {
+    let event = Arc::new(event);
+    metrics::increment(&format!(
+        "event_bus_events_total{{event=\"{}\"}}",
+        event.name()
+    ));
+
+    let mut waiting = Vec::new();
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.retain(
+            |subscriber| match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) if subscriber.lossless => {
+                    waiting.push(subscriber.sender.clone());
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    metrics::increment(&format!(
+                        "event_bus_dropped_total{{subscriber=\"{}\"}}",
+                        subscriber.name
+                    ));
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        );
+    }
+
+    for sender in waiting {
+        let _ = sender.send(event.clone()).await;
+    }
+}
+
+/// Starts the built-in subscribers. Must run before the first publish. Servers
+/// in one process share them, so they are only started again once the
+/// runtime that ran them is gone.
+pub fn spawn() {
+    let Ok(_spawning) = SPAWNING.lock() else {
+        return;
+    };
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        if !subscribers.is_empty() {
+            return;
+        }
+    }
+
+    spawn_worker(subscribe("event_log", true), |event, storages| {
+        if let BusEvent::UpdateAccepted {
+            tenant,
+            msg_type,
+            data,
+            min_role,
+            provenance,
+        } = event
+        {
+            sla_stats::record_broadcast(tenant);
+            match storages.get(tenant) {
+                Ok(core_storage) => event_stream::record(
+                    core_storage,
+                    tenant,
+                    msg_type,
+                    data,
+                    *min_role,
+                    provenance,
+                ),
+                Err(e) => println!(
+                    "Failed to record {} event of tenant {}: {:?}",
+                    msg_type, tenant, e
+                ),
+            }
+        }
+    });
+
+    let mut hibernation = subscribe("hibernation", false);
+    tokio::task::spawn(async move {
+        while let Some(event) = hibernation.recv().await {
+            if let BusEvent::Disconnected { tenant, .. } = &*event
+                && !tenant.is_empty()
+            {
+                tenant_hibernation::touch(tenant);
+            }
+        }
+    });
+
+    spawn_worker(subscribe("activity", false), |event, storages| {
+        if let BusEvent::Authenticated {
+            tenant, user_id, ..
+        } = event
+        {
+            match storages.get(tenant) {
+                Ok(core_storage) => activity_reports::record_login(core_storage, user_id),
+                Err(e) => println!("Failed to record login of user {}: {:?}", user_id, e),
+            }
+        }
+    });
+
+    spawn_worker(subscribe("activity_feed", false), |event, storages| {
+        if let BusEvent::UpdateAccepted {
+            tenant,
+            msg_type,
+            data,
+            min_role,
+            provenance,
+        } = event
+        {
+            match storages.get(tenant) {
+                Ok(core_storage) => activity_feed::record(
+                    core_storage,
+                    tenant,
+                    msg_type,
+                    data,
+                    *min_role,
+                    provenance,
+                ),
+                Err(e) => println!("Failed to record activity of tenant {}: {:?}", tenant, e),
+            }
+        }
+    });
+
+    let mut connections = subscribe("connections", false);
+    tokio::task::spawn(async move {
+        while let Some(event) = connections.recv().await {
+            match &*event {
+                BusEvent::Authenticated {
+                    tenant,
+                    client_id,
+                    user_id,
+                    role,
+                } => println!(
+                    "Client {} authenticated as user {} (role {}) of tenant {}",
+                    client_id, user_id, role, tenant
+                ),
+                BusEvent::Disconnected {
+                    tenant,
+                    client_id,
+                    user_id,
+                } => println!(
+                    "Client disconnected: {} (user {} of tenant {})",
+                    client_id, user_id, tenant
+                ),
+                BusEvent::UpdateAccepted { .. } => {}
+            }
+        }
+    });
+}
+
+/// Runs a subscriber that touches tenant databases. Events are handled one
+/// at a time on the blocking pool, so SQLite work never stalls the runtime,
+/// and each tenant's database stays open between events.
+fn spawn_worker<F>(mut events: mpsc::Receiver<Arc<BusEvent>>, handle: F)
+where
+    F: Fn(&BusEvent, &mut TenantStorages) + Send + Sync + 'static,
+{
+    let handle = Arc::new(handle);
+    let storages = Arc::new(Mutex::new(TenantStorages::default()));
+    tokio::task::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event,
+                _ = tokio::time::sleep(STORAGE_IDLE) => {
+                    if let Ok(mut storages) = storages.lock() {
+                        storages.close_idle();
+                    }
+                    continue;
+                }
+            };
+            let Some(event) = event else {
+                break;
+            };
+
+            let (handle, storages) = (handle.clone(), storages.clone());
+            let handled = tokio::task::spawn_blocking(move || {
+                if let Ok(mut storages) = storages.lock() {
+                    storages.close_idle();
+                    handle(&event, &mut storages);
+                }
+            })
+            .await;
+            if let Err(e) = handled {
+                println!("Event bus subscriber failed: {:?}", e);
+            }
+        }
+    });
+}
+
+/// Tenant databases a worker keeps open between events. Ones unused for
+/// STORAGE_IDLE are closed, and draining or hibernating tenants are opened
+/// per event so their files are never held.
+#[derive(Default)]
+struct TenantStorages {
+    open: HashMap<String, (Arc<CoreLocalStorage>, Instant)>,
+}
+
+impl TenantStorages {
+    fn get(&mut self, tenant: &str) -> rusqlite::Result<Arc<CoreLocalStorage>> {
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            self.open.remove(tenant);
+            return rest::open_storage(tenant);
+        }
+        if let Some((storage, last_used)) = self.open.get_mut(tenant) {
+            *last_used = Instant::now();
+            return Ok(storage.clone());
+        }
+
+        let storage = rest::open_storage(tenant)?;
+        self.open
+            .insert(tenant.to_string(), (storage.clone(), Instant::now()));
+        Ok(storage)
+    }
+
+    fn close_idle(&mut self) {
+        self.open
+            .retain(|_, (_, last_used)| last_used.elapsed() < STORAGE_IDLE);
+    }
+}
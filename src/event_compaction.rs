@@ -0,0 +1,91 @@
+use crate::config;
+use crate::event_stream;
+use crate::instance_lease;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::event_log::event_log_local_storage::EventLogLocalStorage;
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use std::fs;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    let settings = &config::get().event_stream;
+    if !settings.compaction_enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.compaction_interval_hours * 60 * 60);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            if !instance_lease::is_leader() {
+                continue;
+            }
+            if let Err(e) = tokio::task::spawn_blocking(compact_all_tenants).await {
+                eprintln!("Event log compaction failed: {:?}", e);
+            }
+        }
+    }))
+}
+
+fn compact_all_tenants() {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    let min_age_ms = (config::get().event_stream.compaction_min_age_hours * 60 * 60 * 1000) as i64;
+    let cutoff = chrono::Utc::now().timestamp_millis() - min_age_ms;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            continue;
+        }
+
+        let result = path
+            .to_str()
+            .ok_or(rusqlite::Error::InvalidPath(path.clone()))
+            .and_then(CoreLocalStorage::new)
+            .and_then(|core_storage| {
+                schema::migrate(&*core_storage.get_connection()?)?;
+                EventLogLocalStorage::new(Arc::new(core_storage))
+            })
+            .and_then(|storage| {
+                // Open streams may still replay anything after their cursor,
+                // so only events they have already received are folded.
+                let mut up_to_seq = storage.get_last_seq_before(cutoff)?;
+                if let Some(oldest) = event_stream::oldest_cursor(tenant) {
+                    up_to_seq = up_to_seq.min(oldest);
+                }
+                storage.compact(up_to_seq)
+            });
+
+        match result {
+            Ok(0) => {}
+            Ok(compacted) => {
+                metrics::add("event_log_rows_compacted_total", compacted as u64);
+                println!(
+                    "Compacted {} event log row(s) of tenant {}",
+                    compacted, tenant
+                );
+            }
+            Err(e) => eprintln!("Failed to compact event log of tenant {}: {:?}", tenant, e),
+        }
+    }
+}
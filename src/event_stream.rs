@@ -0,0 +1,308 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::event_log::event_log_local_storage::{
+    EventLogLocalStorage, LoggedEvent, Provenance,
+};
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use futures_util::stream;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use warp::http::StatusCode;
+use warp::sse::Event;
+use warp::{Filter, Rejection, Reply};
+
+const REPLAY_PAGE_SIZE: i64 = 500;
+
+static CHANNELS: LazyLock<Mutex<HashMap<String, broadcast::Sender<Arc<LoggedEvent>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static CURSORS: LazyLock<Mutex<HashMap<String, HashMap<u64, i64>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_STREAM_ID: AtomicU64 = AtomicU64::new(1);
+
+struct StreamCursor {
+    tenant: String,
+    id: u64,
+}
+
+impl StreamCursor {
+    fn open(tenant: &str, seq: i64) -> Self {
+        let cursor = StreamCursor {
+            tenant: tenant.to_string(),
+            id: NEXT_STREAM_ID.fetch_add(1, Ordering::Relaxed),
+        };
+        cursor.advance(seq);
+        cursor
+    }
+
+    fn advance(&self, seq: i64) {
+        if let Ok(mut cursors) = CURSORS.lock() {
+            cursors
+                .entry(self.tenant.clone())
+                .or_default()
+                .insert(self.id, seq);
+        }
+    }
+}
+
+impl Drop for StreamCursor {
+    fn drop(&mut self) {
+        if let Ok(mut cursors) = CURSORS.lock()
+            && let Some(streams) = cursors.get_mut(&self.tenant)
+        {
+            streams.remove(&self.id);
+            if streams.is_empty() {
+                cursors.remove(&self.tenant);
+            }
+        }
+    }
+}
+
+pub fn oldest_cursor(tenant: &str) -> Option<i64> {
+    let cursors = CURSORS.lock().ok()?;
+    cursors.get(tenant)?.values().min().copied()
+}
+
+fn event_name(msg_type: &str) -> &str {
+    msg_type.strip_suffix("_update").unwrap_or(msg_type)
+}
+
+pub fn record(
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    msg_type: &str,
+    data: &Value,
+    min_role: i64,
+    provenance: &Provenance,
+) {
+    let timestamp = chrono::Utc::now().timestamp_millis();
+    let appended = EventLogLocalStorage::new(core_storage)
+        .and_then(|storage| storage.append(msg_type, min_role, data, timestamp, provenance));
+    let seq = match appended {
+        Ok(seq) => seq,
+        Err(e) => {
+            println!(
+                "Failed to record {} event of tenant {}: {:?}",
+                msg_type, tenant, e
+            );
+            return;
+        }
+    };
+
+    if let Ok(mut channels) = CHANNELS.lock()
+        && let Some(sender) = channels.get(tenant)
+    {
+        let event = Arc::new(LoggedEvent {
+            seq,
+            timestamp,
+            event_type: msg_type.to_string(),
+            min_role,
+            payload: data.clone(),
+        });
+        if sender.send(event).is_err() {
+            channels.remove(tenant);
+        }
+    }
+}
+
+pub fn release(tenant: &str) -> bool {
+    let Ok(mut channels) = CHANNELS.lock() else {
+        return false;
+    };
+    match channels.get(tenant) {
+        Some(sender) if sender.receiver_count() > 0 => false,
+        _ => {
+            channels.remove(tenant);
+            true
+        }
+    }
+}
+
+fn subscribe(tenant: &str) -> Option<broadcast::Receiver<Arc<LoggedEvent>>> {
+    let mut channels = CHANNELS.lock().ok()?;
+    let sender = channels
+        .entry(tenant.to_string())
+        .or_insert_with(|| broadcast::channel(config::get().event_stream.channel_capacity).0);
+    Some(sender.subscribe())
+}
+
+fn to_sse(event: &LoggedEvent) -> Event {
+    Event::default()
+        .id(event.seq.to_string())
+        .event(event_name(&event.event_type))
+        .data(
+            json!({
+                "type": event.event_type,
+                "data": event.payload,
+                "timestamp": event.timestamp
+            })
+            .to_string(),
+        )
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("events" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("last-event-id"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(
+            |tenant: String,
+             authorization: Option<String>,
+             last_event_id: Option<String>,
+             query: HashMap<String, String>,
+             db_pools: DbPoolMap| {
+                match open_stream(tenant, authorization, last_event_id, query, &db_pools) {
+                    Ok(reply) => Box::new(reply) as Box<dyn Reply>,
+                    Err(reply) => Box::new(reply),
+                }
+            },
+        )
+}
+
+fn open_stream(
+    tenant: String,
+    authorization: Option<String>,
+    last_event_id: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<impl Reply + use<>, ErrorReply> {
+    let authorization =
+        authorization.or_else(|| query.get("token").map(|token| format!("Bearer {}", token)));
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    if user.tenant != tenant {
+        return Err(rest::error_reply(
+            StatusCode::FORBIDDEN,
+            "PERMISSION_DENIED",
+            "Token does not belong to this tenant",
+        ));
+    }
+
+    let storage = rest::open_storage(&tenant)
+        .and_then(EventLogLocalStorage::new)
+        .map(Arc::new)
+        .map_err(|e| rest::internal_error("Failed to open event log", e))?;
+    let last_seq = match last_event_id.or_else(|| query.get("lastEventId").cloned()) {
+        Some(id) => id.trim().parse::<i64>().map_err(|_| {
+            rest::error_reply(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "Last-Event-ID must be an event id",
+            )
+        })?,
+        None => storage
+            .get_last_seq()
+            .map_err(|e| rest::internal_error("Failed to read event log", e))?,
+    };
+    let live = subscribe(&tenant).ok_or_else(|| {
+        rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Event stream unavailable",
+        )
+    })?;
+
+    let settings = &config::get().event_stream;
+    let (tx, rx) = mpsc::channel(settings.buffered_events);
+    println!(
+        "User {} of tenant {} opened an event stream after event {}",
+        user.user_id, tenant, last_seq
+    );
+    metrics::increment("event_streams_opened_total");
+    metrics::add_gauge("event_streams_open", 1);
+    let cursor = StreamCursor::open(&tenant, last_seq);
+    tokio::task::spawn(pump(storage, user.role, cursor, last_seq, live, tx));
+
+    let events = stream::unfold(rx, |mut rx| async move {
+        let event = rx.recv().await?;
+        Some((Ok::<_, Infallible>(event), rx))
+    });
+    Ok(warp::sse::reply(
+        warp::sse::keep_alive()
+            .interval(Duration::from_secs(settings.keep_alive_secs))
+            .stream(events),
+    ))
+}
+
+async fn send(
+    tx: &mpsc::Sender<Event>,
+    event: &LoggedEvent,
+    cursor: &StreamCursor,
+    last_seq: &mut i64,
+) -> bool {
+    *last_seq = event.seq;
+    cursor.advance(event.seq);
+    metrics::increment("event_stream_events_sent_total");
+    tx.send(to_sse(event)).await.is_ok()
+}
+
+async fn pump(
+    storage: Arc<EventLogLocalStorage>,
+    role: i64,
+    cursor: StreamCursor,
+    mut last_seq: i64,
+    mut live: broadcast::Receiver<Arc<LoggedEvent>>,
+    tx: mpsc::Sender<Event>,
+) {
+    'replay: loop {
+        loop {
+            let reader = storage.clone();
+            let after = last_seq;
+            let page = tokio::task::spawn_blocking(move || {
+                reader.get_events_after(after, role, REPLAY_PAGE_SIZE)
+            })
+            .await;
+            let page = match page {
+                Ok(Ok(page)) => page,
+                Ok(Err(e)) => {
+                    println!("Failed to replay event log: {:?}", e);
+                    break 'replay;
+                }
+                Err(e) => {
+                    println!("Event log replay task failed: {:?}", e);
+                    break 'replay;
+                }
+            };
+            if page.is_empty() {
+                break;
+            }
+            for event in &page {
+                if !send(&tx, event, &cursor, &mut last_seq).await {
+                    break 'replay;
+                }
+            }
+        }
+
+        loop {
+            let received = tokio::select! {
+                received = live.recv() => received,
+                _ = tx.closed() => break 'replay,
+            };
+            match received {
+                Ok(event) if event.seq <= last_seq || event.min_role > role => {}
+                Ok(event) => {
+                    if !send(&tx, &event, &cursor, &mut last_seq).await {
+                        break 'replay;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    println!("Event stream lagged by {} events, replaying", skipped);
+                    continue 'replay;
+                }
+                Err(RecvError::Closed) => break 'replay,
+            }
+        }
+    }
+
+    metrics::add_gauge("event_streams_open", -1);
+}
@@ -0,0 +1,568 @@
+use crate::integration_tokens;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::schema;
+use crate::local_storage::settings::settings_local_storage::{
+    EXPORT_POLICY_KEY, SettingsLocalStorage,
+};
+use crate::local_storage::sql_builder::SqlBuilder;
+use crate::rest::{self, ErrorReply};
+use crate::snapshot::Snapshot;
+use crate::timezone;
+use crate::{DbPoolMap, ROLE_PRIVILEGED, get_db_path, with_db_pools};
+use futures_util::stream;
+use rusqlite::params;
+use rusqlite::types::ValueRef;
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+const EXPORTABLE_TABLES: &[&str] = &[
+    "users",
+    "contracts",
+    "sawmills",
+    "locations",
+    "locationSawmillJunction",
+    "shipments",
+    "notes",
+    "reviewItems",
+    "crews",
+    "crewMemberJunction",
+    "sawmillPrices",
+    "assortments",
+    "sawmillAssortmentJunction",
+    "quotaReservations",
+    "contractSawmillAllowlist",
+    "tours",
+];
+
+const DATE_COLUMNS: &[(&str, &str)] = &[("locations", "date"), ("contracts", "startDate")];
+
+const MAX_ROUND_DECIMALS: i64 = 6;
+
+const CURSOR_FIELD: &str = "_cursor";
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const STREAM_BUFFERED_CHUNKS: usize = 4;
+
+enum Redaction {
+    Redact,
+    Hash,
+    Round(i32),
+}
+
+impl Redaction {
+    fn parse(action: &str) -> Option<Self> {
+        match action {
+            "redact" => Some(Redaction::Redact),
+            "hash" => Some(Redaction::Hash),
+            _ => action
+                .strip_prefix("round:")
+                .and_then(|decimals| decimals.parse::<i64>().ok())
+                .filter(|decimals| (0..=MAX_ROUND_DECIMALS).contains(decimals))
+                .map(|decimals| Redaction::Round(decimals as i32)),
+        }
+    }
+}
+
+pub struct ExportPolicy {
+    tenant: String,
+    rules: HashMap<(String, String), Redaction>,
+}
+
+impl ExportPolicy {
+    fn parse(tenant: &str, value: &str) -> Result<Self, String> {
+        let policy = serde_json::from_str::<Value>(value)
+            .ok()
+            .and_then(|policy| policy.as_object().cloned())
+            .ok_or_else(|| "Export policy must be an object of tables".to_string())?;
+
+        let mut rules = HashMap::new();
+        for (table_name, columns) in &policy {
+            let table = schema::table(table_name)
+                .filter(|table| EXPORTABLE_TABLES.contains(&table.name))
+                .ok_or_else(|| format!("Unknown export table: {}", table_name))?;
+            let columns = columns
+                .as_object()
+                .ok_or_else(|| format!("Export policy for {} must be an object", table_name))?;
+
+            for (column, action) in columns {
+                if table.column(column).is_none() {
+                    return Err(format!("Unknown column {}.{}", table_name, column));
+                }
+                let redaction = action.as_str().and_then(Redaction::parse).ok_or_else(|| {
+                    format!(
+                        "Invalid action for {}.{}, expected redact, hash or round:<0-{}>",
+                        table_name, column, MAX_ROUND_DECIMALS
+                    )
+                })?;
+                rules.insert((table_name.clone(), column.clone()), redaction);
+            }
+        }
+
+        Ok(ExportPolicy {
+            tenant: tenant.to_string(),
+            rules,
+        })
+    }
+
+    pub fn load(tenant: &str, core_storage: Arc<CoreLocalStorage>) -> Result<Self, ErrorReply> {
+        let value = SettingsLocalStorage::new(core_storage)
+            .and_then(|storage| storage.get_setting(EXPORT_POLICY_KEY))
+            .map_err(|e| rest::internal_error("Failed to load export policy", e))?;
+
+        match value {
+            Some(value) => ExportPolicy::parse(tenant, &value).map_err(|e| {
+                println!("Invalid export policy for tenant {}: {}", tenant, e);
+                rest::error_reply(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "INTERNAL",
+                    "Export policy is invalid",
+                )
+            }),
+            None => Ok(ExportPolicy {
+                tenant: tenant.to_string(),
+                rules: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn apply(&self, table: &str, column: &str, value: Value) -> Value {
+        let Some(redaction) = self.rules.get(&(table.to_string(), column.to_string())) else {
+            return value;
+        };
+
+        match (redaction, value) {
+            (_, Value::Null) => Value::Null,
+            (Redaction::Redact, _) => Value::Null,
+            (Redaction::Hash, value) => {
+                let text = match value {
+                    Value::String(text) => text,
+                    other => other.to_string(),
+                };
+                let digest = Sha256::digest(format!("{}:{}", self.tenant, text));
+                json!(
+                    digest[..8]
+                        .iter()
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect::<String>()
+                )
+            }
+            (Redaction::Round(decimals), Value::Number(number)) => {
+                let factor = 10f64.powi(*decimals);
+                number
+                    .as_f64()
+                    .map(|number| json!((number * factor).round() / factor))
+                    .unwrap_or(Value::Number(number))
+            }
+            (Redaction::Round(_), value) => value,
+        }
+    }
+}
+
+pub fn validate_policy(value: &str) -> Result<(), String> {
+    ExportPolicy::parse("", value).map(|_| ())
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("export" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::addr::remote())
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |file_name: String,
+             authorization: Option<String>,
+             query: HashMap<String, String>,
+             remote_addr: Option<SocketAddr>,
+             db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    export_table(file_name, authorization, query, remote_addr, db_pools).await,
+                ))
+            },
+        )
+}
+
+async fn export_table(
+    file_name: String,
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    remote_addr: Option<SocketAddr>,
+    db_pools: DbPoolMap,
+) -> Result<Response<Body>, ErrorReply> {
+    let user =
+        integration_tokens::authenticate(authorization, remote_addr, &db_pools, ROLE_PRIVILEGED)?;
+
+    let (table, extension) = file_name
+        .rsplit_once('.')
+        .filter(|(table, extension)| {
+            EXPORTABLE_TABLES.contains(table) && ["csv", "json", "ndjson"].contains(extension)
+        })
+        .map(|(table, extension)| (table.to_string(), extension.to_string()))
+        .ok_or_else(|| rest::error_reply(StatusCode::NOT_FOUND, "NOT_FOUND", "Unknown export"))?;
+    user.require_read(&table)?;
+
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let tenant_timezone = timezone::for_tenant(core_storage.clone());
+    let policy = ExportPolicy::load(&user.tenant, core_storage)?;
+    let invalid_boundary = |name: &str| {
+        rest::error_reply(
+            StatusCode::BAD_REQUEST,
+            "VALIDATION_FAILED",
+            &format!(
+                "{} must be a date, an RFC3339 timestamp or milliseconds",
+                name
+            ),
+        )
+    };
+    let from = match query.get("from") {
+        Some(from) => Some(
+            timezone::parse_timestamp(from, &tenant_timezone)
+                .ok_or_else(|| invalid_boundary("from"))?,
+        ),
+        None => None,
+    };
+    let to = match query.get("to") {
+        Some(to) => Some(
+            timezone::parse_range_end(to, &tenant_timezone)
+                .ok_or_else(|| invalid_boundary("to"))?,
+        ),
+        None => None,
+    };
+
+    let db_path = get_db_path(&user.tenant);
+    let disposition = format!(
+        "attachment; filename=\"{}-{}.{}\"",
+        user.tenant, table, extension
+    );
+    if extension == "ndjson" {
+        let window = RowWindow::parse(&query, from, to)?;
+        return stream_ndjson(db_path, table, window, policy, disposition).await;
+    }
+
+    let export_table = table.clone();
+    let is_json = extension == "json";
+    let body = tokio::task::spawn_blocking(move || {
+        let snapshot = Snapshot::create(&db_path)?;
+        let (columns, rows) = load_rows(&snapshot, &export_table, from, to, &policy)?;
+        Ok::<_, rusqlite::Error>(if is_json {
+            rows_to_json(&columns, rows)
+        } else {
+            rows_to_csv(&columns, rows)
+        })
+    })
+    .await
+    .map_err(|e| {
+        println!("Export task failed: {:?}", e);
+        rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Export failed",
+        )
+    })?
+    .map_err(|e| rest::internal_error("Export failed", e))?;
+
+    let content_type = if is_json {
+        "application/json"
+    } else {
+        "text/csv; charset=utf-8"
+    };
+    Response::builder()
+        .header("Content-Type", content_type)
+        .header("Content-Disposition", disposition)
+        .body(Body::from(body))
+        .map_err(response_error)
+}
+
+fn response_error(e: warp::http::Error) -> ErrorReply {
+    println!("Failed to build export response: {:?}", e);
+    rest::error_reply(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "INTERNAL",
+        "Export failed",
+    )
+}
+
+struct RowWindow {
+    cursor: i64,
+    limit: Option<i64>,
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+impl RowWindow {
+    fn parse(
+        query: &HashMap<String, String>,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Self, ErrorReply> {
+        let invalid = |message: &str| {
+            rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message)
+        };
+        let cursor = match query.get("cursor") {
+            Some(cursor) => cursor
+                .parse::<i64>()
+                .ok()
+                .filter(|cursor| *cursor >= 0)
+                .ok_or_else(|| invalid("cursor must be a non-negative integer"))?,
+            None => 0,
+        };
+        let limit = match query.get("limit") {
+            Some(limit) => Some(
+                limit
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .ok_or_else(|| invalid("limit must be a positive integer"))?,
+            ),
+            None => None,
+        };
+
+        Ok(RowWindow {
+            cursor,
+            limit,
+            from,
+            to,
+        })
+    }
+}
+
+async fn stream_ndjson(
+    db_path: String,
+    table: String,
+    window: RowWindow,
+    policy: ExportPolicy,
+    disposition: String,
+) -> Result<Response<Body>, ErrorReply> {
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let (chunk_tx, chunk_rx) = mpsc::channel(STREAM_BUFFERED_CHUNKS);
+
+    tokio::task::spawn_blocking(move || {
+        let result = stream_rows(&db_path, &table, &window, &policy, ready_tx, &chunk_tx);
+        if let Err(e) = result {
+            println!("NDJSON export of {} failed: {:?}", table, e);
+            let _ = chunk_tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+
+    match ready_rx.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(rest::internal_error("Export failed", e)),
+        Err(_) => {
+            return Err(rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Export failed",
+            ));
+        }
+    }
+
+    let body = Body::wrap_stream(stream::unfold(chunk_rx, |mut chunk_rx| async move {
+        chunk_rx.recv().await.map(|chunk| (chunk, chunk_rx))
+    }));
+
+    Response::builder()
+        .header("Content-Type", "application/x-ndjson")
+        .header("Content-Disposition", disposition)
+        .body(body)
+        .map_err(response_error)
+}
+
+fn stream_rows(
+    db_path: &str,
+    table: &str,
+    window: &RowWindow,
+    policy: &ExportPolicy,
+    ready: oneshot::Sender<rusqlite::Result<()>>,
+    chunks: &mpsc::Sender<std::io::Result<Vec<u8>>>,
+) -> rusqlite::Result<()> {
+    let prepared = Snapshot::create(db_path).and_then(|snapshot| {
+        let builder = SqlBuilder::for_table(table)?;
+        let mut query = format!(
+            "SELECT rowid AS \"{}\", * FROM \"{}\" WHERE rowid > ?1",
+            CURSOR_FIELD, table
+        );
+        if let Some(column) = date_column(&builder, table)
+            && (window.from.is_some() || window.to.is_some())
+        {
+            query.push_str(&format!(
+                " AND \"{}\" >= ?2 AND \"{}\" < ?3",
+                column, column
+            ));
+        }
+        query.push_str(" ORDER BY rowid LIMIT ?4");
+        Ok((snapshot, query))
+    });
+    let (snapshot, query) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+    let mut stmt = match snapshot.connection().prepare(&query) {
+        Ok(stmt) => stmt,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+    if ready.send(Ok(())).is_err() {
+        return Ok(());
+    }
+
+    let columns: Vec<String> = stmt
+        .column_names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    let mut rows = stmt.query(params![
+        window.cursor,
+        window.from.unwrap_or(i64::MIN),
+        window.to.unwrap_or(i64::MAX),
+        window.limit.unwrap_or(-1)
+    ])?;
+
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    while let Some(row) = rows.next()? {
+        let mut object = Map::with_capacity(columns.len());
+        for (i, column) in columns.iter().enumerate() {
+            let value = field_value(row.get_ref(i)?);
+            object.insert(column.clone(), policy.apply(table, column, value));
+        }
+        serde_json::to_writer(&mut chunk, &object)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        chunk.push(b'\n');
+
+        if chunk.len() >= CHUNK_SIZE {
+            let full = std::mem::replace(&mut chunk, Vec::with_capacity(CHUNK_SIZE));
+            if chunks.blocking_send(Ok(full)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        let _ = chunks.blocking_send(Ok(chunk));
+    }
+
+    Ok(())
+}
+
+fn date_column(builder: &SqlBuilder, table: &str) -> Option<&'static str> {
+    DATE_COLUMNS
+        .iter()
+        .find(|(date_table, _)| *date_table == table)
+        .map(|(_, column)| *column)
+        .or_else(|| builder.has_column("lastEdit").then_some("lastEdit"))
+}
+
+fn field_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(value) => json!(value),
+        ValueRef::Real(value) => json!(value),
+        ValueRef::Text(text) => json!(String::from_utf8_lossy(text)),
+        ValueRef::Blob(blob) => json!(format!("<{} bytes>", blob.len())),
+    }
+}
+
+fn load_rows(
+    snapshot: &Snapshot,
+    table: &str,
+    from: Option<i64>,
+    to: Option<i64>,
+    policy: &ExportPolicy,
+) -> rusqlite::Result<(Vec<String>, Vec<Vec<Value>>)> {
+    let builder = SqlBuilder::for_table(table)?;
+    let mut query = builder.select_all();
+    if let Some(column) = date_column(&builder, table)
+        && (from.is_some() || to.is_some())
+    {
+        query.push_str(&format!(
+            " WHERE \"{}\" >= ?1 AND \"{}\" < ?2",
+            column, column
+        ));
+    }
+    let mut stmt = snapshot.connection().prepare(&query)?;
+    let columns: Vec<String> = stmt
+        .column_names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+    let mut rows = if stmt.parameter_count() > 0 {
+        stmt.query(params![from.unwrap_or(i64::MIN), to.unwrap_or(i64::MAX)])?
+    } else {
+        stmt.query([])?
+    };
+    let mut values = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut fields = Vec::with_capacity(columns.len());
+        for (i, column) in columns.iter().enumerate() {
+            let value = field_value(row.get_ref(i)?);
+            fields.push(policy.apply(table, column, value));
+        }
+        values.push(fields);
+    }
+
+    Ok((columns, values))
+}
+
+fn rows_to_csv(columns: &[String], rows: Vec<Vec<Value>>) -> String {
+    let mut csv = columns
+        .iter()
+        .map(|name| escape_csv(name))
+        .collect::<Vec<_>>()
+        .join(",");
+    csv.push('\n');
+
+    for row in rows {
+        let fields: Vec<String> = row
+            .into_iter()
+            .map(|value| match value {
+                Value::Null => String::new(),
+                Value::String(text) => escape_csv(&text),
+                other => other.to_string(),
+            })
+            .collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn rows_to_json(columns: &[String], rows: Vec<Vec<Value>>) -> String {
+    let rows: Vec<Value> = rows
+        .into_iter()
+        .map(|row| {
+            Value::Object(
+                columns
+                    .iter()
+                    .cloned()
+                    .zip(row)
+                    .collect::<Map<String, Value>>(),
+            )
+        })
+        .collect();
+
+    Value::Array(rows).to_string()
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
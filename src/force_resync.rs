@@ -0,0 +1,624 @@
+use crate::client_admin;
+use crate::local_storage::activity_feed::activity_feed_local_storage::ActivityFeedLocalStorage;
+use crate::local_storage::assortment::assortment_local_storage::AssortmentLocalStorage;
+use crate::local_storage::contract::contract_local_storage::ContractLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::crew::crew_local_storage::CrewLocalStorage;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use crate::local_storage::note::note_local_storage::NoteLocalStorage;
+use crate::local_storage::photo::photo_local_storage::PhotoLocalStorage;
+use crate::local_storage::quota_reservation::quota_reservation_local_storage::QuotaReservationLocalStorage;
+use crate::local_storage::resync_request::resync_request_local_storage::{
+    ResyncRequestLocalStorage, STATUS_COMPLETED, STATUS_FAILED, STATUS_PENDING, STATUS_SENT,
+    STATUS_STREAMING,
+};
+use crate::local_storage::review_item::review_item_local_storage::ReviewItemLocalStorage;
+use crate::local_storage::sawmill::sawmill_local_storage::SawmillLocalStorage;
+use crate::local_storage::sawmill_price::sawmill_price_local_storage::SawmillPriceLocalStorage;
+use crate::local_storage::shipment::shipment_local_storage::ShipmentLocalStorage;
+use crate::local_storage::shipment_document::shipment_document_local_storage::ShipmentDocumentLocalStorage;
+use crate::local_storage::tour::tour_local_storage::TourLocalStorage;
+use crate::local_storage::user::user_local_storage::UserLocalStorage;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{
+    Clients, DbPoolMap, ROLE_ADMIN, ROLE_PRIVILEGED, abort_photo_stream, deliver_photo,
+    get_client_db_path_and_tenant, get_client_role, get_client_user_id, is_client_connected,
+    send_message, with_clients, with_db_pools,
+};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const PROGRESS_INTERVAL: usize = 200;
+const REQUEST_LIST_LIMIT: i64 = 100;
+const DEFAULT_REASON: &str = "Local data is being rebuilt by support";
+
+type Fetch = fn(Arc<CoreLocalStorage>, i64) -> rusqlite::Result<Vec<Value>>;
+
+struct Stage {
+    name: &'static str,
+    msg_type: &'static str,
+    table: &'static str,
+    min_role: i64,
+    fetch: Fetch,
+}
+
+const STAGES: &[Stage] = &[
+    Stage {
+        name: "users",
+        msg_type: "user_update",
+        table: "users",
+        min_role: 0,
+        fetch: |core, date| UserLocalStorage::new(core)?.get_user_updates_by_date(date),
+    },
+    Stage {
+        name: "assortments",
+        msg_type: "assortment_update",
+        table: "assortments",
+        min_role: 0,
+        fetch: |core, date| AssortmentLocalStorage::new(core)?.get_assortment_updates_by_date(date),
+    },
+    Stage {
+        name: "sawmills",
+        msg_type: "sawmill_update",
+        table: "sawmills",
+        min_role: 0,
+        fetch: |core, date| SawmillLocalStorage::new(core)?.get_sawmill_updates_by_date(date),
+    },
+    Stage {
+        name: "crews",
+        msg_type: "crew_update",
+        table: "crews",
+        min_role: 0,
+        fetch: |core, date| CrewLocalStorage::new(core)?.get_crew_updates_by_date(date),
+    },
+    Stage {
+        name: "tours",
+        msg_type: "tour_update",
+        table: "tours",
+        min_role: 0,
+        fetch: |core, date| TourLocalStorage::new(core)?.get_tour_updates_by_date(date),
+    },
+    Stage {
+        name: "contracts",
+        msg_type: "contract_update",
+        table: "contracts",
+        min_role: 0,
+        fetch: |core, date| ContractLocalStorage::new(core)?.get_contract_updates_by_date(date),
+    },
+    Stage {
+        name: "locations",
+        msg_type: "location_update",
+        table: "locations",
+        min_role: 0,
+        fetch: |core, date| LocationLocalStorage::new(core)?.get_location_updates_by_date(date),
+    },
+    Stage {
+        name: "shipments",
+        msg_type: "shipment_update",
+        table: "shipments",
+        min_role: 0,
+        fetch: |core, date| ShipmentLocalStorage::new(core)?.get_shipments_by_date(date),
+    },
+    Stage {
+        name: "shipmentDocuments",
+        msg_type: "shipment_document_update",
+        table: "shipmentDocuments",
+        min_role: 0,
+        fetch: |core, date| {
+            ShipmentDocumentLocalStorage::new(core)?.get_document_updates_by_date(date)
+        },
+    },
+    Stage {
+        name: "notes",
+        msg_type: "note_update",
+        table: "notes",
+        min_role: 0,
+        fetch: |core, date| NoteLocalStorage::new(core)?.get_note_updates_by_date(date),
+    },
+    Stage {
+        name: "photos",
+        msg_type: "photo_update",
+        table: "photos",
+        min_role: 0,
+        fetch: |core, date| PhotoLocalStorage::new(core)?.get_photo_updates_by_date(date),
+    },
+    Stage {
+        name: "activity",
+        msg_type: "activity_update",
+        table: "activityFeed",
+        min_role: 0,
+        fetch: |core, date| ActivityFeedLocalStorage::new(core)?.get_activity_updates_by_date(date),
+    },
+    Stage {
+        name: "reviewItems",
+        msg_type: "review_item_update",
+        table: "reviewItems",
+        min_role: ROLE_PRIVILEGED,
+        fetch: |core, date| {
+            ReviewItemLocalStorage::new(core)?.get_review_item_updates_by_date(date)
+        },
+    },
+    Stage {
+        name: "sawmillPrices",
+        msg_type: "sawmill_price_update",
+        table: "sawmillPrices",
+        min_role: ROLE_PRIVILEGED,
+        fetch: |core, date| SawmillPriceLocalStorage::new(core)?.get_price_updates_by_date(date),
+    },
+    Stage {
+        name: "quotaReservations",
+        msg_type: "quota_reservation_update",
+        table: "quotaReservations",
+        min_role: ROLE_PRIVILEGED,
+        fetch: |core, date| {
+            QuotaReservationLocalStorage::new(core)?.get_reservation_updates_by_date(date)
+        },
+    },
+];
+
+fn stages_for(role: i64) -> impl Iterator<Item = &'static Stage> {
+    STAGES.iter().filter(move |stage| role >= stage.min_role)
+}
+
+fn directive(resync: &Value, role: i64, tenant: &str) -> Value {
+    json!({
+        "type": "force_resync",
+        "data": {
+            "resyncId": resync["id"],
+            "reason": resync["reason"],
+            "stages": stages_for(role).map(|stage| stage.name).collect::<Vec<_>>()
+        },
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    })
+}
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let create = warp::path!("admin" / "resyncs")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools.clone()))
+        .and_then(
+            |authorization, body, clients: Clients, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    request_resync(authorization, body, &clients, &db_pools).await,
+                ))
+            },
+        );
+
+    let list = warp::path!("admin" / "resyncs")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_resyncs(authorization, &db_pools))
+        });
+
+    let get = warp::path!("admin" / "resyncs" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|id, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(get_resync(id, authorization, &db_pools))
+        });
+
+    create.or(list).unify().or(get).unify()
+}
+
+fn open_requests(tenant: &str) -> Result<ResyncRequestLocalStorage, ErrorReply> {
+    rest::open_storage(tenant)
+        .and_then(ResyncRequestLocalStorage::new)
+        .map_err(|e| rest::internal_error("Failed to open resync requests", e))
+}
+
+async fn request_resync(
+    authorization: Option<String>,
+    body: Value,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let admin = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let reason = body["reason"]
+        .as_str()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty())
+        .unwrap_or(DEFAULT_REASON);
+
+    let connected: Vec<(String, String, i64)> = {
+        let clients_lock = clients.lock().map_err(|e| {
+            println!("Failed to lock clients: {:?}", e);
+            rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to request resync",
+            )
+        })?;
+        let matches = |client_id: &str, user_id: &str| match (
+            body["clientId"].as_str(),
+            body["userId"].as_str(),
+        ) {
+            (Some(target), _) => target == client_id,
+            (None, Some(target)) => target == user_id,
+            (None, None) => false,
+        };
+        clients_lock
+            .iter()
+            .filter(|(client_id, client)| {
+                client.db_name == admin.tenant
+                    && client.authenticated_at > 0
+                    && matches(client_id, &client.user_id)
+            })
+            .map(|(client_id, client)| (client_id.clone(), client.user_id.clone(), client.role))
+            .collect()
+    };
+
+    let pending_user = match (body["clientId"].as_str(), body["userId"].as_str()) {
+        (Some(_), _) if connected.is_empty() => {
+            return Err(rest::error_reply(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                "Connection not found",
+            ));
+        }
+        (Some(_), _) => None,
+        (None, Some(user_id)) if connected.is_empty() => {
+            let core_storage = rest::open_storage(&admin.tenant)
+                .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+            let exists = UserLocalStorage::new(core_storage)
+                .and_then(|storage| storage.get_user_by_id(user_id))
+                .map_err(|e| rest::internal_error("Failed to load user", e))?
+                .is_some();
+            if !exists {
+                return Err(rest::error_reply(
+                    StatusCode::NOT_FOUND,
+                    "NOT_FOUND",
+                    "User not found",
+                ));
+            }
+            Some(user_id)
+        }
+        (None, Some(_)) => None,
+        (None, None) => {
+            return Err(rest::error_reply(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "userId or clientId is required",
+            ));
+        }
+    };
+
+    let storage = open_requests(&admin.tenant)?;
+    let mut created = Vec::new();
+    if let Some(user_id) = pending_user {
+        let id = Uuid::new_v4().to_string();
+        storage
+            .create_request(&id, user_id, None, reason, &admin.user_id, STATUS_PENDING)
+            .map_err(|e| rest::internal_error("Failed to save resync request", e))?;
+        created.push(id);
+    }
+    for (client_id, user_id, role) in &connected {
+        let id = Uuid::new_v4().to_string();
+        storage
+            .create_request(
+                &id,
+                user_id,
+                Some(client_id),
+                reason,
+                &admin.user_id,
+                STATUS_SENT,
+            )
+            .map_err(|e| rest::internal_error("Failed to save resync request", e))?;
+        let resync = json!({ "id": id, "reason": reason });
+        send_message(
+            client_id.clone(),
+            &directive(&resync, *role, &admin.tenant),
+            clients,
+        )
+        .await;
+        created.push(id);
+    }
+
+    let resyncs = created
+        .iter()
+        .map(|id| storage.get_request(id))
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| rest::internal_error("Failed to load resync request", e))?;
+    metrics::add("force_resyncs_requested_total", resyncs.len() as u64);
+    println!(
+        "Admin {} of tenant {} requested {} resync(s): {}",
+        admin.user_id,
+        admin.tenant,
+        resyncs.len(),
+        reason
+    );
+    client_admin::audit(
+        &admin,
+        "force_resync",
+        if body["clientId"].is_string() {
+            "client"
+        } else {
+            "user"
+        },
+        body["clientId"]
+            .as_str()
+            .or(body["userId"].as_str())
+            .unwrap_or_default(),
+        &json!({ "reason": reason, "resyncIds": created }),
+    );
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "resyncs": resyncs })),
+        StatusCode::CREATED,
+    ))
+}
+
+fn list_resyncs(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let admin = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let resyncs = open_requests(&admin.tenant)?
+        .get_requests(REQUEST_LIST_LIMIT)
+        .map_err(|e| rest::internal_error("Failed to load resync requests", e))?;
+
+    Ok(rest::json_reply(&json!({ "resyncs": resyncs })))
+}
+
+fn get_resync(
+    id: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let admin = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    open_requests(&admin.tenant)?
+        .get_request(&id)
+        .map_err(|e| rest::internal_error("Failed to load resync request", e))?
+        .map(|resync| rest::json_reply(&resync))
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                "Resync request not found",
+            )
+        })
+}
+
+fn client_storage(
+    client_id: &str,
+    clients: &Clients,
+) -> Option<(ResyncRequestLocalStorage, String)> {
+    let (_, tenant) = get_client_db_path_and_tenant(client_id, clients)?;
+    match rest::open_storage(&tenant).and_then(ResyncRequestLocalStorage::new) {
+        Ok(storage) => Some((storage, tenant)),
+        Err(e) => {
+            println!("Failed to open resync requests of {}: {:?}", tenant, e);
+            None
+        }
+    }
+}
+
+pub async fn deliver_pending(client_id: &str, clients: &Clients) {
+    let Some((storage, tenant)) = client_storage(client_id, clients) else {
+        return;
+    };
+    let user_id = get_client_user_id(client_id, clients).unwrap_or_default();
+
+    let open = match storage.get_open_requests(&user_id) {
+        Ok(open) => open,
+        Err(e) => {
+            println!("Failed to load open resyncs of {}: {:?}", user_id, e);
+            return;
+        }
+    };
+    let Some(resync) = open.into_iter().find(|resync| {
+        resync["clientId"]
+            .as_str()
+            .is_none_or(|previous| !is_client_connected(previous, clients))
+    }) else {
+        return;
+    };
+    match storage.assign_client(resync["id"].as_str().unwrap_or_default(), client_id) {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            println!("Failed to assign resync {}: {:?}", resync["id"], e);
+            return;
+        }
+    }
+
+    println!(
+        "Delivering pending resync {} to client {}",
+        resync["id"], client_id
+    );
+    send_message(
+        client_id.to_string(),
+        &directive(&resync, get_client_role(client_id, clients), &tenant),
+        clients,
+    )
+    .await;
+}
+
+fn count_rows(core_storage: &CoreLocalStorage, table: &str) -> i64 {
+    core_storage
+        .get_connection()
+        .and_then(|conn| {
+            conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+                row.get(0)
+            })
+        })
+        .unwrap_or(0)
+}
+
+async fn send_progress(
+    resync_id: &str,
+    stage: &str,
+    sent: usize,
+    total: i64,
+    tenant: &str,
+    client_id: &str,
+    clients: &Clients,
+) -> bool {
+    let response = json!({
+        "type": "force_resync_progress",
+        "data": {
+            "resyncId": resync_id,
+            "stage": stage,
+            "sent": sent,
+            "total": total
+        },
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+    send_message(client_id.to_string(), &response, clients).await
+}
+
+pub async fn stream(data: &Value, client_id: &str, clients: &Clients) -> bool {
+    let Some((storage, tenant)) = client_storage(client_id, clients) else {
+        return false;
+    };
+    let resync_id = data["resyncId"].as_str().unwrap_or_default();
+    let user_id = get_client_user_id(client_id, clients).unwrap_or_default();
+    let role = get_client_role(client_id, clients);
+
+    let resync = storage
+        .get_request(resync_id)
+        .ok()
+        .flatten()
+        .filter(|resync| {
+            resync["userId"] == user_id.as_str()
+                && resync["clientId"] == client_id
+                && resync["status"] != STATUS_COMPLETED
+        });
+    if resync.is_none() {
+        let response = json!({
+            "type": "error",
+            "data": {
+                "code": "NOT_FOUND",
+                "message": "No open resync request for this client",
+                "refType": "force_resync_ready",
+                "id": resync_id
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        send_message(client_id.to_string(), &response, clients).await;
+        return false;
+    }
+
+    let core_storage = match rest::open_storage(&tenant) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to open tenant storage for resync: {:?}", e);
+            return false;
+        }
+    };
+    let mut progress = Map::new();
+    let mut cursors = Map::new();
+    let save_progress = |status: &str, progress: &Map<String, Value>| {
+        if let Err(e) = storage.update_progress(resync_id, status, &Value::Object(progress.clone()))
+        {
+            println!("Failed to record resync {} progress: {:?}", resync_id, e);
+        }
+    };
+    save_progress(STATUS_STREAMING, &progress);
+    println!(
+        "Streaming full resync {} to client {} of tenant {}",
+        resync_id, client_id, tenant
+    );
+
+    for stage in stages_for(role) {
+        let total = count_rows(&core_storage, stage.table);
+        let mut sent = 0;
+        let mut date = 0;
+        let completed = 'stage: loop {
+            let rows = match (stage.fetch)(core_storage.clone(), date) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    println!("Failed to load {} for resync: {:?}", stage.name, e);
+                    break 'stage false;
+                }
+            };
+            if rows.is_empty() {
+                break 'stage true;
+            }
+
+            for row in &rows {
+                let response = json!({
+                    "type": stage.msg_type,
+                    "data": row,
+                    "dbName": tenant,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+                let delivered = if stage.msg_type == "photo_update" {
+                    deliver_photo(client_id, &response, clients).await
+                } else {
+                    send_message(client_id.to_string(), &response, clients).await
+                };
+                if !delivered {
+                    if stage.msg_type == "photo_update" {
+                        abort_photo_stream(client_id, clients);
+                    }
+                    break 'stage false;
+                }
+
+                sent += 1;
+                if let Some(newest_date) = row["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+                if sent % PROGRESS_INTERVAL == 0 {
+                    send_progress(
+                        resync_id, stage.name, sent, total, &tenant, client_id, clients,
+                    )
+                    .await;
+                }
+            }
+        };
+
+        progress.insert(
+            stage.name.to_string(),
+            json!({ "sent": sent, "total": total, "completed": completed }),
+        );
+        if !completed {
+            save_progress(STATUS_FAILED, &progress);
+            metrics::increment("force_resyncs_failed_total");
+            println!(
+                "Resync {} to client {} failed during {}",
+                resync_id, client_id, stage.name
+            );
+            return false;
+        }
+        save_progress(STATUS_STREAMING, &progress);
+        cursors.insert(stage.msg_type.to_string(), json!(date));
+        send_progress(
+            resync_id, stage.name, sent, total, &tenant, client_id, clients,
+        )
+        .await;
+    }
+
+    save_progress(STATUS_COMPLETED, &progress);
+    if let Ok(mut clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get_mut(client_id)
+    {
+        client.sync_completed = true;
+    }
+    metrics::increment("force_resyncs_completed_total");
+    println!("Resync {} to client {} complete", resync_id, client_id);
+
+    let response = json!({
+        "type": "force_resync_complete",
+        "data": { "resyncId": resync_id, "cursors": cursors },
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+    send_message(client_id.to_string(), &response, clients).await
+}
@@ -0,0 +1,219 @@
+use crate::config;
+use crate::local_storage::assortment::assortment_local_storage::AssortmentLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::shipment::shipment_local_storage::{ShipmentLocalStorage, Weighing};
+use crate::metrics;
+use crate::portal::{self, PortalClient};
+use crate::rest::{self, ErrorReply};
+use crate::review_queue;
+use crate::{
+    Clients, DbPoolMap, ROLE_PRIVILEGED, broadcast_to_tenant, with_clients, with_db_pools,
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const MAX_WEIGHT_KG: f64 = 100_000.0;
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("gate" / "shipments" / String / "weight")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |shipment_id, authorization, body, clients: Clients, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    record_weight(shipment_id, authorization, body, &clients, &db_pools).await,
+                ))
+            },
+        )
+}
+
+fn invalid(message: &str) -> ErrorReply {
+    rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message)
+}
+
+fn parse_weight(value: &Value, field: &str) -> Result<Option<f64>, ErrorReply> {
+    match value {
+        Value::Null => Ok(None),
+        value => value
+            .as_f64()
+            .filter(|kg| kg.is_finite() && *kg >= 0.0 && *kg <= MAX_WEIGHT_KG)
+            .map(Some)
+            .ok_or_else(|| {
+                invalid(&format!(
+                    "{} must be a weight between 0 and {} kg",
+                    field, MAX_WEIGHT_KG
+                ))
+            }),
+    }
+}
+
+fn measured_weight(body: &Value) -> Result<f64, ErrorReply> {
+    if let Some(weight) = parse_weight(&body["weightKg"], "weightKg")? {
+        return Ok(weight);
+    }
+
+    let gross = parse_weight(&body["grossKg"], "grossKg")?;
+    let tare = parse_weight(&body["tareKg"], "tareKg")?;
+    match (gross, tare) {
+        (Some(gross), Some(tare)) if gross >= tare => Ok(gross - tare),
+        (Some(_), Some(_)) => Err(invalid("grossKg must not be below tareKg")),
+        _ => Err(invalid("weightKg or grossKg and tareKg are required")),
+    }
+}
+
+fn density_of(core_storage: Arc<CoreLocalStorage>, shipment: &Value) -> rusqlite::Result<f64> {
+    let density = match shipment["assortmentId"].as_str() {
+        Some(assortment_id) => AssortmentLocalStorage::new(core_storage)?
+            .get_assortment_by_id(assortment_id)?
+            .and_then(|assortment| assortment["densityKgPerCubicMeter"].as_f64()),
+        None => None,
+    };
+
+    Ok(density.unwrap_or(config::get().gate.default_density_kg_per_cubic_meter))
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+async fn record_weight(
+    shipment_id: String,
+    authorization: Option<String>,
+    body: Value,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let PortalClient {
+        tenant,
+        sawmill_id,
+        core_storage,
+    } = portal::authenticate(authorization, portal::SCOPE_GATE, db_pools)?;
+
+    let measured_kg = measured_weight(&body)?;
+    let weighed_at = match &body["measuredAt"] {
+        Value::Null => chrono::Utc::now().timestamp_millis(),
+        value => value
+            .as_i64()
+            .filter(|ms| *ms > 0)
+            .ok_or_else(|| invalid("measuredAt must be a timestamp in milliseconds"))?,
+    };
+    let weighbridge_id = match &body["weighbridgeId"] {
+        Value::Null => None,
+        value => Some(
+            value
+                .as_str()
+                .ok_or_else(|| invalid("weighbridgeId must be a string"))?
+                .to_string(),
+        ),
+    };
+
+    let not_found = || rest::error_reply(StatusCode::NOT_FOUND, "NOT_FOUND", "Shipment not found");
+    let shipment = core_storage
+        .get_existing_by_id("shipments", &shipment_id)
+        .map_err(|e| rest::internal_error("Failed to load shipment", e))?
+        .into_iter()
+        .next()
+        .ok_or_else(not_found)?;
+    if shipment["sawmillId"].as_str() != Some(sawmill_id.as_str()) {
+        return Err(rest::error_reply(
+            StatusCode::FORBIDDEN,
+            "PERMISSION_DENIED",
+            "Shipment was not delivered to this sawmill",
+        ));
+    }
+
+    let density = density_of(core_storage.clone(), &shipment)
+        .map_err(|e| rest::internal_error("Failed to load assortment", e))?;
+    let expected_kg = shipment["quantity"]
+        .as_f64()
+        .map(|quantity| quantity * density)
+        .filter(|kg| *kg > 0.0);
+    let discrepancy_percent =
+        expected_kg.map(|expected| round((measured_kg - expected) / expected * 100.0));
+
+    let threshold = config::get().gate.discrepancy_threshold_percent;
+    let review_reason = discrepancy_percent
+        .filter(|percent| percent.abs() > threshold)
+        .map(|percent| {
+            format!(
+                "Measured weight {:.0} kg differs {:+.1}% from expected {:.0} kg",
+                measured_kg,
+                percent,
+                expected_kg.unwrap_or_default()
+            )
+        });
+
+    let weighing = Weighing {
+        measured_kg: round(measured_kg),
+        expected_kg: expected_kg.map(round),
+        discrepancy_percent,
+        weighed_at,
+        weighbridge_id,
+        review_reason,
+    };
+    let recorded = ShipmentLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.record_weighing(&shipment_id, &weighing))
+        .map_err(|e| rest::internal_error("Failed to record weighing", e))?;
+    if !recorded {
+        return Err(not_found());
+    }
+
+    metrics::increment("gate_weighings_total");
+    println!(
+        "Sawmill {} of tenant {} weighed shipment {}: {:.0} kg (expected {:.0} kg, {:+.1}%)",
+        sawmill_id,
+        tenant,
+        shipment_id,
+        measured_kg,
+        weighing.expected_kg.unwrap_or_default(),
+        discrepancy_percent.unwrap_or_default()
+    );
+
+    if let Some(reason) = &weighing.review_reason {
+        metrics::increment("gate_weight_discrepancies_total");
+        match review_queue::open_review_item(core_storage.clone(), "shipment", &shipment_id, reason)
+        {
+            Ok(Some(review_item)) => {
+                broadcast_to_tenant(
+                    &tenant,
+                    "review_item_update",
+                    &review_item,
+                    ROLE_PRIVILEGED,
+                    clients,
+                )
+                .await;
+            }
+            Ok(None) => {}
+            Err(e) => println!("Failed to open review item: {:?}", e),
+        }
+    }
+
+    if let Some(shipment) = core_storage
+        .get_existing_by_id("shipments", &shipment_id)
+        .map_err(|e| rest::internal_error("Failed to load shipment", e))?
+        .into_iter()
+        .next()
+    {
+        broadcast_to_tenant(&tenant, "shipment_update", &shipment, 0, clients).await;
+    }
+
+    Ok(rest::json_reply(&json!({
+        "shipmentId": shipment_id,
+        "measuredWeightKg": weighing.measured_kg,
+        "expectedWeightKg": weighing.expected_kg,
+        "densityKgPerCubicMeter": density,
+        "weightDiscrepancyPercent": discrepancy_percent,
+        "weighedAt": weighed_at,
+        "weighbridgeId": weighing.weighbridge_id,
+        "needsReview": weighing.review_reason.is_some(),
+        "reviewReason": weighing.review_reason
+    })))
+}
@@ -0,0 +1,290 @@
+use crate::coordinates;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use crate::local_storage::settings::settings_local_storage::{
+    FIELD_MAPPING_KEY, SettingsLocalStorage,
+};
+use crate::rest::{self, ErrorReply};
+use crate::{
+    Clients, DbPoolMap, ROLE_PRIVILEGED, broadcast_to_tenant, with_clients, with_db_pools,
+};
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const MAPPED_FIELDS: &[(&str, &str)] = &[
+    ("partieNr", "partieNr"),
+    ("initialQuantity", "quantity"),
+    ("initialOversizeQuantity", "oversizeQuantity"),
+    ("initialPieceCount", "pieceCount"),
+    ("additionalInfo", "additionalInfo"),
+    ("ownerInformation", "ownerInformation"),
+];
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("import" / "locations")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_IMPORT_BODY_BYTES))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |authorization, body, clients: Clients, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    import_locations(authorization, body, &clients, &db_pools).await,
+                ))
+            },
+        )
+}
+
+async fn import_locations(
+    authorization: Option<String>,
+    body: Value,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    let bad_request =
+        |message: &str| rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message);
+
+    let contract_id = body["contractId"]
+        .as_str()
+        .ok_or_else(|| bad_request("contractId is required"))?;
+    let features = body["featureCollection"]["features"]
+        .as_array()
+        .ok_or_else(|| bad_request("featureCollection must be a GeoJSON FeatureCollection"))?;
+
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let contract_exists = !core_storage
+        .get_existing_by_id("contracts", contract_id)
+        .map_err(|e| rest::internal_error("Failed to load contract", e))?
+        .is_empty();
+    if !contract_exists {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Contract not found",
+        ));
+    }
+
+    let stored_mapping = SettingsLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_setting(FIELD_MAPPING_KEY))
+        .map_err(|e| rest::internal_error("Failed to load field mapping", e))?
+        .and_then(|value| serde_json::from_str::<Value>(&value).ok());
+    let mapping = field_mapping(stored_mapping.as_ref(), body.get("fieldMapping"));
+
+    let location_storage = LocationLocalStorage::new(core_storage)
+        .map_err(|e| rest::internal_error("Failed to create location storage", e))?;
+
+    let mut created = Vec::new();
+    let mut errors = Vec::new();
+    for (index, feature) in features.iter().enumerate() {
+        let location = match feature_to_location(feature, &mapping, contract_id, &body) {
+            Ok(location) => location,
+            Err(message) => {
+                errors.push(json!({
+                    "index": index,
+                    "featureId": feature.get("id"),
+                    "message": message
+                }));
+                continue;
+            }
+        };
+
+        let location_id = location["id"].as_str().unwrap_or_default();
+        let saved = location_storage
+            .save_location(&location)
+            .and_then(|_| location_storage.get_location_by_id(location_id));
+        match saved {
+            Ok(location) => {
+                broadcast_to_tenant(&user.tenant, "location_update", &location, 0, clients).await;
+                created.push(location);
+            }
+            Err(e) => {
+                println!("Failed to save imported location: {:?}", e);
+                errors.push(json!({
+                    "index": index,
+                    "featureId": feature.get("id"),
+                    "message": "Failed to save location"
+                }));
+            }
+        }
+    }
+
+    println!(
+        "Imported {} locations for contract {} ({} failed)",
+        created.len(),
+        contract_id,
+        errors.len()
+    );
+
+    Ok(rest::json_reply(&json!({
+        "created": created.len(),
+        "failed": errors.len(),
+        "locations": created,
+        "errors": errors
+    })))
+}
+
+fn field_mapping(stored: Option<&Value>, requested: Option<&Value>) -> Map<String, Value> {
+    let mut mapping = Map::new();
+    for (field, property) in MAPPED_FIELDS {
+        mapping.insert(field.to_string(), json!(property));
+    }
+
+    for layer in [stored, requested].into_iter().flatten() {
+        if let Some(overrides) = layer.as_object() {
+            for (field, property) in overrides {
+                if mapping.contains_key(field) && property.is_string() {
+                    mapping.insert(field.clone(), property.clone());
+                }
+            }
+        }
+    }
+
+    mapping
+}
+
+fn feature_to_location(
+    feature: &Value,
+    mapping: &Map<String, Value>,
+    contract_id: &str,
+    body: &Value,
+) -> Result<Value, String> {
+    if feature["type"].as_str() != Some("Feature") {
+        return Err("Not a GeoJSON Feature".to_string());
+    }
+
+    let (latitude, longitude) = feature_position(&feature["geometry"])?;
+    let properties = &feature["properties"];
+    let property = |field: &str| {
+        let name = mapping[field].as_str().unwrap_or(field);
+        properties.get(name).filter(|value| !value.is_null())
+    };
+
+    let partie_nr = match property("partieNr") {
+        Some(Value::String(value)) if !value.trim().is_empty() => value.trim().to_string(),
+        Some(Value::Number(value)) => value.to_string(),
+        _ => return Err(format!("Missing property {}", mapping["partieNr"])),
+    };
+
+    let quantity = number_property(property("initialQuantity"), "initialQuantity")?;
+    let oversize_quantity = number_property(
+        property("initialOversizeQuantity"),
+        "initialOversizeQuantity",
+    )?;
+    let piece_count = number_property(property("initialPieceCount"), "initialPieceCount")?;
+    if piece_count.fract() != 0.0 {
+        return Err("initialPieceCount must be a whole number".to_string());
+    }
+
+    let text = |field: &str| property(field).and_then(|v| v.as_str()).map(str::to_string);
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let mut location = json!({
+        "id": Uuid::new_v4().to_string(),
+        "done": 0,
+        "started": 0,
+        "lastEdit": now,
+        "latitude": latitude,
+        "longitude": longitude,
+        "partieNr": partie_nr,
+        "date": now,
+        "additionalInfo": text("additionalInfo").unwrap_or_default(),
+        "ownerInformation": text("ownerInformation"),
+        "initialQuantity": quantity,
+        "initialOversizeQuantity": oversize_quantity,
+        "initialPieceCount": piece_count as i64,
+        "currentQuantity": quantity,
+        "currentOversizeQuantity": oversize_quantity,
+        "currentPieceCount": piece_count as i64,
+        "contractId": contract_id,
+        "deleted": 0,
+        "sawmillIds": body.get("sawmillIds").cloned().unwrap_or(json!([])),
+        "oversizeSawmillIds": body.get("oversizeSawmillIds").cloned().unwrap_or(json!([]))
+    });
+    coordinates::normalize_location(&mut location)?;
+
+    Ok(location)
+}
+
+fn number_property(value: Option<&Value>, field: &str) -> Result<f64, String> {
+    let number = match value {
+        None => 0.0,
+        Some(Value::Number(number)) => number.as_f64().unwrap_or_default(),
+        Some(Value::String(text)) => text
+            .trim()
+            .replace(',', ".")
+            .parse()
+            .map_err(|_| format!("{} is not a number: {}", field, text))?,
+        Some(other) => return Err(format!("{} is not a number: {}", field, other)),
+    };
+
+    if !number.is_finite() || number < 0.0 {
+        return Err(format!("{} must not be negative", field));
+    }
+
+    Ok(number)
+}
+
+fn feature_position(geometry: &Value) -> Result<(f64, f64), String> {
+    let coordinates = &geometry["coordinates"];
+    let points: Vec<&Value> = match geometry["type"].as_str() {
+        Some("Point") => vec![coordinates],
+        Some("Polygon") => coordinates
+            .get(0)
+            .and_then(|ring| ring.as_array())
+            .map(|ring| open_ring(ring).collect())
+            .unwrap_or_default(),
+        Some("MultiPolygon") => coordinates
+            .as_array()
+            .map(|polygons| {
+                polygons
+                    .iter()
+                    .filter_map(|polygon| polygon.get(0).and_then(|ring| ring.as_array()))
+                    .flat_map(|ring| open_ring(ring))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        Some(other) => return Err(format!("Unsupported geometry type {}", other)),
+        None => return Err("Missing geometry".to_string()),
+    };
+
+    let mut positions = Vec::with_capacity(points.len());
+    for point in points {
+        let longitude = point.get(0).and_then(|v| v.as_f64());
+        let latitude = point.get(1).and_then(|v| v.as_f64());
+        match (latitude, longitude) {
+            (Some(latitude), Some(longitude))
+                if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) =>
+            {
+                positions.push((latitude, longitude))
+            }
+            _ => return Err("Invalid coordinates".to_string()),
+        }
+    }
+
+    if positions.is_empty() {
+        return Err("Geometry has no coordinates".to_string());
+    }
+
+    let count = positions.len() as f64;
+    let latitude = positions.iter().map(|(latitude, _)| latitude).sum::<f64>() / count;
+    let longitude = positions
+        .iter()
+        .map(|(_, longitude)| longitude)
+        .sum::<f64>()
+        / count;
+
+    Ok((latitude, longitude))
+}
+
+fn open_ring(ring: &[Value]) -> impl Iterator<Item = &Value> {
+    let closed = ring.len() > 1 && ring.first() == ring.last();
+    ring.iter().take(ring.len() - usize::from(closed))
+}
@@ -0,0 +1,741 @@
+use crate::config;
+use crate::integration_tokens;
+use crate::local_storage::contract::contract_table::CONTRACT_TABLE;
+use crate::local_storage::core_table::Table;
+use crate::local_storage::location::location_table::LOCATION_TABLE;
+use crate::local_storage::sawmill::sawmill_table::SAWMILL_TABLE;
+use crate::local_storage::shipment::shipment_table::SHIPMENT_TABLE;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use base64::prelude::*;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, params_from_iter};
+use serde_json::{Map, Value, json};
+use std::net::SocketAddr;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Deepest nesting of selection sets and list values a query may use; the
+/// schema itself needs three levels.
+const MAX_DEPTH: usize = 8;
+
+struct Collection {
+    field: &'static str,
+    type_name: &'static str,
+    table: &'static Table,
+    privileged_fields: &'static [&'static str],
+}
+
+const COLLECTIONS: &[Collection] = &[
+    Collection {
+        field: "contracts",
+        type_name: "Contract",
+        table: &CONTRACT_TABLE,
+        privileged_fields: &["pricePerCubicMeter", "currency", "vatRate"],
+    },
+    Collection {
+        field: "locations",
+        type_name: "Location",
+        table: &LOCATION_TABLE,
+        privileged_fields: &["ownerInformation"],
+    },
+    Collection {
+        field: "shipments",
+        type_name: "Shipment",
+        table: &SHIPMENT_TABLE,
+        privileged_fields: &[
+            "netValue",
+            "vatValue",
+            "grossValue",
+            "currency",
+            "reviewStatus",
+            "reviewReason",
+        ],
+    },
+    Collection {
+        field: "sawmills",
+        type_name: "Sawmill",
+        table: &SAWMILL_TABLE,
+        privileged_fields: &[],
+    },
+];
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    warp::path("graphql")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(warp::addr::remote())
+        .and(with_db_pools(db_pools))
+        .map(|authorization, body, remote_addr, db_pools: DbPoolMap| {
+            run_query(authorization, body, remote_addr, &db_pools).unwrap_or_else(|reply| reply)
+        })
+}
+
+fn run_query(
+    authorization: Option<String>,
+    body: Value,
+    remote_addr: Option<SocketAddr>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    if !config::get().graphql.enabled {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "GraphQL is disabled",
+        ));
+    }
+
+    let user = integration_tokens::authenticate(authorization, remote_addr, db_pools, 0)?;
+    let request_error = |message: String| {
+        warp::reply::with_status(
+            warp::reply::json(&json!({ "errors": [{ "message": message }] })),
+            StatusCode::BAD_REQUEST,
+        )
+    };
+
+    let query = body["query"]
+        .as_str()
+        .ok_or_else(|| request_error("query is required".to_string()))?;
+    let variables = body["variables"].as_object().cloned().unwrap_or_default();
+    let selection = parse_document(query, &variables).map_err(request_error)?;
+    for field in &selection {
+        if let Some(collection) = COLLECTIONS
+            .iter()
+            .find(|collection| collection.field == field.name)
+        {
+            user.require_read(collection.table.name)?;
+        }
+    }
+
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let conn = core_storage
+        .get_connection()
+        .map_err(|e| rest::internal_error("Database unavailable", e))?;
+
+    let mut errors = Vec::new();
+    let data = execute(&conn, &selection, user.role, &mut errors).map_err(request_error)?;
+
+    let mut response = json!({ "data": data });
+    if !errors.is_empty() {
+        response["errors"] = json!(errors);
+    }
+    Ok(rest::json_reply(&response))
+}
+
+struct Field {
+    alias: Option<String>,
+    name: String,
+    arguments: Map<String, Value>,
+    selection: Vec<Field>,
+}
+
+impl Field {
+    fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+fn execute(
+    conn: &Connection,
+    selection: &[Field],
+    role: i64,
+    errors: &mut Vec<Value>,
+) -> Result<Value, String> {
+    let mut data = Map::new();
+    for field in selection {
+        let value = if field.name == "__typename" {
+            json!("Query")
+        } else {
+            let collection = COLLECTIONS
+                .iter()
+                .find(|collection| collection.field == field.name)
+                .ok_or_else(|| format!("Cannot query field \"{}\" on type Query", field.name))?;
+            resolve_collection(conn, collection, field, role, errors)?
+        };
+        data.insert(field.key().to_string(), value);
+    }
+    Ok(Value::Object(data))
+}
+
+fn resolve_collection(
+    conn: &Connection,
+    collection: &Collection,
+    field: &Field,
+    role: i64,
+    errors: &mut Vec<Value>,
+) -> Result<Value, String> {
+    let db_error = |e: rusqlite::Error| {
+        println!("GraphQL query on {} failed: {:?}", collection.field, e);
+        format!("Failed to load {}", collection.field)
+    };
+
+    let mut conditions = Vec::new();
+    let mut params: Vec<rusqlite::types::Value> = Vec::new();
+    for (name, value) in &field.arguments {
+        match (name.as_str(), value) {
+            ("first" | "after", _) => {}
+            ("id", Value::String(id)) => {
+                conditions.push("id = ?");
+                params.push(id.clone().into());
+            }
+            ("updatedSince", Value::Number(since)) if since.is_i64() => {
+                conditions.push("lastEdit > ?");
+                params.push(since.as_i64().unwrap_or_default().into());
+            }
+            ("includeDeleted", Value::Bool(_)) => {}
+            _ => {
+                return Err(format!(
+                    "Invalid argument \"{}\" on field \"{}\"",
+                    name, collection.field
+                ));
+            }
+        }
+    }
+    if field.arguments.get("includeDeleted") != Some(&Value::Bool(true)) {
+        conditions.push("deleted = 0");
+    }
+
+    let count_where = where_clause(&conditions);
+    let count_params = params.clone();
+
+    if let Some(after) = field.arguments.get("after").filter(|v| !v.is_null()) {
+        let cursor = after
+            .as_str()
+            .and_then(|cursor| BASE64_STANDARD.decode(cursor).ok())
+            .and_then(|cursor| String::from_utf8(cursor).ok())
+            .ok_or_else(|| "Invalid cursor in \"after\"".to_string())?;
+        conditions.push("id > ?");
+        params.push(cursor.into());
+    }
+    let max_page_size = config::get().graphql.max_page_size;
+    let first = match field.arguments.get("first") {
+        None | Some(Value::Null) => DEFAULT_PAGE_SIZE.min(max_page_size),
+        Some(first) => first
+            .as_i64()
+            .filter(|first| (1..=max_page_size).contains(first))
+            .ok_or_else(|| format!("\"first\" must be between 1 and {}", max_page_size))?,
+    };
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT * FROM \"{}\"{} ORDER BY id LIMIT {}",
+            collection.table.name,
+            where_clause(&conditions),
+            first + 1
+        ))
+        .map_err(db_error)?;
+    let column_names: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows: Vec<Map<String, Value>> = stmt
+        .query_map(params_from_iter(params.iter()), |row| {
+            let mut values = Map::new();
+            for (index, name) in column_names.iter().enumerate() {
+                values.insert(name.clone(), value_to_json(row.get_ref(index)?));
+            }
+            Ok(values)
+        })
+        .map_err(db_error)?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(db_error)?;
+
+    let has_next_page = rows.len() as i64 > first;
+    rows.truncate(first as usize);
+    let end_cursor = rows
+        .last()
+        .and_then(|row| row["id"].as_str())
+        .map(|id| BASE64_STANDARD.encode(id));
+
+    let mut connection = Map::new();
+    for sub_field in &field.selection {
+        let value = match sub_field.name.as_str() {
+            "__typename" => json!(format!("{}Connection", collection.type_name)),
+            "nodes" => {
+                let mut nodes = Vec::new();
+                for (index, row) in rows.iter().enumerate() {
+                    let path = json!([field.key(), sub_field.key(), index]);
+                    nodes.push(select_node(
+                        collection, row, sub_field, role, &path, errors,
+                    )?);
+                }
+                json!(nodes)
+            }
+            "pageInfo" => {
+                let mut page_info = Map::new();
+                for info_field in &sub_field.selection {
+                    let value = match info_field.name.as_str() {
+                        "hasNextPage" => json!(has_next_page),
+                        "endCursor" => json!(end_cursor),
+                        "__typename" => json!("PageInfo"),
+                        other => {
+                            return Err(format!("Cannot query field \"{}\" on PageInfo", other));
+                        }
+                    };
+                    page_info.insert(info_field.key().to_string(), value);
+                }
+                Value::Object(page_info)
+            }
+            "totalCount" => {
+                let total: i64 = conn
+                    .query_row(
+                        &format!(
+                            "SELECT COUNT(*) FROM \"{}\"{}",
+                            collection.table.name, count_where
+                        ),
+                        params_from_iter(count_params.iter()),
+                        |row| row.get(0),
+                    )
+                    .map_err(db_error)?;
+                json!(total)
+            }
+            other => {
+                return Err(format!(
+                    "Cannot query field \"{}\" on {}Connection",
+                    other, collection.type_name
+                ));
+            }
+        };
+        connection.insert(sub_field.key().to_string(), value);
+    }
+
+    Ok(Value::Object(connection))
+}
+
+fn select_node(
+    collection: &Collection,
+    row: &Map<String, Value>,
+    nodes_field: &Field,
+    role: i64,
+    path: &Value,
+    errors: &mut Vec<Value>,
+) -> Result<Value, String> {
+    let mut node = Map::new();
+    for field in &nodes_field.selection {
+        let value = if field.name == "__typename" {
+            json!(collection.type_name)
+        } else if collection.table.column(&field.name).is_none() || !field.selection.is_empty() {
+            return Err(format!(
+                "Cannot query field \"{}\" on type {}",
+                field.name, collection.type_name
+            ));
+        } else if role < ROLE_PRIVILEGED
+            && collection.privileged_fields.contains(&field.name.as_str())
+        {
+            let mut field_path = path.as_array().cloned().unwrap_or_default();
+            field_path.push(json!(field.key()));
+            errors.push(json!({
+                "message": format!("Field \"{}\" requires a privileged role", field.name),
+                "path": field_path
+            }));
+            Value::Null
+        } else {
+            row.get(&field.name).cloned().unwrap_or(Value::Null)
+        };
+        node.insert(field.key().to_string(), value);
+    }
+    Ok(Value::Object(node))
+}
+
+fn where_clause(conditions: &[&str]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+fn value_to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null | ValueRef::Blob(_) => Value::Null,
+        ValueRef::Integer(i) => json!(i),
+        ValueRef::Real(f) => json!(f),
+        ValueRef::Text(text) => json!(String::from_utf8_lossy(text)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Variable(String),
+    Punct(char),
+    Str(String),
+    Number(Value),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() || c == ',' => {
+                chars.next();
+            }
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '{' | '}' | '(' | ')' | '[' | ']' | ':' | '!' | '=' => {
+                chars.next();
+                tokens.push(Token::Punct(c));
+            }
+            '.' | '@' => return Err("Fragments and directives are not supported".to_string()),
+            '$' => {
+                chars.next();
+                tokens.push(Token::Variable(read_name(&mut chars)));
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(escaped @ ('"' | '\\' | '/')) => value.push(escaped),
+                            _ => return Err("Unsupported escape sequence in string".to_string()),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err("Unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = chars
+                    .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    number.push(c);
+                }
+                let value = match number.parse::<i64>() {
+                    Ok(int) => json!(int),
+                    Err(_) => number
+                        .parse::<f64>()
+                        .map(|float| json!(float))
+                        .map_err(|_| format!("Invalid number {}", number))?,
+                };
+                tokens.push(Token::Number(value));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                tokens.push(Token::Name(read_name(&mut chars)));
+            }
+            other => return Err(format!("Unexpected character '{}'", other)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn read_name(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+        name.push(c);
+    }
+    name
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+    variables: &'a Map<String, Value>,
+}
+
+fn parse_document(query: &str, variables: &Map<String, Value>) -> Result<Vec<Field>, String> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        position: 0,
+        depth: 0,
+        variables,
+    };
+
+    match parser.peek() {
+        Some(Token::Name(keyword)) if keyword == "query" => {
+            parser.position += 1;
+            if let Some(Token::Name(_)) = parser.peek() {
+                parser.position += 1;
+            }
+            if parser.peek() == Some(&Token::Punct('(')) {
+                parser.skip_variable_definitions()?;
+            }
+        }
+        Some(Token::Name(keyword)) if keyword == "mutation" || keyword == "subscription" => {
+            return Err("Only read-only queries are supported".to_string());
+        }
+        _ => {}
+    }
+
+    let selection = parser.parse_selection_set()?;
+    if parser.peek().is_some() {
+        return Err("Only a single operation is supported".to_string());
+    }
+    Ok(selection)
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| "Unexpected end of query".to_string())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punct(c) if *c == punct => Ok(()),
+            other => Err(format!("Expected '{}', found {:?}", punct, other)),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name.clone()),
+            other => Err(format!("Expected a name, found {:?}", other)),
+        }
+    }
+
+    fn skip_variable_definitions(&mut self) -> Result<(), String> {
+        while self.next()? != &Token::Punct(')') {}
+        Ok(())
+    }
+
+    fn descend(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("Query exceeds the maximum depth of {}", MAX_DEPTH));
+        }
+        Ok(())
+    }
+
+    fn parse_selection_set(&mut self) -> Result<Vec<Field>, String> {
+        self.expect('{')?;
+        self.descend()?;
+        let mut fields = Vec::new();
+        while self.peek() != Some(&Token::Punct('}')) {
+            fields.push(self.parse_field()?);
+        }
+        self.expect('}')?;
+        self.depth -= 1;
+
+        if fields.is_empty() {
+            return Err("Selection sets must not be empty".to_string());
+        }
+        Ok(fields)
+    }
+
+    fn parse_field(&mut self) -> Result<Field, String> {
+        let mut alias = None;
+        let mut name = self.name()?;
+        if self.peek() == Some(&Token::Punct(':')) {
+            self.position += 1;
+            alias = Some(name);
+            name = self.name()?;
+        }
+
+        let mut arguments = Map::new();
+        if self.peek() == Some(&Token::Punct('(')) {
+            self.position += 1;
+            while self.peek() != Some(&Token::Punct(')')) {
+                let argument = self.name()?;
+                self.expect(':')?;
+                let value = self.parse_value()?;
+                arguments.insert(argument, value);
+            }
+            self.expect(')')?;
+        }
+
+        let selection = if self.peek() == Some(&Token::Punct('{')) {
+            self.parse_selection_set()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Field {
+            alias,
+            name,
+            arguments,
+            selection,
+        })
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        let value = match self.next()?.clone() {
+            Token::Variable(name) => self.variables.get(&name).cloned().unwrap_or(Value::Null),
+            Token::Str(value) => json!(value),
+            Token::Number(value) => value,
+            Token::Name(name) => match name.as_str() {
+                "true" => json!(true),
+                "false" => json!(false),
+                "null" => Value::Null,
+                other => json!(other),
+            },
+            Token::Punct('[') => {
+                self.descend()?;
+                let mut values = Vec::new();
+                while self.peek() != Some(&Token::Punct(']')) {
+                    values.push(self.parse_value()?);
+                }
+                self.position += 1;
+                self.depth -= 1;
+                json!(values)
+            }
+            other => return Err(format!("Unexpected {:?} in argument", other)),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_storage::core_local_storage::CoreLocalStorage;
+    use crate::local_storage::schema;
+
+    fn parse(query: &str) -> Result<Vec<Field>, String> {
+        parse_document(query, &Map::new())
+    }
+
+    fn storage() -> CoreLocalStorage {
+        let core_storage = CoreLocalStorage::new(":memory:").unwrap();
+        let conn = core_storage.get_connection().unwrap();
+        schema::migrate(&conn).unwrap();
+        for (id, deleted) in [("c1", 0), ("c2", 0), ("c3", 1)] {
+            conn.execute(
+                "INSERT INTO contracts (id, done, lastEdit, title, additionalInfo, startDate,
+                 endDate, availableQuantity, bookedQuantity, shippedQuantity, arrivalAtServer,
+                 deleted, pricePerCubicMeter)
+                 VALUES (?, 0, 1, ?, '', 0, 0, 10, 0, 0, 1, ?, 42.5)",
+                rusqlite::params![id, format!("Contract {}", id), deleted],
+            )
+            .unwrap();
+        }
+        drop(conn);
+        core_storage
+    }
+
+    fn run(query: &str, role: i64) -> (Result<Value, String>, Vec<Value>) {
+        let core_storage = storage();
+        let conn = core_storage.get_connection().unwrap();
+        let mut errors = Vec::new();
+        let data = parse(query).and_then(|selection| execute(&conn, &selection, role, &mut errors));
+        (data, errors)
+    }
+
+    #[test]
+    fn parses_aliases_arguments_and_variables() {
+        let variables = json!({ "since": 5 }).as_object().cloned().unwrap();
+        let selection = parse_document(
+            "query Recent($since: Int) { recent: contracts(first: 2, updatedSince: $since) \
+             { nodes { id } } }",
+            &variables,
+        )
+        .unwrap();
+
+        assert_eq!(selection.len(), 1);
+        assert_eq!(selection[0].key(), "recent");
+        assert_eq!(selection[0].name, "contracts");
+        assert_eq!(selection[0].arguments["first"], 2);
+        assert_eq!(selection[0].arguments["updatedSince"], 5);
+        assert_eq!(selection[0].selection[0].selection[0].name, "id");
+    }
+
+    #[test]
+    fn rejects_mutations_and_malformed_queries() {
+        assert!(parse("mutation { contracts { nodes { id } } }").is_err());
+        assert!(parse("{ contracts { nodes { id } }").is_err());
+        assert!(parse("{ contracts { } }").is_err());
+        assert!(parse("{ contracts { ...fields } }").is_err());
+        assert!(parse("{ contracts(id: \"c1) { nodes { id } } }").is_err());
+    }
+
+    #[test]
+    fn rejects_deeply_nested_queries() {
+        let depth = 10_000;
+        let query = format!("{}{}", "{ a ".repeat(depth), "}".repeat(depth));
+        assert_eq!(
+            parse(&query).err().unwrap(),
+            format!("Query exceeds the maximum depth of {}", MAX_DEPTH)
+        );
+
+        let lists = format!(
+            "{{ contracts(id: {}{}) {{ nodes {{ id }} }} }}",
+            "[".repeat(depth),
+            "]".repeat(depth)
+        );
+        assert!(parse(&lists).err().unwrap().contains("maximum depth"));
+    }
+
+    #[test]
+    fn resolves_pages_of_undeleted_rows() {
+        let (data, errors) = run(
+            "{ contracts(first: 1) { totalCount nodes { id title } pageInfo { hasNextPage endCursor } } }",
+            0,
+        );
+        let contracts = &data.unwrap()["contracts"];
+
+        assert!(errors.is_empty());
+        assert_eq!(contracts["totalCount"], 2);
+        assert_eq!(
+            contracts["nodes"],
+            json!([{ "id": "c1", "title": "Contract c1" }])
+        );
+        assert_eq!(contracts["pageInfo"]["hasNextPage"], true);
+
+        let cursor = contracts["pageInfo"]["endCursor"].as_str().unwrap();
+        let (data, _) = run(
+            &format!(
+                "{{ contracts(after: \"{}\") {{ nodes {{ id }} }} }}",
+                cursor
+            ),
+            0,
+        );
+        assert_eq!(data.unwrap()["contracts"]["nodes"], json!([{ "id": "c2" }]));
+    }
+
+    #[test]
+    fn withholds_privileged_fields_from_basic_users() {
+        let query = "{ contracts(id: \"c1\") { nodes { id pricePerCubicMeter } } }";
+
+        let (data, errors) = run(query, 0);
+        assert_eq!(
+            data.unwrap()["contracts"]["nodes"][0]["pricePerCubicMeter"],
+            Value::Null
+        );
+        assert_eq!(
+            errors[0]["path"],
+            json!(["contracts", "nodes", 0, "pricePerCubicMeter"])
+        );
+
+        let (data, errors) = run(query, ROLE_PRIVILEGED);
+        assert_eq!(
+            data.unwrap()["contracts"]["nodes"][0]["pricePerCubicMeter"],
+            42.5
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_arguments() {
+        assert!(run("{ users { nodes { id } } }", 0).0.is_err());
+        assert!(run("{ contracts { nodes { apiKey } } }", 0).0.is_err());
+        assert!(
+            run("{ contracts(owner: \"x\") { nodes { id } } }", 0)
+                .0
+                .is_err()
+        );
+        assert!(
+            run("{ contracts(first: 0) { nodes { id } } }", 0)
+                .0
+                .is_err()
+        );
+    }
+}
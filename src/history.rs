@@ -0,0 +1,64 @@
+use crate::local_storage::event_log::event_log_local_storage::EventLogLocalStorage;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use serde_json::json;
+use std::collections::HashMap;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    warp::path!("history" / String / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(
+            |entity: String, id: String, authorization, query, db_pools: DbPoolMap| {
+                entity_history(&entity, &id, authorization, query, &db_pools)
+                    .unwrap_or_else(|reply| reply)
+            },
+        )
+}
+
+/// Versions of one entity (e.g. /history/location/{id}) as they were
+/// broadcast, with the user, device and app version that sent each update.
+fn entity_history(
+    entity: &str,
+    id: &str,
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    if entity.is_empty() || !entity.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+        return Err(rest::error_reply(
+            StatusCode::BAD_REQUEST,
+            "VALIDATION_FAILED",
+            "Unknown entity",
+        ));
+    }
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
+
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let versions = EventLogLocalStorage::new(core_storage)
+        .and_then(|storage| {
+            storage.get_entity_history(&format!("{}_update", entity), id, user.role, limit)
+        })
+        .map_err(|e| rest::internal_error("Failed to load history", e))?;
+
+    Ok(rest::json_reply(&json!({
+        "entity": entity,
+        "id": id,
+        "versions": versions
+    })))
+}
@@ -0,0 +1,154 @@
+use crate::config;
+use crate::metrics;
+use crate::replication;
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use uuid::Uuid;
+
+/// Not a tenant database, so the `*.db` scans of the databases directory skip it.
+const LEASE_FILE: &str = "instance_lease.sqlite";
+const LEASE_NAME: &str = "background_jobs";
+
+static INSTANCE_ID: LazyLock<String> =
+    LazyLock::new(|| format!("{}-{}", std::process::id(), Uuid::new_v4()));
+
+/// Until when this instance holds the lease, in milliseconds. The lease is
+/// only trusted until then, so an instance that cannot renew it steps down
+/// on its own before another one may take over.
+static LEADER_UNTIL: AtomicI64 = AtomicI64::new(0);
+
+pub fn instance_id() -> &'static str {
+    &INSTANCE_ID
+}
+
+/// Whether this instance runs the background jobs. Every instance serves
+/// WebSocket and REST traffic; scheduled jobs, cleanups and backups that
+/// must not be applied twice only run on the lease holder. A replication
+/// standby runs none, its databases only change through replication.
+pub fn is_leader() -> bool {
+    !replication::is_standby()
+        && (!config::get().instance.coordination_enabled
+            || LEADER_UNTIL.load(Ordering::Relaxed) > chrono::Utc::now().timestamp_millis())
+}
+
+fn open() -> Result<Connection> {
+    let conn = Connection::open(config::get().databases_dir.join(LEASE_FILE))?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS leases (
+            name TEXT PRIMARY KEY,
+            holder TEXT NOT NULL,
+            acquiredAt INTEGER NOT NULL,
+            expiresAt INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(conn)
+}
+
+/// Takes or renews the lease. Another instance's lease is only taken over
+/// once it has expired. Returns whether this instance holds it.
+fn acquire(conn: &Connection) -> Result<bool> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let expires_at = now + config::get().instance.lease_secs as i64 * 1000;
+
+    conn.execute(
+        "INSERT INTO leases (name, holder, acquiredAt, expiresAt) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(name) DO UPDATE SET
+             acquiredAt = CASE WHEN holder = excluded.holder THEN acquiredAt ELSE excluded.acquiredAt END,
+             holder = excluded.holder,
+             expiresAt = excluded.expiresAt
+         WHERE holder = excluded.holder OR expiresAt < ?3",
+        params![LEASE_NAME, instance_id(), now, expires_at],
+    )?;
+    let holder: Option<String> = conn
+        .query_row(
+            "SELECT holder FROM leases WHERE name = ?",
+            params![LEASE_NAME],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let held = holder.as_deref() == Some(instance_id());
+    // Leave a margin of one renewal so the local view expires before the
+    // lease in the database does.
+    let renew_millis = config::get().instance.renew_secs as i64 * 1000;
+    LEADER_UNTIL.store(
+        if held { expires_at - renew_millis } else { 0 },
+        Ordering::Relaxed,
+    );
+    Ok(held)
+}
+
+fn renew() {
+    let was_leader = is_leader();
+    let held = match open().and_then(|conn| acquire(&conn)) {
+        Ok(held) => held,
+        Err(e) => {
+            eprintln!("Failed to renew instance lease: {:?}", e);
+            is_leader()
+        }
+    };
+
+    metrics::set_gauge("instance_leader", held as i64);
+    if held && !was_leader {
+        println!(
+            "Instance {} acquired the lease and runs background jobs",
+            instance_id()
+        );
+    } else if !held && was_leader {
+        println!(
+            "Instance {} lost the lease and no longer runs background jobs",
+            instance_id()
+        );
+    }
+}
+
+/// Tries to take the lease before the startup cleanups run, so they are
+/// skipped when another instance is already running.
+pub fn init() {
+    if config::get().instance.coordination_enabled {
+        renew();
+    }
+}
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    let settings = &config::get().instance;
+    if !settings.coordination_enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.renew_secs);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            if let Err(e) = tokio::task::spawn_blocking(renew).await {
+                eprintln!("Instance lease renewal failed: {:?}", e);
+            }
+        }
+    }))
+}
+
+/// Gives the lease up on shutdown so another instance can take over without
+/// waiting for it to expire.
+pub fn release() {
+    if !config::get().instance.coordination_enabled || !is_leader() {
+        return;
+    }
+
+    LEADER_UNTIL.store(0, Ordering::Relaxed);
+    let result = open().and_then(|conn| {
+        conn.execute(
+            "DELETE FROM leases WHERE name = ? AND holder = ?",
+            params![LEASE_NAME, instance_id()],
+        )
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to release instance lease: {:?}", e);
+    }
+}
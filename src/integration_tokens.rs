@@ -0,0 +1,306 @@
+use crate::config;
+use crate::local_storage::integration_token::integration_token_local_storage::{
+    IntegrationToken, IntegrationTokenLocalStorage,
+};
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::rate_limit;
+use crate::rest::{self, ErrorReply, RestUser};
+use crate::{DbPoolMap, ROLE_ADMIN, ROLE_PRIVILEGED, with_db_pools};
+use serde_json::{Value, json};
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const TOKEN_PREFIX: &str = "hli_";
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list = warp::path!("admin" / "integration-tokens")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_tokens(authorization, &db_pools))
+        });
+
+    let create = warp::path!("admin" / "integration-tokens")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, body, db_pools: DbPoolMap| {
+            rest::into_reply(create_token(authorization, body, &db_pools))
+        });
+
+    let revoke = warp::path!("admin" / "integration-tokens" / String)
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|id, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(revoke_token(id, authorization, &db_pools))
+        });
+
+    list.or(create).unify().or(revoke).unify()
+}
+
+/// Accepts either a user API key (checked against `min_role`) or an
+/// integration token. Integration tokens are read-only: callers must check
+/// `RestUser::require_read` for every table they serve.
+pub fn authenticate(
+    authorization: Option<String>,
+    remote_addr: Option<SocketAddr>,
+    db_pools: &DbPoolMap,
+    min_role: i64,
+) -> Result<RestUser, ErrorReply> {
+    let Some((tenant, token)) = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .and_then(|token| {
+            let (tenant, _) = token.strip_prefix(TOKEN_PREFIX)?.rsplit_once('.')?;
+            Some((tenant.to_string(), token.to_string()))
+        })
+    else {
+        return rest::authenticate(authorization, db_pools, min_role);
+    };
+
+    let unauthorized = || {
+        rest::error_reply(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Missing or invalid integration token",
+        )
+    };
+    if !config::get().integrations.enabled {
+        return Err(unauthorized());
+    }
+
+    let core_storage = rest::tenant_storage(&tenant, db_pools)?.ok_or_else(unauthorized)?;
+    let storage = IntegrationTokenLocalStorage::new(core_storage)
+        .map_err(|e| rest::internal_error("Failed to open integration tokens", e))?;
+    let integration = storage
+        .get_active_token(&token)
+        .map_err(|e| rest::internal_error("Failed to verify integration token", e))?
+        .ok_or_else(unauthorized)?;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    if integration.expires_at <= now {
+        return Err(rest::error_reply(
+            StatusCode::UNAUTHORIZED,
+            "TOKEN_EXPIRED",
+            "Integration token has expired",
+        ));
+    }
+
+    let ip = remote_addr.map(|addr| addr.ip().to_canonical());
+    if !integration.ip_allowlist.is_empty()
+        && !ip.is_some_and(|ip| {
+            integration
+                .ip_allowlist
+                .iter()
+                .any(|entry| ip_matches(entry, ip) == Some(true))
+        })
+    {
+        metrics::increment("integration_token_ip_rejected_total");
+        return Err(rest::error_reply(
+            StatusCode::FORBIDDEN,
+            "IP_NOT_ALLOWED",
+            "Integration token is not valid from this address",
+        ));
+    }
+
+    if let Err(retry_after) =
+        rate_limit::check(&token, config::get().integrations.rate_limit_per_minute)
+    {
+        return Err(rest::error_reply(
+            StatusCode::TOO_MANY_REQUESTS,
+            "RATE_LIMITED",
+            &format!("Rate limit exceeded, retry in {} seconds", retry_after),
+        ));
+    }
+
+    let last_ip = ip.map(|ip| ip.to_string());
+    if let Err(e) = storage.record_use(&integration.id, last_ip.as_deref(), now) {
+        println!(
+            "Failed to record use of integration token {}: {:?}",
+            integration.id, e
+        );
+    }
+    metrics::increment("integration_token_requests_total");
+
+    Ok(RestUser {
+        tenant,
+        user_id: format!("integration:{}", integration.id),
+        role: ROLE_PRIVILEGED,
+        scopes: Some(integration.scopes),
+    })
+}
+
+fn ip_matches(entry: &str, ip: IpAddr) -> Option<bool> {
+    let (network, prefix) = match entry.split_once('/') {
+        Some((network, prefix)) => (network, Some(prefix.parse::<u32>().ok()?)),
+        None => (entry, None),
+    };
+    let network = network.parse::<IpAddr>().ok()?.to_canonical();
+    let bits = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(bits);
+    if prefix > bits {
+        return None;
+    }
+
+    let (network, address) = match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => {
+            (u32::from(network) as u128, u32::from(address) as u128)
+        }
+        (IpAddr::V6(network), IpAddr::V6(address)) => (u128::from(network), u128::from(address)),
+        _ => return Some(false),
+    };
+    let mask = if prefix == 0 {
+        0
+    } else {
+        u128::MAX << (128 - prefix) >> (128 - bits)
+    };
+    Some(network & mask == address & mask)
+}
+
+fn invalid(message: &str) -> ErrorReply {
+    rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message)
+}
+
+fn string_list(body: &Value, field: &str) -> Result<Vec<String>, ErrorReply> {
+    match &body[field] {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid(&format!("{} must be a list of strings", field))),
+        _ => Err(invalid(&format!("{} must be a list of strings", field))),
+    }
+}
+
+fn list_tokens(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let tokens = IntegrationTokenLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_tokens())
+        .map_err(|e| rest::internal_error("Failed to load integration tokens", e))?;
+
+    Ok(rest::json_reply(&json!({ "tokens": tokens })))
+}
+
+fn create_token(
+    authorization: Option<String>,
+    body: Value,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let settings = &config::get().integrations;
+
+    let name = body["name"]
+        .as_str()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| invalid("name is required"))?;
+
+    let scopes = string_list(&body, "scopes")?;
+    if scopes.is_empty() {
+        return Err(invalid("scopes must list at least one table"));
+    }
+    if let Some(unknown) = scopes.iter().find(|scope| schema::table(scope).is_none()) {
+        return Err(invalid(&format!("Unknown scope: {}", unknown)));
+    }
+
+    let ip_allowlist = string_list(&body, "ipAllowlist")?;
+    if let Some(entry) = ip_allowlist
+        .iter()
+        .find(|entry| ip_matches(entry, IpAddr::from([0, 0, 0, 0])).is_none())
+    {
+        return Err(invalid(&format!(
+            "ipAllowlist entry {} is not an address or CIDR range",
+            entry
+        )));
+    }
+
+    let expires_in_days = match &body["expiresInDays"] {
+        Value::Null => settings.max_lifetime_days,
+        value => value
+            .as_u64()
+            .filter(|days| (1..=settings.max_lifetime_days).contains(days))
+            .ok_or_else(|| {
+                invalid(&format!(
+                    "expiresInDays must be between 1 and {}",
+                    settings.max_lifetime_days
+                ))
+            })?,
+    };
+
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let details = IntegrationToken {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        scopes,
+        ip_allowlist,
+        expires_at: chrono::Utc::now().timestamp_millis() + expires_in_days as i64 * DAY_MS,
+    };
+    let token = format!(
+        "{}{}.{}",
+        TOKEN_PREFIX,
+        user.tenant,
+        Uuid::new_v4().simple()
+    );
+    IntegrationTokenLocalStorage::new(core_storage)
+        .and_then(|storage| storage.create_token(&token, &details, &user.user_id))
+        .map_err(|e| rest::internal_error("Failed to create integration token", e))?;
+
+    println!(
+        "Created integration token {} ({}) for tenant {} with scopes {:?}",
+        details.id, details.name, user.tenant, details.scopes
+    );
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({
+            "id": details.id,
+            "token": token,
+            "name": details.name,
+            "scopes": details.scopes,
+            "ipAllowlist": details.ip_allowlist,
+            "expiresAt": details.expires_at
+        })),
+        StatusCode::CREATED,
+    ))
+}
+
+fn revoke_token(
+    id: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let revoked = IntegrationTokenLocalStorage::new(core_storage)
+        .and_then(|storage| storage.revoke_token(&id))
+        .map_err(|e| rest::internal_error("Failed to revoke integration token", e))?;
+
+    if !revoked {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Integration token not found",
+        ));
+    }
+
+    Ok(rest::json_reply(&json!({ "id": id, "revoked": true })))
+}
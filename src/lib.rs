@@ -0,0 +1,5541 @@
+mod activity_feed;
+mod activity_reports;
+mod archive;
+mod assortments;
+mod auth_challenge;
+mod broadcast_batch;
+mod broadcast_health;
+mod capacity;
+mod client_admin;
+mod config;
+mod conflicts;
+mod contract_aggregates;
+mod contract_allowlist;
+mod contract_split;
+mod coordinates;
+mod db_pool;
+mod deprecations;
+mod digests;
+mod event_bus;
+mod event_compaction;
+mod event_stream;
+mod export;
+mod force_resync;
+mod gate;
+mod geojson_import;
+mod graphql;
+mod history;
+mod instance_lease;
+mod integration_tokens;
+mod local_storage;
+mod location_junctions;
+mod location_split;
+mod metrics;
+mod note_attachments;
+mod permissions;
+mod photo_chunks;
+mod photo_ingest;
+mod photo_recompression;
+mod photo_stream;
+mod photo_transcode;
+mod photo_urls;
+mod plausibility;
+mod portal;
+mod price_lists;
+mod pricing;
+mod projections;
+mod quota;
+mod rate_limit;
+mod replication;
+mod rest;
+mod review_queue;
+mod role_changes;
+mod route_distance;
+mod rule_simulation;
+mod schema_docs;
+mod schema_drift;
+mod script_engine;
+mod scripting;
+mod search;
+mod selftest;
+mod server_features;
+mod shipment_documents;
+mod shipment_reversal;
+mod sla_stats;
+mod snapshot;
+mod status_page;
+mod storage;
+mod stuck_clients;
+mod support_bundle;
+mod sync_cursors;
+mod sync_progress;
+mod sync_scheduler;
+mod sync_sessions;
+mod sync_snapshot;
+mod synthetic;
+mod telemetry;
+mod tenant_drain;
+mod tenant_hibernation;
+pub mod test_fixture;
+mod timezone;
+mod tombstones;
+mod tours;
+mod units;
+mod update_schema;
+mod watchdog;
+mod wire_format;
+mod write_batch;
+mod write_verification;
+mod ws_compression;
+
+use contract_allowlist::Violation;
+use event_bus::BusEvent;
+use local_storage::activity_feed::activity_feed_local_storage::ActivityFeedLocalStorage;
+use local_storage::archive::archive_local_storage::ArchiveLocalStorage;
+use local_storage::assortment::assortment_local_storage::AssortmentLocalStorage;
+use local_storage::contract::contract_local_storage::ContractLocalStorage;
+use local_storage::core_local_storage::CoreLocalStorage;
+use local_storage::crew::crew_local_storage::CrewLocalStorage;
+use local_storage::delta;
+use local_storage::device_secret::device_secret_local_storage::DeviceSecretLocalStorage;
+use local_storage::event_log::event_log_local_storage::Provenance;
+use local_storage::location::location_local_storage::LocationLocalStorage;
+use local_storage::note::note_local_storage::NoteLocalStorage;
+use local_storage::photo::photo_local_storage::PhotoLocalStorage;
+use local_storage::quota_reservation::quota_reservation_local_storage::QuotaReservationLocalStorage;
+use local_storage::review_item::review_item_local_storage::ReviewItemLocalStorage;
+use local_storage::sawmill::sawmill_local_storage::SawmillLocalStorage;
+use local_storage::sawmill_price::sawmill_price_local_storage::SawmillPriceLocalStorage;
+use local_storage::schema;
+use local_storage::settings::settings_local_storage::{
+    CURRENCIES_KEY, CURRENCY_KEY, DIGEST_KEY, EXPORT_POLICY_KEY, FIELD_MAPPING_KEY,
+    PHOTO_LIMITS_KEY, PHOTO_RECOMPRESSION_KEY, PHOTO_TRANSCODING_KEY, SettingsLocalStorage,
+    TIMEZONE_KEY, UNIT_FACTORS_KEY, WRITE_VERIFICATION_KEY,
+};
+use local_storage::shipment::shipment_local_storage::ShipmentLocalStorage;
+use local_storage::shipment_document::shipment_document_local_storage::{
+    self, ShipmentDocumentLocalStorage,
+};
+use local_storage::tour::tour_local_storage::TourLocalStorage;
+use local_storage::user::user_local_storage::UserLocalStorage;
+use sync_cursors::SyncCursors;
+use sync_progress::SyncProgress;
+use sync_sessions::SyncSession;
+use write_batch::WriteBatch;
+
+use dotenv::dotenv;
+use futures_util::{SinkExt, StreamExt};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, Result};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::{Duration, Instant, timeout};
+use tracing::Instrument;
+use uuid::Uuid;
+use warp::Filter;
+use warp::filters::BoxedFilter;
+use warp::ws::{Message, WebSocket};
+
+type DbPool = Pool<SqliteConnectionManager>;
+type DbPoolMap = Arc<StdMutex<HashMap<String, DbPool>>>;
+type Clients = Arc<Mutex<HashMap<String, Client>>>;
+
+#[derive(Debug)]
+struct Client {
+    sender: UnboundedSender<Message>,
+    db_name: String,
+    user_id: String,
+    role: i64,
+    sync_completed: bool,
+    batch_sender: Option<UnboundedSender<Arc<wire_format::Shared>>>,
+    authenticated_at: i64,
+    last_message_at: i64,
+    last_message_type: Option<String>,
+    disconnect: Arc<Notify>,
+    api_key: String,
+    remote_addr: Option<String>,
+    queue_depth: Arc<AtomicUsize>,
+    pending_broadcasts: Arc<AtomicUsize>,
+    broadcast_failures: AtomicUsize,
+    client_version: Option<String>,
+    device_id: Option<String>,
+    sync_batch_size: Option<usize>,
+    sync_budgets: HashMap<String, usize>,
+    sync_checkpoints: HashMap<String, i64>,
+    delta_sync: bool,
+    sync_progress: SyncProgress,
+    warned_deprecations: HashSet<&'static str>,
+    compression: Arc<AtomicBool>,
+    msgpack: Arc<AtomicBool>,
+}
+
+impl Client {
+    /// A connected client that has not authenticated yet. The WebSocket
+    /// handler and the self-test both create clients through this, so new
+    /// fields get their initial value in one place.
+    fn new(sender: UnboundedSender<Message>, remote_addr: Option<String>) -> Self {
+        Client {
+            sender,
+            db_name: String::new(),
+            user_id: String::new(),
+            role: 0,
+            sync_completed: false,
+            batch_sender: None,
+            authenticated_at: 0,
+            last_message_at: 0,
+            last_message_type: None,
+            disconnect: Arc::new(Notify::new()),
+            api_key: String::new(),
+            remote_addr,
+            queue_depth: Arc::new(AtomicUsize::new(0)),
+            pending_broadcasts: Arc::new(AtomicUsize::new(0)),
+            broadcast_failures: AtomicUsize::new(0),
+            client_version: None,
+            device_id: None,
+            sync_batch_size: None,
+            sync_budgets: HashMap::new(),
+            sync_checkpoints: HashMap::new(),
+            delta_sync: false,
+            sync_progress: SyncProgress::default(),
+            warned_deprecations: HashSet::new(),
+            compression: Arc::new(AtomicBool::new(false)),
+            msgpack: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn send_broadcast(&self, msg: &Arc<wire_format::Shared>) -> std::result::Result<(), String> {
+        match &self.batch_sender {
+            Some(batch_sender) => batch_sender.send(msg.clone()).map_err(|e| e.to_string()),
+            None => self
+                .sender
+                .send(msg.frame(&self.msgpack))
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+const ROLE_PRIVILEGED: i64 = 1;
+const ROLE_ADMIN: i64 = 2;
+const MAX_PHOTO_FETCH_BATCH: usize = 50;
+
+fn log_incoming_message(msg_type: &str, client_id: &str, data: &Value) {
+    if msg_type == "photo_update" {
+        let metadata = json!({
+            "id": data.get("id"),
+            "filename": data.get("filename"),
+            "timestamp": data.get("timestamp"),
+            "size": data.get("data")
+                .and_then(|d| d.as_str())
+                .map(|s| s.len())
+                .unwrap_or(0)
+        });
+        println!(
+            "INCOMING [{}] photo_update: {}",
+            client_id,
+            serde_json::to_string(&metadata).unwrap_or_else(|_| "invalid".to_string())
+        );
+    } else if msg_type == "shipment_document_chunk" {
+        println!(
+            "INCOMING [{}] shipment_document_chunk: {} #{} ({} chars)",
+            client_id,
+            data["uploadId"].as_str().unwrap_or_default(),
+            data["index"],
+            data["data"].as_str().map(str::len).unwrap_or(0)
+        );
+    } else {
+        println!(
+            "INCOMING [{}] {}: {}",
+            client_id,
+            msg_type,
+            serde_json::to_string(data).unwrap_or_else(|_| "invalid".to_string())
+        );
+    }
+}
+
+fn log_outgoing_message(msg_type: &str, client_id: &str, data: &Value) {
+    if msg_type == "photo_update" {
+        let metadata = json!({
+            "id": data.get("id"),
+            "filename": data.get("filename"),
+            "timestamp": data.get("timestamp"),
+            "size": data.get("data")
+                .and_then(|d| d.as_str())
+                .map(|s| s.len())
+                .unwrap_or(0)
+        });
+        println!(
+            "OUTGOING [{}] photo_update: {}",
+            client_id,
+            serde_json::to_string(&metadata).unwrap_or_else(|_| "invalid".to_string())
+        );
+    } else if msg_type == "shipment_document_update" && data.get("document").is_some() {
+        println!(
+            "OUTGOING [{}] shipment_document_update: {} ({} bytes)",
+            client_id,
+            data["shipmentId"].as_str().unwrap_or_default(),
+            data["size"]
+        );
+    } else {
+        println!(
+            "OUTGOING [{}] {}: {}",
+            client_id,
+            msg_type,
+            serde_json::to_string(data).unwrap_or_else(|_| "invalid".to_string())
+        );
+    }
+}
+
+fn initialize_database(db_path: &str) -> Result<()> {
+    let dir_path = Path::new(&db_path).parent().unwrap_or(Path::new(""));
+    if !dir_path.exists() {
+        fs::create_dir_all(dir_path).map_err(|e| {
+            eprintln!("Failed to create directory: {:?}", e);
+            rusqlite::Error::ExecuteReturnedResults
+        })?;
+    }
+
+    let conn = Connection::open(db_path)?;
+
+    conn.execute("PRAGMA foreign_keys = ON;", [])?;
+
+    println!("Database initialized: {}", db_path);
+    Ok(())
+}
+
+fn database_exists(tenant: &str) -> bool {
+    let db_path = get_db_path(tenant);
+    Path::new(&db_path).exists()
+}
+
+fn get_db_path(tenant: &str) -> String {
+    config::get()
+        .databases_dir
+        .join(format!("{}.db", tenant))
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn get_db_pool(tenant: &str, db_pools: &DbPoolMap) -> Result<DbPool> {
+    let mut pools = db_pools.lock().map_err(|_| {
+        eprintln!("Failed to lock database pools");
+        rusqlite::Error::ExecuteReturnedResults
+    })?;
+
+    if !pools.contains_key(tenant) {
+        let db_path = get_db_path(tenant);
+        println!(
+            "Creating new connection pool for tenant {} at {}",
+            tenant, db_path
+        );
+
+        if !Path::new(&db_path).exists() {
+            initialize_database(&db_path)?;
+        }
+
+        let manager = SqliteConnectionManager::file(&db_path);
+        let pool = db_pool::build(manager).map_err(|e| {
+            eprintln!("Failed to create connection pool: {:?}", e);
+            rusqlite::Error::InvalidQuery
+        })?;
+
+        let conn = pool.get().map_err(|e| {
+            eprintln!("Failed to get connection for migration: {:?}", e);
+            rusqlite::Error::InvalidQuery
+        })?;
+        schema::migrate(&conn)?;
+
+        pools.insert(tenant.to_string(), pool);
+    }
+
+    Ok(pools.get(tenant).unwrap().clone())
+}
+
+fn get_client_db_path_and_tenant(client_id: &str, clients: &Clients) -> Option<(String, String)> {
+    match clients.lock() {
+        Ok(clients_lock) => clients_lock
+            .get(client_id)
+            .map(|client| (get_db_path(&client.db_name), client.db_name.clone())),
+        Err(e) => {
+            eprintln!("Failed to lock clients: {:?}", e);
+            None
+        }
+    }
+}
+
+fn get_client_role(client_id: &str, clients: &Clients) -> i64 {
+    match clients.lock() {
+        Ok(clients_lock) => clients_lock
+            .get(client_id)
+            .map(|client| client.role)
+            .unwrap_or(0),
+        Err(e) => {
+            eprintln!("Failed to lock clients: {:?}", e);
+            0
+        }
+    }
+}
+
+fn get_client_device_id(client_id: &str, clients: &Clients) -> Option<String> {
+    match clients.lock() {
+        Ok(clients_lock) => clients_lock.get(client_id)?.device_id.clone(),
+        Err(e) => {
+            eprintln!("Failed to lock clients: {:?}", e);
+            None
+        }
+    }
+}
+
+fn get_client_user_id(client_id: &str, clients: &Clients) -> Option<String> {
+    match clients.lock() {
+        Ok(clients_lock) => clients_lock
+            .get(client_id)
+            .map(|client| client.user_id.clone()),
+        Err(e) => {
+            eprintln!("Failed to lock clients: {:?}", e);
+            None
+        }
+    }
+}
+
+fn is_client_connected(client_id: &str, clients: &Clients) -> bool {
+    match clients.lock() {
+        Ok(clients_lock) => clients_lock
+            .get(client_id)
+            .is_some_and(|client| !client.sender.is_closed()),
+        Err(_) => false,
+    }
+}
+
+fn get_client_db_path(client_id: &str, clients: &Clients) -> Option<String> {
+    match clients.lock() {
+        Ok(clients_lock) => clients_lock
+            .get(client_id)
+            .map(|client| get_db_path(&client.db_name)),
+        Err(e) => {
+            eprintln!("Failed to lock clients: {:?}", e);
+            None
+        }
+    }
+}
+
+async fn handle_authentication_request(
+    client_id: String,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+    data: Value,
+) -> bool {
+    let protocol_version = data
+        .get("protocolVersion")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(1)
+        .clamp(1, auth_challenge::CHALLENGE_PROTOCOL_VERSION);
+    let challenged = protocol_version >= auth_challenge::CHALLENGE_PROTOCOL_VERSION;
+
+    let (tenant, user_id) = if challenged {
+        if data.get("apiKey").is_some() {
+            println!("Rejected API key sent with challenge authentication");
+            return false;
+        }
+        match (
+            data.get("tenant").and_then(|v| v.as_str()),
+            data.get("userId").and_then(|v| v.as_str()),
+        ) {
+            (Some(tenant), Some(user_id)) => (tenant, user_id),
+            _ => {
+                println!("No tenant or user id provided");
+                return false;
+            }
+        }
+    } else {
+        let api_key = match data.get("apiKey").and_then(|v| v.as_str()) {
+            Some(key) => key,
+            None => {
+                println!("No API key provided");
+                return false;
+            }
+        };
+
+        match api_key.split_once('-') {
+            Some(parts) => parts,
+            None => {
+                println!("Invalid API key format");
+                return false;
+            }
+        }
+    };
+    let api_key = format!("{}-{}", tenant, user_id);
+
+    println!(
+        "Authentication attempt for tenant: {}, user_id: {}",
+        tenant, user_id
+    );
+
+    if replication::is_standby() {
+        let rejection_response = json!({
+            "type": "authentication_response",
+            "data": {
+                "authenticated": 0,
+                "error": "Server is a standby"
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+
+        send_message(client_id, &rejection_response, clients).await;
+
+        return false;
+    }
+
+    if !database_exists(tenant) {
+        println!("Database for tenant {} does not exist", tenant);
+
+        let rejection_response = json!({
+            "type": "authentication_response",
+            "data": {
+                "authenticated": 0,
+                "error": "Invalid tenant"
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+
+        send_message(client_id, &rejection_response, clients).await;
+
+        return false;
+    }
+
+    if tenant_drain::is_draining(tenant) {
+        println!(
+            "Rejected client {} of draining tenant {}",
+            client_id, tenant
+        );
+
+        let rejection_response = json!({
+            "type": "authentication_response",
+            "data": {
+                "authenticated": 0,
+                "error": "Tenant under maintenance"
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+
+        send_message(client_id, &rejection_response, clients).await;
+
+        return false;
+    }
+
+    let remote_addr = clients
+        .lock()
+        .ok()
+        .and_then(|clients_lock| clients_lock.get(&client_id)?.remote_addr.clone());
+    if let Some(ban) = client_admin::active_ban(tenant, &api_key, remote_addr.as_deref()) {
+        println!("Rejected banned client {} of tenant {}", client_id, tenant);
+
+        let rejection_response = json!({
+            "type": "authentication_response",
+            "data": {
+                "authenticated": 0,
+                "error": "Banned",
+                "reason": ban["reason"],
+                "bannedUntil": ban["expiresAt"]
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+
+        send_message(client_id, &rejection_response, clients).await;
+
+        return false;
+    }
+
+    let device_id = data.get("deviceId").and_then(|v| v.as_str());
+    let device_secret = match device_id {
+        Some(device_id) => match CoreLocalStorage::new(&get_db_path(tenant))
+            .map(Arc::new)
+            .and_then(DeviceSecretLocalStorage::new)
+            .and_then(|storage| storage.get_secret(user_id, device_id))
+        {
+            Ok(secret) => secret,
+            Err(e) => {
+                println!("Failed to get device secret: {:?}", e);
+                return false;
+            }
+        },
+        None => None,
+    };
+
+    let verified = if device_secret.is_some() && !challenged {
+        metrics::increment("auth_challenge_rejected_total");
+        Err("Device requires challenge authentication")
+    } else if challenged || config::get().auth.require_challenge {
+        auth_challenge::verify(
+            &client_id,
+            device_secret.as_deref().unwrap_or(&api_key),
+            &data,
+        )
+    } else {
+        Ok(())
+    };
+
+    if let Err(e) = verified {
+        println!(
+            "Rejected authentication of client {} for tenant {}: {}",
+            client_id, tenant, e
+        );
+
+        let rejection_response = json!({
+            "type": "authentication_response",
+            "data": {
+                "authenticated": 0,
+                "error": e
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+
+        send_message(client_id, &rejection_response, clients).await;
+
+        return false;
+    }
+
+    tenant_hibernation::wake(tenant);
+    let pool = match get_db_pool(tenant, db_pools) {
+        Ok(pool) => pool,
+        Err(e) => {
+            println!("Failed to get database pool: {:?}", e);
+            return false;
+        }
+    };
+
+    let acquire_tenant = tenant.to_string();
+    match tokio::task::spawn_blocking(move || db_pool::acquire(&acquire_tenant, &pool)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(db_pool::AcquireError::Busy)) => {
+            let rejection_response = json!({
+                "type": "authentication_response",
+                "data": {
+                    "authenticated": 0,
+                    "error": db_pool::SERVER_BUSY,
+                    "retryAfterMs": db_pool::retry_after_ms()
+                },
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+
+            send_message(client_id, &rejection_response, clients).await;
+
+            return false;
+        }
+        Ok(Err(db_pool::AcquireError::Failed(_))) => return false,
+        Err(e) => {
+            println!("Failed to get database connection: {:?}", e);
+            return false;
+        }
+    }
+
+    {
+        match clients.lock() {
+            Ok(mut clients_lock) => {
+                if let Some(client) = clients_lock.get_mut(&client_id) {
+                    client.db_name = tenant.to_string();
+                    client.user_id = user_id.to_string();
+                    client.api_key = api_key.to_string();
+                } else {
+                    println!("Client {} not found", client_id);
+                    return false;
+                }
+            }
+            Err(e) => {
+                println!("Failed to lock clients: {:?}", e);
+                return false;
+            }
+        }
+    }
+
+    tracing::Span::current()
+        .record("tenant", tenant)
+        .record("user.id", user_id);
+
+    let db_path = match get_client_db_path(&client_id, clients) {
+        Some(path) => path,
+        None => {
+            println!("No database associated with client {}", client_id);
+            return false;
+        }
+    };
+
+    let core_storage = match CoreLocalStorage::new(&db_path) {
+        Ok(storage) => Arc::new(storage),
+        Err(e) => {
+            println!("Failed to create core storage: {:?}", e);
+            return false;
+        }
+    };
+
+    let user_result = {
+        let user_storage = match UserLocalStorage::new(core_storage.clone()) {
+            Ok(storage) => storage,
+            Err(e) => {
+                println!("Failed to create user storage: {:?}", e);
+                return false;
+            }
+        };
+
+        match user_storage.get_user_by_id(user_id) {
+            Ok(user_opt) => user_opt,
+            Err(e) => {
+                println!("Failed to get user: {:?}", e);
+                return false;
+            }
+        }
+    };
+
+    if user_result.is_none() {
+        println!(
+            "User {} not found in database for tenant {}",
+            user_id, tenant
+        );
+
+        let rejection_response = json!({
+            "type": "authentication_response",
+            "data": {
+                "authenticated": 0,
+                "error": "User not found"
+            },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+
+        send_message(client_id, &rejection_response, clients).await;
+
+        return false;
+    }
+
+    let user_data = user_result.unwrap();
+    let enrolled_secret = match (device_id, &device_secret) {
+        (Some(device_id), Some(_)) => {
+            if let Err(e) = DeviceSecretLocalStorage::new(core_storage.clone())
+                .and_then(|storage| storage.record_use(user_id, device_id))
+            {
+                println!("Failed to record device secret use: {:?}", e);
+            }
+            None
+        }
+        (Some(device_id), None) if challenged => {
+            match DeviceSecretLocalStorage::new(core_storage.clone())
+                .and_then(|storage| storage.create_secret(user_id, device_id))
+            {
+                Ok(secret) => secret,
+                Err(e) => {
+                    println!("Failed to enrol device {}: {:?}", device_id, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let client_version = data.get("clientVersion").and_then(|v| v.as_str());
+    let server = server_features::describe(protocol_version, client_version, core_storage);
+    let role = user_data.get("role").and_then(|v| v.as_i64()).unwrap_or(0);
+    let compression = ws_compression::negotiate(&data);
+    let format = wire_format::negotiate(&data);
+
+    if let Ok(mut clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get_mut(&client_id)
+    {
+        client.role = role;
+        client.authenticated_at = chrono::Utc::now().timestamp_millis();
+        client.client_version = client_version.map(str::to_string);
+        client.device_id = data
+            .get("deviceId")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        client.delta_sync = config::get().broadcast.delta_updates
+            && data.get("deltaSync").and_then(|v| v.as_bool()) == Some(true);
+        if protocol_version < auth_challenge::CHALLENGE_PROTOCOL_VERSION {
+            deprecations::record(
+                deprecations::UNCHALLENGED_AUTHENTICATION,
+                tenant,
+                client_version,
+            );
+        }
+        if protocol_version >= broadcast_batch::BATCH_PROTOCOL_VERSION {
+            client.batch_sender = Some(broadcast_batch::spawn(
+                client.sender.clone(),
+                client.pending_broadcasts.clone(),
+                client.msgpack.clone(),
+            ));
+        }
+    }
+    event_bus::publish(BusEvent::Authenticated {
+        tenant: tenant.to_string(),
+        client_id: client_id.clone(),
+        user_id: user_id.to_string(),
+        role,
+    })
+    .await;
+
+    let authentication_response = json!({
+        "type": "authentication_response",
+        "dbName": tenant,
+        "data": {
+            "id": user_data.get("id").unwrap_or(&json!("")).as_str(),
+            "role": user_data.get("role").unwrap_or(&json!(0)),
+            "lastEdit": user_data.get("lastEdit").unwrap_or(&json!(chrono::Utc::now().timestamp_millis())),
+            "name": user_data.get("name").unwrap_or(&json!("Unknown User")).as_str(),
+            "authenticated": 1,
+            "apiKey": (!challenged).then_some(&api_key),
+            "deviceSecret": enrolled_secret,
+            "bannedStatus": user_data.get("bannedStatus").unwrap_or(&json!(0)),
+            "protocolVersion": protocol_version,
+            "compression": compression,
+            "format": format,
+            "server": server
+        },
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &authentication_response, clients).await;
+    if let Ok(clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get(&client_id)
+    {
+        client
+            .compression
+            .store(compression.is_some(), Ordering::Relaxed);
+        client
+            .msgpack
+            .store(format == wire_format::MSGPACK, Ordering::Relaxed);
+    }
+
+    let user_update = serde_json::json!({
+        "type": "user_update",
+        "data": {
+            "id": user_data.get("id").unwrap_or(&json!("")).as_str(),
+            "role": user_data.get("role").unwrap_or(&json!(0)),
+            "lastEdit": user_data.get("lastEdit").unwrap_or(&json!(chrono::Utc::now().timestamp_millis())),
+            "name": user_data.get("name").unwrap_or(&json!("Unknown User")).as_str(),
+            "authenticated": 1,
+            "apiKey": (!challenged).then_some(&api_key),
+            "bannedStatus": user_data.get("bannedStatus").unwrap_or(&json!(0))
+        },
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id, &user_update, clients).await;
+
+    true
+}
+
+#[tracing::instrument(
+    name = "ws.message",
+    skip_all,
+    fields(msg.type = msg_type, tenant = tracing::field::Empty, user.id = tracing::field::Empty)
+)]
+async fn handle_client_message(
+    msg_type: &str,
+    msg: &Value,
+    data: &Value,
+    client_id: &str,
+    clients: &Clients,
+) {
+    let (db_path, tenant) = match get_client_db_path_and_tenant(client_id, clients) {
+        Some(path_and_tenant) => path_and_tenant,
+        None => {
+            println!("No database associated with client {}", client_id);
+            return;
+        }
+    };
+    let tenant = tenant.as_str();
+    tracing::Span::current().record("tenant", tenant).record(
+        "user.id",
+        get_client_user_id(client_id, clients).unwrap_or_default(),
+    );
+
+    println!(
+        "Processing message of type {} for database {}",
+        msg_type, db_path
+    );
+    for warning in deprecations::check_message(client_id, msg_type, data, clients) {
+        send_message(client_id.to_string(), &warning, clients).await;
+    }
+
+    let core_storage = match write_batch::storage_for(&db_path)
+        .map(Ok)
+        .unwrap_or_else(|| CoreLocalStorage::new(&db_path).map(Arc::new))
+    {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create core storage: {:?}", e);
+            return;
+        }
+    };
+
+    if let Some((entity, action)) = permissions::required(msg_type, data)
+        && let Err(e) = permissions::check(
+            core_storage.clone(),
+            &get_client_user_id(client_id, clients).unwrap_or_default(),
+            get_client_role(client_id, clients),
+            entity,
+            action,
+        )
+    {
+        println!(
+            "Denied {} of client {} in tenant {}: {}",
+            msg_type, client_id, tenant, e
+        );
+        send_error(
+            client_id.to_string(),
+            msg_type,
+            ["id", "key", "contractId", "locationId"]
+                .iter()
+                .find_map(|key| data.get(*key)),
+            "PERMISSION_DENIED",
+            &e,
+            clients,
+        )
+        .await;
+        return;
+    }
+
+    let based = conflicts::split_base(msg_type, data);
+    let based_msg = based
+        .as_ref()
+        .map(|(data, _)| json!({ "type": msg_type, "data": data }));
+    let base_last_edit = based.as_ref().map(|(_, base_last_edit)| *base_last_edit);
+    let data = based.as_ref().map(|(data, _)| data).unwrap_or(data);
+    let msg = based_msg.as_ref().unwrap_or(msg);
+
+    let normalized = normalize_entity(msg_type, data, core_storage.clone());
+    let normalized_msg = normalized
+        .as_ref()
+        .map(|data| json!({ "type": msg_type, "data": data }));
+    let data = normalized.as_ref().unwrap_or(data);
+    let msg = normalized_msg.as_ref().unwrap_or(msg);
+
+    let annotated = match scripting::apply_hooks(msg_type, data, core_storage.clone()) {
+        Ok(annotated) => annotated,
+        Err(e) => {
+            send_error(
+                client_id.to_string(),
+                msg_type,
+                data.get("id"),
+                "VALIDATION_FAILED",
+                &e,
+                clients,
+            )
+            .await;
+            return;
+        }
+    };
+    let annotated_msg = annotated
+        .as_ref()
+        .map(|data| json!({ "type": msg_type, "data": data }));
+    let data = annotated.as_ref().unwrap_or(data);
+    let msg = annotated_msg.as_ref().unwrap_or(msg);
+
+    if let Err(errors) = update_schema::validate(msg_type, data, core_storage.clone()) {
+        let message = update_schema::summarize(&errors);
+        println!(
+            "Rejected {} of client {} in tenant {}: {}",
+            msg_type, client_id, tenant, message
+        );
+        send_error_with_details(
+            client_id.to_string(),
+            msg_type,
+            data.get("id"),
+            "VALIDATION_FAILED",
+            &message,
+            Some(json!(
+                errors.iter().map(|e| e.to_json()).collect::<Vec<_>>()
+            )),
+            clients,
+        )
+        .await;
+        return;
+    }
+
+    let conflict = base_last_edit.and_then(|base_last_edit| {
+        conflicts::detect(
+            msg_type,
+            data,
+            base_last_edit,
+            get_client_user_id(client_id, clients).as_deref(),
+            get_client_role(client_id, clients),
+            core_storage.clone(),
+        )
+    });
+
+    match msg_type {
+        "contract_update" => {
+            let mut contract = data.clone();
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+            if !is_deleted
+                && let Err(e) =
+                    pricing::validate_contract_pricing(&mut contract, core_storage.clone())
+            {
+                println!("Rejected contract update: {}", e);
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "VALIDATION_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let base = delta_base(msg_type, &contract, core_storage.clone());
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                &contract,
+                core_storage.clone(),
+                clients,
+                handle_contract_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                let msg = json!({ "type": msg_type, "data": contract });
+                let delta = base
+                    .filter(|_| !is_deleted)
+                    .and_then(|base| delta::compute(&base, &contract));
+                broadcast_update(client_id.to_string(), &msg, delta, clients).await;
+
+                if is_deleted {
+                    tombstone_attached_notes(
+                        tenant,
+                        "contract",
+                        data,
+                        core_storage.clone(),
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "location_update" => {
+            let location_id = data.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+            let mut location = data.clone();
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()) == Some(1);
+            if !is_deleted
+                && let Err(e) = coordinates::normalize_location(&mut location)
+                    .and_then(|_| {
+                        units::normalize_quantities(msg_type, &mut location, core_storage.clone())
+                    })
+                    .and_then(|_| assortments::validate_location(&location, core_storage.clone()))
+            {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "VALIDATION_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            match ArchiveLocalStorage::new(core_storage.clone())
+                .and_then(|storage| storage.restore_location(location_id))
+            {
+                Ok(true) => println!("Restored archived location {}", location_id),
+                Ok(false) => {}
+                Err(e) => println!("Failed to restore archived location: {:?}", e),
+            }
+            let previous_crew_id = core_storage
+                .get_columns_by_id("locations", location_id, &["crewId"])
+                .ok()
+                .and_then(|locations| locations.into_iter().next())
+                .and_then(|location| location["crewId"].as_str().map(str::to_string));
+            let crew_id = data.get("crewId").map(|v| v.as_str().map(str::to_string));
+            let crew_changed = crew_id
+                .as_ref()
+                .is_some_and(|crew_id| *crew_id != previous_crew_id);
+
+            if crew_changed
+                && let Err(e) = permissions::check(
+                    core_storage.clone(),
+                    &get_client_user_id(client_id, clients).unwrap_or_default(),
+                    get_client_role(client_id, clients),
+                    "location",
+                    "assignCrew",
+                )
+            {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "PERMISSION_DENIED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let base = delta_base(msg_type, &location, core_storage.clone());
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                &location,
+                core_storage.clone(),
+                clients,
+                handle_location_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                let msg = json!({ "type": msg_type, "data": location });
+                let delta = base
+                    .filter(|_| !is_deleted)
+                    .and_then(|base| delta::compute(&base, &location));
+                broadcast_update(client_id.to_string(), &msg, delta, clients).await;
+
+                if is_deleted {
+                    tombstone_attached_notes(
+                        tenant,
+                        "location",
+                        data,
+                        core_storage.clone(),
+                        clients,
+                    )
+                    .await;
+                }
+
+                match quota::settle_for_location(core_storage.clone(), location_id, is_deleted) {
+                    Ok(Some(settled)) => quota::broadcast(tenant, &settled, clients).await,
+                    Ok(None) => {}
+                    Err(e) => println!(
+                        "Failed to settle quota reservation of location {}: {}",
+                        location_id, e
+                    ),
+                }
+
+                if crew_changed && let Some(Some(crew_id)) = &crew_id {
+                    notify_crew_assignment(
+                        tenant,
+                        crew_id,
+                        location_id,
+                        core_storage.clone(),
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "crew_update" => {
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                data,
+                core_storage.clone(),
+                clients,
+                handle_crew_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                broadcast_message(client_id.to_string(), msg, clients).await;
+            }
+        }
+        "tour_update" => {
+            let mut tour = data.clone();
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+            if !is_deleted && let Err(e) = tours::validate_tour(&mut tour, core_storage.clone()) {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "VALIDATION_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                &tour,
+                core_storage.clone(),
+                clients,
+                handle_tour_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                let msg = json!({ "type": msg_type, "data": tour });
+                broadcast_message(client_id.to_string(), &msg, clients).await;
+            }
+        }
+        "tour_start" | "tour_finish" => {
+            let user_id = get_client_user_id(client_id, clients).unwrap_or_default();
+            match tours::transition(
+                msg_type,
+                data,
+                &user_id,
+                get_client_role(client_id, clients),
+                core_storage.clone(),
+            ) {
+                Ok(tour) => {
+                    println!(
+                        "Tour {} {} by {}",
+                        tour["id"].as_str().unwrap_or_default(),
+                        tour["status"].as_str().unwrap_or_default(),
+                        user_id
+                    );
+                    broadcast_server_update(client_id, "tour_update", &tour, 0, clients).await;
+                }
+                Err((code, e)) => {
+                    println!("Rejected {}: {}", msg_type, e);
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("id"),
+                        code,
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "note_update" => {
+            if let Err(e) = note_attachments::validate_attachment(data) {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "VALIDATION_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                data,
+                core_storage.clone(),
+                clients,
+                handle_note_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                broadcast_message(client_id.to_string(), msg, clients).await;
+            }
+        }
+        "photo_update" => {
+            let guard = match photo_ingest::begin(photo_ingest::payload_len(data)) {
+                Some(guard) => guard,
+                None => {
+                    println!(
+                        "Photo ingest saturated, asking client {} to retry",
+                        client_id
+                    );
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("id"),
+                        "RETRY_LATER",
+                        "Server is processing too many large photos, retry later",
+                        clients,
+                    )
+                    .await;
+                    return;
+                }
+            };
+
+            let Some(saved) = run_update(
+                msg_type,
+                client_id,
+                data,
+                core_storage.clone(),
+                clients,
+                handle_photo_update,
+            )
+            .await
+            else {
+                return;
+            };
+            drop(guard);
+            if let Some(photo) = saved {
+                let message = json!({
+                    "type": msg_type,
+                    "data": photo,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+                broadcast_message(client_id.to_string(), &message, clients).await;
+            }
+        }
+        "photo_upload_start" | "photo_upload_chunk" | "photo_upload_complete" => {
+            handle_photo_upload_message(
+                msg_type,
+                data,
+                client_id,
+                core_storage.clone(),
+                tenant,
+                clients,
+            )
+            .await;
+        }
+        "photo_fetch_batch" => {
+            send_photo_batch(data, client_id, core_storage.clone(), tenant, clients).await;
+        }
+        "archive_fetch" => {
+            send_archived_location(data, client_id, core_storage.clone(), tenant, clients).await;
+        }
+        "delta_base_missing" => {
+            send_delta_base(data, client_id, core_storage.clone(), tenant, clients).await;
+        }
+        "shipment_document_upload_start"
+        | "shipment_document_chunk"
+        | "shipment_document_upload_complete"
+        | "shipment_document_delete" => {
+            handle_shipment_document_message(
+                msg_type,
+                data,
+                client_id,
+                core_storage.clone(),
+                tenant,
+                clients,
+            )
+            .await;
+        }
+        "sawmill_update" => {
+            if let Err(e) = coordinates::check_sawmill(data) {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "VALIDATION_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                data,
+                core_storage.clone(),
+                clients,
+                handle_sawmill_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                broadcast_message(client_id.to_string(), msg, clients).await;
+            }
+        }
+        "assortment_update" => {
+            let mut assortment = data.clone();
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+            if !is_deleted
+                && let Err(e) =
+                    assortments::validate_assortment(&mut assortment, core_storage.clone())
+            {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "VALIDATION_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                &assortment,
+                core_storage.clone(),
+                clients,
+                handle_assortment_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                let msg = json!({ "type": msg_type, "data": assortment });
+                broadcast_message(client_id.to_string(), &msg, clients).await;
+            }
+        }
+        "sawmill_price_update" => {
+            let mut price = data.clone();
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+            if !is_deleted
+                && let Err(e) = price_lists::validate_price(&mut price, core_storage.clone())
+            {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "VALIDATION_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                &price,
+                core_storage.clone(),
+                clients,
+                handle_sawmill_price_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                broadcast_server_update(client_id, msg_type, &price, ROLE_PRIVILEGED, clients)
+                    .await;
+            }
+        }
+        "shipment_update" => {
+            let mut shipment = data.clone();
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+            let mut review_reason = None;
+            if !is_deleted {
+                if let Err(e) =
+                    units::normalize_quantities(msg_type, &mut shipment, core_storage.clone())
+                        .and_then(|_| {
+                            assortments::check_shipment(&mut shipment, core_storage.clone())
+                        })
+                        .and_then(|_| tours::check_shipment(&shipment, core_storage.clone()))
+                {
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("id"),
+                        "VALIDATION_FAILED",
+                        &e,
+                        clients,
+                    )
+                    .await;
+                    return;
+                }
+                let allowlist_reason =
+                    match contract_allowlist::check_shipment(&shipment, core_storage.clone()) {
+                        Ok(Some(Violation::Reject(reason))) => {
+                            send_error(
+                                client_id.to_string(),
+                                msg_type,
+                                data.get("id"),
+                                "VALIDATION_FAILED",
+                                &reason,
+                                clients,
+                            )
+                            .await;
+                            return;
+                        }
+                        Ok(Some(Violation::Flag(reason))) => Some(reason),
+                        Ok(None) => None,
+                        Err(e) => {
+                            println!("Failed to check sawmill allowlist: {}", e);
+                            None
+                        }
+                    };
+                if let Err(e) = pricing::apply_line_values(&mut shipment, core_storage.clone()) {
+                    println!("Failed to compute shipment line values: {:?}", e);
+                }
+                route_distance::apply(&mut shipment, core_storage.clone()).await;
+                review_reason = plausibility::flag_shipment(&mut shipment);
+                if review_reason.is_none()
+                    && let Some(reason) = allowlist_reason
+                {
+                    shipment["reviewStatus"] = json!(plausibility::NEEDS_REVIEW);
+                    shipment["reviewReason"] = json!(reason);
+                    review_reason = Some(reason);
+                }
+            }
+
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                &shipment,
+                core_storage.clone(),
+                clients,
+                handle_shipment_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                let msg = json!({ "type": msg_type, "data": shipment });
+                broadcast_message(client_id.to_string(), &msg, clients).await;
+
+                if is_deleted {
+                    tombstone_attached_notes(
+                        tenant,
+                        "shipment",
+                        data,
+                        core_storage.clone(),
+                        clients,
+                    )
+                    .await;
+                }
+
+                if let Some(reason) = review_reason {
+                    println!("Shipment {} flagged for review: {}", shipment["id"], reason);
+                    let shipment_id = shipment["id"].as_str().unwrap_or_default();
+                    match review_queue::open_review_item(
+                        core_storage.clone(),
+                        "shipment",
+                        shipment_id,
+                        &reason,
+                    ) {
+                        Ok(Some(review_item)) => {
+                            broadcast_server_update(
+                                client_id,
+                                "review_item_update",
+                                &review_item,
+                                ROLE_PRIVILEGED,
+                                clients,
+                            )
+                            .await;
+                        }
+                        Ok(None) => {}
+                        Err(e) => println!("Failed to open review item: {:?}", e),
+                    }
+
+                    let notification = json!({
+                        "type": "review_required",
+                        "data": {
+                            "entityType": "shipment",
+                            "entityId": shipment["id"],
+                            "reason": reason
+                        },
+                        "timestamp": chrono::Utc::now().timestamp_millis()
+                    });
+                    send_to_tenant_role(tenant, ROLE_PRIVILEGED, &notification, clients).await;
+                }
+            }
+        }
+        "review_approve" | "review_reject" => {
+            let review_item_id = data.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+            let reviewer_id = get_client_user_id(client_id, clients).unwrap_or_default();
+            match review_queue::decide_review_item(
+                core_storage.clone(),
+                review_item_id,
+                msg_type == "review_approve",
+                &reviewer_id,
+            ) {
+                Ok(updates) => {
+                    for (update_type, update) in updates {
+                        let min_role = if update_type == "review_item_update" {
+                            ROLE_PRIVILEGED
+                        } else {
+                            0
+                        };
+                        broadcast_server_update(client_id, update_type, &update, min_role, clients)
+                            .await;
+                    }
+                }
+                Err(e) => {
+                    println!("Review decision failed: {}", e);
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("id"),
+                        "REVIEW_FAILED",
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "deleted_since" => {
+            let role = get_client_role(client_id, clients);
+            match tombstones::deleted_since(data, role, core_storage.clone()) {
+                Ok(page) => {
+                    let response = json!({
+                        "type": "deleted_since_response",
+                        "data": page,
+                        "dbName": tenant,
+                        "timestamp": chrono::Utc::now().timestamp_millis()
+                    });
+                    send_message(client_id.to_string(), &response, clients).await;
+                }
+                Err((code, e)) => {
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("entity"),
+                        code,
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "search" => match search::search(data, core_storage.clone()) {
+            Ok(result) => {
+                let response = json!({
+                    "type": "search_response",
+                    "data": result,
+                    "dbName": tenant,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+                send_message(client_id.to_string(), &response, clients).await;
+            }
+            Err((code, e)) => {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("query"),
+                    code,
+                    &e,
+                    clients,
+                )
+                .await;
+            }
+        },
+        "activity_fetch" => match activity_feed::fetch(data, core_storage.clone()) {
+            Ok(page) => {
+                let response = json!({
+                    "type": "activity_fetch_response",
+                    "data": page,
+                    "dbName": tenant,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+                send_message(client_id.to_string(), &response, clients).await;
+            }
+            Err((code, e)) => {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("before"),
+                    code,
+                    &e,
+                    clients,
+                )
+                .await;
+            }
+        },
+        "conflict_detail" => {
+            match conflicts::detail(
+                data,
+                get_client_role(client_id, clients),
+                core_storage.clone(),
+            ) {
+                Ok(detail) => {
+                    let response = json!({
+                        "type": "conflict_detail_response",
+                        "data": detail,
+                        "dbName": tenant,
+                        "timestamp": chrono::Utc::now().timestamp_millis()
+                    });
+                    send_message(client_id.to_string(), &response, clients).await;
+                }
+                Err((code, e)) => {
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("id"),
+                        code,
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "conflict_list" | "conflict_resolve" => {
+            let user_id = get_client_user_id(client_id, clients).unwrap_or_default();
+            let role = get_client_role(client_id, clients);
+            let result = if msg_type == "conflict_list" {
+                conflicts::list(data, &user_id, role, core_storage.clone())
+            } else {
+                conflicts::resolve(data, &user_id, role, core_storage.clone())
+            };
+            match result {
+                Ok(result) => {
+                    let response = json!({
+                        "type": format!("{}_response", msg_type),
+                        "data": result,
+                        "dbName": tenant,
+                        "timestamp": chrono::Utc::now().timestamp_millis()
+                    });
+                    send_message(client_id.to_string(), &response, clients).await;
+                    if msg_type == "conflict_resolve" {
+                        broadcast_server_update(
+                            client_id,
+                            "conflict_resolved",
+                            &result,
+                            ROLE_ADMIN,
+                            clients,
+                        )
+                        .await;
+                    }
+                }
+                Err((code, e)) => {
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("id"),
+                        code,
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "contract_allowlist_update" => {
+            match contract_allowlist::update(data, core_storage.clone()) {
+                Ok(contract) => {
+                    println!(
+                        "Sawmill allowlist of contract {} set to {} ({})",
+                        contract["id"],
+                        contract["allowedSawmillIds"],
+                        contract["sawmillAllowlistMode"]
+                    );
+                    let response = json!({
+                        "type": "contract_allowlist_update",
+                        "data": {
+                            "contractId": contract["id"],
+                            "sawmillIds": contract["allowedSawmillIds"],
+                            "mode": contract["sawmillAllowlistMode"],
+                            "synced": 1
+                        },
+                        "dbName": tenant,
+                        "timestamp": chrono::Utc::now().timestamp_millis()
+                    });
+                    send_message(client_id.to_string(), &response, clients).await;
+                    broadcast_server_update(client_id, "contract_update", &contract, 0, clients)
+                        .await;
+                }
+                Err((code, e)) => {
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("contractId"),
+                        code,
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "photo_url_request" => match photo_urls::issue(tenant, data, core_storage.clone()) {
+            Ok(photo_url) => {
+                let response = json!({
+                    "type": "photo_url",
+                    "data": photo_url,
+                    "dbName": tenant,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+                send_message(client_id.to_string(), &response, clients).await;
+            }
+            Err((code, e)) => {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    code,
+                    &e,
+                    clients,
+                )
+                .await;
+            }
+        },
+        "quota_reserve" => {
+            let user_id = get_client_user_id(client_id, clients).unwrap_or_default();
+            match quota::reserve(core_storage.clone(), &user_id, data) {
+                Ok(reserved) => {
+                    let response = json!({
+                        "type": "quota_reserve_response",
+                        "data": {
+                            "reservation": reserved.reservation,
+                            "remaining": reserved.remaining
+                        },
+                        "dbName": tenant,
+                        "timestamp": chrono::Utc::now().timestamp_millis()
+                    });
+                    send_message(client_id.to_string(), &response, clients).await;
+                    quota::broadcast(tenant, &reserved, clients).await;
+                }
+                Err(e) => {
+                    println!("Quota reservation failed: {}", e);
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("locationId"),
+                        "VALIDATION_FAILED",
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "contract_split" => match contract_split::split_contract(core_storage.clone(), data) {
+            Ok(updates) => {
+                for (update_type, update) in updates {
+                    broadcast_server_update(client_id, update_type, &update, 0, clients).await;
+                }
+            }
+            Err(e) => {
+                println!("Contract split failed: {}", e);
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("contractId"),
+                    "SPLIT_FAILED",
+                    &e,
+                    clients,
+                )
+                .await;
+            }
+        },
+        "location_split" => {
+            let user_id = get_client_user_id(client_id, clients).unwrap_or_default();
+            match location_split::split_location(core_storage.clone(), data, &user_id) {
+                Ok(updates) => {
+                    for (update_type, update) in updates {
+                        broadcast_server_update(client_id, update_type, &update, 0, clients).await;
+                    }
+                }
+                Err(e) => {
+                    println!("Location split failed: {}", e);
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("locationId"),
+                        "SPLIT_FAILED",
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "settings_update" => {
+            let Some(result) = run_update(
+                msg_type,
+                client_id,
+                data,
+                core_storage.clone(),
+                clients,
+                handle_settings_update,
+            )
+            .await
+            else {
+                return;
+            };
+            match result {
+                Ok(true) => broadcast_message(client_id.to_string(), msg, clients).await,
+                Ok(false) => {}
+                Err(e) => {
+                    println!("Rejected settings update: {}", e);
+                    send_error(
+                        client_id.to_string(),
+                        msg_type,
+                        data.get("key"),
+                        "VALIDATION_FAILED",
+                        &e,
+                        clients,
+                    )
+                    .await;
+                }
+            }
+        }
+        "user_update" => {
+            let user_id = data.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+            let previous_role = core_storage
+                .get_columns_by_id("users", user_id, &["role"])
+                .ok()
+                .and_then(|users| users.into_iter().next())
+                .and_then(|user| user["role"].as_i64());
+            let role_changed = data
+                .get("role")
+                .is_some_and(|role| role.as_i64() != Some(previous_role.unwrap_or(0)));
+            let other_user = get_client_user_id(client_id, clients).as_deref() != Some(user_id);
+
+            if (role_changed || other_user)
+                && let Err(e) = permissions::check(
+                    core_storage.clone(),
+                    &get_client_user_id(client_id, clients).unwrap_or_default(),
+                    get_client_role(client_id, clients),
+                    "user",
+                    "assignRole",
+                )
+            {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    data.get("id"),
+                    "PERMISSION_DENIED",
+                    &e,
+                    clients,
+                )
+                .await;
+                return;
+            }
+
+            let Some(update_happened) = run_update(
+                msg_type,
+                client_id,
+                data,
+                core_storage.clone(),
+                clients,
+                handle_user_update,
+            )
+            .await
+            else {
+                return;
+            };
+            if update_happened {
+                broadcast_message(client_id.to_string(), msg, clients).await;
+                if let Some(user_id) = data.get("id").and_then(|v| v.as_str()) {
+                    role_changes::propagate(tenant, user_id, core_storage.clone(), clients);
+                }
+            }
+        }
+        _ => println!("Unknown message type: {}", msg_type),
+    }
+
+    if let Some(conflict) = conflict {
+        record_conflict(&conflict, client_id, core_storage, clients).await;
+    }
+}
+
+/// Stores a detected conflict and tells the user whose update caused it and
+/// the tenant's admins, so it can be resolved.
+async fn record_conflict(
+    conflict: &Value,
+    client_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    clients: &Clients,
+) {
+    if let Err(e) = conflicts::record(conflict, core_storage) {
+        println!(
+            "Failed to record conflict on {} {}: {:?}",
+            conflict["entityType"], conflict["entityId"], e
+        );
+        return;
+    }
+    println!(
+        "Recorded conflict on {} {}, {} version won",
+        conflict["entityType"], conflict["entityId"], conflict["winner"]
+    );
+    metrics::increment("conflicts_detected_total");
+
+    if get_client_role(client_id, clients) < ROLE_ADMIN
+        && let Some((_, tenant)) = get_client_db_path_and_tenant(client_id, clients)
+    {
+        let message = json!({
+            "type": "conflict_detected",
+            "data": conflict,
+            "dbName": tenant,
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        send_message(client_id.to_string(), &message, clients).await;
+    }
+    broadcast_server_update(
+        client_id,
+        "conflict_detected",
+        conflict,
+        ROLE_ADMIN,
+        clients,
+    )
+    .await;
+}
+
+fn handle_settings_update(
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> std::result::Result<bool, String> {
+    let key = data.get("key").and_then(|v| v.as_str()).unwrap_or_default();
+    let value = match data.get("value").and_then(|v| v.as_str()) {
+        Some(value) => value,
+        None => return Err("Missing setting value".to_string()),
+    };
+
+    match key {
+        CURRENCY_KEY => {
+            if !pricing::is_valid_currency(value) {
+                return Err(format!("Invalid currency code: {}", value));
+            }
+        }
+        CURRENCIES_KEY => {
+            if let Some(code) = value
+                .split(',')
+                .map(|code| code.trim())
+                .find(|code| !pricing::is_valid_currency(code))
+            {
+                return Err(format!("Invalid currency code: {}", code));
+            }
+        }
+        FIELD_MAPPING_KEY => {
+            let is_valid = serde_json::from_str::<Value>(value)
+                .ok()
+                .and_then(|mapping| mapping.as_object().cloned())
+                .is_some_and(|mapping| mapping.values().all(|property| property.is_string()));
+            if !is_valid {
+                return Err("Field mapping must be an object of property names".to_string());
+            }
+        }
+        DIGEST_KEY => digests::validate_settings(value)?,
+        EXPORT_POLICY_KEY => export::validate_policy(value)?,
+        UNIT_FACTORS_KEY => units::validate_factors(value)?,
+        PHOTO_TRANSCODING_KEY => photo_transcode::validate_settings(value)?,
+        PHOTO_LIMITS_KEY => photo_transcode::validate_limits(value)?,
+        PHOTO_RECOMPRESSION_KEY => photo_recompression::validate_policy(value)?,
+        WRITE_VERIFICATION_KEY => write_verification::validate_settings(value)?,
+        TIMEZONE_KEY => {
+            if timezone::TenantTimezone::parse(value).is_none() {
+                return Err(format!("Unsupported timezone: {}", value));
+            }
+        }
+        _ => return Err(format!("Unknown setting: {}", key)),
+    }
+
+    let last_edit = data
+        .get("lastEdit")
+        .and_then(|v| v.as_i64())
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+
+    let settings_storage = SettingsLocalStorage::new(core_storage)
+        .map_err(|e| format!("Failed to create settings storage: {:?}", e))?;
+    settings_storage
+        .save_setting(key, value, last_edit)
+        .map_err(|e| format!("Failed to save setting: {:?}", e))
+}
+
+fn handle_contract_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match ContractLocalStorage::new(core_storage.clone()) {
+        Ok(contract_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match contract_storage.save_contract(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save contract: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("contracts", id) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            println!("Failed to mark contract as deleted: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    println!("Failed to mark contract as deleted: Missing ID");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create contract storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn normalize_entity(
+    msg_type: &str,
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Option<Value> {
+    let mut normalized = timezone::coerce_timestamps(data);
+    if data.get("deleted").and_then(|v| v.as_i64()) == Some(1) {
+        return normalized;
+    }
+
+    if timezone::has_date_fields(msg_type) {
+        let tenant_timezone = timezone::for_tenant(core_storage.clone());
+        let mut entity = normalized.clone().unwrap_or_else(|| data.clone());
+        if timezone::normalize_dates(msg_type, &mut entity, &tenant_timezone) {
+            normalized = Some(entity);
+        }
+    }
+
+    let Some(defaults) = schema::defaults_for_message(msg_type) else {
+        return normalized;
+    };
+    let Some(id) = data.get("id").and_then(|v| v.as_str()) else {
+        return normalized;
+    };
+    match core_storage.get_columns_by_id(defaults.table, id, &["id"]) {
+        Ok(existing) if existing.is_empty() => {}
+        Ok(_) => return normalized,
+        Err(e) => {
+            println!("Failed to check existing {}: {:?}", defaults.table, e);
+            return normalized;
+        }
+    }
+
+    let mut entity = normalized.clone().unwrap_or_else(|| data.clone());
+    if defaults.apply(&mut entity) {
+        Some(entity)
+    } else {
+        normalized
+    }
+}
+
+fn handle_location_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match LocationLocalStorage::new(core_storage.clone()) {
+        Ok(location_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match location_storage.save_location(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save location: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("locations", id) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            println!("Failed to mark location as deleted: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    println!("Failed to mark location as deleted: Missing ID");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create location storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn handle_note_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match NoteLocalStorage::new(core_storage.clone()) {
+        Ok(note_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match note_storage.save_note(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save note: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("notes", id) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            println!("Failed to mark note as deleted: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    println!("Failed to mark note as deleted: Missing ID");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create note storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn handle_photo_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> Option<Value> {
+    match PhotoLocalStorage::new(core_storage.clone()) {
+        Ok(photo_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                let mut photo = data.clone();
+                let transcoded = match photo_transcode::transcode(&mut photo, core_storage.clone())
+                {
+                    Ok(transcoded) => transcoded,
+                    Err(e) => {
+                        println!("Failed to save photo: {}", e);
+                        return None;
+                    }
+                };
+                let saved = match &transcoded {
+                    Some(transcoded) => {
+                        photo_storage.save_photo_bytes(&photo, &transcoded.photo_file)
+                    }
+                    None => photo_storage.save_photo(&photo),
+                };
+                match saved {
+                    Ok(true) => {
+                        if let Some(transcoded) = transcoded {
+                            let id = photo["id"].as_str().unwrap_or_default();
+                            if let Some(original) = transcoded.original
+                                && let Err(e) = photo_storage.save_original(
+                                    id,
+                                    original.format,
+                                    &original.photo_file,
+                                )
+                            {
+                                println!("Failed to keep original of photo {}: {:?}", id, e);
+                            }
+                            // Clients receive the converted file with the broadcast.
+                            photo["photoFile"] = json!(transcoded.photo_file);
+                        }
+                        Some(photo)
+                    }
+                    Ok(false) => None,
+                    Err(e) => {
+                        println!("Failed to save photo: {:?}", e);
+                        None
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("photos", id) {
+                        Ok(_) => {
+                            if let Err(e) =
+                                core_storage.delete_by_column("photoOriginals", "id", id)
+                            {
+                                println!("Failed to delete original of photo {}: {:?}", id, e);
+                            }
+                            Some(data.clone())
+                        }
+                        Err(e) => {
+                            println!("Failed to mark photo as deleted: {:?}", e);
+                            None
+                        }
+                    }
+                } else {
+                    println!("Failed to mark photo as deleted: Missing ID");
+                    None
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create photo storage: {:?}", e);
+            None
+        }
+    }
+}
+
+fn handle_crew_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match CrewLocalStorage::new(core_storage.clone()) {
+        Ok(crew_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match crew_storage.save_crew(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save crew: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("crews", id) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            println!("Failed to mark crew as deleted: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    println!("Failed to mark crew as deleted: Missing ID");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create crew storage: {:?}", e);
+            false
+        }
+    }
+}
+
+async fn tombstone_attached_notes(
+    tenant: &str,
+    entity_type: &str,
+    entity: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+    clients: &Clients,
+) {
+    let entity_id = entity
+        .get("id")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let tombstones = match NoteLocalStorage::new(core_storage)
+        .and_then(|storage| storage.tombstone_notes_for_entity(entity_type, entity_id))
+    {
+        Ok(tombstones) => tombstones,
+        Err(e) => {
+            println!(
+                "Failed to tombstone notes of {} {}: {:?}",
+                entity_type, entity_id, e
+            );
+            return;
+        }
+    };
+
+    for note in &tombstones {
+        broadcast_to_tenant(tenant, "note_update", note, 0, clients).await;
+    }
+}
+
+async fn notify_crew_assignment(
+    tenant: &str,
+    crew_id: &str,
+    location_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    clients: &Clients,
+) {
+    let member_ids = match CrewLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_member_ids(crew_id))
+    {
+        Ok(member_ids) => member_ids,
+        Err(e) => {
+            println!("Failed to load crew members: {:?}", e);
+            return;
+        }
+    };
+
+    let notification = json!({
+        "type": "location_assigned",
+        "data": {
+            "locationId": location_id,
+            "crewId": crew_id
+        },
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+    send_to_tenant_users(tenant, &member_ids, &notification, clients).await;
+}
+
+fn handle_sawmill_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match SawmillLocalStorage::new(core_storage.clone()) {
+        Ok(sawmill_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match sawmill_storage.save_sawmill(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save sawmill: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("sawmills", id) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            println!("Failed to mark sawmill as deleted: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    println!("Failed to mark sawmill as deleted: Missing ID");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create sawmill storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn handle_tour_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match TourLocalStorage::new(core_storage.clone()) {
+        Ok(tour_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match tour_storage.save_tour(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save tour: {:?}", e);
+                        false
+                    }
+                }
+            } else if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                match core_storage.mark_as_deleted("tours", id) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        println!("Failed to mark tour as deleted: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                println!("Failed to mark tour as deleted: Missing ID");
+                false
+            }
+        }
+        Err(e) => {
+            println!("Failed to create tour storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn handle_assortment_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match AssortmentLocalStorage::new(core_storage.clone()) {
+        Ok(assortment_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match assortment_storage.save_assortment(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save assortment: {:?}", e);
+                        false
+                    }
+                }
+            } else if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                match core_storage.mark_as_deleted("assortments", id) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        println!("Failed to mark assortment as deleted: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                println!("Failed to mark assortment as deleted: Missing ID");
+                false
+            }
+        }
+        Err(e) => {
+            println!("Failed to create assortment storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn handle_sawmill_price_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match SawmillPriceLocalStorage::new(core_storage.clone()) {
+        Ok(price_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match price_storage.save_price(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save sawmill price: {:?}", e);
+                        false
+                    }
+                }
+            } else if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                match core_storage.mark_as_deleted("sawmillPrices", id) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        println!("Failed to mark sawmill price as deleted: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                println!("Failed to mark sawmill price as deleted: Missing ID");
+                false
+            }
+        }
+        Err(e) => {
+            println!("Failed to create sawmill price storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn handle_shipment_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match ShipmentLocalStorage::new(core_storage.clone()) {
+        Ok(shipment_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                match shipment_storage.save_shipment(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save shipment: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("shipments", id) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            println!("Failed to mark shipment as deleted: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    println!("Failed to mark shipment as deleted: Missing ID");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create shipment storage: {:?}", e);
+            false
+        }
+    }
+}
+
+fn handle_user_update(data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    match UserLocalStorage::new(core_storage.clone()) {
+        Ok(user_storage) => {
+            let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+            if !is_deleted {
+                if let Some(name) = data.get("name").and_then(|n| n.as_str())
+                    && name.is_empty()
+                {
+                    println!("Empty user name");
+                    return false;
+                }
+
+                match user_storage.save_user(data) {
+                    Ok(success) => success,
+                    Err(e) => {
+                        println!("Failed to save user: {:?}", e);
+                        false
+                    }
+                }
+            } else {
+                if let Some(id) = data.get("id").and_then(|v| v.as_str()) {
+                    match core_storage.mark_as_deleted("users", id) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            println!("Failed to mark user as deleted: {:?}", e);
+                            false
+                        }
+                    }
+                } else {
+                    println!("Failed to mark user as deleted: Missing ID");
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            println!("Failed to create user storage: {:?}", e);
+            false
+        }
+    }
+}
+
+async fn send_user_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let user_storage = match UserLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create user storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let users = match user_storage.get_user_updates_by_date(date) {
+            Ok(users) => users,
+            Err(e) => {
+                println!("Failed to get user updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if users.is_empty() {
+            should_continue = false;
+        } else {
+            let more = send_sync_page(&client_id, "user_update", &users, tenant, clients).await;
+            for user in &users {
+                if let Some(newest_date) = user["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "user_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_sawmill_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let sawmill_storage = match SawmillLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create sawmill storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let sawmills = match sawmill_storage.get_sawmill_updates_by_date(date) {
+            Ok(sawmills) => sawmills,
+            Err(e) => {
+                println!("Failed to get sawmill updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if sawmills.is_empty() {
+            should_continue = false;
+        } else {
+            let more =
+                send_sync_page(&client_id, "sawmill_update", &sawmills, tenant, clients).await;
+            for sawmill in &sawmills {
+                if let Some(newest_date) = sawmill["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "sawmill_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_contract_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let contract_storage = match ContractLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create contract storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let contracts = match contract_storage.get_contract_updates_by_date(date) {
+            Ok(contracts) => contracts,
+            Err(e) => {
+                println!("Failed to get contract updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if contracts.is_empty() {
+            should_continue = false;
+        } else {
+            let more =
+                send_sync_page(&client_id, "contract_update", &contracts, tenant, clients).await;
+            for contract in &contracts {
+                if let Some(newest_date) = contract["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "contract_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_photo_data(
+    last_sync: i64,
+    chunked: bool,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> Option<i64> {
+    let photo_storage = match PhotoLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create photo storage: {:?}", e);
+            return Some(last_sync);
+        }
+    };
+
+    let user_id = get_client_user_id(&client_id, clients).unwrap_or_default();
+    let mut date = photo_stream::resume_from(tenant, &user_id, last_sync);
+    let mut should_continue = true;
+
+    while should_continue {
+        let photos = match photo_storage.get_photo_updates_by_date(date) {
+            Ok(photos) => photos,
+            Err(e) => {
+                println!("Failed to get photo updates: {:?}", e);
+                return Some(last_sync);
+            }
+        };
+
+        if photos.is_empty() {
+            should_continue = false;
+        } else {
+            for (index, photo) in photos.iter().enumerate() {
+                let messages = chunked
+                    .then(|| photo_chunks::download_messages(photo, tenant))
+                    .flatten()
+                    .unwrap_or_else(|| {
+                        vec![serde_json::json!({
+                            "type": "photo_update",
+                            "data": photo,
+                            "dbName": tenant,
+                            "timestamp": chrono::Utc::now().timestamp_millis()
+                        })]
+                    });
+
+                let mut delivered = true;
+                for message in &messages {
+                    if !deliver_photo(&client_id, message, clients).await {
+                        delivered = false;
+                        break;
+                    }
+                }
+                if !delivered {
+                    let confirmed = photos[index..]
+                        .iter()
+                        .filter_map(|pending| pending["arrivalAtServer"].as_i64())
+                        .map(|arrival| arrival - 1)
+                        .fold(date, i64::min);
+                    photo_stream::record_partial(tenant, &user_id, last_sync, confirmed);
+                    abort_photo_stream(&client_id, clients);
+                    return None;
+                }
+                report_sync_progress(&client_id, "photo_update", 1, tenant, clients).await;
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+                if let Some(newest_date) = photo["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "photo_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    if !send_message(client_id.clone(), &completion_message, clients).await {
+        photo_stream::record_partial(tenant, &user_id, last_sync, date);
+        return None;
+    }
+    photo_stream::clear(tenant, &user_id);
+
+    Some(date)
+}
+
+async fn send_shipment_document_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let document_storage = match ShipmentDocumentLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create shipment document storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    loop {
+        let documents = match document_storage.get_document_updates_by_date(date) {
+            Ok(documents) => documents,
+            Err(e) => {
+                println!("Failed to get shipment document updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if documents.is_empty() {
+            break;
+        }
+
+        let more = send_sync_page(
+            &client_id,
+            "shipment_document_update",
+            &documents,
+            tenant,
+            clients,
+        )
+        .await;
+        for document in &documents {
+            if let Some(newest_date) = document["arrivalAtServer"].as_i64()
+                && date <= newest_date
+            {
+                date = newest_date + 1;
+            }
+        }
+        if !more {
+            break;
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "shipment_document_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_photo_manifest(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let photo_storage = match PhotoLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create photo storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    loop {
+        let entries = match photo_storage.get_photo_manifest_by_date(date) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Failed to get photo manifest: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if entries.is_empty() {
+            break;
+        }
+
+        for entry in &entries {
+            if let Some(newest_date) = entry["arrivalAtServer"].as_i64()
+                && date < newest_date
+            {
+                date = newest_date;
+            }
+        }
+
+        let response = serde_json::json!({
+            "type": "photo_manifest",
+            "data": { "photos": entries },
+            "dbName": tenant,
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        send_message(client_id.clone(), &response, clients).await;
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "photo_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_photo_batch(
+    data: &Value,
+    client_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) {
+    let photo_storage = match PhotoLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create photo storage: {:?}", e);
+            return;
+        }
+    };
+
+    let ids: Vec<&str> = data
+        .get("ids")
+        .and_then(|v| v.as_array())
+        .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut missing = Vec::new();
+    for id in ids.iter().take(MAX_PHOTO_FETCH_BATCH) {
+        let photo = photo_storage
+            .get_photo_metadata(id)
+            .and_then(|metadata| match metadata {
+                Some(_) => photo_storage.get_photo_by_id(id),
+                None => Ok(None),
+            });
+        match photo {
+            Ok(Some(photo)) => {
+                let response = serde_json::json!({
+                    "type": "photo_update",
+                    "data": photo,
+                    "dbName": tenant,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+
+                if !deliver_photo(client_id, &response, clients).await {
+                    metrics::increment("photo_sync_aborted_total");
+                    abort_photo_stream(client_id, clients);
+                    return;
+                }
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            }
+            Ok(None) => missing.push(*id),
+            Err(e) => {
+                println!("Failed to load photo {}: {:?}", id, e);
+                missing.push(*id);
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "photo_fetch_batch_complete",
+        "data": {
+            "ids": ids.iter().take(MAX_PHOTO_FETCH_BATCH).collect::<Vec<_>>(),
+            "missing": missing,
+            "deferred": ids.iter().skip(MAX_PHOTO_FETCH_BATCH).collect::<Vec<_>>()
+        },
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.to_string(), &completion_message, clients).await;
+}
+
+async fn send_note_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let note_storage = match NoteLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create note storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let notes = match note_storage.get_note_updates_by_date(date) {
+            Ok(notes) => notes,
+            Err(e) => {
+                println!("Failed to get note updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if notes.is_empty() {
+            should_continue = false;
+        } else {
+            let more = send_sync_page(&client_id, "note_update", &notes, tenant, clients).await;
+            for note in &notes {
+                if let Some(newest_date) = note["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "note_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_location_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let location_storage = match LocationLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create location storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let locations = match location_storage.get_location_updates_by_date(date) {
+            Ok(locations) => locations,
+            Err(e) => {
+                println!("Failed to get location updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if locations.is_empty() {
+            should_continue = false;
+        } else {
+            let more =
+                send_sync_page(&client_id, "location_update", &locations, tenant, clients).await;
+            for location in &locations {
+                if let Some(newest_date) = location["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "location_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_shipment_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let shipment_storage = match ShipmentLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create shipment storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let shipments = match shipment_storage.get_shipments_by_date(date) {
+            Ok(shipments) => shipments,
+            Err(e) => {
+                println!("Failed to get shipment updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if shipments.is_empty() {
+            should_continue = false;
+        } else {
+            let more =
+                send_sync_page(&client_id, "shipment_update", &shipments, tenant, clients).await;
+            for shipment in &shipments {
+                if let Some(newest_date) = shipment["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "shipment_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_review_item_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let review_item_storage = match ReviewItemLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create review item storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let review_items = match review_item_storage.get_review_item_updates_by_date(date) {
+            Ok(review_items) => review_items,
+            Err(e) => {
+                println!("Failed to get review item updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if review_items.is_empty() {
+            should_continue = false;
+        } else {
+            let more = send_sync_page(
+                &client_id,
+                "review_item_update",
+                &review_items,
+                tenant,
+                clients,
+            )
+            .await;
+            for review_item in &review_items {
+                if let Some(newest_date) = review_item["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "review_item_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_tour_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let tour_storage = match TourLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create tour storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let tours = match tour_storage.get_tour_updates_by_date(date) {
+            Ok(tours) => tours,
+            Err(e) => {
+                println!("Failed to get tour updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if tours.is_empty() {
+            should_continue = false;
+        } else {
+            let more = send_sync_page(&client_id, "tour_update", &tours, tenant, clients).await;
+            for tour in &tours {
+                if let Some(newest_date) = tour["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "tour_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_activity_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let activity_storage = match ActivityFeedLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create activity feed storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let entries = match activity_storage.get_activity_updates_by_date(date) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Failed to get activity updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if entries.is_empty() {
+            should_continue = false;
+        } else {
+            let more =
+                send_sync_page(&client_id, "activity_update", &entries, tenant, clients).await;
+            for entry in &entries {
+                if let Some(newest_date) = entry["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "activity_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_crew_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let crew_storage = match CrewLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create crew storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    let mut should_continue = true;
+
+    while should_continue {
+        let crews = match crew_storage.get_crew_updates_by_date(date) {
+            Ok(crews) => crews,
+            Err(e) => {
+                println!("Failed to get crew updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if crews.is_empty() {
+            should_continue = false;
+        } else {
+            let more = send_sync_page(&client_id, "crew_update", &crews, tenant, clients).await;
+            for crew in &crews {
+                if let Some(newest_date) = crew["arrivalAtServer"].as_i64()
+                    && date <= newest_date
+                {
+                    date = newest_date + 1;
+                }
+            }
+            if !more {
+                should_continue = false;
+            }
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "crew_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_sawmill_price_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let price_storage = match SawmillPriceLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create sawmill price storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    loop {
+        let prices = match price_storage.get_price_updates_by_date(date) {
+            Ok(prices) => prices,
+            Err(e) => {
+                println!("Failed to get sawmill price updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if prices.is_empty() {
+            break;
+        }
+
+        let more =
+            send_sync_page(&client_id, "sawmill_price_update", &prices, tenant, clients).await;
+        for price in &prices {
+            if let Some(newest_date) = price["arrivalAtServer"].as_i64()
+                && date <= newest_date
+            {
+                date = newest_date + 1;
+            }
+        }
+        if !more {
+            break;
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "sawmill_price_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_quota_reservation_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let reservation_storage = match QuotaReservationLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create quota reservation storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    loop {
+        let reservations = match reservation_storage.get_reservation_updates_by_date(date) {
+            Ok(reservations) => reservations,
+            Err(e) => {
+                println!("Failed to get quota reservation updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if reservations.is_empty() {
+            break;
+        }
+
+        let more = send_sync_page(
+            &client_id,
+            "quota_reservation_update",
+            &reservations,
+            tenant,
+            clients,
+        )
+        .await;
+        for reservation in &reservations {
+            if let Some(newest_date) = reservation["arrivalAtServer"].as_i64()
+                && date <= newest_date
+            {
+                date = newest_date + 1;
+            }
+        }
+        if !more {
+            break;
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "quota_reservation_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+async fn send_assortment_data(
+    last_sync: i64,
+    client_id: String,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) -> i64 {
+    let assortment_storage = match AssortmentLocalStorage::new(core_storage) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Failed to create assortment storage: {:?}", e);
+            return last_sync;
+        }
+    };
+
+    let mut date = last_sync;
+    loop {
+        let assortments = match assortment_storage.get_assortment_updates_by_date(date) {
+            Ok(assortments) => assortments,
+            Err(e) => {
+                println!("Failed to get assortment updates: {:?}", e);
+                return last_sync;
+            }
+        };
+
+        if assortments.is_empty() {
+            break;
+        }
+
+        let more = send_sync_page(
+            &client_id,
+            "assortment_update",
+            &assortments,
+            tenant,
+            clients,
+        )
+        .await;
+        for assortment in &assortments {
+            if let Some(newest_date) = assortment["arrivalAtServer"].as_i64()
+                && date <= newest_date
+            {
+                date = newest_date + 1;
+            }
+        }
+        if !more {
+            break;
+        }
+    }
+
+    let completion_message = serde_json::json!({
+        "type": "assortment_update",
+        "data": serde_json::json!({
+            "newSyncDate": date,
+        }),
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.clone(), &completion_message, clients).await;
+
+    date
+}
+
+#[tracing::instrument(
+    name = "ws.sync_request",
+    skip_all,
+    fields(tenant = tracing::field::Empty, user.id = tracing::field::Empty)
+)]
+async fn handle_sync_request(
+    data: &Value,
+    client_id: String,
+    clients: &Clients,
+    resume: bool,
+) -> bool {
+    let (db_path, tenant) = match get_client_db_path_and_tenant(&client_id, clients) {
+        Some((path, tenant)) => (path, tenant),
+        None => {
+            println!("No database associated with client {}", client_id);
+            return false;
+        }
+    };
+    tracing::Span::current()
+        .record("tenant", tenant.as_str())
+        .record(
+            "user.id",
+            get_client_user_id(&client_id, clients).unwrap_or_default(),
+        );
+
+    let mut cursors = match SyncCursors::parse(data) {
+        Ok(cursors) => cursors,
+        Err(message) => {
+            send_error(
+                client_id,
+                "sync_request",
+                None,
+                "VALIDATION_FAILED",
+                &message,
+                clients,
+            )
+            .await;
+            return false;
+        }
+    };
+
+    let core_storage = match CoreLocalStorage::new(&db_path) {
+        Ok(storage) => Arc::new(storage),
+        Err(e) => {
+            println!("Failed to create core storage: {:?}", e);
+            return false;
+        }
+    };
+
+    let user_id = get_client_user_id(&client_id, clients).unwrap_or_default();
+    let device_id = get_client_device_id(&client_id, clients).unwrap_or_default();
+    let mut session = SyncSession::load(core_storage.clone(), &user_id);
+
+    if resume {
+        let checkpoints =
+            sync_sessions::load_checkpoints(core_storage.clone(), &user_id, &device_id);
+        let response = json!({
+            "type": "sync_resumed",
+            "data": { "entities": cursors.advance(&checkpoints) },
+            "dbName": tenant,
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        send_message(client_id.clone(), &response, clients).await;
+    }
+
+    let last_user_sync = session.cursor("user_update", cursors.since("user_update"));
+
+    let last_sawmill_sync = session.cursor("sawmill_update", cursors.since("sawmill_update"));
+
+    let last_contract_sync = session.cursor("contract_update", cursors.since("contract_update"));
+
+    let last_note_sync = session.cursor("note_update", cursors.since("note_update"));
+
+    let last_location_sync = session.cursor("location_update", cursors.since("location_update"));
+
+    let last_shipment_sync = session.cursor("shipment_update", cursors.since("shipment_update"));
+
+    let last_photo_sync = session.cursor("photo_update", cursors.since("photo_update"));
+
+    let last_review_item_sync =
+        session.cursor("review_item_update", cursors.since("review_item_update"));
+
+    let last_crew_sync = session.cursor("crew_update", cursors.since("crew_update"));
+
+    let last_tour_sync = session.cursor("tour_update", cursors.since("tour_update"));
+
+    let last_activity_sync = session.cursor("activity_update", cursors.since("activity_update"));
+
+    let last_shipment_document_sync = session.cursor(
+        "shipment_document_update",
+        cursors.since("shipment_document_update"),
+    );
+
+    let last_sawmill_price_sync = session.cursor(
+        "sawmill_price_update",
+        cursors.since("sawmill_price_update"),
+    );
+
+    let last_quota_reservation_sync = session.cursor(
+        "quota_reservation_update",
+        cursors.since("quota_reservation_update"),
+    );
+
+    let last_assortment_sync =
+        session.cursor("assortment_update", cursors.since("assortment_update"));
+
+    let requested_batch_size = data
+        .get("batchSize")
+        .and_then(|v| v.as_u64())
+        .filter(|size| *size > 0);
+    let sync_batch_size = requested_batch_size
+        .filter(|_| config::get().sync_batch.enabled)
+        .map(|size| size.min(config::get().sync_batch.max_batch_size as u64) as usize)
+        .map(|size| size.min(SYNC_PAGE_SIZE));
+    if let Ok(mut clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get_mut(&client_id)
+    {
+        client.sync_batch_size = sync_batch_size;
+        client.sync_budgets = cursors.limits();
+        client.sync_checkpoints.clear();
+    }
+    if requested_batch_size.is_some() {
+        let response = json!({
+            "type": "sync_batch_mode",
+            "data": {
+                "batchSize": sync_batch_size,
+                "typeBatchSizes": type_batch_sizes(sync_batch_size)
+            },
+            "dbName": tenant,
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        send_message(client_id.clone(), &response, clients).await;
+    }
+
+    if let Some(resume) = session.start(&client_id) {
+        let response = json!({
+            "type": "sync_resume",
+            "data": resume,
+            "dbName": tenant,
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        send_message(client_id.clone(), &response, clients).await;
+    }
+
+    if config::get().sync_progress.enabled {
+        let photo_manifest = data.get("photoManifest").and_then(|v| v.as_bool()) == Some(true);
+        let mut pending = vec![
+            ("user_update", last_user_sync),
+            ("assortment_update", last_assortment_sync),
+            ("sawmill_update", last_sawmill_sync),
+            ("crew_update", last_crew_sync),
+            ("tour_update", last_tour_sync),
+            ("contract_update", last_contract_sync),
+            ("location_update", last_location_sync),
+            ("shipment_update", last_shipment_sync),
+            ("shipment_document_update", last_shipment_document_sync),
+            ("note_update", last_note_sync),
+            ("activity_update", last_activity_sync),
+        ];
+        if !photo_manifest {
+            pending.push(("photo_update", last_photo_sync));
+        }
+        if get_client_role(&client_id, clients) >= ROLE_PRIVILEGED {
+            pending.push(("review_item_update", last_review_item_sync));
+            pending.push(("sawmill_price_update", last_sawmill_price_sync));
+            pending.push(("quota_reservation_update", last_quota_reservation_sync));
+        }
+        pending.retain(|(msg_type, _)| cursors.includes(msg_type));
+
+        let mut progress = SyncProgress::count(&core_storage, &pending);
+        if !progress.is_empty() {
+            let response = json!({
+                "type": "sync_progress",
+                "data": progress.report(),
+                "dbName": tenant,
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+            send_message(client_id.clone(), &response, clients).await;
+        }
+        if let Ok(mut clients_lock) = clients.lock()
+            && let Some(client) = clients_lock.get_mut(&client_id)
+        {
+            client.sync_progress = progress;
+        }
+    }
+
+    if cursors.includes("user_update") {
+        let reached = send_user_data(
+            last_user_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("user_update", reached);
+        }
+    }
+
+    if cursors.includes("assortment_update") {
+        let reached = send_assortment_data(
+            last_assortment_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("assortment_update", reached);
+        }
+    }
+
+    if cursors.includes("sawmill_update") {
+        let reached = send_sawmill_data(
+            last_sawmill_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("sawmill_update", reached);
+        }
+    }
+
+    if cursors.includes("crew_update") {
+        let reached = send_crew_data(
+            last_crew_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("crew_update", reached);
+        }
+    }
+
+    if cursors.includes("tour_update") {
+        let reached = send_tour_data(
+            last_tour_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("tour_update", reached);
+        }
+    }
+
+    if cursors.includes("contract_update") {
+        let reached = send_contract_data(
+            last_contract_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("contract_update", reached);
+        }
+    }
+
+    if cursors.includes("location_update") {
+        let reached = send_location_data(
+            last_location_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("location_update", reached);
+        }
+    }
+
+    if cursors.includes("shipment_update") {
+        let reached = send_shipment_data(
+            last_shipment_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("shipment_update", reached);
+        }
+    }
+
+    if cursors.includes("shipment_document_update") {
+        let reached = send_shipment_document_data(
+            last_shipment_document_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("shipment_document_update", reached);
+        }
+    }
+
+    if cursors.includes("note_update") {
+        let reached = send_note_data(
+            last_note_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("note_update", reached);
+        }
+    }
+
+    if cursors.includes("photo_update") {
+        if data.get("photoManifest").and_then(|v| v.as_bool()) == Some(true) {
+            send_photo_manifest(
+                last_photo_sync,
+                client_id.clone(),
+                core_storage.clone(),
+                &tenant,
+                clients,
+            )
+            .await;
+        } else {
+            let Some(reached) = send_photo_data(
+                last_photo_sync,
+                data.get("photoChunks").and_then(|v| v.as_bool()) == Some(true),
+                client_id.clone(),
+                core_storage.clone(),
+                &tenant,
+                clients,
+            )
+            .await
+            else {
+                return false;
+            };
+            if is_client_connected(&client_id, clients) {
+                session.record("photo_update", reached);
+            }
+        }
+    }
+
+    if cursors.includes("activity_update") {
+        let reached = send_activity_data(
+            last_activity_sync,
+            client_id.clone(),
+            core_storage.clone(),
+            &tenant,
+            clients,
+        )
+        .await;
+        if is_client_connected(&client_id, clients) {
+            session.record("activity_update", reached);
+        }
+    }
+
+    if get_client_role(&client_id, clients) >= ROLE_PRIVILEGED {
+        if cursors.includes("review_item_update") {
+            let reached = send_review_item_data(
+                last_review_item_sync,
+                client_id.clone(),
+                core_storage.clone(),
+                &tenant,
+                clients,
+            )
+            .await;
+            if is_client_connected(&client_id, clients) {
+                session.record("review_item_update", reached);
+            }
+        }
+
+        if cursors.includes("sawmill_price_update") {
+            let reached = send_sawmill_price_data(
+                last_sawmill_price_sync,
+                client_id.clone(),
+                core_storage.clone(),
+                &tenant,
+                clients,
+            )
+            .await;
+            if is_client_connected(&client_id, clients) {
+                session.record("sawmill_price_update", reached);
+            }
+        }
+
+        if cursors.includes("quota_reservation_update") {
+            let reached = send_quota_reservation_data(
+                last_quota_reservation_sync,
+                client_id.clone(),
+                core_storage.clone(),
+                &tenant,
+                clients,
+            )
+            .await;
+            if is_client_connected(&client_id, clients) {
+                session.record("quota_reservation_update", reached);
+            }
+        }
+    }
+
+    session.complete();
+    if is_client_connected(&client_id, clients) {
+        if let Ok(mut clients_lock) = clients.lock()
+            && let Some(client) = clients_lock.get_mut(&client_id)
+        {
+            client.sync_checkpoints.clear();
+        }
+        sync_sessions::clear_checkpoints(core_storage, &user_id, &device_id);
+    }
+    true
+}
+
+async fn handle_photo_upload_message(
+    msg_type: &str,
+    data: &Value,
+    client_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) {
+    let result = match msg_type {
+        "photo_upload_start" => photo_chunks::start_upload(tenant, client_id, data, &core_storage)
+            .map(|reply| ("photo_upload_ready", reply)),
+        "photo_upload_chunk" => photo_chunks::append_chunk(client_id, data)
+            .map(|reply| ("photo_upload_chunk_ack", reply)),
+        _ => photo_chunks::complete_upload(client_id, data).map(|photo| ("photo_update", photo)),
+    };
+
+    match result {
+        Ok(("photo_update", photo)) => {
+            let Some(saved) = run_update(
+                "photo_update",
+                client_id,
+                &photo,
+                core_storage,
+                clients,
+                handle_photo_update,
+            )
+            .await
+            else {
+                return;
+            };
+            let Some(saved) = saved else {
+                send_error(
+                    client_id.to_string(),
+                    msg_type,
+                    photo.get("id"),
+                    "PHOTO_UPLOAD_FAILED",
+                    "The photo could not be saved",
+                    clients,
+                )
+                .await;
+                return;
+            };
+
+            let response = json!({
+                "type": "photo_upload_done",
+                "data": { "uploadId": data["uploadId"], "id": saved["id"] },
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+            send_message(client_id.to_string(), &response, clients).await;
+            let message = json!({
+                "type": "photo_update",
+                "data": saved,
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+            broadcast_message(client_id.to_string(), &message, clients).await;
+        }
+        Ok((reply_type, reply)) => {
+            let response = json!({
+                "type": reply_type,
+                "data": reply,
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+            send_message(client_id.to_string(), &response, clients).await;
+        }
+        Err((code, message)) => {
+            println!("Rejected {}: {}", msg_type, message);
+            let ref_id = data.get("uploadId").or_else(|| data.get("id"));
+            send_error(
+                client_id.to_string(),
+                msg_type,
+                ref_id,
+                code,
+                &message,
+                clients,
+            )
+            .await;
+        }
+    }
+}
+
+async fn handle_shipment_document_message(
+    msg_type: &str,
+    data: &Value,
+    client_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) {
+    let user_id = get_client_user_id(client_id, clients).unwrap_or_default();
+    let role = get_client_role(client_id, clients);
+
+    let result = match msg_type {
+        "shipment_document_upload_start" => {
+            shipment_documents::start_upload(tenant, client_id, &user_id, role, data, &core_storage)
+                .map(|reply| ("shipment_document_upload_ready", reply))
+        }
+        "shipment_document_chunk" => shipment_documents::append_chunk(client_id, data)
+            .map(|reply| ("shipment_document_chunk_ack", reply)),
+        "shipment_document_upload_complete" => {
+            shipment_documents::complete_upload(client_id, data, core_storage)
+                .map(|document| ("shipment_document_update", document))
+        }
+        _ => shipment_documents::delete_document(&user_id, role, data, core_storage)
+            .map(|document| ("shipment_document_update", document)),
+    };
+
+    match result {
+        Ok(("shipment_document_update", document)) => {
+            println!(
+                "Shipment document for {} updated by {}",
+                document["shipmentId"].as_str().unwrap_or_default(),
+                user_id
+            );
+            broadcast_to_tenant(tenant, "shipment_document_update", &document, 0, clients).await;
+        }
+        Ok((reply_type, reply)) => {
+            let response = json!({
+                "type": reply_type,
+                "data": reply,
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+            send_message(client_id.to_string(), &response, clients).await;
+        }
+        Err((code, message)) => {
+            println!("Rejected {}: {}", msg_type, message);
+            let ref_id = data.get("uploadId").or_else(|| data.get("shipmentId"));
+            send_error(
+                client_id.to_string(),
+                msg_type,
+                ref_id,
+                code,
+                &message,
+                clients,
+            )
+            .await;
+        }
+    }
+}
+
+fn delta_base(msg_type: &str, data: &Value, core_storage: Arc<CoreLocalStorage>) -> Option<Value> {
+    if !config::get().broadcast.delta_updates {
+        return None;
+    }
+    let id = data.get("id")?.as_str()?;
+    delta::base_version(core_storage, msg_type, id).unwrap_or_else(|e| {
+        println!("Failed to load delta base of {}: {:?}", id, e);
+        None
+    })
+}
+
+async fn send_delta_base(
+    data: &Value,
+    client_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) {
+    let msg_type = data
+        .get("type")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let id = data.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+    if !delta::DELTA_TYPES.contains(&msg_type) {
+        send_error(
+            client_id.to_string(),
+            "delta_base_missing",
+            data.get("id"),
+            "VALIDATION_FAILED",
+            "Deltas are only sent for location_update and contract_update",
+            clients,
+        )
+        .await;
+        return;
+    }
+
+    let entity = match delta::base_version(core_storage, msg_type, id) {
+        Ok(Some(entity)) => entity,
+        Ok(None) => {
+            send_error(
+                client_id.to_string(),
+                "delta_base_missing",
+                data.get("id"),
+                "NOT_FOUND",
+                "Entity not found",
+                clients,
+            )
+            .await;
+            return;
+        }
+        Err(e) => {
+            println!("Failed to load {} {}: {:?}", msg_type, id, e);
+            send_error(
+                client_id.to_string(),
+                "delta_base_missing",
+                data.get("id"),
+                "DELTA_BASE_FAILED",
+                "Failed to load entity",
+                clients,
+            )
+            .await;
+            return;
+        }
+    };
+
+    metrics::increment("delta_base_requests_total");
+    let response = json!({
+        "type": msg_type,
+        "data": entity,
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+    send_message(client_id.to_string(), &response, clients).await;
+}
+
+async fn send_archived_location(
+    data: &Value,
+    client_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    tenant: &str,
+    clients: &Clients,
+) {
+    let location_id = data
+        .get("locationId")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+
+    let location = ArchiveLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_archived_location(location_id));
+    let location = match location {
+        Ok(Some(location)) => location,
+        Ok(None) => {
+            send_error(
+                client_id.to_string(),
+                "archive_fetch",
+                data.get("locationId"),
+                "NOT_FOUND",
+                "Location is not archived",
+                clients,
+            )
+            .await;
+            return;
+        }
+        Err(e) => {
+            println!("Failed to load archived location: {:?}", e);
+            send_error(
+                client_id.to_string(),
+                "archive_fetch",
+                data.get("locationId"),
+                "ARCHIVE_FETCH_FAILED",
+                "Failed to load archived location",
+                clients,
+            )
+            .await;
+            return;
+        }
+    };
+
+    let response = serde_json::json!({
+        "type": "archive_fetch_response",
+        "data": location,
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    send_message(client_id.to_string(), &response, clients).await;
+}
+
+/// Sends `msg` to one client. Inside a write batch it is sent once the
+/// batch is committed and counts as delivered.
+async fn send_message(client_id: String, msg: &Value, clients: &Clients) -> bool {
+    if write_batch::is_active() {
+        let (msg, clients) = (msg.clone(), clients.clone());
+        write_batch::defer(async move {
+            deliver_message(&client_id, &msg, &clients);
+        });
+        return true;
+    }
+
+    deliver_message(&client_id, msg, clients)
+}
+
+fn deliver_message(client_id: &str, msg: &Value, clients: &Clients) -> bool {
+    match clients.lock() {
+        Ok(clients_lock) => {
+            if let Some(client) = clients_lock.get(client_id) {
+                if let Some(msg_type) = msg.get("type").and_then(|v| v.as_str())
+                    && let Some(data) = msg.get("data")
+                {
+                    log_outgoing_message(msg_type, client_id, data);
+                }
+                if let Err(e) = client.sender.send(wire_format::frame(msg, &client.msgpack)) {
+                    println!("Error sending message to client {}: {:?}", client_id, e);
+                    return false;
+                }
+                true
+            } else {
+                println!("Client {} not found", client_id);
+                false
+            }
+        }
+        Err(e) => {
+            println!("Failed to lock clients: {:?}", e);
+            false
+        }
+    }
+}
+
+/// Rows per page of the `*_by_date` sync queries. A batch never spans two
+/// pages, so the negotiated batch size is clamped to it.
+const SYNC_PAGE_SIZE: usize = 100;
+
+/// Entity types whose sync pages are smaller than `SYNC_PAGE_SIZE`.
+const SMALL_SYNC_PAGES: &[(&str, usize)] = &[(
+    "shipment_document_update",
+    shipment_document_local_storage::SYNC_PAGE_SIZE,
+)];
+
+/// The entity types whose batches stay below the negotiated size because
+/// their pages are smaller, with the size they are actually sent in.
+fn type_batch_sizes(batch_size: Option<usize>) -> Value {
+    let Some(batch_size) = batch_size else {
+        return json!({});
+    };
+
+    SMALL_SYNC_PAGES
+        .iter()
+        .filter(|(_, page_size)| *page_size < batch_size)
+        .map(|(msg_type, page_size)| (msg_type.to_string(), json!(page_size)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Sends one page of sync entities, either one message per entity or, if the
+/// client negotiated a batch size at sync start, as `<msg_type>_batch`
+/// messages carrying up to that many entities. Returns false once the page
+/// used up the limit the client set for this entity type. Once the whole page
+/// is handed to the connection, its newest date becomes the entity's
+/// checkpoint for sync_resume.
+async fn send_sync_page(
+    client_id: &str,
+    msg_type: &str,
+    entities: &[Value],
+    tenant: &str,
+    clients: &Clients,
+) -> bool {
+    let (batch_size, more) = match clients.lock() {
+        Ok(mut clients_lock) => match clients_lock.get_mut(client_id) {
+            Some(client) => {
+                let more = match client.sync_budgets.get_mut(msg_type) {
+                    Some(budget) => {
+                        *budget = budget.saturating_sub(entities.len());
+                        *budget > 0
+                    }
+                    None => true,
+                };
+                (client.sync_batch_size, more)
+            }
+            None => (None, true),
+        },
+        Err(_) => (None, true),
+    };
+
+    let mut delivered = true;
+    match batch_size {
+        None => {
+            for entity in entities {
+                let response = json!({
+                    "type": msg_type,
+                    "data": entity,
+                    "dbName": tenant,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+                delivered &= send_message(client_id.to_string(), &response, clients).await;
+            }
+        }
+        Some(batch_size) => {
+            for batch in entities.chunks(batch_size) {
+                let response = json!({
+                    "type": format!("{}_batch", msg_type),
+                    "data": batch,
+                    "dbName": tenant,
+                    "timestamp": chrono::Utc::now().timestamp_millis()
+                });
+                delivered &= send_message(client_id.to_string(), &response, clients).await;
+            }
+            metrics::add("sync_batched_entities_total", entities.len() as u64);
+        }
+    }
+
+    let reached = entities
+        .iter()
+        .filter_map(|entity| entity["arrivalAtServer"].as_i64())
+        .max();
+    if delivered
+        && let Some(reached) = reached
+        && let Ok(mut clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get_mut(client_id)
+    {
+        client
+            .sync_checkpoints
+            .insert(msg_type.to_string(), reached + 1);
+    }
+    report_sync_progress(client_id, msg_type, entities.len(), tenant, clients).await;
+
+    more
+}
+
+async fn report_sync_progress(
+    client_id: &str,
+    msg_type: &str,
+    sent: usize,
+    tenant: &str,
+    clients: &Clients,
+) {
+    let progress = clients.lock().ok().and_then(|mut clients_lock| {
+        clients_lock
+            .get_mut(client_id)?
+            .sync_progress
+            .advance(msg_type, sent)
+    });
+    if let Some(progress) = progress {
+        let response = json!({
+            "type": "sync_progress",
+            "data": progress,
+            "dbName": tenant,
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        send_message(client_id.to_string(), &response, clients).await;
+    }
+}
+
+async fn deliver_photo(client_id: &str, msg: &Value, clients: &Clients) -> bool {
+    let stream = match clients.lock() {
+        Ok(clients_lock) => clients_lock
+            .get(client_id)
+            .map(|client| (client.sender.clone(), client.queue_depth.clone())),
+        Err(_) => None,
+    };
+    let Some((sender, queue_depth)) = stream else {
+        return false;
+    };
+
+    send_message(client_id.to_string(), msg, clients).await
+        && photo_stream::await_delivery(&sender, &queue_depth).await
+}
+
+fn abort_photo_stream(client_id: &str, clients: &Clients) {
+    println!(
+        "Photo stream to client {} stalled, aborting sync",
+        client_id
+    );
+    if let Ok(clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get(client_id)
+    {
+        client.disconnect.notify_one();
+    }
+}
+
+async fn send_pong(client_id: String, ping: &Value, clients: &Clients) {
+    let now = chrono::Utc::now().timestamp_millis();
+    let stats = match clients.lock() {
+        Ok(clients_lock) => clients_lock.get(&client_id).map(|client| {
+            json!({
+                "queueDepth": client.queue_depth.load(Ordering::Relaxed),
+                "pendingBroadcasts": client.pending_broadcasts.load(Ordering::Relaxed),
+                "batching": client.batch_sender.is_some(),
+                "syncCompleted": client.sync_completed
+            })
+        }),
+        Err(e) => {
+            eprintln!("Failed to lock clients: {:?}", e);
+            None
+        }
+    };
+
+    let mut data = stats.unwrap_or_else(|| json!({}));
+    data["serverTime"] = json!(now);
+    if let Some(ping_timestamp) = ping.get("timestamp").and_then(|v| v.as_i64()) {
+        data["pingTimestamp"] = json!(ping_timestamp);
+    }
+
+    let response = json!({
+        "type": "pong",
+        "data": data,
+        "timestamp": now
+    });
+    send_message(client_id, &response, clients).await;
+}
+
+async fn run_update<T: Send + 'static>(
+    msg_type: &str,
+    client_id: &str,
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+    clients: &Clients,
+    handler: fn(&Value, Arc<CoreLocalStorage>) -> T,
+) -> Option<T> {
+    let ref_id = data.get("id").or_else(|| data.get("key"));
+    let operation = format!("{} {}", msg_type, ref_id.cloned().unwrap_or(Value::Null));
+    let entity = data.clone();
+    let verified_type = msg_type.to_string();
+    let user_id = get_client_user_id(client_id, clients);
+
+    let outcome = watchdog::run(&operation, move || {
+        let result = handler(&entity, core_storage.clone());
+        let failure =
+            write_verification::verify(&verified_type, &entity, core_storage, user_id.as_deref());
+        (result, failure)
+    })
+    .await;
+    match outcome {
+        Ok((result, failure)) => {
+            if let Some(failure) = failure {
+                broadcast_server_update(
+                    client_id,
+                    "write_verification_alert",
+                    &failure,
+                    ROLE_ADMIN,
+                    clients,
+                )
+                .await;
+            }
+            Some(result)
+        }
+        Err(code) => {
+            let message = match code {
+                "TIMEOUT" => "Processing the update timed out",
+                _ => "Processing the update failed",
+            };
+            send_error(
+                client_id.to_string(),
+                msg_type,
+                ref_id,
+                code,
+                message,
+                clients,
+            )
+            .await;
+            None
+        }
+    }
+}
+
+async fn send_error(
+    client_id: String,
+    ref_type: &str,
+    ref_id: Option<&Value>,
+    code: &str,
+    message: &str,
+    clients: &Clients,
+) {
+    send_error_with_details(client_id, ref_type, ref_id, code, message, None, clients).await;
+}
+
+async fn send_error_with_details(
+    client_id: String,
+    ref_type: &str,
+    ref_id: Option<&Value>,
+    code: &str,
+    message: &str,
+    errors: Option<Value>,
+    clients: &Clients,
+) {
+    write_batch::mark_failed();
+    if let Some((_, tenant)) = get_client_db_path_and_tenant(&client_id, clients) {
+        sla_stats::record_error(&tenant, code);
+    }
+    let mut response = json!({
+        "type": "error",
+        "data": {
+            "code": code,
+            "message": message,
+            "refType": ref_type,
+            "id": ref_id.cloned().unwrap_or(Value::Null)
+        },
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+    if let Some(errors) = errors {
+        response["data"]["errors"] = errors;
+    }
+    send_message(client_id, &response, clients).await;
+}
+
+async fn broadcast_server_update(
+    client_id: &str,
+    msg_type: &str,
+    data: &Value,
+    min_role: i64,
+    clients: &Clients,
+) {
+    match get_client_db_path_and_tenant(client_id, clients) {
+        Some((_, tenant)) => broadcast_to_tenant(&tenant, msg_type, data, min_role, clients).await,
+        None => println!("Sender client {} not found", client_id),
+    }
+}
+
+/// Sends a server-side update to the tenant's clients and publishes it.
+/// Inside a write batch this happens once the batch is committed.
+async fn broadcast_to_tenant(
+    tenant: &str,
+    msg_type: &str,
+    data: &Value,
+    min_role: i64,
+    clients: &Clients,
+) {
+    if write_batch::is_active() {
+        let (tenant, msg_type) = (tenant.to_string(), msg_type.to_string());
+        let (data, clients) = (data.clone(), clients.clone());
+        write_batch::defer(async move {
+            publish_to_tenant(&tenant, &msg_type, &data, min_role, &clients).await;
+        });
+        return;
+    }
+
+    publish_to_tenant(tenant, msg_type, data, min_role, clients).await;
+}
+
+async fn publish_to_tenant(
+    tenant: &str,
+    msg_type: &str,
+    data: &Value,
+    min_role: i64,
+    clients: &Clients,
+) {
+    let msg = json!({
+        "type": msg_type,
+        "data": data,
+        "dbName": tenant,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+    send_to_tenant_role(tenant, min_role, &msg, clients).await;
+    event_bus::publish(BusEvent::UpdateAccepted {
+        tenant: tenant.to_string(),
+        msg_type: msg_type.to_string(),
+        data: data.clone(),
+        min_role,
+        provenance: Provenance::default(),
+    })
+    .await;
+}
+
+async fn send_to_tenant_users(tenant: &str, user_ids: &[String], msg: &Value, clients: &Clients) {
+    let msg = wire_format::Shared::new(msg.clone());
+    let (tenant, user_ids) = (tenant.to_string(), user_ids.to_vec());
+    send_to_clients(msg, clients, move |client| {
+        client.db_name == tenant && user_ids.contains(&client.user_id)
+    });
+}
+
+async fn send_to_tenant_role(tenant: &str, min_role: i64, msg: &Value, clients: &Clients) {
+    let msg = wire_format::Shared::new(msg.clone());
+    let tenant = tenant.to_string();
+    send_to_clients(msg, clients, move |client| {
+        client.db_name == tenant && client.role >= min_role
+    });
+}
+
+/// Sends `msg` to every client `include` accepts, once the write batch is
+/// committed if one is active.
+fn send_to_clients<F>(msg: Arc<wire_format::Shared>, clients: &Clients, include: F)
+where
+    F: Fn(&Client) -> bool + Send + 'static,
+{
+    let send = {
+        let clients = clients.clone();
+        move || match clients.lock() {
+            Ok(clients_lock) => {
+                for (id, client) in clients_lock.iter() {
+                    if include(client) {
+                        broadcast_health::send(id, client, &msg);
+                    }
+                }
+            }
+            Err(e) => {
+                println!("Failed to lock clients: {:?}", e);
+            }
+        }
+    };
+
+    if write_batch::is_active() {
+        write_batch::defer(async move { send() });
+    } else {
+        send();
+    }
+}
+
+async fn broadcast_message(client_id: String, msg: &Value, clients: &Clients) {
+    broadcast_update(client_id, msg, None, clients).await;
+}
+
+/// Broadcasts an accepted update. Synced clients that opted into delta sync
+/// get `delta` as a `<type>_delta` message instead of the whole entity.
+/// Inside a write batch nothing is sent before the batch is committed.
+async fn broadcast_update(client_id: String, msg: &Value, delta: Option<Value>, clients: &Clients) {
+    if write_batch::is_active() {
+        let (msg, clients) = (msg.clone(), clients.clone());
+        write_batch::defer(async move {
+            publish_update(client_id, &msg, delta, &clients).await;
+        });
+        return;
+    }
+
+    publish_update(client_id, msg, delta, clients).await;
+}
+
+async fn publish_update(client_id: String, msg: &Value, delta: Option<Value>, clients: &Clients) {
+    let mut json_msg = msg.clone();
+    let (sender_db_name, provenance) = match clients.lock() {
+        Ok(clients_lock) => {
+            let (sender_db_name, provenance) =
+                if let Some(sender_client) = clients_lock.get(&client_id) {
+                    (
+                        sender_client.db_name.clone(),
+                        Provenance {
+                            user_id: Some(sender_client.user_id.clone()),
+                            device_id: sender_client.device_id.clone(),
+                            app_version: sender_client.client_version.clone(),
+                        },
+                    )
+                } else {
+                    println!("Sender client {} not found", client_id);
+                    return;
+                };
+
+            if !json_msg.as_object().unwrap().contains_key("dbName") {
+                json_msg["dbName"] = json!(sender_db_name);
+            }
+            if config::get().broadcast.include_provenance {
+                json_msg["provenance"] = provenance.to_json();
+            }
+            let enhanced_msg = wire_format::Shared::new(json_msg.clone());
+            let delta_msg = delta.map(|delta| {
+                let mut delta_msg = json!({
+                    "type": delta::delta_type(json_msg["type"].as_str().unwrap_or_default()),
+                    "data": delta,
+                    "dbName": json_msg["dbName"]
+                });
+                if config::get().broadcast.include_provenance {
+                    delta_msg["provenance"] = provenance.to_json();
+                }
+                wire_format::Shared::new(delta_msg)
+            });
+
+            for (id, client) in clients_lock.iter() {
+                if client.db_name.is_empty() || client.db_name != sender_db_name {
+                    continue;
+                }
+
+                if id != &client_id {
+                    match &delta_msg {
+                        Some(delta_msg) if client.delta_sync && client.sync_completed => {
+                            metrics::increment("delta_updates_sent_total");
+                            broadcast_health::send(id, client, delta_msg);
+                        }
+                        _ => broadcast_health::send(id, client, &enhanced_msg),
+                    }
+                } else {
+                    let is_deleted = json_msg
+                        .get("data")
+                        .and_then(|data| data.get("deleted"))
+                        .and_then(|deleted| deleted.as_i64())
+                        .unwrap_or(0)
+                        == 1;
+
+                    if is_deleted {
+                        if let Err(e) = client.sender.send(enhanced_msg.frame(&client.msgpack)) {
+                            println!(
+                                "Error sending delete confirmation to client {}: {:?}",
+                                id, e
+                            );
+                        }
+                    } else {
+                        let msg_type = json_msg
+                            .get("type")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("unknown");
+
+                        let entity_id = json_msg
+                            .get("data")
+                            .and_then(|data| data.get("id"))
+                            .cloned()
+                            .unwrap_or(json!("unknown"));
+
+                        let confirm_msg = json!({
+                            "type": msg_type,
+                            "data": {
+                                "id": entity_id,
+                                "synced": 1
+                            },
+                            "dbName": sender_db_name,
+                            "timestamp": chrono::Utc::now().timestamp_millis()
+                        });
+
+                        if let Err(e) = client
+                            .sender
+                            .send(wire_format::frame(&confirm_msg, &client.msgpack))
+                        {
+                            println!("Error sending sync confirmation to client {}: {:?}", id, e);
+                        }
+                    }
+                }
+            }
+            (sender_db_name, provenance)
+        }
+        Err(e) => {
+            println!("Failed to lock clients: {:?}", e);
+            return;
+        }
+    };
+
+    if let Some(msg_type) = json_msg["type"].as_str() {
+        event_bus::publish(BusEvent::UpdateAccepted {
+            tenant: sender_db_name,
+            msg_type: msg_type.to_string(),
+            data: json_msg["data"].clone(),
+            min_role: 0,
+            provenance,
+        })
+        .await;
+    }
+}
+
+async fn authenticate_client(
+    client_id: String,
+    ws_rx: &mut futures_util::stream::SplitStream<WebSocket>,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> bool {
+    while let Some(result) = ws_rx.next().await {
+        match result {
+            Ok(msg) => {
+                if let Ok(text) = msg.to_str()
+                    && let Ok(json_msg) = serde_json::from_str::<Value>(text)
+                {
+                    if json_msg.get("version").and_then(|v| v.as_i64()) != Some(1) {
+                        println!("Wrong client version");
+                        return false;
+                    }
+
+                    if json_msg.get("type").and_then(|v| v.as_str())
+                        == Some("authentication_request")
+                    {
+                        let data = json_msg.get("data").cloned().unwrap_or(json!({}));
+                        return handle_authentication_request(client_id, clients, db_pools, data)
+                            .await;
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("WebSocket error during authentication: {:?}", e);
+                return false;
+            }
+        }
+    }
+    false
+}
+
+async fn wait_for_sync_slot(
+    client_id: &str,
+    tenant: &str,
+    clients: &Clients,
+) -> Option<sync_scheduler::SyncPermit> {
+    let mut queued = match sync_scheduler::admit(tenant) {
+        sync_scheduler::Admission::Granted(permit) => return Some(permit),
+        sync_scheduler::Admission::Queued(queued) => queued,
+    };
+
+    let mut announced = None;
+    loop {
+        let position = queued.position();
+        if position.is_some() && position != announced {
+            let response = json!({
+                "type": "sync_queued",
+                "data": { "position": position },
+                "dbName": tenant,
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+            send_message(client_id.to_string(), &response, clients).await;
+            announced = position;
+        }
+
+        if !is_client_connected(client_id, clients) {
+            return None;
+        }
+
+        tokio::select! {
+            permit = queued.changed() => {
+                if permit.is_some() {
+                    return permit;
+                }
+            }
+            _ = tokio::time::sleep(Duration::from_secs(5)) => {}
+        }
+    }
+}
+
+async fn handle_authenticated_client(
+    client_id: String,
+    mut ws_rx: futures_util::stream::SplitStream<WebSocket>,
+    clients: Clients,
+) {
+    let disconnect = match clients.lock() {
+        Ok(clients_lock) => clients_lock
+            .get(&client_id)
+            .map(|client| client.disconnect.clone()),
+        Err(_) => None,
+    }
+    .unwrap_or_default();
+    let mut batch: Option<WriteBatch> = None;
+    let mut last_write_at: Option<Instant> = None;
+
+    loop {
+        let result = tokio::select! {
+            result = ws_rx.next() => result,
+            _ = disconnect.notified() => {
+                println!("Disconnecting client {}", client_id);
+                None
+            }
+            _ = tokio::time::sleep(write_batch::burst_gap()), if batch.is_some() => {
+                if let Some(batch) = batch.take() {
+                    commit_write_batch(batch, &client_id, &clients).await;
+                }
+                continue;
+            }
+        };
+        let Some(result) = result else {
+            break;
+        };
+
+        match result {
+            Ok(msg) => {
+                if let Some(json_msg) = wire_format::decode(&msg) {
+                    let msg_type = json_msg
+                        .get("type")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("unknown");
+                    stuck_clients::record_message(&client_id, msg_type, &clients);
+
+                    let data = json_msg.get("data").cloned().unwrap_or(json!({}));
+                    log_incoming_message(msg_type, &client_id, &data);
+
+                    let client_db_name = {
+                        match clients.lock() {
+                            Ok(clients_lock) => {
+                                if let Some(client) = clients_lock.get(&client_id) {
+                                    client.db_name.clone()
+                                } else {
+                                    String::new()
+                                }
+                            }
+                            Err(_) => String::new(),
+                        }
+                    };
+
+                    if msg_type == "ping" {
+                        send_pong(client_id.clone(), &data, &clients).await;
+                        continue;
+                    }
+
+                    let batchable = write_batch::is_batchable(msg_type);
+                    if !batchable && let Some(batch) = batch.take() {
+                        commit_write_batch(batch, &client_id, &clients).await;
+                    }
+
+                    let Some(_activity) = tenant_drain::begin(&client_db_name) else {
+                        send_error(
+                            client_id.clone(),
+                            msg_type,
+                            data.get("id"),
+                            "TENANT_DRAINING",
+                            "Tenant is under maintenance",
+                            &clients,
+                        )
+                        .await;
+                        continue;
+                    };
+                    sla_stats::record_message(&client_db_name);
+
+                    if msg_type == "sync_request" || msg_type == "sync_resume" {
+                        let Some(_permit) =
+                            wait_for_sync_slot(&client_id, &client_db_name, &clients).await
+                        else {
+                            println!("Client {} left the sync queue", client_id);
+                            break;
+                        };
+
+                        let sync_started_at = Instant::now();
+                        if handle_sync_request(
+                            &data,
+                            client_id.clone(),
+                            &clients,
+                            msg_type == "sync_resume",
+                        )
+                        .await
+                        {
+                            sla_stats::record_sync(&client_db_name, sync_started_at.elapsed());
+                            println!("Sync to client complete");
+                            let limited = {
+                                match clients.lock() {
+                                    Ok(mut clients_lock) => {
+                                        if let Some(client) = clients_lock.get_mut(&client_id) {
+                                            client.sync_completed = true;
+                                            println!("Client {} marked as fully synced", client_id);
+                                            let mut limited: Vec<String> = client
+                                                .sync_budgets
+                                                .drain()
+                                                .filter(|(_, budget)| *budget == 0)
+                                                .map(|(msg_type, _)| msg_type)
+                                                .collect();
+                                            limited.sort();
+                                            Some(limited)
+                                        } else {
+                                            None
+                                        }
+                                    }
+                                    Err(e) => {
+                                        println!(
+                                            "Failed to lock clients to update sync status: {:?}",
+                                            e
+                                        );
+                                        None
+                                    }
+                                }
+                            };
+
+                            if let Some(limited) = limited {
+                                let response = serde_json::json!({
+                                    "type": "sync_from_server_complete",
+                                    "data": { "limited": limited },
+                                    "dbName": client_db_name,
+                                    "timestamp": chrono::Utc::now().timestamp_millis()
+                                });
+
+                                send_message(client_id.clone(), &response, &clients).await;
+                            }
+                        }
+                    } else if msg_type == "force_resync_ready" {
+                        let Some(_permit) =
+                            wait_for_sync_slot(&client_id, &client_db_name, &clients).await
+                        else {
+                            println!("Client {} left the sync queue", client_id);
+                            break;
+                        };
+
+                        force_resync::stream(&data, &client_id, &clients).await;
+                    } else if msg_type == "sync_complete" {
+                        let response = serde_json::json!({
+                            "type": "sync_to_server_complete",
+                            "dbName": client_db_name,
+                            "timestamp": chrono::Utc::now().timestamp_millis()
+                        });
+
+                        send_message(client_id.clone(), &response, &clients).await;
+                    } else if batchable {
+                        let in_burst = batch.is_some()
+                            || last_write_at
+                                .is_some_and(|at| at.elapsed() <= write_batch::burst_gap());
+                        last_write_at = Some(Instant::now());
+                        if in_burst && batch.is_none() {
+                            batch = open_write_batch(&client_id, &clients);
+                        }
+
+                        let handled = sla_stats::track(handle_client_message(
+                            msg_type, &json_msg, &data, &client_id, &clients,
+                        ));
+                        match batch.as_mut() {
+                            Some(open) => {
+                                open.run(msg_type, data.get("id").cloned(), handled).await;
+                                if open.is_full()
+                                    && let Some(batch) = batch.take()
+                                {
+                                    commit_write_batch(batch, &client_id, &clients).await;
+                                }
+                            }
+                            None => handled.await,
+                        }
+                    } else {
+                        sla_stats::track(handle_client_message(
+                            msg_type, &json_msg, &data, &client_id, &clients,
+                        ))
+                        .await;
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("WebSocket error: {:?}", e);
+                break;
+            }
+        }
+    }
+    if let Some(batch) = batch.take() {
+        commit_write_batch(batch, &client_id, &clients).await;
+    }
+
+    let removed = match clients.lock() {
+        Ok(mut clients_lock) => clients_lock
+            .remove(&client_id)
+            .inspect(broadcast_health::forget),
+        Err(e) => {
+            eprintln!("Failed to lock clients for cleanup: {:?}", e);
+            None
+        }
+    };
+    if let Some(client) = removed {
+        if !client.sync_checkpoints.is_empty() {
+            sync_sessions::save_checkpoints(
+                &client.db_name,
+                &client.user_id,
+                client.device_id.as_deref().unwrap_or_default(),
+                &client.sync_checkpoints,
+            );
+        }
+        event_bus::publish(BusEvent::Disconnected {
+            tenant: client.db_name,
+            client_id,
+            user_id: client.user_id,
+        })
+        .await;
+    }
+}
+
+/// Commits the batch, which sends the confirmations and broadcasts of its
+/// updates, and tells the client about every update the commit lost.
+async fn commit_write_batch(batch: WriteBatch, client_id: &str, clients: &Clients) {
+    for (msg_type, id) in batch.commit().await {
+        send_error(
+            client_id.to_string(),
+            &msg_type,
+            id.as_ref(),
+            "COMMIT_FAILED",
+            "Update could not be saved, send it again",
+            clients,
+        )
+        .await;
+    }
+}
+
+fn open_write_batch(client_id: &str, clients: &Clients) -> Option<WriteBatch> {
+    let (db_path, _) = get_client_db_path_and_tenant(client_id, clients)?;
+    match WriteBatch::begin(&db_path) {
+        Ok(batch) => {
+            println!(
+                "Batching writes of client {} to {}",
+                client_id,
+                batch.db_path()
+            );
+            Some(batch)
+        }
+        Err(e) => {
+            println!(
+                "Failed to open write batch for client {}: {:?}",
+                client_id, e
+            );
+            None
+        }
+    }
+}
+
+async fn handle_connection(
+    ws: WebSocket,
+    clients: Clients,
+    db_pools: DbPoolMap,
+    remote_addr: Option<SocketAddr>,
+) {
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let client_id = format!("client-{}", Uuid::new_v4());
+    let client = Client::new(tx.clone(), remote_addr.map(|addr| addr.ip().to_string()));
+    let queue_depth = client.queue_depth.clone();
+    let compression = client.compression.clone();
+
+    match clients.lock() {
+        Ok(mut clients_lock) => {
+            clients_lock.insert(client_id.clone(), client);
+        }
+        Err(e) => {
+            eprintln!("Failed to lock clients for insertion: {:?}", e);
+            return;
+        }
+    }
+
+    tokio::task::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            queue_depth.store(rx.len() + 1, Ordering::Relaxed);
+            let message = ws_compression::encode(message, &compression);
+            if let Err(e) = ws_tx.send(message).await {
+                eprintln!("Error sending WebSocket message: {:?}", e);
+                break;
+            }
+            queue_depth.store(rx.len(), Ordering::Relaxed);
+        }
+    });
+
+    send_message(
+        client_id.clone(),
+        &auth_challenge::issue(&client_id),
+        &clients,
+    )
+    .await;
+
+    let span = tracing::info_span!(
+        "ws.connection",
+        client.id = %client_id,
+        tenant = tracing::field::Empty,
+        user.id = tracing::field::Empty
+    );
+
+    let authenticated = match timeout(
+        Duration::from_secs(10),
+        authenticate_client(client_id.clone(), &mut ws_rx, &clients, &db_pools),
+    )
+    .instrument(span.clone())
+    .await
+    {
+        Ok(result) => result,
+        Err(_) => {
+            eprintln!("Authentication timeout for client {}", client_id);
+            false
+        }
+    };
+    auth_challenge::discard(&client_id);
+
+    if authenticated {
+        force_resync::deliver_pending(&client_id, &clients).await;
+        handle_authenticated_client(client_id.clone(), ws_rx, clients.clone())
+            .instrument(span)
+            .await;
+    } else {
+        eprintln!("Authentication failed for client {}", client_id);
+
+        match clients.lock() {
+            Ok(mut clients_lock) => {
+                clients_lock.remove(&client_id);
+                println!("Removed unauthenticated client: {}", client_id);
+            }
+            Err(e) => {
+                eprintln!("Failed to lock clients for cleanup: {:?}", e);
+            }
+        }
+    }
+}
+
+fn with_clients(
+    clients: Clients,
+) -> impl Filter<Extract = (Clients,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || clients.clone())
+}
+
+fn with_db_pools(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (DbPoolMap,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || db_pools.clone())
+}
+
+pub async fn run() -> Result<()> {
+    dotenv().ok();
+
+    let (config, options) = match config::Options::from_args(std::env::args().skip(1))
+        .and_then(|options| Ok((config::init(&options)?, options)))
+    {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if options.print_config {
+        print!("{}", config.render());
+        return Ok(());
+    }
+
+    if let Some(generate) = &options.generate_demo {
+        match synthetic::generate(generate) {
+            Ok(summary) => println!("Generated synthetic data: {}", summary),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(simulation) = &options.simulate_rules {
+        match rule_simulation::run(simulation) {
+            Ok(report) => println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            ),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(migration) = &options.migrate_storage {
+        match storage::migrate(migration) {
+            Ok(report) => println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            ),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    if options.selftest {
+        std::process::exit(if selftest::run().await { 0 } else { 1 });
+    }
+
+    if options.test_fixture {
+        std::process::exit(if test_fixture::run_until_interrupted().await {
+            0
+        } else {
+            1
+        });
+    }
+
+    let _tracer_provider = telemetry::init(&config.telemetry);
+
+    if let Err(e) = storage::ensure_roots() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let (storage_ok, _) = storage::check_free_space();
+    if !storage_ok {
+        eprintln!("Storage roots are below storage.min_free_mb, GET /ready reports unavailable");
+    }
+
+    if config.selftest.on_startup && !selftest::run().await {
+        eprintln!("Refusing to start after a failed self-test");
+        std::process::exit(1);
+    }
+
+    let port = config.port;
+    instance_lease::init();
+    if instance_lease::is_leader() {
+        snapshot::cleanup_stale_snapshots();
+        sync_snapshot::cleanup_expired_snapshots();
+        support_bundle::cleanup_expired_bundles();
+    } else {
+        println!("Another instance holds the lease, skipping startup cleanups");
+    }
+    if config.schema.check_on_startup {
+        schema_drift::check_all_tenants();
+        location_junctions::check_all_tenants();
+    }
+
+    println!("Starting WebSocket server on port {}...", port);
+    match serve(([0, 0, 0, 0], port).into(), std::future::pending()) {
+        Ok((_, server)) => server.await,
+        Err(e) => {
+            eprintln!("Failed to bind port {}: {}", port, e);
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
+
+fn boxed_routes<F, R>(filter: F) -> BoxedFilter<(Box<dyn warp::Reply>,)>
+where
+    F: Filter<Extract = (R,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    R: warp::Reply + 'static,
+{
+    filter
+        .map(|reply: R| Box::new(reply) as Box<dyn warp::Reply>)
+        .boxed()
+}
+
+fn serve(
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::result::Result<(SocketAddr, impl Future<Output = ()> + Send), warp::Error> {
+    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
+    let db_pools: DbPoolMap = Arc::new(StdMutex::new(HashMap::new()));
+    event_bus::spawn();
+    // Stopped on shutdown, so servers started by test_fixture do not leave
+    // their loops running for the rest of the process.
+    let background_tasks: Vec<_> = [
+        instance_lease::spawn(),
+        tenant_hibernation::spawn(clients.clone(), db_pools.clone()),
+        stuck_clients::spawn(clients.clone()),
+        archive::spawn(),
+        photo_recompression::spawn(),
+        event_compaction::spawn(),
+        replication::spawn(),
+        digests::spawn(),
+        capacity::spawn(clients.clone()),
+        quota::spawn(clients.clone()),
+        activity_reports::spawn(),
+        sla_stats::spawn(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let ws_route = warp::path("ws")
+        .and(warp::ws())
+        .and(with_clients(clients.clone()))
+        .and(with_db_pools(db_pools.clone()))
+        .and(warp::addr::remote())
+        .map(|ws: warp::ws::Ws, clients, db_pools, remote_addr| {
+            ws.on_upgrade(move |socket| handle_connection(socket, clients, db_pools, remote_addr))
+        });
+
+    let health_route = warp::path::end().map(|| "User Sync WebSocket Server is running.");
+    let metrics_route = warp::path("metrics")
+        .and(warp::path::end())
+        .map(metrics::render);
+    // Each group is boxed so the combined filter type stays shallow enough
+    // for the compiler; one unboxed chain of every route overflows it.
+    let sync_routes = boxed_routes(
+        review_queue::route(db_pools.clone())
+            .or(export::route(db_pools.clone()))
+            .or(note_attachments::route(db_pools.clone()))
+            .or(portal::route(db_pools.clone()))
+            .or(geojson_import::route(clients.clone(), db_pools.clone()))
+            .or(sync_snapshot::route(db_pools.clone()))
+            .or(stuck_clients::route(clients.clone(), db_pools.clone()))
+            .or(schema_drift::route(db_pools.clone()))
+            .or(coordinates::route(db_pools.clone()))
+            .or(location_junctions::route(db_pools.clone())),
+    );
+    let admin_routes = boxed_routes(
+        client_admin::route(clients.clone(), db_pools.clone())
+            .or(digests::route(db_pools.clone()))
+            .or(shipment_documents::route(db_pools.clone()))
+            .or(graphql::route(db_pools.clone()))
+            .or(tenant_drain::route(clients.clone(), db_pools.clone()))
+            .or(scripting::route(db_pools.clone()))
+            .or(capacity::route(clients.clone()))
+            .or(price_lists::route(db_pools.clone()))
+            .or(activity_reports::route(db_pools.clone()))
+            .or(support_bundle::route(db_pools.clone()))
+            .or(replication::route()),
+    );
+    let data_routes = boxed_routes(
+        photo_urls::route(db_pools.clone())
+            .or(projections::route(db_pools.clone()))
+            .or(event_stream::route(db_pools.clone()))
+            .or(permissions::route(clients.clone(), db_pools.clone()))
+            .or(gate::route(clients.clone(), db_pools.clone()))
+            .or(update_schema::route())
+            .or(force_resync::route(clients.clone(), db_pools.clone()))
+            .or(sla_stats::route(db_pools.clone()))
+            .or(write_verification::route(db_pools.clone()))
+            .or(search::route(db_pools.clone())),
+    );
+    let service_routes = boxed_routes(
+        integration_tokens::route(db_pools.clone())
+            .or(deprecations::route(db_pools.clone()))
+            .or(contract_aggregates::route(
+                clients.clone(),
+                db_pools.clone(),
+            ))
+            .or(storage::route())
+            .or(history::route(db_pools.clone()))
+            .or(schema_docs::route(db_pools.clone()))
+            .or(tours::route(db_pools.clone()))
+            .or(status_page::route())
+            .or(photo_recompression::route(db_pools.clone())),
+    );
+    let rest_routes = boxed_routes(rest::with_etag(
+        sync_routes
+            .or(admin_routes)
+            .or(data_routes)
+            .or(service_routes),
+    ));
+    let ws_route = boxed_routes(ws_route);
+    let routes = ws_route.or(metrics_route).or(rest_routes).or(health_route);
+
+    let disconnect_clients = clients.clone();
+    warp::serve(routes).try_bind_with_graceful_shutdown(addr, async move {
+        shutdown.await;
+        for task in &background_tasks {
+            task.abort();
+        }
+        instance_lease::release();
+        if let Ok(clients_lock) = disconnect_clients.lock() {
+            for client in clients_lock.values() {
+                client.disconnect.notify_one();
+            }
+        }
+    })
+}
@@ -0,0 +1,131 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct ActivityFeedLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl ActivityFeedLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ActivityFeedLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    #[tracing::instrument(name = "db.get_activity_updates_by_date", skip(self))]
+    pub fn get_activity_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let query =
+            "SELECT * FROM activityFeed WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 500";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            match row {
+                Ok(entry) => entries.push(entry),
+                Err(e) => eprintln!("Error fetching activity: {}", e),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// A page of the feed, newest first. `before` is the lastEdit of the last
+    /// entry of the previous page, `before_id` breaks ties between entries of
+    /// the same millisecond.
+    pub fn get_page(
+        &self,
+        before: Option<i64>,
+        before_id: &str,
+        user_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<Value>> {
+        let query = "SELECT * FROM activityFeed
+             WHERE deleted = 0
+               AND (?1 IS NULL OR lastEdit < ?1 OR (lastEdit = ?1 AND id < ?2))
+               AND (?3 IS NULL OR userId = ?3)
+             ORDER BY lastEdit DESC, id DESC LIMIT ?4";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(
+            params![before, before_id, user_id, limit as i64],
+            row_to_json,
+        )?;
+        rows.collect()
+    }
+
+    pub fn has_entity(&self, entity_type: &str, entity_id: &str) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT 1 FROM activityFeed WHERE entityType = ? AND entityId = ? LIMIT 1",
+            params![entity_type, entity_id],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|found| found.is_some())
+    }
+
+    pub fn save_activity(&self, entry: &Value) -> Result<()> {
+        let mut entry_for_save = entry.clone();
+        if let Value::Object(ref mut map) = entry_for_save {
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+            if let Some(details) = map.get("details").filter(|details| details.is_object()) {
+                let details = details.to_string();
+                map.insert("details".to_string(), details.into());
+            }
+        }
+
+        self.core_storage
+            .insert("activityFeed", &entry_for_save)
+            .map(|_| ())
+    }
+
+    /// Removes entries older than `cutoff` and everything beyond the newest
+    /// `max_entries`. Returns the number of removed entries.
+    pub fn prune(&self, cutoff: i64, max_entries: usize) -> Result<usize> {
+        let conn = self.core_storage.get_connection()?;
+        let expired = conn.execute(
+            "DELETE FROM activityFeed WHERE lastEdit < ?",
+            params![cutoff],
+        )?;
+        let overflow = conn.execute(
+            "DELETE FROM activityFeed WHERE id IN (
+                 SELECT id FROM activityFeed ORDER BY lastEdit DESC, id DESC LIMIT -1 OFFSET ?
+             )",
+            params![max_entries as i64],
+        )?;
+
+        Ok(expired + overflow)
+    }
+}
+
+fn row_to_json(row: &rusqlite::Row) -> Result<Value> {
+    let details: Option<String> = row.get("details")?;
+
+    Ok(json!({
+        "id": row.get::<_, String>("id")?,
+        "lastEdit": row.get::<_, i64>("lastEdit")?,
+        "userId": row.get::<_, Option<String>>("userId")?,
+        "action": row.get::<_, String>("action")?,
+        "entityType": row.get::<_, String>("entityType")?,
+        "entityId": row.get::<_, String>("entityId")?,
+        "details": details
+            .and_then(|details| serde_json::from_str::<Value>(&details).ok())
+            .unwrap_or(Value::Null),
+        "arrivalAtServer": row.get::<_, i64>("arrivalAtServer")?,
+        "deleted": row.get::<_, i64>("deleted")?
+    }))
+}
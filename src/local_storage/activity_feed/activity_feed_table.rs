@@ -0,0 +1,25 @@
+use crate::local_storage::core_table::{Column, Table};
+use rusqlite::{Connection, Result};
+
+pub const ACTIVITY_FEED_TABLE: Table = Table {
+    name: "activityFeed",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("userId", "TEXT"),
+        Column::new("action", "TEXT NOT NULL"),
+        Column::new("entityType", "TEXT NOT NULL"),
+        Column::new("entityId", "TEXT NOT NULL"),
+        Column::new("details", "TEXT"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
+
+pub fn ensure_index(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS activityFeedEntity ON activityFeed (entityType, entityId);
+         CREATE INDEX IF NOT EXISTS activityFeedArrival ON activityFeed (arrivalAtServer)",
+    )
+}
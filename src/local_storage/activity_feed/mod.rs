@@ -0,0 +1,2 @@
+pub mod activity_feed_local_storage;
+pub mod activity_feed_table;
@@ -0,0 +1,206 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct ActivityReportLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl ActivityReportLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ActivityReportLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn record_user_activity(&self, user_id: &str, month: &str, seen_at: i64) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO userActivity (userId, month, lastSeen) VALUES (?, ?, ?)
+             ON CONFLICT(userId, month) DO UPDATE SET lastSeen = excluded.lastSeen",
+            params![user_id, month, seen_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_last_seen(&self) -> Result<Option<i64>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row("SELECT MAX(lastSeen) FROM userActivity", [], |row| {
+            row.get(0)
+        })
+    }
+
+    #[tracing::instrument(name = "db.collect_activity", skip(self))]
+    pub fn collect_activity(&self, month: &str, start: i64, end: i64) -> Result<Value> {
+        let conn = self.core_storage.get_connection()?;
+
+        let active_users: i64 = conn.query_row(
+            "SELECT COUNT(DISTINCT userId) FROM (
+                 SELECT userId FROM userActivity WHERE month = ?1
+                 UNION SELECT userId FROM shipments
+                 WHERE arrivalAtServer >= ?2 AND arrivalAtServer < ?3
+             )",
+            params![month, start, end],
+            |row| row.get(0),
+        )?;
+        let registered_users: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM users WHERE COALESCE(deleted, 0) = 0",
+            [],
+            |row| row.get(0),
+        )?;
+        let shipments = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(quantity), 0), COALESCE(SUM(oversizeQuantity), 0)
+             FROM shipments
+             WHERE COALESCE(deleted, 0) = 0 AND arrivalAtServer >= ?1 AND arrivalAtServer < ?2",
+            params![start, end],
+            |row| {
+                Ok(json!({
+                    "count": row.get::<_, i64>(0)?,
+                    "quantity": row.get::<_, f64>(1)?,
+                    "oversizeQuantity": row.get::<_, f64>(2)?
+                }))
+            },
+        )?;
+        let new_locations: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM (
+                 SELECT id FROM locations
+                 WHERE COALESCE(deleted, 0) = 0 AND date >= ?1 AND date < ?2
+                 UNION SELECT id FROM locations_archive
+                 WHERE COALESCE(deleted, 0) = 0 AND date >= ?1 AND date < ?2
+             )",
+            params![start, end],
+            |row| row.get(0),
+        )?;
+        let photos = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM (
+                 SELECT length(photoFile) AS size FROM photos
+                 WHERE COALESCE(deleted, 0) = 0 AND arrivalAtServer >= ?1 AND arrivalAtServer < ?2
+                 UNION ALL SELECT length(photoFile) FROM photos_archive
+                 WHERE COALESCE(deleted, 0) = 0 AND arrivalAtServer >= ?1 AND arrivalAtServer < ?2
+             )",
+            params![start, end],
+            |row| {
+                Ok(json!({
+                    "added": row.get::<_, i64>(0)?,
+                    "bytes": row.get::<_, i64>(1)?
+                }))
+            },
+        )?;
+        let mut stmt = conn.prepare(
+            "SELECT s.sawmillId, m.name, COUNT(*), COUNT(s.distanceKm),
+                    COALESCE(SUM(s.distanceKm), 0)
+             FROM shipments s LEFT JOIN sawmills m ON m.id = s.sawmillId
+             WHERE COALESCE(s.deleted, 0) = 0 AND s.arrivalAtServer >= ?1 AND s.arrivalAtServer < ?2
+             GROUP BY s.sawmillId ORDER BY s.sawmillId",
+        )?;
+        let haulage = stmt
+            .query_map(params![start, end], |row| {
+                Ok(json!({
+                    "sawmillId": row.get::<_, String>(0)?,
+                    "name": row.get::<_, Option<String>>(1)?,
+                    "shipments": row.get::<_, i64>(2)?,
+                    "shipmentsWithDistance": row.get::<_, i64>(3)?,
+                    "distanceKm": (row.get::<_, f64>(4)? * 100.0).round() / 100.0
+                }))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let total_km = haulage
+            .iter()
+            .filter_map(|sawmill| sawmill["distanceKm"].as_f64())
+            .sum::<f64>();
+        let database_bytes: i64 = conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(json!({
+            "users": {
+                "active": active_users,
+                "registered": registered_users
+            },
+            "shipments": shipments,
+            "locations": { "new": new_locations },
+            "photos": photos,
+            "haulage": {
+                "distanceKm": (total_km * 100.0).round() / 100.0,
+                "sawmills": haulage
+            },
+            "storage": { "databaseBytes": database_bytes }
+        }))
+    }
+
+    pub fn save_report(&self, month: &str, start: i64, end: i64, report: &Value) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO activityReports (id, periodStart, periodEnd, generatedAt, report)
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                month,
+                start,
+                end,
+                report["generatedAt"].as_i64().unwrap_or_default(),
+                report.to_string()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn has_report(&self, month: &str) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM activityReports WHERE id = ?)",
+            params![month],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn get_report(&self, month: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT report FROM activityReports WHERE id = ?",
+            params![month],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map(|report| report.and_then(|report| serde_json::from_str(&report).ok()))
+    }
+
+    pub fn get_previous_report(&self, month: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT report FROM activityReports WHERE id < ? ORDER BY id DESC LIMIT 1",
+            params![month],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map(|report| report.and_then(|report| serde_json::from_str(&report).ok()))
+    }
+
+    pub fn list_reports(&self) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, periodStart, periodEnd, generatedAt FROM activityReports ORDER BY id DESC",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(json!({
+                "month": row.get::<_, String>(0)?,
+                "periodStart": row.get::<_, i64>(1)?,
+                "periodEnd": row.get::<_, i64>(2)?,
+                "generatedAt": row.get::<_, i64>(3)?
+            }))
+        })?;
+
+        rows.collect()
+    }
+}
@@ -0,0 +1,23 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const ACTIVITY_REPORT_TABLE: Table = Table {
+    name: "activityReports",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("periodStart", "INTEGER NOT NULL"),
+        Column::new("periodEnd", "INTEGER NOT NULL"),
+        Column::new("generatedAt", "INTEGER NOT NULL"),
+        Column::new("report", "TEXT NOT NULL"),
+    ],
+    constraints: &[],
+};
+
+pub const USER_ACTIVITY_TABLE: Table = Table {
+    name: "userActivity",
+    columns: &[
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("month", "TEXT NOT NULL"),
+        Column::new("lastSeen", "INTEGER NOT NULL"),
+    ],
+    constraints: &["PRIMARY KEY (userId, month)"],
+};
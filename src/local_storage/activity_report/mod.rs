@@ -0,0 +1,2 @@
+pub mod activity_report_local_storage;
+pub mod activity_report_table;
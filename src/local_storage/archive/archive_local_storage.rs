@@ -0,0 +1,131 @@
+use crate::local_storage::archive::archive_table::ARCHIVED_TABLES;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::photo::photo_local_storage;
+use crate::local_storage::search::search_key;
+use rusqlite::{Connection, Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct ArchiveLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl ArchiveLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ArchiveLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    #[tracing::instrument(name = "db.archive_done_locations", skip(self))]
+    pub fn archive_done_locations(&self, cutoff: i64) -> Result<i64> {
+        let conn = self.core_storage.get_connection()?;
+        let tx = conn.unchecked_transaction()?;
+
+        tx.execute_batch("DROP TABLE IF EXISTS temp.archive_ids")?;
+        tx.execute(
+            "CREATE TEMP TABLE archive_ids AS
+             SELECT id FROM locations WHERE done = 1 AND lastEdit < ?",
+            params![cutoff],
+        )?;
+        let archived: i64 = tx.query_row("SELECT COUNT(*) FROM temp.archive_ids", [], |row| {
+            row.get(0)
+        })?;
+
+        if archived > 0 {
+            move_rows(&tx, true)?;
+        }
+
+        tx.execute_batch("DROP TABLE temp.archive_ids")?;
+        tx.commit()?;
+        Ok(archived)
+    }
+
+    pub fn restore_location(&self, location_id: &str) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let archived: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM locations_archive WHERE id = ?)",
+            params![location_id],
+            |row| row.get(0),
+        )?;
+        if !archived {
+            return Ok(false);
+        }
+
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch("DROP TABLE IF EXISTS temp.archive_ids")?;
+        tx.execute(
+            "CREATE TEMP TABLE archive_ids AS SELECT ? AS id",
+            params![location_id],
+        )?;
+        move_rows(&tx, false)?;
+        tx.execute_batch("DROP TABLE temp.archive_ids")?;
+        tx.commit()?;
+
+        Ok(true)
+    }
+
+    pub fn get_archived_location(&self, location_id: &str) -> Result<Option<Value>> {
+        let Some(mut location) = self
+            .core_storage
+            .get_by_id("locations_archive", location_id)?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+        if let Value::Object(map) = &mut location {
+            search_key::strip("locations", map);
+        }
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT sawmillId FROM locationSawmillJunction_archive
+             WHERE locationId = ? AND isOversize = ?",
+        )?;
+        for (field, is_oversize) in [("sawmillIds", 0), ("oversizeSawmillIds", 1)] {
+            let sawmill_ids = stmt
+                .query_map(params![location_id, is_oversize], |row| {
+                    row.get::<_, String>(0)
+                })?
+                .collect::<Result<Vec<_>>>()?;
+            location[field] = json!(sawmill_ids);
+        }
+
+        let mut stmt = conn.prepare("SELECT * FROM photos_archive WHERE locationId = ?")?;
+        let photos = stmt
+            .query_map(params![location_id], photo_local_storage::row_to_json)?
+            .collect::<Result<Vec<_>>>()?;
+        location["photos"] = json!(photos);
+
+        Ok(Some(location))
+    }
+}
+
+fn move_rows(conn: &Connection, to_archive: bool) -> Result<()> {
+    for (live, archive, key) in ARCHIVED_TABLES {
+        let (from, to) = if to_archive {
+            (live, archive)
+        } else {
+            (archive, live)
+        };
+        let columns = from
+            .columns
+            .iter()
+            .map(|column| format!("\"{}\"", column.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        conn.execute_batch(&format!(
+            "INSERT OR REPLACE INTO \"{to}\" ({columns}) SELECT {columns} FROM \"{from}\"
+             WHERE \"{key}\" IN (SELECT id FROM temp.archive_ids);
+             DELETE FROM \"{from}\" WHERE \"{key}\" IN (SELECT id FROM temp.archive_ids);",
+            to = to.name,
+            from = from.name,
+        ))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::local_storage::core_table::Table;
+use crate::local_storage::location::location_table::{
+    LOCATION_SAWMILL_JUNCTION_TABLE, LOCATION_TABLE,
+};
+use crate::local_storage::photo::photo_table::PHOTO_TABLE;
+
+pub const LOCATION_ARCHIVE_TABLE: Table = Table {
+    name: "locations_archive",
+    columns: LOCATION_TABLE.columns,
+    constraints: &[],
+};
+
+pub const LOCATION_SAWMILL_JUNCTION_ARCHIVE_TABLE: Table = Table {
+    name: "locationSawmillJunction_archive",
+    columns: LOCATION_SAWMILL_JUNCTION_TABLE.columns,
+    constraints: &["PRIMARY KEY (locationId, sawmillId, isOversize)"],
+};
+
+pub const PHOTO_ARCHIVE_TABLE: Table = Table {
+    name: "photos_archive",
+    columns: PHOTO_TABLE.columns,
+    constraints: &[],
+};
+
+pub const ARCHIVED_TABLES: &[(&Table, &Table, &str)] = &[
+    (&LOCATION_TABLE, &LOCATION_ARCHIVE_TABLE, "id"),
+    (
+        &LOCATION_SAWMILL_JUNCTION_TABLE,
+        &LOCATION_SAWMILL_JUNCTION_ARCHIVE_TABLE,
+        "locationId",
+    ),
+    (&PHOTO_TABLE, &PHOTO_ARCHIVE_TABLE, "locationId"),
+];
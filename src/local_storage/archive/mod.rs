@@ -0,0 +1,2 @@
+pub mod archive_local_storage;
+pub mod archive_table;
@@ -0,0 +1,88 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub struct AssortmentLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl AssortmentLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = AssortmentLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_assortment_by_id(&self, id: &str) -> Result<Option<Value>> {
+        let assortment_json = self.core_storage.get_existing_by_id("assortments", id)?;
+
+        Ok(assortment_json.into_iter().next())
+    }
+
+    pub fn get_assortment_by_code(&self, code: &str) -> Result<Option<Value>> {
+        let query = "SELECT * FROM assortments WHERE deleted = 0 AND code = ? LIMIT 1";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let mut rows = stmt.query_map(params![code], row_to_json)?;
+        rows.next().transpose()
+    }
+
+    #[tracing::instrument(name = "db.get_assortment_updates_by_date", skip(self))]
+    pub fn get_assortment_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let query =
+            "SELECT * FROM assortments WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
+
+        let mut assortments = Vec::new();
+        for row in rows {
+            match row {
+                Ok(assortment) => assortments.push(assortment),
+                Err(e) => eprintln!("Error fetching assortment: {}", e),
+            }
+        }
+
+        Ok(assortments)
+    }
+
+    pub fn save_assortment(&self, assortment_data: &Value) -> Result<bool> {
+        let mut assortment_for_save = assortment_data.clone();
+        if let serde_json::Value::Object(ref mut map) = assortment_for_save {
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+        }
+
+        self.core_storage
+            .insert_or_update("assortments", &assortment_for_save)
+    }
+}
+
+fn row_to_json(row: &rusqlite::Row) -> Result<Value> {
+    let id: String = row.get("id")?;
+    let last_edit: i64 = row.get("lastEdit")?;
+    let code: String = row.get("code")?;
+    let description: Option<String> = row.get("description")?;
+    let arrival_at_server: i64 = row.get("arrivalAtServer")?;
+    let deleted: i64 = row.get("deleted")?;
+    let density: Option<f64> = row.get("densityKgPerCubicMeter")?;
+
+    Ok(serde_json::json!({
+        "id": id,
+        "lastEdit": last_edit,
+        "code": code,
+        "description": description,
+        "arrivalAtServer": arrival_at_server,
+        "deleted": deleted,
+        "densityKgPerCubicMeter": density
+    }))
+}
@@ -0,0 +1,15 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const ASSORTMENT_TABLE: Table = Table {
+    name: "assortments",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("code", "TEXT NOT NULL"),
+        Column::new("description", "TEXT"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("densityKgPerCubicMeter", "REAL"),
+    ],
+    constraints: &[],
+};
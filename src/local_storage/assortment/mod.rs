@@ -0,0 +1,2 @@
+pub mod assortment_local_storage;
+pub mod assortment_table;
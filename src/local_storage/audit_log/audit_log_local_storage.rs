@@ -0,0 +1,70 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub struct AuditLogLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl AuditLogLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = AuditLogLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn record(
+        &self,
+        user_id: &str,
+        action: &str,
+        target_type: &str,
+        target_id: &str,
+        details: &Value,
+    ) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO auditLog (id, timestamp, userId, action, targetType, targetId, details)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                Uuid::new_v4().to_string(),
+                chrono::Utc::now().timestamp_millis(),
+                user_id,
+                action,
+                target_type,
+                target_id,
+                details.to_string()
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_entries(&self, limit: i64) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, userId, action, targetType, targetId, details
+             FROM auditLog ORDER BY timestamp DESC LIMIT ?",
+        )?;
+
+        let rows = stmt.query_map(params![limit], |row| {
+            let details: Option<String> = row.get(6)?;
+            Ok(json!({
+                "id": row.get::<_, String>(0)?,
+                "timestamp": row.get::<_, i64>(1)?,
+                "userId": row.get::<_, String>(2)?,
+                "action": row.get::<_, String>(3)?,
+                "targetType": row.get::<_, Option<String>>(4)?,
+                "targetId": row.get::<_, Option<String>>(5)?,
+                "details": details
+                    .and_then(|details| serde_json::from_str::<Value>(&details).ok())
+            }))
+        })?;
+
+        rows.collect()
+    }
+}
@@ -0,0 +1,15 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const AUDIT_LOG_TABLE: Table = Table {
+    name: "auditLog",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("timestamp", "INTEGER NOT NULL"),
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("action", "TEXT NOT NULL"),
+        Column::new("targetType", "TEXT"),
+        Column::new("targetId", "TEXT"),
+        Column::new("details", "TEXT"),
+    ],
+    constraints: &[],
+};
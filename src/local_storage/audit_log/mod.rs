@@ -0,0 +1,2 @@
+pub mod audit_log_local_storage;
+pub mod audit_log_table;
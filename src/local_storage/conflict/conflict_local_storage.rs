@@ -0,0 +1,124 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub const STATUS_OPEN: &str = "open";
+pub const STATUS_RESOLVED: &str = "resolved";
+
+const VERSION_COLUMNS: &[&str] = &["localVersion", "serverVersion", "fields"];
+
+pub struct ConflictLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl ConflictLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ConflictLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn save_conflict(&self, conflict: &Value) -> Result<()> {
+        let mut conflict_for_save = conflict.clone();
+        if let Value::Object(ref mut map) = conflict_for_save {
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+            for column in VERSION_COLUMNS {
+                if let Some(version) = map.get(*column) {
+                    let version = version.to_string();
+                    map.insert(column.to_string(), version.into());
+                }
+            }
+        }
+
+        self.core_storage
+            .insert("conflicts", &conflict_for_save)
+            .map(|_| ())
+    }
+
+    pub fn get_conflict(&self, id: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT * FROM conflicts WHERE id = ? AND deleted = 0",
+            params![id],
+            row_to_json,
+        )
+        .optional()
+    }
+
+    /// Newest conflicts first, optionally only those of one status or
+    /// recorded for one user's updates.
+    pub fn get_conflicts(
+        &self,
+        status: Option<&str>,
+        user_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<Value>> {
+        let query = "SELECT * FROM conflicts
+             WHERE deleted = 0
+               AND (?1 IS NULL OR status = ?1)
+               AND (?2 IS NULL OR userId = ?2)
+             ORDER BY lastEdit DESC LIMIT ?3";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![status, user_id, limit as i64], row_to_json)?;
+        rows.collect()
+    }
+
+    /// Marks an open conflict as resolved. Returns false when it was
+    /// resolved already.
+    pub fn resolve(&self, id: &str, resolution: &str, resolved_by: &str) -> Result<bool> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let conn = self.core_storage.get_connection()?;
+
+        let updated = conn.execute(
+            "UPDATE conflicts
+             SET status = ?1, resolution = ?2, resolvedBy = ?3, resolvedAt = ?4,
+                 lastEdit = ?4, arrivalAtServer = ?4
+             WHERE id = ?5 AND status = ?6",
+            params![
+                STATUS_RESOLVED,
+                resolution,
+                resolved_by,
+                now,
+                id,
+                STATUS_OPEN
+            ],
+        )?;
+
+        Ok(updated > 0)
+    }
+}
+
+fn row_to_json(row: &rusqlite::Row) -> Result<Value> {
+    let version = |column: &str| -> Result<Value> {
+        let version: String = row.get(column)?;
+        Ok(serde_json::from_str(&version).unwrap_or(Value::Null))
+    };
+
+    Ok(json!({
+        "id": row.get::<_, String>("id")?,
+        "lastEdit": row.get::<_, i64>("lastEdit")?,
+        "entityType": row.get::<_, String>("entityType")?,
+        "entityId": row.get::<_, String>("entityId")?,
+        "userId": row.get::<_, Option<String>>("userId")?,
+        "baseLastEdit": row.get::<_, i64>("baseLastEdit")?,
+        "localVersion": version("localVersion")?,
+        "serverVersion": version("serverVersion")?,
+        "fields": version("fields")?,
+        "winner": row.get::<_, String>("winner")?,
+        "status": row.get::<_, String>("status")?,
+        "resolution": row.get::<_, Option<String>>("resolution")?,
+        "resolvedBy": row.get::<_, Option<String>>("resolvedBy")?,
+        "resolvedAt": row.get::<_, Option<i64>>("resolvedAt")?,
+        "arrivalAtServer": row.get::<_, i64>("arrivalAtServer")?
+    }))
+}
@@ -0,0 +1,33 @@
+use crate::local_storage::core_table::{Column, Table};
+use rusqlite::{Connection, Result};
+
+pub const CONFLICT_TABLE: Table = Table {
+    name: "conflicts",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("entityType", "TEXT NOT NULL"),
+        Column::new("entityId", "TEXT NOT NULL"),
+        Column::new("userId", "TEXT"),
+        Column::new("baseLastEdit", "INTEGER NOT NULL"),
+        Column::new("localVersion", "TEXT NOT NULL"),
+        Column::new("serverVersion", "TEXT NOT NULL"),
+        Column::new("fields", "TEXT NOT NULL"),
+        Column::new("winner", "TEXT NOT NULL"),
+        Column::new("status", "TEXT NOT NULL DEFAULT 'open'"),
+        Column::new("resolution", "TEXT"),
+        Column::new("resolvedBy", "TEXT"),
+        Column::new("resolvedAt", "INTEGER"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
+
+pub fn ensure_index(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS conflictsStatus ON conflicts (status, lastEdit)",
+        [],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,2 @@
+pub mod conflict_local_storage;
+pub mod conflict_table;
@@ -1,6 +1,6 @@
 use crate::local_storage::core_local_storage::CoreLocalStorage;
-use rusqlite::{Result, params};
-use serde_json::Value;
+use rusqlite::{Connection, OptionalExtension, Result, Row, params};
+use serde_json::{Value, json};
 use std::sync::Arc;
 
 pub struct ContractLocalStorage {
@@ -16,50 +16,25 @@ impl ContractLocalStorage {
         Ok(storage)
     }
 
+    #[tracing::instrument(name = "db.get_contract_updates_by_date", skip(self))]
     pub fn get_contract_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
-        let query = format!(
-            "SELECT * FROM contracts WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100",
-        );
+        let query =
+            "SELECT * FROM contracts WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100"
+                .to_string();
 
         let conn = self.core_storage.get_connection()?;
         let mut stmt = conn.prepare(&query)?;
-        
-        let rows = stmt.query_map(params![last_edit], |row| {
-            let id: String = row.get(0)?;
-            let done: i64 = row.get(1)?;
-            let last_edit: i64 = row.get(2)?;
-            let title: String = row.get(3)?;
-            let additional_info: String = row.get(4)?;
-            let start_date: i64 = row.get(5)?;
-            let end_date: i64 = row.get(6)?;
-            let available_quantity: f64 = row.get(7)?;
-            let booked_quantity: f64 = row.get(8)?;
-            let shipped_quantity: f64 = row.get(9)?;
-            let arrival_at_server: i64 = row.get(10)?;
-            let deleted: i64 = row.get(11)?;
-
-            let contract_json = serde_json::json!({
-                "id": id,
-                "done": done,
-                "lastEdit": last_edit,
-                "title": title,
-                "additionalInfo": additional_info,
-                "startDate": start_date,
-                "endDate": end_date,
-                "availableQuantity": available_quantity,
-                "bookedQuantity": booked_quantity,
-                "shippedQuantity": shipped_quantity,
-                "arrivalAtServer": arrival_at_server,
-                "deleted": deleted
-            });
-
-            Ok(contract_json)
-        })?;
+
+        let rows = stmt.query_map(params![last_edit], contract_from_row)?;
 
         let mut contracts = Vec::new();
         for row in rows {
             match row {
-                Ok(contract) => contracts.push(contract),
+                Ok(mut contract) => {
+                    let id = contract["id"].as_str().unwrap_or_default().to_string();
+                    contract["allowedSawmillIds"] = json!(allowed_sawmill_ids(&conn, &id)?);
+                    contracts.push(contract);
+                }
                 Err(e) => eprintln!("Error fetching contract: {}", e),
             }
         }
@@ -67,15 +42,137 @@ impl ContractLocalStorage {
         Ok(contracts)
     }
 
+    pub fn get_contract_by_id(&self, id: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let contract = conn
+            .query_row(
+                "SELECT * FROM contracts WHERE id = ?",
+                params![id],
+                contract_from_row,
+            )
+            .optional()?;
+
+        contract
+            .map(|mut contract| {
+                contract["allowedSawmillIds"] = json!(allowed_sawmill_ids(&conn, id)?);
+                Ok(contract)
+            })
+            .transpose()
+    }
+
+    pub fn get_sawmill_allowlist(
+        &self,
+        contract_id: &str,
+    ) -> Result<(Vec<String>, Option<String>)> {
+        let conn = self.core_storage.get_connection()?;
+        let mode = conn
+            .query_row(
+                "SELECT sawmillAllowlistMode FROM contracts WHERE id = ?",
+                params![contract_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+
+        Ok((allowed_sawmill_ids(&conn, contract_id)?, mode))
+    }
+
+    pub fn set_sawmill_allowlist(
+        &self,
+        contract_id: &str,
+        sawmill_ids: &[String],
+        mode: &str,
+    ) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let tx = conn.unchecked_transaction()?;
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let updated = tx.execute(
+            "UPDATE contracts SET sawmillAllowlistMode = ?, lastEdit = MAX(lastEdit + 1, ?),
+             arrivalAtServer = ? WHERE id = ? AND COALESCE(deleted, 0) = 0",
+            params![mode, now, now, contract_id],
+        )?;
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        tx.execute(
+            "DELETE FROM contractSawmillAllowlist WHERE contractId = ?",
+            params![contract_id],
+        )?;
+        for sawmill_id in sawmill_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO contractSawmillAllowlist (contractId, sawmillId) VALUES (?, ?)",
+                params![contract_id, sawmill_id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+
     pub fn save_contract(&self, contract_data: &Value) -> Result<bool> {
         let mut contract_for_save = contract_data.clone();
         if let serde_json::Value::Object(ref mut map) = contract_for_save {
-            map.insert("arrivalAtServer".to_string(), chrono::Utc::now().timestamp_millis().into());
+            map.remove("allowedSawmillIds");
+            map.remove("sawmillAllowlistMode");
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
         }
 
-        let result = self.core_storage
+        let result = self
+            .core_storage
             .insert_or_update("contracts", &contract_for_save)?;
 
         Ok(result)
     }
 }
+
+fn contract_from_row(row: &Row) -> Result<Value> {
+    let id: String = row.get(0)?;
+    let done: i64 = row.get(1)?;
+    let last_edit: i64 = row.get(2)?;
+    let title: String = row.get(3)?;
+    let additional_info: String = row.get(4)?;
+    let start_date: i64 = row.get(5)?;
+    let end_date: i64 = row.get(6)?;
+    let available_quantity: f64 = row.get(7)?;
+    let booked_quantity: f64 = row.get(8)?;
+    let shipped_quantity: f64 = row.get(9)?;
+    let arrival_at_server: i64 = row.get(10)?;
+    let deleted: i64 = row.get(11)?;
+    let price_per_cubic_meter: Option<f64> = row.get("pricePerCubicMeter")?;
+    let currency: Option<String> = row.get("currency")?;
+    let vat_rate: Option<f64> = row.get("vatRate")?;
+    let sawmill_allowlist_mode: Option<String> = row.get("sawmillAllowlistMode")?;
+
+    Ok(json!({
+        "id": id,
+        "done": done,
+        "lastEdit": last_edit,
+        "title": title,
+        "additionalInfo": additional_info,
+        "startDate": start_date,
+        "endDate": end_date,
+        "availableQuantity": available_quantity,
+        "bookedQuantity": booked_quantity,
+        "shippedQuantity": shipped_quantity,
+        "arrivalAtServer": arrival_at_server,
+        "deleted": deleted,
+        "pricePerCubicMeter": price_per_cubic_meter,
+        "currency": currency,
+        "vatRate": vat_rate,
+        "sawmillAllowlistMode": sawmill_allowlist_mode
+    }))
+}
+
+fn allowed_sawmill_ids(conn: &Connection, contract_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT sawmillId FROM contractSawmillAllowlist WHERE contractId = ? ORDER BY sawmillId",
+    )?;
+
+    let rows = stmt.query_map(params![contract_id], |row| row.get::<_, String>(0))?;
+    rows.collect()
+}
@@ -0,0 +1,121 @@
+use crate::local_storage::core_table::{Column, EntityDefaults, FieldDefault, Table};
+
+pub const CONTRACT_TABLE: Table = Table {
+    name: "contracts",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("done", "INTEGER NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("title", "TEXT NOT NULL"),
+        Column::new("additionalInfo", "TEXT NOT NULL"),
+        Column::new("startDate", "INTEGER NOT NULL"),
+        Column::new("endDate", "INTEGER NOT NULL"),
+        Column::new("availableQuantity", "REAL NOT NULL"),
+        Column::new("bookedQuantity", "REAL NOT NULL"),
+        Column::new("shippedQuantity", "REAL NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("pricePerCubicMeter", "REAL"),
+        Column::new("currency", "TEXT"),
+        Column::new("vatRate", "REAL"),
+        Column::new("sawmillAllowlistMode", "TEXT"),
+        Column::new("titleSearch", "TEXT"),
+    ],
+    constraints: &[],
+};
+
+pub const CONTRACT_SAWMILL_ALLOWLIST_TABLE: Table = Table {
+    name: "contractSawmillAllowlist",
+    columns: &[
+        Column::new("contractId", "TEXT NOT NULL"),
+        Column::new("sawmillId", "TEXT NOT NULL"),
+    ],
+    constraints: &[
+        "PRIMARY KEY (contractId, sawmillId)",
+        "FOREIGN KEY (contractId) REFERENCES contracts(id) ON DELETE CASCADE",
+        "FOREIGN KEY (sawmillId) REFERENCES sawmills(id) ON DELETE CASCADE",
+    ],
+};
+
+pub const CONTRACT_DEFAULTS: EntityDefaults = EntityDefaults {
+    table: "contracts",
+    message_type: "contract_update",
+    fields: &[
+        ("done", FieldDefault::Integer(0)),
+        ("additionalInfo", FieldDefault::Text("")),
+        ("bookedQuantity", FieldDefault::Real(0.0)),
+        ("shippedQuantity", FieldDefault::Real(0.0)),
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_storage::core_local_storage::CoreLocalStorage;
+    use crate::local_storage::schema;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn storage() -> Arc<CoreLocalStorage> {
+        let core_storage = CoreLocalStorage::new(":memory:").unwrap();
+        schema::migrate(&core_storage.get_connection().unwrap()).unwrap();
+        Arc::new(core_storage)
+    }
+
+    #[test]
+    fn defaults_are_registered_for_contract_updates() {
+        let defaults = schema::defaults_for_message("contract_update").unwrap();
+        assert_eq!(defaults.table, CONTRACT_DEFAULTS.table);
+        assert!(schema::defaults_for_message("contract_delete").is_none());
+    }
+
+    #[test]
+    fn apply_fills_missing_and_null_fields_only() {
+        let mut contract = json!({"id": "c1", "done": 1, "additionalInfo": null});
+
+        assert!(CONTRACT_DEFAULTS.apply(&mut contract));
+        assert_eq!(contract["done"], 1);
+        assert_eq!(contract["additionalInfo"], "");
+        assert_eq!(contract["bookedQuantity"], 0.0);
+        assert_eq!(contract["shippedQuantity"], 0.0);
+        assert!(!CONTRACT_DEFAULTS.apply(&mut contract));
+    }
+
+    #[test]
+    fn new_contracts_get_defaults() {
+        let update = json!({"id": "c1", "title": "Spring"});
+
+        let normalized = crate::normalize_entity("contract_update", &update, storage()).unwrap();
+        assert_eq!(normalized["done"], 0);
+        assert_eq!(normalized["additionalInfo"], "");
+        assert_eq!(normalized["bookedQuantity"], 0.0);
+    }
+
+    #[test]
+    fn partial_updates_of_existing_contracts_are_left_untouched() {
+        let core_storage = storage();
+        core_storage
+            .insert(
+                "contracts",
+                &json!({
+                    "id": "c1", "done": 1, "lastEdit": 1, "title": "Spring",
+                    "additionalInfo": "stored", "startDate": 0, "endDate": 0,
+                    "availableQuantity": 10.0, "bookedQuantity": 4.0,
+                    "shippedQuantity": 2.0, "arrivalAtServer": 1
+                }),
+            )
+            .unwrap();
+        let update = json!({"id": "c1", "title": "Autumn"});
+
+        let normalized = crate::normalize_entity("contract_update", &update, core_storage);
+        assert!(normalized.is_none());
+    }
+
+    #[test]
+    fn deletions_are_skipped() {
+        let update = json!({"id": "c1", "deleted": 1});
+
+        let normalized = crate::normalize_entity("contract_update", &update, storage());
+        assert!(normalized.is_none());
+    }
+}
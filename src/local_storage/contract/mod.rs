@@ -1 +1,2 @@
 pub mod contract_local_storage;
+pub mod contract_table;
@@ -1,22 +1,33 @@
+use crate::local_storage::schema;
+use crate::local_storage::search::search_key;
+use crate::local_storage::sql_builder::SqlBuilder;
+use crate::watchdog;
 use base64::prelude::*;
+use rusqlite::trace::TraceEventCodes;
 use rusqlite::{Connection, Result, params};
 use serde_json;
 use std::sync::Mutex;
 
 pub struct CoreLocalStorage {
     connection: Mutex<Connection>,
+    transactions: Mutex<Vec<Option<String>>>,
 }
 
 impl CoreLocalStorage {
     pub fn new(db_path: &str) -> Result<Self> {
         let conn = Connection::open(db_path)?;
+        conn.trace_v2(
+            TraceEventCodes::SQLITE_TRACE_STMT,
+            Some(watchdog::record_statement),
+        );
 
         Ok(CoreLocalStorage {
             connection: Mutex::new(conn),
+            transactions: Mutex::new(Vec::new()),
         })
     }
 
-    pub fn get_connection(&self) -> Result<std::sync::MutexGuard<Connection>> {
+    pub fn get_connection(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
         match self.connection.lock() {
             Ok(guard) => Ok(guard),
             Err(e) => {
@@ -26,42 +37,106 @@ impl CoreLocalStorage {
         }
     }
 
-    pub fn get_existing_by_id(&self, table_name: &str, id: &str) -> Result<Vec<serde_json::Value>> {
+    fn get_transactions(&self) -> Result<std::sync::MutexGuard<'_, Vec<Option<String>>>> {
+        self.transactions.lock().map_err(|e| {
+            eprintln!("Failed to acquire transaction lock: {:?}", e);
+            rusqlite::Error::ExecuteReturnedResults
+        })
+    }
+
+    /// Starts a transaction. If one is already open on this connection (a
+    /// write batch or an enclosing workflow), a savepoint is opened instead,
+    /// so every `begin` is closed by exactly one `commit` or `rollback`.
+    pub fn begin(&self) -> Result<()> {
         let conn = self.get_connection()?;
-        let query = format!("SELECT * FROM {} WHERE deleted = 0 AND id = ?", table_name);
+        let mut transactions = self.get_transactions()?;
 
-        let mut stmt = conn.prepare(&query)?;
+        if conn.is_autocommit() {
+            conn.execute_batch("BEGIN IMMEDIATE")?;
+            transactions.push(None);
+        } else {
+            let name = format!("nested_{}", transactions.len());
+            conn.execute_batch(&format!("SAVEPOINT \"{}\"", name))?;
+            transactions.push(Some(name));
+        }
 
-        let column_names: Vec<String> = stmt
-            .column_names()
-            .into_iter()
-            .map(|name| name.to_string())
-            .collect();
+        Ok(())
+    }
 
-        let rows = stmt.query_map(params![id], |row| {
-            let mut map = serde_json::Map::new();
-            for (i, column_name) in column_names.iter().enumerate() {
-                let value = self.get_value_from_row(row, i)?;
-                map.insert(column_name.to_string(), value);
-            }
-            Ok(serde_json::Value::Object(map))
-        })?;
+    pub fn commit(&self) -> Result<()> {
+        let conn = self.get_connection()?;
+        match self.get_transactions()?.pop() {
+            Some(None) => conn.execute_batch("COMMIT").inspect_err(|_| {
+                if let Err(e) = conn.execute_batch("ROLLBACK") {
+                    eprintln!("Failed to roll back after failed commit: {:?}", e);
+                }
+            }),
+            Some(Some(name)) => conn.execute_batch(&format!("RELEASE \"{}\"", name)),
+            None => Err(no_transaction()),
+        }
+    }
 
-        let mut results = Vec::new();
-        for row_result in rows {
-            if let Ok(row_value) = row_result {
-                results.push(row_value);
+    pub fn rollback(&self) -> Result<()> {
+        let conn = self.get_connection()?;
+        match self.get_transactions()?.pop() {
+            Some(None) => conn.execute_batch("ROLLBACK"),
+            Some(Some(name)) => {
+                conn.execute_batch(&format!("ROLLBACK TO \"{0}\"; RELEASE \"{0}\"", name))
             }
+            None => Err(no_transaction()),
         }
+    }
 
-        Ok(results)
+    /// Opens a named savepoint inside the current transaction. Work after it
+    /// can be undone with `rollback_to` without giving up earlier changes.
+    pub fn savepoint(&self, name: &str) -> Result<()> {
+        self.get_connection()?
+            .execute_batch(&format!("SAVEPOINT \"{}\"", name))
+    }
+
+    /// Undoes everything since `savepoint(name)`. The savepoint stays open and
+    /// still has to be released.
+    pub fn rollback_to(&self, name: &str) -> Result<()> {
+        self.get_connection()?
+            .execute_batch(&format!("ROLLBACK TO \"{}\"", name))
     }
 
+    pub fn release(&self, name: &str) -> Result<()> {
+        self.get_connection()?
+            .execute_batch(&format!("RELEASE \"{}\"", name))
+    }
+
+    #[tracing::instrument(name = "db.get_existing_by_id", skip_all, fields(db.table = table_name))]
+    pub fn get_existing_by_id(&self, table_name: &str, id: &str) -> Result<Vec<serde_json::Value>> {
+        let query = SqlBuilder::for_table(table_name)?.select_where(&["deleted", "id"])?;
+        self.query_json(&query, params![0, id])
+    }
+
+    #[tracing::instrument(name = "db.get_by_id", skip_all, fields(db.table = table_name))]
     pub fn get_by_id(&self, table_name: &str, id: &str) -> Result<Vec<serde_json::Value>> {
+        let query = SqlBuilder::for_table(table_name)?.select_where(&["id"])?;
+        self.query_json(&query, params![id])
+    }
+
+    #[tracing::instrument(name = "db.get_columns_by_id", skip_all, fields(db.table = table_name))]
+    pub fn get_columns_by_id(
+        &self,
+        table_name: &str,
+        id: &str,
+        columns: &[&str],
+    ) -> Result<Vec<serde_json::Value>> {
+        let query = SqlBuilder::for_table(table_name)?.select_columns_where(columns, &["id"])?;
+        self.query_json(&query, params![id])
+    }
+
+    fn query_json(
+        &self,
+        query: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<serde_json::Value>> {
         let conn = self.get_connection()?;
-        let query = format!("SELECT * FROM {} WHERE id = ?", table_name);
 
-        let mut stmt = conn.prepare(&query)?;
+        let mut stmt = conn.prepare(query)?;
 
         let column_names: Vec<String> = stmt
             .column_names()
@@ -69,7 +144,7 @@ impl CoreLocalStorage {
             .map(|name| name.to_string())
             .collect();
 
-        let rows = stmt.query_map(params![id], |row| {
+        let rows = stmt.query_map(params, |row| {
             let mut map = serde_json::Map::new();
             for (i, column_name) in column_names.iter().enumerate() {
                 let value = self.get_value_from_row(row, i)?;
@@ -79,10 +154,8 @@ impl CoreLocalStorage {
         })?;
 
         let mut results = Vec::new();
-        for row_result in rows {
-            if let Ok(row_value) = row_result {
-                results.push(row_value);
-            }
+        for row_value in rows.flatten() {
+            results.push(row_value);
         }
 
         Ok(results)
@@ -117,28 +190,16 @@ impl CoreLocalStorage {
         }
     }
 
+    #[tracing::instrument(name = "db.insert", skip_all, fields(db.table = table_name))]
     pub fn insert(&self, table_name: &str, data: &serde_json::Value) -> Result<i64> {
-        if let serde_json::Value::Object(map) = data {
-            let conn = self.get_connection()?;
-            let columns: Vec<String> = map.keys().cloned().collect();
-            let placeholders: Vec<String> = (0..columns.len()).map(|_| "?".to_string()).collect();
-
-            let column_str = columns.join(", ");
-            let placeholder_str = placeholders.join(", ");
-
-            let query = format!(
-                "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
-                table_name, column_str, placeholder_str
-            );
+        let data = search_key::with_keys(table_name, data);
+        if let serde_json::Value::Object(map) = data.as_ref() {
+            let columns: Vec<&str> = map.keys().map(String::as_str).collect();
+            let query = SqlBuilder::for_table(table_name)?.insert_or_replace(&columns)?;
 
+            let conn = self.get_connection()?;
             let mut stmt = conn.prepare(&query)?;
-            let mut param_values = Vec::new();
-
-            for col in &columns {
-                if let Some(value) = map.get(col) {
-                    param_values.push(json_to_param(value));
-                }
-            }
+            let param_values: Vec<_> = map.values().map(json_to_param).collect();
 
             stmt.execute(rusqlite::params_from_iter(param_values))?;
             Ok(conn.last_insert_rowid())
@@ -149,8 +210,10 @@ impl CoreLocalStorage {
         }
     }
 
+    #[tracing::instrument(name = "db.update", skip_all, fields(db.table = table_name))]
     pub fn update(&self, table_name: &str, data: &serde_json::Value) -> Result<usize> {
-        if let serde_json::Value::Object(map) = data {
+        let data = search_key::with_keys(table_name, data);
+        if let serde_json::Value::Object(map) = data.as_ref() {
             if !map.contains_key("id") {
                 return Err(rusqlite::Error::InvalidParameterName(
                     "Data must contain an 'id' field".to_string(),
@@ -175,22 +238,11 @@ impl CoreLocalStorage {
                 }
             };
 
+            let builder = SqlBuilder::for_table(table_name)?;
             let conn = self.get_connection()?;
 
-            let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table_name))?;
-            let columns = stmt.query_map([], |row| Ok(row.get::<_, String>(1)?))?;
-            let mut has_last_edit = false;
-            for column_result in columns {
-                let column_name = column_result?;
-                if column_name == "lastEdit" {
-                    has_last_edit = true;
-                    break;
-                }
-            }
-
-            if !has_last_edit {
-            } else {
-                let query = format!("SELECT lastEdit FROM {} WHERE id = ?", table_name);
+            if builder.has_column("lastEdit") {
+                let query = builder.select_column_where("lastEdit", &["id"])?;
                 let mut stmt = conn.prepare(&query)?;
 
                 let existing_last_edit: i64 =
@@ -210,15 +262,14 @@ impl CoreLocalStorage {
 
             for (key, value) in map {
                 if key != "id" {
-                    updates.push(format!("{} = ?", key));
+                    updates.push(key.as_str());
                     param_values.push(json_to_param(value));
                 }
             }
 
             param_values.push(json_to_param(id));
 
-            let update_str = updates.join(", ");
-            let query = format!("UPDATE {} SET {} WHERE id = ?", table_name, update_str);
+            let query = builder.update_where(&updates, &["id"])?;
 
             let mut stmt = conn.prepare(&query)?;
             let rows_affected = stmt.execute(rusqlite::params_from_iter(param_values))?;
@@ -230,6 +281,85 @@ impl CoreLocalStorage {
         }
     }
 
+    /// Writes the fields of `data` that are columns of the table, overwriting
+    /// just those columns of an existing row regardless of lastEdit, or
+    /// inserting the row.
+    /// Arrays of bytes are stored as blobs in BLOB columns.
+    #[tracing::instrument(name = "db.upsert", skip_all, fields(db.table = table_name))]
+    pub fn upsert(&self, table_name: &str, data: &serde_json::Value) -> Result<usize> {
+        let data = search_key::with_keys(table_name, data);
+        let (Some(table), serde_json::Value::Object(map)) =
+            (schema::table(table_name), data.as_ref())
+        else {
+            return Err(rusqlite::Error::InvalidParameterName(
+                "Data must be a JSON object of a known table".to_string(),
+            ));
+        };
+        if !map.contains_key("id") {
+            return Err(rusqlite::Error::InvalidParameterName(
+                "Data must contain an 'id' field".to_string(),
+            ));
+        }
+
+        let mut columns = Vec::new();
+        let mut param_values = Vec::new();
+        for (key, value) in map {
+            let Some(column) = table.column(key) else {
+                continue;
+            };
+            columns.push(column.name);
+            param_values.push(match value {
+                serde_json::Value::Array(bytes) if column.definition.contains("BLOB") => {
+                    let bytes = bytes
+                        .iter()
+                        .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                        .collect::<Option<Vec<u8>>>()
+                        .ok_or_else(|| {
+                            rusqlite::Error::InvalidColumnType(
+                                0,
+                                column.name.to_string(),
+                                rusqlite::types::Type::Blob,
+                            )
+                        })?;
+                    Box::new(bytes) as Box<dyn rusqlite::ToSql>
+                }
+                value => json_to_param(value),
+            });
+        }
+
+        let builder = SqlBuilder::for_table(table_name)?;
+        let id_index = columns
+            .iter()
+            .position(|column| *column == "id")
+            .unwrap_or(0);
+        let mut update_columns = columns.clone();
+        update_columns.remove(id_index);
+        let conn = self.get_connection()?;
+
+        if !update_columns.is_empty() {
+            let mut update_params: Vec<&dyn rusqlite::ToSql> = param_values
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != id_index)
+                .map(|(_, value)| value.as_ref())
+                .collect();
+            update_params.push(param_values[id_index].as_ref());
+            let updated = conn.execute(
+                &builder.update_where(&update_columns, &["id"])?,
+                update_params.as_slice(),
+            )?;
+            if updated > 0 {
+                return Ok(updated);
+            }
+        }
+
+        conn.execute(
+            &builder.insert_or_replace(&columns)?,
+            rusqlite::params_from_iter(param_values),
+        )
+    }
+
+    #[tracing::instrument(name = "db.insert_or_update", skip_all, fields(db.table = table_name))]
     pub fn insert_or_update(&self, table_name: &str, data: &serde_json::Value) -> Result<bool> {
         if let serde_json::Value::Object(map) = data {
             if !map.contains_key("id") {
@@ -237,22 +367,18 @@ impl CoreLocalStorage {
                     "Data must contain an 'id' field".to_string(),
                 ));
             }
-    
+
             let id = map.get("id").unwrap().as_str().unwrap_or("");
-            
-            let existing = match self.get_by_id(table_name, id) {
-                Ok(records) => records,
-                Err(e) => return Err(e)
-            };
-    
+
+            let existing = self.get_columns_by_id(table_name, id, &["id", "deleted"])?;
+
             if !existing.is_empty() {
-                if let Some(item) = existing.first() {
-                    if let Some(deleted) = item.get("deleted") {
-                        if deleted.as_i64() == Some(0) {
-                            self.update(table_name, data)?;
-                            return Ok(true);
-                        }
-                    }
+                if let Some(item) = existing.first()
+                    && let Some(deleted) = item.get("deleted")
+                    && deleted.as_i64() == Some(0)
+                {
+                    self.update(table_name, data)?;
+                    return Ok(true);
                 }
             } else {
                 self.insert(table_name, data)?;
@@ -263,35 +389,70 @@ impl CoreLocalStorage {
                 "Data must be a JSON object".to_string(),
             ));
         }
-    
+
         Ok(false)
     }
 
+    #[tracing::instrument(name = "db.delete_by_column", skip_all, fields(db.table = table_name))]
     pub fn delete_by_column(
         &self,
         table_name: &str,
         column_name: &str,
         value: &str,
     ) -> Result<usize> {
+        let query = SqlBuilder::for_table(table_name)?.delete_where(&[column_name])?;
         let conn = self.get_connection()?;
-        let query = format!("DELETE FROM {} WHERE {} = ?", table_name, column_name);
 
-        let result = conn.execute(&query, params![value]);
-        result
+        conn.execute(&query, params![value])
     }
 
+    #[tracing::instrument(name = "db.get_tombstones", skip_all, fields(db.table = table_name))]
+    pub fn get_tombstones(
+        &self,
+        table_name: &str,
+        since: i64,
+        after_id: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, i64)>> {
+        let table = SqlBuilder::for_table(table_name)?;
+        if !table.has_column("deleted") || !table.has_column("arrivalAtServer") {
+            return Err(rusqlite::Error::InvalidColumnName(format!(
+                "{} has no tombstones",
+                table_name
+            )));
+        }
+        let query = format!(
+            "SELECT id, arrivalAtServer FROM \"{}\" WHERE deleted = 1
+             AND (arrivalAtServer > ?1 OR (arrivalAtServer = ?1 AND id > ?2))
+             ORDER BY arrivalAtServer ASC, id ASC LIMIT ?3",
+            table_name
+        );
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(&query)?;
+
+        let rows = stmt.query_map(params![since, after_id, limit as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        rows.collect()
+    }
+
+    #[tracing::instrument(name = "db.mark_as_deleted", skip_all, fields(db.table = table_name))]
     pub fn mark_as_deleted(&self, table_name: &str, id: &str) -> Result<usize> {
+        let query = SqlBuilder::for_table(table_name)?
+            .update_where(&["deleted", "lastEdit", "arrivalAtServer"], &["id"])?;
         let conn = self.get_connection()?;
-        
+
         let current_time = chrono::Utc::now().timestamp_millis();
-        let query = format!("UPDATE {} SET deleted = 1, lastEdit = ?, arrivalAtServer = ? WHERE id = ?", table_name);
-        
-        let result = conn.execute(&query, params![current_time, current_time, id])?;
-        
+        let result = conn.execute(&query, params![1, current_time, current_time, id])?;
+
         Ok(result)
     }
 }
 
+fn no_transaction() -> rusqlite::Error {
+    rusqlite::Error::InvalidParameterName("No open transaction".to_string())
+}
+
 fn json_to_param(value: &serde_json::Value) -> Box<dyn rusqlite::ToSql> {
     match value {
         serde_json::Value::Null => Box::new(Option::<String>::None),
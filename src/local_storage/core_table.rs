@@ -0,0 +1,164 @@
+use rusqlite::{Connection, Result};
+use serde_json::{Value, json};
+
+pub struct Column {
+    pub name: &'static str,
+    pub definition: &'static str,
+}
+
+impl Column {
+    pub const fn new(name: &'static str, definition: &'static str) -> Self {
+        Column { name, definition }
+    }
+
+    pub fn declared_type(&self) -> &'static str {
+        self.definition
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+    }
+
+    pub fn not_null(&self) -> bool {
+        self.definition.contains("NOT NULL")
+    }
+}
+
+pub struct ColumnInfo {
+    pub name: String,
+    pub declared_type: String,
+    pub not_null: bool,
+}
+
+pub struct Table {
+    pub name: &'static str,
+    pub columns: &'static [Column],
+    pub constraints: &'static [&'static str],
+}
+
+impl Table {
+    pub fn column(&self, name: &str) -> Option<&'static Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    pub fn create_statement(&self) -> String {
+        self.create_statement_named(self.name)
+    }
+
+    fn create_statement_named(&self, name: &str) -> String {
+        let mut parts: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| format!("{} {}", column.name, column.definition))
+            .collect();
+        parts.extend(self.constraints.iter().map(|c| c.to_string()));
+
+        format!("CREATE TABLE IF NOT EXISTS {} ({})", name, parts.join(", "))
+    }
+
+    pub fn rebuild(&self, conn: &Connection) -> Result<()> {
+        let existing = existing_columns(conn, self.name)?;
+        let columns = self
+            .columns
+            .iter()
+            .filter(|column| existing.iter().any(|name| name == column.name))
+            .map(|column| column.name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let rebuilt_name = format!("{}_rebuild", self.name);
+
+        conn.execute_batch(&format!(
+            "BEGIN;
+             DROP TABLE IF EXISTS {rebuilt};
+             {create};
+             INSERT INTO {rebuilt} ({columns}) SELECT {columns} FROM {table};
+             DROP TABLE {table};
+             ALTER TABLE {rebuilt} RENAME TO {table};
+             COMMIT;",
+            rebuilt = rebuilt_name,
+            create = self.create_statement_named(&rebuilt_name),
+            table = self.name,
+        ))
+        .inspect_err(|_| {
+            let _ = conn.execute_batch("ROLLBACK");
+        })
+    }
+
+    pub fn ensure(&self, conn: &Connection) -> Result<()> {
+        conn.execute_batch(&self.create_statement())?;
+
+        let existing = existing_columns(conn, self.name)?;
+        for column in self.columns {
+            if !existing.iter().any(|name| name == column.name) {
+                println!("Adding column {}.{}", self.name, column.name);
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    self.name, column.name, column.definition
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub enum FieldDefault {
+    Now,
+    Integer(i64),
+    Real(f64),
+    Text(&'static str),
+}
+
+impl FieldDefault {
+    fn value(&self) -> Value {
+        match self {
+            FieldDefault::Now => json!(chrono::Utc::now().timestamp_millis()),
+            FieldDefault::Integer(value) => json!(value),
+            FieldDefault::Real(value) => json!(value),
+            FieldDefault::Text(value) => json!(value),
+        }
+    }
+}
+
+pub struct EntityDefaults {
+    pub table: &'static str,
+    pub message_type: &'static str,
+    pub fields: &'static [(&'static str, FieldDefault)],
+}
+
+impl EntityDefaults {
+    pub fn apply(&self, data: &mut Value) -> bool {
+        let Some(map) = data.as_object_mut() else {
+            return false;
+        };
+
+        let mut applied = false;
+        for (field, default) in self.fields {
+            if map.get(*field).is_none_or(Value::is_null) {
+                map.insert(field.to_string(), default.value());
+                applied = true;
+            }
+        }
+
+        applied
+    }
+}
+
+pub fn existing_columns(conn: &Connection, table_name: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table_name))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+
+    columns.collect()
+}
+
+pub fn column_info(conn: &Connection, table_name: &str) -> Result<Vec<ColumnInfo>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table_name))?;
+    let columns = stmt.query_map([], |row| {
+        Ok(ColumnInfo {
+            name: row.get(1)?,
+            declared_type: row.get(2)?,
+            not_null: row.get::<_, i64>(3)? != 0,
+        })
+    })?;
+
+    columns.collect()
+}
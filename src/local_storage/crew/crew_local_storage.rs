@@ -0,0 +1,103 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub struct CrewLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl CrewLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = CrewLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_member_ids(&self, crew_id: &str) -> Result<Vec<String>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare("SELECT userId FROM crewMemberJunction WHERE crewId = ?")?;
+
+        let rows = stmt.query_map(params![crew_id], |row| row.get::<_, String>(0))?;
+        rows.collect()
+    }
+
+    #[tracing::instrument(name = "db.get_crew_updates_by_date", skip(self))]
+    pub fn get_crew_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let crew_ids = {
+            let conn = self.core_storage.get_connection()?;
+            let mut stmt = conn.prepare(
+                "SELECT id FROM crews WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100",
+            )?;
+
+            let rows = stmt.query_map(params![last_edit], |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+
+        let mut crews = Vec::new();
+        for id in crew_ids.iter() {
+            match self.get_crew_by_id(id) {
+                Ok(crew) => crews.push(crew),
+                Err(e) => eprintln!("Error fetching crew {}: {}", id, e),
+            }
+        }
+
+        Ok(crews)
+    }
+
+    pub fn get_crew_by_id(&self, id: &str) -> Result<Value> {
+        let crew_json = self.core_storage.get_by_id("crews", id)?;
+
+        if crew_json.is_empty() {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+
+        let mut crew_data = crew_json[0].clone();
+        let member_ids = self.get_member_ids(id)?;
+
+        if let serde_json::Value::Object(ref mut map) = crew_data {
+            map.insert(
+                "memberIds".to_string(),
+                serde_json::Value::Array(
+                    member_ids
+                        .into_iter()
+                        .map(serde_json::Value::String)
+                        .collect(),
+                ),
+            );
+        }
+
+        Ok(crew_data)
+    }
+
+    pub fn save_crew(&self, crew_data: &Value) -> Result<bool> {
+        let crew_id = crew_data["id"].as_str().unwrap_or("");
+
+        if let Some(member_ids) = crew_data["memberIds"].as_array() {
+            self.core_storage
+                .delete_by_column("crewMemberJunction", "crewId", crew_id)?;
+
+            for member_value in member_ids {
+                if let Some(user_id) = member_value.as_str() {
+                    self.core_storage.insert(
+                        "crewMemberJunction",
+                        &serde_json::json!({ "crewId": crew_id, "userId": user_id }),
+                    )?;
+                }
+            }
+        }
+
+        let mut crew_for_save = crew_data.clone();
+        if let serde_json::Value::Object(ref mut map) = crew_for_save {
+            map.remove("memberIds");
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+        }
+
+        self.core_storage.insert_or_update("crews", &crew_for_save)
+    }
+}
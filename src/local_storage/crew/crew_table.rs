@@ -0,0 +1,26 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const CREW_TABLE: Table = Table {
+    name: "crews",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("name", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
+
+pub const CREW_MEMBER_JUNCTION_TABLE: Table = Table {
+    name: "crewMemberJunction",
+    columns: &[
+        Column::new("crewId", "TEXT NOT NULL"),
+        Column::new("userId", "TEXT NOT NULL"),
+    ],
+    constraints: &[
+        "PRIMARY KEY (crewId, userId)",
+        "FOREIGN KEY (crewId) REFERENCES crews(id) ON DELETE CASCADE",
+        "FOREIGN KEY (userId) REFERENCES users(id) ON DELETE CASCADE",
+    ],
+};
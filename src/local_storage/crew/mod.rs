@@ -0,0 +1,2 @@
+pub mod crew_local_storage;
+pub mod crew_table;
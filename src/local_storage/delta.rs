@@ -0,0 +1,50 @@
+use crate::local_storage::contract::contract_local_storage::ContractLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use rusqlite::Result;
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+
+pub const DELTA_TYPES: &[&str] = &["location_update", "contract_update"];
+
+pub fn delta_type(msg_type: &str) -> String {
+    format!("{}_delta", msg_type.trim_end_matches("_update"))
+}
+
+/// Stored version of an entity, with its relations, as clients received it.
+pub fn base_version(
+    core_storage: Arc<CoreLocalStorage>,
+    msg_type: &str,
+    id: &str,
+) -> Result<Option<Value>> {
+    match msg_type {
+        "location_update" => {
+            match LocationLocalStorage::new(core_storage)?.get_location_by_id(id) {
+                Ok(location) => Ok(Some(location)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+        "contract_update" => ContractLocalStorage::new(core_storage)?.get_contract_by_id(id),
+        _ => Ok(None),
+    }
+}
+
+/// Fields of `current` that differ from `base`, together with id, lastEdit and
+/// the lastEdit of the base the client must have to apply it.
+pub fn compute(base: &Value, current: &Value) -> Option<Value> {
+    let (Some(base_fields), Some(current_fields)) = (base.as_object(), current.as_object()) else {
+        return None;
+    };
+
+    let mut delta: Map<String, Value> = current_fields
+        .iter()
+        .filter(|(field, value)| base_fields.get(*field) != Some(*value))
+        .map(|(field, value)| (field.clone(), value.clone()))
+        .collect();
+    delta.insert("id".to_string(), current["id"].clone());
+    delta.insert("lastEdit".to_string(), current["lastEdit"].clone());
+    delta.insert("baseLastEdit".to_string(), json!(base["lastEdit"]));
+
+    Some(Value::Object(delta))
+}
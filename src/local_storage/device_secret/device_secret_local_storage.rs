@@ -0,0 +1,60 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub struct DeviceSecretLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl DeviceSecretLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = DeviceSecretLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_secret(&self, user_id: &str, device_id: &str) -> Result<Option<String>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT secret FROM deviceSecrets WHERE userId = ? AND deviceId = ?",
+            params![user_id, device_id],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    /// Enrols the device and returns its new secret, or None if it was
+    /// enrolled concurrently.
+    pub fn create_secret(&self, user_id: &str, device_id: &str) -> Result<Option<String>> {
+        let conn = self.core_storage.get_connection()?;
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO deviceSecrets (userId, deviceId, secret, createdAt)
+             VALUES (?, ?, ?, ?)",
+            params![
+                user_id,
+                device_id,
+                secret,
+                chrono::Utc::now().timestamp_millis()
+            ],
+        )?;
+
+        Ok((inserted > 0).then_some(secret))
+    }
+
+    pub fn record_use(&self, user_id: &str, device_id: &str) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "UPDATE deviceSecrets SET lastUsedAt = ? WHERE userId = ? AND deviceId = ?",
+            params![chrono::Utc::now().timestamp_millis(), user_id, device_id],
+        )?;
+
+        Ok(())
+    }
+}
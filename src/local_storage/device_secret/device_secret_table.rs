@@ -0,0 +1,13 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const DEVICE_SECRET_TABLE: Table = Table {
+    name: "deviceSecrets",
+    columns: &[
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("deviceId", "TEXT NOT NULL"),
+        Column::new("secret", "TEXT NOT NULL"),
+        Column::new("createdAt", "INTEGER NOT NULL"),
+        Column::new("lastUsedAt", "INTEGER"),
+    ],
+    constraints: &["PRIMARY KEY (userId, deviceId)"],
+};
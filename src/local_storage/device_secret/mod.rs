@@ -0,0 +1,2 @@
+pub mod device_secret_local_storage;
+pub mod device_secret_table;
@@ -0,0 +1,339 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct LoggedEvent {
+    pub seq: i64,
+    pub timestamp: i64,
+    pub event_type: String,
+    pub min_role: i64,
+    pub payload: Value,
+}
+
+/// Who sent the update behind an event. Events the server generates itself
+/// have no provenance.
+#[derive(Clone, Default)]
+pub struct Provenance {
+    pub user_id: Option<String>,
+    pub device_id: Option<String>,
+    pub app_version: Option<String>,
+}
+
+impl Provenance {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "userId": self.user_id,
+            "deviceId": self.device_id,
+            "appVersion": self.app_version
+        })
+    }
+}
+
+pub struct EventLogLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl EventLogLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = EventLogLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn append(
+        &self,
+        event_type: &str,
+        min_role: i64,
+        payload: &Value,
+        timestamp: i64,
+        provenance: &Provenance,
+    ) -> Result<i64> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO eventLog (timestamp, eventType, entityId, minRole, payload, userId,
+                                   deviceId, appVersion)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                timestamp,
+                event_type,
+                payload["id"].as_str(),
+                min_role,
+                payload.to_string(),
+                provenance.user_id,
+                provenance.device_id,
+                provenance.app_version
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get_events_after(&self, seq: i64, role: i64, limit: i64) -> Result<Vec<LoggedEvent>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT seq, timestamp, eventType, minRole, payload FROM eventLog
+             WHERE seq > ? AND minRole <= ? ORDER BY seq LIMIT ?",
+        )?;
+
+        let rows = stmt.query_map(params![seq, role, limit], |row| {
+            let payload: String = row.get(4)?;
+            Ok(LoggedEvent {
+                seq: row.get(0)?,
+                timestamp: row.get(1)?,
+                event_type: row.get(2)?,
+                min_role: row.get(3)?,
+                payload: serde_json::from_str(&payload).unwrap_or(Value::Null),
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Every logged version of one entity, newest first, with the provenance
+    /// of the update that produced it.
+    pub fn get_entity_history(
+        &self,
+        event_type: &str,
+        entity_id: &str,
+        role: i64,
+        limit: i64,
+    ) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT seq, timestamp, payload, userId, deviceId, appVersion, compacted
+             FROM eventLog
+             WHERE eventType = ? AND entityId = ? AND minRole <= ?
+             ORDER BY seq DESC LIMIT ?",
+        )?;
+
+        let rows = stmt.query_map(params![event_type, entity_id, role, limit], history_entry)?;
+        rows.collect()
+    }
+
+    /// The entity as it was after the update with lastEdit `last_edit`, in
+    /// the shape of a history entry. Partial updates only log the fields they
+    /// set, so the data is rebuilt from every logged version up to that one.
+    pub fn get_entity_version(
+        &self,
+        event_type: &str,
+        entity_id: &str,
+        role: i64,
+        last_edit: i64,
+    ) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT seq, timestamp, payload, userId, deviceId, appVersion, compacted
+             FROM eventLog
+             WHERE eventType = ?1 AND entityId = ?2 AND minRole <= ?3
+             AND seq <= (
+                 SELECT MAX(seq) FROM eventLog
+                 WHERE eventType = ?1 AND entityId = ?2 AND minRole <= ?3
+                 AND json_extract(payload, '$.lastEdit') = ?4
+             )
+             ORDER BY seq",
+        )?;
+
+        let mut versions = stmt
+            .query_map(
+                params![event_type, entity_id, role, last_edit],
+                history_entry,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+        let mut data = serde_json::Map::new();
+        for version in &versions {
+            if let Some(fields) = version["data"].as_object() {
+                data.extend(fields.clone());
+            }
+        }
+
+        Ok(versions.pop().map(|mut version| {
+            version["data"] = Value::Object(data);
+            version
+        }))
+    }
+
+    pub fn get_last_seq_before(&self, timestamp: i64) -> Result<i64> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT COALESCE(MAX(seq), 0) FROM eventLog WHERE timestamp < ?",
+            params![timestamp],
+            |row| row.get(0),
+        )
+    }
+
+    /// Folds the events the server generated itself into the newest *_update
+    /// event per entity, type and role among the events up to `up_to_seq`;
+    /// the survivor counts the events folded into it. Client updates carry
+    /// provenance and stay, since history and conflict detection read them.
+    /// Returns the number of deleted rows.
+    pub fn compact(&self, up_to_seq: i64) -> Result<usize> {
+        let conn = self.core_storage.get_connection()?;
+        let tx = conn.unchecked_transaction()?;
+
+        tx.execute(
+            "UPDATE eventLog
+             SET compacted = compacted + (
+                 SELECT COUNT(*) + COALESCE(SUM(older.compacted), 0) FROM eventLog older
+                 WHERE older.eventType = eventLog.eventType
+                 AND older.entityId = eventLog.entityId
+                 AND older.minRole = eventLog.minRole
+                 AND older.seq < eventLog.seq
+                 AND older.userId IS NULL
+             )
+             WHERE seq IN (
+                 SELECT MAX(seq) FROM eventLog
+                 WHERE seq <= ?1 AND entityId IS NOT NULL AND substr(eventType, -7) = '_update'
+                 GROUP BY eventType, entityId, minRole
+                 HAVING COUNT(*) > 1
+             )",
+            params![up_to_seq],
+        )?;
+        let deleted = tx.execute(
+            "DELETE FROM eventLog
+             WHERE seq <= ?1 AND entityId IS NOT NULL AND substr(eventType, -7) = '_update'
+             AND userId IS NULL
+             AND seq < (
+                 SELECT MAX(newer.seq) FROM eventLog newer
+                 WHERE newer.eventType = eventLog.eventType
+                 AND newer.entityId = eventLog.entityId
+                 AND newer.minRole = eventLog.minRole
+                 AND newer.seq <= ?1
+             )",
+            params![up_to_seq],
+        )?;
+        tx.commit()?;
+
+        Ok(deleted)
+    }
+
+    /// Events after `seq` for every role, with their provenance, as a standby
+    /// replays them.
+    pub fn get_replication_page(&self, seq: i64, limit: i64) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT seq, timestamp, eventType, entityId, minRole, payload, compacted, userId,
+                    deviceId, appVersion
+             FROM eventLog WHERE seq > ? ORDER BY seq LIMIT ?",
+        )?;
+
+        let rows = stmt.query_map(params![seq, limit], |row| {
+            let payload: String = row.get(5)?;
+            Ok(json!({
+                "seq": row.get::<_, i64>(0)?,
+                "timestamp": row.get::<_, i64>(1)?,
+                "eventType": row.get::<_, String>(2)?,
+                "entityId": row.get::<_, Option<String>>(3)?,
+                "minRole": row.get::<_, i64>(4)?,
+                "payload": serde_json::from_str::<Value>(&payload).unwrap_or(Value::Null),
+                "compacted": row.get::<_, i64>(6)?,
+                "userId": row.get::<_, Option<String>>(7)?,
+                "deviceId": row.get::<_, Option<String>>(8)?,
+                "appVersion": row.get::<_, Option<String>>(9)?
+            }))
+        })?;
+        rows.collect()
+    }
+
+    /// Stores an event of the primary under its original seq.
+    pub fn insert_replicated(&self, event: &Value) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO eventLog (seq, timestamp, eventType, entityId, minRole,
+                                              payload, compacted, userId, deviceId, appVersion)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                event["seq"].as_i64(),
+                event["timestamp"].as_i64(),
+                event["eventType"].as_str(),
+                event["entityId"].as_str(),
+                event["minRole"].as_i64().unwrap_or(0),
+                event["payload"].to_string(),
+                event["compacted"].as_i64().unwrap_or(0),
+                event["userId"].as_str(),
+                event["deviceId"].as_str(),
+                event["appVersion"].as_str()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_last_seq(&self) -> Result<i64> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM eventLog", [], |row| {
+            row.get(0)
+        })
+    }
+}
+
+fn history_entry(row: &rusqlite::Row) -> Result<Value> {
+    let payload: String = row.get(2)?;
+    let provenance = Provenance {
+        user_id: row.get(3)?,
+        device_id: row.get(4)?,
+        app_version: row.get(5)?,
+    };
+    Ok(json!({
+        "seq": row.get::<_, i64>(0)?,
+        "timestamp": row.get::<_, i64>(1)?,
+        "data": serde_json::from_str::<Value>(&payload).unwrap_or(Value::Null),
+        "provenance": provenance.to_json(),
+        "compactedVersions": row.get::<_, i64>(6)?
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_storage::schema;
+
+    fn storage() -> EventLogLocalStorage {
+        let core_storage = CoreLocalStorage::new(":memory:").unwrap();
+        schema::migrate(&core_storage.get_connection().unwrap()).unwrap();
+        EventLogLocalStorage::new(Arc::new(core_storage)).unwrap()
+    }
+
+    fn client(user_id: &str) -> Provenance {
+        Provenance {
+            user_id: Some(user_id.to_string()),
+            device_id: Some("d1".to_string()),
+            app_version: Some("2.0.0".to_string()),
+        }
+    }
+
+    #[test]
+    fn compaction_keeps_client_updates_and_their_provenance() {
+        let storage = storage();
+        let server = Provenance::default();
+        for (quantity, provenance) in [
+            (1, client("u1")),
+            (2, server.clone()),
+            (3, client("u2")),
+            (4, server.clone()),
+            (5, server),
+        ] {
+            let data = json!({"id": "l1", "initialQuantity": quantity});
+            storage
+                .append("location_update", 0, &data, quantity, &provenance)
+                .unwrap();
+        }
+
+        assert_eq!(storage.compact(5).unwrap(), 2);
+
+        let history = storage
+            .get_entity_history("location_update", "l1", 0, 10)
+            .unwrap();
+        let quantities: Vec<_> = history
+            .iter()
+            .map(|version| version["data"]["initialQuantity"].as_i64().unwrap())
+            .collect();
+        assert_eq!(quantities, [5, 3, 1]);
+        assert_eq!(history[0]["compactedVersions"], 2);
+        assert_eq!(history[1]["provenance"]["userId"], "u2");
+        assert_eq!(history[2]["provenance"]["userId"], "u1");
+    }
+}
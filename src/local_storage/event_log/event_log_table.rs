@@ -0,0 +1,26 @@
+use crate::local_storage::core_table::{Column, Table};
+use rusqlite::{Connection, Result};
+
+pub const EVENT_LOG_TABLE: Table = Table {
+    name: "eventLog",
+    columns: &[
+        Column::new("seq", "INTEGER PRIMARY KEY AUTOINCREMENT"),
+        Column::new("timestamp", "INTEGER NOT NULL"),
+        Column::new("eventType", "TEXT NOT NULL"),
+        Column::new("entityId", "TEXT"),
+        Column::new("minRole", "INTEGER NOT NULL DEFAULT 0"),
+        Column::new("payload", "TEXT NOT NULL"),
+        Column::new("compacted", "INTEGER NOT NULL DEFAULT 0"),
+        Column::new("userId", "TEXT"),
+        Column::new("deviceId", "TEXT"),
+        Column::new("appVersion", "TEXT"),
+    ],
+    constraints: &[],
+};
+
+pub fn ensure_index(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS eventLogEntity
+         ON eventLog (eventType, entityId, minRole, seq)",
+    )
+}
@@ -0,0 +1,2 @@
+pub mod event_log_local_storage;
+pub mod event_log_table;
@@ -0,0 +1,89 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, Row, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct HookScript {
+    pub id: String,
+    pub name: String,
+    pub source: String,
+}
+
+pub struct HookScriptLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl HookScriptLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = HookScriptLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    fn row_to_json(row: &Row) -> Result<Value> {
+        Ok(json!({
+            "id": row.get::<_, String>("id")?,
+            "name": row.get::<_, String>("name")?,
+            "messageType": row.get::<_, String>("messageType")?,
+            "source": row.get::<_, String>("source")?,
+            "enabled": row.get::<_, i64>("enabled")?,
+            "lastEdit": row.get::<_, i64>("lastEdit")?,
+            "updatedBy": row.get::<_, String>("updatedBy")?
+        }))
+    }
+
+    pub fn get_scripts(&self) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare("SELECT * FROM hookScripts ORDER BY messageType, name")?;
+        stmt.query_map([], Self::row_to_json)?.collect()
+    }
+
+    pub fn get_enabled_scripts(&self, message_type: &str) -> Result<Vec<HookScript>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, source FROM hookScripts
+             WHERE messageType = ? AND enabled = 1 ORDER BY name, id",
+        )?;
+        stmt.query_map(params![message_type], |row| {
+            Ok(HookScript {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                source: row.get(2)?,
+            })
+        })?
+        .collect()
+    }
+
+    pub fn save_script(&self, script: &Value, updated_by: &str) -> Result<Value> {
+        let conn = self.core_storage.get_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO hookScripts
+             (id, name, messageType, source, enabled, lastEdit, updatedBy)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                script["id"].as_str(),
+                script["name"].as_str(),
+                script["messageType"].as_str(),
+                script["source"].as_str(),
+                script["enabled"].as_i64().unwrap_or(1),
+                chrono::Utc::now().timestamp_millis(),
+                updated_by
+            ],
+        )?;
+
+        conn.query_row(
+            "SELECT * FROM hookScripts WHERE id = ?",
+            params![script["id"].as_str()],
+            Self::row_to_json,
+        )
+    }
+
+    pub fn delete_script(&self, id: &str) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let changed = conn.execute("DELETE FROM hookScripts WHERE id = ?", params![id])?;
+
+        Ok(changed > 0)
+    }
+}
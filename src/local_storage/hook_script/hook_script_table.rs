@@ -0,0 +1,15 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const HOOK_SCRIPT_TABLE: Table = Table {
+    name: "hookScripts",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("name", "TEXT NOT NULL"),
+        Column::new("messageType", "TEXT NOT NULL"),
+        Column::new("source", "TEXT NOT NULL"),
+        Column::new("enabled", "INTEGER DEFAULT 1"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("updatedBy", "TEXT NOT NULL"),
+    ],
+    constraints: &[],
+};
@@ -0,0 +1,2 @@
+pub mod hook_script_local_storage;
+pub mod hook_script_table;
@@ -0,0 +1,131 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct IntegrationToken {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub ip_allowlist: Vec<String>,
+    pub expires_at: i64,
+}
+
+pub struct IntegrationTokenLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl IntegrationTokenLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = IntegrationTokenLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_active_token(&self, token: &str) -> Result<Option<IntegrationToken>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT id, name, scopes, ipAllowlist, expiresAt FROM integrationTokens
+             WHERE token = ? AND revoked = 0",
+            params![token],
+            |row| {
+                let list = |index: usize| -> Result<Vec<String>> {
+                    let raw: String = row.get(index)?;
+                    Ok(serde_json::from_str(&raw).unwrap_or_default())
+                };
+                Ok(IntegrationToken {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    scopes: list(2)?,
+                    ip_allowlist: list(3)?,
+                    expires_at: row.get(4)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    pub fn create_token(
+        &self,
+        token: &str,
+        details: &IntegrationToken,
+        created_by: &str,
+    ) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO integrationTokens
+             (id, token, name, scopes, ipAllowlist, expiresAt, createdBy, createdAt, revoked)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, 0)",
+            params![
+                details.id,
+                token,
+                details.name,
+                json!(details.scopes).to_string(),
+                json!(details.ip_allowlist).to_string(),
+                details.expires_at,
+                created_by,
+                chrono::Utc::now().timestamp_millis()
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn record_use(&self, id: &str, ip: Option<&str>, timestamp: i64) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "UPDATE integrationTokens
+             SET useCount = useCount + 1, lastUsedAt = ?, lastUsedIp = ?
+             WHERE id = ?",
+            params![timestamp, ip, id],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_tokens(&self) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, scopes, ipAllowlist, expiresAt, createdBy, createdAt, revoked,
+             useCount, lastUsedAt, lastUsedIp
+             FROM integrationTokens ORDER BY createdAt DESC",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let list = |index: usize| -> Result<Value> {
+                let raw: String = row.get(index)?;
+                Ok(serde_json::from_str(&raw).unwrap_or_else(|_| json!([])))
+            };
+            Ok(json!({
+                "id": row.get::<_, String>(0)?,
+                "name": row.get::<_, String>(1)?,
+                "scopes": list(2)?,
+                "ipAllowlist": list(3)?,
+                "expiresAt": row.get::<_, i64>(4)?,
+                "createdBy": row.get::<_, String>(5)?,
+                "createdAt": row.get::<_, i64>(6)?,
+                "revoked": row.get::<_, i64>(7)? != 0,
+                "useCount": row.get::<_, i64>(8)?,
+                "lastUsedAt": row.get::<_, Option<i64>>(9)?,
+                "lastUsedIp": row.get::<_, Option<String>>(10)?
+            }))
+        })?;
+
+        rows.collect()
+    }
+
+    pub fn revoke_token(&self, id: &str) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let rows_affected = conn.execute(
+            "UPDATE integrationTokens SET revoked = 1 WHERE id = ? AND revoked = 0",
+            params![id],
+        )?;
+
+        Ok(rows_affected > 0)
+    }
+}
@@ -0,0 +1,20 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const INTEGRATION_TOKEN_TABLE: Table = Table {
+    name: "integrationTokens",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("token", "TEXT NOT NULL"),
+        Column::new("name", "TEXT NOT NULL"),
+        Column::new("scopes", "TEXT NOT NULL"),
+        Column::new("ipAllowlist", "TEXT NOT NULL DEFAULT '[]'"),
+        Column::new("expiresAt", "INTEGER NOT NULL"),
+        Column::new("createdBy", "TEXT NOT NULL"),
+        Column::new("createdAt", "INTEGER NOT NULL"),
+        Column::new("revoked", "INTEGER DEFAULT 0"),
+        Column::new("useCount", "INTEGER NOT NULL DEFAULT 0"),
+        Column::new("lastUsedAt", "INTEGER"),
+        Column::new("lastUsedIp", "TEXT"),
+    ],
+    constraints: &["UNIQUE (token)"],
+};
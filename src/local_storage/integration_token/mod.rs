@@ -0,0 +1,2 @@
+pub mod integration_token_local_storage;
+pub mod integration_token_table;
@@ -1,4 +1,5 @@
 use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::search::search_key;
 use rusqlite::{Result, params};
 use serde_json::Value;
 use std::sync::Arc;
@@ -17,9 +18,9 @@ impl LocationLocalStorage {
     }
 
     fn get_sawmill_ids(&self, id: &str, is_oversize: bool) -> Result<Vec<String>> {
-        let query = format!(
+        let query =
             "SELECT sawmillId FROM locationSawmillJunction WHERE locationId = ? AND isOversize = ?"
-        );
+                .to_string();
 
         let conn = self.core_storage.get_connection()?;
 
@@ -42,11 +43,10 @@ impl LocationLocalStorage {
         Ok(sawmill_ids)
     }
 
+    #[tracing::instrument(name = "db.get_location_updates_by_date", skip(self))]
     pub fn get_location_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
         let location_ids = {
-            let query = format!(
-                "SELECT id FROM locations WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100",
-            );
+            let query = "SELECT id FROM locations WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100".to_string();
 
             let conn = self.core_storage.get_connection()?;
             let mut stmt = conn.prepare(&query)?;
@@ -67,7 +67,7 @@ impl LocationLocalStorage {
         };
 
         let mut locations = Vec::new();
-        for (_, id) in location_ids.iter().enumerate() {
+        for id in location_ids.iter() {
             match self.get_location_by_id(id) {
                 Ok(location) => locations.push(location),
                 Err(e) => eprintln!("Error fetching location {}: {}", id, e),
@@ -89,12 +89,13 @@ impl LocationLocalStorage {
         let oversize_sawmill_ids = self.get_sawmill_ids(id, true)?;
 
         if let serde_json::Value::Object(ref mut map) = location_data {
+            search_key::strip("locations", map);
             map.insert(
                 "sawmillIds".to_string(),
                 serde_json::Value::Array(
                     sawmill_ids
                         .into_iter()
-                        .map(|id| serde_json::Value::String(id))
+                        .map(serde_json::Value::String)
                         .collect(),
                 ),
             );
@@ -103,7 +104,7 @@ impl LocationLocalStorage {
                 serde_json::Value::Array(
                     oversize_sawmill_ids
                         .into_iter()
-                        .map(|id| serde_json::Value::String(id))
+                        .map(serde_json::Value::String)
                         .collect(),
                 ),
             );
@@ -129,13 +130,31 @@ impl LocationLocalStorage {
     }
 
     pub fn save_location(&self, location_data: &Value) -> Result<bool> {
+        self.core_storage.savepoint("save_location")?;
+
+        match self.apply_location_save(location_data) {
+            Ok(saved) => {
+                self.core_storage.release("save_location")?;
+                Ok(saved)
+            }
+            Err(e) => {
+                if let Err(rollback_error) = self
+                    .core_storage
+                    .rollback_to("save_location")
+                    .and_then(|_| self.core_storage.release("save_location"))
+                {
+                    eprintln!("Failed to roll back location save: {:?}", rollback_error);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn apply_location_save(&self, location_data: &Value) -> Result<bool> {
         let location_id = location_data["id"].as_str().unwrap_or("");
 
-        self.core_storage.delete_by_column(
-            "locationSawmillJunction",
-            "locationId",
-            location_id,
-        )?;
+        self.core_storage
+            .delete_by_column("locationSawmillJunction", "locationId", location_id)?;
 
         if let Some(sawmill_ids) = location_data["sawmillIds"].as_array() {
             for sawmill_value in sawmill_ids {
@@ -157,7 +176,10 @@ impl LocationLocalStorage {
         if let serde_json::Value::Object(ref mut map) = location_for_save {
             map.remove("sawmillIds");
             map.remove("oversizeSawmillIds");
-            map.insert("arrivalAtServer".to_string(), chrono::Utc::now().timestamp_millis().into());
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
         }
 
         self.core_storage
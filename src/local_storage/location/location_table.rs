@@ -0,0 +1,78 @@
+use crate::local_storage::core_table::{Column, EntityDefaults, FieldDefault, Table};
+
+pub const LOCATION_TABLE: Table = Table {
+    name: "locations",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("done", "INTEGER NOT NULL"),
+        Column::new("started", "INTEGER NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("latitude", "REAL NOT NULL"),
+        Column::new("longitude", "REAL NOT NULL"),
+        Column::new("partieNr", "TEXT NOT NULL"),
+        Column::new("date", "INTEGER NOT NULL"),
+        Column::new("additionalInfo", "TEXT NOT NULL"),
+        Column::new("ownerInformation", "TEXT"),
+        Column::new("initialQuantity", "REAL NOT NULL"),
+        Column::new("initialOversizeQuantity", "REAL NOT NULL"),
+        Column::new("initialPieceCount", "INTEGER NOT NULL"),
+        Column::new("currentQuantity", "REAL NOT NULL"),
+        Column::new("currentOversizeQuantity", "REAL NOT NULL"),
+        Column::new("currentPieceCount", "INTEGER NOT NULL"),
+        Column::new("contractId", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("crewId", "TEXT"),
+        Column::new("unit", "TEXT DEFAULT 'fm'"),
+        Column::new("enteredUnit", "TEXT"),
+        Column::new("assortmentId", "TEXT"),
+        Column::new("partieNrSearch", "TEXT"),
+    ],
+    constraints: &[],
+};
+
+pub const LOCATION_SAWMILL_JUNCTION_TABLE: Table = Table {
+    name: "locationSawmillJunction",
+    columns: &[
+        Column::new("locationId", "TEXT NOT NULL"),
+        Column::new("sawmillId", "TEXT NOT NULL"),
+        Column::new("isOversize", "INTEGER NOT NULL"),
+    ],
+    constraints: &[
+        "PRIMARY KEY (locationId, sawmillId, isOversize)",
+        "FOREIGN KEY (locationId) REFERENCES locations(id) ON DELETE CASCADE",
+        "FOREIGN KEY (sawmillId) REFERENCES sawmills(id) ON DELETE CASCADE",
+    ],
+};
+
+pub const LOCATION_DEFAULTS: EntityDefaults = EntityDefaults {
+    table: "locations",
+    message_type: "location_update",
+    fields: &[
+        ("done", FieldDefault::Integer(0)),
+        ("started", FieldDefault::Integer(0)),
+        ("date", FieldDefault::Now),
+        ("additionalInfo", FieldDefault::Text("")),
+        ("initialOversizeQuantity", FieldDefault::Real(0.0)),
+        ("initialPieceCount", FieldDefault::Integer(0)),
+        ("currentOversizeQuantity", FieldDefault::Real(0.0)),
+        ("currentPieceCount", FieldDefault::Integer(0)),
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn new_locations_are_dated_now() {
+        let before = chrono::Utc::now().timestamp_millis();
+        let mut location = json!({"id": "l1", "currentPieceCount": 12});
+
+        assert!(LOCATION_DEFAULTS.apply(&mut location));
+        assert!(location["date"].as_i64().unwrap() >= before);
+        assert_eq!(location["currentPieceCount"], 12);
+        assert_eq!(location["initialOversizeQuantity"], 0.0);
+    }
+}
@@ -1 +1,2 @@
 pub mod location_local_storage;
+pub mod location_table;
@@ -1,8 +1,38 @@
-pub mod core_local_storage;
+pub mod activity_feed;
+pub mod activity_report;
+pub mod archive;
+pub mod assortment;
+pub mod audit_log;
+pub mod conflict;
 pub mod contract;
+pub mod core_local_storage;
+pub mod core_table;
+pub mod crew;
+pub mod delta;
+pub mod device_secret;
+pub mod event_log;
+pub mod hook_script;
+pub mod integration_token;
 pub mod location;
 pub mod note;
+pub mod permission;
 pub mod photo;
+pub mod portal_token;
+pub mod projection;
+pub mod quota_reservation;
+pub mod resync_request;
+pub mod review_item;
 pub mod sawmill;
+pub mod sawmill_price;
+pub mod schema;
+pub mod search;
+pub mod settings;
 pub mod shipment;
+pub mod shipment_document;
+pub mod signing_key;
+pub mod sla_stat;
+pub mod sql_builder;
+pub mod sync_session;
+pub mod tour;
 pub mod user;
+pub mod write_verification;
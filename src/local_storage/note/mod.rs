@@ -1 +1,2 @@
 pub mod note_local_storage;
+pub mod note_table;
@@ -1,5 +1,5 @@
 use crate::local_storage::core_local_storage::CoreLocalStorage;
-use rusqlite::{Result, params};
+use rusqlite::{Result, Row, params};
 use serde_json::Value;
 use std::sync::Arc;
 
@@ -16,33 +16,15 @@ impl NoteLocalStorage {
         Ok(storage)
     }
 
+    #[tracing::instrument(name = "db.get_note_updates_by_date", skip(self))]
     pub fn get_note_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
-        let query = format!(
-            "SELECT * FROM notes WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100",
-        );
+        let query = "SELECT * FROM notes WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100"
+            .to_string();
 
         let conn = self.core_storage.get_connection()?;
         let mut stmt = conn.prepare(&query)?;
-        
-        let rows = stmt.query_map(params![last_edit], |row| {
-            let id: String = row.get(0)?;
-            let last_edit: i64 = row.get(1)?;
-            let text: String = row.get(2)?;
-            let user_id: String = row.get(3)?;
-            let arrival_at_server: i64 = row.get(4)?;
-            let deleted: i64 = row.get(5)?;
-
-            let note_json = serde_json::json!({
-                "id": id,
-                "lastEdit": last_edit,
-                "text": text,
-                "userId": user_id,
-                "arrivalAtServer": arrival_at_server,
-                "deleted": deleted
-            });
-
-            Ok(note_json)
-        })?;
+
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
 
         let mut notes = Vec::new();
         for row in rows {
@@ -55,15 +37,60 @@ impl NoteLocalStorage {
         Ok(notes)
     }
 
+    pub fn get_notes_by_entity(&self, entity_type: &str, entity_id: &str) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM notes WHERE entityType = ? AND entityId = ? AND deleted = 0
+             ORDER BY lastEdit ASC",
+        )?;
+
+        let rows = stmt.query_map(params![entity_type, entity_id], row_to_json)?;
+        rows.collect()
+    }
+
+    pub fn tombstone_notes_for_entity(
+        &self,
+        entity_type: &str,
+        entity_id: &str,
+    ) -> Result<Vec<Value>> {
+        let notes = self.get_notes_by_entity(entity_type, entity_id)?;
+
+        let mut tombstones = Vec::new();
+        for note in notes {
+            let note_id = note["id"].as_str().unwrap_or_default();
+            self.core_storage.mark_as_deleted("notes", note_id)?;
+            tombstones.extend(self.core_storage.get_by_id("notes", note_id)?);
+        }
+
+        Ok(tombstones)
+    }
+
     pub fn save_note(&self, note_data: &Value) -> Result<bool> {
         let mut note_for_save = note_data.clone();
         if let serde_json::Value::Object(ref mut map) = note_for_save {
-            map.insert("arrivalAtServer".to_string(), chrono::Utc::now().timestamp_millis().into());
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
         }
 
-        let result = self.core_storage
+        let result = self
+            .core_storage
             .insert_or_update("notes", &note_for_save)?;
 
         Ok(result)
     }
 }
+
+fn row_to_json(row: &Row) -> Result<Value> {
+    Ok(serde_json::json!({
+        "id": row.get::<_, String>("id")?,
+        "lastEdit": row.get::<_, i64>("lastEdit")?,
+        "text": row.get::<_, String>("text")?,
+        "userId": row.get::<_, String>("userId")?,
+        "arrivalAtServer": row.get::<_, i64>("arrivalAtServer")?,
+        "deleted": row.get::<_, i64>("deleted")?,
+        "entityType": row.get::<_, Option<String>>("entityType")?,
+        "entityId": row.get::<_, Option<String>>("entityId")?
+    }))
+}
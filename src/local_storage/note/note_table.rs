@@ -0,0 +1,16 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const NOTE_TABLE: Table = Table {
+    name: "notes",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("text", "TEXT NOT NULL"),
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("entityType", "TEXT"),
+        Column::new("entityId", "TEXT"),
+    ],
+    constraints: &[],
+};
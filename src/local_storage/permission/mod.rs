@@ -0,0 +1,2 @@
+pub mod permission_local_storage;
+pub mod permission_table;
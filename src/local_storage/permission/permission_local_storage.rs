@@ -0,0 +1,135 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct PermissionLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl PermissionLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = PermissionLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_custom_role(&self, user_id: &str) -> Result<Option<String>> {
+        let conn = self.core_storage.get_connection()?;
+        let custom_role = conn
+            .query_row(
+                "SELECT customRole FROM users WHERE id = ? AND deleted = 0",
+                params![user_id],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?;
+
+        Ok(custom_role.flatten())
+    }
+
+    pub fn get_overrides(
+        &self,
+        roles: &[&str],
+        entity: &str,
+        action: &str,
+    ) -> Result<Vec<(String, bool)>> {
+        let conn = self.core_storage.get_connection()?;
+        let placeholders = vec!["?"; roles.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT role, allowed FROM permissions
+             WHERE entity = ? AND action = ? AND role IN ({})",
+            placeholders
+        ))?;
+
+        let mut values: Vec<&str> = vec![entity, action];
+        values.extend_from_slice(roles);
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? != 0))
+        })?;
+
+        rows.collect()
+    }
+
+    pub fn get_permissions(&self) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT role, entity, action, allowed, lastEdit, updatedBy
+             FROM permissions ORDER BY role, entity, action",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(json!({
+                "role": row.get::<_, String>(0)?,
+                "entity": row.get::<_, String>(1)?,
+                "action": row.get::<_, String>(2)?,
+                "allowed": row.get::<_, i64>(3)? != 0,
+                "lastEdit": row.get::<_, i64>(4)?,
+                "updatedBy": row.get::<_, Option<String>>(5)?
+            }))
+        })?;
+
+        rows.collect()
+    }
+
+    pub fn get_custom_roles(&self) -> Result<Vec<String>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT customRole FROM users WHERE customRole IS NOT NULL AND deleted = 0
+             UNION SELECT role FROM permissions
+             ORDER BY 1",
+        )?;
+
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect()
+    }
+
+    pub fn set_permission(
+        &self,
+        role: &str,
+        entity: &str,
+        action: &str,
+        allowed: Option<bool>,
+        updated_by: &str,
+    ) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+
+        let changed = match allowed {
+            Some(allowed) => conn.execute(
+                "INSERT INTO permissions (role, entity, action, allowed, lastEdit, updatedBy)
+                 VALUES (?, ?, ?, ?, ?, ?)
+                 ON CONFLICT (role, entity, action)
+                 DO UPDATE SET allowed = excluded.allowed, lastEdit = excluded.lastEdit,
+                               updatedBy = excluded.updatedBy",
+                params![
+                    role,
+                    entity,
+                    action,
+                    allowed as i64,
+                    chrono::Utc::now().timestamp_millis(),
+                    updated_by
+                ],
+            )?,
+            None => conn.execute(
+                "DELETE FROM permissions WHERE role = ? AND entity = ? AND action = ?",
+                params![role, entity, action],
+            )?,
+        };
+
+        Ok(changed > 0)
+    }
+
+    pub fn set_custom_role(&self, user_id: &str, custom_role: Option<&str>) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let changed = conn.execute(
+            "UPDATE users SET customRole = ?, lastEdit = ?, arrivalAtServer = ?
+             WHERE id = ? AND deleted = 0",
+            params![custom_role, now, now, user_id],
+        )?;
+
+        Ok(changed > 0)
+    }
+}
@@ -0,0 +1,14 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const PERMISSION_TABLE: Table = Table {
+    name: "permissions",
+    columns: &[
+        Column::new("role", "TEXT NOT NULL"),
+        Column::new("entity", "TEXT NOT NULL"),
+        Column::new("action", "TEXT NOT NULL"),
+        Column::new("allowed", "INTEGER NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("updatedBy", "TEXT"),
+    ],
+    constraints: &["PRIMARY KEY (role, entity, action)"],
+};
@@ -1 +1,2 @@
 pub mod photo_local_storage;
+pub mod photo_table;
@@ -1,12 +1,28 @@
+use crate::config;
 use crate::local_storage::core_local_storage::CoreLocalStorage;
-use rusqlite::{Result, params};
-use serde_json::Value;
+use crate::metrics;
+use crate::storage::{self, Root};
+use rusqlite::{DatabaseName, OptionalExtension, Result, Row, params};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
 use std::sync::Arc;
+use uuid::Uuid;
 
 pub struct PhotoLocalStorage {
     core_storage: Arc<CoreLocalStorage>,
 }
 
+/// Where a photo's file is stored, for streaming it.
+pub struct PhotoBlob {
+    pub row_id: i64,
+    pub size: u64,
+    pub format: Option<String>,
+    pub hash: Option<String>,
+}
+
 impl PhotoLocalStorage {
     pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
         let storage = PhotoLocalStorage {
@@ -16,33 +32,15 @@ impl PhotoLocalStorage {
         Ok(storage)
     }
 
+    #[tracing::instrument(name = "db.get_photo_updates_by_date", skip(self))]
     pub fn get_photo_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
-        let query = format!(
-            "SELECT * FROM photos WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 20",
-        );
+        let query = "SELECT * FROM photos WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 20"
+            .to_string();
 
         let conn = self.core_storage.get_connection()?;
         let mut stmt = conn.prepare(&query)?;
-        
-        let rows = stmt.query_map(params![last_edit], |row| {
-            let id: String = row.get(0)?;
-            let last_edit: i64 = row.get(1)?;
-            let photo_file: Vec<u8> = row.get(2)?; 
-            let location_id: String = row.get(3)?;
-            let arrival_at_server: i64 = row.get(4)?;
-            let deleted: i64 = row.get(5)?;
-
-            let photo_json = serde_json::json!({
-                "id": id,
-                "lastEdit": last_edit,
-                "photoFile": photo_file,
-                "locationId": location_id,
-                "arrivalAtServer": arrival_at_server,
-                "deleted": deleted
-            });
 
-            Ok(photo_json)
-        })?;
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
 
         let mut photos = Vec::new();
         for row in rows {
@@ -55,25 +53,119 @@ impl PhotoLocalStorage {
         Ok(photos)
     }
 
+    pub fn get_photo_by_id(&self, id: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare("SELECT * FROM photos WHERE id = ?")?;
+
+        stmt.query_map(params![id], row_to_json)?.next().transpose()
+    }
+
+    pub fn get_photo_metadata(&self, id: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(&format!("{} WHERE id = ?", METADATA_QUERY))?;
+
+        stmt.query_map(params![id], metadata_from_row)?
+            .next()
+            .transpose()
+    }
+
+    pub fn get_photo_blob(&self, id: &str) -> Result<Option<PhotoBlob>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT rowid, length(photoFile), photoFormat, photoHash FROM photos
+             WHERE id = ? AND COALESCE(deleted, 0) = 0",
+            params![id],
+            |row| {
+                Ok(PhotoBlob {
+                    row_id: row.get(0)?,
+                    size: row.get(1)?,
+                    format: row.get(2)?,
+                    hash: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    pub fn read_photo_chunk(&self, row_id: i64, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let conn = self.core_storage.get_connection()?;
+        let blob = conn.blob_open(DatabaseName::Main, "photos", "photoFile", row_id, true)?;
+
+        let mut buffer = vec![0; len];
+        let read = blob.read_at(&mut buffer, offset as usize)?;
+        buffer.truncate(read);
+        Ok(buffer)
+    }
+
+    #[tracing::instrument(name = "db.get_photo_manifest_by_date", skip(self))]
+    pub fn get_photo_manifest_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let mut entries: Vec<Value> = {
+            let conn = self.core_storage.get_connection()?;
+            let mut stmt = conn.prepare(&format!(
+                "{} WHERE arrivalAtServer > ? ORDER BY arrivalAtServer ASC LIMIT 500",
+                METADATA_QUERY
+            ))?;
+
+            stmt.query_map(params![last_edit], metadata_from_row)?
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        for entry in entries.iter_mut() {
+            if entry["hash"].is_null() {
+                let id = entry["id"].as_str().unwrap_or_default().to_string();
+                entry["hash"] = json!(self.backfill_photo_hash(&id)?);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn backfill_photo_hash(&self, id: &str) -> Result<String> {
+        let conn = self.core_storage.get_connection()?;
+        let photo_file: Vec<u8> = conn.query_row(
+            "SELECT photoFile FROM photos WHERE id = ?",
+            params![id],
+            |row| row.get(0),
+        )?;
+        let hash = photo_hash(&photo_file);
+
+        conn.execute(
+            "UPDATE photos SET photoHash = ? WHERE id = ?",
+            params![hash, id],
+        )?;
+        Ok(hash)
+    }
+
     pub fn save_photo(&self, photo_data: &Value) -> Result<bool> {
-        let id = photo_data["id"].as_str().unwrap_or_default();
-        let last_edit = photo_data["lastEdit"].as_i64().unwrap_or(0);
+        if let Value::Array(arr) = &photo_data["photoFile"]
+            && arr.len() >= config::get().photo_ingest.spill_threshold_bytes
+        {
+            return self.save_photo_spilled(photo_data, arr);
+        }
+
         let photo_file = match &photo_data["photoFile"] {
-            Value::Array(arr) => {
-                let bytes: Vec<u8> = arr.iter()
-                    .filter_map(|v| v.as_u64().map(|n| n as u8))
-                    .collect();
-                bytes
-            },
+            Value::Array(arr) => photo_bytes(arr)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
             _ => Vec::new(),
         };
+        self.save_photo_bytes(photo_data, &photo_file)
+    }
+
+    /// Saves a photo whose file is already in memory as bytes, e.g. after
+    /// transcoding; `photo_data["photoFile"]` is ignored.
+    pub fn save_photo_bytes(&self, photo_data: &Value, photo_file: &[u8]) -> Result<bool> {
+        let id = photo_data["id"].as_str().unwrap_or_default();
+        let last_edit = photo_data["lastEdit"].as_i64().unwrap_or(0);
         let location_id = photo_data["locationId"].as_str().unwrap_or_default();
         let arrival_at_server = chrono::Utc::now().timestamp_millis();
-        
+
+        let photo_hash = photo_hash(photo_file);
+        let photo_format = photo_data["photoFormat"].as_str();
+        let photo_policy = photo_policy(photo_data);
+
         let conn = self.core_storage.get_connection()?;
-        let query = format!(
-            "INSERT OR REPLACE INTO photos (id, lastEdit, photoFile, locationId, arrivalAtServer) VALUES (?, ?, ?, ?, ?)",
-        );
+        let query = "INSERT OR REPLACE INTO photos (id, lastEdit, photoFile, locationId, arrivalAtServer, photoHash, photoFormat, photoPolicy) VALUES (?, ?, ?, ?, ?, ?, ?, ?)".to_string();
 
         conn.execute(
             &query,
@@ -82,10 +174,348 @@ impl PhotoLocalStorage {
                 last_edit,
                 photo_file,
                 location_id,
-                arrival_at_server
+                arrival_at_server,
+                photo_hash,
+                photo_format,
+                photo_policy
+            ],
+        )?;
+
+        Ok(true)
+    }
+
+    fn save_photo_spilled(&self, photo_data: &Value, photo_file: &[Value]) -> Result<bool> {
+        let id = photo_data["id"].as_str().unwrap_or_default();
+        let last_edit = photo_data["lastEdit"].as_i64().unwrap_or(0);
+        let location_id = photo_data["locationId"].as_str().unwrap_or_default();
+        let photo_format = photo_data["photoFormat"].as_str();
+        let photo_policy = photo_policy(photo_data);
+        let arrival_at_server = chrono::Utc::now().timestamp_millis();
+
+        let spill_path = storage::dir(Root::Photos).join(format!("photo-{}.tmp", Uuid::new_v4()));
+
+        let result = spill_photo_file(&spill_path, photo_file)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+            .and_then(|(len, photo_hash)| {
+                let conn = self.core_storage.get_connection()?;
+                let tx = conn.unchecked_transaction()?;
+
+                tx.execute(
+                    "INSERT OR REPLACE INTO photos (id, lastEdit, photoFile, locationId, arrivalAtServer, photoHash, photoFormat, photoPolicy) VALUES (?, ?, zeroblob(?), ?, ?, ?, ?, ?)",
+                    params![id, last_edit, len, location_id, arrival_at_server, photo_hash, photo_format, photo_policy],
+                )?;
+                let row_id = tx.last_insert_rowid();
+
+                {
+                    let mut blob =
+                        tx.blob_open(DatabaseName::Main, "photos", "photoFile", row_id, false)?;
+                    let mut file = File::open(&spill_path)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                    io::copy(&mut file, &mut blob)
+                        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                }
+
+                tx.commit()?;
+                metrics::increment("photo_ingest_spilled_total");
+                Ok(true)
+            });
+
+        if let Err(e) = fs::remove_file(&spill_path) {
+            eprintln!("Failed to remove photo spill file {:?}: {}", spill_path, e);
+        }
+
+        result
+    }
+
+    pub fn save_original(&self, id: &str, photo_format: &str, photo_file: &[u8]) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO photoOriginals (id, photoFormat, photoFile, arrivalAtServer) VALUES (?, ?, ?, ?)",
+            params![
+                id,
+                photo_format,
+                photo_file,
+                chrono::Utc::now().timestamp_millis()
             ],
         )?;
+        Ok(())
+    }
+
+    /// Photos last edited before `cutoff` that the recompression job has not
+    /// processed yet, oldest first.
+    pub fn get_recompression_candidates(&self, cutoff: i64, limit: usize) -> Result<Vec<String>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id FROM photos
+             WHERE lastEdit < ? AND COALESCE(deleted, 0) = 0
+               AND id NOT IN (SELECT photoId FROM photoRecompressions)
+             ORDER BY lastEdit ASC LIMIT ?",
+        )?;
+
+        stmt.query_map(params![cutoff, limit as i64], |row| row.get(0))?
+            .collect()
+    }
+
+    pub fn get_photo_file(&self, id: &str) -> Result<Option<(Vec<u8>, Option<String>)>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT photoFile, photoFormat FROM photos WHERE id = ?",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+    }
+
+    /// Replaces a photo with its recompressed version and records it in the
+    /// manifest. Passing `None` only records that the photo was processed.
+    /// Returns false when the photo changed since it was read.
+    pub fn save_recompressed(
+        &self,
+        id: &str,
+        previous: &[u8],
+        previous_format: Option<&str>,
+        recompressed: Option<(&[u8], &str)>,
+        quality: u64,
+    ) -> Result<bool> {
+        let previous_hash = photo_hash(previous);
+        let (photo_file, photo_format) = match recompressed {
+            Some((photo_file, photo_format)) => (photo_file, Some(photo_format)),
+            None => (previous, previous_format),
+        };
+        let new_hash = photo_hash(photo_file);
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let conn = self.core_storage.get_connection()?;
+        let tx = conn.unchecked_transaction()?;
+        if recompressed.is_some() {
+            let changed = tx.execute(
+                "UPDATE photos SET photoFile = ?, photoHash = ?, photoFormat = ?, lastEdit = ?,
+                     arrivalAtServer = ?
+                 WHERE id = ? AND COALESCE(photoHash, ?) = ?",
+                params![
+                    photo_file,
+                    new_hash,
+                    photo_format,
+                    now,
+                    now,
+                    id,
+                    previous_hash,
+                    previous_hash
+                ],
+            )?;
+            if changed == 0 {
+                return Ok(false);
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO photoRecompressions (photoId, recompressedAt, previousHash,
+                 previousFormat, previousBytes, photoHash, photoFormat, bytes, quality)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                id,
+                now,
+                previous_hash,
+                previous_format,
+                previous.len() as i64,
+                new_hash,
+                photo_format,
+                photo_file.len() as i64,
+                quality as i64
+            ],
+        )?;
+        tx.commit()?;
 
         Ok(true)
     }
+
+    /// Space saved by the recompression job and its manifest, newest first.
+    /// `previous_hash` looks up the entry of a photo restored from a backup.
+    pub fn get_recompression_manifest(
+        &self,
+        previous_hash: Option<&str>,
+        limit: usize,
+    ) -> Result<Value> {
+        let conn = self.core_storage.get_connection()?;
+        let (photos, previous_bytes, bytes): (i64, i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(previousBytes), 0), COALESCE(SUM(bytes), 0)
+             FROM photoRecompressions",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT * FROM photoRecompressions WHERE (?1 IS NULL OR previousHash = ?1)
+             ORDER BY recompressedAt DESC LIMIT ?2",
+        )?;
+        let entries = stmt
+            .query_map(params![previous_hash, limit as i64], |row| {
+                Ok(json!({
+                    "photoId": row.get::<_, String>("photoId")?,
+                    "recompressedAt": row.get::<_, i64>("recompressedAt")?,
+                    "previousHash": row.get::<_, String>("previousHash")?,
+                    "previousFormat": row.get::<_, Option<String>>("previousFormat")?,
+                    "previousBytes": row.get::<_, i64>("previousBytes")?,
+                    "hash": row.get::<_, String>("photoHash")?,
+                    "format": row.get::<_, Option<String>>("photoFormat")?,
+                    "bytes": row.get::<_, i64>("bytes")?,
+                    "quality": row.get::<_, i64>("quality")?
+                }))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(json!({
+            "photos": photos,
+            "previousBytes": previous_bytes,
+            "bytes": bytes,
+            "savedBytes": previous_bytes - bytes,
+            "entries": entries
+        }))
+    }
+}
+
+/// Bytes converted from the JSON array per write.
+const SPILL_CHUNK_BYTES: usize = 64 * 1024;
+
+fn spill_photo_file(path: &Path, photo_file: &[Value]) -> io::Result<(i64, String)> {
+    let mut file = File::create(path)?;
+    let mut hasher = Sha256::new();
+    let mut chunk = Vec::with_capacity(SPILL_CHUNK_BYTES.min(photo_file.len()));
+
+    for (offset, values) in photo_file.chunks(SPILL_CHUNK_BYTES).enumerate() {
+        chunk.clear();
+        for (index, value) in values.iter().enumerate() {
+            chunk.push(photo_byte(offset * SPILL_CHUNK_BYTES + index, value)?);
+        }
+        file.write_all(&chunk)?;
+        hasher.update(&chunk);
+    }
+
+    file.flush()?;
+    Ok((photo_file.len() as i64, hex(&hasher.finalize())))
+}
+
+/// The bytes of a photoFile array. Values that are not bytes are rejected
+/// rather than coerced, see `photo_byte`.
+pub fn photo_bytes(photo_file: &[Value]) -> io::Result<Vec<u8>> {
+    photo_file
+        .iter()
+        .enumerate()
+        .map(|(index, value)| photo_byte(index, value))
+        .collect()
+}
+
+/// Rejects anything but an integer in 0..=255, so a malformed array fails
+/// the update instead of storing a photo that differs from the client's.
+fn photo_byte(index: usize, value: &Value) -> io::Result<u8> {
+    value
+        .as_u64()
+        .and_then(|n| u8::try_from(n).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("photoFile[{}] is not a byte: {}", index, value),
+            )
+        })
+}
+
+fn photo_policy(photo_data: &Value) -> Option<String> {
+    photo_data
+        .get("photoPolicy")
+        .filter(|policy| policy.is_object())
+        .map(Value::to_string)
+}
+
+fn parse_policy(policy: Option<String>) -> Value {
+    policy
+        .and_then(|policy| serde_json::from_str(&policy).ok())
+        .unwrap_or(Value::Null)
+}
+
+fn photo_hash(photo_file: &[u8]) -> String {
+    hex(&Sha256::digest(photo_file))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+const METADATA_QUERY: &str = "SELECT id, lastEdit, locationId, arrivalAtServer, deleted, photoHash,
+     photoFormat, photoPolicy, length(photoFile) AS size FROM photos";
+
+fn metadata_from_row(row: &Row) -> Result<Value> {
+    Ok(json!({
+        "id": row.get::<_, String>("id")?,
+        "lastEdit": row.get::<_, i64>("lastEdit")?,
+        "locationId": row.get::<_, String>("locationId")?,
+        "arrivalAtServer": row.get::<_, i64>("arrivalAtServer")?,
+        "deleted": row.get::<_, Option<i64>>("deleted")?.unwrap_or(0),
+        "size": row.get::<_, Option<i64>>("size")?.unwrap_or(0),
+        "hash": row.get::<_, Option<String>>("photoHash")?,
+        "format": row.get::<_, Option<String>>("photoFormat")?,
+        "policy": parse_policy(row.get("photoPolicy")?)
+    }))
+}
+
+pub fn row_to_json(row: &Row) -> Result<Value> {
+    Ok(json!({
+        "id": row.get::<_, String>("id")?,
+        "lastEdit": row.get::<_, i64>("lastEdit")?,
+        "photoFile": row.get::<_, Vec<u8>>("photoFile")?,
+        "locationId": row.get::<_, String>("locationId")?,
+        "arrivalAtServer": row.get::<_, i64>("arrivalAtServer")?,
+        "deleted": row.get::<_, i64>("deleted")?,
+        "photoFormat": row.get::<_, Option<String>>("photoFormat")?,
+        "photoPolicy": parse_policy(row.get("photoPolicy")?)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spill_rejects_values_that_are_not_bytes() {
+        let path = std::env::temp_dir().join(format!("photo-{}.tmp", Uuid::new_v4()));
+
+        let spilled = spill_photo_file(&path, &[json!(0), json!(255)]).unwrap();
+        assert_eq!(spilled, (2, photo_hash(&[0, 255])));
+        for value in [json!(256), json!(-1), json!(1.5), json!("7")] {
+            let error = spill_photo_file(&path, &[json!(1), value]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recompressed_photos_get_a_new_last_edit_and_hash() {
+        let core_storage = CoreLocalStorage::new(":memory:").unwrap();
+        crate::local_storage::schema::migrate(&core_storage.get_connection().unwrap()).unwrap();
+        let storage = PhotoLocalStorage::new(Arc::new(core_storage)).unwrap();
+        let photo = json!({"id": "p1", "lastEdit": 1, "locationId": "l1"});
+        storage.save_photo_bytes(&photo, &[1, 2, 3]).unwrap();
+        let before = storage.get_photo_blob("p1").unwrap().unwrap();
+
+        let replaced = storage
+            .save_recompressed("p1", &[1, 2, 3], None, Some((&[4], "webp")), 60)
+            .unwrap();
+
+        assert!(replaced);
+        let after = storage.get_photo_blob("p1").unwrap().unwrap();
+        assert_eq!(after.size, 1);
+        assert_ne!(after.hash, before.hash);
+        let photo = storage.get_photo_by_id("p1").unwrap().unwrap();
+        assert!(photo["lastEdit"].as_i64().unwrap() > 1);
+    }
+
+    #[test]
+    fn photo_bytes_are_rejected_instead_of_truncated() {
+        assert_eq!(photo_bytes(&[json!(0), json!(255)]).unwrap(), [0, 255]);
+        for value in [json!(256), json!(-1), json!(1.5), json!("7"), Value::Null] {
+            let error = photo_bytes(&[json!(1), value]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
 }
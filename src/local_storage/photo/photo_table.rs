@@ -0,0 +1,46 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const PHOTO_TABLE: Table = Table {
+    name: "photos",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("photoFile", "BLOB NOT NULL"),
+        Column::new("locationId", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("photoHash", "TEXT"),
+        Column::new("photoFormat", "TEXT"),
+        Column::new("photoPolicy", "TEXT"),
+    ],
+    constraints: &[],
+};
+
+pub const PHOTO_ORIGINAL_TABLE: Table = Table {
+    name: "photoOriginals",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("photoFormat", "TEXT NOT NULL"),
+        Column::new("photoFile", "BLOB NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+    ],
+    constraints: &[],
+};
+
+/// One row per photo the recompression job has processed. The previous hash
+/// identifies originals restored from a backup.
+pub const PHOTO_RECOMPRESSION_TABLE: Table = Table {
+    name: "photoRecompressions",
+    columns: &[
+        Column::new("photoId", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("recompressedAt", "INTEGER NOT NULL"),
+        Column::new("previousHash", "TEXT NOT NULL"),
+        Column::new("previousFormat", "TEXT"),
+        Column::new("previousBytes", "INTEGER NOT NULL"),
+        Column::new("photoHash", "TEXT NOT NULL"),
+        Column::new("photoFormat", "TEXT"),
+        Column::new("bytes", "INTEGER NOT NULL"),
+        Column::new("quality", "INTEGER NOT NULL"),
+    ],
+    constraints: &[],
+};
@@ -0,0 +1,2 @@
+pub mod portal_token_local_storage;
+pub mod portal_token_table;
@@ -0,0 +1,63 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use std::sync::Arc;
+
+pub struct PortalTokenLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl PortalTokenLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = PortalTokenLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_active_sawmill_id(&self, token: &str, scope: &str) -> Result<Option<String>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT sawmillId FROM portalTokens
+             WHERE token = ? AND revoked = 0 AND COALESCE(scope, 'portal') = ?",
+            params![token, scope],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    pub fn create_token(
+        &self,
+        token: &str,
+        sawmill_id: &str,
+        created_by: &str,
+        scope: &str,
+    ) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO portalTokens (token, sawmillId, createdBy, createdAt, revoked, scope)
+             VALUES (?, ?, ?, ?, 0, ?)",
+            params![
+                token,
+                sawmill_id,
+                created_by,
+                chrono::Utc::now().timestamp_millis(),
+                scope
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn revoke_token(&self, token: &str) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let rows_affected = conn.execute(
+            "UPDATE portalTokens SET revoked = 1 WHERE token = ? AND revoked = 0",
+            params![token],
+        )?;
+
+        Ok(rows_affected > 0)
+    }
+}
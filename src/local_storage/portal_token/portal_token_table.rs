@@ -0,0 +1,14 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const PORTAL_TOKEN_TABLE: Table = Table {
+    name: "portalTokens",
+    columns: &[
+        Column::new("token", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("sawmillId", "TEXT NOT NULL"),
+        Column::new("createdBy", "TEXT NOT NULL"),
+        Column::new("createdAt", "INTEGER NOT NULL"),
+        Column::new("revoked", "INTEGER DEFAULT 0"),
+        Column::new("scope", "TEXT DEFAULT 'portal'"),
+    ],
+    constraints: &[],
+};
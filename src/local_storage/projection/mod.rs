@@ -0,0 +1,2 @@
+pub mod projection_local_storage;
+pub mod projection_views;
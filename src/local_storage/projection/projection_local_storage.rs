@@ -0,0 +1,99 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::projection::projection_views::{Field, FieldKind, Projection};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Result, Row, params_from_iter};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+
+pub struct ProjectionQuery {
+    pub conditions: Vec<&'static str>,
+    pub params: Vec<SqlValue>,
+    pub sort: &'static Field,
+    pub descending: bool,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+pub struct ProjectionLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl ProjectionLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ProjectionLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    #[tracing::instrument(name = "db.query_projection", skip_all, fields(projection = projection.name))]
+    pub fn query(
+        &self,
+        projection: &Projection,
+        query: &ProjectionQuery,
+    ) -> Result<(Vec<Value>, i64)> {
+        let conn = self.core_storage.get_connection()?;
+        let where_clause = std::iter::once(projection.base_condition)
+            .chain(query.conditions.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        let total: i64 = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM {} WHERE {}",
+                projection.from, where_clause
+            ),
+            params_from_iter(query.params.iter()),
+            |row| row.get(0),
+        )?;
+
+        let columns = projection
+            .fields
+            .iter()
+            .map(|field| format!("{} AS \"{}\"", field.sql, field.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let direction = if query.descending { "DESC" } else { "ASC" };
+        let id = projection
+            .field("id")
+            .map(|field| field.sql)
+            .unwrap_or("rowid");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM {} WHERE {} ORDER BY \"{}\" {}, {} {} LIMIT {} OFFSET {}",
+            columns,
+            projection.from,
+            where_clause,
+            query.sort.name,
+            direction,
+            id,
+            direction,
+            query.limit,
+            query.offset
+        ))?;
+
+        let items = stmt
+            .query_map(params_from_iter(query.params.iter()), |row| {
+                row_to_dto(row, projection)
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((items, total))
+    }
+}
+
+fn row_to_dto(row: &Row, projection: &Projection) -> Result<Value> {
+    let mut dto = Map::new();
+
+    for (index, field) in projection.fields.iter().enumerate() {
+        let value = match field.kind {
+            FieldKind::Text => json!(row.get::<_, Option<String>>(index)?),
+            FieldKind::Integer => json!(row.get::<_, Option<i64>>(index)?),
+            FieldKind::Real => json!(row.get::<_, Option<f64>>(index)?),
+            FieldKind::Bool => json!(row.get::<_, Option<i64>>(index)?.map(|value| value != 0)),
+        };
+        dto.insert(field.name.to_string(), value);
+    }
+
+    Ok(Value::Object(dto))
+}
@@ -0,0 +1,233 @@
+pub enum FieldKind {
+    Text,
+    Integer,
+    Real,
+    Bool,
+}
+
+pub struct Field {
+    pub name: &'static str,
+    pub sql: &'static str,
+    pub kind: FieldKind,
+}
+
+pub enum FilterKind {
+    Text,
+    Bool,
+    Search,
+    From,
+    To,
+}
+
+pub struct Filter {
+    pub param: &'static str,
+    pub condition: &'static str,
+    pub kind: FilterKind,
+}
+
+pub struct Projection {
+    pub name: &'static str,
+    pub from: &'static str,
+    pub base_condition: &'static str,
+    pub fields: &'static [Field],
+    pub filters: &'static [Filter],
+    pub default_sort: &'static str,
+}
+
+impl Projection {
+    pub fn field(&self, name: &str) -> Option<&'static Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+const fn field(name: &'static str, sql: &'static str, kind: FieldKind) -> Field {
+    Field { name, sql, kind }
+}
+
+const fn filter(param: &'static str, condition: &'static str, kind: FilterKind) -> Filter {
+    Filter {
+        param,
+        condition,
+        kind,
+    }
+}
+
+pub const LOCATION_PROJECTION: Projection = Projection {
+    name: "locations",
+    from: "locations l LEFT JOIN contracts c ON c.id = l.contractId",
+    base_condition: "COALESCE(l.deleted, 0) = 0",
+    fields: &[
+        field("id", "l.id", FieldKind::Text),
+        field("partieNr", "l.partieNr", FieldKind::Text),
+        field("date", "l.date", FieldKind::Integer),
+        field("started", "l.started", FieldKind::Bool),
+        field("done", "l.done", FieldKind::Bool),
+        field("latitude", "l.latitude", FieldKind::Real),
+        field("longitude", "l.longitude", FieldKind::Real),
+        field("contractId", "l.contractId", FieldKind::Text),
+        field("contractTitle", "c.title", FieldKind::Text),
+        field("crewId", "l.crewId", FieldKind::Text),
+        field("unit", "l.unit", FieldKind::Text),
+        field("initialQuantity", "l.initialQuantity", FieldKind::Real),
+        field("remainingQuantity", "l.currentQuantity", FieldKind::Real),
+        field(
+            "remainingOversizeQuantity",
+            "l.currentOversizeQuantity",
+            FieldKind::Real,
+        ),
+        field(
+            "remainingPieceCount",
+            "l.currentPieceCount",
+            FieldKind::Integer,
+        ),
+        field(
+            "shippedQuantity",
+            "(SELECT COALESCE(SUM(s.quantity), 0) FROM shipments s
+              WHERE s.locationId = l.id AND COALESCE(s.deleted, 0) = 0)",
+            FieldKind::Real,
+        ),
+        field(
+            "shipmentCount",
+            "(SELECT COUNT(*) FROM shipments s
+              WHERE s.locationId = l.id AND COALESCE(s.deleted, 0) = 0)",
+            FieldKind::Integer,
+        ),
+        field(
+            "photoCount",
+            "(SELECT COUNT(*) FROM photos p
+              WHERE p.locationId = l.id AND COALESCE(p.deleted, 0) = 0)",
+            FieldKind::Integer,
+        ),
+        field(
+            "sawmills",
+            "(SELECT GROUP_CONCAT(m.name, ', ') FROM locationSawmillJunction j
+              JOIN sawmills m ON m.id = j.sawmillId WHERE j.locationId = l.id)",
+            FieldKind::Text,
+        ),
+        field("lastEdit", "l.lastEdit", FieldKind::Integer),
+    ],
+    filters: &[
+        filter("done", "l.done = ?", FilterKind::Bool),
+        filter("started", "l.started = ?", FilterKind::Bool),
+        filter("contractId", "l.contractId = ?", FilterKind::Text),
+        filter("crewId", "l.crewId = ?", FilterKind::Text),
+        filter(
+            "search",
+            "(l.partieNrSearch LIKE ? ESCAPE '\\' OR c.titleSearch LIKE ? ESCAPE '\\')",
+            FilterKind::Search,
+        ),
+        filter("from", "l.date >= ?", FilterKind::From),
+        filter("to", "l.date < ?", FilterKind::To),
+    ],
+    default_sort: "date",
+};
+
+pub const CONTRACT_PROJECTION: Projection = Projection {
+    name: "contracts",
+    from: "contracts c",
+    base_condition: "COALESCE(c.deleted, 0) = 0",
+    fields: &[
+        field("id", "c.id", FieldKind::Text),
+        field("title", "c.title", FieldKind::Text),
+        field("done", "c.done", FieldKind::Bool),
+        field("startDate", "c.startDate", FieldKind::Integer),
+        field("endDate", "c.endDate", FieldKind::Integer),
+        field("availableQuantity", "c.availableQuantity", FieldKind::Real),
+        field("bookedQuantity", "c.bookedQuantity", FieldKind::Real),
+        field("shippedQuantity", "c.shippedQuantity", FieldKind::Real),
+        field(
+            "locationCount",
+            "(SELECT COUNT(*) FROM locations l
+              WHERE l.contractId = c.id AND COALESCE(l.deleted, 0) = 0)",
+            FieldKind::Integer,
+        ),
+        field(
+            "activeLocationCount",
+            "(SELECT COUNT(*) FROM locations l
+              WHERE l.contractId = c.id AND COALESCE(l.deleted, 0) = 0 AND l.done = 0)",
+            FieldKind::Integer,
+        ),
+        field(
+            "remainingQuantity",
+            "(SELECT COALESCE(SUM(l.currentQuantity), 0) FROM locations l
+              WHERE l.contractId = c.id AND COALESCE(l.deleted, 0) = 0)",
+            FieldKind::Real,
+        ),
+        field(
+            "pricePerCubicMeter",
+            "c.pricePerCubicMeter",
+            FieldKind::Real,
+        ),
+        field("currency", "c.currency", FieldKind::Text),
+        field("lastEdit", "c.lastEdit", FieldKind::Integer),
+    ],
+    filters: &[
+        filter("done", "c.done = ?", FilterKind::Bool),
+        filter(
+            "search",
+            "c.titleSearch LIKE ? ESCAPE '\\'",
+            FilterKind::Search,
+        ),
+        filter("from", "c.endDate >= ?", FilterKind::From),
+        filter("to", "c.startDate < ?", FilterKind::To),
+    ],
+    default_sort: "startDate",
+};
+
+pub const SHIPMENT_PROJECTION: Projection = Projection {
+    name: "shipments",
+    from: "shipments s
+           LEFT JOIN locations l ON l.id = s.locationId
+           LEFT JOIN contracts c ON c.id = s.contractId
+           LEFT JOIN sawmills m ON m.id = s.sawmillId
+           LEFT JOIN users u ON u.id = s.userId",
+    base_condition: "COALESCE(s.deleted, 0) = 0",
+    fields: &[
+        field("id", "s.id", FieldKind::Text),
+        field("date", "s.lastEdit", FieldKind::Integer),
+        field("quantity", "s.quantity", FieldKind::Real),
+        field("oversizeQuantity", "s.oversizeQuantity", FieldKind::Real),
+        field("pieceCount", "s.pieceCount", FieldKind::Integer),
+        field("unit", "s.unit", FieldKind::Text),
+        field("locationId", "s.locationId", FieldKind::Text),
+        field("partieNr", "l.partieNr", FieldKind::Text),
+        field("contractId", "s.contractId", FieldKind::Text),
+        field("contractTitle", "c.title", FieldKind::Text),
+        field("sawmillId", "s.sawmillId", FieldKind::Text),
+        field("sawmillName", "m.name", FieldKind::Text),
+        field("userId", "s.userId", FieldKind::Text),
+        field("userName", "u.name", FieldKind::Text),
+        field("reviewStatus", "s.reviewStatus", FieldKind::Text),
+        field("grossValue", "s.grossValue", FieldKind::Real),
+        field("currency", "s.currency", FieldKind::Text),
+        field("distanceKm", "s.distanceKm", FieldKind::Real),
+    ],
+    filters: &[
+        filter("contractId", "s.contractId = ?", FilterKind::Text),
+        filter("locationId", "s.locationId = ?", FilterKind::Text),
+        filter("sawmillId", "s.sawmillId = ?", FilterKind::Text),
+        filter("userId", "s.userId = ?", FilterKind::Text),
+        filter("reviewStatus", "s.reviewStatus = ?", FilterKind::Text),
+        filter(
+            "search",
+            "(l.partieNrSearch LIKE ? ESCAPE '\\' OR m.nameSearch LIKE ? ESCAPE '\\')",
+            FilterKind::Search,
+        ),
+        filter("from", "s.lastEdit >= ?", FilterKind::From),
+        filter("to", "s.lastEdit < ?", FilterKind::To),
+    ],
+    default_sort: "date",
+};
+
+pub const PROJECTIONS: &[&Projection] = &[
+    &LOCATION_PROJECTION,
+    &CONTRACT_PROJECTION,
+    &SHIPMENT_PROJECTION,
+];
+
+pub fn projection(name: &str) -> Option<&'static Projection> {
+    PROJECTIONS
+        .iter()
+        .copied()
+        .find(|projection| projection.name == name)
+}
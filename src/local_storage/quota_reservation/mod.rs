@@ -0,0 +1,2 @@
+pub mod quota_reservation_local_storage;
+pub mod quota_reservation_table;
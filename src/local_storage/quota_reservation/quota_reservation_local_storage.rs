@@ -0,0 +1,103 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub const STATUS_ACTIVE: &str = "active";
+
+pub struct QuotaReservationLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl QuotaReservationLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = QuotaReservationLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    #[tracing::instrument(name = "db.get_quota_reservation_updates_by_date", skip(self))]
+    pub fn get_reservation_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let query = "SELECT * FROM quotaReservations WHERE arrivalAtServer > ?
+                     ORDER BY lastEdit ASC LIMIT 100";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
+
+        let mut reservations = Vec::new();
+        for row in rows {
+            match row {
+                Ok(reservation) => reservations.push(reservation),
+                Err(e) => eprintln!("Error fetching quota reservation: {}", e),
+            }
+        }
+
+        Ok(reservations)
+    }
+
+    pub fn get_active_by_location(&self, location_id: &str) -> Result<Option<Value>> {
+        let query = "SELECT * FROM quotaReservations
+                     WHERE deleted = 0 AND status = ? AND locationId = ? LIMIT 1";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let mut rows = stmt.query_map(params![STATUS_ACTIVE, location_id], row_to_json)?;
+        rows.next().transpose()
+    }
+
+    pub fn get_expired(&self, now: i64) -> Result<Vec<Value>> {
+        let query = "SELECT * FROM quotaReservations
+                     WHERE deleted = 0 AND status = ? AND expiresAt <= ?
+                     ORDER BY expiresAt ASC";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![STATUS_ACTIVE, now], row_to_json)?;
+        rows.collect()
+    }
+
+    pub fn save_reservation(&self, reservation_data: &Value) -> Result<bool> {
+        let mut reservation_for_save = reservation_data.clone();
+        if let serde_json::Value::Object(ref mut map) = reservation_for_save {
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+        }
+
+        self.core_storage
+            .insert_or_update("quotaReservations", &reservation_for_save)
+    }
+}
+
+fn row_to_json(row: &rusqlite::Row) -> Result<Value> {
+    let id: String = row.get("id")?;
+    let last_edit: i64 = row.get("lastEdit")?;
+    let contract_id: String = row.get("contractId")?;
+    let location_id: String = row.get("locationId")?;
+    let user_id: String = row.get("userId")?;
+    let quantity: f64 = row.get("quantity")?;
+    let status: String = row.get("status")?;
+    let expires_at: i64 = row.get("expiresAt")?;
+    let arrival_at_server: i64 = row.get("arrivalAtServer")?;
+    let deleted: i64 = row.get("deleted")?;
+
+    Ok(serde_json::json!({
+        "id": id,
+        "lastEdit": last_edit,
+        "contractId": contract_id,
+        "locationId": location_id,
+        "userId": user_id,
+        "quantity": quantity,
+        "status": status,
+        "expiresAt": expires_at,
+        "arrivalAtServer": arrival_at_server,
+        "deleted": deleted
+    }))
+}
@@ -0,0 +1,18 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const QUOTA_RESERVATION_TABLE: Table = Table {
+    name: "quotaReservations",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("contractId", "TEXT NOT NULL"),
+        Column::new("locationId", "TEXT NOT NULL"),
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("quantity", "REAL NOT NULL"),
+        Column::new("status", "TEXT NOT NULL DEFAULT 'active'"),
+        Column::new("expiresAt", "INTEGER NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
@@ -0,0 +1,2 @@
+pub mod resync_request_local_storage;
+pub mod resync_request_table;
@@ -0,0 +1,132 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, Row, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_STREAMING: &str = "streaming";
+pub const STATUS_COMPLETED: &str = "completed";
+pub const STATUS_FAILED: &str = "failed";
+
+pub struct ResyncRequestLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+fn row_to_json(row: &Row) -> Result<Value> {
+    let progress: String = row.get("progress")?;
+    Ok(json!({
+        "id": row.get::<_, String>("id")?,
+        "userId": row.get::<_, String>("userId")?,
+        "clientId": row.get::<_, Option<String>>("clientId")?,
+        "reason": row.get::<_, String>("reason")?,
+        "requestedBy": row.get::<_, String>("requestedBy")?,
+        "status": row.get::<_, String>("status")?,
+        "progress": serde_json::from_str::<Value>(&progress).unwrap_or(json!({})),
+        "createdAt": row.get::<_, i64>("createdAt")?,
+        "updatedAt": row.get::<_, i64>("updatedAt")?
+    }))
+}
+
+impl ResyncRequestLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ResyncRequestLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn create_request(
+        &self,
+        id: &str,
+        user_id: &str,
+        client_id: Option<&str>,
+        reason: &str,
+        requested_by: &str,
+        status: &str,
+    ) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+        let now = chrono::Utc::now().timestamp_millis();
+
+        conn.execute(
+            "INSERT INTO resyncRequests
+             (id, userId, clientId, reason, requestedBy, status, progress, createdAt, updatedAt)
+             VALUES (?, ?, ?, ?, ?, ?, '{}', ?, ?)",
+            params![
+                id,
+                user_id,
+                client_id,
+                reason,
+                requested_by,
+                status,
+                now,
+                now
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_request(&self, id: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT * FROM resyncRequests WHERE id = ?",
+            params![id],
+            row_to_json,
+        )
+        .optional()
+    }
+
+    pub fn get_requests(&self, limit: i64) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt =
+            conn.prepare("SELECT * FROM resyncRequests ORDER BY createdAt DESC LIMIT ?")?;
+
+        stmt.query_map(params![limit], row_to_json)?.collect()
+    }
+
+    pub fn get_open_requests(&self, user_id: &str) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM resyncRequests
+             WHERE userId = ? AND status != ?
+             ORDER BY createdAt",
+        )?;
+
+        stmt.query_map(params![user_id, STATUS_COMPLETED], row_to_json)?
+            .collect()
+    }
+
+    pub fn assign_client(&self, id: &str, client_id: &str) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+
+        let changed = conn.execute(
+            "UPDATE resyncRequests SET clientId = ?, status = ?, updatedAt = ?
+             WHERE id = ? AND status != ?",
+            params![
+                client_id,
+                STATUS_SENT,
+                chrono::Utc::now().timestamp_millis(),
+                id,
+                STATUS_COMPLETED
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    pub fn update_progress(&self, id: &str, status: &str, progress: &Value) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "UPDATE resyncRequests SET status = ?, progress = ?, updatedAt = ? WHERE id = ?",
+            params![
+                status,
+                progress.to_string(),
+                chrono::Utc::now().timestamp_millis(),
+                id
+            ],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const RESYNC_REQUEST_TABLE: Table = Table {
+    name: "resyncRequests",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("clientId", "TEXT"),
+        Column::new("reason", "TEXT NOT NULL"),
+        Column::new("requestedBy", "TEXT NOT NULL"),
+        Column::new("status", "TEXT NOT NULL"),
+        Column::new("progress", "TEXT NOT NULL DEFAULT '{}'"),
+        Column::new("createdAt", "INTEGER NOT NULL"),
+        Column::new("updatedAt", "INTEGER NOT NULL"),
+    ],
+    constraints: &[],
+};
@@ -0,0 +1,2 @@
+pub mod review_item_local_storage;
+pub mod review_item_table;
@@ -0,0 +1,111 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub const STATUS_OPEN: &str = "open";
+pub const STATUS_APPROVED: &str = "approved";
+pub const STATUS_REJECTED: &str = "rejected";
+
+pub struct ReviewItemLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl ReviewItemLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ReviewItemLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_review_item_by_id(&self, id: &str) -> Result<Option<Value>> {
+        let review_item_json = self.core_storage.get_existing_by_id("reviewItems", id)?;
+
+        Ok(review_item_json.into_iter().next())
+    }
+
+    #[tracing::instrument(name = "db.get_review_item_updates_by_date", skip(self))]
+    pub fn get_review_item_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let query =
+            "SELECT * FROM reviewItems WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
+
+        let mut review_items = Vec::new();
+        for row in rows {
+            match row {
+                Ok(review_item) => review_items.push(review_item),
+                Err(e) => eprintln!("Error fetching review item: {}", e),
+            }
+        }
+
+        Ok(review_items)
+    }
+
+    pub fn get_review_items_by_status(&self, status: &str) -> Result<Vec<Value>> {
+        let query =
+            "SELECT * FROM reviewItems WHERE deleted = 0 AND status = ? ORDER BY lastEdit ASC";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![status], row_to_json)?;
+        rows.collect()
+    }
+
+    pub fn get_open_review_item(
+        &self,
+        entity_type: &str,
+        entity_id: &str,
+    ) -> Result<Option<Value>> {
+        let query = "SELECT * FROM reviewItems WHERE deleted = 0 AND status = ? AND entityType = ? AND entityId = ? LIMIT 1";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let mut rows = stmt.query_map(params![STATUS_OPEN, entity_type, entity_id], row_to_json)?;
+        rows.next().transpose()
+    }
+
+    pub fn save_review_item(&self, review_item_data: &Value) -> Result<bool> {
+        let mut review_item_for_save = review_item_data.clone();
+        if let serde_json::Value::Object(ref mut map) = review_item_for_save {
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+        }
+
+        self.core_storage
+            .insert_or_update("reviewItems", &review_item_for_save)
+    }
+}
+
+fn row_to_json(row: &rusqlite::Row) -> Result<Value> {
+    let id: String = row.get("id")?;
+    let last_edit: i64 = row.get("lastEdit")?;
+    let entity_type: String = row.get("entityType")?;
+    let entity_id: String = row.get("entityId")?;
+    let reason: String = row.get("reason")?;
+    let status: String = row.get("status")?;
+    let reviewer_id: Option<String> = row.get("reviewerId")?;
+    let arrival_at_server: i64 = row.get("arrivalAtServer")?;
+    let deleted: i64 = row.get("deleted")?;
+
+    Ok(serde_json::json!({
+        "id": id,
+        "lastEdit": last_edit,
+        "entityType": entity_type,
+        "entityId": entity_id,
+        "reason": reason,
+        "status": status,
+        "reviewerId": reviewer_id,
+        "arrivalAtServer": arrival_at_server,
+        "deleted": deleted
+    }))
+}
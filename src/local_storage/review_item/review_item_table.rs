@@ -0,0 +1,17 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const REVIEW_ITEM_TABLE: Table = Table {
+    name: "reviewItems",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("entityType", "TEXT NOT NULL"),
+        Column::new("entityId", "TEXT NOT NULL"),
+        Column::new("reason", "TEXT NOT NULL"),
+        Column::new("status", "TEXT NOT NULL"),
+        Column::new("reviewerId", "TEXT"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
@@ -1 +1,2 @@
 pub mod sawmill_local_storage;
+pub mod sawmill_table;
@@ -1,5 +1,5 @@
 use crate::local_storage::core_local_storage::CoreLocalStorage;
-use rusqlite::{Result, params};
+use rusqlite::{Connection, Result, params};
 use serde_json::Value;
 use std::sync::Arc;
 
@@ -16,27 +16,37 @@ impl SawmillLocalStorage {
         Ok(storage)
     }
 
+    pub fn get_assortment_ids(&self, sawmill_id: &str) -> Result<Vec<String>> {
+        let conn = self.core_storage.get_connection()?;
+        assortment_ids(&conn, sawmill_id)
+    }
+
+    #[tracing::instrument(name = "db.get_sawmill_updates_by_date", skip(self))]
     pub fn get_sawmill_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
-        let query = format!(
-            "SELECT * FROM sawmills WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100",
-        );
+        let query =
+            "SELECT * FROM sawmills WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100"
+                .to_string();
 
         let conn = self.core_storage.get_connection()?;
         let mut stmt = conn.prepare(&query)?;
-        
+
         let rows = stmt.query_map(params![last_edit], |row| {
             let id: String = row.get(0)?;
             let last_edit: i64 = row.get(1)?;
             let name: String = row.get(2)?;
             let arrival_at_server: i64 = row.get(3)?;
             let deleted: i64 = row.get(4)?;
+            let latitude: Option<f64> = row.get("latitude")?;
+            let longitude: Option<f64> = row.get("longitude")?;
 
             let sawmill_json = serde_json::json!({
                 "id": id,
                 "lastEdit": last_edit,
                 "name": name,
                 "arrivalAtServer": arrival_at_server,
-                "deleted": deleted
+                "deleted": deleted,
+                "latitude": latitude,
+                "longitude": longitude
             });
 
             Ok(sawmill_json)
@@ -45,7 +55,9 @@ impl SawmillLocalStorage {
         let mut sawmills = Vec::new();
         for row in rows {
             match row {
-                Ok(sawmill) => {
+                Ok(mut sawmill) => {
+                    let id = sawmill["id"].as_str().unwrap_or_default().to_string();
+                    sawmill["assortmentIds"] = serde_json::json!(assortment_ids(&conn, &id)?);
                     sawmills.push(sawmill);
                 }
                 Err(e) => eprintln!("Error fetching sawmill: {}", e),
@@ -56,14 +68,46 @@ impl SawmillLocalStorage {
     }
 
     pub fn save_sawmill(&self, sawmill_data: &Value) -> Result<bool> {
+        let sawmill_id = sawmill_data["id"].as_str().unwrap_or("");
+
+        if let Some(assortment_ids) = sawmill_data["assortmentIds"].as_array() {
+            self.core_storage.delete_by_column(
+                "sawmillAssortmentJunction",
+                "sawmillId",
+                sawmill_id,
+            )?;
+
+            for assortment_value in assortment_ids {
+                if let Some(assortment_id) = assortment_value.as_str() {
+                    self.core_storage.insert(
+                        "sawmillAssortmentJunction",
+                        &serde_json::json!({ "sawmillId": sawmill_id, "assortmentId": assortment_id }),
+                    )?;
+                }
+            }
+        }
+
         let mut sawmill_for_save = sawmill_data.clone();
         if let serde_json::Value::Object(ref mut map) = sawmill_for_save {
-            map.insert("arrivalAtServer".to_string(), chrono::Utc::now().timestamp_millis().into());
+            map.remove("assortmentIds");
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
         }
 
-        let result = self.core_storage
+        let result = self
+            .core_storage
             .insert_or_update("sawmills", &sawmill_for_save)?;
 
         Ok(result)
     }
 }
+
+fn assortment_ids(conn: &Connection, sawmill_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn
+        .prepare_cached("SELECT assortmentId FROM sawmillAssortmentJunction WHERE sawmillId = ?")?;
+
+    let rows = stmt.query_map(params![sawmill_id], |row| row.get::<_, String>(0))?;
+    rows.collect()
+}
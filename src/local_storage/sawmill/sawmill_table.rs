@@ -0,0 +1,29 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const SAWMILL_TABLE: Table = Table {
+    name: "sawmills",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("name", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("latitude", "REAL"),
+        Column::new("longitude", "REAL"),
+        Column::new("nameSearch", "TEXT"),
+    ],
+    constraints: &[],
+};
+
+pub const SAWMILL_ASSORTMENT_JUNCTION_TABLE: Table = Table {
+    name: "sawmillAssortmentJunction",
+    columns: &[
+        Column::new("sawmillId", "TEXT NOT NULL"),
+        Column::new("assortmentId", "TEXT NOT NULL"),
+    ],
+    constraints: &[
+        "PRIMARY KEY (sawmillId, assortmentId)",
+        "FOREIGN KEY (sawmillId) REFERENCES sawmills(id) ON DELETE CASCADE",
+        "FOREIGN KEY (assortmentId) REFERENCES assortments(id) ON DELETE CASCADE",
+    ],
+};
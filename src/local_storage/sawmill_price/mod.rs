@@ -0,0 +1,2 @@
+pub mod sawmill_price_local_storage;
+pub mod sawmill_price_table;
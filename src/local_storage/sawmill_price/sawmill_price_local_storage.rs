@@ -0,0 +1,132 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub struct SawmillPriceLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl SawmillPriceLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = SawmillPriceLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    #[tracing::instrument(name = "db.get_sawmill_price_updates_by_date", skip(self))]
+    pub fn get_price_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let query =
+            "SELECT * FROM sawmillPrices WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
+
+        let mut prices = Vec::new();
+        for row in rows {
+            match row {
+                Ok(price) => prices.push(price),
+                Err(e) => eprintln!("Error fetching sawmill price: {}", e),
+            }
+        }
+
+        Ok(prices)
+    }
+
+    pub fn get_prices_by_sawmill(&self, sawmill_id: &str) -> Result<Vec<Value>> {
+        let query = "SELECT * FROM sawmillPrices WHERE deleted = 0 AND sawmillId = ?
+                     ORDER BY assortment ASC, validFrom ASC";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![sawmill_id], row_to_json)?;
+        rows.collect()
+    }
+
+    pub fn find_overlapping_price(
+        &self,
+        id: &str,
+        sawmill_id: &str,
+        assortment: &str,
+        valid_from: i64,
+        valid_to: Option<i64>,
+    ) -> Result<Option<Value>> {
+        let query = "SELECT * FROM sawmillPrices
+                     WHERE deleted = 0 AND id != ? AND sawmillId = ? AND assortment = ?
+                     AND (validTo IS NULL OR validTo > ?) AND (? IS NULL OR validFrom < ?)
+                     ORDER BY validFrom ASC LIMIT 1";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let mut rows = stmt.query_map(
+            params![id, sawmill_id, assortment, valid_from, valid_to, valid_to],
+            row_to_json,
+        )?;
+        rows.next().transpose()
+    }
+
+    pub fn resolve_price(
+        &self,
+        sawmill_id: &str,
+        assortment: &str,
+        at: i64,
+    ) -> Result<Option<Value>> {
+        let query = "SELECT * FROM sawmillPrices
+                     WHERE deleted = 0 AND sawmillId = ? AND assortment = ?
+                     AND validFrom <= ? AND (validTo IS NULL OR validTo > ?)
+                     ORDER BY validFrom DESC LIMIT 1";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let mut rows = stmt.query_map(params![sawmill_id, assortment, at, at], row_to_json)?;
+        rows.next().transpose()
+    }
+
+    pub fn save_price(&self, price_data: &Value) -> Result<bool> {
+        let mut price_for_save = price_data.clone();
+        if let serde_json::Value::Object(ref mut map) = price_for_save {
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+        }
+
+        self.core_storage
+            .insert_or_update("sawmillPrices", &price_for_save)
+    }
+}
+
+fn row_to_json(row: &rusqlite::Row) -> Result<Value> {
+    let id: String = row.get("id")?;
+    let last_edit: i64 = row.get("lastEdit")?;
+    let sawmill_id: String = row.get("sawmillId")?;
+    let assortment: String = row.get("assortment")?;
+    let price_per_unit: f64 = row.get("pricePerUnit")?;
+    let unit: Option<String> = row.get("unit")?;
+    let currency: Option<String> = row.get("currency")?;
+    let valid_from: i64 = row.get("validFrom")?;
+    let valid_to: Option<i64> = row.get("validTo")?;
+    let arrival_at_server: i64 = row.get("arrivalAtServer")?;
+    let deleted: i64 = row.get("deleted")?;
+
+    Ok(serde_json::json!({
+        "id": id,
+        "lastEdit": last_edit,
+        "sawmillId": sawmill_id,
+        "assortment": assortment,
+        "pricePerUnit": price_per_unit,
+        "unit": unit,
+        "currency": currency,
+        "validFrom": valid_from,
+        "validTo": valid_to,
+        "arrivalAtServer": arrival_at_server,
+        "deleted": deleted
+    }))
+}
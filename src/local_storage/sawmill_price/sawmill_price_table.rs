@@ -0,0 +1,19 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const SAWMILL_PRICE_TABLE: Table = Table {
+    name: "sawmillPrices",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("sawmillId", "TEXT NOT NULL"),
+        Column::new("assortment", "TEXT NOT NULL"),
+        Column::new("pricePerUnit", "REAL NOT NULL"),
+        Column::new("unit", "TEXT DEFAULT 'fm'"),
+        Column::new("currency", "TEXT"),
+        Column::new("validFrom", "INTEGER NOT NULL"),
+        Column::new("validTo", "INTEGER"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
@@ -0,0 +1,324 @@
+use crate::local_storage::activity_feed::activity_feed_table::{self, ACTIVITY_FEED_TABLE};
+use crate::local_storage::activity_report::activity_report_table::{
+    ACTIVITY_REPORT_TABLE, USER_ACTIVITY_TABLE,
+};
+use crate::local_storage::archive::archive_table::{
+    LOCATION_ARCHIVE_TABLE, LOCATION_SAWMILL_JUNCTION_ARCHIVE_TABLE, PHOTO_ARCHIVE_TABLE,
+};
+use crate::local_storage::assortment::assortment_table::ASSORTMENT_TABLE;
+use crate::local_storage::audit_log::audit_log_table::AUDIT_LOG_TABLE;
+use crate::local_storage::conflict::conflict_table::{self, CONFLICT_TABLE};
+use crate::local_storage::contract::contract_table::{
+    CONTRACT_DEFAULTS, CONTRACT_SAWMILL_ALLOWLIST_TABLE, CONTRACT_TABLE,
+};
+use crate::local_storage::core_table::{EntityDefaults, Table, column_info};
+use crate::local_storage::crew::crew_table::{CREW_MEMBER_JUNCTION_TABLE, CREW_TABLE};
+use crate::local_storage::device_secret::device_secret_table::DEVICE_SECRET_TABLE;
+use crate::local_storage::event_log::event_log_table::{self, EVENT_LOG_TABLE};
+use crate::local_storage::hook_script::hook_script_table::HOOK_SCRIPT_TABLE;
+use crate::local_storage::integration_token::integration_token_table::INTEGRATION_TOKEN_TABLE;
+use crate::local_storage::location::location_table::{
+    LOCATION_DEFAULTS, LOCATION_SAWMILL_JUNCTION_TABLE, LOCATION_TABLE,
+};
+use crate::local_storage::note::note_table::NOTE_TABLE;
+use crate::local_storage::permission::permission_table::PERMISSION_TABLE;
+use crate::local_storage::photo::photo_table::{
+    PHOTO_ORIGINAL_TABLE, PHOTO_RECOMPRESSION_TABLE, PHOTO_TABLE,
+};
+use crate::local_storage::portal_token::portal_token_table::PORTAL_TOKEN_TABLE;
+use crate::local_storage::quota_reservation::quota_reservation_table::QUOTA_RESERVATION_TABLE;
+use crate::local_storage::resync_request::resync_request_table::RESYNC_REQUEST_TABLE;
+use crate::local_storage::review_item::review_item_table::REVIEW_ITEM_TABLE;
+use crate::local_storage::sawmill::sawmill_table::{
+    SAWMILL_ASSORTMENT_JUNCTION_TABLE, SAWMILL_TABLE,
+};
+use crate::local_storage::sawmill_price::sawmill_price_table::SAWMILL_PRICE_TABLE;
+use crate::local_storage::search::search_key;
+use crate::local_storage::search::search_table::{self, SEARCH_ENTRY_TABLE};
+use crate::local_storage::settings::settings_table::SETTINGS_TABLE;
+use crate::local_storage::shipment::shipment_table::{SHIPMENT_DEFAULTS, SHIPMENT_TABLE};
+use crate::local_storage::shipment_document::shipment_document_table::SHIPMENT_DOCUMENT_TABLE;
+use crate::local_storage::signing_key::signing_key_table::SIGNING_KEY_TABLE;
+use crate::local_storage::sla_stat::sla_stat_table::SLA_STAT_TABLE;
+use crate::local_storage::sync_session::sync_session_table::{
+    SYNC_CHECKPOINT_TABLE, SYNC_SESSION_TABLE,
+};
+use crate::local_storage::tour::tour_table::TOUR_TABLE;
+use crate::local_storage::user::user_table::USER_TABLE;
+use crate::local_storage::write_verification::write_verification_table::WRITE_VERIFICATION_TABLE;
+use crate::timezone;
+use rusqlite::{Connection, Result, params};
+use serde_json::{Value, json};
+
+pub const TABLES: &[&Table] = &[
+    &USER_TABLE,
+    &CONTRACT_TABLE,
+    &SAWMILL_TABLE,
+    &LOCATION_TABLE,
+    &LOCATION_SAWMILL_JUNCTION_TABLE,
+    &NOTE_TABLE,
+    &PHOTO_TABLE,
+    &SHIPMENT_TABLE,
+    &SETTINGS_TABLE,
+    &REVIEW_ITEM_TABLE,
+    &PORTAL_TOKEN_TABLE,
+    &CREW_TABLE,
+    &CREW_MEMBER_JUNCTION_TABLE,
+    &LOCATION_ARCHIVE_TABLE,
+    &LOCATION_SAWMILL_JUNCTION_ARCHIVE_TABLE,
+    &PHOTO_ARCHIVE_TABLE,
+    &AUDIT_LOG_TABLE,
+    &SHIPMENT_DOCUMENT_TABLE,
+    &HOOK_SCRIPT_TABLE,
+    &SAWMILL_PRICE_TABLE,
+    &ASSORTMENT_TABLE,
+    &SAWMILL_ASSORTMENT_JUNCTION_TABLE,
+    &QUOTA_RESERVATION_TABLE,
+    &PHOTO_ORIGINAL_TABLE,
+    &ACTIVITY_REPORT_TABLE,
+    &USER_ACTIVITY_TABLE,
+    &SYNC_SESSION_TABLE,
+    &CONTRACT_SAWMILL_ALLOWLIST_TABLE,
+    &EVENT_LOG_TABLE,
+    &PERMISSION_TABLE,
+    &RESYNC_REQUEST_TABLE,
+    &SLA_STAT_TABLE,
+    &WRITE_VERIFICATION_TABLE,
+    &SEARCH_ENTRY_TABLE,
+    &INTEGRATION_TOKEN_TABLE,
+    &SYNC_CHECKPOINT_TABLE,
+    &TOUR_TABLE,
+    &PHOTO_RECOMPRESSION_TABLE,
+    &ACTIVITY_FEED_TABLE,
+    &CONFLICT_TABLE,
+    &DEVICE_SECRET_TABLE,
+    &SIGNING_KEY_TABLE,
+];
+
+pub const TIMESTAMP_COLUMNS: &[&str] = &[
+    "lastEdit",
+    "arrivalAtServer",
+    "date",
+    "startDate",
+    "endDate",
+    "createdAt",
+    "timestamp",
+    "validFrom",
+    "validTo",
+];
+
+pub const DEFAULTS: &[&EntityDefaults] =
+    &[&LOCATION_DEFAULTS, &CONTRACT_DEFAULTS, &SHIPMENT_DEFAULTS];
+
+pub fn table(name: &str) -> Option<&'static Table> {
+    TABLES.iter().copied().find(|table| table.name == name)
+}
+
+pub fn defaults_for_message(message_type: &str) -> Option<&'static EntityDefaults> {
+    DEFAULTS
+        .iter()
+        .copied()
+        .find(|defaults| defaults.message_type == message_type)
+}
+
+pub fn migrate(conn: &Connection) -> Result<()> {
+    for table in TABLES {
+        table.ensure(conn)?;
+        migrate_timestamps(conn, table)?;
+    }
+    search_key::backfill(conn)?;
+    search_table::ensure_index(conn)?;
+    event_log_table::ensure_index(conn)?;
+    activity_feed_table::ensure_index(conn)?;
+    conflict_table::ensure_index(conn)?;
+
+    Ok(())
+}
+
+fn migrate_timestamps(conn: &Connection, table: &Table) -> Result<()> {
+    let timestamp_columns: Vec<&str> = table
+        .columns
+        .iter()
+        .filter(|column| TIMESTAMP_COLUMNS.contains(&column.name))
+        .filter(|column| column.declared_type() == "INTEGER")
+        .map(|column| column.name)
+        .collect();
+    if timestamp_columns.is_empty() {
+        return Ok(());
+    }
+
+    let actual = column_info(conn, table.name)?;
+    if actual.iter().any(|info| {
+        timestamp_columns.contains(&info.name.as_str())
+            && !info.declared_type.eq_ignore_ascii_case("INTEGER")
+    }) {
+        println!("Rebuilding {} with INTEGER timestamp columns", table.name);
+        table.rebuild(conn)?;
+    }
+
+    for column in timestamp_columns {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT rowid, \"{column}\" FROM \"{table}\" WHERE typeof(\"{column}\") = 'text'",
+            table = table.name
+        ))?;
+        let values = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        for (rowid, value) in &values {
+            match timezone::parse_timestamp(value, &timezone::UTC) {
+                Some(millis) => {
+                    conn.execute(
+                        &format!(
+                            "UPDATE \"{}\" SET \"{}\" = ? WHERE rowid = ?",
+                            table.name, column
+                        ),
+                        params![millis, rowid],
+                    )?;
+                }
+                None => println!(
+                    "Cannot convert {}.{} value {:?} to a timestamp",
+                    table.name, column, value
+                ),
+            }
+        }
+        if !values.is_empty() {
+            println!(
+                "Converted {} {}.{} value(s) to epoch milliseconds",
+                values.len(),
+                table.name,
+                column
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub enum DriftKind {
+    MissingTable,
+    MissingColumn,
+    UnexpectedColumn,
+    DefinitionMismatch,
+}
+
+pub struct Drift {
+    pub table: &'static Table,
+    pub column: Option<String>,
+    pub kind: DriftKind,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl Drift {
+    pub fn is_additive(&self) -> bool {
+        matches!(
+            self.kind,
+            DriftKind::MissingTable | DriftKind::MissingColumn
+        )
+    }
+
+    pub fn to_json(&self) -> Value {
+        let kind = match self.kind {
+            DriftKind::MissingTable => "missing_table",
+            DriftKind::MissingColumn => "missing_column",
+            DriftKind::UnexpectedColumn => "unexpected_column",
+            DriftKind::DefinitionMismatch => "definition_mismatch",
+        };
+
+        json!({
+            "table": self.table.name,
+            "column": self.column,
+            "kind": kind,
+            "expected": self.expected,
+            "actual": self.actual,
+            "repairable": self.is_additive()
+        })
+    }
+}
+
+pub fn detect_drift(conn: &Connection) -> Result<Vec<Drift>> {
+    let mut drifts = Vec::new();
+
+    for table in TABLES {
+        let actual = column_info(conn, table.name)?;
+        if actual.is_empty() {
+            drifts.push(Drift {
+                table,
+                column: None,
+                kind: DriftKind::MissingTable,
+                expected: None,
+                actual: None,
+            });
+            continue;
+        }
+
+        for column in table.columns {
+            let Some(info) = actual.iter().find(|info| info.name == column.name) else {
+                drifts.push(Drift {
+                    table,
+                    column: Some(column.name.to_string()),
+                    kind: DriftKind::MissingColumn,
+                    expected: Some(column.definition.to_string()),
+                    actual: None,
+                });
+                continue;
+            };
+
+            if !info
+                .declared_type
+                .eq_ignore_ascii_case(column.declared_type())
+                || info.not_null != column.not_null()
+            {
+                drifts.push(Drift {
+                    table,
+                    column: Some(column.name.to_string()),
+                    kind: DriftKind::DefinitionMismatch,
+                    expected: Some(column.definition.to_string()),
+                    actual: Some(format!(
+                        "{}{}",
+                        info.declared_type,
+                        if info.not_null { " NOT NULL" } else { "" }
+                    )),
+                });
+            }
+        }
+
+        for info in &actual {
+            if table.column(&info.name).is_none() {
+                drifts.push(Drift {
+                    table,
+                    column: Some(info.name.clone()),
+                    kind: DriftKind::UnexpectedColumn,
+                    expected: None,
+                    actual: Some(info.declared_type.clone()),
+                });
+            }
+        }
+    }
+
+    Ok(drifts)
+}
+
+pub fn repair_additive(conn: &Connection, drifts: &[Drift]) -> Vec<Value> {
+    let mut tables: Vec<&'static Table> = Vec::new();
+    for drift in drifts.iter().filter(|drift| drift.is_additive()) {
+        if !tables.iter().any(|table| table.name == drift.table.name) {
+            tables.push(drift.table);
+        }
+    }
+
+    tables
+        .into_iter()
+        .map(|table| match table.ensure(conn) {
+            Ok(()) => json!({ "table": table.name, "repaired": true }),
+            Err(e) => json!({
+                "table": table.name,
+                "repaired": false,
+                "error": e.to_string()
+            }),
+        })
+        .collect()
+}
@@ -0,0 +1,3 @@
+pub mod search_key;
+pub mod search_local_storage;
+pub mod search_table;
@@ -0,0 +1,143 @@
+use rusqlite::{Connection, Result, params};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+
+/// A lowercased, diacritic-free copy of a name or title column, kept next to
+/// the column so searches can compare against it with a plain LIKE.
+pub struct SearchKey {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub key: &'static str,
+}
+
+pub const SEARCH_KEYS: &[SearchKey] = &[
+    SearchKey {
+        table: "contracts",
+        column: "title",
+        key: "titleSearch",
+    },
+    SearchKey {
+        table: "locations",
+        column: "partieNr",
+        key: "partieNrSearch",
+    },
+    SearchKey {
+        table: "sawmills",
+        column: "name",
+        key: "nameSearch",
+    },
+];
+
+pub fn key_column(table: &str, column: &str) -> Option<&'static str> {
+    SEARCH_KEYS
+        .iter()
+        .find(|key| key.table == table && key.column == column)
+        .map(|key| key.key)
+}
+
+/// Removes the search keys from a row read with `SELECT *` before it is sent
+/// to clients.
+pub fn strip(table: &str, map: &mut Map<String, Value>) {
+    for key in SEARCH_KEYS.iter().filter(|key| key.table == table) {
+        map.remove(key.key);
+    }
+}
+
+/// Folds text for searching: lowercase, accents and umlauts reduced to their
+/// base letter and ligatures spelled out, so "Sägewerk" and "SAGEWERK" both
+/// become "sagewerk".
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => folded.push('a'),
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => folded.push('c'),
+            'ď' | 'đ' => folded.push('d'),
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => folded.push('e'),
+            'ğ' | 'ġ' | 'ģ' => folded.push('g'),
+            'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' | 'ı' => folded.push('i'),
+            'ķ' => folded.push('k'),
+            'ĺ' | 'ļ' | 'ľ' | 'ł' => folded.push('l'),
+            'ñ' | 'ń' | 'ņ' | 'ň' => folded.push('n'),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => folded.push('o'),
+            'ŕ' | 'ř' => folded.push('r'),
+            'ś' | 'ş' | 'š' | 'ș' => folded.push('s'),
+            'ţ' | 'ť' | 'ț' => folded.push('t'),
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' | 'ų' => folded.push('u'),
+            'ý' | 'ÿ' => folded.push('y'),
+            'ź' | 'ż' | 'ž' => folded.push('z'),
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            c => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// Adds the search keys of a row that is about to be written. Rows that do
+/// not touch a keyed column are returned unchanged.
+pub fn with_keys<'a>(table: &str, data: &'a Value) -> Cow<'a, Value> {
+    let Value::Object(map) = data else {
+        return Cow::Borrowed(data);
+    };
+    let keys: Map<String, Value> = SEARCH_KEYS
+        .iter()
+        .filter(|key| key.table == table)
+        .filter_map(|key| {
+            let value = map.get(key.column)?;
+            Some((
+                key.key.to_string(),
+                value
+                    .as_str()
+                    .map(fold)
+                    .map(Value::String)
+                    .unwrap_or(Value::Null),
+            ))
+        })
+        .collect();
+    if keys.is_empty() {
+        return Cow::Borrowed(data);
+    }
+
+    let mut map = map.clone();
+    map.extend(keys);
+    Cow::Owned(Value::Object(map))
+}
+
+/// Fills search keys of rows written before the key columns existed.
+pub fn backfill(conn: &Connection) -> Result<()> {
+    for key in SEARCH_KEYS {
+        let rows = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, \"{column}\" FROM \"{table}\"
+                 WHERE \"{key}\" IS NULL AND \"{column}\" IS NOT NULL",
+                table = key.table,
+                column = key.column,
+                key = key.key
+            ))?;
+            stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?
+        };
+        if rows.is_empty() {
+            continue;
+        }
+
+        let tx = conn.unchecked_transaction()?;
+        for (id, value) in &rows {
+            tx.execute(
+                &format!(
+                    "UPDATE \"{}\" SET \"{}\" = ? WHERE id = ?",
+                    key.table, key.key
+                ),
+                params![fold(value), id],
+            )?;
+        }
+        tx.commit()?;
+        println!("Filled {} {} for {} rows", key.table, key.key, rows.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::search::search_table::SEARCH_INDEX;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Result, Row, params_from_iter};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const HIGHLIGHT_START: &str = "<mark>";
+const HIGHLIGHT_END: &str = "</mark>";
+const SNIPPET_TOKENS: i64 = 12;
+const TITLE_WEIGHT: f64 = 4.0;
+
+pub struct SearchLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+fn row_to_json(row: &Row) -> Result<Value> {
+    Ok(json!({
+        "entityType": row.get::<_, String>("entityType")?,
+        "id": row.get::<_, String>("entityId")?,
+        "title": row.get::<_, String>("title")?,
+        "snippet": row.get::<_, String>("snippet")?,
+        "score": -row.get::<_, f64>("score")?
+    }))
+}
+
+impl SearchLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = SearchLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn search(
+        &self,
+        match_expression: &str,
+        entity_types: &[&str],
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let type_filter = if entity_types.is_empty() {
+            String::new()
+        } else {
+            format!(
+                " AND entityType IN ({})",
+                vec!["?"; entity_types.len()].join(", ")
+            )
+        };
+        let query = format!(
+            "SELECT entityType, entityId, title,
+                 snippet({index}, -1, ?, ?, '…', ?) AS snippet,
+                 bm25({index}, 0.0, 0.0, ?, 1.0) AS score
+             FROM {index}
+             WHERE {index} MATCH ?{type_filter}
+             ORDER BY score, entityType, entityId
+             LIMIT ? OFFSET ?",
+            index = SEARCH_INDEX,
+            type_filter = type_filter
+        );
+
+        let mut params: Vec<SqlValue> = vec![
+            HIGHLIGHT_START.to_string().into(),
+            HIGHLIGHT_END.to_string().into(),
+            SNIPPET_TOKENS.into(),
+            TITLE_WEIGHT.into(),
+            match_expression.to_string().into(),
+        ];
+        params.extend(
+            entity_types
+                .iter()
+                .map(|entity_type| SqlValue::from(entity_type.to_string())),
+        );
+        params.push(limit.into());
+        params.push(offset.into());
+
+        let mut stmt = conn.prepare(&query)?;
+        stmt.query_map(params_from_iter(params), row_to_json)?
+            .collect()
+    }
+}
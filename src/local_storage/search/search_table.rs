@@ -0,0 +1,206 @@
+use crate::local_storage::core_table::{Column, Table};
+use crate::local_storage::search::search_key;
+use rusqlite::{Connection, OptionalExtension, Result};
+
+pub const SEARCH_ENTRY_TABLE: Table = Table {
+    name: "searchEntries",
+    columns: &[
+        Column::new("id", "INTEGER PRIMARY KEY"),
+        Column::new("entityType", "TEXT NOT NULL"),
+        Column::new("entityId", "TEXT NOT NULL"),
+    ],
+    constraints: &["UNIQUE (entityType, entityId)"],
+};
+
+pub const SEARCH_INDEX: &str = "searchIndex";
+
+const TOKENIZER: &str = "unicode61 remove_diacritics 2";
+
+pub struct SearchSource {
+    pub entity_type: &'static str,
+    pub table: &'static str,
+    pub title: &'static str,
+    pub body: &'static [&'static str],
+}
+
+pub const SEARCH_SOURCES: &[SearchSource] = &[
+    SearchSource {
+        entity_type: "contract",
+        table: "contracts",
+        title: "title",
+        body: &["additionalInfo"],
+    },
+    SearchSource {
+        entity_type: "location",
+        table: "locations",
+        title: "partieNr",
+        body: &["additionalInfo", "ownerInformation"],
+    },
+    SearchSource {
+        entity_type: "note",
+        table: "notes",
+        title: "text",
+        body: &[],
+    },
+    SearchSource {
+        entity_type: "sawmill",
+        table: "sawmills",
+        title: "name",
+        body: &[],
+    },
+];
+
+impl SearchSource {
+    /// The folded title goes into the body as well, so spellings such as
+    /// "ss" for "ß" match.
+    fn values(&self, row: &str) -> String {
+        let columns: Vec<&str> = self
+            .body
+            .iter()
+            .copied()
+            .chain(search_key::key_column(self.table, self.title))
+            .collect();
+        let body = if columns.is_empty() {
+            "''".to_string()
+        } else {
+            columns
+                .iter()
+                .map(|column| format!("COALESCE({}\"{}\", '')", row, column))
+                .collect::<Vec<_>>()
+                .join(" || ' ' || ")
+        };
+        format!(
+            "'{}', {row}id, COALESCE({row}\"{}\", ''), {}",
+            self.entity_type,
+            self.title,
+            body,
+            row = row
+        )
+    }
+
+    fn index_row(&self) -> String {
+        format!(
+            "INSERT OR IGNORE INTO {entries} (entityType, entityId) VALUES ('{entity}', new.id);
+             DELETE FROM {index} WHERE rowid =
+                 (SELECT id FROM {entries} WHERE entityType = '{entity}' AND entityId = new.id);
+             INSERT INTO {index} (rowid, entityType, entityId, title, body)
+                 SELECT id, {values} FROM {entries}
+                 WHERE entityType = '{entity}' AND entityId = new.id
+                 AND COALESCE(new.deleted, 0) = 0;",
+            entries = SEARCH_ENTRY_TABLE.name,
+            index = SEARCH_INDEX,
+            entity = self.entity_type,
+            values = self.values("new."),
+        )
+    }
+
+    fn unindex_row(&self) -> String {
+        format!(
+            "DELETE FROM {index} WHERE rowid =
+                 (SELECT id FROM {entries} WHERE entityType = '{entity}' AND entityId = old.id);
+             DELETE FROM {entries} WHERE entityType = '{entity}' AND entityId = old.id;",
+            entries = SEARCH_ENTRY_TABLE.name,
+            index = SEARCH_INDEX,
+            entity = self.entity_type,
+        )
+    }
+
+    fn ensure_triggers(&self, conn: &Connection) -> Result<()> {
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS {index}_{table}_insert AFTER INSERT ON \"{table}\"
+             BEGIN {index_row} END;
+             CREATE TRIGGER IF NOT EXISTS {index}_{table}_update AFTER UPDATE ON \"{table}\"
+             BEGIN {index_row} END;
+             CREATE TRIGGER IF NOT EXISTS {index}_{table}_delete AFTER DELETE ON \"{table}\"
+             BEGIN {unindex_row} END;",
+            index = SEARCH_INDEX,
+            table = self.table,
+            index_row = self.index_row(),
+            unindex_row = self.unindex_row(),
+        ))
+    }
+
+    fn backfill(&self, conn: &Connection) -> Result<usize> {
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO {entries} (entityType, entityId)
+                 SELECT '{entity}', id FROM \"{table}\"",
+                entries = SEARCH_ENTRY_TABLE.name,
+                entity = self.entity_type,
+                table = self.table,
+            ),
+            [],
+        )?;
+        conn.execute(
+            &format!(
+                "INSERT INTO {index} (rowid, entityType, entityId, title, body)
+                 SELECT entries.id, {values} FROM \"{table}\"
+                 JOIN {entries} entries
+                     ON entries.entityType = '{entity}' AND entries.entityId = \"{table}\".id
+                 WHERE COALESCE(\"{table}\".deleted, 0) = 0",
+                index = SEARCH_INDEX,
+                entries = SEARCH_ENTRY_TABLE.name,
+                entity = self.entity_type,
+                table = self.table,
+                values = self.values(&format!("\"{}\".", self.table)),
+            ),
+            [],
+        )
+    }
+}
+
+/// Drops an index built before the search keys existed, together with its
+/// triggers, so it is rebuilt with the current definition.
+fn drop_outdated_index(conn: &Connection) -> Result<bool> {
+    let definition: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [SEARCH_INDEX],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(definition) = definition else {
+        return Ok(true);
+    };
+    if definition.contains(TOKENIZER) {
+        return Ok(false);
+    }
+
+    let mut statements = format!("DROP TABLE {};", SEARCH_INDEX);
+    for source in SEARCH_SOURCES {
+        for event in ["insert", "update", "delete"] {
+            statements.push_str(&format!(
+                "DROP TRIGGER IF EXISTS {}_{}_{};",
+                SEARCH_INDEX, source.table, event
+            ));
+        }
+    }
+    conn.execute_batch(&statements)?;
+    println!("Dropped outdated {}", SEARCH_INDEX);
+    Ok(true)
+}
+
+pub fn ensure_index(conn: &Connection) -> Result<()> {
+    if drop_outdated_index(conn)? {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE {} USING fts5(
+                 entityType UNINDEXED, entityId UNINDEXED, title, body,
+                 tokenize = '{}'
+             );
+             DELETE FROM {};",
+            SEARCH_INDEX, TOKENIZER, SEARCH_ENTRY_TABLE.name
+        ))?;
+        let mut indexed = 0;
+        for source in SEARCH_SOURCES {
+            indexed += source.backfill(&tx)?;
+        }
+        tx.commit()?;
+        println!("Created {} with {} entries", SEARCH_INDEX, indexed);
+    }
+
+    for source in SEARCH_SOURCES {
+        source.ensure_triggers(conn)?;
+    }
+    Ok(())
+}
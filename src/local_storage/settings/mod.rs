@@ -0,0 +1,2 @@
+pub mod settings_local_storage;
+pub mod settings_table;
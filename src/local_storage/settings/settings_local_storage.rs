@@ -0,0 +1,55 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use std::sync::Arc;
+
+pub const CURRENCY_KEY: &str = "currency";
+pub const CURRENCIES_KEY: &str = "currencies";
+pub const FIELD_MAPPING_KEY: &str = "geojsonFieldMapping";
+pub const TIMEZONE_KEY: &str = "timezone";
+pub const DIGEST_KEY: &str = "reportDigest";
+pub const DIGEST_LAST_SENT_KEY: &str = "reportDigestLastSent";
+pub const EXPORT_POLICY_KEY: &str = "exportPolicy";
+pub const UNIT_FACTORS_KEY: &str = "unitFactors";
+pub const PHOTO_TRANSCODING_KEY: &str = "photoTranscoding";
+pub const PHOTO_LIMITS_KEY: &str = "photoLimits";
+pub const PHOTO_RECOMPRESSION_KEY: &str = "photoRecompression";
+pub const WRITE_VERIFICATION_KEY: &str = "writeVerification";
+
+pub struct SettingsLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl SettingsLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = SettingsLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT value FROM settings WHERE key = ?",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    pub fn save_setting(&self, key: &str, value: &str, last_edit: i64) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let arrival_at_server = chrono::Utc::now().timestamp_millis();
+
+        let rows_affected = conn.execute(
+            "INSERT INTO settings (key, value, lastEdit, arrivalAtServer) VALUES (?, ?, ?, ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, lastEdit = excluded.lastEdit,
+             arrivalAtServer = excluded.arrivalAtServer WHERE excluded.lastEdit > settings.lastEdit",
+            params![key, value, last_edit, arrival_at_server],
+        )?;
+
+        Ok(rows_affected > 0)
+    }
+}
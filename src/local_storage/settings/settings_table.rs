@@ -0,0 +1,12 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const SETTINGS_TABLE: Table = Table {
+    name: "settings",
+    columns: &[
+        Column::new("key", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("value", "TEXT NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+    ],
+    constraints: &[],
+};
@@ -1 +1,2 @@
 pub mod shipment_local_storage;
+pub mod shipment_table;
@@ -1,8 +1,26 @@
 use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::plausibility::NEEDS_REVIEW;
 use rusqlite::{Result, params};
 use serde_json::Value;
 use std::sync::Arc;
 
+const WEIGHT_COLUMNS: &[&str] = &[
+    "measuredWeightKg",
+    "expectedWeightKg",
+    "weightDiscrepancyPercent",
+    "weighedAt",
+    "weighbridgeId",
+];
+
+pub struct Weighing {
+    pub measured_kg: f64,
+    pub expected_kg: Option<f64>,
+    pub discrepancy_percent: Option<f64>,
+    pub weighed_at: i64,
+    pub weighbridge_id: Option<String>,
+    pub review_reason: Option<String>,
+}
+
 pub struct ShipmentLocalStorage {
     core_storage: Arc<CoreLocalStorage>,
 }
@@ -16,14 +34,15 @@ impl ShipmentLocalStorage {
         Ok(storage)
     }
 
+    #[tracing::instrument(name = "db.get_shipments_by_date", skip(self))]
     pub fn get_shipments_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
-        let query = format!(
-            "SELECT * FROM shipments WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100",
-        );
+        let query =
+            "SELECT * FROM shipments WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100"
+                .to_string();
 
         let conn = self.core_storage.get_connection()?;
         let mut stmt = conn.prepare(&query)?;
-        
+
         let rows = stmt.query_map(params![last_edit], |row| {
             let id: String = row.get(0)?;
             let last_edit: i64 = row.get(1)?;
@@ -37,6 +56,23 @@ impl ShipmentLocalStorage {
             let arrival_at_server: i64 = row.get(9)?;
             let deleted: i64 = row.get(10)?;
             let additional_info: Option<String> = row.get(11).unwrap_or(None);
+            let net_value: Option<f64> = row.get("netValue")?;
+            let vat_value: Option<f64> = row.get("vatValue")?;
+            let gross_value: Option<f64> = row.get("grossValue")?;
+            let currency: Option<String> = row.get("currency")?;
+            let review_status: Option<String> = row.get("reviewStatus")?;
+            let review_reason: Option<String> = row.get("reviewReason")?;
+            let unit: Option<String> = row.get("unit")?;
+            let entered_unit: Option<String> = row.get("enteredUnit")?;
+            let assortment_id: Option<String> = row.get("assortmentId")?;
+            let distance_km: Option<f64> = row.get("distanceKm")?;
+            let distance_source: Option<String> = row.get("distanceSource")?;
+            let measured_weight_kg: Option<f64> = row.get("measuredWeightKg")?;
+            let expected_weight_kg: Option<f64> = row.get("expectedWeightKg")?;
+            let weight_discrepancy_percent: Option<f64> = row.get("weightDiscrepancyPercent")?;
+            let weighed_at: Option<i64> = row.get("weighedAt")?;
+            let weighbridge_id: Option<String> = row.get("weighbridgeId")?;
+            let tour_id: Option<String> = row.get("tourId")?;
 
             let mut shipment_json = serde_json::json!({
                 "id": id,
@@ -49,7 +85,24 @@ impl ShipmentLocalStorage {
                 "sawmillId": sawmill_id,
                 "locationId": location_id,
                 "arrivalAtServer": arrival_at_server,
-                "deleted": deleted
+                "deleted": deleted,
+                "netValue": net_value,
+                "vatValue": vat_value,
+                "grossValue": gross_value,
+                "currency": currency,
+                "reviewStatus": review_status,
+                "reviewReason": review_reason,
+                "unit": unit,
+                "enteredUnit": entered_unit,
+                "assortmentId": assortment_id,
+                "distanceKm": distance_km,
+                "distanceSource": distance_source,
+                "measuredWeightKg": measured_weight_kg,
+                "expectedWeightKg": expected_weight_kg,
+                "weightDiscrepancyPercent": weight_discrepancy_percent,
+                "weighedAt": weighed_at,
+                "weighbridgeId": weighbridge_id,
+                "tourId": tour_id
             });
 
             if let Some(info) = additional_info {
@@ -73,12 +126,47 @@ impl ShipmentLocalStorage {
     pub fn save_shipment(&self, shipment_data: &Value) -> Result<bool> {
         let mut shipment_for_save = shipment_data.clone();
         if let serde_json::Value::Object(ref mut map) = shipment_for_save {
-            map.insert("arrivalAtServer".to_string(), chrono::Utc::now().timestamp_millis().into());
+            for key in WEIGHT_COLUMNS {
+                map.remove(*key);
+            }
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
         }
 
-        let result = self.core_storage
+        let result = self
+            .core_storage
             .insert_or_update("shipments", &shipment_for_save)?;
 
         Ok(result)
     }
+
+    pub fn record_weighing(&self, id: &str, weighing: &Weighing) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let changed = conn.execute(
+            "UPDATE shipments SET measuredWeightKg = ?, expectedWeightKg = ?,
+                 weightDiscrepancyPercent = ?, weighedAt = ?, weighbridgeId = ?,
+                 reviewStatus = COALESCE(?, reviewStatus),
+                 reviewReason = COALESCE(?, reviewReason),
+                 lastEdit = ?, arrivalAtServer = ?
+             WHERE id = ? AND deleted = 0",
+            params![
+                weighing.measured_kg,
+                weighing.expected_kg,
+                weighing.discrepancy_percent,
+                weighing.weighed_at,
+                weighing.weighbridge_id,
+                weighing.review_reason.as_ref().map(|_| NEEDS_REVIEW),
+                weighing.review_reason,
+                now,
+                now,
+                id
+            ],
+        )?;
+
+        Ok(changed > 0)
+    }
 }
@@ -0,0 +1,47 @@
+use crate::local_storage::core_table::{Column, EntityDefaults, FieldDefault, Table};
+
+pub const SHIPMENT_TABLE: Table = Table {
+    name: "shipments",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("quantity", "REAL NOT NULL"),
+        Column::new("oversizeQuantity", "REAL NOT NULL"),
+        Column::new("pieceCount", "INTEGER NOT NULL"),
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("contractId", "TEXT NOT NULL"),
+        Column::new("sawmillId", "TEXT NOT NULL"),
+        Column::new("locationId", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("additionalInfo", "TEXT"),
+        Column::new("netValue", "REAL"),
+        Column::new("vatValue", "REAL"),
+        Column::new("grossValue", "REAL"),
+        Column::new("currency", "TEXT"),
+        Column::new("reviewStatus", "TEXT"),
+        Column::new("reviewReason", "TEXT"),
+        Column::new("unit", "TEXT DEFAULT 'fm'"),
+        Column::new("enteredUnit", "TEXT"),
+        Column::new("assortmentId", "TEXT"),
+        Column::new("distanceKm", "REAL"),
+        Column::new("distanceSource", "TEXT"),
+        Column::new("measuredWeightKg", "REAL"),
+        Column::new("expectedWeightKg", "REAL"),
+        Column::new("weightDiscrepancyPercent", "REAL"),
+        Column::new("weighedAt", "INTEGER"),
+        Column::new("weighbridgeId", "TEXT"),
+        Column::new("tourId", "TEXT"),
+    ],
+    constraints: &[],
+};
+
+pub const SHIPMENT_DEFAULTS: EntityDefaults = EntityDefaults {
+    table: "shipments",
+    message_type: "shipment_update",
+    fields: &[
+        ("oversizeQuantity", FieldDefault::Real(0.0)),
+        ("pieceCount", FieldDefault::Integer(0)),
+        ("additionalInfo", FieldDefault::Text("")),
+    ],
+};
@@ -0,0 +1,2 @@
+pub mod shipment_document_local_storage;
+pub mod shipment_document_table;
@@ -0,0 +1,116 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use base64::prelude::*;
+use rusqlite::{Result, Row, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// Documents per sync page; they carry their files, so pages stay small.
+pub const SYNC_PAGE_SIZE: usize = 20;
+
+pub struct ShipmentDocument<'a> {
+    pub shipment_id: &'a str,
+    pub last_edit: i64,
+    pub mime_type: &'a str,
+    pub document: &'a [u8],
+    pub document_hash: &'a str,
+    pub uploaded_by: &'a str,
+}
+
+pub struct ShipmentDocumentLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl ShipmentDocumentLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = ShipmentDocumentLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn save_document(&self, document: &ShipmentDocument) -> Result<Value> {
+        let arrival_at_server = chrono::Utc::now().timestamp_millis();
+
+        let conn = self.core_storage.get_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO shipmentDocuments
+             (shipmentId, lastEdit, mimeType, document, documentHash, uploadedBy, arrivalAtServer, deleted)
+             VALUES (?, ?, ?, ?, ?, ?, ?, 0)",
+            params![
+                document.shipment_id,
+                document.last_edit,
+                document.mime_type,
+                document.document,
+                document.document_hash,
+                document.uploaded_by,
+                arrival_at_server
+            ],
+        )?;
+
+        Ok(json!({
+            "shipmentId": document.shipment_id,
+            "lastEdit": document.last_edit,
+            "mimeType": document.mime_type,
+            "document": BASE64_STANDARD.encode(document.document),
+            "documentHash": document.document_hash,
+            "size": document.document.len(),
+            "uploadedBy": document.uploaded_by,
+            "arrivalAtServer": arrival_at_server,
+            "deleted": 0
+        }))
+    }
+
+    pub fn mark_document_deleted(&self, shipment_id: &str, last_edit: i64) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let changed = conn.execute(
+            "UPDATE shipmentDocuments SET deleted = 1, document = x'', lastEdit = ?, arrivalAtServer = ?
+             WHERE shipmentId = ? AND deleted = 0",
+            params![
+                last_edit,
+                chrono::Utc::now().timestamp_millis(),
+                shipment_id
+            ],
+        )?;
+
+        Ok(changed > 0)
+    }
+
+    pub fn get_document(&self, shipment_id: &str) -> Result<Option<(String, Vec<u8>)>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT mimeType, document FROM shipmentDocuments WHERE shipmentId = ? AND deleted = 0",
+        )?;
+
+        stmt.query_map(params![shipment_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .next()
+            .transpose()
+    }
+
+    #[tracing::instrument(name = "db.get_shipment_document_updates_by_date", skip(self))]
+    pub fn get_document_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM shipmentDocuments WHERE arrivalAtServer > ?
+             ORDER BY arrivalAtServer ASC LIMIT ?",
+        )?;
+
+        let rows = stmt.query_map(params![last_edit, SYNC_PAGE_SIZE as i64], row_to_json)?;
+        rows.collect()
+    }
+}
+
+fn row_to_json(row: &Row) -> Result<Value> {
+    let document: Vec<u8> = row.get("document")?;
+    Ok(json!({
+        "shipmentId": row.get::<_, String>("shipmentId")?,
+        "lastEdit": row.get::<_, i64>("lastEdit")?,
+        "mimeType": row.get::<_, String>("mimeType")?,
+        "document": BASE64_STANDARD.encode(&document),
+        "documentHash": row.get::<_, String>("documentHash")?,
+        "size": document.len(),
+        "uploadedBy": row.get::<_, String>("uploadedBy")?,
+        "arrivalAtServer": row.get::<_, i64>("arrivalAtServer")?,
+        "deleted": row.get::<_, Option<i64>>("deleted")?.unwrap_or(0)
+    }))
+}
@@ -0,0 +1,16 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const SHIPMENT_DOCUMENT_TABLE: Table = Table {
+    name: "shipmentDocuments",
+    columns: &[
+        Column::new("shipmentId", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("mimeType", "TEXT NOT NULL"),
+        Column::new("document", "BLOB NOT NULL"),
+        Column::new("documentHash", "TEXT NOT NULL"),
+        Column::new("uploadedBy", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
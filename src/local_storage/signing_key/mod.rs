@@ -0,0 +1,2 @@
+pub mod signing_key_local_storage;
+pub mod signing_key_table;
@@ -0,0 +1,37 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub const PHOTO_URLS_PURPOSE: &str = "photoUrls";
+
+pub struct SigningKeyLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl SigningKeyLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = SigningKeyLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    /// The tenant's key for `purpose`, generated on first use. Instances
+    /// sharing the database agree on it, whichever created it.
+    pub fn get_or_create_key(&self, purpose: &str) -> Result<String> {
+        let conn = self.core_storage.get_connection()?;
+        let key = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+        conn.execute(
+            "INSERT OR IGNORE INTO signingKeys (purpose, key, createdAt) VALUES (?, ?, ?)",
+            params![purpose, key, chrono::Utc::now().timestamp_millis()],
+        )?;
+        conn.query_row(
+            "SELECT key FROM signingKeys WHERE purpose = ?",
+            params![purpose],
+            |row| row.get(0),
+        )
+    }
+}
@@ -0,0 +1,11 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const SIGNING_KEY_TABLE: Table = Table {
+    name: "signingKeys",
+    columns: &[
+        Column::new("purpose", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("key", "TEXT NOT NULL"),
+        Column::new("createdAt", "INTEGER NOT NULL"),
+    ],
+    constraints: &[],
+};
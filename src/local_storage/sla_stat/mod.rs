@@ -0,0 +1,2 @@
+pub mod sla_stat_local_storage;
+pub mod sla_stat_table;
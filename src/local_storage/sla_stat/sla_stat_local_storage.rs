@@ -0,0 +1,68 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use std::sync::Arc;
+
+pub struct SlaStat {
+    pub day: String,
+    pub metric: String,
+    pub count: i64,
+    pub total: f64,
+}
+
+pub struct SlaStatLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl SlaStatLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = SlaStatLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn add_stats(&self, stats: &[SlaStat]) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+        let tx = conn.unchecked_transaction()?;
+
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO slaStats (day, metric, count, total) VALUES (?, ?, ?, ?)
+                 ON CONFLICT(day, metric) DO UPDATE SET
+                     count = count + excluded.count,
+                     total = total + excluded.total",
+            )?;
+            for stat in stats {
+                stmt.execute(params![stat.day, stat.metric, stat.count, stat.total])?;
+            }
+        }
+
+        tx.commit()
+    }
+
+    pub fn get_stats(&self, from: &str, to: &str) -> Result<Vec<SlaStat>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT day, metric, count, total FROM slaStats
+             WHERE day >= ? AND day <= ?
+             ORDER BY day, metric",
+        )?;
+
+        stmt.query_map(params![from, to], |row| {
+            Ok(SlaStat {
+                day: row.get(0)?,
+                metric: row.get(1)?,
+                count: row.get(2)?,
+                total: row.get(3)?,
+            })
+        })?
+        .collect()
+    }
+
+    pub fn delete_before(&self, day: &str) -> Result<usize> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute("DELETE FROM slaStats WHERE day < ?", params![day])
+    }
+}
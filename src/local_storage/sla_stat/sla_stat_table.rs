@@ -0,0 +1,12 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const SLA_STAT_TABLE: Table = Table {
+    name: "slaStats",
+    columns: &[
+        Column::new("day", "TEXT NOT NULL"),
+        Column::new("metric", "TEXT NOT NULL"),
+        Column::new("count", "INTEGER NOT NULL DEFAULT 0"),
+        Column::new("total", "REAL NOT NULL DEFAULT 0"),
+    ],
+    constraints: &["PRIMARY KEY (day, metric)"],
+};
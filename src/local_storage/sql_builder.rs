@@ -0,0 +1,104 @@
+use crate::local_storage::core_table::Table;
+use crate::local_storage::schema;
+use rusqlite::{Error, Result};
+
+pub struct SqlBuilder {
+    table: &'static Table,
+}
+
+impl SqlBuilder {
+    pub fn for_table(table_name: &str) -> Result<Self> {
+        schema::table(table_name)
+            .map(|table| SqlBuilder { table })
+            .ok_or_else(|| Error::InvalidParameterName(format!("Unknown table: {}", table_name)))
+    }
+
+    pub fn has_column(&self, column_name: &str) -> bool {
+        self.table.column(column_name).is_some()
+    }
+
+    pub fn select_all(&self) -> String {
+        format!("SELECT * FROM {}", quote(self.table.name))
+    }
+
+    pub fn select_where(&self, conditions: &[&str]) -> Result<String> {
+        Ok(format!(
+            "{} WHERE {}",
+            self.select_all(),
+            self.assignments(conditions, " AND ")?
+        ))
+    }
+
+    pub fn select_column_where(&self, column: &str, conditions: &[&str]) -> Result<String> {
+        Ok(format!(
+            "SELECT {} FROM {} WHERE {}",
+            self.column(column)?,
+            quote(self.table.name),
+            self.assignments(conditions, " AND ")?
+        ))
+    }
+
+    pub fn select_columns_where(&self, columns: &[&str], conditions: &[&str]) -> Result<String> {
+        let names = columns
+            .iter()
+            .map(|column| self.column(column))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(format!(
+            "SELECT {} FROM {} WHERE {}",
+            names.join(", "),
+            quote(self.table.name),
+            self.assignments(conditions, " AND ")?
+        ))
+    }
+
+    pub fn insert_or_replace(&self, columns: &[&str]) -> Result<String> {
+        let names = columns
+            .iter()
+            .map(|column| self.column(column))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(format!(
+            "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+            quote(self.table.name),
+            names.join(", "),
+            vec!["?"; names.len()].join(", ")
+        ))
+    }
+
+    pub fn update_where(&self, columns: &[&str], conditions: &[&str]) -> Result<String> {
+        Ok(format!(
+            "UPDATE {} SET {} WHERE {}",
+            quote(self.table.name),
+            self.assignments(columns, ", ")?,
+            self.assignments(conditions, " AND ")?
+        ))
+    }
+
+    pub fn delete_where(&self, conditions: &[&str]) -> Result<String> {
+        Ok(format!(
+            "DELETE FROM {} WHERE {}",
+            quote(self.table.name),
+            self.assignments(conditions, " AND ")?
+        ))
+    }
+
+    fn assignments(&self, columns: &[&str], separator: &str) -> Result<String> {
+        Ok(columns
+            .iter()
+            .map(|column| Ok(format!("{} = ?", self.column(column)?)))
+            .collect::<Result<Vec<_>>>()?
+            .join(separator))
+    }
+
+    fn column(&self, column_name: &str) -> Result<String> {
+        self.table
+            .column(column_name)
+            .map(|column| quote(column.name))
+            .ok_or_else(|| Error::InvalidColumnName(format!("{}.{}", self.table.name, column_name)))
+    }
+}
+
+fn quote(identifier: &'static str) -> String {
+    format!("\"{}\"", identifier)
+}
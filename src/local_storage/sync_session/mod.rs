@@ -0,0 +1,2 @@
+pub mod sync_session_local_storage;
+pub mod sync_session_table;
@@ -0,0 +1,128 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{OptionalExtension, Result, params};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct SyncSessionLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl SyncSessionLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = SyncSessionLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_interrupted_session(&self, user_id: &str) -> Result<Option<Value>> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.query_row(
+            "SELECT clientId, startedAt, updatedAt, cursors FROM syncSessions
+             WHERE userId = ? AND completedAt IS NULL",
+            params![user_id],
+            |row| {
+                let cursors: String = row.get(3)?;
+                Ok(json!({
+                    "clientId": row.get::<_, String>(0)?,
+                    "startedAt": row.get::<_, i64>(1)?,
+                    "updatedAt": row.get::<_, i64>(2)?,
+                    "cursors": serde_json::from_str::<Value>(&cursors).unwrap_or(json!({}))
+                }))
+            },
+        )
+        .optional()
+    }
+
+    pub fn start_session(
+        &self,
+        user_id: &str,
+        client_id: &str,
+        started_at: i64,
+        cursors: &Value,
+    ) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO syncSessions
+             (userId, clientId, startedAt, updatedAt, cursors, completedAt)
+             VALUES (?, ?, ?, ?, ?, NULL)",
+            params![
+                user_id,
+                client_id,
+                started_at,
+                started_at,
+                cursors.to_string()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_cursors(&self, user_id: &str, cursors: &Value, updated_at: i64) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "UPDATE syncSessions SET cursors = ?, updatedAt = ?
+             WHERE userId = ? AND completedAt IS NULL",
+            params![cursors.to_string(), updated_at, user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn complete_session(&self, user_id: &str, completed_at: i64) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "UPDATE syncSessions SET completedAt = ?, updatedAt = ?
+             WHERE userId = ? AND completedAt IS NULL",
+            params![completed_at, completed_at, user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_checkpoints(&self, user_id: &str, device_id: &str) -> Result<HashMap<String, i64>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT entity, cursor FROM syncCheckpoints WHERE userId = ? AND deviceId = ?",
+        )?;
+        let rows = stmt.query_map(params![user_id, device_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+
+        rows.collect()
+    }
+
+    pub fn save_checkpoints(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        checkpoints: &HashMap<String, i64>,
+        updated_at: i64,
+    ) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        for (entity, cursor) in checkpoints {
+            conn.execute(
+                "INSERT INTO syncCheckpoints (userId, deviceId, entity, cursor, updatedAt)
+                 VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT (userId, deviceId, entity)
+                 DO UPDATE SET cursor = MAX(cursor, excluded.cursor), updatedAt = excluded.updatedAt",
+                params![user_id, device_id, entity, cursor, updated_at],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn clear_checkpoints(&self, user_id: &str, device_id: &str) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "DELETE FROM syncCheckpoints WHERE userId = ? AND deviceId = ?",
+            params![user_id, device_id],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const SYNC_SESSION_TABLE: Table = Table {
+    name: "syncSessions",
+    columns: &[
+        Column::new("userId", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("clientId", "TEXT NOT NULL"),
+        Column::new("startedAt", "INTEGER NOT NULL"),
+        Column::new("updatedAt", "INTEGER NOT NULL"),
+        Column::new("cursors", "TEXT NOT NULL"),
+        Column::new("completedAt", "INTEGER"),
+    ],
+    constraints: &[],
+};
+
+pub const SYNC_CHECKPOINT_TABLE: Table = Table {
+    name: "syncCheckpoints",
+    columns: &[
+        Column::new("userId", "TEXT NOT NULL"),
+        Column::new("deviceId", "TEXT NOT NULL"),
+        Column::new("entity", "TEXT NOT NULL"),
+        Column::new("cursor", "INTEGER NOT NULL"),
+        Column::new("updatedAt", "INTEGER NOT NULL"),
+    ],
+    constraints: &["PRIMARY KEY (userId, deviceId, entity)"],
+};
@@ -0,0 +1,2 @@
+pub mod tour_local_storage;
+pub mod tour_table;
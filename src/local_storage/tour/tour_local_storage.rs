@@ -0,0 +1,145 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, params};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub struct TourLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+impl TourLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = TourLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn get_tour_by_id(&self, id: &str) -> Result<Option<Value>> {
+        let tour_json = self.core_storage.get_existing_by_id("tours", id)?;
+
+        Ok(tour_json.into_iter().next())
+    }
+
+    #[tracing::instrument(name = "db.get_tour_updates_by_date", skip(self))]
+    pub fn get_tour_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
+        let query = "SELECT * FROM tours WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![last_edit], row_to_json)?;
+
+        let mut tours = Vec::new();
+        for row in rows {
+            match row {
+                Ok(tour) => tours.push(tour),
+                Err(e) => eprintln!("Error fetching tour: {}", e),
+            }
+        }
+
+        Ok(tours)
+    }
+
+    /// Tours dated within [from, to), optionally of one driver.
+    pub fn get_tours_between(
+        &self,
+        from: i64,
+        to: i64,
+        driver_id: Option<&str>,
+    ) -> Result<Vec<Value>> {
+        let query = "SELECT * FROM tours WHERE deleted = 0 AND date >= ? AND date < ?
+                     AND (?3 IS NULL OR driverId = ?3) ORDER BY date ASC, id ASC";
+
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt = conn.prepare(query)?;
+
+        let rows = stmt.query_map(params![from, to, driver_id], row_to_json)?;
+        rows.collect()
+    }
+
+    pub fn save_tour(&self, tour_data: &Value) -> Result<bool> {
+        let mut tour_for_save = tour_data.clone();
+        if let serde_json::Value::Object(ref mut map) = tour_for_save {
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
+        }
+
+        self.core_storage.insert_or_update("tours", &tour_for_save)
+    }
+
+    /// Moves a tour from one status to the next and stamps the matching
+    /// timestamp column. Returns false when the tour was not in `from`.
+    pub fn transition(
+        &self,
+        id: &str,
+        from: &str,
+        to: &str,
+        timestamp_column: &str,
+        at: i64,
+    ) -> Result<bool> {
+        let conn = self.core_storage.get_connection()?;
+        let changed = conn.execute(
+            &format!(
+                "UPDATE tours SET status = ?, {} = ?, lastEdit = ?, arrivalAtServer = ?
+                 WHERE id = ? AND status = ? AND deleted = 0",
+                timestamp_column
+            ),
+            params![to, at, at, at, id, from],
+        )?;
+
+        Ok(changed > 0)
+    }
+
+    /// Totals over the shipments of a tour. Stops are the distinct locations
+    /// the shipments were loaded at.
+    pub fn get_summary(&self, tour_id: &str) -> Result<Value> {
+        let conn = self.core_storage.get_connection()?;
+        conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(quantity), 0), COUNT(DISTINCT locationId),
+                    COALESCE(SUM(distanceKm), 0), COUNT(distanceKm)
+             FROM shipments WHERE tourId = ? AND deleted = 0",
+            params![tour_id],
+            |row| {
+                let shipments: i64 = row.get(0)?;
+                let with_distance: i64 = row.get(4)?;
+                Ok(serde_json::json!({
+                    "shipments": shipments,
+                    "totalQuantity": (row.get::<_, f64>(1)? * 100.0).round() / 100.0,
+                    "stops": row.get::<_, i64>(2)?,
+                    "distanceKm": (row.get::<_, f64>(3)? * 100.0).round() / 100.0,
+                    "shipmentsWithoutDistance": shipments - with_distance
+                }))
+            },
+        )
+    }
+}
+
+fn row_to_json(row: &rusqlite::Row) -> Result<Value> {
+    let id: String = row.get("id")?;
+    let last_edit: i64 = row.get("lastEdit")?;
+    let driver_id: String = row.get("driverId")?;
+    let vehicle: Option<String> = row.get("vehicle")?;
+    let date: i64 = row.get("date")?;
+    let status: String = row.get("status")?;
+    let started_at: Option<i64> = row.get("startedAt")?;
+    let finished_at: Option<i64> = row.get("finishedAt")?;
+    let arrival_at_server: i64 = row.get("arrivalAtServer")?;
+    let deleted: i64 = row.get("deleted")?;
+
+    Ok(serde_json::json!({
+        "id": id,
+        "lastEdit": last_edit,
+        "driverId": driver_id,
+        "vehicle": vehicle,
+        "date": date,
+        "status": status,
+        "startedAt": started_at,
+        "finishedAt": finished_at,
+        "arrivalAtServer": arrival_at_server,
+        "deleted": deleted
+    }))
+}
@@ -0,0 +1,18 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const TOUR_TABLE: Table = Table {
+    name: "tours",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("driverId", "TEXT NOT NULL"),
+        Column::new("vehicle", "TEXT"),
+        Column::new("date", "INTEGER NOT NULL"),
+        Column::new("status", "TEXT NOT NULL DEFAULT 'planned'"),
+        Column::new("startedAt", "INTEGER"),
+        Column::new("finishedAt", "INTEGER"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+    ],
+    constraints: &[],
+};
@@ -1 +1,2 @@
 pub mod user_local_storage;
+pub mod user_table;
@@ -26,14 +26,14 @@ impl UserLocalStorage {
         Ok(Some(user_json[0].clone()))
     }
 
+    #[tracing::instrument(name = "db.get_user_updates_by_date", skip(self))]
     pub fn get_user_updates_by_date(&self, last_edit: i64) -> Result<Vec<Value>> {
-        let query = format!(
-            "SELECT * FROM users WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100",
-        );
+        let query = "SELECT * FROM users WHERE arrivalAtServer > ? ORDER BY lastEdit ASC LIMIT 100"
+            .to_string();
 
         let conn = self.core_storage.get_connection()?;
         let mut stmt = conn.prepare(&query)?;
-        
+
         let rows = stmt.query_map(params![last_edit], |row| {
             let id: String = row.get(0)?;
             let last_edit: i64 = row.get(1)?;
@@ -41,6 +41,7 @@ impl UserLocalStorage {
             let name: String = row.get(3)?;
             let arrival_at_server: i64 = row.get(4)?;
             let deleted: i64 = row.get(5)?;
+            let custom_role: Option<String> = row.get("customRole")?;
 
             let user_json = serde_json::json!({
                 "id": id,
@@ -48,7 +49,8 @@ impl UserLocalStorage {
                 "role": role,
                 "name": name,
                 "arrivalAtServer": arrival_at_server,
-                "deleted": deleted
+                "deleted": deleted,
+                "customRole": custom_role
             });
 
             Ok(user_json)
@@ -68,10 +70,15 @@ impl UserLocalStorage {
     pub fn save_user(&self, user_data: &Value) -> Result<bool> {
         let mut user_for_save = user_data.clone();
         if let serde_json::Value::Object(ref mut map) = user_for_save {
-            map.insert("arrivalAtServer".to_string(), chrono::Utc::now().timestamp_millis().into());
+            map.remove("customRole");
+            map.insert(
+                "arrivalAtServer".to_string(),
+                chrono::Utc::now().timestamp_millis().into(),
+            );
         }
 
-        let result = self.core_storage
+        let result = self
+            .core_storage
             .insert_or_update("users", &user_for_save)?;
 
         Ok(result)
@@ -0,0 +1,15 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const USER_TABLE: Table = Table {
+    name: "users",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("lastEdit", "INTEGER NOT NULL"),
+        Column::new("role", "INTEGER NOT NULL"),
+        Column::new("name", "TEXT NOT NULL"),
+        Column::new("arrivalAtServer", "INTEGER NOT NULL"),
+        Column::new("deleted", "INTEGER DEFAULT 0"),
+        Column::new("customRole", "TEXT"),
+    ],
+    constraints: &[],
+};
@@ -0,0 +1,2 @@
+pub mod write_verification_local_storage;
+pub mod write_verification_table;
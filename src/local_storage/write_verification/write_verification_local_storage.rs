@@ -0,0 +1,64 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use rusqlite::{Result, Row, params};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct WriteVerificationLocalStorage {
+    core_storage: Arc<CoreLocalStorage>,
+}
+
+fn row_to_json(row: &Row) -> Result<Value> {
+    let differences: String = row.get("differences")?;
+    Ok(json!({
+        "id": row.get::<_, String>("id")?,
+        "timestamp": row.get::<_, i64>("timestamp")?,
+        "userId": row.get::<_, Option<String>>("userId")?,
+        "messageType": row.get::<_, String>("messageType")?,
+        "entityId": row.get::<_, String>("entityId")?,
+        "outcome": row.get::<_, String>("outcome")?,
+        "payloadLastEdit": row.get::<_, Option<i64>>("payloadLastEdit")?,
+        "storedLastEdit": row.get::<_, Option<i64>>("storedLastEdit")?,
+        "differences": serde_json::from_str::<Value>(&differences).unwrap_or(json!([]))
+    }))
+}
+
+impl WriteVerificationLocalStorage {
+    pub fn new(core_storage: Arc<CoreLocalStorage>) -> Result<Self> {
+        let storage = WriteVerificationLocalStorage {
+            core_storage: core_storage.clone(),
+        };
+
+        Ok(storage)
+    }
+
+    pub fn record_failure(&self, failure: &Value) -> Result<()> {
+        let conn = self.core_storage.get_connection()?;
+
+        conn.execute(
+            "INSERT INTO writeVerifications
+             (id, timestamp, userId, messageType, entityId, outcome,
+              payloadLastEdit, storedLastEdit, differences)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                failure["id"].as_str(),
+                failure["timestamp"].as_i64(),
+                failure["userId"].as_str(),
+                failure["messageType"].as_str(),
+                failure["entityId"].as_str(),
+                failure["outcome"].as_str(),
+                failure["payloadLastEdit"].as_i64(),
+                failure["storedLastEdit"].as_i64(),
+                failure["differences"].to_string()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_failures(&self, limit: i64) -> Result<Vec<Value>> {
+        let conn = self.core_storage.get_connection()?;
+        let mut stmt =
+            conn.prepare("SELECT * FROM writeVerifications ORDER BY timestamp DESC LIMIT ?")?;
+
+        stmt.query_map(params![limit], row_to_json)?.collect()
+    }
+}
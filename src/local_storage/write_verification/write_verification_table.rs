@@ -0,0 +1,17 @@
+use crate::local_storage::core_table::{Column, Table};
+
+pub const WRITE_VERIFICATION_TABLE: Table = Table {
+    name: "writeVerifications",
+    columns: &[
+        Column::new("id", "TEXT PRIMARY KEY NOT NULL"),
+        Column::new("timestamp", "INTEGER NOT NULL"),
+        Column::new("userId", "TEXT"),
+        Column::new("messageType", "TEXT NOT NULL"),
+        Column::new("entityId", "TEXT NOT NULL"),
+        Column::new("outcome", "TEXT NOT NULL"),
+        Column::new("payloadLastEdit", "INTEGER"),
+        Column::new("storedLastEdit", "INTEGER"),
+        Column::new("differences", "TEXT NOT NULL DEFAULT '[]'"),
+    ],
+    constraints: &[],
+};
@@ -0,0 +1,160 @@
+use crate::config;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use rusqlite::Connection;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use warp::{Filter, Rejection};
+
+pub fn check_all_tenants() {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    let mut flagged_locations = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+
+        match Connection::open(&path).and_then(|conn| find_missing_junctions(&conn)) {
+            Ok(flagged) if !flagged.is_empty() => {
+                flagged_locations += flagged.len() as i64;
+                eprintln!(
+                    "Tenant {} has {} location(s) with missing sawmill links: {}",
+                    tenant,
+                    flagged.len(),
+                    Value::Array(flagged)
+                );
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!(
+                "Failed to check location sawmill links of tenant {}: {:?}",
+                tenant, e
+            ),
+        }
+    }
+
+    metrics::set_gauge("locations_missing_junctions", flagged_locations);
+    println!(
+        "Location consistency check finished, {} location(s) flagged",
+        flagged_locations
+    );
+}
+
+fn find_missing_junctions(conn: &Connection) -> rusqlite::Result<Vec<Value>> {
+    let mut actual: HashMap<(String, bool), BTreeSet<String>> = HashMap::new();
+    {
+        let mut stmt =
+            conn.prepare("SELECT locationId, sawmillId, isOversize FROM locationSawmillJunction")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)? != 0,
+            ))
+        })?;
+        for row in rows {
+            let (location_id, sawmill_id, is_oversize) = row?;
+            actual
+                .entry((location_id, is_oversize))
+                .or_default()
+                .insert(sawmill_id);
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT l.id, l.partieNr, l.contractId, e.seq, e.payload
+         FROM locations l
+         JOIN eventLog e ON e.seq = (
+             SELECT MAX(seq) FROM eventLog
+             WHERE eventType = 'location_update' AND entityId = l.id
+         )
+         WHERE l.deleted = 0
+         ORDER BY l.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+
+    let mut flagged = Vec::new();
+    for row in rows {
+        let (id, partie_nr, contract_id, seq, payload) = row?;
+        let payload: Value = serde_json::from_str(&payload).unwrap_or(Value::Null);
+
+        let mut missing = json!({});
+        for (field, is_oversize) in [("sawmillIds", false), ("oversizeSawmillIds", true)] {
+            let linked = actual.get(&(id.clone(), is_oversize));
+            let absent: Vec<&str> = payload[field]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .filter(|sawmill_id| !linked.is_some_and(|linked| linked.contains(*sawmill_id)))
+                .collect();
+            if !absent.is_empty() {
+                missing[field] = json!(absent);
+            }
+        }
+
+        if missing.as_object().is_some_and(|m| !m.is_empty()) {
+            flagged.push(json!({
+                "id": id,
+                "partieNr": partie_nr,
+                "contractId": contract_id,
+                "eventSeq": seq,
+                "missing": missing
+            }));
+        }
+    }
+
+    Ok(flagged)
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    warp::path!("data-quality" / "location-junctions")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            junction_report(authorization, &db_pools).unwrap_or_else(|reply| reply)
+        })
+}
+
+fn junction_report(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let flagged = core_storage
+        .get_connection()
+        .and_then(|conn| find_missing_junctions(&conn))
+        .map_err(|e| rest::internal_error("Failed to check location sawmill links", e))?;
+
+    Ok(rest::json_reply(&json!({
+        "flagged": flagged.len(),
+        "locations": flagged
+    })))
+}
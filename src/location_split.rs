@@ -0,0 +1,199 @@
+use crate::local_storage::audit_log::audit_log_local_storage::AuditLogLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const SPLIT_QUANTITIES: &[(&str, &str, &str)] = &[
+    ("quantity", "currentQuantity", "initialQuantity"),
+    (
+        "oversizeQuantity",
+        "currentOversizeQuantity",
+        "initialOversizeQuantity",
+    ),
+    ("pieceCount", "currentPieceCount", "initialPieceCount"),
+];
+
+const OVERRIDABLE_FIELDS: &[&str] = &[
+    "latitude",
+    "longitude",
+    "partieNr",
+    "additionalInfo",
+    "ownerInformation",
+    "crewId",
+];
+
+pub fn split_location(
+    core_storage: Arc<CoreLocalStorage>,
+    data: &Value,
+    user_id: &str,
+) -> Result<Vec<(&'static str, Value)>, String> {
+    core_storage
+        .begin()
+        .map_err(|e| format!("Failed to start transaction: {:?}", e))?;
+
+    match apply_split(core_storage.clone(), data, user_id) {
+        Ok(updates) => {
+            core_storage
+                .commit()
+                .map_err(|e| format!("Failed to commit location split: {:?}", e))?;
+            Ok(updates)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = core_storage.rollback() {
+                eprintln!("Failed to roll back location split: {:?}", rollback_error);
+            }
+            Err(e)
+        }
+    }
+}
+
+fn moved_amount(
+    data: &Value,
+    field: &str,
+    current: f64,
+    ratio: Option<f64>,
+) -> Result<f64, String> {
+    let moved = match (data.get(field).filter(|v| !v.is_null()), ratio) {
+        (Some(value), _) => value
+            .as_f64()
+            .ok_or_else(|| format!("{} must be a number", field))?,
+        (None, Some(ratio)) => current * ratio,
+        (None, None) => 0.0,
+    };
+    if moved < 0.0 || moved > current {
+        return Err(format!(
+            "{} must be between 0 and the current {} of the location",
+            field, current
+        ));
+    }
+    Ok(moved)
+}
+
+fn apply_split(
+    core_storage: Arc<CoreLocalStorage>,
+    data: &Value,
+    user_id: &str,
+) -> Result<Vec<(&'static str, Value)>, String> {
+    let db_error = |e: rusqlite::Error| format!("Database error: {:?}", e);
+
+    let location_id = data["locationId"]
+        .as_str()
+        .ok_or("locationId is required")?;
+    let ratio = match data.get("ratio").filter(|v| !v.is_null()) {
+        Some(ratio) => Some(
+            ratio
+                .as_f64()
+                .filter(|ratio| (0.0..=1.0).contains(ratio))
+                .ok_or("ratio must be between 0 and 1")?,
+        ),
+        None => None,
+    };
+
+    let mut location = core_storage
+        .get_existing_by_id("locations", location_id)
+        .map_err(db_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Location {} not found", location_id))?;
+
+    let new_location_id = data["newLocation"]["id"]
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    if !core_storage
+        .get_by_id("locations", &new_location_id)
+        .map_err(db_error)?
+        .is_empty()
+    {
+        return Err(format!("Location {} already exists", new_location_id));
+    }
+
+    let location_storage = LocationLocalStorage::new(core_storage.clone()).map_err(db_error)?;
+    let mut new_location = location_storage
+        .get_location_by_id(location_id)
+        .map_err(db_error)?;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut moved = json!({});
+    for (field, current_column, initial_column) in SPLIT_QUANTITIES {
+        let is_count = *field == "pieceCount";
+        let number = |value: f64| {
+            if is_count {
+                json!(value as i64)
+            } else {
+                json!(value)
+            }
+        };
+        let current = location[*current_column].as_f64().unwrap_or(0.0);
+        let initial = location[*initial_column].as_f64().unwrap_or(0.0);
+        let mut amount = moved_amount(data, field, current, ratio)?;
+        if is_count {
+            amount = amount.round();
+        }
+
+        location[*current_column] = number(current - amount);
+        location[*initial_column] = number((initial - amount).max(0.0));
+        new_location[*current_column] = number(amount);
+        new_location[*initial_column] = number(amount);
+        moved[*field] = number(amount);
+    }
+    if moved["quantity"].as_f64() == Some(0.0) && moved["oversizeQuantity"].as_f64() == Some(0.0) {
+        return Err("A split must move a quantity to the new location".to_string());
+    }
+
+    for field in OVERRIDABLE_FIELDS {
+        if let Some(value) = data["newLocation"].get(*field).filter(|v| !v.is_null()) {
+            new_location[*field] = value.clone();
+        }
+    }
+    new_location["id"] = json!(new_location_id);
+    new_location["lastEdit"] = json!(now);
+    new_location["deleted"] = json!(0);
+    location_storage
+        .save_location(&new_location)
+        .map_err(db_error)?;
+
+    location["lastEdit"] = json!(now);
+    location["arrivalAtServer"] = json!(now);
+    core_storage
+        .update("locations", &location)
+        .map_err(db_error)?;
+
+    AuditLogLocalStorage::new(core_storage.clone())
+        .and_then(|storage| {
+            storage.record(
+                user_id,
+                "location_split",
+                "location",
+                location_id,
+                &json!({
+                    "newLocationId": new_location_id,
+                    "contractId": location["contractId"],
+                    "moved": moved
+                }),
+            )
+        })
+        .map_err(db_error)?;
+
+    println!(
+        "Split location {} into {}: moved {}",
+        location_id, new_location_id, moved
+    );
+
+    Ok(vec![
+        (
+            "location_update",
+            location_storage
+                .get_location_by_id(location_id)
+                .map_err(db_error)?,
+        ),
+        (
+            "location_update",
+            location_storage
+                .get_location_by_id(&new_location_id)
+                .map_err(db_error)?,
+        ),
+    ])
+}
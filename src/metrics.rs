@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<String, u64>,
+    gauges: BTreeMap<String, i64>,
+}
+
+static REGISTRY: LazyLock<Mutex<Registry>> = LazyLock::new(|| Mutex::new(Registry::default()));
+
+pub fn increment(name: &str) {
+    add(name, 1);
+}
+
+pub fn add(name: &str, value: u64) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.counters.entry(name.to_string()).or_insert(0) += value;
+    }
+}
+
+pub fn set_gauge(name: &str, value: i64) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.gauges.insert(name.to_string(), value);
+    }
+}
+
+pub fn add_gauge(name: &str, delta: i64) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.gauges.entry(name.to_string()).or_insert(0) += delta;
+    }
+}
+
+pub fn render() -> String {
+    let registry = match REGISTRY.lock() {
+        Ok(registry) => registry,
+        Err(e) => {
+            eprintln!("Failed to lock metrics registry: {:?}", e);
+            return String::new();
+        }
+    };
+
+    let mut output = String::new();
+    render_family(&mut output, "counter", &registry.counters);
+    render_family(&mut output, "gauge", &registry.gauges);
+    output
+}
+
+fn render_family<T: std::fmt::Display>(
+    output: &mut String,
+    kind: &str,
+    values: &BTreeMap<String, T>,
+) {
+    let mut last_family = "";
+    for (name, value) in values {
+        let family = name.split('{').next().unwrap_or(name);
+        if family != last_family {
+            output.push_str(&format!("# TYPE {} {}\n", family, kind));
+            last_family = family;
+        }
+        output.push_str(&format!("{} {}\n", name, value));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::local_storage::note::note_local_storage::NoteLocalStorage;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, with_db_pools};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+pub const ENTITY_TYPES: &[&str] = &["location", "contract", "shipment"];
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    warp::path("notes")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(|authorization, query, db_pools: DbPoolMap| {
+            list_notes(authorization, query, &db_pools).unwrap_or_else(|reply| reply)
+        })
+}
+
+fn list_notes(
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, 0)?;
+
+    let (entity_type, entity_id) = match (query.get("entityType"), query.get("entityId")) {
+        (Some(entity_type), Some(entity_id)) if ENTITY_TYPES.contains(&entity_type.as_str()) => {
+            (entity_type, entity_id)
+        }
+        _ => {
+            return Err(rest::error_reply(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "entityType (location, contract or shipment) and entityId are required",
+            ));
+        }
+    };
+
+    let notes = rest::open_storage(&user.tenant)
+        .and_then(NoteLocalStorage::new)
+        .and_then(|storage| storage.get_notes_by_entity(entity_type, entity_id))
+        .map_err(|e| rest::internal_error("Failed to load notes", e))?;
+
+    Ok(rest::json_reply(&json!({ "notes": notes })))
+}
+
+pub fn validate_attachment(note: &Value) -> Result<(), String> {
+    let entity_type = note.get("entityType").filter(|v| !v.is_null());
+    let entity_id = note.get("entityId").filter(|v| !v.is_null());
+
+    match (entity_type, entity_id) {
+        (None, None) => Ok(()),
+        (Some(Value::String(entity_type)), Some(Value::String(_)))
+            if ENTITY_TYPES.contains(&entity_type.as_str()) =>
+        {
+            Ok(())
+        }
+        (Some(Value::String(entity_type)), Some(Value::String(_))) => {
+            Err(format!("Notes cannot be attached to {}", entity_type))
+        }
+        _ => Err("entityType and entityId must be set together".to_string()),
+    }
+}
@@ -0,0 +1,347 @@
+use crate::client_admin;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::permission::permission_local_storage::PermissionLocalStorage;
+use crate::local_storage::user::user_local_storage::UserLocalStorage;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{
+    Clients, DbPoolMap, ROLE_ADMIN, ROLE_PRIVILEGED, broadcast_to_tenant, with_clients,
+    with_db_pools,
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const MAX_ROLE_NAME_LENGTH: usize = 64;
+
+const BUILT_IN_ROLES: &[(&str, i64)] = &[
+    ("user", 0),
+    ("privileged", ROLE_PRIVILEGED),
+    ("admin", ROLE_ADMIN),
+];
+
+const DEFAULTS: &[(&str, &str, i64)] = &[
+    ("contract", "write", 0),
+    ("contract", "delete", 0),
+    ("contract", "split", ROLE_ADMIN),
+    ("contract", "allowlist", ROLE_ADMIN),
+    ("location", "write", 0),
+    ("location", "delete", 0),
+    ("location", "assignCrew", ROLE_PRIVILEGED),
+    ("location", "split", 0),
+    ("shipment", "write", 0),
+    ("shipment", "delete", 0),
+    ("note", "write", 0),
+    ("note", "delete", 0),
+    ("photo", "write", 0),
+    ("photo", "delete", 0),
+    ("sawmill", "write", 0),
+    ("sawmill", "delete", 0),
+    ("crew", "write", ROLE_PRIVILEGED),
+    ("crew", "delete", ROLE_PRIVILEGED),
+    ("tour", "write", ROLE_PRIVILEGED),
+    ("tour", "delete", ROLE_PRIVILEGED),
+    ("tour", "drive", 0),
+    ("assortment", "write", ROLE_PRIVILEGED),
+    ("assortment", "delete", ROLE_PRIVILEGED),
+    ("sawmillPrice", "write", ROLE_PRIVILEGED),
+    ("sawmillPrice", "delete", ROLE_PRIVILEGED),
+    ("review", "decide", ROLE_PRIVILEGED),
+    ("quota", "reserve", ROLE_PRIVILEGED),
+    ("settings", "write", ROLE_ADMIN),
+    ("user", "write", 0),
+    ("user", "delete", 0),
+    ("user", "assignRole", ROLE_ADMIN),
+];
+
+pub fn required(msg_type: &str, data: &Value) -> Option<(&'static str, &'static str)> {
+    let entity = match msg_type {
+        "contract_update" => "contract",
+        "location_update" => "location",
+        "shipment_update" => "shipment",
+        "note_update" => "note",
+        "photo_update" => "photo",
+        "sawmill_update" => "sawmill",
+        "crew_update" => "crew",
+        "tour_update" => "tour",
+        "assortment_update" => "assortment",
+        "sawmill_price_update" => "sawmillPrice",
+        "user_update" => "user",
+        "settings_update" => return Some(("settings", "write")),
+        "review_approve" | "review_reject" => return Some(("review", "decide")),
+        "quota_reserve" => return Some(("quota", "reserve")),
+        "tour_start" | "tour_finish" => return Some(("tour", "drive")),
+        "photo_upload_start" | "photo_upload_complete" => return Some(("photo", "write")),
+        "contract_split" => return Some(("contract", "split")),
+        "location_split" => return Some(("location", "split")),
+        "contract_allowlist_update" => return Some(("contract", "allowlist")),
+        _ => return None,
+    };
+    let is_deleted = data.get("deleted").and_then(|v| v.as_i64()).unwrap_or(0) == 1;
+
+    Some((entity, if is_deleted { "delete" } else { "write" }))
+}
+
+fn role_name(role: i64) -> &'static str {
+    BUILT_IN_ROLES
+        .iter()
+        .rev()
+        .find(|(_, level)| role >= *level)
+        .map(|(name, _)| *name)
+        .unwrap_or("user")
+}
+
+fn default_min_role(entity: &str, action: &str) -> Option<i64> {
+    DEFAULTS
+        .iter()
+        .find(|(e, a, _)| *e == entity && *a == action)
+        .map(|(_, _, min_role)| *min_role)
+}
+
+pub fn is_allowed(
+    core_storage: Arc<CoreLocalStorage>,
+    user_id: &str,
+    role: i64,
+    entity: &str,
+    action: &str,
+) -> rusqlite::Result<bool> {
+    let storage = PermissionLocalStorage::new(core_storage)?;
+    let custom_role = storage.get_custom_role(user_id)?;
+    let built_in = role_name(role);
+
+    let mut roles = vec![built_in];
+    roles.extend(custom_role.as_deref());
+    let overrides = storage.get_overrides(&roles, entity, action)?;
+    let decision = custom_role
+        .as_deref()
+        .and_then(|custom| overrides.iter().find(|(role, _)| role == custom))
+        .or_else(|| overrides.iter().find(|(role, _)| role == built_in))
+        .map(|(_, allowed)| *allowed);
+
+    Ok(decision.unwrap_or_else(|| {
+        default_min_role(entity, action).is_some_and(|min_role| role >= min_role)
+    }))
+}
+
+pub fn check(
+    core_storage: Arc<CoreLocalStorage>,
+    user_id: &str,
+    role: i64,
+    entity: &str,
+    action: &str,
+) -> Result<(), String> {
+    match is_allowed(core_storage, user_id, role, entity, action) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            metrics::increment(&format!(
+                "permission_denied_total{{entity=\"{}\",action=\"{}\"}}",
+                entity, action
+            ));
+            Err(format!("Your role may not {} {}", action, entity))
+        }
+        Err(e) => {
+            println!(
+                "Failed to check permission {}:{} of user {}: {:?}",
+                entity, action, user_id, e
+            );
+            Err("Permissions are unavailable".to_string())
+        }
+    }
+}
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list = warp::path!("admin" / "permissions")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_permissions(authorization, &db_pools))
+        });
+
+    let set = warp::path!("admin" / "permissions")
+        .and(warp::put())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, body, db_pools: DbPoolMap| {
+            rest::into_reply(set_permission(authorization, body, &db_pools))
+        });
+
+    let assign = warp::path!("admin" / "users" / String / "role")
+        .and(warp::put())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |user_id, authorization, body, clients: Clients, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    assign_custom_role(user_id, authorization, body, &clients, &db_pools).await,
+                ))
+            },
+        );
+
+    list.or(set).unify().or(assign).unify()
+}
+
+fn invalid(message: &str) -> ErrorReply {
+    rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message)
+}
+
+fn parse_role_name(value: &Value) -> Result<&str, ErrorReply> {
+    value
+        .as_str()
+        .map(str::trim)
+        .filter(|name| {
+            !name.is_empty()
+                && name.len() <= MAX_ROLE_NAME_LENGTH
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .ok_or_else(|| {
+            invalid(&format!(
+                "role must be 1 to {} letters, digits, dashes or underscores",
+                MAX_ROLE_NAME_LENGTH
+            ))
+        })
+}
+
+fn list_permissions(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let storage = rest::open_storage(&user.tenant)
+        .and_then(PermissionLocalStorage::new)
+        .map_err(|e| rest::internal_error("Failed to open permissions", e))?;
+    let overrides = storage
+        .get_permissions()
+        .map_err(|e| rest::internal_error("Failed to load permissions", e))?;
+    let custom_roles: Vec<String> = storage
+        .get_custom_roles()
+        .map_err(|e| rest::internal_error("Failed to load roles", e))?
+        .into_iter()
+        .filter(|role| !BUILT_IN_ROLES.iter().any(|(name, _)| name == role))
+        .collect();
+
+    let defaults: Vec<Value> = DEFAULTS
+        .iter()
+        .map(|(entity, action, min_role)| {
+            json!({
+                "entity": entity,
+                "action": action,
+                "minRole": role_name(*min_role)
+            })
+        })
+        .collect();
+
+    Ok(rest::json_reply(&json!({
+        "roles": BUILT_IN_ROLES.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+        "customRoles": custom_roles,
+        "defaults": defaults,
+        "overrides": overrides
+    })))
+}
+
+fn set_permission(
+    authorization: Option<String>,
+    body: Value,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let role = parse_role_name(&body["role"])?;
+    let entity = body["entity"].as_str().unwrap_or_default();
+    let action = body["action"].as_str().unwrap_or_default();
+    if default_min_role(entity, action).is_none() {
+        return Err(invalid(&format!(
+            "Unknown permission {}:{}",
+            entity, action
+        )));
+    }
+    let allowed = match &body["allowed"] {
+        Value::Null => None,
+        Value::Bool(allowed) => Some(*allowed),
+        _ => return Err(invalid("allowed must be true, false or null")),
+    };
+
+    rest::open_storage(&user.tenant)
+        .and_then(PermissionLocalStorage::new)
+        .and_then(|storage| storage.set_permission(role, entity, action, allowed, &user.user_id))
+        .map_err(|e| rest::internal_error("Failed to save permission", e))?;
+
+    let permission = json!({
+        "role": role,
+        "entity": entity,
+        "action": action,
+        "allowed": allowed
+    });
+    println!(
+        "Admin {} of tenant {} set permission {}:{} of role {} to {:?}",
+        user.user_id, user.tenant, entity, action, role, allowed
+    );
+    client_admin::audit(
+        &user,
+        "permission_update",
+        "permission",
+        &format!("{}:{}:{}", role, entity, action),
+        &permission,
+    );
+
+    Ok(rest::json_reply(&permission))
+}
+
+async fn assign_custom_role(
+    user_id: String,
+    authorization: Option<String>,
+    body: Value,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let admin = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let custom_role = match &body["customRole"] {
+        Value::Null => None,
+        value => Some(parse_role_name(value)?),
+    };
+    if custom_role.is_some_and(|role| BUILT_IN_ROLES.iter().any(|(name, _)| *name == role)) {
+        return Err(invalid("customRole must not be a built-in role"));
+    }
+
+    let core_storage = rest::open_storage(&admin.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let updated = PermissionLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.set_custom_role(&user_id, custom_role))
+        .map_err(|e| rest::internal_error("Failed to assign role", e))?;
+    if !updated {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "User not found",
+        ));
+    }
+
+    let user = UserLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_user_by_id(&user_id))
+        .map_err(|e| rest::internal_error("Failed to load user", e))?
+        .ok_or_else(|| rest::error_reply(StatusCode::NOT_FOUND, "NOT_FOUND", "User not found"))?;
+    println!(
+        "Admin {} of tenant {} assigned custom role {:?} to user {}",
+        admin.user_id, admin.tenant, custom_role, user_id
+    );
+    client_admin::audit(
+        &admin,
+        "custom_role_update",
+        "user",
+        &user_id,
+        &json!({ "customRole": custom_role }),
+    );
+    broadcast_to_tenant(&admin.tenant, "user_update", &user, 0, clients).await;
+
+    Ok(rest::json_reply(
+        &json!({ "userId": user_id, "customRole": custom_role }),
+    ))
+}
@@ -0,0 +1,244 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::photo::photo_local_storage::photo_bytes;
+use base64::prelude::*;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub type ChunkError = (&'static str, String);
+
+struct Upload {
+    tenant: String,
+    client_id: String,
+    photo: Value,
+    size: usize,
+    next_index: usize,
+    bytes: Vec<u8>,
+    started: Instant,
+}
+
+static UPLOADS: LazyLock<Mutex<HashMap<String, Upload>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn validation_error(message: &str) -> ChunkError {
+    ("VALIDATION_FAILED", message.to_string())
+}
+
+fn state_error() -> ChunkError {
+    (
+        "PHOTO_UPLOAD_FAILED",
+        "Upload state unavailable".to_string(),
+    )
+}
+
+pub fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Starts a chunked photo upload. The message carries the photo's fields
+/// without photoFile plus its size in bytes.
+pub fn start_upload(
+    tenant: &str,
+    client_id: &str,
+    data: &Value,
+    core_storage: &CoreLocalStorage,
+) -> Result<Value, ChunkError> {
+    let settings = &config::get().photo_chunks;
+
+    let id = data["id"]
+        .as_str()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| validation_error("id is required"))?;
+    let location_id = data["locationId"]
+        .as_str()
+        .ok_or_else(|| validation_error("locationId is required"))?;
+    if data["lastEdit"].as_i64().is_none() {
+        return Err(validation_error("lastEdit is required"));
+    }
+    let size = data["size"]
+        .as_u64()
+        .map(|size| size as usize)
+        .filter(|size| (1..=settings.max_size_bytes).contains(size))
+        .ok_or_else(|| {
+            (
+                "VALIDATION_FAILED",
+                format!(
+                    "size must be between 1 and {} bytes",
+                    settings.max_size_bytes
+                ),
+            )
+        })?;
+
+    let location = core_storage
+        .get_existing_by_id("locations", location_id)
+        .map_err(|e| {
+            println!("Failed to load location {}: {:?}", location_id, e);
+            ("PHOTO_UPLOAD_FAILED", "Database error".to_string())
+        })?;
+    if location.is_empty() {
+        return Err(("NOT_FOUND", format!("Location {} not found", location_id)));
+    }
+
+    let mut photo = data.clone();
+    if let Value::Object(map) = &mut photo {
+        map.remove("size");
+        map.remove("photoFile");
+    }
+
+    let upload_id = Uuid::new_v4().to_string();
+    let mut uploads = UPLOADS.lock().map_err(|_| state_error())?;
+    let timeout = Duration::from_secs(settings.upload_timeout_secs);
+    uploads.retain(|_, upload| {
+        upload.started.elapsed() < timeout
+            && !(upload.tenant == tenant && upload.photo["id"].as_str() == Some(id))
+    });
+    uploads.insert(
+        upload_id.clone(),
+        Upload {
+            tenant: tenant.to_string(),
+            client_id: client_id.to_string(),
+            photo,
+            size,
+            next_index: 0,
+            bytes: Vec::with_capacity(size),
+            started: Instant::now(),
+        },
+    );
+
+    Ok(json!({
+        "uploadId": upload_id,
+        "id": id,
+        "chunkSize": settings.chunk_size_bytes,
+        "chunkCount": size.div_ceil(settings.chunk_size_bytes)
+    }))
+}
+
+pub fn append_chunk(client_id: &str, data: &Value) -> Result<Value, ChunkError> {
+    let upload_id = data["uploadId"].as_str().unwrap_or_default();
+    let index = data["index"]
+        .as_u64()
+        .ok_or_else(|| validation_error("index is required"))? as usize;
+    let chunk = data["data"]
+        .as_str()
+        .and_then(|chunk| BASE64_STANDARD.decode(chunk).ok())
+        .ok_or_else(|| validation_error("data must be base64 encoded"))?;
+
+    let mut uploads = UPLOADS.lock().map_err(|_| state_error())?;
+    let upload = uploads
+        .get_mut(upload_id)
+        .filter(|upload| upload.client_id == client_id)
+        .ok_or_else(|| ("NOT_FOUND", format!("Upload {} not found", upload_id)))?;
+
+    if index != upload.next_index {
+        return Err((
+            "VALIDATION_FAILED",
+            format!("Expected chunk {}, got {}", upload.next_index, index),
+        ));
+    }
+    if chunk.len() > config::get().photo_chunks.chunk_size_bytes
+        || upload.bytes.len() + chunk.len() > upload.size
+    {
+        uploads.remove(upload_id);
+        return Err(validation_error("Chunk exceeds the announced photo size"));
+    }
+
+    upload.bytes.extend_from_slice(&chunk);
+    upload.next_index += 1;
+
+    Ok(json!({
+        "uploadId": upload_id,
+        "index": index,
+        "received": upload.bytes.len()
+    }))
+}
+
+/// Finishes an upload once the checksum of the reassembled bytes matches.
+/// Returns the photo in the shape of a photo_update.
+pub fn complete_upload(client_id: &str, data: &Value) -> Result<Value, ChunkError> {
+    let upload_id = data["uploadId"].as_str().unwrap_or_default();
+    let expected_hash = data["hash"]
+        .as_str()
+        .map(str::to_lowercase)
+        .ok_or_else(|| validation_error("hash is required"))?;
+    let upload = {
+        let mut uploads = UPLOADS.lock().map_err(|_| state_error())?;
+        match uploads.get(upload_id) {
+            Some(upload) if upload.client_id == client_id => uploads.remove(upload_id),
+            _ => None,
+        }
+        .ok_or_else(|| ("NOT_FOUND", format!("Upload {} not found", upload_id)))?
+    };
+
+    if upload.bytes.len() != upload.size {
+        return Err((
+            "VALIDATION_FAILED",
+            format!("Received {} of {} bytes", upload.bytes.len(), upload.size),
+        ));
+    }
+    if hash(&upload.bytes) != expected_hash {
+        return Err(validation_error("Photo hash does not match"));
+    }
+
+    let mut photo = upload.photo;
+    photo["photoFile"] = json!(upload.bytes);
+    Ok(photo)
+}
+
+/// Splits a synced photo into the messages of a chunked download: a start
+/// message with the photo's fields, one message per chunk and a final one
+/// with the checksum. Photos that fit into one chunk are left alone.
+pub fn download_messages(photo: &Value, tenant: &str) -> Option<Vec<Value>> {
+    let chunk_size = config::get().photo_chunks.chunk_size_bytes;
+    let bytes = match photo_bytes(photo["photoFile"].as_array()?) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            println!("Not chunking photo {}: {}", photo["id"], e);
+            return None;
+        }
+    };
+    if bytes.len() <= chunk_size {
+        return None;
+    }
+
+    let id = photo["id"].clone();
+    let timestamp = chrono::Utc::now().timestamp_millis();
+    let mut fields = photo.clone();
+    if let Value::Object(map) = &mut fields {
+        map.remove("photoFile");
+    }
+    fields["size"] = json!(bytes.len());
+    fields["chunkCount"] = json!(bytes.len().div_ceil(chunk_size));
+
+    let mut messages = vec![json!({
+        "type": "photo_download_start",
+        "data": fields,
+        "dbName": tenant,
+        "timestamp": timestamp
+    })];
+    messages.extend(bytes.chunks(chunk_size).enumerate().map(|(index, chunk)| {
+        json!({
+            "type": "photo_download_chunk",
+            "data": {
+                "id": id,
+                "index": index,
+                "data": BASE64_STANDARD.encode(chunk)
+            },
+            "dbName": tenant,
+            "timestamp": timestamp
+        })
+    }));
+    messages.push(json!({
+        "type": "photo_download_complete",
+        "data": { "id": id, "hash": hash(&bytes) },
+        "dbName": tenant,
+        "timestamp": timestamp
+    }));
+    Some(messages)
+}
@@ -0,0 +1,60 @@
+use crate::config;
+use crate::metrics;
+use serde_json::Value;
+use std::sync::LazyLock;
+use std::time::Instant;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+static LARGE_PAYLOAD_PERMITS: LazyLock<Semaphore> =
+    LazyLock::new(|| Semaphore::new(config::get().photo_ingest.max_concurrent));
+
+pub struct PhotoIngestGuard {
+    permit: Option<SemaphorePermit<'static>>,
+    started: Instant,
+}
+
+/// Number of photo bytes in a photo_update.
+pub fn payload_len(data: &Value) -> usize {
+    data["photoFile"].as_array().map_or(0, Vec::len)
+}
+
+pub fn begin(payload_len: usize) -> Option<PhotoIngestGuard> {
+    let permit = if payload_len >= config::get().photo_ingest.large_payload_bytes {
+        match LARGE_PAYLOAD_PERMITS.try_acquire() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                metrics::increment("photo_ingest_rejected_total");
+                return None;
+            }
+        }
+    } else {
+        None
+    };
+
+    metrics::add_gauge("photo_ingest_in_flight", 1);
+    metrics::add("photo_ingest_payload_bytes_total", payload_len as u64);
+    metrics::set_gauge(
+        "photo_ingest_permits_available",
+        LARGE_PAYLOAD_PERMITS.available_permits() as i64,
+    );
+
+    Some(PhotoIngestGuard {
+        permit,
+        started: Instant::now(),
+    })
+}
+
+impl Drop for PhotoIngestGuard {
+    fn drop(&mut self) {
+        metrics::add_gauge("photo_ingest_in_flight", -1);
+        metrics::increment("photo_ingest_processed_total");
+        metrics::add(
+            "photo_ingest_duration_ms_total",
+            self.started.elapsed().as_millis() as u64,
+        );
+
+        let available = LARGE_PAYLOAD_PERMITS.available_permits() as i64;
+        let released = if self.permit.is_some() { 1 } else { 0 };
+        metrics::set_gauge("photo_ingest_permits_available", available + released);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::config;
+use crate::instance_lease;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::photo::photo_local_storage::PhotoLocalStorage;
+use crate::local_storage::schema;
+use crate::local_storage::settings::settings_local_storage::{
+    PHOTO_RECOMPRESSION_KEY, SettingsLocalStorage,
+};
+use crate::metrics;
+use crate::photo_transcode;
+use crate::rest::{self, ErrorReply};
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use chrono::Months;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use warp::{Filter, Rejection, Reply};
+
+const TARGET_FORMATS: &[&str] = &["jpeg", "webp"];
+const DEFAULT_MANIFEST_LIMIT: usize = 100;
+
+/// Per-tenant policy, stored in the photoRecompression setting:
+/// `{"enabled": true, "minAgeMonths": 12, "quality": 60, "format": "webp"}`.
+/// Without a format photos keep theirs; PNG and HEIC become JPEG.
+struct RecompressionPolicy {
+    min_age_months: u32,
+    quality: u64,
+    format: Option<String>,
+}
+
+fn parse_policy(value: &str) -> Result<Option<RecompressionPolicy>, String> {
+    let policy = serde_json::from_str::<Value>(value)
+        .ok()
+        .filter(Value::is_object)
+        .ok_or_else(|| "Photo recompression must be an object".to_string())?;
+
+    if policy["enabled"].as_bool() == Some(false) {
+        return Ok(None);
+    }
+    let min_age_months = policy["minAgeMonths"]
+        .as_u64()
+        .filter(|months| (1..=1200).contains(months))
+        .ok_or_else(|| "minAgeMonths must be between 1 and 1200".to_string())?
+        as u32;
+    let quality = policy["quality"]
+        .as_u64()
+        .filter(|quality| (1..=100).contains(quality))
+        .ok_or_else(|| "quality must be between 1 and 100".to_string())?;
+    let format = match policy.get("format") {
+        None | Some(Value::Null) => None,
+        Some(format) => Some(
+            format
+                .as_str()
+                .filter(|format| TARGET_FORMATS.contains(format))
+                .ok_or_else(|| format!("format must be one of {}", TARGET_FORMATS.join(", ")))?
+                .to_string(),
+        ),
+    };
+
+    Ok(Some(RecompressionPolicy {
+        min_age_months,
+        quality,
+        format,
+    }))
+}
+
+pub fn validate_policy(value: &str) -> Result<(), String> {
+    parse_policy(value).map(|_| ())
+}
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    let settings = &config::get().photo_recompression;
+    if !settings.enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.interval_hours * 60 * 60);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            if !instance_lease::is_leader() {
+                continue;
+            }
+            if let Err(e) = tokio::task::spawn_blocking(recompress_all_tenants).await {
+                eprintln!("Photo recompression run failed: {:?}", e);
+            }
+        }
+    }))
+}
+
+fn recompress_all_tenants() {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            continue;
+        }
+
+        let result = path
+            .to_str()
+            .ok_or(rusqlite::Error::InvalidPath(path.clone()))
+            .and_then(CoreLocalStorage::new)
+            .and_then(|core_storage| {
+                schema::migrate(&*core_storage.get_connection()?)?;
+                recompress_tenant(Arc::new(core_storage))
+            });
+
+        match result {
+            Ok((0, _)) => {}
+            Ok((recompressed, saved_bytes)) => println!(
+                "Recompressed {} photo(s) of tenant {}, saved {} bytes",
+                recompressed, tenant, saved_bytes
+            ),
+            Err(e) => eprintln!("Failed to recompress photos of tenant {}: {:?}", tenant, e),
+        }
+    }
+}
+
+/// Recompresses one batch of aged photos. Returns the number of replaced
+/// photos and the bytes saved.
+fn recompress_tenant(core_storage: Arc<CoreLocalStorage>) -> rusqlite::Result<(usize, u64)> {
+    let Some(policy) = SettingsLocalStorage::new(core_storage.clone())?
+        .get_setting(PHOTO_RECOMPRESSION_KEY)?
+        .and_then(|value| {
+            parse_policy(&value).unwrap_or_else(|e| {
+                println!(
+                    "Ignoring invalid {} settings: {}",
+                    PHOTO_RECOMPRESSION_KEY, e
+                );
+                None
+            })
+        })
+    else {
+        return Ok((0, 0));
+    };
+
+    let Some(cutoff) = chrono::Utc::now().checked_sub_months(Months::new(policy.min_age_months))
+    else {
+        return Ok((0, 0));
+    };
+    let photo_storage = PhotoLocalStorage::new(core_storage)?;
+    let candidates = photo_storage.get_recompression_candidates(
+        cutoff.timestamp_millis(),
+        config::get().photo_recompression.batch_size,
+    )?;
+
+    let (mut recompressed, mut saved_bytes) = (0, 0);
+    for id in candidates {
+        let Some((photo_file, stored_format)) = photo_storage.get_photo_file(&id)? else {
+            continue;
+        };
+        let source = photo_transcode::detect_format(&photo_file);
+        let format = match (&policy.format, source) {
+            (Some(format), _) => format.as_str(),
+            (None, Some(source)) if TARGET_FORMATS.contains(&source) => source,
+            (None, _) => "jpeg",
+        };
+
+        let converted = match source {
+            Some(_) => photo_transcode::convert(&photo_file, format, policy.quality, None)
+                .map_err(|e| println!("Failed to recompress photo {}: {}", id, e))
+                .ok()
+                .filter(|converted| converted.len() < photo_file.len()),
+            None => None,
+        };
+        let saved = converted
+            .as_ref()
+            .map_or(0, |converted| (photo_file.len() - converted.len()) as u64);
+
+        let replaced = photo_storage.save_recompressed(
+            &id,
+            &photo_file,
+            stored_format.as_deref().or(source),
+            converted.as_deref().map(|converted| (converted, format)),
+            policy.quality,
+        )?;
+        if replaced && converted.is_some() {
+            recompressed += 1;
+            saved_bytes += saved;
+        }
+    }
+
+    metrics::add("photo_recompressed_total", recompressed as u64);
+    metrics::add("photo_recompression_saved_bytes_total", saved_bytes);
+    Ok((recompressed, saved_bytes))
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("admin" / "photo-recompression")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(|authorization, query, db_pools: DbPoolMap| {
+            rest::into_reply(manifest(authorization, query, &db_pools))
+        })
+}
+
+/// Space savings and manifest of the tenant. `previousHash` finds the entry
+/// of a photo restored from a backup taken before it was recompressed.
+fn manifest(
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(DEFAULT_MANIFEST_LIMIT)
+        .clamp(1, 1000);
+
+    let manifest = rest::open_storage(&user.tenant)
+        .and_then(PhotoLocalStorage::new)
+        .and_then(|storage| {
+            storage.get_recompression_manifest(query.get("previousHash").map(String::as_str), limit)
+        })
+        .map_err(|e| rest::internal_error("Failed to load recompression manifest", e))?;
+
+    Ok(rest::json_reply(&manifest))
+}
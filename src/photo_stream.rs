@@ -0,0 +1,77 @@
+use crate::metrics;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use warp::ws::Message;
+
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct Progress {
+    started_from: i64,
+    confirmed: i64,
+}
+
+static PROGRESS: LazyLock<Mutex<HashMap<(String, String), Progress>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn key(tenant: &str, user_id: &str) -> (String, String) {
+    (tenant.to_string(), user_id.to_string())
+}
+
+pub fn resume_from(tenant: &str, user_id: &str, last_sync: i64) -> i64 {
+    let Ok(progress) = PROGRESS.lock() else {
+        return last_sync;
+    };
+    match progress.get(&key(tenant, user_id)) {
+        Some(entry) if entry.started_from <= last_sync && last_sync < entry.confirmed => {
+            println!(
+                "Resuming photo sync for {} in {} at {} instead of {}",
+                user_id, tenant, entry.confirmed, last_sync
+            );
+            metrics::increment("photo_sync_resumed_total");
+            entry.confirmed
+        }
+        _ => last_sync,
+    }
+}
+
+pub fn record_partial(tenant: &str, user_id: &str, started_from: i64, confirmed: i64) {
+    metrics::increment("photo_sync_aborted_total");
+    if confirmed <= started_from {
+        return;
+    }
+    if let Ok(mut progress) = PROGRESS.lock() {
+        progress.insert(
+            key(tenant, user_id),
+            Progress {
+                started_from,
+                confirmed,
+            },
+        );
+    }
+}
+
+pub fn clear(tenant: &str, user_id: &str) {
+    if let Ok(mut progress) = PROGRESS.lock() {
+        progress.remove(&key(tenant, user_id));
+    }
+}
+
+pub async fn await_delivery(sender: &UnboundedSender<Message>, queue_depth: &AtomicUsize) -> bool {
+    let deadline = tokio::time::Instant::now() + STALL_TIMEOUT;
+    loop {
+        if sender.is_closed() {
+            return false;
+        }
+        if queue_depth.load(Ordering::Relaxed) == 0 {
+            return true;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,448 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::photo::photo_local_storage::photo_bytes;
+use crate::local_storage::settings::settings_local_storage::{
+    PHOTO_LIMITS_KEY, PHOTO_TRANSCODING_KEY, SettingsLocalStorage,
+};
+use crate::metrics;
+use serde_json::{Value, json};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TARGET_FORMATS: &[&str] = &["jpeg", "webp"];
+const DEFAULT_QUALITY: u64 = 85;
+const MIN_DIMENSION: u64 = 64;
+const MIN_BYTES: u64 = 10_000;
+
+struct TranscodeSettings {
+    format: String,
+    quality: u64,
+    keep_original: bool,
+}
+
+struct PhotoLimits {
+    max_dimension: Option<u32>,
+    max_bytes: Option<u64>,
+}
+
+pub struct Original {
+    pub format: &'static str,
+    pub photo_file: Vec<u8>,
+}
+
+pub struct Transcoded {
+    pub photo_file: Vec<u8>,
+    pub original: Option<Original>,
+}
+
+fn parse_settings(value: &str) -> Result<Option<TranscodeSettings>, String> {
+    let settings = serde_json::from_str::<Value>(value)
+        .ok()
+        .filter(Value::is_object)
+        .ok_or_else(|| "Photo transcoding must be an object".to_string())?;
+
+    if settings["enabled"].as_bool() == Some(false) {
+        return Ok(None);
+    }
+    let format = settings["format"]
+        .as_str()
+        .filter(|format| TARGET_FORMATS.contains(format))
+        .ok_or_else(|| format!("format must be one of {}", TARGET_FORMATS.join(", ")))?;
+    let quality = match settings.get("quality") {
+        None | Some(Value::Null) => DEFAULT_QUALITY,
+        Some(quality) => quality
+            .as_u64()
+            .filter(|quality| (1..=100).contains(quality))
+            .ok_or_else(|| "quality must be between 1 and 100".to_string())?,
+    };
+    let keep_original = match settings.get("keepOriginal") {
+        None | Some(Value::Null) => false,
+        Some(keep_original) => keep_original
+            .as_bool()
+            .ok_or_else(|| "keepOriginal must be a boolean".to_string())?,
+    };
+
+    Ok(Some(TranscodeSettings {
+        format: format.to_string(),
+        quality,
+        keep_original,
+    }))
+}
+
+pub fn validate_settings(value: &str) -> Result<(), String> {
+    parse_settings(value).map(|_| ())
+}
+
+fn parse_limits(value: &str) -> Result<Option<PhotoLimits>, String> {
+    let limits = serde_json::from_str::<Value>(value)
+        .ok()
+        .filter(Value::is_object)
+        .ok_or_else(|| "Photo limits must be an object".to_string())?;
+
+    if limits["enabled"].as_bool() == Some(false) {
+        return Ok(None);
+    }
+    let max_dimension = match limits.get("maxDimension") {
+        None | Some(Value::Null) => None,
+        Some(max_dimension) => Some(
+            max_dimension
+                .as_u64()
+                .filter(|max_dimension| (MIN_DIMENSION..=u32::MAX as u64).contains(max_dimension))
+                .ok_or_else(|| format!("maxDimension must be at least {} pixels", MIN_DIMENSION))?
+                as u32,
+        ),
+    };
+    let max_bytes = match limits.get("maxBytes") {
+        None | Some(Value::Null) => None,
+        Some(max_bytes) => Some(
+            max_bytes
+                .as_u64()
+                .filter(|max_bytes| *max_bytes >= MIN_BYTES)
+                .ok_or_else(|| format!("maxBytes must be at least {}", MIN_BYTES))?,
+        ),
+    };
+    if max_dimension.is_none() && max_bytes.is_none() {
+        return Err("maxDimension or maxBytes is required".to_string());
+    }
+
+    Ok(Some(PhotoLimits {
+        max_dimension,
+        max_bytes,
+    }))
+}
+
+pub fn validate_limits(value: &str) -> Result<(), String> {
+    parse_limits(value).map(|_| ())
+}
+
+fn load_setting<T>(
+    core_storage: Arc<CoreLocalStorage>,
+    key: &str,
+    parse: fn(&str) -> Result<Option<T>, String>,
+) -> Option<T> {
+    let stored =
+        SettingsLocalStorage::new(core_storage).and_then(|storage| storage.get_setting(key));
+    match stored {
+        Ok(Some(value)) => parse(&value).unwrap_or_else(|e| {
+            println!("Ignoring invalid {} settings: {}", key, e);
+            None
+        }),
+        Ok(None) => None,
+        Err(e) => {
+            println!("Failed to load {} settings: {:?}", key, e);
+            None
+        }
+    }
+}
+
+pub fn client_policy(core_storage: Arc<CoreLocalStorage>) -> Value {
+    let settings = load_setting(core_storage.clone(), PHOTO_TRANSCODING_KEY, parse_settings);
+    let limits = load_setting(core_storage, PHOTO_LIMITS_KEY, parse_limits);
+
+    json!({
+        "transcoding": settings.map(|settings| json!({
+            "format": settings.format,
+            "quality": settings.quality
+        })),
+        "maxDimension": limits.as_ref().and_then(|limits| limits.max_dimension),
+        "maxBytes": limits.as_ref().and_then(|limits| limits.max_bytes)
+    })
+}
+
+impl PhotoLimits {
+    fn target_dimension(&self, size: u64, dimensions: Option<(u32, u32)>) -> Option<u32> {
+        let longest = dimensions.map(|(width, height)| width.max(height));
+        let mut target = match (self.max_dimension, longest) {
+            (Some(max_dimension), Some(longest)) if longest > max_dimension => Some(max_dimension),
+            _ => None,
+        };
+
+        if let Some(max_bytes) = self.max_bytes.filter(|max_bytes| size > *max_bytes) {
+            let scaled = match longest {
+                Some(longest) => {
+                    Some((longest as f64 * (max_bytes as f64 / size as f64).sqrt()) as u32)
+                }
+                None => self.max_dimension,
+            };
+            target = match (target, scaled) {
+                (Some(target), Some(scaled)) => Some(target.min(scaled)),
+                (target, scaled) => target.or(scaled),
+            };
+        }
+
+        target.map(|target| target.max(MIN_DIMENSION as u32))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "maxDimension": self.max_dimension,
+            "maxBytes": self.max_bytes
+        })
+    }
+}
+
+pub fn detect_format(photo_file: &[u8]) -> Option<&'static str> {
+    const HEIF_BRANDS: &[&[u8]] = &[
+        b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"hevm", b"hevs", b"mif1", b"msf1",
+    ];
+
+    if photo_file.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpeg")
+    } else if photo_file.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if photo_file.starts_with(b"RIFF") && photo_file.get(8..12) == Some(b"WEBP") {
+        Some("webp")
+    } else if photo_file.get(4..8) == Some(b"ftyp")
+        && photo_file
+            .get(8..12)
+            .is_some_and(|brand| HEIF_BRANDS.contains(&brand))
+    {
+        Some("heic")
+    } else {
+        None
+    }
+}
+
+pub fn dimensions(photo_file: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| {
+        photo_file
+            .get(at..at + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+    };
+    let le16 = |at: usize| {
+        photo_file
+            .get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as u32)
+    };
+    let le24 = |at: usize| {
+        photo_file
+            .get(at..at + 3)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], 0]))
+    };
+
+    match detect_format(photo_file)? {
+        "png" => {
+            let ihdr = photo_file.get(16..24)?;
+            Some((
+                u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]),
+                u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]),
+            ))
+        }
+        "jpeg" => {
+            let mut offset = 2;
+            loop {
+                while *photo_file.get(offset)? == 0xFF && *photo_file.get(offset + 1)? == 0xFF {
+                    offset += 1;
+                }
+                if *photo_file.get(offset)? != 0xFF {
+                    return None;
+                }
+                let marker = *photo_file.get(offset + 1)?;
+                if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+                    return Some((be16(offset + 7)?, be16(offset + 5)?));
+                }
+                if marker == 0xD9 || marker == 0xDA {
+                    return None;
+                }
+                offset += 2 + be16(offset + 2)? as usize;
+            }
+        }
+        "webp" => match photo_file.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3FFF, le16(28)? & 0x3FFF)),
+            b"VP8L" => {
+                let bits = photo_file.get(21..25)?;
+                let bits = u32::from_le_bytes([bits[0], bits[1], bits[2], bits[3]]);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Converts a photo per the tenant's transcoding settings and size limits.
+/// The converted file is returned as bytes for the caller to store, the
+/// photo's metadata fields are updated in place. A photoFile array holding
+/// anything but bytes is rejected.
+pub fn transcode(
+    photo: &mut Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> io::Result<Option<Transcoded>> {
+    let photo_file = match &photo["photoFile"] {
+        Value::Array(arr) => photo_bytes(arr)?,
+        _ => return Ok(None),
+    };
+    let Some(source) = detect_format(&photo_file) else {
+        return Ok(None);
+    };
+    photo["photoFormat"] = json!(source);
+
+    let settings = load_setting(core_storage.clone(), PHOTO_TRANSCODING_KEY, parse_settings);
+    let limits = load_setting(core_storage, PHOTO_LIMITS_KEY, parse_limits);
+    let original_dimensions = dimensions(&photo_file);
+    let resize = limits
+        .as_ref()
+        .and_then(|limits| limits.target_dimension(photo_file.len() as u64, original_dimensions));
+
+    let (format, quality) = match &settings {
+        Some(settings) => (settings.format.as_str(), settings.quality),
+        None if source == "heic" => ("jpeg", DEFAULT_QUALITY),
+        None => (source, DEFAULT_QUALITY),
+    };
+    if let Some(limits) = &limits {
+        let mut policy = limits.to_json();
+        policy["downscaled"] = json!(false);
+        policy["originalBytes"] = json!(photo_file.len());
+        if let Some((width, height)) = original_dimensions {
+            policy["originalWidth"] = json!(width);
+            policy["originalHeight"] = json!(height);
+        }
+        photo["photoPolicy"] = policy;
+    }
+    if resize.is_none() && (settings.is_none() || source == format) {
+        return Ok(None);
+    }
+
+    let id = photo["id"].as_str().unwrap_or_default().to_string();
+    let started = Instant::now();
+    let result = convert(&photo_file, format, quality, resize);
+    metrics::add(
+        "photo_transcode_duration_ms_total",
+        started.elapsed().as_millis() as u64,
+    );
+
+    match result {
+        Ok(converted) => {
+            metrics::increment(&format!(
+                "photo_transcode_total{{from=\"{}\",to=\"{}\"}}",
+                source, format
+            ));
+            metrics::add("photo_transcode_input_bytes_total", photo_file.len() as u64);
+            metrics::add("photo_transcode_output_bytes_total", converted.len() as u64);
+            if let Some(resize) = resize {
+                metrics::increment("photo_downscaled_total");
+                photo["photoPolicy"]["downscaled"] = json!(true);
+                photo["photoPolicy"]["targetDimension"] = json!(resize);
+                if let Some((width, height)) = dimensions(&converted) {
+                    photo["photoPolicy"]["width"] = json!(width);
+                    photo["photoPolicy"]["height"] = json!(height);
+                }
+            }
+            println!(
+                "Transcoded photo {} from {} to {} ({} -> {} bytes, resized to {:?}) in {:?}",
+                id,
+                source,
+                format,
+                photo_file.len(),
+                converted.len(),
+                resize,
+                started.elapsed()
+            );
+
+            photo["photoFormat"] = json!(format);
+            let original = settings
+                .is_some_and(|settings| settings.keep_original)
+                .then_some(Original {
+                    format: source,
+                    photo_file,
+                });
+            Ok(Some(Transcoded {
+                photo_file: converted,
+                original,
+            }))
+        }
+        Err(e) => {
+            metrics::increment("photo_transcode_failed_total");
+            println!(
+                "Failed to transcode photo {} from {} to {}, keeping the original: {}",
+                id, source, format, e
+            );
+            Ok(None)
+        }
+    }
+}
+
+pub fn convert(
+    photo_file: &[u8],
+    format: &str,
+    quality: u64,
+    resize: Option<u32>,
+) -> Result<Vec<u8>, String> {
+    let transcode_config = &config::get().photo_transcode;
+    let quality = quality.to_string();
+    let dimension = resize.map(|resize| resize.to_string()).unwrap_or_default();
+    let args: Vec<String> = match resize {
+        Some(_) => &transcode_config.resize_args,
+        None => &transcode_config.args,
+    }
+    .iter()
+    .map(|arg| {
+        arg.replace("{format}", format)
+            .replace("{quality}", &quality)
+            .replace("{dimension}", &dimension)
+    })
+    .collect();
+
+    let mut child = Command::new(&transcode_config.command)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", transcode_config.command, e))?;
+
+    let mut stdin = child.stdin.take().ok_or("Converter stdin unavailable")?;
+    let mut stdout = child.stdout.take().ok_or("Converter stdout unavailable")?;
+    let mut stderr = child.stderr.take().ok_or("Converter stderr unavailable")?;
+    let input = photo_file.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let error_reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output);
+        output
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(transcode_config.timeout_secs);
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "Converter exceeded {} seconds",
+                    transcode_config.timeout_secs
+                ));
+            }
+            Err(e) => return Err(format!("Failed to wait for converter: {}", e)),
+        }
+    };
+
+    let _ = writer.join();
+    let output = reader
+        .join()
+        .map_err(|_| "Converter output reader panicked".to_string())?
+        .map_err(|e| format!("Failed to read converter output: {}", e))?;
+    let errors = error_reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(format!(
+            "Converter exited with {}: {}",
+            status,
+            errors.trim()
+        ));
+    }
+    if detect_format(&output) != Some(format) {
+        return Err(format!("Converter did not produce a {} image", format));
+    }
+
+    Ok(output)
+}
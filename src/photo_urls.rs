@@ -0,0 +1,278 @@
+use crate::auth_challenge::{constant_time_eq, hex, hmac_sha256};
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::photo::photo_local_storage::{PhotoBlob, PhotoLocalStorage};
+use crate::local_storage::signing_key::signing_key_local_storage::{
+    PHOTO_URLS_PURPOSE, SigningKeyLocalStorage,
+};
+use crate::metrics;
+use crate::photo_transcode;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, with_db_pools};
+use futures_util::stream;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+static TENANT_KEYS: LazyLock<Mutex<HashMap<String, Vec<u8>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The configured signing key, or else the tenant's own key, generated once
+/// and kept in its database so links survive restarts and work on every
+/// instance.
+fn signing_key(tenant: &str, core_storage: Arc<CoreLocalStorage>) -> rusqlite::Result<Vec<u8>> {
+    let configured = &config::get().photo_urls.signing_key;
+    if !configured.is_empty() {
+        return Ok(configured.as_bytes().to_vec());
+    }
+    let mut keys = TENANT_KEYS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = keys.get(tenant) {
+        return Ok(key.clone());
+    }
+
+    let key = SigningKeyLocalStorage::new(core_storage)?
+        .get_or_create_key(PHOTO_URLS_PURPOSE)?
+        .into_bytes();
+    keys.insert(tenant.to_string(), key.clone());
+    Ok(key)
+}
+
+fn sign(key: &[u8], tenant: &str, id: &str, expires: i64) -> String {
+    hex(&hmac_sha256(
+        key,
+        format!("{}/{}/{}", tenant, id, expires).as_bytes(),
+    ))
+}
+
+pub fn issue(
+    tenant: &str,
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let id = data["id"]
+        .as_str()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| ("VALIDATION_FAILED", "id is required".to_string()))?;
+
+    let photo = PhotoLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_photo_blob(id))
+        .map_err(|e| {
+            println!("Failed to look up photo {}: {:?}", id, e);
+            ("INTERNAL", "Failed to look up photo".to_string())
+        })?;
+    let Some(photo) = photo else {
+        return Err(("NOT_FOUND", format!("Photo {} not found", id)));
+    };
+
+    let key = signing_key(tenant, core_storage).map_err(|e| {
+        println!(
+            "Failed to load photo link key of tenant {}: {:?}",
+            tenant, e
+        );
+        ("INTERNAL", "Failed to sign photo link".to_string())
+    })?;
+    let expires =
+        chrono::Utc::now().timestamp_millis() + (config::get().photo_urls.ttl_secs * 1000) as i64;
+    metrics::increment("photo_urls_issued_total");
+
+    Ok(json!({
+        "id": id,
+        "url": format!(
+            "/photos/{}/{}?expires={}&signature={}",
+            tenant,
+            id,
+            expires,
+            sign(&key, tenant, id, expires)
+        ),
+        "size": photo.size,
+        "expiresAt": expires
+    }))
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("photos" / String / String)
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::header::optional::<String>("range"))
+        .and(warp::header::optional::<String>("if-range"))
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |tenant: String,
+             id: String,
+             query: HashMap<String, String>,
+             range: Option<String>,
+             if_range: Option<String>,
+             db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    download_photo(tenant, id, query, range, if_range, db_pools).await,
+                ))
+            },
+        )
+}
+
+fn verify(
+    key: &[u8],
+    tenant: &str,
+    id: &str,
+    query: &HashMap<String, String>,
+) -> Result<(), ErrorReply> {
+    let forbidden =
+        |message| rest::error_reply(StatusCode::FORBIDDEN, "PERMISSION_DENIED", message);
+
+    let expires = query
+        .get("expires")
+        .and_then(|expires| expires.parse::<i64>().ok())
+        .ok_or_else(|| forbidden("Missing or invalid photo link"))?;
+    let signature = query
+        .get("signature")
+        .map(|signature| signature.to_lowercase())
+        .unwrap_or_default();
+    if !constant_time_eq(
+        sign(key, tenant, id, expires).as_bytes(),
+        signature.as_bytes(),
+    ) {
+        metrics::increment("photo_urls_rejected_total");
+        return Err(forbidden("Missing or invalid photo link"));
+    }
+    if expires < chrono::Utc::now().timestamp_millis() {
+        metrics::increment("photo_urls_rejected_total");
+        return Err(forbidden("Photo link expired"));
+    }
+
+    Ok(())
+}
+
+async fn download_photo(
+    tenant: String,
+    id: String,
+    query: HashMap<String, String>,
+    range: Option<String>,
+    if_range: Option<String>,
+    db_pools: DbPoolMap,
+) -> Result<Response<Body>, ErrorReply> {
+    let not_found = || rest::error_reply(StatusCode::NOT_FOUND, "NOT_FOUND", "Photo not found");
+    let invalid_link = || {
+        metrics::increment("photo_urls_rejected_total");
+        rest::error_reply(
+            StatusCode::FORBIDDEN,
+            "PERMISSION_DENIED",
+            "Missing or invalid photo link",
+        )
+    };
+
+    let core_storage = rest::tenant_storage(&tenant, &db_pools)?.ok_or_else(invalid_link)?;
+    let key = signing_key(&tenant, core_storage.clone())
+        .map_err(|e| rest::internal_error("Failed to load photo link key", e))?;
+    verify(&key, &tenant, &id, &query)?;
+    let storage = PhotoLocalStorage::new(core_storage)
+        .map(Arc::new)
+        .map_err(|e| rest::internal_error("Failed to open photo storage", e))?;
+    let PhotoBlob {
+        row_id,
+        size,
+        format,
+        hash,
+    } = storage
+        .get_photo_blob(&id)
+        .map_err(|e| rest::internal_error("Failed to look up photo", e))?
+        .ok_or_else(not_found)?;
+    // Recompression rewrites the file in place, so the tag names its content.
+    let hash = match hash {
+        Some(hash) => hash,
+        None => storage
+            .backfill_photo_hash(&id)
+            .map_err(|e| rest::internal_error("Failed to hash photo", e))?,
+    };
+    let etag = format!("\"{}-{}\"", id, hash);
+    // A range of an older version must not be spliced into the current one.
+    let range = range.filter(|_| if_range.is_none_or(|if_range| if_range == etag));
+
+    let format = match format {
+        Some(format) => Some(format),
+        None => storage
+            .read_photo_chunk(row_id, 0, 12)
+            .ok()
+            .and_then(|head| photo_transcode::detect_format(&head))
+            .map(str::to_string),
+    };
+    let content_type = match format.as_deref() {
+        Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("heic") => "image/heic",
+        _ => "application/octet-stream",
+    };
+
+    let (start, end) = match range.as_deref().map(|range| rest::parse_range(range, size)) {
+        None => (0, size.saturating_sub(1)),
+        Some(Some(bounds)) => bounds,
+        Some(None) => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("Content-Range", format!("bytes */{}", size))
+                .body(Body::empty())
+                .map_err(|e| response_error(e.to_string()));
+        }
+    };
+    let length = if size == 0 { 0 } else { end - start + 1 };
+
+    let body = Body::wrap_stream(stream::unfold(
+        (storage, start, length),
+        move |(storage, offset, remaining)| async move {
+            if remaining == 0 {
+                return None;
+            }
+            let len = CHUNK_SIZE.min(remaining as usize);
+            let reader = storage.clone();
+            let chunk =
+                tokio::task::spawn_blocking(move || reader.read_photo_chunk(row_id, offset, len))
+                    .await
+                    .map_err(|e| std::io::Error::other(e.to_string()))
+                    .and_then(|chunk| chunk.map_err(std::io::Error::other));
+
+            match chunk {
+                Ok(chunk) if chunk.is_empty() => None,
+                Ok(chunk) => {
+                    let read = chunk.len() as u64;
+                    Some((Ok(chunk), (storage, offset + read, remaining - read)))
+                }
+                Err(e) => Some((Err(e), (storage, offset, 0))),
+            }
+        },
+    ));
+
+    metrics::increment("photo_downloads_total");
+    metrics::add("photo_download_bytes_total", length);
+
+    let mut response = Response::builder()
+        .header("Content-Type", content_type)
+        .header("Accept-Ranges", "bytes")
+        .header("Content-Length", length)
+        .header("Cache-Control", "private")
+        .header("ETag", etag);
+    if range.is_some() {
+        response = response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header("Content-Range", format!("bytes {}-{}/{}", start, end, size));
+    }
+
+    response
+        .body(body)
+        .map_err(|e| response_error(e.to_string()))
+}
+
+fn response_error(e: String) -> ErrorReply {
+    println!("Failed to build photo response: {}", e);
+    rest::error_reply(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "INTERNAL",
+        "Photo download failed",
+    )
+}
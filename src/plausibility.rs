@@ -0,0 +1,54 @@
+use crate::config;
+use serde_json::Value;
+
+pub const NEEDS_REVIEW: &str = "needs_review";
+
+pub fn check_shipment(shipment: &Value) -> Option<String> {
+    let config = config::get();
+    let quantity = shipment["quantity"].as_f64().unwrap_or(0.0);
+    let piece_count = shipment["pieceCount"].as_f64().unwrap_or(0.0);
+
+    if quantity > config.plausibility.max_shipment_quantity {
+        return Some(format!(
+            "Quantity {} exceeds the maximum of {} per shipment",
+            quantity, config.plausibility.max_shipment_quantity
+        ));
+    }
+
+    if quantity > 0.0 && piece_count > 0.0 {
+        let pieces_per_cubic_meter = piece_count / quantity;
+        if pieces_per_cubic_meter < config.plausibility.min_pieces_per_cubic_meter
+            || pieces_per_cubic_meter > config.plausibility.max_pieces_per_cubic_meter
+        {
+            return Some(format!(
+                "{} pieces for {} m³ is outside the plausible range of {} to {} pieces per m³",
+                piece_count,
+                quantity,
+                config.plausibility.min_pieces_per_cubic_meter,
+                config.plausibility.max_pieces_per_cubic_meter
+            ));
+        }
+    }
+
+    None
+}
+
+pub fn flag_shipment(shipment: &mut Value) -> Option<String> {
+    if !shipment.is_object() {
+        return None;
+    }
+
+    let reason = check_shipment(shipment);
+    match &reason {
+        Some(reason) => {
+            shipment["reviewStatus"] = Value::String(NEEDS_REVIEW.to_string());
+            shipment["reviewReason"] = Value::String(reason.clone());
+        }
+        None => {
+            shipment["reviewStatus"] = Value::Null;
+            shipment["reviewReason"] = Value::Null;
+        }
+    }
+
+    reason
+}
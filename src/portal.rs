@@ -0,0 +1,259 @@
+use crate::config;
+use crate::export::ExportPolicy;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::portal_token::portal_token_local_storage::PortalTokenLocalStorage;
+use crate::rate_limit;
+use crate::rest::{self, ErrorReply};
+use crate::timezone;
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use rusqlite::params;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const SHIPMENT_PAGE_SIZE: i64 = 500;
+pub const SCOPE_PORTAL: &str = "portal";
+pub const SCOPE_GATE: &str = "gate";
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list_shipments = warp::path!("portal" / "shipments")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, query, db_pools: DbPoolMap| {
+            rest::into_reply(list_shipments(authorization, query, &db_pools))
+        });
+
+    let create_token = warp::path!("portal" / "tokens")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, body, db_pools: DbPoolMap| {
+            rest::into_reply(create_token(authorization, body, &db_pools))
+        });
+
+    let revoke_token = warp::path!("portal" / "tokens" / String)
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|token, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(revoke_token(token, authorization, &db_pools))
+        });
+
+    list_shipments
+        .or(create_token)
+        .unify()
+        .or(revoke_token)
+        .unify()
+}
+
+pub struct PortalClient {
+    pub tenant: String,
+    pub sawmill_id: String,
+    pub core_storage: Arc<CoreLocalStorage>,
+}
+
+pub fn authenticate(
+    authorization: Option<String>,
+    scope: &str,
+    db_pools: &DbPoolMap,
+) -> Result<PortalClient, ErrorReply> {
+    let unauthorized = || {
+        rest::error_reply(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Missing or invalid portal token",
+        )
+    };
+
+    let token = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or_else(unauthorized)?;
+    let (tenant, _) = token.rsplit_once('.').ok_or_else(unauthorized)?;
+
+    let core_storage = rest::tenant_storage(tenant, db_pools)?.ok_or_else(unauthorized)?;
+    let sawmill_id = PortalTokenLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_active_sawmill_id(token, scope))
+        .map_err(|e| rest::internal_error("Failed to verify portal token", e))?
+        .ok_or_else(unauthorized)?;
+
+    if let Err(retry_after) = rate_limit::check(token, config::get().portal.rate_limit_per_minute) {
+        return Err(rest::error_reply(
+            StatusCode::TOO_MANY_REQUESTS,
+            "RATE_LIMITED",
+            &format!("Rate limit exceeded, retry in {} seconds", retry_after),
+        ));
+    }
+
+    Ok(PortalClient {
+        tenant: tenant.to_string(),
+        sawmill_id,
+        core_storage,
+    })
+}
+
+fn list_shipments(
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let PortalClient {
+        tenant,
+        sawmill_id,
+        core_storage,
+    } = authenticate(authorization, SCOPE_PORTAL, db_pools)?;
+
+    if query
+        .get("sawmill")
+        .is_some_and(|requested| *requested != sawmill_id)
+    {
+        return Err(rest::error_reply(
+            StatusCode::FORBIDDEN,
+            "PERMISSION_DENIED",
+            "Token is not valid for this sawmill",
+        ));
+    }
+
+    let since = match query.get("since") {
+        Some(since) => {
+            timezone::parse_timestamp(since, &timezone::for_tenant(core_storage.clone()))
+                .ok_or_else(|| {
+                    rest::error_reply(
+                        StatusCode::BAD_REQUEST,
+                        "VALIDATION_FAILED",
+                        "since must be a date, an RFC3339 timestamp or milliseconds",
+                    )
+                })?
+        }
+        None => 0,
+    };
+
+    let policy = ExportPolicy::load(&tenant, core_storage.clone())?;
+    let shipments = core_storage
+        .get_connection()
+        .and_then(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT s.id, s.lastEdit, s.quantity, s.oversizeQuantity, s.pieceCount,
+                 s.sawmillId, l.partieNr
+                 FROM shipments s LEFT JOIN locations l ON l.id = s.locationId
+                 WHERE s.sawmillId = ? AND s.deleted = 0 AND s.lastEdit > ?
+                 ORDER BY s.lastEdit LIMIT ?",
+            )?;
+
+            stmt.query_map(params![sawmill_id, since, SHIPMENT_PAGE_SIZE], |row| {
+                let shipment =
+                    |column: &str, value: Value| policy.apply("shipments", column, value);
+                Ok(json!({
+                    "id": row.get::<_, String>("id")?,
+                    "date": shipment("lastEdit", json!(row.get::<_, i64>("lastEdit")?)),
+                    "quantity": shipment("quantity", json!(row.get::<_, f64>("quantity")?)),
+                    "oversizeQuantity": shipment(
+                        "oversizeQuantity",
+                        json!(row.get::<_, f64>("oversizeQuantity")?)
+                    ),
+                    "pieceCount": shipment("pieceCount", json!(row.get::<_, i64>("pieceCount")?)),
+                    "sawmillId": row.get::<_, String>("sawmillId")?,
+                    "partieNr": policy.apply(
+                        "locations",
+                        "partieNr",
+                        json!(row.get::<_, Option<String>>("partieNr")?)
+                    )
+                }))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .map_err(|e| rest::internal_error("Failed to load shipments", e))?;
+
+    Ok(rest::json_reply(&json!({
+        "sawmillId": sawmill_id,
+        "shipments": shipments
+    })))
+}
+
+fn create_token(
+    authorization: Option<String>,
+    body: Value,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let sawmill_id = body["sawmillId"].as_str().ok_or_else(|| {
+        rest::error_reply(
+            StatusCode::BAD_REQUEST,
+            "VALIDATION_FAILED",
+            "sawmillId is required",
+        )
+    })?;
+
+    let scope = match body.get("scope") {
+        None | Some(Value::Null) => SCOPE_PORTAL,
+        Some(scope) => [SCOPE_PORTAL, SCOPE_GATE]
+            .into_iter()
+            .find(|known| scope.as_str() == Some(*known))
+            .ok_or_else(|| {
+                rest::error_reply(
+                    StatusCode::BAD_REQUEST,
+                    "VALIDATION_FAILED",
+                    "scope must be portal or gate",
+                )
+            })?,
+    };
+
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let sawmill_exists = !core_storage
+        .get_existing_by_id("sawmills", sawmill_id)
+        .map_err(|e| rest::internal_error("Failed to load sawmill", e))?
+        .is_empty();
+    if !sawmill_exists {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Sawmill not found",
+        ));
+    }
+
+    let token = format!("{}.{}", user.tenant, Uuid::new_v4().simple());
+    PortalTokenLocalStorage::new(core_storage)
+        .and_then(|storage| storage.create_token(&token, sawmill_id, &user.user_id, scope))
+        .map_err(|e| rest::internal_error("Failed to create portal token", e))?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({ "token": token, "sawmillId": sawmill_id, "scope": scope })),
+        StatusCode::CREATED,
+    ))
+}
+
+fn revoke_token(
+    token: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let revoked = PortalTokenLocalStorage::new(core_storage)
+        .and_then(|storage| storage.revoke_token(&token))
+        .map_err(|e| rest::internal_error("Failed to revoke portal token", e))?;
+
+    if !revoked {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Portal token not found",
+        ));
+    }
+
+    Ok(rest::json_reply(
+        &json!({ "token": token, "revoked": true }),
+    ))
+}
@@ -0,0 +1,163 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::sawmill_price::sawmill_price_local_storage::SawmillPriceLocalStorage;
+use crate::pricing;
+use crate::rest::{self, ErrorReply};
+use crate::units;
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const MAX_ASSORTMENT_LEN: usize = 64;
+
+pub fn validate_price(
+    price: &mut Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<(), String> {
+    let id = price["id"].as_str().unwrap_or_default().to_string();
+    let sawmill_id = price["sawmillId"]
+        .as_str()
+        .ok_or_else(|| "sawmillId is required".to_string())?
+        .to_string();
+    let sawmill = core_storage
+        .get_existing_by_id("sawmills", &sawmill_id)
+        .map_err(|e| format!("Failed to load sawmill: {}", e))?;
+    if sawmill.is_empty() {
+        return Err(format!("Unknown sawmill: {}", sawmill_id));
+    }
+
+    let assortment = price["assortment"]
+        .as_str()
+        .map(str::trim)
+        .filter(|assortment| !assortment.is_empty())
+        .ok_or_else(|| "assortment is required".to_string())?
+        .to_string();
+    if assortment.chars().count() > MAX_ASSORTMENT_LEN {
+        return Err(format!(
+            "assortment must not exceed {} characters",
+            MAX_ASSORTMENT_LEN
+        ));
+    }
+    price["assortment"] = json!(assortment);
+
+    match price["pricePerUnit"].as_f64() {
+        Some(value) if value.is_finite() && value >= 0.0 => {}
+        _ => return Err("pricePerUnit must be a non-negative number".to_string()),
+    }
+
+    let unit = match price.get("unit") {
+        None | Some(Value::Null) => units::CANONICAL_UNIT.to_string(),
+        Some(Value::String(unit)) => unit.trim().to_lowercase(),
+        Some(_) => return Err("unit must be a string".to_string()),
+    };
+    if !units::factors(core_storage.clone())
+        .iter()
+        .any(|(known, _)| *known == unit)
+    {
+        return Err(format!("Unknown unit: {}", unit));
+    }
+    price["unit"] = json!(unit);
+
+    let (default_currency, allowed) = pricing::tenant_currencies(core_storage.clone())
+        .map_err(|e| format!("Failed to load currencies: {}", e))?;
+    match price.get("currency") {
+        None | Some(Value::Null) => price["currency"] = json!(default_currency),
+        Some(Value::String(code)) if allowed.contains(code) => {}
+        Some(Value::String(code)) => {
+            return Err(format!("Currency {} is not enabled for this tenant", code));
+        }
+        Some(_) => return Err("currency must be a string".to_string()),
+    }
+
+    let valid_from = price["validFrom"]
+        .as_i64()
+        .ok_or_else(|| "validFrom must be a timestamp".to_string())?;
+    let valid_to = match price.get("validTo") {
+        None | Some(Value::Null) => None,
+        Some(value) => Some(
+            value
+                .as_i64()
+                .ok_or_else(|| "validTo must be a timestamp".to_string())?,
+        ),
+    };
+    if valid_to.is_some_and(|valid_to| valid_to <= valid_from) {
+        return Err("validTo must be after validFrom".to_string());
+    }
+    price["validTo"] = json!(valid_to);
+
+    let overlapping = SawmillPriceLocalStorage::new(core_storage)
+        .and_then(|storage| {
+            storage.find_overlapping_price(&id, &sawmill_id, &assortment, valid_from, valid_to)
+        })
+        .map_err(|e| format!("Failed to check price periods: {}", e))?;
+    if let Some(existing) = overlapping {
+        return Err(format!(
+            "Price period overlaps price {} of {} for {} valid from {}",
+            existing["id"].as_str().unwrap_or_default(),
+            sawmill_id,
+            assortment,
+            existing["validFrom"]
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("sawmills" / String / "prices")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(|sawmill_id, authorization, query, db_pools: DbPoolMap| {
+            rest::into_reply(list_prices(sawmill_id, authorization, query, &db_pools))
+        })
+}
+
+fn list_prices(
+    sawmill_id: String,
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    let storage = rest::open_storage(&user.tenant)
+        .and_then(SawmillPriceLocalStorage::new)
+        .map_err(|e| rest::internal_error("Failed to open sawmill prices", e))?;
+
+    let Some(assortment) = query.get("assortment") else {
+        let prices = storage
+            .get_prices_by_sawmill(&sawmill_id)
+            .map_err(|e| rest::internal_error("Failed to load sawmill prices", e))?;
+        return Ok(rest::json_reply(
+            &json!({ "sawmillId": sawmill_id, "prices": prices }),
+        ));
+    };
+
+    let at = match query.get("date") {
+        Some(date) => date.parse::<i64>().map_err(|_| {
+            rest::error_reply(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "date must be a timestamp in milliseconds",
+            )
+        })?,
+        None => chrono::Utc::now().timestamp_millis(),
+    };
+    let price = storage
+        .resolve_price(&sawmill_id, assortment, at)
+        .map_err(|e| rest::internal_error("Failed to resolve sawmill price", e))?
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                "No price is valid for this assortment at that date",
+            )
+        })?;
+
+    Ok(rest::json_reply(&price))
+}
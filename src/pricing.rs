@@ -0,0 +1,127 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::settings::settings_local_storage::{
+    CURRENCIES_KEY, CURRENCY_KEY, SettingsLocalStorage,
+};
+use rusqlite::Result;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub const DEFAULT_CURRENCY: &str = "EUR";
+
+pub fn is_valid_currency(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())
+}
+
+pub fn tenant_currencies(core_storage: Arc<CoreLocalStorage>) -> Result<(String, Vec<String>)> {
+    let settings = SettingsLocalStorage::new(core_storage)?;
+
+    let default_currency = settings
+        .get_setting(CURRENCY_KEY)?
+        .unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+
+    let mut allowed: Vec<String> = settings
+        .get_setting(CURRENCIES_KEY)?
+        .map(|list| {
+            list.split(',')
+                .map(|code| code.trim().to_string())
+                .filter(|code| !code.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if !allowed.contains(&default_currency) {
+        allowed.push(default_currency.clone());
+    }
+
+    Ok((default_currency, allowed))
+}
+
+pub fn validate_contract_pricing(
+    contract: &mut Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> std::result::Result<(), String> {
+    let price = match contract.get("pricePerCubicMeter") {
+        None | Some(Value::Null) => None,
+        Some(value) => match value.as_f64() {
+            Some(price) if price.is_finite() && price >= 0.0 => Some(price),
+            _ => return Err("pricePerCubicMeter must be a non-negative number".to_string()),
+        },
+    };
+
+    match contract.get("vatRate") {
+        None | Some(Value::Null) => {}
+        Some(value) => match value.as_f64() {
+            Some(rate) if (0.0..=100.0).contains(&rate) => {}
+            _ => return Err("vatRate must be a percentage between 0 and 100".to_string()),
+        },
+    }
+
+    let (default_currency, allowed) =
+        tenant_currencies(core_storage).map_err(|e| format!("Failed to load currencies: {}", e))?;
+
+    match contract.get("currency") {
+        None | Some(Value::Null) => {
+            if price.is_some() {
+                contract["currency"] = json!(default_currency);
+            }
+        }
+        Some(Value::String(code)) => {
+            if !is_valid_currency(code) {
+                return Err(format!("Invalid currency code: {}", code));
+            }
+            if !allowed.contains(code) {
+                return Err(format!("Currency {} is not enabled for this tenant", code));
+            }
+        }
+        Some(_) => return Err("currency must be a string".to_string()),
+    }
+
+    Ok(())
+}
+
+pub fn apply_line_values(shipment: &mut Value, core_storage: Arc<CoreLocalStorage>) -> Result<()> {
+    if !shipment.is_object() {
+        return Ok(());
+    }
+
+    let contract_id = shipment["contractId"].as_str().unwrap_or_default();
+    let contract = core_storage
+        .get_existing_by_id("contracts", contract_id)?
+        .into_iter()
+        .next();
+
+    let price = contract
+        .as_ref()
+        .and_then(|contract| contract["pricePerCubicMeter"].as_f64());
+
+    match (contract, price) {
+        (Some(contract), Some(price)) => {
+            let quantity = shipment["quantity"].as_f64().unwrap_or(0.0);
+            let vat_rate = contract["vatRate"].as_f64().unwrap_or(0.0);
+            let currency = match contract["currency"].as_str() {
+                Some(currency) => currency.to_string(),
+                None => tenant_currencies(core_storage)?.0,
+            };
+
+            let net_value = round_to_cents(quantity * price);
+            let vat_value = round_to_cents(net_value * vat_rate / 100.0);
+
+            shipment["netValue"] = json!(net_value);
+            shipment["vatValue"] = json!(vat_value);
+            shipment["grossValue"] = json!(round_to_cents(net_value + vat_value));
+            shipment["currency"] = json!(currency);
+        }
+        _ => {
+            shipment["netValue"] = Value::Null;
+            shipment["vatValue"] = Value::Null;
+            shipment["grossValue"] = Value::Null;
+            shipment["currency"] = Value::Null;
+        }
+    }
+
+    Ok(())
+}
+
+fn round_to_cents(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
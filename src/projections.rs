@@ -0,0 +1,187 @@
+use crate::integration_tokens;
+use crate::local_storage::projection::projection_local_storage::{
+    ProjectionLocalStorage, ProjectionQuery,
+};
+use crate::local_storage::projection::projection_views::{self, FilterKind, Projection};
+use crate::local_storage::search::search_key;
+use crate::rest::{self, ErrorReply};
+use crate::timezone::{self, TenantTimezone};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use rusqlite::types::Value as SqlValue;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("office" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::addr::remote())
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |name: String,
+             authorization: Option<String>,
+             query: HashMap<String, String>,
+             remote_addr: Option<SocketAddr>,
+             db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    list_projection(name, authorization, query, remote_addr, db_pools).await,
+                ))
+            },
+        )
+}
+
+fn invalid(message: &str) -> ErrorReply {
+    rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message)
+}
+
+fn parse_query(
+    projection: &'static Projection,
+    query: &HashMap<String, String>,
+    timezone: &TenantTimezone,
+) -> Result<ProjectionQuery, ErrorReply> {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    for filter in projection.filters {
+        let Some(raw) = query.get(filter.param).map(|raw| raw.trim()) else {
+            continue;
+        };
+        let value = match filter.kind {
+            FilterKind::Text => SqlValue::Text(raw.to_string()),
+            FilterKind::Bool => match raw {
+                "true" | "1" => SqlValue::Integer(1),
+                "false" | "0" => SqlValue::Integer(0),
+                _ => return Err(invalid(&format!("{} must be true or false", filter.param))),
+            },
+            FilterKind::Search => {
+                let escaped = search_key::fold(raw)
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                SqlValue::Text(format!("%{}%", escaped))
+            }
+            FilterKind::From | FilterKind::To => {
+                let parsed = if matches!(filter.kind, FilterKind::From) {
+                    timezone::parse_timestamp(raw, timezone)
+                } else {
+                    timezone::parse_range_end(raw, timezone)
+                };
+                SqlValue::Integer(parsed.ok_or_else(|| {
+                    invalid(&format!(
+                        "{} must be a date, an RFC3339 timestamp or milliseconds",
+                        filter.param
+                    ))
+                })?)
+            }
+        };
+
+        for _ in 0..filter.condition.matches('?').count() {
+            params.push(value.clone());
+        }
+        conditions.push(filter.condition);
+    }
+
+    let sort_name = query
+        .get("sort")
+        .map(String::as_str)
+        .unwrap_or(projection.default_sort);
+    let sort = projection.field(sort_name).ok_or_else(|| {
+        invalid(&format!(
+            "sort must be one of {}",
+            projection
+                .fields
+                .iter()
+                .map(|field| field.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    })?;
+    let descending = match query.get("order").map(String::as_str) {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return Err(invalid("order must be asc or desc")),
+    };
+
+    let limit = match query.get("limit") {
+        None => DEFAULT_LIMIT,
+        Some(limit) => limit
+            .parse::<i64>()
+            .ok()
+            .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+            .ok_or_else(|| invalid(&format!("limit must be between 1 and {}", MAX_LIMIT)))?,
+    };
+    let offset = match query.get("offset") {
+        None => 0,
+        Some(offset) => offset
+            .parse::<i64>()
+            .ok()
+            .filter(|offset| *offset >= 0)
+            .ok_or_else(|| invalid("offset must be a non-negative integer"))?,
+    };
+
+    Ok(ProjectionQuery {
+        conditions,
+        params,
+        sort,
+        descending,
+        limit,
+        offset,
+    })
+}
+
+async fn list_projection(
+    name: String,
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    remote_addr: Option<SocketAddr>,
+    db_pools: DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user =
+        integration_tokens::authenticate(authorization, remote_addr, &db_pools, ROLE_PRIVILEGED)?;
+    let projection = projection_views::projection(&name)
+        .ok_or_else(|| rest::error_reply(StatusCode::NOT_FOUND, "NOT_FOUND", "Unknown view"))?;
+    user.require_read(projection.name)?;
+
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let timezone = timezone::for_tenant(core_storage.clone());
+    let projection_query = parse_query(projection, &query, &timezone)?;
+
+    let page = json!({
+        "limit": projection_query.limit,
+        "offset": projection_query.offset,
+        "sort": projection_query.sort.name,
+        "order": if projection_query.descending { "desc" } else { "asc" }
+    });
+
+    let (items, total) = tokio::task::spawn_blocking(move || {
+        ProjectionLocalStorage::new(core_storage)
+            .and_then(|storage| storage.query(projection, &projection_query))
+    })
+    .await
+    .map_err(|e| {
+        println!("Projection task failed: {:?}", e);
+        rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Failed to load view",
+        )
+    })?
+    .map_err(|e| rest::internal_error("Failed to load view", e))?;
+
+    Ok(rest::json_reply(&json!({
+        "view": projection.name,
+        "items": items,
+        "total": total,
+        "page": page
+    })))
+}
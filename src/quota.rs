@@ -0,0 +1,334 @@
+use crate::config;
+use crate::instance_lease;
+use crate::local_storage::contract::contract_local_storage::ContractLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::quota_reservation::quota_reservation_local_storage::QuotaReservationLocalStorage;
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use crate::{Clients, ROLE_PRIVILEGED, broadcast_to_tenant};
+use serde_json::{Value, json};
+use std::fs;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use uuid::Uuid;
+
+const QUANTITY_EPSILON: f64 = 1e-9;
+
+pub struct Reservation {
+    pub reservation: Value,
+    pub contract: Option<Value>,
+    pub remaining: f64,
+}
+
+pub fn reserve(
+    core_storage: Arc<CoreLocalStorage>,
+    user_id: &str,
+    data: &Value,
+) -> Result<Reservation, String> {
+    in_transaction(&core_storage, || {
+        apply_reserve(core_storage.clone(), user_id, data)
+    })
+}
+
+pub fn settle_for_location(
+    core_storage: Arc<CoreLocalStorage>,
+    location_id: &str,
+    deleted: bool,
+) -> Result<Option<Reservation>, String> {
+    let active = QuotaReservationLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_active_by_location(location_id))
+        .map_err(db_error)?;
+    let Some(reservation) = active else {
+        return Ok(None);
+    };
+
+    let status = if deleted { "released" } else { "completed" };
+    in_transaction(&core_storage, || {
+        finish(core_storage.clone(), reservation, status, deleted)
+    })
+    .map(Some)
+}
+
+pub fn spawn(clients: Clients) -> Option<JoinHandle<()>> {
+    let period = Duration::from_secs(config::get().quota.expiry_check_interval_secs);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            if !instance_lease::is_leader() {
+                continue;
+            }
+            let expired = match tokio::task::spawn_blocking(expire_all_tenants).await {
+                Ok(expired) => expired,
+                Err(e) => {
+                    eprintln!("Quota expiry run failed: {:?}", e);
+                    continue;
+                }
+            };
+
+            for (tenant, expired) in expired {
+                for expired in expired {
+                    broadcast(&tenant, &expired, &clients).await;
+                }
+            }
+        }
+    }))
+}
+
+pub async fn broadcast(tenant: &str, settled: &Reservation, clients: &Clients) {
+    broadcast_to_tenant(
+        tenant,
+        "quota_reservation_update",
+        &settled.reservation,
+        ROLE_PRIVILEGED,
+        clients,
+    )
+    .await;
+    if let Some(contract) = &settled.contract {
+        broadcast_to_tenant(tenant, "contract_update", contract, 0, clients).await;
+    }
+}
+
+fn apply_reserve(
+    core_storage: Arc<CoreLocalStorage>,
+    user_id: &str,
+    data: &Value,
+) -> Result<Reservation, String> {
+    let contract_id = data["contractId"]
+        .as_str()
+        .ok_or("contractId is required")?;
+    let location_id = data["locationId"]
+        .as_str()
+        .ok_or("locationId is required")?;
+    let quantity = data["quantity"]
+        .as_f64()
+        .filter(|quantity| quantity.is_finite() && *quantity > 0.0)
+        .ok_or("quantity must be a positive number")?;
+
+    if !core_storage
+        .get_existing_by_id("locations", location_id)
+        .map_err(db_error)?
+        .is_empty()
+    {
+        return Err(format!("Location {} already exists", location_id));
+    }
+    let reservation_storage =
+        QuotaReservationLocalStorage::new(core_storage.clone()).map_err(db_error)?;
+    if reservation_storage
+        .get_active_by_location(location_id)
+        .map_err(db_error)?
+        .is_some()
+    {
+        return Err(format!(
+            "Location {} already has an active reservation",
+            location_id
+        ));
+    }
+
+    let mut contract = core_storage
+        .get_existing_by_id("contracts", contract_id)
+        .map_err(db_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Contract {} not found", contract_id))?;
+    if contract["done"].as_i64() == Some(1) {
+        return Err(format!("Contract {} is closed", contract_id));
+    }
+
+    let available = contract["availableQuantity"].as_f64().unwrap_or(0.0);
+    let booked = contract["bookedQuantity"].as_f64().unwrap_or(0.0);
+    let remaining = available - booked;
+    if quantity > remaining + QUANTITY_EPSILON {
+        return Err(format!(
+            "Only {:.2} of contract {} remaining",
+            remaining.max(0.0),
+            contract_id
+        ));
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    contract["bookedQuantity"] = json!(booked + quantity);
+    contract["lastEdit"] = json!(now);
+    ContractLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.save_contract(&contract))
+        .map_err(db_error)?;
+
+    let ttl_ms = (config::get().quota.reservation_ttl_hours * 60 * 60 * 1000) as i64;
+    let reservation = json!({
+        "id": data["id"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        "lastEdit": now,
+        "contractId": contract_id,
+        "locationId": location_id,
+        "userId": user_id,
+        "quantity": quantity,
+        "status": "active",
+        "expiresAt": now + ttl_ms,
+        "deleted": 0
+    });
+    reservation_storage
+        .save_reservation(&reservation)
+        .map_err(db_error)?;
+    metrics::increment("quota_reservations_total");
+
+    Ok(Reservation {
+        reservation,
+        contract: Some(contract),
+        remaining: remaining - quantity,
+    })
+}
+
+fn finish(
+    core_storage: Arc<CoreLocalStorage>,
+    mut reservation: Value,
+    status: &str,
+    release: bool,
+) -> Result<Reservation, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let contract_id = reservation["contractId"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let mut contract = core_storage
+        .get_existing_by_id("contracts", &contract_id)
+        .map_err(db_error)?
+        .into_iter()
+        .next();
+
+    let mut remaining = 0.0;
+    if let Some(contract) = contract.as_mut() {
+        let available = contract["availableQuantity"].as_f64().unwrap_or(0.0);
+        let mut booked = contract["bookedQuantity"].as_f64().unwrap_or(0.0);
+        if release {
+            booked = (booked - reservation["quantity"].as_f64().unwrap_or(0.0)).max(0.0);
+            contract["bookedQuantity"] = json!(booked);
+            contract["lastEdit"] = json!(now);
+            ContractLocalStorage::new(core_storage.clone())
+                .and_then(|storage| storage.save_contract(contract))
+                .map_err(db_error)?;
+        }
+        remaining = available - booked;
+    }
+
+    reservation["status"] = json!(status);
+    reservation["lastEdit"] = json!(now);
+    QuotaReservationLocalStorage::new(core_storage)
+        .and_then(|storage| storage.save_reservation(&reservation))
+        .map_err(db_error)?;
+
+    Ok(Reservation {
+        reservation,
+        contract: contract.filter(|_| release),
+        remaining,
+    })
+}
+
+fn expire_all_tenants() -> Vec<(String, Vec<Reservation>)> {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut expired_by_tenant = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            continue;
+        }
+
+        let core_storage = match path
+            .to_str()
+            .ok_or(rusqlite::Error::InvalidPath(path.clone()))
+            .and_then(CoreLocalStorage::new)
+            .and_then(|core_storage| {
+                schema::migrate(&*core_storage.get_connection()?)?;
+                Ok(Arc::new(core_storage))
+            }) {
+            Ok(core_storage) => core_storage,
+            Err(e) => {
+                eprintln!("Failed to open tenant {} for quota expiry: {:?}", tenant, e);
+                continue;
+            }
+        };
+
+        let due = match QuotaReservationLocalStorage::new(core_storage.clone())
+            .and_then(|storage| storage.get_expired(now))
+        {
+            Ok(due) => due,
+            Err(e) => {
+                eprintln!("Failed to load quota reservations of {}: {:?}", tenant, e);
+                continue;
+            }
+        };
+
+        let mut expired = Vec::new();
+        for reservation in due {
+            let id = reservation["id"].clone();
+            match in_transaction(&core_storage, || {
+                finish(core_storage.clone(), reservation, "expired", true)
+            }) {
+                Ok(settled) => expired.push(settled),
+                Err(e) => eprintln!("Failed to expire quota reservation {}: {}", id, e),
+            }
+        }
+        if !expired.is_empty() {
+            metrics::add("quota_reservations_expired_total", expired.len() as u64);
+            println!(
+                "Expired {} quota reservation(s) of tenant {}",
+                expired.len(),
+                tenant
+            );
+            expired_by_tenant.push((tenant.to_string(), expired));
+        }
+    }
+
+    expired_by_tenant
+}
+
+fn in_transaction<T>(
+    core_storage: &CoreLocalStorage,
+    work: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    core_storage
+        .begin()
+        .map_err(|e| format!("Failed to start transaction: {:?}", e))?;
+
+    match work() {
+        Ok(result) => {
+            core_storage
+                .commit()
+                .map_err(|e| format!("Failed to commit quota reservation: {:?}", e))?;
+            Ok(result)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = core_storage.rollback() {
+                eprintln!(
+                    "Failed to roll back quota reservation: {:?}",
+                    rollback_error
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> String {
+    format!("Database error: {:?}", e)
+}
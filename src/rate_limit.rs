@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+static WINDOWS: LazyLock<Mutex<HashMap<String, (Instant, u32)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn check(key: &str, limit_per_minute: u32) -> Result<(), u64> {
+    let now = Instant::now();
+    let mut windows = WINDOWS.lock().unwrap_or_else(|e| e.into_inner());
+    windows.retain(|_, (started, _)| now.duration_since(*started) < WINDOW);
+
+    let (started, count) = windows.entry(key.to_string()).or_insert((now, 0));
+    if *count >= limit_per_minute {
+        let retry_after = WINDOW.saturating_sub(now.duration_since(*started));
+        return Err(retry_after.as_secs().max(1));
+    }
+
+    *count += 1;
+    Ok(())
+}
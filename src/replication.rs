@@ -0,0 +1,550 @@
+use crate::auth_challenge::constant_time_eq;
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::event_log::event_log_local_storage::EventLogLocalStorage;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::snapshot::Snapshot;
+use crate::update_schema;
+use crate::{database_exists, get_db_path};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use warp::http::StatusCode;
+use warp::http::header::CONTENT_TYPE;
+use warp::hyper::Body;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+/// Not a tenant database, so the `*.db` scans of the databases directory skip it.
+const PROMOTED_FILE: &str = "replication_promoted";
+const MAX_PAGE_SIZE: i64 = 5000;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Survives restarts through PROMOTED_FILE, so a promoted standby never
+/// starts following the old primary again.
+static PROMOTED: LazyLock<AtomicBool> =
+    LazyLock::new(|| AtomicBool::new(promoted_marker().exists()));
+
+fn promoted_marker() -> PathBuf {
+    config::get().databases_dir.join(PROMOTED_FILE)
+}
+
+/// Whether this server follows a primary. A standby serves no clients and
+/// runs no scheduled jobs until it is promoted.
+pub fn is_standby() -> bool {
+    config::get().replication.mode == "standby" && !PROMOTED.load(Ordering::Relaxed)
+}
+
+fn is_tenant_name(tenant: &str) -> bool {
+    !tenant.is_empty()
+        && tenant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn route() -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let tenants = warp::path!("replication" / "tenants")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(|authorization| rest::into_reply(list_tenants(authorization)));
+
+    let events = warp::path!("replication" / String / "events")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .map(|tenant: String, authorization, query| {
+            rest::into_reply(read_events(&tenant, authorization, query))
+        });
+
+    let snapshot = warp::path!("replication" / String / "snapshot")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(|tenant: String, authorization| async move {
+            Ok::<_, Rejection>(rest::into_reply(send_snapshot(tenant, authorization).await))
+        });
+
+    let promote = warp::path!("admin" / "replication" / "promote")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(|authorization| rest::into_reply(promote(authorization)));
+
+    tenants
+        .or(events)
+        .unify()
+        .or(snapshot)
+        .unify()
+        .or(promote)
+        .unify()
+}
+
+/// Replication requests authenticate with the shared secret both servers
+/// are configured with, not with a tenant's API key.
+fn authorize(authorization: Option<String>, mode: &str) -> Result<(), ErrorReply> {
+    let settings = &config::get().replication;
+    if settings.mode != mode {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            &format!("This server is not a replication {}", mode),
+        ));
+    }
+
+    let token = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(token.as_bytes(), settings.shared_secret.as_bytes()) {
+        metrics::increment("replication_unauthorized_total");
+        return Err(rest::error_reply(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Missing or invalid replication secret",
+        ));
+    }
+    Ok(())
+}
+
+fn require_tenant(tenant: &str) -> Result<(), ErrorReply> {
+    if is_tenant_name(tenant) && database_exists(tenant) {
+        Ok(())
+    } else {
+        Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Unknown tenant",
+        ))
+    }
+}
+
+fn list_tenants(authorization: Option<String>) -> Result<ErrorReply, ErrorReply> {
+    authorize(authorization, "primary")?;
+
+    let entries = fs::read_dir(&config::get().databases_dir).map_err(|e| {
+        println!("Failed to read databases directory: {}", e);
+        rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Failed to list tenants",
+        )
+    })?;
+    let mut tenants: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.strip_suffix(".db")?.to_string();
+            is_tenant_name(&name).then_some(name)
+        })
+        .collect();
+    tenants.sort();
+
+    Ok(rest::json_reply(&json!({ "tenants": tenants })))
+}
+
+fn read_events(
+    tenant: &str,
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+) -> Result<ErrorReply, ErrorReply> {
+    authorize(authorization, "primary")?;
+    require_tenant(tenant)?;
+
+    let after = query
+        .get("after")
+        .and_then(|after| after.parse::<i64>().ok())
+        .unwrap_or(0);
+    let limit = query
+        .get("limit")
+        .and_then(|limit| limit.parse::<i64>().ok())
+        .unwrap_or(config::get().replication.page_size)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let events = rest::open_storage(tenant)
+        .and_then(EventLogLocalStorage::new)
+        .and_then(|storage| storage.get_replication_page(after, limit))
+        .map_err(|e| rest::internal_error("Failed to read event log", e))?;
+    metrics::add("replication_events_served_total", events.len() as u64);
+
+    Ok(rest::json_reply(&json!({
+        "tenant": tenant,
+        "events": events
+    })))
+}
+
+/// A consistent copy of the tenant database. Its event log ends where the
+/// standby continues with /replication/{tenant}/events.
+async fn send_snapshot(
+    tenant: String,
+    authorization: Option<String>,
+) -> Result<Response, ErrorReply> {
+    authorize(authorization, "primary")?;
+    require_tenant(&tenant)?;
+
+    let db_path = get_db_path(&tenant);
+    let copied = tokio::task::spawn_blocking(move || {
+        let snapshot = Snapshot::create(&db_path).map_err(|e| e.to_string())?;
+        fs::read(snapshot.path()).map_err(|e| e.to_string())
+    })
+    .await;
+    let bytes = match copied {
+        Ok(Ok(bytes)) => bytes,
+        Ok(Err(e)) => {
+            println!(
+                "Failed to snapshot tenant {} for replication: {}",
+                tenant, e
+            );
+            return Err(rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to create snapshot",
+            ));
+        }
+        Err(e) => {
+            println!("Replication snapshot task failed: {:?}", e);
+            return Err(rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to create snapshot",
+            ));
+        }
+    };
+    metrics::increment("replication_snapshots_served_total");
+
+    let mut response = Response::new(Body::from(bytes));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        warp::http::HeaderValue::from_static("application/vnd.sqlite3"),
+    );
+    Ok(response)
+}
+
+/// Stops following the primary for good. Clients and scheduled jobs are
+/// accepted from then on; the old primary must be shut down first.
+fn promote(authorization: Option<String>) -> Result<ErrorReply, ErrorReply> {
+    authorize(authorization, "standby")?;
+
+    if let Err(e) = fs::write(promoted_marker(), chrono::Utc::now().to_rfc3339()) {
+        println!("Failed to persist promotion: {}", e);
+        return Err(rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Failed to persist promotion",
+        ));
+    }
+    if !PROMOTED.swap(true, Ordering::Relaxed) {
+        metrics::increment("replication_promotions_total");
+        println!("Standby promoted to primary");
+    }
+
+    Ok(rest::json_reply(&json!({ "promoted": true })))
+}
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    if !is_standby() {
+        return None;
+    }
+
+    let period = Duration::from_secs(config::get().replication.poll_interval_secs);
+    Some(tokio::task::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut ticker = interval(period);
+
+        while is_standby() {
+            ticker.tick().await;
+            replicate_all(&client).await;
+        }
+        println!("Stopped replicating from the primary");
+    }))
+}
+
+async fn fetch(
+    client: &reqwest::Client,
+    path: &str,
+    timeout: Duration,
+) -> Result<reqwest::Response, String> {
+    let settings = &config::get().replication;
+    let url = format!("{}/{}", settings.primary_url.trim_end_matches('/'), path);
+    let response = client
+        .get(&url)
+        .bearer_auth(&settings.shared_secret)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| format!("{}: {}", url, e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", url, response.status()));
+    }
+    Ok(response)
+}
+
+async fn fetch_json(client: &reqwest::Client, path: &str) -> Result<Value, String> {
+    let body = fetch(client, path, FETCH_TIMEOUT)
+        .await?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&body).map_err(|e| e.to_string())
+}
+
+async fn replicate_all(client: &reqwest::Client) {
+    let tenants = match fetch_json(client, "replication/tenants").await {
+        Ok(tenants) => tenants,
+        Err(e) => {
+            eprintln!("Failed to list tenants of the primary: {}", e);
+            metrics::increment("replication_failures_total");
+            return;
+        }
+    };
+
+    for tenant in tenants["tenants"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tenant| tenant.as_str())
+        .filter(|tenant| is_tenant_name(tenant))
+    {
+        if !is_standby() {
+            return;
+        }
+        if let Err(e) = replicate_tenant(client, tenant).await {
+            eprintln!("Failed to replicate tenant {}: {}", tenant, e);
+            metrics::increment("replication_failures_total");
+        }
+    }
+}
+
+async fn replicate_tenant(client: &reqwest::Client, tenant: &str) -> Result<(), String> {
+    if !database_exists(tenant) {
+        return install_snapshot(client, tenant).await;
+    }
+
+    let page_size = config::get().replication.page_size;
+    loop {
+        let db_path = get_db_path(tenant);
+        let after = tokio::task::spawn_blocking(move || {
+            CoreLocalStorage::new(&db_path)
+                .map(std::sync::Arc::new)
+                .and_then(EventLogLocalStorage::new)
+                .and_then(|storage| storage.get_last_seq())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{:?}", e))?;
+
+        let page = fetch_json(
+            client,
+            &format!(
+                "replication/{}/events?after={}&limit={}",
+                tenant, after, page_size
+            ),
+        )
+        .await?;
+        let events = page["events"].as_array().cloned().unwrap_or_default();
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let count = events.len();
+        let db_path = get_db_path(tenant);
+        match tokio::task::spawn_blocking(move || apply(&db_path, &events)).await {
+            Ok(Ok(())) => metrics::add("replication_events_applied_total", count as u64),
+            Ok(Err(e)) => {
+                eprintln!(
+                    "Failed to apply events of tenant {}, installing a new snapshot: {:?}",
+                    tenant, e
+                );
+                metrics::increment("replication_apply_failures_total");
+                return install_snapshot(client, tenant).await;
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+        if (count as i64) < page_size {
+            return Ok(());
+        }
+    }
+}
+
+/// Replays events of the primary in one transaction: entity updates are
+/// written to their tables and every event is added to the local event log
+/// under its original seq, which is where the next page starts.
+fn apply(db_path: &str, events: &[Value]) -> rusqlite::Result<()> {
+    let core_storage = std::sync::Arc::new(CoreLocalStorage::new(db_path)?);
+    let event_log = EventLogLocalStorage::new(core_storage.clone())?;
+
+    core_storage.begin()?;
+    let result = events.iter().try_for_each(|event| {
+        if let Some(table) =
+            update_schema::table_for(event["eventType"].as_str().unwrap_or_default())
+            && event["payload"]["id"].is_string()
+        {
+            let mut row = event["payload"].clone();
+            if row.get("arrivalAtServer").is_none() {
+                row["arrivalAtServer"] = event["timestamp"].clone();
+            }
+            core_storage.upsert(table, &row)?;
+        }
+        event_log.insert_replicated(event)
+    });
+
+    match result {
+        Ok(()) => core_storage.commit(),
+        Err(e) => {
+            if let Err(rollback_error) = core_storage.rollback() {
+                println!(
+                    "Failed to roll back replicated events: {:?}",
+                    rollback_error
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn install_snapshot(client: &reqwest::Client, tenant: &str) -> Result<(), String> {
+    let bytes = fetch(
+        client,
+        &format!("replication/{}/snapshot", tenant),
+        SNAPSHOT_TIMEOUT,
+    )
+    .await?
+    .bytes()
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let db_path = get_db_path(tenant);
+    tokio::task::spawn_blocking(move || {
+        let partial = format!("{}.replica", db_path);
+        fs::write(&partial, &bytes)?;
+        for suffix in ["-wal", "-shm"] {
+            let path = format!("{}{}", db_path, suffix);
+            if let Err(e) = fs::remove_file(&path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                return Err(e);
+            }
+        }
+        fs::rename(&partial, &db_path)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+
+    metrics::increment("replication_snapshots_installed_total");
+    println!("Installed a snapshot of tenant {} from the primary", tenant);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local_storage::schema;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn standby_db() -> String {
+        let path = std::env::temp_dir().join(format!("replication-test-{}.db", Uuid::new_v4()));
+        let path = path.to_string_lossy().into_owned();
+        let core_storage = CoreLocalStorage::new(&path).unwrap();
+        schema::migrate(&core_storage.get_connection().unwrap()).unwrap();
+        path
+    }
+
+    fn event(seq: i64, event_type: &str, payload: Value) -> Value {
+        json!({
+            "seq": seq,
+            "timestamp": 1000 + seq,
+            "eventType": event_type,
+            "entityId": payload["id"],
+            "minRole": 0,
+            "payload": payload,
+            "compacted": 0,
+            "userId": "u1",
+            "deviceId": "d1",
+            "appVersion": "1.0.0"
+        })
+    }
+
+    #[test]
+    fn applies_events_in_order_and_continues_after_the_last_seq() {
+        let db_path = standby_db();
+        let contract = json!({
+            "id": "c1", "done": 0, "lastEdit": 1, "title": "Eiche", "additionalInfo": "",
+            "startDate": 0, "endDate": 0, "availableQuantity": 10.0, "bookedQuantity": 0.0,
+            "shippedQuantity": 0.0, "deleted": 0, "sawmillIds": ["s1"]
+        });
+
+        apply(
+            &db_path,
+            &[
+                event(7, "contract_update", contract),
+                event(
+                    9,
+                    "conflict_detected",
+                    json!({ "id": "x1", "entityId": "c1" }),
+                ),
+                event(
+                    12,
+                    "contract_update",
+                    json!({ "id": "c1", "lastEdit": 2, "title": "Buche" }),
+                ),
+            ],
+        )
+        .unwrap();
+
+        let core_storage = Arc::new(CoreLocalStorage::new(&db_path).unwrap());
+        let row = core_storage.get_by_id("contracts", "c1").unwrap().remove(0);
+        assert_eq!(row["title"], "Buche");
+        assert_eq!(row["lastEdit"], 2);
+        assert_eq!(row["availableQuantity"], 10.0);
+        assert_eq!(row["arrivalAtServer"], 1012);
+
+        let event_log = EventLogLocalStorage::new(core_storage).unwrap();
+        assert_eq!(event_log.get_last_seq().unwrap(), 12);
+        let history = event_log
+            .get_entity_history("contract_update", "c1", 0, 10)
+            .unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["provenance"]["deviceId"], "d1");
+
+        fs::remove_file(&db_path).unwrap();
+    }
+
+    #[test]
+    fn failed_pages_leave_the_standby_unchanged() {
+        let db_path = standby_db();
+        let result = apply(
+            &db_path,
+            &[
+                event(
+                    1,
+                    "sawmill_update",
+                    json!({ "id": "s1", "name": "Nord", "lastEdit": 1 }),
+                ),
+                event(
+                    2,
+                    "photo_update",
+                    json!({ "id": "p1", "photoFile": [1, 300] }),
+                ),
+            ],
+        );
+
+        assert!(result.is_err());
+        let core_storage = Arc::new(CoreLocalStorage::new(&db_path).unwrap());
+        assert!(core_storage.get_by_id("sawmills", "s1").unwrap().is_empty());
+        assert_eq!(
+            EventLogLocalStorage::new(core_storage)
+                .unwrap()
+                .get_last_seq()
+                .unwrap(),
+            0
+        );
+
+        fs::remove_file(&db_path).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+use crate::db_pool::{self, AcquireError};
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::user::user_local_storage::UserLocalStorage;
+use crate::replication;
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use crate::write_batch;
+use crate::{DbPoolMap, database_exists, get_db_path, get_db_pool};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use warp::http::header::{ETAG, IF_NONE_MATCH};
+use warp::http::{HeaderValue, Method, StatusCode};
+use warp::hyper::Body;
+use warp::hyper::body::HttpBody;
+use warp::reply::{Json, Response, WithStatus};
+use warp::{Filter, Rejection, Reply};
+
+pub struct RestUser {
+    pub tenant: String,
+    pub user_id: String,
+    pub role: i64,
+    pub scopes: Option<Vec<String>>,
+}
+
+impl RestUser {
+    pub fn require_read(&self, table: &str) -> Result<(), ErrorReply> {
+        match &self.scopes {
+            Some(scopes) if !scopes.iter().any(|scope| scope == table) => Err(error_reply(
+                StatusCode::FORBIDDEN,
+                "PERMISSION_DENIED",
+                &format!("Token has no read scope for {}", table),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub type ErrorReply = WithStatus<Json>;
+
+/// Largest JSON request body the REST routes read; bigger bodies, and bodies
+/// without a Content-Length, are rejected before anything is buffered.
+pub const MAX_JSON_BODY_BYTES: u64 = 1024 * 1024;
+/// GeoJSON imports carry whole location sets, so they get more room.
+pub const MAX_IMPORT_BODY_BYTES: u64 = 16 * 1024 * 1024;
+
+pub fn json_body(limit: u64) -> impl Filter<Extract = (Value,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(limit).and(warp::body::json::<Value>())
+}
+
+pub fn error_reply(status: StatusCode, code: &str, message: &str) -> ErrorReply {
+    warp::reply::with_status(
+        warp::reply::json(&json!({
+            "error": {
+                "code": code,
+                "message": message
+            }
+        })),
+        status,
+    )
+}
+
+pub fn json_reply(value: &Value) -> ErrorReply {
+    warp::reply::with_status(warp::reply::json(value), StatusCode::OK)
+}
+
+pub fn authenticate(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+    min_role: i64,
+) -> Result<RestUser, ErrorReply> {
+    let unauthorized = || {
+        error_reply(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Missing or invalid API key",
+        )
+    };
+
+    let api_key = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or_else(unauthorized)?;
+
+    let (tenant, user_id) = api_key.split_once('-').ok_or_else(unauthorized)?;
+
+    let core_storage = tenant_storage(tenant, db_pools)?.ok_or_else(unauthorized)?;
+
+    let user = UserLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_user_by_id(user_id))
+        .map_err(|e| {
+            println!("Failed to get user: {:?}", e);
+            error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Database unavailable",
+            )
+        })?
+        .ok_or_else(unauthorized)?;
+
+    let role = user.get("role").and_then(|v| v.as_i64()).unwrap_or(0);
+    if role < min_role {
+        return Err(error_reply(
+            StatusCode::FORBIDDEN,
+            "PERMISSION_DENIED",
+            "Insufficient role for this endpoint",
+        ));
+    }
+
+    Ok(RestUser {
+        tenant: tenant.to_string(),
+        user_id: user_id.to_string(),
+        role,
+        scopes: None,
+    })
+}
+
+pub fn tenant_storage(
+    tenant: &str,
+    db_pools: &DbPoolMap,
+) -> Result<Option<Arc<CoreLocalStorage>>, ErrorReply> {
+    if !database_exists(tenant) {
+        return Ok(None);
+    }
+    if tenant_drain::is_draining(tenant) {
+        return Err(error_reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "TENANT_DRAINING",
+            "Tenant is under maintenance",
+        ));
+    }
+    if replication::is_standby() {
+        return Err(error_reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "STANDBY",
+            "Server is a standby",
+        ));
+    }
+
+    let unavailable = || {
+        error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Database unavailable",
+        )
+    };
+    tenant_hibernation::wake(tenant);
+    let pool = get_db_pool(tenant, db_pools).map_err(|e| {
+        println!("Failed to get database pool: {:?}", e);
+        unavailable()
+    })?;
+    match db_pool::acquire(tenant, &pool) {
+        Ok(_) => {}
+        Err(AcquireError::Busy) => {
+            return Err(error_reply(
+                StatusCode::SERVICE_UNAVAILABLE,
+                db_pool::SERVER_BUSY,
+                "Server is busy, try again shortly",
+            ));
+        }
+        Err(AcquireError::Failed(_)) => return Err(unavailable()),
+    }
+
+    open_storage(tenant)
+        .map(Some)
+        .map_err(|e| internal_error("Database unavailable", e))
+}
+
+pub fn open_storage(tenant: &str) -> rusqlite::Result<Arc<CoreLocalStorage>> {
+    let db_path = get_db_path(tenant);
+    if let Some(storage) = write_batch::storage_for(&db_path) {
+        return Ok(storage);
+    }
+
+    Ok(Arc::new(CoreLocalStorage::new(&db_path)?))
+}
+
+pub fn internal_error(context: &str, e: rusqlite::Error) -> ErrorReply {
+    println!("{}: {:?}", context, e);
+    error_reply(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL", context)
+}
+
+pub fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let last = size.checked_sub(1)?;
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => (size - suffix.parse::<u64>().ok()?.min(size), last),
+        (start, "") => (start.parse().ok()?, last),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(last)),
+    };
+
+    (start <= end && end < size).then_some((start, end))
+}
+
+pub fn into_reply<T: Reply + 'static>(result: Result<T, ErrorReply>) -> Box<dyn Reply> {
+    match result {
+        Ok(reply) => Box::new(reply),
+        Err(reply) => Box::new(reply),
+    }
+}
+
+/// Adds a strong ETag to successful GET responses and answers a matching
+/// If-None-Match with 304 Not Modified. The tag is a hash of the rendered
+/// body, so the handler still runs its queries and renders the response on
+/// every request; a 304 only saves sending the body. Handlers that can tell
+/// the version of their data up front set their own ETag, which is kept
+/// as is and not buffered.
+pub fn with_etag<F, R>(
+    filter: F,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    warp::method()
+        .and(warp::header::optional::<String>(IF_NONE_MATCH.as_str()))
+        .and(filter)
+        .and_then(
+            |method: Method, if_none_match: Option<String>, reply: R| async move {
+                let response =
+                    conditional_response(&method, if_none_match, reply.into_response()).await;
+                Ok::<_, Rejection>(Box::new(response) as Box<dyn Reply>)
+            },
+        )
+}
+
+async fn conditional_response(
+    method: &Method,
+    if_none_match: Option<String>,
+    response: Response,
+) -> Response {
+    if method != Method::GET
+        || response.status() != StatusCode::OK
+        || response.body().size_hint().exact().is_none()
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match parts.headers.get(ETAG) {
+        Some(_) => body,
+        None => {
+            let bytes = match warp::hyper::body::to_bytes(body).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    println!("Failed to buffer response body: {:?}", e);
+                    return error_reply(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "INTERNAL",
+                        "Failed to render response",
+                    )
+                    .into_response();
+                }
+            };
+            if let Ok(etag) = HeaderValue::from_str(&format!("\"{:016x}\"", fnv1a(&bytes))) {
+                parts.headers.insert(ETAG, etag);
+            }
+            Body::from(bytes)
+        }
+    };
+
+    match parts.headers.get(ETAG).cloned() {
+        Some(etag) if etag_matches(if_none_match.as_deref(), &etag) => {
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.clear();
+            parts.headers.insert(ETAG, etag);
+            Response::from_parts(parts, Body::empty())
+        }
+        _ => Response::from_parts(parts, body),
+    }
+}
+
+fn etag_matches(if_none_match: Option<&str>, etag: &HeaderValue) -> bool {
+    let Some(if_none_match) = if_none_match else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or_default();
+
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag.trim_start_matches("W/")
+    })
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,150 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::review_item::review_item_local_storage::{
+    ReviewItemLocalStorage, STATUS_APPROVED, STATUS_OPEN, STATUS_REJECTED,
+};
+use crate::rest::{self, ErrorReply};
+use crate::shipment_reversal;
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use uuid::Uuid;
+use warp::{Filter, Rejection};
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    warp::path("review-queue")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            list_review_queue(authorization, &db_pools).unwrap_or_else(|reply| reply)
+        })
+}
+
+fn list_review_queue(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let mut items = ReviewItemLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_review_items_by_status(STATUS_OPEN))
+        .map_err(|e| rest::internal_error("Failed to load review queue", e))?;
+
+    for item in items.iter_mut() {
+        if item["entityType"].as_str() == Some("shipment") {
+            let shipment = core_storage
+                .get_existing_by_id("shipments", item["entityId"].as_str().unwrap_or_default())
+                .map_err(|e| rest::internal_error("Failed to load review queue", e))?
+                .into_iter()
+                .next();
+            item["data"] = shipment.unwrap_or(Value::Null);
+        }
+    }
+
+    Ok(rest::json_reply(&json!({ "items": items })))
+}
+
+pub fn open_review_item(
+    core_storage: Arc<CoreLocalStorage>,
+    entity_type: &str,
+    entity_id: &str,
+    reason: &str,
+) -> rusqlite::Result<Option<Value>> {
+    let review_item_storage = ReviewItemLocalStorage::new(core_storage)?;
+    if review_item_storage
+        .get_open_review_item(entity_type, entity_id)?
+        .is_some()
+    {
+        return Ok(None);
+    }
+
+    let review_item = json!({
+        "id": Uuid::new_v4().to_string(),
+        "lastEdit": chrono::Utc::now().timestamp_millis(),
+        "entityType": entity_type,
+        "entityId": entity_id,
+        "reason": reason,
+        "status": STATUS_OPEN,
+        "reviewerId": null,
+        "deleted": 0
+    });
+    review_item_storage.save_review_item(&review_item)?;
+
+    review_item_storage.get_review_item_by_id(review_item["id"].as_str().unwrap_or_default())
+}
+
+pub fn decide_review_item(
+    core_storage: Arc<CoreLocalStorage>,
+    review_item_id: &str,
+    approve: bool,
+    reviewer_id: &str,
+) -> Result<Vec<(&'static str, Value)>, String> {
+    let review_item_storage = ReviewItemLocalStorage::new(core_storage.clone())
+        .map_err(|e| format!("Failed to create review item storage: {:?}", e))?;
+
+    let mut review_item = review_item_storage
+        .get_review_item_by_id(review_item_id)
+        .map_err(|e| format!("Failed to load review item: {:?}", e))?
+        .ok_or_else(|| format!("Review item {} not found", review_item_id))?;
+
+    if review_item["status"].as_str() != Some(STATUS_OPEN) {
+        return Err(format!("Review item {} is already closed", review_item_id));
+    }
+
+    let entity_type = review_item["entityType"].as_str().unwrap_or_default();
+    let entity_id = review_item["entityId"].as_str().unwrap_or_default();
+    let mut updates = match (entity_type, approve) {
+        ("shipment", true) => clear_shipment_flag(core_storage.clone(), entity_id)
+            .map_err(|e| format!("Failed to clear shipment flag: {:?}", e))?,
+        ("shipment", false) => shipment_reversal::reverse_shipment(core_storage.clone(), entity_id)
+            .map_err(|e| format!("Failed to reverse shipment: {:?}", e))?,
+        _ => Vec::new(),
+    };
+
+    review_item["status"] = json!(if approve {
+        STATUS_APPROVED
+    } else {
+        STATUS_REJECTED
+    });
+    review_item["reviewerId"] = json!(reviewer_id);
+    review_item["lastEdit"] = json!(chrono::Utc::now().timestamp_millis());
+    review_item_storage
+        .save_review_item(&review_item)
+        .map_err(|e| format!("Failed to save review item: {:?}", e))?;
+
+    let review_item = review_item_storage
+        .get_review_item_by_id(review_item_id)
+        .map_err(|e| format!("Failed to load review item: {:?}", e))?
+        .unwrap_or(review_item);
+    updates.push(("review_item_update", review_item));
+
+    Ok(updates)
+}
+
+fn clear_shipment_flag(
+    core_storage: Arc<CoreLocalStorage>,
+    shipment_id: &str,
+) -> rusqlite::Result<Vec<(&'static str, Value)>> {
+    let mut shipment = match core_storage
+        .get_existing_by_id("shipments", shipment_id)?
+        .into_iter()
+        .next()
+    {
+        Some(shipment) => shipment,
+        None => return Ok(Vec::new()),
+    };
+
+    let now = chrono::Utc::now().timestamp_millis();
+    shipment["reviewStatus"] = Value::Null;
+    shipment["reviewReason"] = Value::Null;
+    shipment["lastEdit"] = json!(now);
+    shipment["arrivalAtServer"] = json!(now);
+    core_storage.update("shipments", &shipment)?;
+
+    Ok(vec![("shipment_update", shipment)])
+}
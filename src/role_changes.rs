@@ -0,0 +1,91 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::user::user_local_storage::UserLocalStorage;
+use crate::metrics;
+use crate::wire_format;
+use crate::{Clients, ROLE_PRIVILEGED};
+use serde_json::json;
+use std::sync::Arc;
+use warp::ws::Message;
+
+const CLOSE_CODE_SESSION_REVOKED: u16 = 4003;
+
+pub fn propagate(
+    tenant: &str,
+    user_id: &str,
+    core_storage: Arc<CoreLocalStorage>,
+    clients: &Clients,
+) {
+    let user = match UserLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_user_by_id(user_id))
+    {
+        Ok(user) => user,
+        Err(e) => {
+            println!(
+                "Failed to load user {} for role propagation: {:?}",
+                user_id, e
+            );
+            return;
+        }
+    };
+    let role = user.map(|user| user["role"].as_i64().unwrap_or(0));
+
+    let Ok(mut clients_lock) = clients.lock() else {
+        return;
+    };
+    for (client_id, client) in clients_lock
+        .iter_mut()
+        .filter(|(_, client)| client.db_name == tenant && client.user_id == user_id)
+    {
+        let previous_role = client.role;
+        let revoke = match role {
+            None => Some("User has been deactivated"),
+            Some(role)
+                if previous_role >= ROLE_PRIVILEGED
+                    && role < ROLE_PRIVILEGED
+                    && client.sync_completed =>
+            {
+                Some("Role no longer permits previously synced data")
+            }
+            Some(_) => None,
+        };
+
+        if let Some(reason) = revoke {
+            let frame = json!({
+                "type": "session_revoked",
+                "data": { "reason": reason, "role": role },
+                "timestamp": chrono::Utc::now().timestamp_millis()
+            });
+            let _ = client
+                .sender
+                .send(wire_format::frame(&frame, &client.msgpack));
+            let _ = client
+                .sender
+                .send(Message::close_with(CLOSE_CODE_SESSION_REVOKED, reason));
+            client.disconnect.notify_one();
+            client.role = role.unwrap_or(0).min(previous_role);
+            metrics::increment("ws_sessions_revoked_total");
+            println!(
+                "Revoked session {} of user {} in {}: {}",
+                client_id, user_id, tenant, reason
+            );
+            continue;
+        }
+
+        let Some(role) = role.filter(|role| *role != previous_role) else {
+            continue;
+        };
+        client.role = role;
+        let frame = json!({
+            "type": "role_changed",
+            "data": { "role": role, "previousRole": previous_role },
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        let _ = client
+            .sender
+            .send(wire_format::frame(&frame, &client.msgpack));
+        println!(
+            "Changed role of session {} of user {} in {} from {} to {}",
+            client_id, user_id, tenant, previous_role, role
+        );
+    }
+}
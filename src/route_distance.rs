@@ -0,0 +1,144 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::metrics;
+use serde_json::{Value, json};
+use std::sync::{Arc, LazyLock};
+use tokio::time::{Duration, timeout};
+
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+#[derive(Clone, Copy)]
+struct Point {
+    latitude: f64,
+    longitude: f64,
+}
+
+fn point_of(
+    core_storage: &CoreLocalStorage,
+    table: &str,
+    id: &str,
+) -> rusqlite::Result<Option<Point>> {
+    let row = core_storage
+        .get_columns_by_id(table, id, &["latitude", "longitude"])?
+        .into_iter()
+        .next();
+
+    Ok(row.and_then(|row| {
+        Some(Point {
+            latitude: row["latitude"].as_f64()?,
+            longitude: row["longitude"].as_f64()?,
+        })
+    }))
+}
+
+fn great_circle_km(from: Point, to: Point) -> f64 {
+    let (from_lat, to_lat) = (from.latitude.to_radians(), to.latitude.to_radians());
+    let delta_lat = to_lat - from_lat;
+    let delta_lon = (to.longitude - from.longitude).to_radians();
+
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + from_lat.cos() * to_lat.cos() * (delta_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+async fn routed_km(url: &str, from: Point, to: Point) -> Result<f64, String> {
+    let url = url
+        .replace("{fromLat}", &from.latitude.to_string())
+        .replace("{fromLon}", &from.longitude.to_string())
+        .replace("{toLat}", &to.latitude.to_string())
+        .replace("{toLon}", &to.longitude.to_string());
+
+    let request = async {
+        let response = CLIENT
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| format!("Routing service unreachable: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Routing service answered {}", response.status()));
+        }
+        response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read routing response: {}", e))
+    };
+    let body = timeout(
+        Duration::from_secs(config::get().route_distance.timeout_secs),
+        request,
+    )
+    .await
+    .map_err(|_| "Routing service timed out".to_string())??;
+
+    let response: Value = serde_json::from_str(&body)
+        .map_err(|e| format!("Routing service returned invalid JSON: {}", e))?;
+    response["distanceKm"]
+        .as_f64()
+        .or_else(|| {
+            response["routes"][0]["distance"]
+                .as_f64()
+                .map(|m| m / 1000.0)
+        })
+        .filter(|km| km.is_finite() && *km >= 0.0)
+        .ok_or_else(|| "Routing response contains no distance".to_string())
+}
+
+fn round_km(km: f64) -> f64 {
+    (km * 100.0).round() / 100.0
+}
+
+/// Whether distances are fetched from a routing service rather than
+/// computed locally.
+pub fn is_routed() -> bool {
+    !config::get().route_distance.routing_url.is_empty()
+}
+
+pub async fn apply(shipment: &mut Value, core_storage: Arc<CoreLocalStorage>) {
+    if !shipment.is_object() {
+        return;
+    }
+    shipment["distanceKm"] = Value::Null;
+    shipment["distanceSource"] = Value::Null;
+
+    let location_id = shipment["locationId"].as_str().unwrap_or_default();
+    let sawmill_id = shipment["sawmillId"].as_str().unwrap_or_default();
+    let points = point_of(&core_storage, "locations", location_id)
+        .and_then(|from| Ok(from.zip(point_of(&core_storage, "sawmills", sawmill_id)?)));
+    let (from, to) = match points {
+        Ok(Some(points)) => points,
+        Ok(None) => {
+            metrics::increment("route_distance_missing_coordinates_total");
+            return;
+        }
+        Err(e) => {
+            println!("Failed to load shipment route coordinates: {:?}", e);
+            return;
+        }
+    };
+
+    let routing_url = &config::get().route_distance.routing_url;
+    let (km, source) = if routing_url.is_empty() {
+        (great_circle_km(from, to), "greatCircle")
+    } else {
+        match routed_km(routing_url, from, to).await {
+            Ok(km) => (km, "routing"),
+            Err(e) => {
+                metrics::increment("route_distance_routing_failed_total");
+                println!(
+                    "Falling back to great-circle distance for shipment {}: {}",
+                    shipment["id"], e
+                );
+                (great_circle_km(from, to), "greatCircle")
+            }
+        }
+    };
+
+    metrics::increment(&format!("route_distance_total{{source=\"{}\"}}", source));
+    println!(
+        "Shipment {} from location {} to sawmill {}: {:.2} km ({})",
+        shipment["id"], location_id, sawmill_id, km, source
+    );
+    shipment["distanceKm"] = json!(round_km(km));
+    shipment["distanceSource"] = json!(source);
+}
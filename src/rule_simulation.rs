@@ -0,0 +1,228 @@
+use crate::contract_allowlist::{self, Violation};
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use crate::{
+    assortments, coordinates, database_exists, get_db_path, plausibility, pricing, scripting,
+    units, update_schema,
+};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+const SAMPLES_PER_RULE: usize = 20;
+const SIMULATED_TYPES: &[(&str, &str)] = &[
+    ("contract_update", "contracts"),
+    ("sawmill_update", "sawmills"),
+    ("location_update", "locations"),
+    ("shipment_update", "shipments"),
+];
+
+pub struct SimulationOptions {
+    pub tenant: String,
+    pub strict: bool,
+}
+
+impl SimulationOptions {
+    pub fn new(tenant: &str) -> Self {
+        SimulationOptions {
+            tenant: tenant.to_string(),
+            strict: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+    Reject,
+    Flag,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Reject => "reject",
+            Outcome::Flag => "flag",
+        }
+    }
+}
+
+#[derive(Default)]
+struct RuleTally {
+    count: usize,
+    samples: Vec<Value>,
+}
+
+pub fn run(options: &SimulationOptions) -> Result<Value, String> {
+    let started = Instant::now();
+    if !database_exists(&options.tenant) {
+        return Err(format!("Tenant {} does not exist", options.tenant));
+    }
+
+    let db_path = get_db_path(&options.tenant);
+    let core_storage = Arc::new(
+        CoreLocalStorage::new(&db_path)
+            .map_err(|e| format!("Failed to open {}: {}", db_path, e))?,
+    );
+    core_storage
+        .get_connection()
+        .and_then(|conn| conn.pragma_update(None, "query_only", true))
+        .map_err(|e| format!("Failed to open {} read-only: {}", db_path, e))?;
+
+    let mut checked = BTreeMap::new();
+    let mut tallies: BTreeMap<(&str, &str, &str), RuleTally> = BTreeMap::new();
+    let (mut rejected_records, mut flagged_records) = (0, 0);
+
+    for (msg_type, table) in SIMULATED_TYPES {
+        let records = load_records(table, core_storage.clone())
+            .map_err(|e| format!("Failed to load {}: {}", table, e))?;
+        checked.insert(*table, records.len());
+
+        for record in records {
+            let findings = evaluate(msg_type, &record, core_storage.clone(), options.strict);
+            if findings
+                .iter()
+                .any(|(_, outcome, _)| *outcome == Outcome::Reject)
+            {
+                rejected_records += 1;
+            } else if !findings.is_empty() {
+                flagged_records += 1;
+            }
+
+            for (rule, outcome, reason) in findings {
+                let tally = tallies.entry((*table, rule, outcome.as_str())).or_default();
+                tally.count += 1;
+                if tally.samples.len() < SAMPLES_PER_RULE {
+                    tally
+                        .samples
+                        .push(json!({ "id": record["id"], "reason": reason }));
+                }
+            }
+        }
+    }
+
+    let rules: Vec<Value> = tallies
+        .into_iter()
+        .map(|((entity, rule, outcome), tally)| {
+            json!({
+                "entity": entity,
+                "rule": rule,
+                "outcome": outcome,
+                "count": tally.count,
+                "samples": tally.samples
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "tenant": options.tenant,
+        "strict": options.strict,
+        "checked": checked,
+        "rejectedRecords": rejected_records,
+        "flaggedRecords": flagged_records,
+        "rules": rules,
+        "elapsedMs": started.elapsed().as_millis() as u64
+    }))
+}
+
+fn load_records(table: &str, core_storage: Arc<CoreLocalStorage>) -> rusqlite::Result<Vec<Value>> {
+    let ids = core_storage
+        .get_connection()?
+        .prepare(&format!(
+            "SELECT id FROM \"{}\" WHERE deleted = 0 ORDER BY id",
+            table
+        ))?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let location_storage = LocationLocalStorage::new(core_storage.clone())?;
+    let mut records = Vec::with_capacity(ids.len());
+    for id in ids {
+        if table == "locations" {
+            records.push(location_storage.get_location_by_id(&id)?);
+        } else if let Some(record) = core_storage.get_by_id(table, &id)?.into_iter().next() {
+            records.push(record);
+        }
+    }
+
+    Ok(records)
+}
+
+fn evaluate(
+    msg_type: &str,
+    record: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+    strict: bool,
+) -> Vec<(&'static str, Outcome, String)> {
+    let mut findings = Vec::new();
+
+    let annotated = match scripting::apply_hooks(msg_type, record, core_storage.clone()) {
+        Ok(annotated) => annotated,
+        Err(reason) => {
+            findings.push(("hookScript", Outcome::Reject, reason));
+            None
+        }
+    };
+    let mut entity = annotated.unwrap_or_else(|| record.clone());
+
+    if let Err(errors) = update_schema::validate(msg_type, &entity, core_storage.clone()) {
+        findings.push(("schema", Outcome::Reject, update_schema::summarize(&errors)));
+    }
+
+    let mut reject = |rule: &'static str, result: Result<(), String>| {
+        if let Err(reason) = result {
+            findings.push((rule, Outcome::Reject, reason));
+        }
+    };
+    match msg_type {
+        "contract_update" => reject(
+            "pricing",
+            pricing::validate_contract_pricing(&mut entity, core_storage.clone()),
+        ),
+        "sawmill_update" => reject("coordinates", coordinates::check_sawmill(&entity)),
+        "location_update" => {
+            reject("coordinates", coordinates::normalize_location(&mut entity));
+            reject(
+                "units",
+                units::normalize_quantities(msg_type, &mut entity, core_storage.clone()),
+            );
+            reject(
+                "assortments",
+                assortments::validate_location(&entity, core_storage.clone()),
+            );
+        }
+        "shipment_update" => {
+            reject(
+                "units",
+                units::normalize_quantities(msg_type, &mut entity, core_storage.clone()),
+            );
+            reject(
+                "assortments",
+                assortments::check_shipment(&mut entity, core_storage.clone()),
+            );
+
+            match contract_allowlist::check_shipment(&entity, core_storage.clone()) {
+                Ok(Some(Violation::Reject(reason))) => {
+                    findings.push(("contractAllowlist", Outcome::Reject, reason))
+                }
+                Ok(Some(Violation::Flag(reason))) => findings.push((
+                    "contractAllowlist",
+                    if strict {
+                        Outcome::Reject
+                    } else {
+                        Outcome::Flag
+                    },
+                    reason,
+                )),
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to check sawmill allowlist: {}", e),
+            }
+            if let Some(reason) = plausibility::check_shipment(&entity) {
+                findings.push(("plausibility", Outcome::Flag, reason));
+            }
+        }
+        _ => {}
+    }
+
+    findings
+}
@@ -0,0 +1,297 @@
+use crate::local_storage::core_table::{Table, column_info};
+use crate::local_storage::schema;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use rusqlite::Connection;
+use serde_json::{Value, json};
+use warp::{Filter, Rejection, Reply};
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let json = warp::path!("admin" / "schema")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(
+                describe(authorization, &db_pools).map(|document| rest::json_reply(&document)),
+            )
+        });
+
+    let markdown = warp::path!("admin" / "schema.md")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(describe(authorization, &db_pools).map(|document| {
+                warp::reply::with_header(
+                    to_markdown(&document),
+                    "Content-Type",
+                    "text/markdown; charset=utf-8",
+                )
+            }))
+        });
+
+    let html = warp::path!("admin" / "schema.html")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(
+                describe(authorization, &db_pools)
+                    .map(|document| warp::reply::html(to_html(&document))),
+            )
+        });
+
+    json.or(markdown).unify().or(html).unify()
+}
+
+fn describe(authorization: Option<String>, db_pools: &DbPoolMap) -> Result<Value, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let conn = core_storage
+        .get_connection()
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let tables = schema::TABLES
+        .iter()
+        .map(|table| describe_table(&conn, table))
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| rest::internal_error("Failed to read schema", e))?;
+    let undocumented =
+        undocumented_tables(&conn).map_err(|e| rest::internal_error("Failed to read schema", e))?;
+
+    Ok(json!({
+        "tenant": user.tenant,
+        "generatedAt": chrono::Utc::now().timestamp_millis(),
+        "tables": tables,
+        "undocumentedTables": undocumented
+    }))
+}
+
+/// Declared columns of a table module merged with what the tenant database
+/// actually has. References are declared foreign keys plus `<entity>Id`
+/// columns that name another table.
+fn describe_table(conn: &Connection, table: &Table) -> rusqlite::Result<Value> {
+    let live = column_info(conn, table.name)?;
+    let foreign_keys = foreign_keys(conn, table.name)?;
+
+    let mut columns: Vec<Value> = table
+        .columns
+        .iter()
+        .map(|column| {
+            let info = live.iter().find(|info| info.name == column.name);
+            let references = foreign_keys
+                .iter()
+                .find(|(from, _)| from == column.name)
+                .map(|(_, to)| json!({ "table": to, "declared": true }))
+                .or_else(|| {
+                    referenced_table(column.name)
+                        .map(|to| json!({ "table": to, "declared": false }))
+                });
+            json!({
+                "name": column.name,
+                "type": info.map(|info| info.declared_type.as_str()).unwrap_or(column.declared_type()),
+                "notNull": column.not_null(),
+                "primaryKey": column.definition.contains("PRIMARY KEY"),
+                "present": info.is_some(),
+                "references": references
+            })
+        })
+        .collect();
+    columns.extend(
+        live.iter()
+            .filter(|info| table.column(&info.name).is_none())
+            .map(|info| {
+                json!({
+                    "name": info.name,
+                    "type": info.declared_type,
+                    "notNull": info.not_null,
+                    "primaryKey": false,
+                    "present": true,
+                    "undeclared": true
+                })
+            }),
+    );
+
+    let rows = if live.is_empty() {
+        None
+    } else {
+        Some(conn.query_row(
+            &format!("SELECT COUNT(*) FROM \"{}\"", table.name),
+            [],
+            |row| row.get::<_, i64>(0),
+        )?)
+    };
+
+    Ok(json!({
+        "name": table.name,
+        "columns": columns,
+        "constraints": table.constraints,
+        "rows": rows
+    }))
+}
+
+fn foreign_keys(conn: &Connection, table_name: &str) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA foreign_key_list(\"{}\")", table_name))?;
+    let keys = stmt.query_map([], |row| Ok((row.get::<_, String>(3)?, row.get(2)?)))?;
+
+    keys.collect()
+}
+
+fn referenced_table(column: &str) -> Option<&'static str> {
+    let entity = column
+        .strip_suffix("Id")
+        .filter(|entity| !entity.is_empty())?;
+    let plural = format!("{}s", entity);
+    schema::TABLES
+        .iter()
+        .map(|table| table.name)
+        .find(|name| *name == plural)
+}
+
+fn undocumented_tables(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table'
+         AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(names
+        .into_iter()
+        .filter(|name| schema::table(name).is_none())
+        .collect())
+}
+
+fn column_notes(column: &Value) -> Vec<String> {
+    let mut notes = Vec::new();
+    if column["primaryKey"] == true {
+        notes.push("primary key".to_string());
+    }
+    if column["notNull"] == true {
+        notes.push("required".to_string());
+    }
+    if let Some(table) = column["references"]["table"].as_str() {
+        notes.push(format!("references {}", table));
+    }
+    if column["present"] == false {
+        notes.push("missing in database".to_string());
+    }
+    if column["undeclared"] == true {
+        notes.push("not declared by the server".to_string());
+    }
+    notes
+}
+
+fn to_markdown(document: &Value) -> String {
+    let mut markdown = format!(
+        "# Database schema of {}\n",
+        document["tenant"].as_str().unwrap_or_default()
+    );
+
+    for table in document["tables"].as_array().into_iter().flatten() {
+        markdown.push_str(&format!(
+            "\n## {}\n\n",
+            table["name"].as_str().unwrap_or_default()
+        ));
+        match table["rows"].as_i64() {
+            Some(rows) => markdown.push_str(&format!("{} row(s)\n\n", rows)),
+            None => markdown.push_str("Not created yet\n\n"),
+        }
+        markdown.push_str("| Column | Type | Notes |\n|---|---|---|\n");
+        for column in table["columns"].as_array().into_iter().flatten() {
+            markdown.push_str(&format!(
+                "| {} | {} | {} |\n",
+                column["name"].as_str().unwrap_or_default(),
+                column["type"].as_str().unwrap_or_default(),
+                column_notes(column).join(", ")
+            ));
+        }
+        for constraint in table["constraints"].as_array().into_iter().flatten() {
+            markdown.push_str(&format!(
+                "\nConstraint: `{}`\n",
+                constraint.as_str().unwrap_or_default()
+            ));
+        }
+    }
+
+    let undocumented: Vec<&str> = document["undocumentedTables"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    if !undocumented.is_empty() {
+        markdown.push_str(&format!(
+            "\n## Other tables\n\n{}\n",
+            undocumented.join(", ")
+        ));
+    }
+
+    markdown
+}
+
+fn to_html(document: &Value) -> String {
+    let tenant = escape(document["tenant"].as_str().unwrap_or_default());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Database schema of {0}</title></head>\n<body>\n<h1>Database schema of {0}</h1>\n",
+        tenant
+    );
+
+    for table in document["tables"].as_array().into_iter().flatten() {
+        html.push_str(&format!(
+            "<h2>{}</h2>\n",
+            escape(table["name"].as_str().unwrap_or_default())
+        ));
+        match table["rows"].as_i64() {
+            Some(rows) => html.push_str(&format!("<p>{} row(s)</p>\n", rows)),
+            None => html.push_str("<p>Not created yet</p>\n"),
+        }
+        html.push_str(
+            "<table border=\"1\">\n<tr><th>Column</th><th>Type</th><th>Notes</th></tr>\n",
+        );
+        for column in table["columns"].as_array().into_iter().flatten() {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(column["name"].as_str().unwrap_or_default()),
+                escape(column["type"].as_str().unwrap_or_default()),
+                escape(&column_notes(column).join(", "))
+            ));
+        }
+        html.push_str("</table>\n");
+        for constraint in table["constraints"].as_array().into_iter().flatten() {
+            html.push_str(&format!(
+                "<p>Constraint: <code>{}</code></p>\n",
+                escape(constraint.as_str().unwrap_or_default())
+            ));
+        }
+    }
+
+    let undocumented: Vec<String> = document["undocumentedTables"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(escape)
+        .collect();
+    if !undocumented.is_empty() {
+        html.push_str(&format!(
+            "<h2>Other tables</h2>\n<p>{}</p>\n",
+            undocumented.join(", ")
+        ));
+    }
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,106 @@
+use crate::config;
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_ADMIN, get_db_path, with_db_pools};
+use rusqlite::Connection;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+use warp::{Filter, Rejection};
+
+pub fn check_all_tenants() {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    let repair = config::get().schema.auto_repair;
+    let mut drifted_tenants = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+
+        match check_tenant(tenant, &path, repair) {
+            Ok(report) if report["drift"].as_array().is_some_and(|d| !d.is_empty()) => {
+                drifted_tenants += 1;
+                eprintln!("Schema drift in tenant {}: {}", tenant, report);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to check schema of tenant {}: {:?}", tenant, e),
+        }
+    }
+
+    metrics::set_gauge("schema_drift_tenants", drifted_tenants);
+    println!(
+        "Schema check finished, {} tenant(s) with drift",
+        drifted_tenants
+    );
+}
+
+fn check_tenant(tenant: &str, path: &Path, repair: bool) -> rusqlite::Result<Value> {
+    let conn = Connection::open(path)?;
+    let drifts = schema::detect_drift(&conn)?;
+
+    let repairs = if repair {
+        schema::repair_additive(&conn, &drifts)
+    } else {
+        Vec::new()
+    };
+    let remaining = if repairs.is_empty() {
+        drifts
+    } else {
+        schema::detect_drift(&conn)?
+    };
+
+    Ok(json!({
+        "tenant": tenant,
+        "drift": remaining.iter().map(|drift| drift.to_json()).collect::<Vec<_>>(),
+        "repairs": repairs
+    }))
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    let report = warp::path!("admin" / "schema-drift")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            tenant_report(authorization, &db_pools, false).unwrap_or_else(|reply| reply)
+        });
+
+    let repair = warp::path!("admin" / "schema-drift" / "repair")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            tenant_report(authorization, &db_pools, true).unwrap_or_else(|reply| reply)
+        });
+
+    report.or(repair).unify()
+}
+
+fn tenant_report(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+    repair: bool,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let db_path = get_db_path(&user.tenant);
+
+    let report = check_tenant(&user.tenant, Path::new(&db_path), repair)
+        .map_err(|e| rest::internal_error("Failed to check schema", e))?;
+
+    Ok(rest::json_reply(&report))
+}
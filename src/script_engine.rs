@@ -0,0 +1,790 @@
+use serde_json::{Value, json};
+use std::time::Instant;
+
+pub struct Limits {
+    pub max_steps: usize,
+    pub max_string_len: usize,
+    pub deadline: Instant,
+}
+
+pub enum Outcome {
+    Rejected(String),
+    Annotated(Vec<(String, Value)>),
+}
+
+pub struct Script {
+    rules: Vec<Rule>,
+}
+
+enum Rule {
+    Reject {
+        message: Expr,
+        condition: Expr,
+    },
+    Set {
+        field: String,
+        value: Expr,
+        condition: Option<Expr>,
+    },
+}
+
+enum Expr {
+    Literal(Value),
+    Field(Vec<String>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+const FUNCTIONS: &[(&str, usize, usize)] = &[
+    ("len", 1, 1),
+    ("digits", 1, 1),
+    ("digit_sum", 1, 1),
+    ("int", 1, 1),
+    ("str", 1, 1),
+    ("lower", 1, 1),
+    ("upper", 1, 1),
+    ("trim", 1, 1),
+    ("substr", 2, 3),
+    ("starts_with", 2, 2),
+    ("ends_with", 2, 2),
+    ("contains", 2, 2),
+    ("is_number", 1, 1),
+    ("abs", 1, 1),
+    ("round", 1, 2),
+    ("min", 2, 2),
+    ("max", 2, 2),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Str(String),
+    Number(f64),
+    Op(&'static str),
+}
+
+pub fn parse(source: &str) -> Result<Script, String> {
+    let mut rules = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let rule = tokenize(line)
+            .and_then(|tokens| {
+                Parser {
+                    tokens,
+                    position: 0,
+                }
+                .parse_rule()
+            })
+            .map_err(|e| format!("Line {}: {}", index + 1, e))?;
+        rules.push(rule);
+    }
+
+    Ok(Script { rules })
+}
+
+impl Script {
+    pub fn run(&self, data: &Value, limits: &Limits) -> Result<Outcome, String> {
+        let mut evaluator = Evaluator {
+            limits,
+            steps: 0,
+            data: data.clone(),
+        };
+        let mut annotations = Vec::new();
+
+        for rule in &self.rules {
+            match rule {
+                Rule::Reject { message, condition } => {
+                    if truthy(&evaluator.eval(condition)?) {
+                        let message = match evaluator.eval(message)? {
+                            Value::String(message) => message,
+                            other => other.to_string(),
+                        };
+                        return Ok(Outcome::Rejected(message));
+                    }
+                }
+                Rule::Set {
+                    field,
+                    value,
+                    condition,
+                } => {
+                    let applies = match condition {
+                        Some(condition) => truthy(&evaluator.eval(condition)?),
+                        None => true,
+                    };
+                    if applies {
+                        let value = evaluator.eval(value)?;
+                        evaluator.data[field.as_str()] = value.clone();
+                        annotations.push((field.clone(), value));
+                    }
+                }
+            }
+        }
+
+        Ok(Outcome::Annotated(annotations))
+    }
+
+    pub fn assigned_fields(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().filter_map(|rule| match rule {
+            Rule::Set { field, .. } => Some(field.as_str()),
+            Rule::Reject { .. } => None,
+        })
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    const OPERATORS: &[&str] = &[
+        "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", ",",
+        ".", "=",
+    ];
+
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err("Unterminated string".to_string()),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err("Unterminated string".to_string()),
+                }
+            };
+            tokens.push(Token::Str(value));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("Invalid number {}", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("Unexpected character '{}'", c))?;
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| "Unexpected end of line".to_string())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(token)) if *token == op) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Name(name)) if name == keyword) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_rule(mut self) -> Result<Rule, String> {
+        let rule = match self.next()? {
+            Token::Name(keyword) if keyword == "reject" => {
+                let message = self.parse_expr()?;
+                if !self.eat_keyword("if") {
+                    return Err("Expected 'if' after the reject message".to_string());
+                }
+                Rule::Reject {
+                    message,
+                    condition: self.parse_expr()?,
+                }
+            }
+            Token::Name(keyword) if keyword == "set" => {
+                let field = match self.next()? {
+                    Token::Name(field) => field,
+                    _ => return Err("Expected a field name after 'set'".to_string()),
+                };
+                if !self.eat_op("=") {
+                    return Err("Expected '=' after the field name".to_string());
+                }
+                let value = self.parse_expr()?;
+                let condition = if self.eat_keyword("if") {
+                    Some(self.parse_expr()?)
+                } else {
+                    None
+                };
+                Rule::Set {
+                    field,
+                    value,
+                    condition,
+                }
+            }
+            _ => return Err("Rules start with 'reject' or 'set'".to_string()),
+        };
+
+        match self.peek() {
+            None => Ok(rule),
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, String> {
+        self.parse_binary(0)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: &[&[(&str, BinaryOp)]] = &[
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[
+                ("==", BinaryOp::Eq),
+                ("!=", BinaryOp::Ne),
+                ("<=", BinaryOp::Le),
+                (">=", BinaryOp::Ge),
+                ("<", BinaryOp::Lt),
+                (">", BinaryOp::Gt),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[
+                ("*", BinaryOp::Mul),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+        ];
+
+        let Some(operators) = LEVELS.get(level) else {
+            return self.parse_unary();
+        };
+
+        let mut left = self.parse_binary(level + 1)?;
+        'outer: loop {
+            for (symbol, op) in operators.iter() {
+                if self.eat_op(symbol) {
+                    let right = self.parse_binary(level + 1)?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.eat_op("!") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.eat_op("-") {
+            return Ok(Expr::Negate(Box::new(self.parse_unary()?)));
+        }
+
+        match self.next()? {
+            Token::Number(number) => Ok(Expr::Literal(json!(number))),
+            Token::Str(value) => Ok(Expr::Literal(json!(value))),
+            Token::Op("(") => {
+                let expr = self.parse_expr()?;
+                if !self.eat_op(")") {
+                    return Err("Expected ')'".to_string());
+                }
+                Ok(expr)
+            }
+            Token::Name(name) => match name.as_str() {
+                "true" => Ok(Expr::Literal(json!(true))),
+                "false" => Ok(Expr::Literal(json!(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                "if" => Err("Unexpected 'if'".to_string()),
+                _ if self.eat_op("(") => {
+                    let mut args = Vec::new();
+                    if !self.eat_op(")") {
+                        loop {
+                            args.push(self.parse_expr()?);
+                            if self.eat_op(")") {
+                                break;
+                            }
+                            if !self.eat_op(",") {
+                                return Err("Expected ',' or ')' in call".to_string());
+                            }
+                        }
+                    }
+
+                    let (_, min_args, max_args) = FUNCTIONS
+                        .iter()
+                        .find(|(function, _, _)| *function == name)
+                        .ok_or_else(|| format!("Unknown function {}", name))?;
+                    if !(*min_args..=*max_args).contains(&args.len()) {
+                        return Err(format!("Wrong number of arguments for {}", name));
+                    }
+                    Ok(Expr::Call(name, args))
+                }
+                _ => {
+                    let mut path = vec![name];
+                    while self.eat_op(".") {
+                        match self.next()? {
+                            Token::Name(segment) => path.push(segment),
+                            _ => return Err("Expected a field name after '.'".to_string()),
+                        }
+                    }
+                    Ok(Expr::Field(path))
+                }
+            },
+            token => Err(format!("Unexpected {:?}", token)),
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    limits: &'a Limits,
+    steps: usize,
+    data: Value,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        self.steps += 1;
+        if self.steps > self.limits.max_steps {
+            return Err("Script exceeded its step limit".to_string());
+        }
+        if self.steps.is_multiple_of(64) && Instant::now() > self.limits.deadline {
+            return Err("Script exceeded its time limit".to_string());
+        }
+
+        let value = match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::Field(path) => path
+                .iter()
+                .try_fold(&self.data, |value, segment| value.get(segment))
+                .cloned()
+                .unwrap_or(Value::Null),
+            Expr::Not(inner) => json!(!truthy(&self.eval(inner)?)),
+            Expr::Negate(inner) => json!(-number(&self.eval(inner)?)?),
+            Expr::Binary(BinaryOp::And, left, right) => {
+                let left = self.eval(left)?;
+                if truthy(&left) {
+                    self.eval(right)?
+                } else {
+                    left
+                }
+            }
+            Expr::Binary(BinaryOp::Or, left, right) => {
+                let left = self.eval(left)?;
+                if truthy(&left) {
+                    left
+                } else {
+                    self.eval(right)?
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.binary(*op, &left, &right)?
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, &args)?
+            }
+        };
+
+        if let Value::String(s) = &value
+            && s.len() > self.limits.max_string_len
+        {
+            return Err("Script exceeded its string size limit".to_string());
+        }
+        Ok(value)
+    }
+
+    fn binary(&self, op: BinaryOp, left: &Value, right: &Value) -> Result<Value, String> {
+        let value = match op {
+            BinaryOp::Eq => json!(equal(left, right)),
+            BinaryOp::Ne => json!(!equal(left, right)),
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                let ordering = match (left, right) {
+                    (Value::String(a), Value::String(b)) => a.cmp(b),
+                    _ => number(left)?
+                        .partial_cmp(&number(right)?)
+                        .ok_or("Cannot compare NaN")?,
+                };
+                json!(match op {
+                    BinaryOp::Lt => ordering.is_lt(),
+                    BinaryOp::Le => ordering.is_le(),
+                    BinaryOp::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                })
+            }
+            BinaryOp::Add if left.is_string() || right.is_string() => {
+                json!(format!("{}{}", text(left), text(right)))
+            }
+            BinaryOp::Add => json!(number(left)? + number(right)?),
+            BinaryOp::Sub => json!(number(left)? - number(right)?),
+            BinaryOp::Mul => json!(number(left)? * number(right)?),
+            BinaryOp::Div | BinaryOp::Rem => {
+                let divisor = number(right)?;
+                if divisor == 0.0 {
+                    return Err("Division by zero".to_string());
+                }
+                if op == BinaryOp::Div {
+                    json!(number(left)? / divisor)
+                } else {
+                    json!(number(left)? % divisor)
+                }
+            }
+            BinaryOp::And | BinaryOp::Or => unreachable!("short-circuited in eval"),
+        };
+        Ok(value)
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        let arg = |index: usize| args.get(index).unwrap_or(&Value::Null);
+        let value = match name {
+            "len" => match arg(0) {
+                Value::Array(values) => json!(values.len()),
+                Value::Null => json!(0),
+                other => json!(text(other).chars().count()),
+            },
+            "digits" => json!(
+                text(arg(0))
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .collect::<String>()
+            ),
+            "digit_sum" => json!(
+                text(arg(0))
+                    .chars()
+                    .filter_map(|c| c.to_digit(10))
+                    .sum::<u32>()
+            ),
+            "int" => match arg(0) {
+                Value::String(s) => s
+                    .trim()
+                    .parse::<f64>()
+                    .map(|n| json!(n.trunc() as i64))
+                    .unwrap_or(Value::Null),
+                other => json!(number(other)?.trunc() as i64),
+            },
+            "str" => json!(text(arg(0))),
+            "lower" => json!(text(arg(0)).to_lowercase()),
+            "upper" => json!(text(arg(0)).to_uppercase()),
+            "trim" => json!(text(arg(0)).trim()),
+            "substr" => {
+                let source = text(arg(0));
+                let start = number(arg(1))?.max(0.0) as usize;
+                let chars = source.chars().skip(start);
+                match args.get(2) {
+                    Some(len) => json!(
+                        chars
+                            .take(number(len)?.max(0.0) as usize)
+                            .collect::<String>()
+                    ),
+                    None => json!(chars.collect::<String>()),
+                }
+            }
+            "starts_with" => json!(text(arg(0)).starts_with(&text(arg(1)))),
+            "ends_with" => json!(text(arg(0)).ends_with(&text(arg(1)))),
+            "contains" => json!(text(arg(0)).contains(&text(arg(1)))),
+            "is_number" => json!(arg(0).is_number()),
+            "abs" => json!(number(arg(0))?.abs()),
+            "round" => {
+                let factor = 10f64.powi(args.get(1).map(number).transpose()?.unwrap_or(0.0) as i32);
+                json!((number(arg(0))? * factor).round() / factor)
+            }
+            "min" => json!(number(arg(0))?.min(number(arg(1))?)),
+            "max" => json!(number(arg(0))?.max(number(arg(1))?)),
+            other => return Err(format!("Unknown function {}", other)),
+        };
+        Ok(value)
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => left == right,
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Number(n) => n.as_f64().ok_or_else(|| "Invalid number".to_string()),
+        Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+        other => Err(format!("Expected a number, got {}", other)),
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limits() -> Limits {
+        Limits {
+            max_steps: 10_000,
+            max_string_len: 4096,
+            deadline: Instant::now() + Duration::from_secs(60),
+        }
+    }
+
+    fn run(source: &str, data: Value) -> Result<Outcome, String> {
+        parse(source)?.run(&data, &limits())
+    }
+
+    fn annotations(source: &str, data: Value) -> Vec<(String, Value)> {
+        match run(source, data) {
+            Ok(Outcome::Annotated(fields)) => fields,
+            Ok(Outcome::Rejected(message)) => panic!("Unexpected rejection: {}", message),
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    fn rejection(source: &str, data: Value) -> Option<String> {
+        match run(source, data) {
+            Ok(Outcome::Rejected(message)) => Some(message),
+            Ok(Outcome::Annotated(_)) => None,
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    fn eval(expr: &str) -> Value {
+        let fields = annotations(&format!("set result = {}", expr), json!({}));
+        fields.into_iter().next().unwrap().1
+    }
+
+    #[test]
+    fn parses_rules_and_skips_comments_and_blank_lines() {
+        let script = parse(
+            "# checks\n\n  reject \"short\" if len(partieNr) < 4\nset note = \"x\" # trailing\n",
+        )
+        .unwrap();
+
+        assert_eq!(script.rules.len(), 2);
+        assert_eq!(script.assigned_fields().collect::<Vec<_>>(), ["note"]);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let cases = [
+            ("set a = \"open", "Line 1: Unterminated string"),
+            ("\nset a = 1 @ 2", "Line 2: Unexpected character '@'"),
+            ("set a = nope(1)", "Line 1: Unknown function nope"),
+            (
+                "set a = len(1, 2)",
+                "Line 1: Wrong number of arguments for len",
+            ),
+            (
+                "reject \"x\" len(a) > 1",
+                "Line 1: Expected 'if' after the reject message",
+            ),
+            ("set = 1", "Line 1: Expected a field name after 'set'"),
+            ("set a 1", "Line 1: Expected '=' after the field name"),
+            ("delete a", "Line 1: Rules start with 'reject' or 'set'"),
+            ("set a = (1 + 2", "Line 1: Expected ')'"),
+            ("set a = 1 2", "Line 1: Unexpected Number(2.0)"),
+            ("set a = 1 +", "Line 1: Unexpected end of line"),
+            ("set a = 1.2.3", "Line 1: Invalid number 1.2.3"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(parse(source).err().as_deref(), Some(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn evaluates_operators_with_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), json!(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), json!(9.0));
+        assert_eq!(eval("7 % 4 - -1"), json!(4.0));
+        assert_eq!(eval("1 < 2 && 2 <= 2 && !(3 > 4)"), json!(true));
+        assert_eq!(eval("\"a\" < \"b\""), json!(true));
+        assert_eq!(eval("2 == 2.0"), json!(true));
+        assert_eq!(eval("\"n\" + len(\"ab\")"), json!("n2"));
+        assert_eq!(eval("null || \"fallback\""), json!("fallback"));
+        assert_eq!(eval("0 && 1 / 0"), json!(0.0));
+    }
+
+    #[test]
+    fn evaluates_functions() {
+        assert_eq!(eval("digits(\"A-12 b3\")"), json!("123"));
+        assert_eq!(eval("digit_sum(\"4711\")"), json!(13));
+        assert_eq!(eval("int(\" 12.9 \")"), json!(12));
+        assert_eq!(eval("int(\"x\")"), Value::Null);
+        assert_eq!(eval("upper(trim(\" eilt \"))"), json!("EILT"));
+        assert_eq!(eval("substr(\"Fichte\", 1, 3)"), json!("ich"));
+        assert_eq!(eval("substr(\"Fichte\", 4)"), json!("te"));
+        assert_eq!(eval("starts_with(\"P-1\", \"P-\")"), json!(true));
+        assert_eq!(eval("contains(lower(\"EILT\"), \"ei\")"), json!(true));
+        assert_eq!(eval("round(2.345, 2)"), json!(2.35));
+        assert_eq!(eval("max(abs(-3), min(1, 2))"), json!(3.0));
+        assert_eq!(eval("len(\"Säge\")"), json!(4));
+    }
+
+    #[test]
+    fn reads_fields_and_sees_earlier_assignments() {
+        let data = json!({"partieNr": "P-12", "contract": {"title": "Fichte"}});
+
+        let fields = annotations(
+            "set additionalInfo = contract.title + \"/\" + partieNr\n\
+             set sawmillIds = len(additionalInfo) if missing == null",
+            data,
+        );
+
+        assert_eq!(
+            fields,
+            [
+                ("additionalInfo".to_string(), json!("Fichte/P-12")),
+                ("sawmillIds".to_string(), json!(11)),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_rejection() {
+        let source = "reject \"Partie \" + partieNr + \" too short\" if len(partieNr) < 4\n\
+                      reject len(partieNr) if true";
+
+        assert_eq!(
+            rejection(source, json!({"partieNr": "P1"})).as_deref(),
+            Some("Partie P1 too short")
+        );
+        assert_eq!(
+            rejection(source, json!({"partieNr": "P-12"})).as_deref(),
+            Some("4")
+        );
+        assert_eq!(rejection("reject \"x\" if false", json!({})), None);
+    }
+
+    #[test]
+    fn runtime_errors_are_returned() {
+        let cases = [
+            ("set a = 1 / 0", "Division by zero"),
+            ("set a = 1 % 0", "Division by zero"),
+            ("set a = \"x\" * 2", "Expected a number, got \"x\""),
+            ("set a = -partieNr", "Expected a number, got \"P1\""),
+            ("set a = abs(null)", "Expected a number, got null"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(
+                run(source, json!({"partieNr": "P1"})).err().as_deref(),
+                Some(expected),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn enforces_step_string_and_time_limits() {
+        let script = parse("set a = 1 + 1 + 1 + 1").unwrap();
+        let few_steps = Limits {
+            max_steps: 3,
+            ..limits()
+        };
+        assert_eq!(
+            script.run(&json!({}), &few_steps).err().as_deref(),
+            Some("Script exceeded its step limit")
+        );
+
+        let script = parse("set a = \"abc\" + \"def\"").unwrap();
+        let short_strings = Limits {
+            max_string_len: 5,
+            ..limits()
+        };
+        assert_eq!(
+            script.run(&json!({}), &short_strings).err().as_deref(),
+            Some("Script exceeded its string size limit")
+        );
+
+        let long = vec!["1"; 100].join(" + ");
+        let script = parse(&format!("set a = {}", long)).unwrap();
+        let expired = Limits {
+            deadline: Instant::now() - Duration::from_millis(1),
+            ..limits()
+        };
+        assert_eq!(
+            script.run(&json!({}), &expired).err().as_deref(),
+            Some("Script exceeded its time limit")
+        );
+    }
+}
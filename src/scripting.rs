@@ -0,0 +1,361 @@
+use crate::client_admin;
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::hook_script::hook_script_local_storage::{
+    HookScript, HookScriptLocalStorage,
+};
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::rest::{self, ErrorReply, RestUser};
+use crate::script_engine::{self, Limits, Outcome, Script};
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const HOOK_TARGETS: &[(&str, &str)] = &[
+    ("contract_update", "contracts"),
+    ("location_update", "locations"),
+    ("note_update", "notes"),
+    ("sawmill_update", "sawmills"),
+    ("shipment_update", "shipments"),
+];
+
+const PROTECTED_FIELDS: &[&str] = &["id", "lastEdit", "arrivalAtServer", "deleted"];
+
+fn target_table(message_type: &str) -> Option<&'static str> {
+    HOOK_TARGETS
+        .iter()
+        .find(|(target, _)| *target == message_type)
+        .map(|(_, table)| *table)
+}
+
+fn limits() -> Limits {
+    let config = &config::get().scripting;
+    Limits {
+        max_steps: config.max_steps,
+        max_string_len: config.max_string_bytes,
+        deadline: Instant::now() + Duration::from_millis(config.timeout_ms),
+    }
+}
+
+fn compile(message_type: &str, source: &str) -> Result<Script, String> {
+    let table = target_table(message_type)
+        .and_then(schema::table)
+        .ok_or_else(|| format!("Hooks are not supported for {}", message_type))?;
+    if source.len() > config::get().scripting.max_script_bytes {
+        return Err(format!(
+            "Script exceeds {} bytes",
+            config::get().scripting.max_script_bytes
+        ));
+    }
+
+    let script = script_engine::parse(source)?;
+    if let Some(field) = script
+        .assigned_fields()
+        .find(|field| PROTECTED_FIELDS.contains(field) || table.column(field).is_none())
+    {
+        return Err(format!("Field {} cannot be set on {}", field, table.name));
+    }
+
+    Ok(script)
+}
+
+pub fn apply_hooks(
+    msg_type: &str,
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Option<Value>, String> {
+    if !config::get().scripting.enabled
+        || target_table(msg_type).is_none()
+        || data.get("deleted").and_then(|v| v.as_i64()) == Some(1)
+    {
+        return Ok(None);
+    }
+
+    let scripts = match HookScriptLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_enabled_scripts(msg_type))
+    {
+        Ok(scripts) => scripts,
+        Err(e) => {
+            println!("Failed to load hook scripts: {:?}", e);
+            return Ok(None);
+        }
+    };
+    if scripts.is_empty() {
+        return Ok(None);
+    }
+
+    run_hooks(msg_type, data, &scripts, &limits())
+}
+
+/// Runs the hooks in order on the update. A rejection rejects the update;
+/// a hook that fails to compile or run is logged and skipped.
+fn run_hooks(
+    msg_type: &str,
+    data: &Value,
+    scripts: &[HookScript],
+    limits: &Limits,
+) -> Result<Option<Value>, String> {
+    let mut entity = data.clone();
+    let mut annotated = false;
+    for hook in scripts {
+        let outcome =
+            compile(msg_type, &hook.source).and_then(|script| script.run(&entity, limits));
+        match outcome {
+            Ok(Outcome::Rejected(message)) => {
+                metrics::increment("script_rejections_total");
+                println!(
+                    "Hook script {} rejected {}: {}",
+                    hook.name, msg_type, message
+                );
+                return Err(message);
+            }
+            Ok(Outcome::Annotated(fields)) => {
+                for (field, value) in fields {
+                    entity[field.as_str()] = value;
+                    annotated = true;
+                }
+            }
+            Err(e) => {
+                metrics::increment("script_errors_total");
+                println!("Hook script {} ({}) failed: {}", hook.name, hook.id, e);
+            }
+        }
+    }
+
+    Ok(annotated.then_some(entity))
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list = warp::path!("admin" / "scripts")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_scripts(authorization, &db_pools))
+        });
+
+    let test = warp::path!("admin" / "scripts" / "test")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, body, db_pools: DbPoolMap| {
+            rest::into_reply(test_script(authorization, body, &db_pools))
+        });
+
+    let save = warp::path!("admin" / "scripts" / String)
+        .and(warp::put())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|id, authorization, body, db_pools: DbPoolMap| {
+            rest::into_reply(save_script(id, authorization, body, &db_pools))
+        });
+
+    let delete = warp::path!("admin" / "scripts" / String)
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|id, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(delete_script(id, authorization, &db_pools))
+        });
+
+    list.or(test).unify().or(save).unify().or(delete).unify()
+}
+
+fn script_storage(user: &RestUser) -> Result<HookScriptLocalStorage, ErrorReply> {
+    rest::open_storage(&user.tenant)
+        .and_then(HookScriptLocalStorage::new)
+        .map_err(|e| rest::internal_error("Failed to open hook scripts", e))
+}
+
+fn validation_failed(message: &str) -> ErrorReply {
+    rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message)
+}
+
+fn list_scripts(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let scripts = script_storage(&user)?
+        .get_scripts()
+        .map_err(|e| rest::internal_error("Failed to load hook scripts", e))?;
+
+    Ok(rest::json_reply(&json!({
+        "enabled": config::get().scripting.enabled,
+        "messageTypes": HOOK_TARGETS.iter().map(|(target, _)| *target).collect::<Vec<_>>(),
+        "scripts": scripts
+    })))
+}
+
+fn save_script(
+    id: String,
+    authorization: Option<String>,
+    body: Value,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let message_type = body["messageType"].as_str().unwrap_or_default();
+    let source = body["source"].as_str().unwrap_or_default();
+    let name = body["name"]
+        .as_str()
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or(&id);
+    let enabled = match body.get("enabled") {
+        None | Some(Value::Null) => 1,
+        Some(Value::Bool(enabled)) => i64::from(*enabled),
+        Some(enabled) => enabled
+            .as_i64()
+            .filter(|v| *v == 0 || *v == 1)
+            .ok_or_else(|| validation_failed("enabled must be a boolean"))?,
+    };
+    compile(message_type, source).map_err(|e| validation_failed(&e))?;
+
+    let script = json!({
+        "id": id,
+        "name": name,
+        "messageType": message_type,
+        "source": source,
+        "enabled": enabled
+    });
+    let saved = script_storage(&user)?
+        .save_script(&script, &user.user_id)
+        .map_err(|e| rest::internal_error("Failed to save hook script", e))?;
+
+    client_admin::audit(&user, "script_save", "hookScript", &id, &script);
+    println!(
+        "Admin {} saved hook script {} for {}",
+        user.user_id, id, message_type
+    );
+
+    Ok(rest::json_reply(&saved))
+}
+
+fn delete_script(
+    id: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let deleted = script_storage(&user)?
+        .delete_script(&id)
+        .map_err(|e| rest::internal_error("Failed to delete hook script", e))?;
+    if !deleted {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Hook script not found",
+        ));
+    }
+
+    client_admin::audit(&user, "script_delete", "hookScript", &id, &json!({}));
+
+    Ok(rest::json_reply(&json!({ "id": id, "deleted": true })))
+}
+
+fn test_script(
+    authorization: Option<String>,
+    body: Value,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let message_type = body["messageType"].as_str().unwrap_or_default();
+    let script = compile(message_type, body["source"].as_str().unwrap_or_default())
+        .map_err(|e| validation_failed(&e))?;
+    if !body["data"].is_object() {
+        return Err(validation_failed("data must be an object"));
+    }
+
+    let started = Instant::now();
+    let mut result = match script.run(&body["data"], &limits()) {
+        Ok(Outcome::Rejected(message)) => json!({ "outcome": "rejected", "message": message }),
+        Ok(Outcome::Annotated(fields)) => {
+            let mut data = body["data"].clone();
+            let mut annotations = serde_json::Map::new();
+            for (field, value) in fields {
+                data[field.as_str()] = value.clone();
+                annotations.insert(field, value);
+            }
+            json!({ "outcome": "accepted", "annotations": annotations, "data": data })
+        }
+        Err(e) => json!({ "outcome": "error", "message": e }),
+    };
+
+    result["elapsedMicros"] = json!(started.elapsed().as_micros() as u64);
+    Ok(rest::json_reply(&result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(name: &str, source: &str) -> HookScript {
+        HookScript {
+            id: name.to_string(),
+            name: name.to_string(),
+            source: source.to_string(),
+        }
+    }
+
+    fn run(scripts: &[HookScript], data: Value) -> Result<Option<Value>, String> {
+        run_hooks("location_update", &data, scripts, &limits())
+    }
+
+    #[test]
+    fn rejecting_hooks_reject_the_update() {
+        let scripts = [
+            hook("info", "set additionalInfo = \"checked\""),
+            hook(
+                "partie",
+                "reject \"Partie number too short\" if len(partieNr) < 4",
+            ),
+        ];
+
+        let rejected = run(&scripts, json!({"id": "l1", "partieNr": "P1"}));
+
+        assert_eq!(rejected, Err("Partie number too short".to_string()));
+    }
+
+    #[test]
+    fn failing_hooks_are_skipped_without_panicking() {
+        let scripts = [
+            hook("broken", "set additionalInfo = "),
+            hook("division", "set additionalInfo = 1 / 0"),
+            hook("protected", "set lastEdit = 1"),
+            hook(
+                "looping",
+                &format!("set additionalInfo = {}", vec!["1"; 20_000].join(" + ")),
+            ),
+            hook("info", "set additionalInfo = upper(partieNr)"),
+        ];
+
+        let annotated = run(&scripts, json!({"id": "l1", "partieNr": "p-12"}));
+
+        assert_eq!(
+            annotated,
+            Ok(Some(
+                json!({"id": "l1", "partieNr": "p-12", "additionalInfo": "P-12"})
+            ))
+        );
+    }
+
+    #[test]
+    fn hooks_only_annotate_supported_columns() {
+        assert!(compile("location_update", "set additionalInfo = 1").is_ok());
+        assert_eq!(
+            compile("location_update", "set nope = 1").err().as_deref(),
+            Some("Field nope cannot be set on locations")
+        );
+        assert_eq!(
+            compile("photo_update", "set a = 1").err().as_deref(),
+            Some("Hooks are not supported for photo_update")
+        );
+    }
+}
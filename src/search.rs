@@ -0,0 +1,151 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::search::search_key;
+use crate::local_storage::search::search_local_storage::SearchLocalStorage;
+use crate::local_storage::search::search_table::SEARCH_SOURCES;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, with_db_pools};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+const MIN_QUERY_LENGTH: usize = 2;
+
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = search_key::fold(query)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+fn entity_types(data: &Value) -> Result<Vec<&str>, (&'static str, String)> {
+    let Some(types) = data.get("types").filter(|types| !types.is_null()) else {
+        return Ok(Vec::new());
+    };
+    let types = types
+        .as_array()
+        .ok_or_else(|| invalid("types must be a list of entity types".to_string()))?;
+
+    types
+        .iter()
+        .map(|entity_type| {
+            entity_type
+                .as_str()
+                .filter(|entity_type| {
+                    SEARCH_SOURCES
+                        .iter()
+                        .any(|source| source.entity_type == *entity_type)
+                })
+                .ok_or_else(|| invalid(format!("Unknown entity type: {}", entity_type)))
+        })
+        .collect()
+}
+
+fn count(data: &Value, field: &str, default: i64) -> Result<i64, (&'static str, String)> {
+    match data.get(field) {
+        None | Some(Value::Null) => Ok(default),
+        Some(value) => value
+            .as_i64()
+            .filter(|value| *value >= 0)
+            .ok_or_else(|| invalid(format!("{} must be a non-negative integer", field))),
+    }
+}
+
+pub fn search(
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let query = data["query"].as_str().unwrap_or_default().trim();
+    if query.chars().count() < MIN_QUERY_LENGTH {
+        return Err(invalid(format!(
+            "query must be at least {} characters",
+            MIN_QUERY_LENGTH
+        )));
+    }
+    let types = entity_types(data)?;
+    let limit = count(data, "limit", DEFAULT_LIMIT)?.clamp(1, MAX_LIMIT);
+    let offset = count(data, "offset", 0)?;
+    let Some(expression) = match_expression(query) else {
+        return Ok(json!({ "query": query, "hits": [], "offset": offset, "hasMore": false }));
+    };
+
+    let mut hits = SearchLocalStorage::new(core_storage)
+        .and_then(|storage| storage.search(&expression, &types, limit + 1, offset))
+        .map_err(|e| ("INTERNAL", format!("Search failed: {:?}", e)))?;
+    let has_more = hits.len() as i64 > limit;
+    hits.truncate(limit as usize);
+
+    Ok(json!({
+        "query": query,
+        "hits": hits,
+        "offset": offset,
+        "hasMore": has_more
+    }))
+}
+
+fn invalid(message: String) -> (&'static str, String) {
+    ("VALIDATION_FAILED", message)
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("search")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(|authorization, query, db_pools: DbPoolMap| {
+            rest::into_reply(search_request(authorization, query, &db_pools))
+        })
+}
+
+fn search_request(
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, 0)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let number = |field: &str| -> Result<Value, ErrorReply> {
+        match query.get(field) {
+            None => Ok(Value::Null),
+            Some(value) => value.parse::<i64>().map(|value| json!(value)).map_err(|_| {
+                rest::error_reply(
+                    StatusCode::BAD_REQUEST,
+                    "VALIDATION_FAILED",
+                    &format!("{} must be an integer", field),
+                )
+            }),
+        }
+    };
+    let types: Option<Vec<&str>> = query
+        .get("types")
+        .map(|types| types.split(',').map(str::trim).collect());
+    let data = json!({
+        "query": query.get("q"),
+        "types": types,
+        "limit": number("limit")?,
+        "offset": number("offset")?
+    });
+
+    match search(&data, core_storage) {
+        Ok(result) => Ok(rest::json_reply(&result)),
+        Err(("INTERNAL", e)) => {
+            println!("{}", e);
+            Err(rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Search failed",
+            ))
+        }
+        Err((code, e)) => Err(rest::error_reply(StatusCode::BAD_REQUEST, code, &e)),
+    }
+}
@@ -0,0 +1,437 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::schema;
+use crate::tombstones;
+use crate::{
+    Client, Clients, DbPoolMap, ROLE_ADMIN, get_db_path, get_db_pool,
+    handle_authentication_request, handle_client_message, handle_sync_request,
+};
+use rusqlite::{Connection, params};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use uuid::Uuid;
+use warp::ws::Message;
+
+const ADMIN_USER: &str = "selftest_admin";
+const OBSERVER_USER: &str = "selftest_observer";
+const CONTRACT_ID: &str = "selftest-contract";
+const LOCATION_ID: &str = "selftest-location";
+
+struct Probe {
+    client_id: String,
+    rx: UnboundedReceiver<Message>,
+}
+
+impl Probe {
+    fn connect(clients: &Clients) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let client_id = format!("selftest-{}", Uuid::new_v4());
+
+        if let Ok(mut clients_lock) = clients.lock() {
+            clients_lock.insert(client_id.clone(), Client::new(tx, None));
+        }
+
+        Probe { client_id, rx }
+    }
+
+    fn drain(&mut self) -> Vec<Value> {
+        let mut frames = Vec::new();
+        while let Ok(message) = self.rx.try_recv() {
+            if let Ok(frame) = message
+                .to_str()
+                .map_err(|_| ())
+                .and_then(|text| serde_json::from_str(text).map_err(|_| ()))
+            {
+                frames.push(frame);
+            }
+        }
+        frames
+    }
+}
+
+struct Harness {
+    tenant: String,
+    clients: Clients,
+    db_pools: DbPoolMap,
+    admin: Probe,
+    observer: Probe,
+    cursors: Map<String, Value>,
+    last_edit: i64,
+}
+
+type Step = Result<String, String>;
+
+fn frames_of<'a>(frames: &'a [Value], msg_type: &str) -> Vec<&'a Value> {
+    frames
+        .iter()
+        .filter(|frame| frame["type"] == msg_type)
+        .collect()
+}
+
+fn find_entity<'a>(frames: &'a [Value], msg_type: &str, id: &str) -> Option<&'a Value> {
+    frames_of(frames, msg_type)
+        .into_iter()
+        .map(|frame| &frame["data"])
+        .find(|data| data["id"] == id)
+}
+
+fn ensure_no_errors(frames: &[Value]) -> Result<(), String> {
+    match frames_of(frames, "error").first() {
+        Some(error) => Err(format!("server reported an error: {}", error["data"])),
+        None => Ok(()),
+    }
+}
+
+impl Harness {
+    fn storage(&self) -> Result<CoreLocalStorage, String> {
+        CoreLocalStorage::new(&get_db_path(&self.tenant)).map_err(|e| format!("{:?}", e))
+    }
+
+    fn next_edit(&mut self) -> i64 {
+        self.last_edit = chrono::Utc::now()
+            .timestamp_millis()
+            .max(self.last_edit + 1);
+        self.last_edit
+    }
+
+    fn create_tenant(&mut self) -> Step {
+        get_db_pool(&self.tenant, &self.db_pools).map_err(|e| format!("{:?}", e))?;
+        let conn = Connection::open(get_db_path(&self.tenant)).map_err(|e| e.to_string())?;
+        let now = chrono::Utc::now().timestamp_millis();
+        for (id, role, name) in [
+            (ADMIN_USER, ROLE_ADMIN, "Selftest Admin"),
+            (OBSERVER_USER, 0, "Selftest Observer"),
+        ] {
+            conn.execute(
+                "INSERT INTO users (id, lastEdit, role, name, arrivalAtServer, deleted)
+                 VALUES (?, ?, ?, ?, ?, 0)",
+                params![id, now, role, name, now],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+
+        Ok(format!("created {}", get_db_path(&self.tenant)))
+    }
+
+    async fn authenticate(&mut self) -> Step {
+        let rejected = Probe::connect(&self.clients);
+        let accepted = handle_authentication_request(
+            rejected.client_id.clone(),
+            &self.clients,
+            &self.db_pools,
+            json!({ "apiKey": format!("{}-unknown_user", self.tenant) }),
+        )
+        .await;
+        if let Ok(mut clients_lock) = self.clients.lock() {
+            clients_lock.remove(&rejected.client_id);
+        }
+        if accepted {
+            return Err("an unknown user was authenticated".to_string());
+        }
+
+        for (probe, user_id, role) in [
+            (&mut self.admin, ADMIN_USER, ROLE_ADMIN),
+            (&mut self.observer, OBSERVER_USER, 0),
+        ] {
+            let accepted = handle_authentication_request(
+                probe.client_id.clone(),
+                &self.clients,
+                &self.db_pools,
+                json!({ "apiKey": format!("{}-{}", self.tenant, user_id) }),
+            )
+            .await;
+            let frames = probe.drain();
+            let response = frames_of(&frames, "authentication_response")
+                .first()
+                .map(|frame| frame["data"].clone())
+                .unwrap_or(Value::Null);
+
+            if !accepted || response["authenticated"] != 1 {
+                return Err(format!("{} was not authenticated: {}", user_id, response));
+            }
+            if response["role"] != role {
+                return Err(format!(
+                    "{} authenticated with role {} instead of {}",
+                    user_id, response["role"], role
+                ));
+            }
+        }
+
+        Ok("accepted 2 users, rejected 1 unknown user".to_string())
+    }
+
+    async fn sync(&mut self) -> Result<Vec<Value>, String> {
+        let completed = handle_sync_request(
+            &Value::Object(self.cursors.clone()),
+            self.admin.client_id.clone(),
+            &self.clients,
+            false,
+        )
+        .await;
+        let frames = self.admin.drain();
+        ensure_no_errors(&frames)?;
+        if !completed {
+            return Err("sync did not complete".to_string());
+        }
+
+        for frame in &frames {
+            if let (Some(msg_type), Some(cursor)) = (
+                frame["type"].as_str(),
+                frame["data"]["newSyncDate"].as_i64(),
+            ) {
+                let previous = self.cursors.get(msg_type).and_then(Value::as_i64);
+                if previous.is_some_and(|previous| cursor < previous) {
+                    return Err(format!(
+                        "{} cursor moved backwards from {:?} to {}",
+                        msg_type, previous, cursor
+                    ));
+                }
+                self.cursors.insert(msg_type.to_string(), json!(cursor));
+            }
+        }
+        Ok(frames)
+    }
+
+    async fn initial_sync(&mut self) -> Step {
+        let frames = self.sync().await?;
+        for msg_type in ["user_update", "contract_update", "location_update"] {
+            if !self.cursors.contains_key(msg_type) {
+                return Err(format!("sync did not report a cursor for {}", msg_type));
+            }
+        }
+        for user_id in [ADMIN_USER, OBSERVER_USER] {
+            if find_entity(&frames, "user_update", user_id).is_none() {
+                return Err(format!("user {} missing from sync", user_id));
+            }
+        }
+
+        Ok(format!(
+            "{} frames, {} cursors",
+            frames.len(),
+            self.cursors.len()
+        ))
+    }
+
+    async fn send(&mut self, msg_type: &str, data: Value) -> Result<Vec<Value>, String> {
+        let msg = json!({ "type": msg_type, "data": data });
+        handle_client_message(msg_type, &msg, &data, &self.admin.client_id, &self.clients).await;
+
+        let frames = self.admin.drain();
+        ensure_no_errors(&frames)?;
+        let id = data["id"].as_str().unwrap_or_default();
+        if find_entity(&frames, msg_type, id).is_none() {
+            return Err(format!("{} {} was not confirmed", msg_type, id));
+        }
+
+        let observed = self.observer.drain();
+        match find_entity(&observed, msg_type, id) {
+            Some(broadcast) if broadcast["deleted"] == data["deleted"] => Ok(frames),
+            Some(broadcast) => Err(format!(
+                "observer received a stale {} {}: {}",
+                msg_type, id, broadcast
+            )),
+            None => Err(format!("{} {} was not broadcast", msg_type, id)),
+        }
+    }
+
+    async fn create_entities(&mut self) -> Step {
+        let last_edit = self.next_edit();
+        self.send(
+            "contract_update",
+            json!({
+                "id": CONTRACT_ID,
+                "lastEdit": last_edit,
+                "title": "Selftest contract",
+                "startDate": last_edit,
+                "endDate": last_edit + 86_400_000,
+                "availableQuantity": 100.0
+            }),
+        )
+        .await?;
+
+        let last_edit = self.next_edit();
+        self.send("location_update", self.location(last_edit, 40.0, 0))
+            .await?;
+
+        let storage = self.storage()?;
+        for (table, id) in [("contracts", CONTRACT_ID), ("locations", LOCATION_ID)] {
+            let rows = storage
+                .get_existing_by_id(table, id)
+                .map_err(|e| format!("{:?}", e))?;
+            if rows.is_empty() {
+                return Err(format!("{} {} was not stored", table, id));
+            }
+        }
+
+        Ok("stored and broadcast a contract and a location".to_string())
+    }
+
+    fn location(&self, last_edit: i64, quantity: f64, deleted: i64) -> Value {
+        json!({
+            "id": LOCATION_ID,
+            "lastEdit": last_edit,
+            "latitude": 48.137,
+            "longitude": 11.575,
+            "partieNr": "SELFTEST-1",
+            "initialQuantity": 40.0,
+            "currentQuantity": quantity,
+            "contractId": CONTRACT_ID,
+            "deleted": deleted
+        })
+    }
+
+    async fn incremental_sync(&mut self) -> Step {
+        let frames = self.sync().await?;
+        if find_entity(&frames, "contract_update", CONTRACT_ID).is_none() {
+            return Err("contract missing from incremental sync".to_string());
+        }
+        let location = find_entity(&frames, "location_update", LOCATION_ID)
+            .ok_or("location missing from incremental sync")?;
+        if location["contractId"] != CONTRACT_ID {
+            return Err(format!("location synced as {}", location));
+        }
+
+        let again = self.sync().await?;
+        if find_entity(&again, "location_update", LOCATION_ID).is_some() {
+            return Err("location was synced again after its cursor".to_string());
+        }
+
+        Ok(format!("{} frames, nothing repeated", frames.len()))
+    }
+
+    async fn update_entity(&mut self) -> Step {
+        let last_edit = self.next_edit();
+        self.send("location_update", self.location(last_edit, 25.0, 0))
+            .await?;
+
+        let stored = self
+            .storage()?
+            .get_existing_by_id("locations", LOCATION_ID)
+            .map_err(|e| format!("{:?}", e))?;
+        let quantity = stored
+            .first()
+            .and_then(|row| row["currentQuantity"].as_f64());
+        if quantity != Some(25.0) {
+            return Err(format!("currentQuantity is {:?} instead of 25", quantity));
+        }
+
+        let frames = self.sync().await?;
+        match find_entity(&frames, "location_update", LOCATION_ID) {
+            Some(location) if location["currentQuantity"] == 25.0 => {
+                Ok("update stored and synced".to_string())
+            }
+            other => Err(format!("updated location synced as {:?}", other)),
+        }
+    }
+
+    async fn delete_entity(&mut self) -> Step {
+        let last_edit = self.next_edit();
+        self.send("location_update", self.location(last_edit, 25.0, 1))
+            .await?;
+
+        let frames = self.sync().await?;
+        match find_entity(&frames, "location_update", LOCATION_ID) {
+            Some(location) if location["deleted"] == 1 => {}
+            other => return Err(format!("deleted location synced as {:?}", other)),
+        }
+
+        let page = tombstones::deleted_since(
+            &json!({ "entity": "location" }),
+            ROLE_ADMIN,
+            Arc::new(self.storage()?),
+        )
+        .map_err(|(code, e)| format!("{}: {}", code, e))?;
+        if !page.to_string().contains(LOCATION_ID) {
+            return Err(format!("tombstone missing from deleted_since: {}", page));
+        }
+
+        Ok("tombstone synced and listed".to_string())
+    }
+
+    fn verify_schema(&self) -> Step {
+        let conn = Connection::open(get_db_path(&self.tenant)).map_err(|e| e.to_string())?;
+        schema::migrate(&conn).map_err(|e| format!("migration is not idempotent: {}", e))?;
+        let drifts = schema::detect_drift(&conn).map_err(|e| e.to_string())?;
+        if !drifts.is_empty() {
+            return Err(format!(
+                "schema drift: {}",
+                drifts
+                    .iter()
+                    .map(|drift| drift.to_json().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        Ok(format!("{} tables without drift", schema::TABLES.len()))
+    }
+
+    fn cleanup(&self) {
+        if let Ok(mut clients_lock) = self.clients.lock() {
+            clients_lock.clear();
+        }
+        if let Ok(mut pools) = self.db_pools.lock() {
+            pools.remove(&self.tenant);
+        }
+
+        let db_path = get_db_path(&self.tenant);
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let path = format!("{}{}", db_path, suffix);
+            if let Err(e) = fs::remove_file(&path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                eprintln!("Failed to remove self-test database {}: {}", path, e);
+            }
+        }
+    }
+}
+
+pub async fn run() -> bool {
+    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
+    let mut harness = Harness {
+        tenant: format!("selftest_{}", Uuid::new_v4().simple()),
+        admin: Probe::connect(&clients),
+        observer: Probe::connect(&clients),
+        clients,
+        db_pools: Arc::new(Mutex::new(HashMap::new())),
+        cursors: Map::new(),
+        last_edit: 0,
+    };
+    println!("Running self-test against tenant {}", harness.tenant);
+
+    let started = Instant::now();
+    macro_rules! step {
+        ($name:expr, $result:expr) => {{
+            let step_started = Instant::now();
+            match $result {
+                Ok(detail) => println!(
+                    "SELFTEST PASS {} ({}) in {:?}",
+                    $name,
+                    detail,
+                    step_started.elapsed()
+                ),
+                Err(e) => {
+                    eprintln!("SELFTEST FAIL {}: {}", $name, e);
+                    harness.cleanup();
+                    return false;
+                }
+            }
+        }};
+    }
+
+    step!("create tenant", harness.create_tenant());
+    step!("authenticate", harness.authenticate().await);
+    step!("initial sync", harness.initial_sync().await);
+    step!("create entities", harness.create_entities().await);
+    step!("incremental sync", harness.incremental_sync().await);
+    step!("update entity", harness.update_entity().await);
+    step!("delete entity", harness.delete_entity().await);
+    step!("verify schema", harness.verify_schema());
+    harness.cleanup();
+
+    println!("Self-test passed in {:?}", started.elapsed());
+    true
+}
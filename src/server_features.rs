@@ -0,0 +1,133 @@
+use crate::auth_challenge::CHALLENGE_PROTOCOL_VERSION;
+use crate::broadcast_batch::BATCH_PROTOCOL_VERSION;
+use crate::config;
+use crate::deprecations;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::settings::settings_local_storage::{
+    SettingsLocalStorage, UNIT_FACTORS_KEY,
+};
+use crate::{photo_transcode, pricing, timezone};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const CAPABILITIES: &[(&str, i64)] = &[
+    ("broadcastBatch", BATCH_PROTOCOL_VERSION),
+    ("authChallenge", CHALLENGE_PROTOCOL_VERSION),
+    ("photoFetchBatch", 1),
+    ("photoUrls", 1),
+    ("archiveFetch", 1),
+    ("deletedSince", 1),
+    ("shipmentDocuments", 1),
+    ("quotaReservations", 1),
+    ("reviewQueue", 1),
+    ("contractAllowlists", 1),
+    ("syncBatch", 1),
+    ("syncCursors", 1),
+    ("syncResume", 1),
+    ("deltaSync", 1),
+    ("tours", 1),
+    ("photoChunks", 1),
+    ("activityFeed", 1),
+    ("conflictRecords", 1),
+];
+
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .split(['+', '-'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.trim().parse().unwrap_or(0))
+        .collect()
+}
+
+fn is_below(version: &str, minimum: &str) -> bool {
+    let (mut version, mut minimum) = (version_parts(version), version_parts(minimum));
+    let len = version.len().max(minimum.len());
+    version.resize(len, 0);
+    minimum.resize(len, 0);
+
+    version < minimum
+}
+
+fn deprecations(protocol_version: i64, client_version: Option<&str>) -> Vec<Value> {
+    let settings = &config::get().client_compat;
+    let mut deprecations = Vec::new();
+
+    if protocol_version < CHALLENGE_PROTOCOL_VERSION {
+        deprecations.push(json!({
+            "id": "unchallengedAuthentication",
+            "message": format!(
+                "Protocol versions below {} authenticate without a challenge and will be removed",
+                CHALLENGE_PROTOCOL_VERSION
+            ),
+            "sunset": deprecations::sunset(deprecations::UNCHALLENGED_AUTHENTICATION)
+        }));
+    }
+    if let Some(client_version) = client_version
+        && !settings.min_version.is_empty()
+        && is_below(client_version, &settings.min_version)
+    {
+        deprecations.push(json!({
+            "id": "clientVersion",
+            "message": format!(
+                "Client version {} is older than the minimum supported version {}",
+                client_version, settings.min_version
+            ),
+            "sunset": Value::Null
+        }));
+    }
+
+    deprecations
+}
+
+fn tenant_features(core_storage: Arc<CoreLocalStorage>) -> Value {
+    let (currency, currencies) =
+        pricing::tenant_currencies(core_storage.clone()).unwrap_or_else(|e| {
+            println!("Failed to load currencies: {:?}", e);
+            (pricing::DEFAULT_CURRENCY.to_string(), Vec::new())
+        });
+    let unit_factors = SettingsLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_setting(UNIT_FACTORS_KEY))
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_str::<Value>(&value).ok());
+    let settings = config::get();
+
+    json!({
+        "currency": currency,
+        "currencies": currencies,
+        "timezone": timezone::for_tenant(core_storage.clone()).name(),
+        "unitFactors": unit_factors,
+        "photoPolicy": photo_transcode::client_policy(core_storage),
+        "routing": !settings.route_distance.routing_url.is_empty(),
+        "scripting": settings.scripting.enabled,
+        "writeBatching": settings.write_batch.enabled
+    })
+}
+
+pub fn describe(
+    protocol_version: i64,
+    client_version: Option<&str>,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Value {
+    let settings = &config::get().client_compat;
+    let capabilities: Vec<&str> = CAPABILITIES
+        .iter()
+        .filter(|(_, min_protocol)| protocol_version >= *min_protocol)
+        .map(|(name, _)| *name)
+        .collect();
+
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocolVersion": protocol_version,
+        "maxProtocolVersion": CHALLENGE_PROTOCOL_VERSION,
+        "minClientVersion": (!settings.min_version.is_empty()).then_some(&settings.min_version),
+        "updateRequired": client_version.is_some_and(|client_version| {
+            !settings.min_version.is_empty() && is_below(client_version, &settings.min_version)
+        }),
+        "capabilities": capabilities,
+        "features": tenant_features(core_storage),
+        "deprecations": deprecations(protocol_version, client_version)
+    })
+}
@@ -0,0 +1,315 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::shipment_document::shipment_document_local_storage::{
+    ShipmentDocument, ShipmentDocumentLocalStorage,
+};
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use base64::prelude::*;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use warp::http::{Response, StatusCode};
+use warp::{Filter, Rejection, Reply};
+
+const MIME_TYPES: &[&str] = &["image/png", "image/jpeg", "application/pdf"];
+
+pub type DocumentError = (&'static str, String);
+
+struct Upload {
+    tenant: String,
+    client_id: String,
+    user_id: String,
+    shipment_id: String,
+    mime_type: String,
+    last_edit: i64,
+    size: usize,
+    hash: Option<String>,
+    next_index: usize,
+    bytes: Vec<u8>,
+    started: Instant,
+}
+
+static UPLOADS: LazyLock<Mutex<HashMap<String, Upload>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn validation_error(message: &str) -> DocumentError {
+    ("VALIDATION_FAILED", message.to_string())
+}
+
+fn db_error(e: rusqlite::Error) -> DocumentError {
+    println!("Shipment document storage error: {:?}", e);
+    ("DOCUMENT_FAILED", "Database error".to_string())
+}
+
+pub fn check_permission(
+    core_storage: &CoreLocalStorage,
+    shipment_id: &str,
+    user_id: &str,
+    role: i64,
+) -> Result<(), DocumentError> {
+    let shipment = core_storage
+        .get_existing_by_id("shipments", shipment_id)
+        .map_err(db_error)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ("NOT_FOUND", format!("Shipment {} not found", shipment_id)))?;
+
+    if role >= ROLE_ADMIN || shipment["userId"].as_str() == Some(user_id) {
+        Ok(())
+    } else {
+        Err((
+            "PERMISSION_DENIED",
+            "Only the shipment's creator or admins can attach delivery documents".to_string(),
+        ))
+    }
+}
+
+pub fn start_upload(
+    tenant: &str,
+    client_id: &str,
+    user_id: &str,
+    role: i64,
+    data: &Value,
+    core_storage: &CoreLocalStorage,
+) -> Result<Value, DocumentError> {
+    let settings = &config::get().shipment_documents;
+
+    let shipment_id = data["shipmentId"]
+        .as_str()
+        .ok_or_else(|| validation_error("shipmentId is required"))?;
+    let mime_type = data["mimeType"]
+        .as_str()
+        .filter(|mime_type| MIME_TYPES.contains(mime_type))
+        .ok_or_else(|| {
+            validation_error("mimeType must be image/png, image/jpeg or application/pdf")
+        })?;
+    let size = data["size"]
+        .as_u64()
+        .map(|size| size as usize)
+        .filter(|size| (1..=settings.max_size_bytes).contains(size))
+        .ok_or_else(|| {
+            (
+                "VALIDATION_FAILED",
+                format!(
+                    "size must be between 1 and {} bytes",
+                    settings.max_size_bytes
+                ),
+            )
+        })?;
+    let hash = data["hash"].as_str().map(str::to_lowercase);
+    let last_edit = data["lastEdit"]
+        .as_i64()
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+
+    check_permission(core_storage, shipment_id, user_id, role)?;
+
+    let upload_id = Uuid::new_v4().to_string();
+    let mut uploads = UPLOADS
+        .lock()
+        .map_err(|_| ("DOCUMENT_FAILED", "Upload state unavailable".to_string()))?;
+    let timeout = Duration::from_secs(settings.upload_timeout_secs);
+    uploads.retain(|_, upload| {
+        upload.started.elapsed() < timeout
+            && !(upload.tenant == tenant && upload.shipment_id == shipment_id)
+    });
+    uploads.insert(
+        upload_id.clone(),
+        Upload {
+            tenant: tenant.to_string(),
+            client_id: client_id.to_string(),
+            user_id: user_id.to_string(),
+            shipment_id: shipment_id.to_string(),
+            mime_type: mime_type.to_string(),
+            last_edit,
+            size,
+            hash,
+            next_index: 0,
+            bytes: Vec::with_capacity(size),
+            started: Instant::now(),
+        },
+    );
+
+    Ok(json!({
+        "uploadId": upload_id,
+        "shipmentId": shipment_id,
+        "chunkSize": settings.chunk_size_bytes,
+        "chunkCount": size.div_ceil(settings.chunk_size_bytes)
+    }))
+}
+
+pub fn append_chunk(client_id: &str, data: &Value) -> Result<Value, DocumentError> {
+    let upload_id = data["uploadId"].as_str().unwrap_or_default();
+    let index = data["index"]
+        .as_u64()
+        .ok_or_else(|| validation_error("index is required"))? as usize;
+    let chunk = data["data"]
+        .as_str()
+        .and_then(|chunk| BASE64_STANDARD.decode(chunk).ok())
+        .ok_or_else(|| validation_error("data must be base64 encoded"))?;
+
+    let mut uploads = UPLOADS
+        .lock()
+        .map_err(|_| ("DOCUMENT_FAILED", "Upload state unavailable".to_string()))?;
+    let upload = uploads
+        .get_mut(upload_id)
+        .filter(|upload| upload.client_id == client_id)
+        .ok_or_else(|| ("NOT_FOUND", format!("Upload {} not found", upload_id)))?;
+
+    if index != upload.next_index {
+        return Err((
+            "VALIDATION_FAILED",
+            format!("Expected chunk {}, got {}", upload.next_index, index),
+        ));
+    }
+    if chunk.len() > config::get().shipment_documents.chunk_size_bytes
+        || upload.bytes.len() + chunk.len() > upload.size
+    {
+        uploads.remove(upload_id);
+        return Err(validation_error(
+            "Chunk exceeds the announced document size",
+        ));
+    }
+
+    upload.bytes.extend_from_slice(&chunk);
+    upload.next_index += 1;
+
+    Ok(json!({
+        "uploadId": upload_id,
+        "index": index,
+        "received": upload.bytes.len()
+    }))
+}
+
+pub fn complete_upload(
+    client_id: &str,
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, DocumentError> {
+    let upload_id = data["uploadId"].as_str().unwrap_or_default();
+    let upload = {
+        let mut uploads = UPLOADS
+            .lock()
+            .map_err(|_| ("DOCUMENT_FAILED", "Upload state unavailable".to_string()))?;
+        match uploads.get(upload_id) {
+            Some(upload) if upload.client_id == client_id => uploads.remove(upload_id),
+            _ => None,
+        }
+        .ok_or_else(|| ("NOT_FOUND", format!("Upload {} not found", upload_id)))?
+    };
+
+    if upload.bytes.len() != upload.size {
+        return Err((
+            "VALIDATION_FAILED",
+            format!("Received {} of {} bytes", upload.bytes.len(), upload.size),
+        ));
+    }
+    let document_hash = Sha256::digest(&upload.bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    if upload
+        .hash
+        .as_ref()
+        .is_some_and(|hash| *hash != document_hash)
+    {
+        return Err(validation_error("Document hash does not match"));
+    }
+
+    ShipmentDocumentLocalStorage::new(core_storage)
+        .and_then(|storage| {
+            storage.save_document(&ShipmentDocument {
+                shipment_id: &upload.shipment_id,
+                last_edit: upload.last_edit,
+                mime_type: &upload.mime_type,
+                document: &upload.bytes,
+                document_hash: &document_hash,
+                uploaded_by: &upload.user_id,
+            })
+        })
+        .map_err(db_error)
+}
+
+pub fn delete_document(
+    user_id: &str,
+    role: i64,
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, DocumentError> {
+    let shipment_id = data["shipmentId"]
+        .as_str()
+        .ok_or_else(|| validation_error("shipmentId is required"))?;
+    let last_edit = data["lastEdit"]
+        .as_i64()
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+
+    check_permission(&core_storage, shipment_id, user_id, role)?;
+
+    let deleted = ShipmentDocumentLocalStorage::new(core_storage)
+        .and_then(|storage| storage.mark_document_deleted(shipment_id, last_edit))
+        .map_err(db_error)?;
+    if !deleted {
+        return Err((
+            "NOT_FOUND",
+            format!("Shipment {} has no document", shipment_id),
+        ));
+    }
+
+    Ok(json!({
+        "shipmentId": shipment_id,
+        "lastEdit": last_edit,
+        "deleted": 1
+    }))
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("shipments" / String / "document")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|shipment_id, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(download_document(shipment_id, authorization, &db_pools))
+        })
+}
+
+fn download_document(
+    shipment_id: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<Response<Vec<u8>>, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, 0)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+
+    let (mime_type, document) = ShipmentDocumentLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_document(&shipment_id))
+        .map_err(|e| rest::internal_error("Failed to load shipment document", e))?
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                "Shipment has no document",
+            )
+        })?;
+
+    Response::builder()
+        .header("Content-Type", mime_type)
+        .header(
+            "Content-Disposition",
+            format!("inline; filename=\"shipment-{}\"", shipment_id),
+        )
+        .body(document)
+        .map_err(|e| {
+            println!("Failed to build document response: {:?}", e);
+            rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to build response",
+            )
+        })
+}
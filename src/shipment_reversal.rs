@@ -0,0 +1,99 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use crate::local_storage::note::note_local_storage::NoteLocalStorage;
+use rusqlite::Result;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub fn reverse_shipment(
+    core_storage: Arc<CoreLocalStorage>,
+    shipment_id: &str,
+) -> Result<Vec<(&'static str, Value)>> {
+    core_storage.begin()?;
+
+    match apply_reversal(core_storage.clone(), shipment_id) {
+        Ok(updates) => {
+            core_storage.commit()?;
+            Ok(updates)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = core_storage.rollback() {
+                eprintln!(
+                    "Failed to roll back shipment reversal: {:?}",
+                    rollback_error
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+fn apply_reversal(
+    core_storage: Arc<CoreLocalStorage>,
+    shipment_id: &str,
+) -> Result<Vec<(&'static str, Value)>> {
+    let shipment = core_storage
+        .get_existing_by_id("shipments", shipment_id)?
+        .into_iter()
+        .next()
+        .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let quantity = shipment["quantity"].as_f64().unwrap_or(0.0);
+    let oversize_quantity = shipment["oversizeQuantity"].as_f64().unwrap_or(0.0);
+    let piece_count = shipment["pieceCount"].as_i64().unwrap_or(0);
+    let mut updates = Vec::new();
+
+    let location_id = shipment["locationId"].as_str().unwrap_or_default();
+    if let Some(mut location) = core_storage
+        .get_existing_by_id("locations", location_id)?
+        .into_iter()
+        .next()
+    {
+        location["currentQuantity"] =
+            json!(location["currentQuantity"].as_f64().unwrap_or(0.0) + quantity);
+        location["currentOversizeQuantity"] =
+            json!(location["currentOversizeQuantity"].as_f64().unwrap_or(0.0) + oversize_quantity);
+        location["currentPieceCount"] =
+            json!(location["currentPieceCount"].as_i64().unwrap_or(0) + piece_count);
+        location["lastEdit"] = json!(now);
+        location["arrivalAtServer"] = json!(now);
+        core_storage.update("locations", &location)?;
+
+        let location =
+            LocationLocalStorage::new(core_storage.clone())?.get_location_by_id(location_id)?;
+        updates.push(("location_update", location));
+    }
+
+    let contract_id = shipment["contractId"].as_str().unwrap_or_default();
+    if let Some(mut contract) = core_storage
+        .get_existing_by_id("contracts", contract_id)?
+        .into_iter()
+        .next()
+    {
+        let shipped_quantity = contract["shippedQuantity"].as_f64().unwrap_or(0.0) - quantity;
+        contract["shippedQuantity"] = json!(shipped_quantity.max(0.0));
+        contract["lastEdit"] = json!(now);
+        contract["arrivalAtServer"] = json!(now);
+        core_storage.update("contracts", &contract)?;
+        updates.push(("contract_update", contract));
+    }
+
+    core_storage.mark_as_deleted("shipments", shipment_id)?;
+    updates.push((
+        "shipment_update",
+        json!({
+            "id": shipment_id,
+            "deleted": 1,
+            "lastEdit": now
+        }),
+    ));
+
+    for note in NoteLocalStorage::new(core_storage.clone())?
+        .tombstone_notes_for_entity("shipment", shipment_id)?
+    {
+        updates.push(("note_update", note));
+    }
+
+    Ok(updates)
+}
@@ -0,0 +1,474 @@
+use crate::config;
+use crate::instance_lease;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::schema;
+use crate::local_storage::sla_stat::sla_stat_local_storage::{SlaStat, SlaStatLocalStorage};
+use crate::rest::{self, ErrorReply};
+use crate::tenant_drain;
+use crate::tenant_hibernation;
+use crate::{DbPoolMap, ROLE_ADMIN, database_exists, with_db_pools};
+use chrono::{Days, Months, NaiveDate, Utc};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::future::Future;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+use warp::reply::WithHeader;
+use warp::{Filter, Rejection, Reply};
+
+const MESSAGES: &str = "messages";
+const SYNCS: &str = "syncs";
+const BROADCASTS: &str = "broadcasts";
+const ERROR_PREFIX: &str = "errors.";
+const DEFAULT_RANGE_DAYS: u64 = 30;
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Default)]
+struct Counter {
+    count: i64,
+    total: f64,
+}
+
+type DayCounters = HashMap<(String, String), Counter>;
+
+static PENDING: LazyLock<Mutex<HashMap<String, DayCounters>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+tokio::task_local! {
+    static RECEIVED_AT: Instant;
+}
+
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn record(tenant: &str, metric: &str, value: f64) {
+    if tenant.is_empty() || !config::get().sla_stats.enabled {
+        return;
+    }
+
+    let day = today().format(DAY_FORMAT).to_string();
+    if let Ok(mut pending) = PENDING.lock() {
+        let counter = pending
+            .entry(tenant.to_string())
+            .or_default()
+            .entry((day, metric.to_string()))
+            .or_default();
+        counter.count += 1;
+        counter.total += value;
+    }
+}
+
+pub fn record_message(tenant: &str) {
+    record(tenant, MESSAGES, 0.0);
+}
+
+pub fn record_error(tenant: &str, code: &str) {
+    record(tenant, &format!("{}{}", ERROR_PREFIX, code), 0.0);
+}
+
+pub fn record_sync(tenant: &str, duration: Duration) {
+    record(tenant, SYNCS, millis(duration));
+}
+
+pub fn record_broadcast(tenant: &str) {
+    if let Ok(received_at) = RECEIVED_AT.try_with(|received_at| *received_at) {
+        record(tenant, BROADCASTS, millis(received_at.elapsed()));
+    }
+}
+
+pub async fn track<F: Future<Output = ()>>(handled: F) {
+    RECEIVED_AT.scope(Instant::now(), handled).await;
+}
+
+fn take_pending(tenant: Option<&str>) -> Vec<(String, Vec<SlaStat>)> {
+    let Ok(mut pending) = PENDING.lock() else {
+        return Vec::new();
+    };
+    let tenants: Vec<String> = match tenant {
+        Some(tenant) => vec![tenant.to_string()],
+        None => pending.keys().cloned().collect(),
+    };
+
+    tenants
+        .into_iter()
+        .filter_map(|tenant| {
+            let counters = pending.remove(&tenant)?;
+            let stats = counters
+                .into_iter()
+                .map(|((day, metric), counter)| SlaStat {
+                    day,
+                    metric,
+                    count: counter.count,
+                    total: counter.total,
+                })
+                .collect();
+            Some((tenant, stats))
+        })
+        .collect()
+}
+
+fn restore_pending(tenant: String, stats: Vec<SlaStat>) {
+    if let Ok(mut pending) = PENDING.lock() {
+        let counters = pending.entry(tenant).or_default();
+        for stat in stats {
+            let counter = counters.entry((stat.day, stat.metric)).or_default();
+            counter.count += stat.count;
+            counter.total += stat.total;
+        }
+    }
+}
+
+pub fn flush(tenant: Option<&str>) {
+    for (tenant, stats) in take_pending(tenant) {
+        if !database_exists(&tenant) {
+            continue;
+        }
+        if tenant_drain::is_draining(&tenant) {
+            restore_pending(tenant, stats);
+            continue;
+        }
+
+        let written = rest::open_storage(&tenant)
+            .and_then(SlaStatLocalStorage::new)
+            .and_then(|storage| storage.add_stats(&stats));
+        if let Err(e) = written {
+            eprintln!("Failed to write SLA stats of tenant {}: {:?}", tenant, e);
+            restore_pending(tenant, stats);
+        }
+    }
+}
+
+pub fn spawn() -> Option<JoinHandle<()>> {
+    let settings = &config::get().sla_stats;
+    if !settings.enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.flush_interval_secs);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+        let mut pruned_on = None;
+
+        loop {
+            ticker.tick().await;
+            let day = today();
+            let prune = pruned_on != Some(day) && instance_lease::is_leader();
+            match tokio::task::spawn_blocking(move || {
+                flush(None);
+                if prune {
+                    prune_expired(day);
+                }
+            })
+            .await
+            {
+                Ok(()) => {
+                    if prune {
+                        pruned_on = Some(day);
+                    }
+                }
+                Err(e) => eprintln!("SLA stats flush failed: {:?}", e),
+            }
+        }
+    }))
+}
+
+fn prune_expired(today: NaiveDate) {
+    let Some(cutoff) =
+        today.checked_sub_months(Months::new(config::get().sla_stats.retention_months))
+    else {
+        return;
+    };
+    let cutoff = cutoff.format(DAY_FORMAT).to_string();
+
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if tenant_drain::is_draining(tenant) || tenant_hibernation::is_hibernating(tenant) {
+            continue;
+        }
+
+        let deleted = path
+            .to_str()
+            .ok_or(rusqlite::Error::InvalidPath(path.clone()))
+            .and_then(CoreLocalStorage::new)
+            .and_then(|core_storage| {
+                schema::migrate(&*core_storage.get_connection()?)?;
+                SlaStatLocalStorage::new(Arc::new(core_storage))?.delete_before(&cutoff)
+            });
+        match deleted {
+            Ok(0) => {}
+            Ok(deleted) => println!(
+                "Deleted {} SLA stats of tenant {} before {}",
+                deleted, tenant, cutoff
+            ),
+            Err(e) => eprintln!("Failed to prune SLA stats of tenant {}: {:?}", tenant, e),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    messages: i64,
+    errors: BTreeMap<String, i64>,
+    syncs: i64,
+    sync_ms: f64,
+    broadcasts: i64,
+    broadcast_ms: f64,
+}
+
+fn average(total: f64, count: i64) -> Option<f64> {
+    (count > 0).then(|| (total / count as f64 * 100.0).round() / 100.0)
+}
+
+impl Summary {
+    fn add(&mut self, stat: &SlaStat) {
+        match stat.metric.as_str() {
+            MESSAGES => self.messages += stat.count,
+            SYNCS => {
+                self.syncs += stat.count;
+                self.sync_ms += stat.total;
+            }
+            BROADCASTS => {
+                self.broadcasts += stat.count;
+                self.broadcast_ms += stat.total;
+            }
+            metric => {
+                if let Some(code) = metric.strip_prefix(ERROR_PREFIX) {
+                    *self.errors.entry(code.to_string()).or_insert(0) += stat.count;
+                }
+            }
+        }
+    }
+
+    fn error_count(&self) -> i64 {
+        self.errors.values().sum()
+    }
+
+    fn error_rate_percent(&self) -> Option<f64> {
+        average(self.error_count() as f64 * 100.0, self.messages)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "messagesProcessed": self.messages,
+            "errorCount": self.error_count(),
+            "errorRatePercent": self.error_rate_percent(),
+            "errors": self.errors,
+            "syncs": self.syncs,
+            "averageSyncMs": average(self.sync_ms, self.syncs),
+            "broadcasts": self.broadcasts,
+            "averageBroadcastLatencyMs": average(self.broadcast_ms, self.broadcasts)
+        })
+    }
+}
+
+struct Report {
+    from: String,
+    to: String,
+    days: BTreeMap<String, Summary>,
+    totals: Summary,
+}
+
+impl Report {
+    fn load(tenant: &str, query: &HashMap<String, String>) -> Result<Self, ErrorReply> {
+        let parse_day = |name: &str| match query.get(name) {
+            Some(value) => NaiveDate::parse_from_str(value, DAY_FORMAT)
+                .map(Some)
+                .map_err(|_| {
+                    rest::error_reply(
+                        StatusCode::BAD_REQUEST,
+                        "VALIDATION_FAILED",
+                        &format!("{} must be a date formatted as YYYY-MM-DD", name),
+                    )
+                }),
+            None => Ok(None),
+        };
+        let to = parse_day("to")?.unwrap_or_else(today);
+        let from = match parse_day("from")? {
+            Some(from) => from,
+            None => to
+                .checked_sub_days(Days::new(DEFAULT_RANGE_DAYS - 1))
+                .unwrap_or(to),
+        };
+        if from > to {
+            return Err(rest::error_reply(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "from must not be after to",
+            ));
+        }
+        let from = from.format(DAY_FORMAT).to_string();
+        let to = to.format(DAY_FORMAT).to_string();
+
+        flush(Some(tenant));
+        let stats = rest::open_storage(tenant)
+            .and_then(SlaStatLocalStorage::new)
+            .and_then(|storage| storage.get_stats(&from, &to))
+            .map_err(|e| rest::internal_error("Failed to load SLA stats", e))?;
+
+        let mut days: BTreeMap<String, Summary> = BTreeMap::new();
+        let mut totals = Summary::default();
+        for stat in &stats {
+            days.entry(stat.day.clone()).or_default().add(stat);
+            totals.add(stat);
+        }
+
+        Ok(Report {
+            from,
+            to,
+            days,
+            totals,
+        })
+    }
+
+    fn to_json(&self, tenant: &str) -> Value {
+        let days: Vec<Value> = self
+            .days
+            .iter()
+            .map(|(day, summary)| {
+                let mut entry = summary.to_json();
+                entry["day"] = json!(day);
+                entry
+            })
+            .collect();
+
+        json!({
+            "tenant": tenant,
+            "from": self.from,
+            "to": self.to,
+            "days": days,
+            "totals": self.totals.to_json()
+        })
+    }
+
+    fn to_csv(&self) -> String {
+        let codes: Vec<&String> = self.totals.errors.keys().collect();
+        let mut header = vec![
+            "day".to_string(),
+            "messagesProcessed".to_string(),
+            "errorCount".to_string(),
+            "errorRatePercent".to_string(),
+            "syncs".to_string(),
+            "averageSyncMs".to_string(),
+            "broadcasts".to_string(),
+            "averageBroadcastLatencyMs".to_string(),
+        ];
+        header.extend(codes.iter().map(|code| format!("{}{}", ERROR_PREFIX, code)));
+        let mut csv = header.join(",");
+        csv.push('\n');
+
+        let optional = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+        for (day, summary) in &self.days {
+            let mut fields = vec![
+                day.clone(),
+                summary.messages.to_string(),
+                summary.error_count().to_string(),
+                optional(summary.error_rate_percent()),
+                summary.syncs.to_string(),
+                optional(average(summary.sync_ms, summary.syncs)),
+                summary.broadcasts.to_string(),
+                optional(average(summary.broadcast_ms, summary.broadcasts)),
+            ];
+            fields.extend(
+                codes
+                    .iter()
+                    .map(|code| summary.errors.get(*code).copied().unwrap_or(0).to_string()),
+            );
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let report = warp::path!("admin" / "sla-stats")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools.clone()))
+        .map(
+            |authorization, query: HashMap<String, String>, db_pools: DbPoolMap| {
+                rest::into_reply(json_report(authorization, &query, &db_pools))
+            },
+        );
+
+    let csv = warp::path!("admin" / "sla-stats.csv")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools))
+        .map(
+            |authorization, query: HashMap<String, String>, db_pools: DbPoolMap| {
+                rest::into_reply(csv_report(authorization, &query, &db_pools))
+            },
+        );
+
+    report.or(csv).unify()
+}
+
+fn json_report(
+    authorization: Option<String>,
+    query: &HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let report = Report::load(&user.tenant, query)?;
+
+    Ok(rest::json_reply(&report.to_json(&user.tenant)))
+}
+
+fn csv_report(
+    authorization: Option<String>,
+    query: &HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<WithHeader<Response<Body>>, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let report = Report::load(&user.tenant, query)?;
+
+    let response = Response::builder()
+        .header("Content-Type", "text/csv; charset=utf-8")
+        .body(Body::from(report.to_csv()))
+        .map_err(|e| {
+            println!("Failed to build SLA stats response: {:?}", e);
+            rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to export SLA stats",
+            )
+        })?;
+    Ok(warp::reply::with_header(
+        response,
+        "Content-Disposition",
+        format!(
+            "attachment; filename=\"sla-stats-{}-{}-{}.csv\"",
+            user.tenant, report.from, report.to
+        ),
+    ))
+}
@@ -0,0 +1,104 @@
+use crate::metrics;
+use crate::storage::{self, Root};
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const SNAPSHOT_PREFIX: &str = "holz-snapshot-";
+
+pub struct Snapshot {
+    path: PathBuf,
+    connection: Connection,
+}
+
+impl Snapshot {
+    pub fn create(db_path: &str) -> Result<Self> {
+        let started = Instant::now();
+        let path =
+            storage::dir(Root::Backups).join(format!("{}{}.db", SNAPSHOT_PREFIX, Uuid::new_v4()));
+
+        let result = copy_database(db_path, &path).and_then(|_| {
+            Connection::open_with_flags(
+                &path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+        });
+
+        match result {
+            Ok(connection) => {
+                metrics::increment("snapshots_created_total");
+                metrics::add_gauge("snapshots_open", 1);
+                metrics::add(
+                    "snapshot_create_duration_ms_total",
+                    started.elapsed().as_millis() as u64,
+                );
+                Ok(Snapshot { path, connection })
+            }
+            Err(e) => {
+                remove_snapshot_file(&path);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        metrics::add_gauge("snapshots_open", -1);
+        remove_snapshot_file(&self.path);
+    }
+}
+
+pub fn copy_database(db_path: &str, snapshot_path: &PathBuf) -> Result<()> {
+    let source = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut destination = Connection::open(snapshot_path)?;
+
+    {
+        let backup = Backup::new(&source, &mut destination)?;
+        backup.run_to_completion(256, Duration::from_millis(5), None)?;
+    }
+
+    destination.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(()))?;
+    Ok(())
+}
+
+fn remove_snapshot_file(path: &PathBuf) {
+    if path.exists()
+        && let Err(e) = fs::remove_file(path)
+    {
+        eprintln!("Failed to remove snapshot {:?}: {}", path, e);
+    }
+}
+
+pub fn cleanup_stale_snapshots() {
+    let entries = match fs::read_dir(storage::dir(Root::Backups)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read snapshot directory: {}", e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let is_snapshot = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(SNAPSHOT_PREFIX));
+
+        if is_snapshot {
+            println!("Removing stale snapshot {:?}", entry.path());
+            remove_snapshot_file(&entry.path());
+        }
+    }
+}
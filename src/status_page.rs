@@ -0,0 +1,206 @@
+use crate::auth_challenge::CHALLENGE_PROTOCOL_VERSION;
+use crate::config;
+use crate::rate_limit;
+use crate::rest::{self, ErrorReply};
+use crate::storage;
+use serde_json::{Value, json};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const MAINTENANCE_FILE: &str = "status_maintenance.json";
+const MAX_MESSAGE_CHARS: usize = 500;
+
+static CACHE: LazyLock<Mutex<Option<(Instant, Value)>>> = LazyLock::new(|| Mutex::new(None));
+
+fn maintenance_path() -> PathBuf {
+    config::get().databases_dir.join(MAINTENANCE_FILE)
+}
+
+/// Planned maintenance windows that have not ended yet, ordered by start.
+fn load_windows() -> Vec<Value> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut windows: Vec<Value> = fs::read(maintenance_path())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    windows.retain(|window| window["end"].as_i64().is_some_and(|end| end > now));
+    windows.sort_by_key(|window| window["start"].as_i64().unwrap_or_default());
+    windows
+}
+
+/// The public status. Only server-wide facts go in here: no tenant names,
+/// counts or per-tenant state.
+fn build_status() -> Value {
+    let now = chrono::Utc::now().timestamp_millis();
+    let (storage_ok, _) = storage::check_free_space();
+    let maintenance = load_windows();
+    let in_maintenance = maintenance
+        .iter()
+        .any(|window| window["start"].as_i64().is_some_and(|start| start <= now));
+
+    json!({
+        "status": if storage_ok && !in_maintenance { "up" } else { "degraded" },
+        "protocolVersion": CHALLENGE_PROTOCOL_VERSION,
+        "serverVersion": env!("CARGO_PKG_VERSION"),
+        "maintenance": maintenance,
+        "generatedAt": now
+    })
+}
+
+fn cached_status() -> Value {
+    let max_age = Duration::from_secs(config::get().status_page.cache_secs);
+    let Ok(mut cache) = CACHE.lock() else {
+        return build_status();
+    };
+    if let Some((built, status)) = cache.as_ref()
+        && built.elapsed() < max_age
+    {
+        return status.clone();
+    }
+
+    let status = build_status();
+    *cache = Some((Instant::now(), status.clone()));
+    status
+}
+
+fn invalidate() {
+    if let Ok(mut cache) = CACHE.lock() {
+        *cache = None;
+    }
+}
+
+pub fn route() -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let status = warp::path!("status.json")
+        .and(warp::get())
+        .and(warp::addr::remote())
+        .map(|remote_addr: Option<SocketAddr>| rest::into_reply(public_status(remote_addr)));
+
+    let get_maintenance = warp::path!("admin" / "status" / "maintenance")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(|authorization: Option<String>| {
+            rest::into_reply(
+                authorize(authorization)
+                    .map(|_| rest::json_reply(&json!({ "windows": load_windows() }))),
+            )
+        });
+
+    let put_maintenance = warp::path!("admin" / "status" / "maintenance")
+        .and(warp::put())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .map(|authorization, body| rest::into_reply(set_maintenance(authorization, body)));
+
+    status
+        .or(get_maintenance)
+        .unify()
+        .or(put_maintenance)
+        .unify()
+}
+
+fn public_status(remote_addr: Option<SocketAddr>) -> Result<impl Reply, ErrorReply> {
+    let settings = &config::get().status_page;
+    if !settings.enabled {
+        return Err(rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "The status page is disabled",
+        ));
+    }
+    let key = format!(
+        "status:{}",
+        remote_addr
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default()
+    );
+    if let Err(retry_after) = rate_limit::check(&key, settings.rate_limit_per_minute) {
+        return Err(rest::error_reply(
+            StatusCode::TOO_MANY_REQUESTS,
+            "RATE_LIMITED",
+            &format!("Rate limit exceeded, retry in {} seconds", retry_after),
+        ));
+    }
+
+    Ok(warp::reply::with_header(
+        rest::json_reply(&cached_status()),
+        "Cache-Control",
+        format!("public, max-age={}", settings.cache_secs),
+    ))
+}
+
+fn authorize(authorization: Option<String>) -> Result<(), ErrorReply> {
+    let settings = &config::get().status_page;
+    let api_key = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "));
+    if settings.api_key.is_empty() || api_key != Some(settings.api_key.as_str()) {
+        return Err(rest::error_reply(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Missing or invalid API key",
+        ));
+    }
+
+    Ok(())
+}
+
+fn parse_window(window: &Value) -> Result<Value, String> {
+    let start = window["start"]
+        .as_i64()
+        .ok_or_else(|| "start must be a timestamp in milliseconds".to_string())?;
+    let end = window["end"]
+        .as_i64()
+        .ok_or_else(|| "end must be a timestamp in milliseconds".to_string())?;
+    if end <= start {
+        return Err("end must be after start".to_string());
+    }
+    let message = match window.get("message") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(message)) if message.chars().count() <= MAX_MESSAGE_CHARS => {
+            Some(message.clone())
+        }
+        Some(_) => {
+            return Err(format!(
+                "message must be a string of at most {} characters",
+                MAX_MESSAGE_CHARS
+            ));
+        }
+    };
+
+    Ok(json!({ "start": start, "end": end, "message": message }))
+}
+
+/// Replaces the planned maintenance windows with the `windows` of the body.
+fn set_maintenance(authorization: Option<String>, body: Value) -> Result<ErrorReply, ErrorReply> {
+    authorize(authorization)?;
+
+    let validation_error =
+        |message: &str| rest::error_reply(StatusCode::BAD_REQUEST, "VALIDATION_FAILED", message);
+    let windows = body["windows"]
+        .as_array()
+        .ok_or_else(|| validation_error("windows must be a list"))?
+        .iter()
+        .map(parse_window)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| validation_error(&e))?;
+
+    let path = maintenance_path();
+    let bytes = serde_json::to_vec_pretty(&windows).unwrap_or_default();
+    if let Err(e) = fs::write(&path, bytes) {
+        println!("Failed to write {:?}: {}", path, e);
+        return Err(rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Failed to save maintenance windows",
+        ));
+    }
+    invalidate();
+    println!("Status page maintenance windows set: {}", windows.len());
+
+    Ok(rest::json_reply(&json!({ "windows": load_windows() })))
+}
@@ -0,0 +1,302 @@
+use crate::config;
+use crate::metrics;
+use crate::snapshot;
+use rusqlite::{Connection, OpenFlags};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const MB: u64 = 1024 * 1024;
+const MIGRATING_SUFFIX: &str = ".migrating";
+const MIGRATED_SUFFIX: &str = ".migrated";
+const DATABASE_FILE_SUFFIXES: &[&str] = &["", "-wal", "-shm"];
+
+#[derive(Clone, Copy)]
+pub enum Root {
+    Databases,
+    Photos,
+    Backups,
+    Exports,
+}
+
+pub const ROOTS: &[Root] = &[Root::Databases, Root::Photos, Root::Backups, Root::Exports];
+
+impl Root {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Root::Databases => "databases",
+            Root::Photos => "photos",
+            Root::Backups => "backups",
+            Root::Exports => "exports",
+        }
+    }
+}
+
+/// Directory for an artifact type. Photo, backup and export roots fall back to
+/// the directories configured in their own sections.
+pub fn dir(root: Root) -> &'static Path {
+    let settings = config::get();
+    let (configured, fallback) = match root {
+        Root::Databases => return &settings.databases_dir,
+        Root::Photos => (
+            &settings.storage.photos_dir,
+            &settings.photo_ingest.spill_dir,
+        ),
+        Root::Backups => (&settings.storage.backups_dir, &settings.snapshots.dir),
+        Root::Exports => (&settings.storage.exports_dir, &settings.support_bundle.dir),
+    };
+
+    if configured.as_os_str().is_empty() {
+        fallback
+    } else {
+        configured
+    }
+}
+
+pub fn ensure_roots() -> Result<(), String> {
+    for root in ROOTS {
+        let path = dir(*root);
+        if !path.exists() {
+            fs::create_dir_all(path).map_err(|e| {
+                format!(
+                    "Failed to create {} directory {:?}: {}",
+                    root.as_str(),
+                    path,
+                    e
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Free space of every root, also exported as storage_free_bytes gauges. A
+/// root is unhealthy when it has less than min_free_mb left or cannot be
+/// checked.
+pub fn check_free_space() -> (bool, Vec<Value>) {
+    let min_free_bytes = config::get().storage.min_free_mb * MB;
+    let mut healthy = true;
+    let mut roots = Vec::new();
+
+    for root in ROOTS {
+        let path = dir(*root);
+        let (free_bytes, ok, error) = match fs4::available_space(path) {
+            Ok(free_bytes) => {
+                metrics::set_gauge(
+                    &format!("storage_free_bytes{{root=\"{}\"}}", root.as_str()),
+                    free_bytes as i64,
+                );
+                (Some(free_bytes), free_bytes >= min_free_bytes, None)
+            }
+            Err(e) => (None, false, Some(e.to_string())),
+        };
+        healthy &= ok;
+        roots.push(json!({
+            "root": root.as_str(),
+            "path": path,
+            "freeBytes": free_bytes,
+            "minFreeBytes": min_free_bytes,
+            "ok": ok,
+            "error": error
+        }));
+    }
+
+    (healthy, roots)
+}
+
+pub fn route() -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("ready").and(warp::get()).map(|| {
+        let (healthy, roots) = check_free_space();
+        let status = if healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+
+        Box::new(warp::reply::with_status(
+            warp::reply::json(&json!({ "ready": healthy, "storage": roots })),
+            status,
+        )) as Box<dyn Reply>
+    })
+}
+
+pub struct MigrationOptions {
+    pub from: PathBuf,
+    pub dry_run: bool,
+}
+
+impl MigrationOptions {
+    pub fn new(from: &str) -> Self {
+        MigrationOptions {
+            from: PathBuf::from(from),
+            dry_run: false,
+        }
+    }
+}
+
+/// Moves tenant databases from a previous databases directory into the
+/// configured databases root. Each database is copied with the SQLite backup
+/// API, checked, and only then renamed into place; the originals are kept with
+/// a .migrated suffix. Must run while no server uses either directory.
+pub fn migrate(options: &MigrationOptions) -> Result<Value, String> {
+    let target_dir = dir(Root::Databases);
+    let from = options
+        .from
+        .canonicalize()
+        .map_err(|e| format!("Failed to open {:?}: {}", options.from, e))?;
+    if !options.dry_run {
+        ensure_roots()?;
+    }
+    if target_dir.canonicalize().is_ok_and(|target| target == from) {
+        return Err(format!(
+            "{:?} already is the databases directory, nothing to migrate",
+            from
+        ));
+    }
+
+    let mut tenants: Vec<(String, PathBuf)> = fs::read_dir(&from)
+        .map_err(|e| format!("Failed to read {:?}: {}", from, e))?
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let tenant = path.file_name()?.to_str()?.strip_suffix(".db")?.to_string();
+            Some((tenant, path))
+        })
+        .collect();
+    tenants.sort();
+
+    let min_free_bytes = config::get().storage.min_free_mb * MB;
+    let mut results = Vec::new();
+    let (mut migrated, mut skipped) = (0, 0);
+    for (tenant, source) in tenants {
+        let target = target_dir.join(format!("{}.db", tenant));
+        let bytes: u64 = DATABASE_FILE_SUFFIXES
+            .iter()
+            .filter_map(|suffix| fs::metadata(with_suffix(&source, suffix)).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        let outcome = if target.exists() {
+            Err("target database already exists".to_string())
+        } else if options.dry_run {
+            Ok("planned")
+        } else {
+            match fs4::available_space(target_dir) {
+                Ok(free) if free < bytes + min_free_bytes => {
+                    Err(format!("only {} MB free in the databases root", free / MB))
+                }
+                Err(e) => Err(format!("failed to check free space: {}", e)),
+                Ok(_) => migrate_database(&source, &target).map(|_| "migrated"),
+            }
+        };
+
+        let result = match outcome {
+            Ok(status) => {
+                if status == "migrated" {
+                    migrated += 1;
+                    println!("Migrated tenant {} to {:?}", tenant, target);
+                }
+                json!({ "tenant": tenant, "bytes": bytes, "status": status })
+            }
+            Err(reason) => {
+                skipped += 1;
+                eprintln!("Not migrating tenant {}: {}", tenant, reason);
+                json!({ "tenant": tenant, "bytes": bytes, "status": "skipped", "reason": reason })
+            }
+        };
+        results.push(result);
+    }
+
+    Ok(json!({
+        "from": from,
+        "to": target_dir,
+        "dryRun": options.dry_run,
+        "migrated": migrated,
+        "skipped": skipped,
+        "tenants": results
+    }))
+}
+
+fn migrate_database(source: &Path, target: &Path) -> Result<(), String> {
+    let source_str = source
+        .to_str()
+        .ok_or_else(|| format!("{:?} is not a valid path", source))?;
+    let staging = with_suffix(target, MIGRATING_SUFFIX);
+
+    let copied = snapshot::copy_database(source_str, &staging)
+        .map_err(|e| format!("copy failed: {:?}", e))
+        .and_then(|_| verify_copy(source, &staging));
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&staging);
+        return Err(e);
+    }
+
+    fs::rename(&staging, target).map_err(|e| format!("failed to move copy into place: {}", e))?;
+    for suffix in DATABASE_FILE_SUFFIXES {
+        let original = with_suffix(source, suffix);
+        if original.exists() {
+            let kept = with_suffix(source, &format!("{}{}", MIGRATED_SUFFIX, suffix));
+            if let Err(e) = fs::rename(&original, &kept) {
+                eprintln!("Failed to rename migrated {:?}: {}", original, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_copy(source: &Path, copy: &Path) -> Result<(), String> {
+    let open = |path: &Path| {
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("failed to open {:?}: {:?}", path, e))
+    };
+    let (source, copy) = (open(source)?, open(copy)?);
+
+    let integrity: String = copy
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("integrity check failed: {:?}", e))?;
+    if integrity != "ok" {
+        return Err(format!("integrity check failed: {}", integrity));
+    }
+
+    let (source_counts, copy_counts) = (row_counts(&source)?, row_counts(&copy)?);
+    if source_counts != copy_counts {
+        return Err("row counts of the copy differ from the original".to_string());
+    }
+
+    Ok(())
+}
+
+fn row_counts(conn: &Connection) -> Result<Vec<(String, i64)>, String> {
+    let count = || -> rusqlite::Result<Vec<(String, i64)>> {
+        let tables = conn
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type = 'table'
+                 AND name NOT LIKE 'sqlite_%' ORDER BY name",
+            )?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        tables
+            .into_iter()
+            .map(|table| {
+                let rows =
+                    conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| {
+                        row.get(0)
+                    })?;
+                Ok((table, rows))
+            })
+            .collect()
+    };
+
+    count().map_err(|e| format!("failed to count rows: {:?}", e))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
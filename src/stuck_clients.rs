@@ -0,0 +1,116 @@
+use crate::config;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::{Clients, DbPoolMap, ROLE_ADMIN, with_clients, with_db_pools};
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use warp::ws::Message;
+use warp::{Filter, Rejection};
+
+pub fn record_message(client_id: &str, msg_type: &str, clients: &Clients) {
+    if let Ok(mut clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get_mut(client_id)
+    {
+        client.last_message_at = chrono::Utc::now().timestamp_millis();
+        client.last_message_type = Some(msg_type.to_string());
+    }
+}
+
+pub fn spawn(clients: Clients) -> Option<JoinHandle<()>> {
+    let settings = &config::get().stuck_clients;
+    let check_interval = Duration::from_secs(settings.check_interval_secs);
+
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(check_interval);
+        let mut flagged = HashSet::new();
+
+        loop {
+            ticker.tick().await;
+
+            let stuck = find_stuck_clients(&clients, None);
+            metrics::set_gauge("ws_stuck_clients", stuck.len() as i64);
+
+            let mut still_stuck = HashSet::new();
+            for client in &stuck {
+                let client_id = client["clientId"].as_str().unwrap_or_default().to_string();
+                if !flagged.contains(&client_id) {
+                    metrics::increment("ws_stuck_clients_detected_total");
+                    eprintln!("ALERT: stuck client detected: {}", client);
+                }
+
+                if settings.disconnect {
+                    disconnect(&client_id, &clients);
+                } else {
+                    still_stuck.insert(client_id);
+                }
+            }
+            flagged = still_stuck;
+        }
+    }))
+}
+
+fn find_stuck_clients(clients: &Clients, tenant: Option<&str>) -> Vec<Value> {
+    let threshold_ms = (config::get().stuck_clients.threshold_minutes * 60 * 1000) as i64;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let clients_lock = match clients.lock() {
+        Ok(clients_lock) => clients_lock,
+        Err(e) => {
+            println!("Failed to lock clients: {:?}", e);
+            return Vec::new();
+        }
+    };
+
+    clients_lock
+        .iter()
+        .filter(|(_, client)| client.authenticated_at > 0)
+        .filter(|(_, client)| tenant.is_none_or(|tenant| client.db_name == tenant))
+        .filter(|(_, client)| {
+            let connected_for = now - client.authenticated_at;
+            let idle_for = now - client.last_message_at.max(client.authenticated_at);
+            connected_for > threshold_ms && (!client.sync_completed || idle_for > threshold_ms)
+        })
+        .map(|(client_id, client)| {
+            json!({
+                "clientId": client_id,
+                "tenant": client.db_name,
+                "userId": client.user_id,
+                "authenticatedAt": client.authenticated_at,
+                "lastMessageAt": (client.last_message_at > 0).then_some(client.last_message_at),
+                "lastMessageType": client.last_message_type,
+                "syncCompleted": client.sync_completed
+            })
+        })
+        .collect()
+}
+
+fn disconnect(client_id: &str, clients: &Clients) {
+    if let Ok(clients_lock) = clients.lock()
+        && let Some(client) = clients_lock.get(client_id)
+    {
+        let _ = client.sender.send(Message::close());
+        client.disconnect.notify_one();
+        metrics::increment("ws_stuck_clients_disconnected_total");
+    }
+}
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (ErrorReply,), Error = Rejection> + Clone {
+    warp::path!("admin" / "stuck-clients")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, clients: Clients, db_pools: DbPoolMap| {
+            rest::authenticate(authorization, &db_pools, ROLE_ADMIN)
+                .map(|user| {
+                    let stuck = find_stuck_clients(&clients, Some(&user.tenant));
+                    rest::json_reply(&json!({ "clients": stuck }))
+                })
+                .unwrap_or_else(|reply| reply)
+        })
+}
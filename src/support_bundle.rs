@@ -0,0 +1,513 @@
+use crate::client_admin;
+use crate::config;
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::snapshot;
+use crate::storage::{self, Root};
+use crate::{DbPoolMap, ROLE_ADMIN, get_db_path, with_db_pools};
+use chrono::{Datelike, Timelike, Utc};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use futures_util::stream;
+use rusqlite::Connection;
+use serde_json::{Value, json};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+const BUNDLE_PREFIX: &str = "holz-support-";
+const CHUNK_SIZE: usize = 64 * 1024;
+
+const BLOB_COLUMNS: &[(&str, &str)] = &[
+    ("photos", "photoFile"),
+    ("photos_archive", "photoFile"),
+    ("photoOriginals", "photoFile"),
+    ("shipmentDocuments", "document"),
+];
+const COORDINATE_TABLES: &[&str] = &["locations", "locations_archive"];
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let create = warp::path!("admin" / "support-bundles")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .and_then(|authorization, db_pools: DbPoolMap| async move {
+            Ok::<_, Rejection>(rest::into_reply(
+                create_bundle(authorization, db_pools).await,
+            ))
+        });
+
+    let download = warp::path!("admin" / "support-bundles" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |id: String, authorization, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    download_bundle(id, authorization, db_pools).await,
+                ))
+            },
+        );
+
+    create.or(download).unify()
+}
+
+async fn create_bundle(
+    authorization: Option<String>,
+    db_pools: DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, &db_pools, ROLE_ADMIN)?;
+    let id = Uuid::new_v4().to_string();
+    let path = bundle_path(&user.tenant, &id);
+    let db_path = get_db_path(&user.tenant);
+
+    let build_path = path.clone();
+    let build_tenant = user.tenant.clone();
+    let manifest = tokio::task::spawn_blocking(move || {
+        cleanup_expired_bundles();
+        write_bundle(&build_tenant, &db_path, &build_path)
+    })
+    .await
+    .map_err(|e| format!("{:?}", e))
+    .and_then(|result| result);
+
+    let manifest = match manifest {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            println!("Failed to build support bundle: {}", e);
+            let _ = fs::remove_file(&path);
+            return Err(rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to build support bundle",
+            ));
+        }
+    };
+
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let ttl_millis = (config::get().support_bundle.download_ttl_secs * 1000) as i64;
+    metrics::increment("support_bundles_created_total");
+    println!(
+        "Admin {} built support bundle {} for tenant {} ({} bytes)",
+        user.user_id, id, user.tenant, size
+    );
+    client_admin::audit(
+        &user,
+        "support_bundle_create",
+        "support_bundle",
+        &id,
+        &manifest["anonymization"],
+    );
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({
+            "id": id,
+            "size": size,
+            "downloadUrl": format!("/admin/support-bundles/{}", id),
+            "expiresAt": Utc::now().timestamp_millis() + ttl_millis,
+            "manifest": manifest
+        })),
+        StatusCode::CREATED,
+    ))
+}
+
+async fn download_bundle(
+    id: String,
+    authorization: Option<String>,
+    db_pools: DbPoolMap,
+) -> Result<Response<Body>, ErrorReply> {
+    let user = rest::authenticate(authorization, &db_pools, ROLE_ADMIN)?;
+    let not_found = || {
+        rest::error_reply(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "Support bundle not found",
+        )
+    };
+
+    if Uuid::parse_str(&id).is_err() {
+        return Err(not_found());
+    }
+
+    let file = tokio::fs::File::open(bundle_path(&user.tenant, &id))
+        .await
+        .map_err(|_| not_found())?;
+    let size = file.metadata().await.map_err(|_| not_found())?.len();
+
+    let body = Body::wrap_stream(stream::unfold(file, |mut file| async move {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(buffer), file))
+            }
+            Err(e) => Some((Err::<Vec<u8>, _>(e), file)),
+        }
+    }));
+
+    Response::builder()
+        .header("Content-Type", "application/zip")
+        .header("Content-Length", size)
+        .header(
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"support-{}-{}.zip\"",
+                user.tenant, id
+            ),
+        )
+        .body(body)
+        .map_err(|e| {
+            println!("Failed to build support bundle response: {}", e);
+            rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Support bundle download failed",
+            )
+        })
+}
+
+fn bundle_path(tenant: &str, id: &str) -> PathBuf {
+    storage::dir(Root::Exports).join(format!("{}{}-{}.zip", BUNDLE_PREFIX, tenant, id))
+}
+
+fn write_bundle(tenant: &str, db_path: &str, path: &Path) -> Result<Value, String> {
+    let settings = &config::get().support_bundle;
+    let copy_path = path.with_extension("db");
+
+    let result = (|| {
+        snapshot::copy_database(db_path, &copy_path).map_err(|e| format!("{:?}", e))?;
+        let anonymization = {
+            let conn = Connection::open(&copy_path).map_err(|e| format!("{:?}", e))?;
+            schema::migrate(&conn).map_err(|e| format!("{:?}", e))?;
+            let steps = anonymize(&conn).map_err(|e| format!("{:?}", e))?;
+            conn.execute_batch("VACUUM")
+                .map_err(|e| format!("{:?}", e))?;
+            steps
+        };
+
+        let manifest = json!({
+            "tenant": tenant,
+            "createdAt": Utc::now().timestamp_millis(),
+            "serverVersion": env!("CARGO_PKG_VERSION"),
+            "anonymization": anonymization
+        });
+
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let mut add = |name: &str, reader: &mut dyn Read| {
+            zip.add(name, reader)
+                .map_err(|e| format!("Failed to add {}: {}", name, e))
+        };
+
+        add(
+            "manifest.json",
+            &mut serde_json::to_vec_pretty(&manifest)
+                .unwrap_or_default()
+                .as_slice(),
+        )?;
+        add(
+            &format!("{}.db", tenant),
+            &mut File::open(&copy_path).map_err(|e| e.to_string())?,
+        )?;
+        add("metrics.txt", &mut metrics::render().as_bytes())?;
+        if !settings.log_path.is_empty() {
+            match log_tail(&settings.log_path, settings.log_tail_bytes) {
+                Ok(mut log) => add("server.log", &mut log)?,
+                Err(e) => println!("Failed to read log file {}: {}", settings.log_path, e),
+            }
+        }
+
+        zip.finish()
+            .and_then(|mut writer| writer.flush())
+            .map_err(|e| e.to_string())?;
+        Ok(manifest)
+    })();
+
+    if let Err(e) = fs::remove_file(&copy_path) {
+        eprintln!(
+            "Failed to remove support bundle copy {:?}: {}",
+            copy_path, e
+        );
+    }
+    result
+}
+
+fn anonymize(conn: &Connection) -> rusqlite::Result<Vec<Value>> {
+    let settings = &config::get().support_bundle;
+    let mut steps = Vec::new();
+    let mut step = |name: &str, target: String, sql: String| -> rusqlite::Result<()> {
+        let rows = conn.execute(&sql, [])?;
+        steps.push(json!({ "step": name, "target": target, "rows": rows }));
+        Ok(())
+    };
+
+    if settings.anonymize_user_names {
+        step(
+            "anonymize_names",
+            "users.name".to_string(),
+            "UPDATE users SET name = 'User ' || rowid".to_string(),
+        )?;
+    }
+    for table in COORDINATE_TABLES {
+        step(
+            "round_coordinates",
+            format!("{}.latitude,longitude", table),
+            format!(
+                "UPDATE \"{table}\" SET latitude = round(latitude, {decimals}),
+                 longitude = round(longitude, {decimals})",
+                decimals = settings.coordinate_decimals
+            ),
+        )?;
+    }
+    for (table, column) in BLOB_COLUMNS {
+        step(
+            "truncate_blobs",
+            format!("{}.{}", table, column),
+            format!(
+                "UPDATE \"{table}\" SET \"{column}\" = substr(\"{column}\", 1, {max})
+                 WHERE length(\"{column}\") > {max}",
+                max = settings.max_blob_bytes
+            ),
+        )?;
+    }
+    for target in &settings.scrub_columns {
+        let Some((table, column)) = target
+            .split_once('.')
+            .and_then(|(table_name, column_name)| {
+                let table = schema::table(table_name)?;
+                Some((table, table.column(column_name)?))
+            })
+        else {
+            println!("Skipping unknown support bundle scrub column {}", target);
+            continue;
+        };
+        let blank = if column.not_null() { "''" } else { "NULL" };
+        step(
+            "scrub_column",
+            target.clone(),
+            format!(
+                "UPDATE \"{}\" SET \"{}\" = {}",
+                table.name, column.name, blank
+            ),
+        )?;
+    }
+    step(
+        "remove_secrets",
+        "portalTokens".to_string(),
+        "DELETE FROM portalTokens".to_string(),
+    )?;
+    step(
+        "remove_secrets",
+        "deviceSecrets".to_string(),
+        "DELETE FROM deviceSecrets".to_string(),
+    )?;
+    step(
+        "remove_secrets",
+        "signingKeys".to_string(),
+        "DELETE FROM signingKeys".to_string(),
+    )?;
+    step(
+        "remove_secrets",
+        "settings.reportDigest".to_string(),
+        "DELETE FROM settings WHERE key = 'reportDigest'".to_string(),
+    )?;
+    step(
+        "scrub_column",
+        "auditLog.details".to_string(),
+        "UPDATE auditLog SET details = NULL".to_string(),
+    )?;
+
+    Ok(steps)
+}
+
+fn log_tail(path: &str, max_bytes: u64) -> io::Result<io::Take<File>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(max_bytes)))?;
+    Ok(file.take(max_bytes))
+}
+
+pub fn cleanup_expired_bundles() {
+    let ttl = Duration::from_secs(config::get().support_bundle.download_ttl_secs);
+    let Ok(entries) = fs::read_dir(storage::dir(Root::Exports)) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let is_bundle = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(BUNDLE_PREFIX));
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > ttl);
+
+        if is_bundle && expired {
+            println!("Removing expired support bundle {:?}", entry.path());
+            if let Err(e) = fs::remove_file(entry.path()) {
+                eprintln!("Failed to remove support bundle {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+}
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+struct ZipWriter<W: Write> {
+    out: W,
+    offset: u64,
+    entries: Vec<ZipEntry>,
+    time: u16,
+    date: u16,
+}
+
+struct Counting<'a, W: Write> {
+    out: &'a mut W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counting<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> ZipWriter<W> {
+    const FLAGS: u16 = 0x0808;
+
+    fn new(out: W) -> Self {
+        let now = Utc::now();
+        ZipWriter {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+            time: ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16,
+            date: (((now.year() - 1980).max(0) as u32) << 9 | (now.month() << 5) | now.day())
+                as u16,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn add(&mut self, name: &str, reader: &mut dyn Read) -> io::Result<()> {
+        let offset = self.offset;
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes());
+        header.extend_from_slice(&Self::FLAGS.to_le_bytes());
+        header.extend_from_slice(&8u16.to_le_bytes());
+        header.extend_from_slice(&self.time.to_le_bytes());
+        header.extend_from_slice(&self.date.to_le_bytes());
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.write(&header)?;
+
+        let mut crc = Crc::new();
+        let mut counting = Counting {
+            out: &mut self.out,
+            written: 0,
+        };
+        {
+            let mut encoder = DeflateEncoder::new(&mut counting, Compression::default());
+            let mut buffer = vec![0; CHUNK_SIZE];
+            loop {
+                let read = reader.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                crc.update(&buffer[..read]);
+                encoder.write_all(&buffer[..read])?;
+            }
+            encoder.finish()?;
+        }
+        let compressed_size = counting.written;
+        self.offset += compressed_size;
+
+        let too_large = |_| io::Error::other("Support bundle entries must stay below 4 GiB");
+        let entry = ZipEntry {
+            name: name.to_string(),
+            crc: crc.sum(),
+            compressed_size: u32::try_from(compressed_size).map_err(too_large)?,
+            size: crc.amount(),
+            offset: u32::try_from(offset).map_err(too_large)?,
+        };
+
+        let mut descriptor = Vec::with_capacity(16);
+        descriptor.extend_from_slice(&0x08074b50u32.to_le_bytes());
+        descriptor.extend_from_slice(&entry.crc.to_le_bytes());
+        descriptor.extend_from_slice(&entry.compressed_size.to_le_bytes());
+        descriptor.extend_from_slice(&entry.size.to_le_bytes());
+        self.write(&descriptor)?;
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        let directory_offset = self.offset;
+        let entries = std::mem::take(&mut self.entries);
+
+        for entry in &entries {
+            let mut record = Vec::with_capacity(46 + entry.name.len());
+            record.extend_from_slice(&0x02014b50u32.to_le_bytes());
+            record.extend_from_slice(&20u16.to_le_bytes());
+            record.extend_from_slice(&20u16.to_le_bytes());
+            record.extend_from_slice(&Self::FLAGS.to_le_bytes());
+            record.extend_from_slice(&8u16.to_le_bytes());
+            record.extend_from_slice(&self.time.to_le_bytes());
+            record.extend_from_slice(&self.date.to_le_bytes());
+            record.extend_from_slice(&entry.crc.to_le_bytes());
+            record.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            record.extend_from_slice(&entry.size.to_le_bytes());
+            record.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            record.extend_from_slice(&[0; 12]);
+            record.extend_from_slice(&entry.offset.to_le_bytes());
+            record.extend_from_slice(entry.name.as_bytes());
+            self.write(&record)?;
+        }
+
+        let directory_size = self.offset - directory_offset;
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        end.extend_from_slice(&(directory_size as u32).to_le_bytes());
+        end.extend_from_slice(&(directory_offset as u32).to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&end)?;
+
+        Ok(self.out)
+    }
+}
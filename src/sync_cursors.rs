@@ -0,0 +1,207 @@
+use crate::config;
+use serde_json::{Map, Value, json};
+use std::collections::{HashMap, HashSet};
+
+pub const SYNCED_TYPES: &[&str] = &[
+    "user_update",
+    "assortment_update",
+    "sawmill_update",
+    "crew_update",
+    "tour_update",
+    "contract_update",
+    "location_update",
+    "shipment_update",
+    "shipment_document_update",
+    "note_update",
+    "photo_update",
+    "activity_update",
+    "review_item_update",
+    "sawmill_price_update",
+    "quota_reservation_update",
+];
+
+struct CursorRequest {
+    cursor: Option<i64>,
+    limit: Option<usize>,
+    include: bool,
+}
+
+/// Cursors of a sync_request. Clients either send the flat `<type>: date`
+/// fields, which sync every entity, or a `cursors` object
+/// (`{"shipment_update": {"cursor": 0, "limit": 500, "include": true}}`),
+/// which syncs only the listed entities. Either form can be narrowed with an
+/// `entities` list such as `["photos", "shipments"]`.
+pub struct SyncCursors {
+    flat: HashMap<String, i64>,
+    structured: Option<HashMap<String, CursorRequest>>,
+    selected: Option<HashSet<&'static str>>,
+}
+
+impl SyncCursors {
+    pub fn parse(data: &Value) -> Result<Self, String> {
+        let flat = SYNCED_TYPES
+            .iter()
+            .filter_map(|msg_type| Some((msg_type.to_string(), data.get(*msg_type)?.as_i64()?)))
+            .collect();
+
+        let structured = match data.get("cursors") {
+            None | Some(Value::Null) => None,
+            Some(Value::Object(cursors)) => Some(
+                cursors
+                    .iter()
+                    .map(|(msg_type, request)| {
+                        Ok((msg_type.clone(), parse_request(msg_type, request)?))
+                    })
+                    .collect::<Result<HashMap<_, _>, String>>()?,
+            ),
+            Some(_) => return Err("cursors must be an object".to_string()),
+        };
+
+        let selected = match data.get("entities") {
+            None | Some(Value::Null) => None,
+            Some(Value::Array(entities)) => Some(
+                entities
+                    .iter()
+                    .map(|entity| {
+                        let name = entity
+                            .as_str()
+                            .ok_or_else(|| "entities must be a list of strings".to_string())?;
+                        entity_type(name).ok_or_else(|| format!("Unknown entity: {}", name))
+                    })
+                    .collect::<Result<HashSet<_>, String>>()?,
+            ),
+            Some(_) => return Err("entities must be a list of strings".to_string()),
+        };
+
+        Ok(SyncCursors {
+            flat,
+            structured,
+            selected,
+        })
+    }
+
+    pub fn includes(&self, msg_type: &str) -> bool {
+        if let Some(selected) = &self.selected
+            && !selected.contains(msg_type)
+        {
+            return false;
+        }
+        match &self.structured {
+            Some(cursors) => cursors.get(msg_type).is_some_and(|request| request.include),
+            None => true,
+        }
+    }
+
+    pub fn since(&self, msg_type: &str) -> i64 {
+        match &self.structured {
+            Some(cursors) => cursors.get(msg_type).and_then(|request| request.cursor),
+            None => self.flat.get(msg_type).copied(),
+        }
+        .unwrap_or(0)
+    }
+
+    /// Moves cursors forward to the checkpoints of an interrupted sync and
+    /// returns the advanced entities.
+    pub fn advance(&mut self, checkpoints: &HashMap<String, i64>) -> Map<String, Value> {
+        let mut advanced = Map::new();
+        for (msg_type, checkpoint) in checkpoints {
+            let requested = self.since(msg_type);
+            if !self.includes(msg_type) || *checkpoint <= requested {
+                continue;
+            }
+            match &mut self.structured {
+                Some(cursors) => {
+                    if let Some(request) = cursors.get_mut(msg_type) {
+                        request.cursor = Some(*checkpoint);
+                    }
+                }
+                None => {
+                    self.flat.insert(msg_type.clone(), *checkpoint);
+                }
+            }
+            advanced.insert(
+                msg_type.clone(),
+                json!({ "requested": requested, "resumedFrom": checkpoint }),
+            );
+        }
+        advanced
+    }
+
+    /// Per-entity limits of included entities.
+    pub fn limits(&self) -> HashMap<String, usize> {
+        self.structured
+            .iter()
+            .flatten()
+            .filter(|(_, request)| request.include)
+            .filter_map(|(msg_type, request)| Some((msg_type.clone(), request.limit?)))
+            .collect()
+    }
+}
+
+/// Sync message type of an entity name, given either as the message type
+/// (`shipment_update`) or as the plural (`shipments`, `shipment_documents`).
+fn entity_type(name: &str) -> Option<&'static str> {
+    SYNCED_TYPES.iter().copied().find(|msg_type| {
+        *msg_type == name
+            || msg_type
+                .strip_suffix("_update")
+                .is_some_and(|entity| name.strip_suffix('s') == Some(entity))
+    })
+}
+
+fn parse_request(msg_type: &str, request: &Value) -> Result<CursorRequest, String> {
+    if !SYNCED_TYPES.contains(&msg_type) {
+        return Err(format!("Unknown entity in cursors: {}", msg_type));
+    }
+    let Value::Object(fields) = request else {
+        return Err(format!("cursors.{} must be an object", msg_type));
+    };
+    if let Some(unknown) = fields
+        .keys()
+        .find(|key| !["cursor", "limit", "include"].contains(&key.as_str()))
+    {
+        return Err(format!("Unknown field cursors.{}.{}", msg_type, unknown));
+    }
+
+    let cursor = match fields.get("cursor") {
+        None | Some(Value::Null) => None,
+        Some(cursor) => Some(
+            cursor
+                .as_i64()
+                .filter(|cursor| *cursor >= 0)
+                .ok_or_else(|| format!("cursors.{}.cursor must be a timestamp", msg_type))?,
+        ),
+    };
+
+    let max_limit = config::get().sync_batch.max_sync_limit;
+    let limit = match fields.get("limit") {
+        None | Some(Value::Null) => None,
+        Some(limit) => Some(
+            limit
+                .as_u64()
+                .filter(|limit| (1..=max_limit as u64).contains(limit))
+                .ok_or_else(|| {
+                    format!(
+                        "cursors.{}.limit must be between 1 and {}",
+                        msg_type, max_limit
+                    )
+                })? as usize,
+        ),
+    };
+    if limit.is_some() && msg_type == "photo_update" {
+        return Err("cursors.photo_update does not support a limit".to_string());
+    }
+
+    let include = match fields.get("include") {
+        None | Some(Value::Null) => true,
+        Some(include) => include
+            .as_bool()
+            .ok_or_else(|| format!("cursors.{}.include must be a boolean", msg_type))?,
+    };
+
+    Ok(CursorRequest {
+        cursor,
+        limit,
+        include,
+    })
+}
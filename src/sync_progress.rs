@@ -0,0 +1,110 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+const SYNC_TABLES: &[(&str, &str)] = &[
+    ("user_update", "users"),
+    ("assortment_update", "assortments"),
+    ("sawmill_update", "sawmills"),
+    ("crew_update", "crews"),
+    ("tour_update", "tours"),
+    ("contract_update", "contracts"),
+    ("location_update", "locations"),
+    ("shipment_update", "shipments"),
+    ("shipment_document_update", "shipmentDocuments"),
+    ("note_update", "notes"),
+    ("photo_update", "photos"),
+    ("activity_update", "activityFeed"),
+    ("review_item_update", "reviewItems"),
+    ("sawmill_price_update", "sawmillPrices"),
+    ("quota_reservation_update", "quotaReservations"),
+];
+
+#[derive(Debug)]
+struct EntityProgress {
+    msg_type: String,
+    total: usize,
+    sent: usize,
+}
+
+/// Progress of a running sync, reported to the client as sync_progress
+/// messages at most every interval_ms.
+#[derive(Debug, Default)]
+pub struct SyncProgress {
+    entities: Vec<EntityProgress>,
+    last_report: Option<Instant>,
+}
+
+impl SyncProgress {
+    /// Counts what each entity has pending since its cursor. Entities whose
+    /// count fails are left out of the progress.
+    pub fn count(core_storage: &CoreLocalStorage, cursors: &[(&str, i64)]) -> Self {
+        let entities = match core_storage.get_connection() {
+            Ok(conn) => cursors
+                .iter()
+                .filter_map(|(msg_type, since)| {
+                    let (_, table) = SYNC_TABLES.iter().find(|(name, _)| name == msg_type)?;
+                    let total = conn
+                        .query_row(
+                            &format!("SELECT COUNT(*) FROM {} WHERE arrivalAtServer > ?", table),
+                            [since],
+                            |row| row.get::<_, i64>(0),
+                        )
+                        .map_err(|e| println!("Failed to count pending {}: {:?}", msg_type, e))
+                        .ok()?;
+                    Some(EntityProgress {
+                        msg_type: msg_type.to_string(),
+                        total: total as usize,
+                        sent: 0,
+                    })
+                })
+                .collect(),
+            Err(e) => {
+                println!("Failed to count pending sync updates: {:?}", e);
+                Vec::new()
+            }
+        };
+
+        SyncProgress {
+            entities,
+            last_report: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Counts sent entities and returns the progress when a report is due.
+    pub fn advance(&mut self, msg_type: &str, sent: usize) -> Option<Value> {
+        let entity = self
+            .entities
+            .iter_mut()
+            .find(|entity| entity.msg_type == msg_type)?;
+        entity.sent = (entity.sent + sent).min(entity.total);
+        let finished = entity.sent == entity.total;
+
+        let interval = Duration::from_millis(config::get().sync_progress.interval_ms);
+        let due = self
+            .last_report
+            .is_none_or(|reported| reported.elapsed() >= interval);
+        if !due && !finished {
+            return None;
+        }
+        Some(self.report())
+    }
+
+    pub fn report(&mut self) -> Value {
+        self.last_report = Some(Instant::now());
+        json!({
+            "entities": self.entities.iter().map(|entity| json!({
+                "entity": entity.msg_type,
+                "total": entity.total,
+                "sent": entity.sent
+            })).collect::<Vec<_>>(),
+            "total": self.entities.iter().map(|entity| entity.total).sum::<usize>(),
+            "sent": self.entities.iter().map(|entity| entity.sent).sum::<usize>()
+        })
+    }
+}
@@ -0,0 +1,214 @@
+use crate::config;
+use crate::metrics;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use tokio::sync::watch;
+
+#[derive(Default)]
+struct Scheduler {
+    running: usize,
+    running_per_tenant: HashMap<String, usize>,
+    waiting: Vec<Waiter>,
+    next_seq: u64,
+}
+
+struct Waiter {
+    seq: u64,
+    tenant: String,
+    priority: i64,
+    state: watch::Sender<WaitState>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WaitState {
+    Queued(usize),
+    Granted,
+}
+
+static SCHEDULER: LazyLock<Mutex<Scheduler>> = LazyLock::new(|| Mutex::new(Scheduler::default()));
+
+pub struct SyncPermit {
+    tenant: String,
+}
+
+impl Drop for SyncPermit {
+    fn drop(&mut self) {
+        release(&self.tenant);
+    }
+}
+
+pub struct QueuedSync {
+    seq: u64,
+    tenant: String,
+    state: watch::Receiver<WaitState>,
+    granted: bool,
+}
+
+impl QueuedSync {
+    pub fn position(&self) -> Option<usize> {
+        match *self.state.borrow() {
+            WaitState::Queued(position) => Some(position),
+            WaitState::Granted => None,
+        }
+    }
+
+    pub async fn changed(&mut self) -> Option<SyncPermit> {
+        if self.state.changed().await.is_err() {
+            return None;
+        }
+
+        match *self.state.borrow() {
+            WaitState::Granted => {
+                self.granted = true;
+                Some(SyncPermit {
+                    tenant: self.tenant.clone(),
+                })
+            }
+            WaitState::Queued(_) => None,
+        }
+    }
+}
+
+impl Drop for QueuedSync {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+
+        let already_granted = *self.state.borrow() == WaitState::Granted;
+        if already_granted {
+            release(&self.tenant);
+        } else if let Ok(mut scheduler) = SCHEDULER.lock() {
+            scheduler.waiting.retain(|waiter| waiter.seq != self.seq);
+            scheduler.publish_positions();
+        }
+    }
+}
+
+pub enum Admission {
+    Granted(SyncPermit),
+    Queued(QueuedSync),
+}
+
+pub fn admit(tenant: &str) -> Admission {
+    let priority = config::get()
+        .sync_scheduler
+        .priorities
+        .get(tenant)
+        .copied()
+        .unwrap_or(0);
+
+    let mut scheduler = match SCHEDULER.lock() {
+        Ok(scheduler) => scheduler,
+        Err(e) => {
+            eprintln!("Failed to lock sync scheduler: {:?}", e);
+            return Admission::Granted(SyncPermit {
+                tenant: tenant.to_string(),
+            });
+        }
+    };
+
+    let seq = scheduler.next_seq;
+    scheduler.next_seq += 1;
+    let (state, receiver) = watch::channel(WaitState::Queued(0));
+    scheduler.waiting.push(Waiter {
+        seq,
+        tenant: tenant.to_string(),
+        priority,
+        state,
+    });
+    scheduler.grant_waiting();
+
+    let mut queued = QueuedSync {
+        seq,
+        tenant: tenant.to_string(),
+        state: receiver,
+        granted: false,
+    };
+    if *queued.state.borrow_and_update() == WaitState::Granted {
+        queued.granted = true;
+        return Admission::Granted(SyncPermit {
+            tenant: tenant.to_string(),
+        });
+    }
+
+    metrics::increment("sync_requests_queued_total");
+    Admission::Queued(queued)
+}
+
+fn release(tenant: &str) {
+    if let Ok(mut scheduler) = SCHEDULER.lock() {
+        scheduler.finish(tenant);
+        scheduler.grant_waiting();
+    }
+}
+
+impl Scheduler {
+    fn has_room(&self, tenant: &str) -> bool {
+        let settings = &config::get().sync_scheduler;
+        let tenant_running = self.running_per_tenant.get(tenant).copied().unwrap_or(0);
+        self.running < settings.max_concurrent && tenant_running < settings.max_per_tenant
+    }
+
+    fn start(&mut self, tenant: &str) {
+        self.running += 1;
+        *self
+            .running_per_tenant
+            .entry(tenant.to_string())
+            .or_insert(0) += 1;
+    }
+
+    fn finish(&mut self, tenant: &str) {
+        self.running = self.running.saturating_sub(1);
+        if let Some(running) = self.running_per_tenant.get_mut(tenant) {
+            *running = running.saturating_sub(1);
+            if *running == 0 {
+                self.running_per_tenant.remove(tenant);
+            }
+        }
+    }
+
+    fn grant_waiting(&mut self) {
+        loop {
+            let next = self
+                .waiting
+                .iter()
+                .enumerate()
+                .filter(|(_, waiter)| self.has_room(&waiter.tenant))
+                .max_by(|(_, a), (_, b)| {
+                    let running = |tenant: &str| self.running_per_tenant.get(tenant).copied();
+                    a.priority
+                        .cmp(&b.priority)
+                        .then(running(&b.tenant).cmp(&running(&a.tenant)))
+                        .then(b.seq.cmp(&a.seq))
+                })
+                .map(|(index, _)| index);
+
+            let Some(index) = next else {
+                break;
+            };
+            let waiter = self.waiting.remove(index);
+            self.start(&waiter.tenant);
+            if waiter.state.send(WaitState::Granted).is_err() {
+                self.finish(&waiter.tenant);
+            }
+        }
+
+        self.publish_positions();
+        metrics::set_gauge("sync_sessions_running", self.running as i64);
+    }
+
+    fn publish_positions(&mut self) {
+        self.waiting
+            .sort_by(|a, b| b.priority.cmp(&a.priority).then(a.seq.cmp(&b.seq)));
+        for (index, waiter) in self.waiting.iter().enumerate() {
+            waiter.state.send_if_modified(|state| {
+                let position = WaitState::Queued(index + 1);
+                let changed = *state != position;
+                *state = position;
+                changed
+            });
+        }
+        metrics::set_gauge("sync_sessions_queued", self.waiting.len() as i64);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::sync_session::sync_session_local_storage::SyncSessionLocalStorage;
+use crate::metrics;
+use crate::rest;
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct SyncSession {
+    storage: Option<SyncSessionLocalStorage>,
+    user_id: String,
+    interrupted: Option<Value>,
+    cursors: Map<String, Value>,
+    resumed: Map<String, Value>,
+}
+
+impl SyncSession {
+    pub fn load(core_storage: Arc<CoreLocalStorage>, user_id: &str) -> Self {
+        let storage = match SyncSessionLocalStorage::new(core_storage) {
+            Ok(storage) => Some(storage),
+            Err(e) => {
+                println!("Failed to create sync session storage: {:?}", e);
+                None
+            }
+        };
+        let interrupted = storage.as_ref().and_then(|storage| {
+            storage
+                .get_interrupted_session(user_id)
+                .unwrap_or_else(|e| {
+                    println!("Failed to load sync session of {}: {:?}", user_id, e);
+                    None
+                })
+        });
+
+        SyncSession {
+            storage,
+            user_id: user_id.to_string(),
+            interrupted,
+            cursors: Map::new(),
+            resumed: Map::new(),
+        }
+    }
+
+    pub fn cursor(&mut self, entity: &str, requested: i64) -> i64 {
+        let previous = self
+            .interrupted
+            .as_ref()
+            .and_then(|session| session["cursors"].get(entity));
+        let from = previous.and_then(|cursor| cursor["from"].as_i64());
+        let reached = previous.and_then(|cursor| cursor["reached"].as_i64());
+
+        match (from, reached) {
+            (Some(from), Some(reached)) if from <= requested && requested < reached => {
+                self.cursors.insert(
+                    entity.to_string(),
+                    json!({ "from": from, "reached": reached }),
+                );
+                self.resumed.insert(
+                    entity.to_string(),
+                    json!({ "requested": requested, "resumedFrom": reached }),
+                );
+                reached
+            }
+            _ => {
+                self.cursors
+                    .insert(entity.to_string(), json!({ "from": requested }));
+                requested
+            }
+        }
+    }
+
+    pub fn start(&self, client_id: &str) -> Option<Value> {
+        let now = chrono::Utc::now().timestamp_millis();
+        if let Some(storage) = &self.storage
+            && let Err(e) = storage.start_session(
+                &self.user_id,
+                client_id,
+                now,
+                &Value::Object(self.cursors.clone()),
+            )
+        {
+            println!(
+                "Failed to persist sync session of {}: {:?}",
+                self.user_id, e
+            );
+        }
+
+        let interrupted = self.interrupted.as_ref()?;
+        if self.resumed.is_empty() {
+            return None;
+        }
+        metrics::increment("sync_sessions_resumed_total");
+        println!(
+            "Resuming interrupted sync of {} from session started at {} ({} entities)",
+            self.user_id,
+            interrupted["startedAt"],
+            self.resumed.len()
+        );
+
+        Some(json!({
+            "startedAt": interrupted["startedAt"],
+            "interruptedAt": interrupted["updatedAt"],
+            "entities": self.resumed
+        }))
+    }
+
+    pub fn record(&mut self, entity: &str, reached: i64) {
+        let Some(cursor) = self.cursors.get_mut(entity) else {
+            return;
+        };
+        if cursor["reached"]
+            .as_i64()
+            .is_some_and(|known| known >= reached)
+        {
+            return;
+        }
+        cursor["reached"] = json!(reached);
+
+        if let Some(storage) = &self.storage
+            && let Err(e) = storage.update_cursors(
+                &self.user_id,
+                &Value::Object(self.cursors.clone()),
+                chrono::Utc::now().timestamp_millis(),
+            )
+        {
+            println!(
+                "Failed to record {} sync cursor of {}: {:?}",
+                entity, self.user_id, e
+            );
+        }
+    }
+
+    pub fn complete(&self) {
+        if let Some(storage) = &self.storage
+            && let Err(e) =
+                storage.complete_session(&self.user_id, chrono::Utc::now().timestamp_millis())
+        {
+            println!(
+                "Failed to complete sync session of {}: {:?}",
+                self.user_id, e
+            );
+        }
+    }
+}
+
+/// Checkpoints are the dates a client reached page by page during a sync. They
+/// are stored per user and device when the client disconnects mid-sync, so a
+/// sync_resume from the same device continues after the last delivered page
+/// instead of the start of the entity.
+pub fn save_checkpoints(
+    tenant: &str,
+    user_id: &str,
+    device_id: &str,
+    checkpoints: &HashMap<String, i64>,
+) {
+    let saved = rest::open_storage(tenant)
+        .and_then(SyncSessionLocalStorage::new)
+        .and_then(|storage| {
+            storage.save_checkpoints(
+                user_id,
+                device_id,
+                checkpoints,
+                chrono::Utc::now().timestamp_millis(),
+            )
+        });
+    match saved {
+        Ok(()) => metrics::increment("sync_checkpoints_saved_total"),
+        Err(e) => println!("Failed to save sync checkpoints of {}: {:?}", user_id, e),
+    }
+}
+
+pub fn load_checkpoints(
+    core_storage: Arc<CoreLocalStorage>,
+    user_id: &str,
+    device_id: &str,
+) -> HashMap<String, i64> {
+    SyncSessionLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_checkpoints(user_id, device_id))
+        .unwrap_or_else(|e| {
+            println!("Failed to load sync checkpoints of {}: {:?}", user_id, e);
+            HashMap::new()
+        })
+}
+
+pub fn clear_checkpoints(core_storage: Arc<CoreLocalStorage>, user_id: &str, device_id: &str) {
+    if let Err(e) = SyncSessionLocalStorage::new(core_storage)
+        .and_then(|storage| storage.clear_checkpoints(user_id, device_id))
+    {
+        println!("Failed to clear sync checkpoints of {}: {:?}", user_id, e);
+    }
+}
@@ -0,0 +1,345 @@
+use crate::config;
+use crate::local_storage::activity_feed::activity_feed_local_storage::ActivityFeedLocalStorage;
+use crate::local_storage::assortment::assortment_local_storage::AssortmentLocalStorage;
+use crate::local_storage::contract::contract_local_storage::ContractLocalStorage;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::crew::crew_local_storage::CrewLocalStorage;
+use crate::local_storage::location::location_local_storage::LocationLocalStorage;
+use crate::local_storage::note::note_local_storage::NoteLocalStorage;
+use crate::local_storage::photo::photo_local_storage::PhotoLocalStorage;
+use crate::local_storage::quota_reservation::quota_reservation_local_storage::QuotaReservationLocalStorage;
+use crate::local_storage::review_item::review_item_local_storage::ReviewItemLocalStorage;
+use crate::local_storage::sawmill::sawmill_local_storage::SawmillLocalStorage;
+use crate::local_storage::sawmill_price::sawmill_price_local_storage::SawmillPriceLocalStorage;
+use crate::local_storage::shipment::shipment_local_storage::ShipmentLocalStorage;
+use crate::local_storage::tour::tour_local_storage::TourLocalStorage;
+use crate::local_storage::user::user_local_storage::UserLocalStorage;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::snapshot::Snapshot;
+use crate::storage::{self, Root};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, get_db_path, with_db_pools};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use futures_util::stream;
+use serde_json::{Map, Value, json};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use uuid::Uuid;
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+const SYNC_SNAPSHOT_PREFIX: &str = "holz-sync-";
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let create = warp::path!("sync-snapshots")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .and_then(|authorization, db_pools: DbPoolMap| async move {
+            Ok::<_, Rejection>(rest::into_reply(
+                create_snapshot(authorization, db_pools).await,
+            ))
+        });
+
+    let download = warp::path!("sync-snapshots" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("range"))
+        .and(with_db_pools(db_pools))
+        .and_then(
+            |id: String, authorization, range: Option<String>, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    download_snapshot(id, authorization, range, db_pools).await,
+                ))
+            },
+        );
+
+    create.or(download).unify()
+}
+
+async fn create_snapshot(
+    authorization: Option<String>,
+    db_pools: DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, &db_pools, 0)?;
+    let id = Uuid::new_v4().to_string();
+    let path = snapshot_path(&user.tenant, &id);
+    let db_path = get_db_path(&user.tenant);
+    let include_review_items = user.role >= ROLE_PRIVILEGED;
+
+    let build_path = path.clone();
+    let cursors = tokio::task::spawn_blocking(move || {
+        cleanup_expired_snapshots();
+        write_snapshot(&db_path, &build_path, include_review_items)
+    })
+    .await
+    .map_err(|e| format!("{:?}", e))
+    .and_then(|result| result);
+
+    let cursors = match cursors {
+        Ok(cursors) => cursors,
+        Err(e) => {
+            println!("Failed to build sync snapshot: {}", e);
+            let _ = fs::remove_file(&path);
+            return Err(rest::error_reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "INTERNAL",
+                "Failed to build snapshot",
+            ));
+        }
+    };
+
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let ttl_millis = (config::get().snapshots.download_ttl_secs * 1000) as i64;
+    metrics::increment("sync_snapshots_created_total");
+    println!(
+        "Built sync snapshot {} for tenant {} ({} bytes)",
+        id, user.tenant, size
+    );
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&json!({
+            "id": id,
+            "size": size,
+            "cursors": cursors,
+            "expiresAt": chrono::Utc::now().timestamp_millis() + ttl_millis
+        })),
+        StatusCode::CREATED,
+    ))
+}
+
+async fn download_snapshot(
+    id: String,
+    authorization: Option<String>,
+    range: Option<String>,
+    db_pools: DbPoolMap,
+) -> Result<Response<Body>, ErrorReply> {
+    let user = rest::authenticate(authorization, &db_pools, 0)?;
+    let not_found = || rest::error_reply(StatusCode::NOT_FOUND, "NOT_FOUND", "Snapshot not found");
+
+    if Uuid::parse_str(&id).is_err() {
+        return Err(not_found());
+    }
+
+    let path = snapshot_path(&user.tenant, &id);
+    let mut file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|_| not_found())?;
+    let size = file.metadata().await.map_err(|_| not_found())?.len();
+
+    let (start, end) = match range.as_deref().map(|range| rest::parse_range(range, size)) {
+        None => (0, size.saturating_sub(1)),
+        Some(Some(bounds)) => bounds,
+        Some(None) => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("Content-Range", format!("bytes */{}", size))
+                .body(Body::empty())
+                .map_err(|e| response_error(e.to_string()));
+        }
+    };
+    let length = if size == 0 { 0 } else { end - start + 1 };
+
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|e| response_error(e.to_string()))?;
+
+    let body = Body::wrap_stream(stream::unfold(
+        (file, length),
+        |(mut file, remaining)| async move {
+            if remaining == 0 {
+                return None;
+            }
+            let mut buffer = vec![0; CHUNK_SIZE.min(remaining as usize)];
+            match file.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    buffer.truncate(read);
+                    Some((Ok(buffer), (file, remaining - read as u64)))
+                }
+                Err(e) => Some((Err(e), (file, 0))),
+            }
+        },
+    ));
+
+    let mut response = Response::builder()
+        .header("Content-Type", "application/gzip")
+        .header("Accept-Ranges", "bytes")
+        .header("Content-Length", length)
+        .header("ETag", format!("\"{}\"", id))
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}-{}.jsonl.gz\"", user.tenant, id),
+        );
+    if range.is_some() {
+        response = response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header("Content-Range", format!("bytes {}-{}/{}", start, end, size));
+    }
+
+    response
+        .body(body)
+        .map_err(|e| response_error(e.to_string()))
+}
+
+fn response_error(e: String) -> ErrorReply {
+    println!("Failed to build snapshot response: {}", e);
+    rest::error_reply(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "INTERNAL",
+        "Snapshot download failed",
+    )
+}
+
+fn snapshot_path(tenant: &str, id: &str) -> PathBuf {
+    storage::dir(Root::Backups).join(format!(
+        "{}{}-{}.jsonl.gz",
+        SYNC_SNAPSHOT_PREFIX, tenant, id
+    ))
+}
+
+fn write_snapshot(
+    db_path: &str,
+    path: &PathBuf,
+    include_review_items: bool,
+) -> Result<Map<String, Value>, String> {
+    let snapshot = Snapshot::create(db_path).map_err(|e| format!("{:?}", e))?;
+    let snapshot_path = snapshot.path().to_string_lossy().to_string();
+    let core_storage = CoreLocalStorage::new(&snapshot_path)
+        .map(Arc::new)
+        .map_err(|e| format!("{:?}", e))?;
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut out = GzEncoder::new(BufWriter::new(file), Compression::default());
+    let mut cursors = Map::new();
+
+    macro_rules! entity {
+        ($msg_type:expr, $storage:ident, $fetch:ident) => {{
+            let storage = $storage::new(core_storage.clone()).map_err(|e| format!("{:?}", e))?;
+            let cursor = write_entity(&mut out, $msg_type, |date| storage.$fetch(date))?;
+            cursors.insert($msg_type.to_string(), json!(cursor));
+        }};
+    }
+
+    entity!("user_update", UserLocalStorage, get_user_updates_by_date);
+    entity!(
+        "assortment_update",
+        AssortmentLocalStorage,
+        get_assortment_updates_by_date
+    );
+    entity!(
+        "sawmill_update",
+        SawmillLocalStorage,
+        get_sawmill_updates_by_date
+    );
+    entity!("crew_update", CrewLocalStorage, get_crew_updates_by_date);
+    entity!("tour_update", TourLocalStorage, get_tour_updates_by_date);
+    entity!(
+        "contract_update",
+        ContractLocalStorage,
+        get_contract_updates_by_date
+    );
+    entity!(
+        "location_update",
+        LocationLocalStorage,
+        get_location_updates_by_date
+    );
+    entity!(
+        "shipment_update",
+        ShipmentLocalStorage,
+        get_shipments_by_date
+    );
+    entity!("note_update", NoteLocalStorage, get_note_updates_by_date);
+    entity!("photo_update", PhotoLocalStorage, get_photo_updates_by_date);
+    entity!(
+        "activity_update",
+        ActivityFeedLocalStorage,
+        get_activity_updates_by_date
+    );
+    if include_review_items {
+        entity!(
+            "review_item_update",
+            ReviewItemLocalStorage,
+            get_review_item_updates_by_date
+        );
+        entity!(
+            "sawmill_price_update",
+            SawmillPriceLocalStorage,
+            get_price_updates_by_date
+        );
+        entity!(
+            "quota_reservation_update",
+            QuotaReservationLocalStorage,
+            get_reservation_updates_by_date
+        );
+    }
+
+    let complete = json!({ "type": "snapshot_complete", "data": { "cursors": cursors } });
+    writeln!(out, "{}", complete).map_err(|e| e.to_string())?;
+    out.finish()
+        .and_then(|mut writer| writer.flush())
+        .map_err(|e| e.to_string())?;
+
+    Ok(cursors)
+}
+
+fn write_entity(
+    out: &mut impl Write,
+    msg_type: &str,
+    fetch: impl Fn(i64) -> rusqlite::Result<Vec<Value>>,
+) -> Result<i64, String> {
+    let mut date = 0;
+
+    loop {
+        let rows = fetch(date).map_err(|e| format!("{:?}", e))?;
+        if rows.is_empty() {
+            return Ok(date);
+        }
+
+        for row in &rows {
+            let line = json!({ "type": msg_type, "data": row });
+            writeln!(out, "{}", line).map_err(|e| e.to_string())?;
+            if let Some(newest_date) = row["arrivalAtServer"].as_i64()
+                && date <= newest_date
+            {
+                date = newest_date + 1;
+            }
+        }
+    }
+}
+
+pub fn cleanup_expired_snapshots() {
+    let ttl = Duration::from_secs(config::get().snapshots.download_ttl_secs);
+    let Ok(entries) = fs::read_dir(storage::dir(Root::Backups)) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let is_sync_snapshot = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(SYNC_SNAPSHOT_PREFIX));
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > ttl);
+
+        if is_sync_snapshot && expired {
+            println!("Removing expired sync snapshot {:?}", entry.path());
+            if let Err(e) = fs::remove_file(entry.path()) {
+                eprintln!("Failed to remove sync snapshot {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use crate::get_db_path;
+use crate::local_storage::schema;
+use chrono::{Datelike, Duration, TimeZone, Utc};
+use rusqlite::{Connection, Transaction, params};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+const DISTRICT_SPREAD_DEGREES: f64 = 0.12;
+const SITE_SPREAD_DEGREES: f64 = 0.03;
+const MAX_PHOTO_BYTES: usize = 20 * 1024 * 1024;
+
+const SAWMILL_NAMES: &[&str] = &[
+    "Sägewerk Huber",
+    "Holzindustrie Ziegler",
+    "Sägewerk Brunner",
+    "Holzwerk Gebr. Maier",
+    "Säge Hintermoser",
+    "Holz Pfeifer Kulmbach",
+    "Sägewerk Rottmoos",
+    "Bayerwald Holz",
+];
+
+const FOREST_NAMES: &[&str] = &[
+    "Staatswald",
+    "Gemeindewald",
+    "Privatwald",
+    "Kirchenwald",
+    "Körperschaftswald",
+];
+
+const FOREST_DISTRICTS: &[(&str, f64, f64)] = &[
+    ("Ebersberg", 48.08, 11.97),
+    ("Freising", 48.40, 11.74),
+    ("Regen", 48.97, 13.13),
+    ("Zwiesel", 49.02, 13.23),
+    ("Miesbach", 47.79, 11.83),
+    ("Rosenheim", 47.86, 12.12),
+    ("Landsberg", 48.05, 10.87),
+    ("Wunsiedel", 50.04, 12.00),
+    ("Cham", 49.22, 12.66),
+    ("Amberg", 49.44, 11.86),
+    ("Bad Tölz", 47.76, 11.56),
+    ("Kelheim", 48.92, 11.87),
+    ("Spessart", 49.95, 9.45),
+    ("Steigerwald", 49.80, 10.50),
+];
+
+const ASSORTMENTS: &[&str] = &[
+    "Fichte Langholz",
+    "Fichte Abschnitte",
+    "Kiefer Abschnitte",
+    "Buche Stammholz",
+    "Lärche Langholz",
+    "Fichte Käferholz",
+];
+
+pub struct GenerateOptions {
+    pub tenant: String,
+    pub contracts: usize,
+    pub locations_per_contract: usize,
+    pub photos_per_location: usize,
+    pub photo_bytes: usize,
+    pub drivers: usize,
+    pub season: Option<i32>,
+    pub seed: u64,
+    pub append: bool,
+}
+
+impl GenerateOptions {
+    pub fn new(tenant: &str) -> Result<Self, String> {
+        if tenant.is_empty()
+            || !tenant
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!("Invalid tenant name: {}", tenant));
+        }
+
+        Ok(GenerateOptions {
+            tenant: tenant.to_string(),
+            contracts: 10,
+            locations_per_contract: 8,
+            photos_per_location: 1,
+            photo_bytes: 200 * 1024,
+            drivers: 4,
+            season: None,
+            seed: 1,
+            append: false,
+        })
+    }
+
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        let number = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("{} requires a number", option))
+        };
+
+        match option {
+            "--demo-contracts" => self.contracts = number()?,
+            "--demo-locations" => self.locations_per_contract = number()?,
+            "--demo-photos" => self.photos_per_location = number()?,
+            "--demo-photo-bytes" => {
+                self.photo_bytes = number()?.min(MAX_PHOTO_BYTES);
+            }
+            "--demo-drivers" => self.drivers = number()?.max(1),
+            "--demo-season" => {
+                self.season = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("{} requires a year", option))?,
+                )
+            }
+            "--demo-seed" => {
+                self.seed = value
+                    .parse()
+                    .map_err(|_| format!("{} requires a number", option))?
+            }
+            _ => return Err(format!("Unknown argument: {}", option)),
+        }
+
+        Ok(())
+    }
+}
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, (min, max): (f64, f64)) -> f64 {
+        min + self.float() * (max - min)
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        (self.next() % len.max(1) as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, values: &[&'a str]) -> &'a str {
+        values[self.index(values.len())]
+    }
+
+    fn id(&mut self) -> String {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.next().to_le_bytes());
+        bytes[8..].copy_from_slice(&self.next().to_le_bytes());
+        uuid::Builder::from_random_bytes(bytes)
+            .into_uuid()
+            .to_string()
+    }
+
+    fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+fn season_bounds(season: Option<i32>) -> (i64, i64) {
+    let now = Utc::now();
+    let year = season.unwrap_or(if now.month() >= 4 {
+        now.year() - 1
+    } else {
+        now.year() - 2
+    });
+    let start = Utc
+        .with_ymd_and_hms(year, 10, 1, 6, 0, 0)
+        .single()
+        .unwrap_or(now);
+    let end = start + Duration::days(182);
+
+    (start.timestamp_millis(), end.timestamp_millis())
+}
+
+fn photo_file(rng: &mut Rng, size: usize) -> Vec<u8> {
+    const HEADER: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00, 0x01, 0x01, 0x00, 0x00,
+        0x01, 0x00, 0x01, 0x00, 0x00,
+    ];
+
+    let size = size.max(HEADER.len() + 2);
+    let mut bytes = vec![0u8; size];
+    bytes[..HEADER.len()].copy_from_slice(HEADER);
+    rng.fill(&mut bytes[HEADER.len()..size - 2]);
+    bytes[size - 2..].copy_from_slice(&[0xff, 0xd9]);
+    bytes
+}
+
+pub fn generate(options: &GenerateOptions) -> Result<Value, String> {
+    let started = Instant::now();
+    let db_path = get_db_path(&options.tenant);
+    if let Some(parent) = Path::new(&db_path).parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+
+    let mut conn =
+        Connection::open(&db_path).map_err(|e| format!("Failed to open {}: {}", db_path, e))?;
+    schema::migrate(&conn).map_err(|e| format!("Failed to migrate {}: {}", db_path, e))?;
+
+    let existing: i64 = conn
+        .query_row("SELECT COUNT(*) FROM contracts", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to inspect {}: {}", db_path, e))?;
+    if existing > 0 && !options.append {
+        return Err(format!(
+            "Tenant {} already has {} contracts, pass --demo-append to add to it",
+            options.tenant, existing
+        ));
+    }
+
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Failed to start transaction: {}", e))?;
+    let mut summary =
+        populate(&tx, options).map_err(|e| format!("Failed to generate data: {}", e))?;
+    tx.commit()
+        .map_err(|e| format!("Failed to commit generated data: {}", e))?;
+
+    summary["tenant"] = json!(options.tenant);
+    summary["elapsedMs"] = json!(started.elapsed().as_millis() as u64);
+    Ok(summary)
+}
+
+fn populate(tx: &Transaction, options: &GenerateOptions) -> rusqlite::Result<Value> {
+    let mut rng = Rng(options.seed);
+    let now = Utc::now().timestamp_millis();
+    let (season_start, season_end) = season_bounds(options.season);
+
+    let mut users = vec![("demo-admin".to_string(), "Demo Admin".to_string(), 2)];
+    users.push(("demo-office".to_string(), "Demo Büro".to_string(), 1));
+    for n in 1..=options.drivers {
+        users.push((format!("demo-driver{}", n), format!("Demo Fahrer {}", n), 0));
+    }
+    for (id, name, role) in &users {
+        tx.execute(
+            "INSERT OR IGNORE INTO users (id, lastEdit, role, name, arrivalAtServer, deleted)
+             VALUES (?, ?, ?, ?, ?, 0)",
+            params![id, now, role, name, now],
+        )?;
+    }
+    let drivers: Vec<&str> = users
+        .iter()
+        .filter(|(_, _, role)| *role == 0)
+        .map(|(id, _, _)| id.as_str())
+        .collect();
+
+    let mut sawmill_ids = Vec::new();
+    for name in SAWMILL_NAMES {
+        let id = rng.id();
+        tx.execute(
+            "INSERT INTO sawmills (id, lastEdit, name, arrivalAtServer, deleted)
+             VALUES (?, ?, ?, ?, 0)",
+            params![id, now, name, now],
+        )?;
+        sawmill_ids.push(id);
+    }
+
+    let mut counts = (0usize, 0usize, 0usize);
+    for contract_number in 1..=options.contracts {
+        let contract_id = rng.id();
+        let price = round(rng.range((58.0, 112.0)), 2);
+        let vat_rate = 0.055;
+        let (place, latitude, longitude) = FOREST_DISTRICTS[rng.index(FOREST_DISTRICTS.len())];
+        let spread = (-DISTRICT_SPREAD_DEGREES, DISTRICT_SPREAD_DEGREES);
+        let center = (latitude + rng.range(spread), longitude + rng.range(spread));
+
+        let mut booked = 0.0;
+        let mut shipped = 0.0;
+        let mut all_done = options.locations_per_contract > 0;
+        for _ in 0..options.locations_per_contract {
+            let location_id = rng.id();
+            let initial = round(rng.range((20.0, 220.0)), 1);
+            let oversize = round(initial * rng.range((0.0, 0.1)), 1);
+            let pieces = (initial / rng.range((0.6, 1.1))).round() as i64;
+            let date =
+                season_start + (rng.float() * (season_end - season_start) as f64 * 0.6) as i64;
+            let sawmills: Vec<&String> = (0..1 + rng.index(2))
+                .map(|_| &sawmill_ids[rng.index(sawmill_ids.len())])
+                .collect();
+
+            let mut remaining = initial;
+            let mut remaining_oversize = oversize;
+            let mut last_shipment = date;
+            while remaining > 0.5 && last_shipment < season_end {
+                last_shipment += (rng.range((1.0, 14.0)) * 86_400_000.0) as i64;
+                if last_shipment > season_end.min(now) {
+                    break;
+                }
+
+                let quantity = round(rng.range((18.0, 32.0)).min(remaining), 1);
+                let oversize_quantity =
+                    round((quantity * oversize / initial).min(remaining_oversize), 1);
+                remaining = round(remaining - quantity, 1);
+                remaining_oversize = round(remaining_oversize - oversize_quantity, 1);
+                let net = round(quantity * price, 2);
+                let vat = round(net * vat_rate, 2);
+
+                tx.execute(
+                    "INSERT INTO shipments (id, lastEdit, quantity, oversizeQuantity, pieceCount,
+                     userId, contractId, sawmillId, locationId, arrivalAtServer, deleted,
+                     additionalInfo, netValue, vatValue, grossValue, currency, unit)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, '', ?, ?, ?, 'EUR', 'fm')",
+                    params![
+                        rng.id(),
+                        last_shipment,
+                        quantity,
+                        oversize_quantity,
+                        (quantity / initial * pieces as f64).round() as i64,
+                        drivers[rng.index(drivers.len())],
+                        contract_id,
+                        sawmills[rng.index(sawmills.len())],
+                        location_id,
+                        last_shipment,
+                        net,
+                        vat,
+                        round(net + vat, 2)
+                    ],
+                )?;
+                shipped += quantity;
+                counts.2 += 1;
+            }
+
+            let done = remaining <= 0.5;
+            all_done &= done;
+            booked += initial;
+            let current_pieces = (remaining / initial * pieces as f64).round() as i64;
+            tx.execute(
+                "INSERT INTO locations (id, done, started, lastEdit, latitude, longitude, partieNr,
+                 date, additionalInfo, ownerInformation, initialQuantity, initialOversizeQuantity,
+                 initialPieceCount, currentQuantity, currentOversizeQuantity, currentPieceCount,
+                 contractId, arrivalAtServer, deleted, unit)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, 'fm')",
+                params![
+                    location_id,
+                    done as i64,
+                    (remaining < initial) as i64,
+                    last_shipment,
+                    round(
+                        center.0 + rng.range((-SITE_SPREAD_DEGREES, SITE_SPREAD_DEGREES)),
+                        6
+                    ),
+                    round(
+                        center.1 + rng.range((-SITE_SPREAD_DEGREES, SITE_SPREAD_DEGREES)),
+                        6
+                    ),
+                    format!("{}", 10_000 + rng.index(90_000)),
+                    date,
+                    rng.pick(ASSORTMENTS),
+                    format!("{} {}", rng.pick(FOREST_NAMES), place),
+                    initial,
+                    oversize,
+                    pieces,
+                    remaining.max(0.0),
+                    remaining_oversize.max(0.0),
+                    current_pieces.max(0),
+                    contract_id,
+                    last_shipment
+                ],
+            )?;
+            for (index, sawmill_id) in sawmills.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO locationSawmillJunction (locationId, sawmillId, isOversize)
+                     VALUES (?, ?, ?)",
+                    params![location_id, sawmill_id, (index > 0) as i64],
+                )?;
+            }
+
+            for _ in 0..options.photos_per_location {
+                let photo = photo_file(&mut rng, options.photo_bytes);
+                let hash: String = Sha256::digest(&photo)
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                tx.execute(
+                    "INSERT INTO photos (id, lastEdit, photoFile, locationId, arrivalAtServer,
+                     deleted, photoHash)
+                     VALUES (?, ?, ?, ?, ?, 0, ?)",
+                    params![rng.id(), date, photo, location_id, date, hash],
+                )?;
+            }
+            counts.1 += 1;
+        }
+
+        let season_year = Utc
+            .timestamp_millis_opt(season_start)
+            .single()
+            .map(|start| start.year())
+            .unwrap_or_default();
+        tx.execute(
+            "INSERT INTO contracts (id, done, lastEdit, title, additionalInfo, startDate, endDate,
+             availableQuantity, bookedQuantity, shippedQuantity, arrivalAtServer, deleted,
+             pricePerCubicMeter, currency, vatRate)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, ?, 'EUR', ?)",
+            params![
+                contract_id,
+                all_done as i64,
+                now,
+                format!("{} {}/{:03}", place, season_year, contract_number),
+                format!("Synthetische Demodaten, Saison {}", season_year),
+                season_start,
+                season_end,
+                round(booked * rng.range((1.0, 1.25)), 1),
+                round(booked, 1),
+                round(shipped, 1),
+                now,
+                price,
+                vat_rate
+            ],
+        )?;
+        counts.0 += 1;
+    }
+
+    Ok(json!({
+        "users": users.len(),
+        "sawmills": sawmill_ids.len(),
+        "contracts": counts.0,
+        "locations": counts.1,
+        "shipments": counts.2,
+        "photos": counts.1 * options.photos_per_location,
+        "photoBytes": options.photo_bytes
+    }))
+}
@@ -0,0 +1,322 @@
+use crate::client_admin;
+use crate::metrics;
+use crate::rest::{self, ErrorReply, RestUser};
+use crate::wire_format;
+use crate::{Clients, DbPoolMap, ROLE_ADMIN, with_clients, with_db_pools};
+use rusqlite::params;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use warp::http::StatusCode;
+use warp::ws::Message;
+use warp::{Filter, Rejection, Reply};
+
+const CLOSE_CODE_DRAINING: u16 = 4002;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const MAX_TIMEOUT_SECS: u64 = 600;
+
+struct Drain {
+    reason: String,
+    started_at: i64,
+    started_by: String,
+    admin_ids: Vec<String>,
+}
+
+#[derive(Default)]
+struct State {
+    drains: HashMap<String, Drain>,
+    in_flight: HashMap<String, usize>,
+}
+
+static STATE: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::default()));
+
+pub struct ActivityGuard {
+    tenant: String,
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        if let Ok(mut state) = STATE.lock()
+            && let Some(in_flight) = state.in_flight.get_mut(&self.tenant)
+        {
+            *in_flight = in_flight.saturating_sub(1);
+            if *in_flight == 0 {
+                state.in_flight.remove(&self.tenant);
+            }
+        }
+    }
+}
+
+pub fn begin(tenant: &str) -> Option<ActivityGuard> {
+    let mut state = STATE.lock().ok()?;
+    if state.drains.contains_key(tenant) {
+        return None;
+    }
+
+    *state.in_flight.entry(tenant.to_string()).or_insert(0) += 1;
+    Some(ActivityGuard {
+        tenant: tenant.to_string(),
+    })
+}
+
+pub fn is_draining(tenant: &str) -> bool {
+    STATE
+        .lock()
+        .is_ok_and(|state| state.drains.contains_key(tenant))
+}
+
+pub fn in_flight(tenant: &str) -> usize {
+    STATE
+        .lock()
+        .map(|state| state.in_flight.get(tenant).copied().unwrap_or(0))
+        .unwrap_or(0)
+}
+
+pub fn route(
+    clients: Clients,
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let drain = warp::path!("admin" / "tenant" / "drain")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(rest::json_body(rest::MAX_JSON_BODY_BYTES))
+        .and(with_clients(clients))
+        .and(with_db_pools(db_pools.clone()))
+        .and_then(
+            |authorization, body, clients: Clients, db_pools: DbPoolMap| async move {
+                Ok::<_, Rejection>(rest::into_reply(
+                    drain_tenant(authorization, body, &clients, &db_pools).await,
+                ))
+            },
+        );
+
+    let status = warp::path!("admin" / "tenant" / "drain")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(drain_status(authorization, &db_pools))
+        });
+
+    let undrain = warp::path!("admin" / "tenant" / "undrain")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(undrain_tenant(authorization, &db_pools))
+        });
+
+    drain.or(status).unify().or(undrain).unify()
+}
+
+fn authorize(authorization: Option<String>, db_pools: &DbPoolMap) -> Result<RestUser, ErrorReply> {
+    let (tenant, user_id) = authorization
+        .as_deref()
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .and_then(|api_key| api_key.split_once('-'))
+        .unwrap_or_default();
+
+    let state = STATE.lock().map_err(|_| {
+        rest::error_reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL",
+            "Drain state unavailable",
+        )
+    })?;
+    match state.drains.get(tenant) {
+        Some(drain) if drain.admin_ids.iter().any(|id| id == user_id) => Ok(RestUser {
+            tenant: tenant.to_string(),
+            user_id: user_id.to_string(),
+            role: ROLE_ADMIN,
+            scopes: None,
+        }),
+        Some(_) => Err(rest::error_reply(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Missing or invalid API key",
+        )),
+        None => {
+            drop(state);
+            rest::authenticate(authorization, db_pools, ROLE_ADMIN)
+        }
+    }
+}
+
+fn drain_json(tenant: &str, drain: &Drain, in_flight: usize) -> Value {
+    json!({
+        "tenant": tenant,
+        "draining": true,
+        "reason": drain.reason,
+        "startedAt": drain.started_at,
+        "startedBy": drain.started_by,
+        "inFlight": in_flight
+    })
+}
+
+async fn drain_tenant(
+    authorization: Option<String>,
+    body: Value,
+    clients: &Clients,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = authorize(authorization, db_pools)?;
+    let reason = body["reason"]
+        .as_str()
+        .filter(|reason| !reason.trim().is_empty())
+        .unwrap_or("Tenant maintenance")
+        .to_string();
+    let timeout_secs = body["timeoutSecs"]
+        .as_u64()
+        .unwrap_or(DEFAULT_TIMEOUT_SECS)
+        .min(MAX_TIMEOUT_SECS);
+
+    if !is_draining(&user.tenant) {
+        let admin_ids = rest::open_storage(&user.tenant)
+            .and_then(|core_storage| {
+                let conn = core_storage.get_connection()?;
+                let mut stmt =
+                    conn.prepare("SELECT id FROM users WHERE role >= ? AND deleted = 0")?;
+                stmt.query_map(params![ROLE_ADMIN], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .map_err(|e| rest::internal_error("Failed to load tenant admins", e))?;
+
+        client_admin::audit(
+            &user,
+            "tenant_drain",
+            "tenant",
+            &user.tenant,
+            &json!({ "reason": reason }),
+        );
+
+        if let Ok(mut state) = STATE.lock() {
+            state.drains.insert(
+                user.tenant.clone(),
+                Drain {
+                    reason: reason.clone(),
+                    started_at: chrono::Utc::now().timestamp_millis(),
+                    started_by: user.user_id.clone(),
+                    admin_ids,
+                },
+            );
+        }
+        metrics::increment("tenant_drains_total");
+        println!(
+            "Admin {} started draining tenant {}: {}",
+            user.user_id, user.tenant, reason
+        );
+    }
+
+    let disconnected = disconnect_tenant_clients(&user.tenant, &reason, clients);
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout_secs);
+    while in_flight(&user.tenant) > 0 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let remaining = in_flight(&user.tenant);
+    if remaining > 0 {
+        return Err(rest::error_reply(
+            StatusCode::CONFLICT,
+            "DRAIN_INCOMPLETE",
+            &format!(
+                "{} requests are still in flight, retry to keep waiting",
+                remaining
+            ),
+        ));
+    }
+
+    let pool_closed = match db_pools.lock() {
+        Ok(mut pools) => pools.remove(&user.tenant).is_some(),
+        Err(_) => false,
+    };
+    println!(
+        "Tenant {} drained, {} clients disconnected",
+        user.tenant, disconnected
+    );
+
+    Ok(rest::json_reply(&json!({
+        "tenant": user.tenant,
+        "draining": true,
+        "disconnected": disconnected,
+        "poolClosed": pool_closed
+    })))
+}
+
+fn disconnect_tenant_clients(tenant: &str, reason: &str, clients: &Clients) -> usize {
+    let Ok(clients_lock) = clients.lock() else {
+        return 0;
+    };
+
+    let frame = wire_format::Shared::new(json!({
+        "type": "tenant_draining",
+        "data": { "reason": reason },
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    }));
+    let mut disconnected = 0;
+    for client in clients_lock
+        .values()
+        .filter(|client| client.db_name == tenant)
+    {
+        let _ = client.sender.send(frame.frame(&client.msgpack));
+        let _ = client
+            .sender
+            .send(Message::close_with(CLOSE_CODE_DRAINING, reason.to_string()));
+        client.disconnect.notify_one();
+        disconnected += 1;
+    }
+    disconnected
+}
+
+fn drain_status(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = authorize(authorization, db_pools)?;
+    let status = STATE
+        .lock()
+        .ok()
+        .and_then(|state| {
+            let in_flight = state.in_flight.get(&user.tenant).copied().unwrap_or(0);
+            state
+                .drains
+                .get(&user.tenant)
+                .map(|drain| drain_json(&user.tenant, drain, in_flight))
+        })
+        .unwrap_or_else(|| json!({ "tenant": user.tenant, "draining": false }));
+
+    Ok(rest::json_reply(&status))
+}
+
+fn undrain_tenant(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = authorize(authorization, db_pools)?;
+    let drain = STATE
+        .lock()
+        .ok()
+        .and_then(|mut state| state.drains.remove(&user.tenant))
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::CONFLICT,
+                "NOT_DRAINING",
+                "Tenant is not being drained",
+            )
+        })?;
+
+    println!("Admin {} undrained tenant {}", user.user_id, user.tenant);
+    client_admin::audit(
+        &user,
+        "tenant_undrain",
+        "tenant",
+        &user.tenant,
+        &json!({ "reason": drain.reason, "startedAt": drain.started_at }),
+    );
+
+    Ok(rest::json_reply(&json!({
+        "tenant": user.tenant,
+        "draining": false
+    })))
+}
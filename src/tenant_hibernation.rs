@@ -0,0 +1,226 @@
+use crate::config;
+use crate::event_stream;
+use crate::local_storage::activity_report::activity_report_local_storage::ActivityReportLocalStorage;
+use crate::metrics;
+use crate::rest;
+use crate::sla_stats;
+use crate::tenant_drain;
+use crate::{Clients, DbPoolMap};
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+use std::time::UNIX_EPOCH;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+struct Hibernation {
+    since: i64,
+    idle_since: i64,
+}
+
+#[derive(Default)]
+struct State {
+    last_seen: HashMap<String, i64>,
+    hibernated: HashMap<String, Hibernation>,
+}
+
+static STATE: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::default()));
+
+pub fn is_hibernating(tenant: &str) -> bool {
+    STATE
+        .lock()
+        .is_ok_and(|state| state.hibernated.contains_key(tenant))
+}
+
+pub fn hibernated_since(tenant: &str) -> Option<i64> {
+    STATE
+        .lock()
+        .ok()?
+        .hibernated
+        .get(tenant)
+        .map(|hibernation| hibernation.since)
+}
+
+pub fn touch(tenant: &str) {
+    if let Ok(mut state) = STATE.lock() {
+        state
+            .last_seen
+            .insert(tenant.to_string(), chrono::Utc::now().timestamp_millis());
+    }
+}
+
+pub fn wake(tenant: &str) -> bool {
+    let now = chrono::Utc::now().timestamp_millis();
+    let Ok(mut state) = STATE.lock() else {
+        return false;
+    };
+    state.last_seen.insert(tenant.to_string(), now);
+    let Some(hibernation) = state.hibernated.remove(tenant) else {
+        return false;
+    };
+    metrics::set_gauge("tenants_hibernated", state.hibernated.len() as i64);
+    drop(state);
+
+    metrics::increment("tenant_wakes_total");
+    println!(
+        "Waking tenant {} after {} minute(s) of hibernation (idle since {})",
+        tenant,
+        (now - hibernation.since) / 60_000,
+        hibernation.idle_since
+    );
+    true
+}
+
+pub fn spawn(clients: Clients, db_pools: DbPoolMap) -> Option<JoinHandle<()>> {
+    let settings = &config::get().hibernation;
+    if !settings.enabled {
+        return None;
+    }
+
+    let period = Duration::from_secs(settings.check_interval_secs);
+    Some(tokio::task::spawn(async move {
+        let mut ticker = interval(period);
+
+        loop {
+            ticker.tick().await;
+            let connected = connected_tenants(&clients);
+            let db_pools = db_pools.clone();
+            if let Err(e) =
+                tokio::task::spawn_blocking(move || hibernate_idle_tenants(&connected, &db_pools))
+                    .await
+            {
+                eprintln!("Hibernation check failed: {:?}", e);
+            }
+        }
+    }))
+}
+
+fn connected_tenants(clients: &Clients) -> HashSet<String> {
+    clients
+        .lock()
+        .map(|clients_lock| {
+            clients_lock
+                .values()
+                .filter(|client| !client.db_name.is_empty())
+                .map(|client| client.db_name.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn hibernate_idle_tenants(connected: &HashSet<String>, db_pools: &DbPoolMap) {
+    let entries = match fs::read_dir(&config::get().databases_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read databases directory: {}", e);
+            return;
+        }
+    };
+
+    let cutoff =
+        chrono::Utc::now().timestamp_millis() - config::get().hibernation.idle_days as i64 * DAY_MS;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(tenant) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".db"))
+        else {
+            continue;
+        };
+        if connected.contains(tenant) {
+            touch(tenant);
+            continue;
+        }
+        if is_hibernating(tenant)
+            || tenant_drain::is_draining(tenant)
+            || tenant_drain::in_flight(tenant) > 0
+        {
+            continue;
+        }
+
+        let idle_since = last_seen(tenant, &path);
+        if idle_since > cutoff || !event_stream::release(tenant) {
+            continue;
+        }
+        hibernate(tenant, idle_since, &path, db_pools);
+    }
+}
+
+fn last_seen(tenant: &str, path: &Path) -> i64 {
+    if let Some(last_seen) = STATE
+        .lock()
+        .ok()
+        .and_then(|state| state.last_seen.get(tenant).copied())
+    {
+        return last_seen;
+    }
+
+    let stored = rest::open_storage(tenant)
+        .and_then(ActivityReportLocalStorage::new)
+        .and_then(|storage| storage.get_last_seen());
+    let last_seen = match stored {
+        Ok(Some(last_seen)) => Some(last_seen),
+        Ok(None) => modified_at(path),
+        Err(e) => {
+            eprintln!("Failed to read last activity of tenant {}: {:?}", tenant, e);
+            modified_at(path)
+        }
+    }
+    .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    if let Ok(mut state) = STATE.lock() {
+        state
+            .last_seen
+            .entry(tenant.to_string())
+            .or_insert(last_seen);
+    }
+    last_seen
+}
+
+fn modified_at(path: &Path) -> Option<i64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as i64)
+}
+
+fn hibernate(tenant: &str, idle_since: i64, path: &Path, db_pools: &DbPoolMap) {
+    sla_stats::flush(Some(tenant));
+
+    let hibernated = match STATE.lock() {
+        Ok(mut state) => {
+            state.hibernated.insert(
+                tenant.to_string(),
+                Hibernation {
+                    since: chrono::Utc::now().timestamp_millis(),
+                    idle_since,
+                },
+            );
+            metrics::set_gauge("tenants_hibernated", state.hibernated.len() as i64);
+            state.hibernated.len()
+        }
+        Err(_) => return,
+    };
+
+    let pool = db_pools
+        .lock()
+        .ok()
+        .and_then(|mut pools| pools.remove(tenant));
+    drop(pool);
+    if let Err(e) = Connection::open(path)
+        .and_then(|conn| conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)"))
+    {
+        eprintln!(
+            "Failed to checkpoint hibernating tenant {}: {:?}",
+            tenant, e
+        );
+    }
+
+    metrics::increment("tenant_hibernations_total");
+    println!(
+        "Hibernated tenant {} (idle since {}, {} tenant(s) hibernating)",
+        tenant, idle_since, hibernated
+    );
+}
@@ -0,0 +1,223 @@
+use crate::auth_challenge::{self, CHALLENGE_PROTOCOL_VERSION};
+use crate::config::{self, Config};
+use crate::{ROLE_ADMIN, get_db_path, get_db_pool, serve};
+use rusqlite::{Connection, params};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, timeout};
+use uuid::Uuid;
+
+const ADMIN_USER: &str = "fixture_admin";
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub url: String,
+    pub ws_url: String,
+    pub tenant: String,
+    pub admin_api_key: String,
+    pub databases_dir: PathBuf,
+    shutdown: Option<oneshot::Sender<()>>,
+    server: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    pub fn api_key(&self, user_id: &str) -> String {
+        format!("{}-{}", self.tenant, user_id)
+    }
+
+    pub fn add_user(&self, user_id: &str, name: &str, role: i64) -> Result<String, String> {
+        insert_user(&self.tenant, user_id, name, role)
+            .map_err(|e| format!("Failed to add user {}: {:?}", user_id, e))?;
+        Ok(self.api_key(user_id))
+    }
+
+    /// The authentication_request answering an authentication_challenge
+    /// with `nonce` for `api_key`, without a device.
+    pub fn authentication_request(&self, api_key: &str, nonce: &str) -> Value {
+        self.device_authentication_request(api_key, None, api_key, nonce)
+    }
+
+    /// The authentication_request of `device_id` signed with `key`: the API
+    /// key while the device enrols, its deviceSecret afterwards.
+    pub fn device_authentication_request(
+        &self,
+        api_key: &str,
+        device_id: Option<&str>,
+        key: &str,
+        nonce: &str,
+    ) -> Value {
+        let (tenant, user_id) = api_key.split_once('-').unwrap_or((api_key, ""));
+        json!({
+            "type": "authentication_request",
+            "version": 1,
+            "data": {
+                "tenant": tenant,
+                "userId": user_id,
+                "deviceId": device_id,
+                "protocolVersion": CHALLENGE_PROTOCOL_VERSION,
+                "challenge": {
+                    "nonce": nonce,
+                    "signature": auth_challenge::hex(&auth_challenge::hmac_sha256(
+                        key.as_bytes(),
+                        nonce.as_bytes()
+                    ))
+                }
+            }
+        })
+    }
+
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(mut server) = self.server.take()
+            && timeout(SHUTDOWN_TIMEOUT, &mut server).await.is_err()
+        {
+            println!(
+                "Test fixture {} did not shut down within {:?}, aborting",
+                self.url, SHUTDOWN_TIMEOUT
+            );
+            server.abort();
+        }
+        self.remove_database();
+    }
+
+    fn remove_database(&self) {
+        let db_path = get_db_path(&self.tenant);
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let path = format!("{}{}", db_path, suffix);
+            if let Err(e) = fs::remove_file(&path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                eprintln!("Failed to remove test fixture database {}: {}", path, e);
+            }
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.abort();
+            self.remove_database();
+        }
+    }
+}
+
+pub(crate) fn configure(config: &mut Config) {
+    config.databases_dir =
+        std::env::temp_dir().join(format!("holz-logistik-fixture-{}", Uuid::new_v4().simple()));
+    config.instance.coordination_enabled = false;
+}
+
+fn insert_user(tenant: &str, user_id: &str, name: &str, role: i64) -> rusqlite::Result<()> {
+    let conn = Connection::open(get_db_path(tenant))?;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    conn.execute(
+        "INSERT INTO users (id, lastEdit, role, name, arrivalAtServer, deleted)
+         VALUES (?, ?, ?, ?, ?, 0)",
+        params![user_id, now, role, name, now],
+    )?;
+    Ok(())
+}
+
+fn create_tenant(tenant: &str) -> rusqlite::Result<()> {
+    get_db_pool(tenant, &Arc::new(Mutex::new(HashMap::new())))?;
+    insert_user(tenant, ADMIN_USER, "Fixture Admin", ROLE_ADMIN)
+}
+
+pub async fn spawn() -> Result<TestServer, String> {
+    let config = config::init_with(|| {
+        let mut config = Config::load(None)?;
+        configure(&mut config);
+        Ok(config)
+    })?;
+    fs::create_dir_all(&config.databases_dir)
+        .map_err(|e| format!("Failed to create {}: {}", config.databases_dir.display(), e))?;
+
+    let tenant = format!("fixture_{}", Uuid::new_v4().simple());
+    create_tenant(&tenant)
+        .map_err(|e| format!("Failed to create test fixture tenant {}: {:?}", tenant, e))?;
+
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    let (addr, server) = serve(([127, 0, 0, 1], 0).into(), async move {
+        let _ = shutdown_rx.await;
+    })
+    .map_err(|e| format!("Failed to bind test fixture server: {}", e))?;
+    let server = tokio::task::spawn(server);
+
+    let test_server = TestServer {
+        addr,
+        url: format!("http://{}", addr),
+        ws_url: format!("ws://{}/ws", addr),
+        admin_api_key: format!("{}-{}", tenant, ADMIN_USER),
+        tenant,
+        databases_dir: config.databases_dir.clone(),
+        shutdown: Some(shutdown),
+        server: Some(server),
+    };
+    println!(
+        "Test fixture serving tenant {} at {}",
+        test_server.tenant, test_server.url
+    );
+    Ok(test_server)
+}
+
+pub(crate) async fn run_until_interrupted() -> bool {
+    let server = match spawn().await {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+
+    println!(
+        "TEST FIXTURE READY {}",
+        json!({
+            "url": server.url,
+            "wsUrl": server.ws_url,
+            "tenant": server.tenant,
+            "adminApiKey": server.admin_api_key,
+            "databasesDir": server.databases_dir
+        })
+    );
+
+    interrupted().await;
+    server.shutdown().await;
+    if let Err(e) = fs::remove_dir(&config::get().databases_dir) {
+        eprintln!("Failed to remove test fixture directory: {}", e);
+    }
+    true
+}
+
+#[cfg(unix)]
+async fn interrupted() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn interrupted() {
+    let _ = tokio::signal::ctrl_c().await;
+}
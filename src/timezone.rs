@@ -0,0 +1,243 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::settings::settings_local_storage::{SettingsLocalStorage, TIMEZONE_KEY};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, Utc, Weekday};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const CENTRAL_EUROPEAN_ZONES: &[&str] = &[
+    "Europe/Vienna",
+    "Europe/Berlin",
+    "Europe/Zurich",
+    "Europe/Rome",
+    "Europe/Paris",
+    "Europe/Prague",
+    "Europe/Budapest",
+    "Europe/Ljubljana",
+    "Europe/Bratislava",
+    "Europe/Warsaw",
+    "Europe/Zagreb",
+    "Europe/Amsterdam",
+    "Europe/Brussels",
+    "Europe/Luxembourg",
+    "Europe/Madrid",
+    "Europe/Copenhagen",
+    "Europe/Oslo",
+    "Europe/Stockholm",
+];
+const WESTERN_EUROPEAN_ZONES: &[&str] = &["Europe/London", "Europe/Dublin", "Europe/Lisbon"];
+const EASTERN_EUROPEAN_ZONES: &[&str] = &[
+    "Europe/Helsinki",
+    "Europe/Tallinn",
+    "Europe/Riga",
+    "Europe/Vilnius",
+    "Europe/Athens",
+    "Europe/Bucharest",
+    "Europe/Sofia",
+];
+
+const DATE_FIELDS: &[(&str, &[&str])] = &[
+    ("location_update", &["date"]),
+    ("contract_update", &["startDate", "endDate"]),
+    ("sawmill_price_update", &["validFrom", "validTo"]),
+];
+
+const TIMESTAMP_FIELDS: &[&str] = &["lastEdit", "arrivalAtServer"];
+
+#[derive(Clone, Copy)]
+pub enum TenantTimezone {
+    Fixed(FixedOffset),
+    European {
+        name: &'static str,
+        standard_hours: i32,
+    },
+}
+
+impl TenantTimezone {
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("UTC") || name == "Z" {
+            return FixedOffset::east_opt(0).map(TenantTimezone::Fixed);
+        }
+
+        for (zones, standard_hours) in [
+            (WESTERN_EUROPEAN_ZONES, 0),
+            (CENTRAL_EUROPEAN_ZONES, 1),
+            (EASTERN_EUROPEAN_ZONES, 2),
+        ] {
+            if let Some(zone) = zones.iter().find(|zone| **zone == name) {
+                return Some(TenantTimezone::European {
+                    name: zone,
+                    standard_hours,
+                });
+            }
+        }
+
+        let (sign, offset) = match name
+            .strip_prefix("UTC")
+            .unwrap_or(name)
+            .split_at_checked(1)?
+        {
+            ("+", offset) => (1, offset),
+            ("-", offset) => (-1, offset),
+            _ => return None,
+        };
+        let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+        let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+        if seconds > 14 * 3600 {
+            return None;
+        }
+        FixedOffset::east_opt(sign * seconds).map(TenantTimezone::Fixed)
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            TenantTimezone::Fixed(offset) if offset.local_minus_utc() == 0 => "UTC".to_string(),
+            TenantTimezone::Fixed(offset) => format!("UTC{}", offset),
+            TenantTimezone::European { name, .. } => name.to_string(),
+        }
+    }
+
+    pub fn offset_at(&self, instant: DateTime<Utc>) -> FixedOffset {
+        match self {
+            TenantTimezone::Fixed(offset) => *offset,
+            TenantTimezone::European { standard_hours, .. } => {
+                let hours = if is_european_summer_time(instant) {
+                    standard_hours + 1
+                } else {
+                    *standard_hours
+                };
+                FixedOffset::east_opt(hours * 3600).unwrap_or_else(utc)
+            }
+        }
+    }
+
+    pub fn to_local(self, instant: DateTime<Utc>) -> DateTime<FixedOffset> {
+        instant.with_timezone(&self.offset_at(instant))
+    }
+
+    pub fn local_to_millis(&self, local: NaiveDateTime) -> i64 {
+        let standard = match self {
+            TenantTimezone::Fixed(offset) => *offset,
+            TenantTimezone::European { standard_hours, .. } => {
+                FixedOffset::east_opt(standard_hours * 3600).unwrap_or_else(utc)
+            }
+        };
+        let standard_utc = (local - Duration::seconds(standard.local_minus_utc() as i64)).and_utc();
+        let offset = self.offset_at(standard_utc);
+
+        (local - Duration::seconds(offset.local_minus_utc() as i64))
+            .and_utc()
+            .timestamp_millis()
+    }
+
+    pub fn start_of_day(&self, date: NaiveDate) -> i64 {
+        self.local_to_millis(date.and_hms_opt(0, 0, 0).unwrap_or_default())
+    }
+}
+
+pub const UTC: TenantTimezone = TenantTimezone::Fixed(utc());
+
+const fn utc() -> FixedOffset {
+    match FixedOffset::east_opt(0) {
+        Some(offset) => offset,
+        None => panic!("UTC offset out of range"),
+    }
+}
+
+fn is_european_summer_time(instant: DateTime<Utc>) -> bool {
+    let year = instant.year();
+    let change = |month| {
+        NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, 5)
+            .or_else(|| NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, 4))
+            .and_then(|date| date.and_hms_opt(1, 0, 0))
+            .map(|time| time.and_utc())
+    };
+
+    match (change(3), change(10)) {
+        (Some(start), Some(end)) => instant >= start && instant < end,
+        _ => false,
+    }
+}
+
+pub fn for_tenant(core_storage: Arc<CoreLocalStorage>) -> TenantTimezone {
+    SettingsLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_setting(TIMEZONE_KEY))
+        .ok()
+        .flatten()
+        .and_then(|name| TenantTimezone::parse(&name))
+        .unwrap_or(UTC)
+}
+
+pub fn parse_timestamp(value: &str, timezone: &TenantTimezone) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(millis) = value.parse::<i64>() {
+        return Some(millis);
+    }
+    if let Ok(instant) = DateTime::parse_from_rfc3339(value) {
+        return Some(instant.timestamp_millis());
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(local) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(timezone.local_to_millis(local));
+        }
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| timezone.start_of_day(date))
+}
+
+pub fn parse_range_end(value: &str, timezone: &TenantTimezone) -> Option<i64> {
+    match NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d") {
+        Ok(date) => date
+            .succ_opt()
+            .map(|next_day| timezone.start_of_day(next_day)),
+        Err(_) => parse_timestamp(value, timezone),
+    }
+}
+
+pub fn has_date_fields(msg_type: &str) -> bool {
+    DATE_FIELDS.iter().any(|(message, _)| *message == msg_type)
+}
+
+pub fn normalize_dates(msg_type: &str, data: &mut Value, timezone: &TenantTimezone) -> bool {
+    let Some((_, fields)) = DATE_FIELDS.iter().find(|(message, _)| *message == msg_type) else {
+        return false;
+    };
+
+    let mut normalized = false;
+    for field in *fields {
+        if let Some(Value::String(text)) = data.get(*field)
+            && let Some(millis) = parse_timestamp(text, timezone)
+        {
+            data[*field] = json!(millis);
+            normalized = true;
+        }
+    }
+
+    normalized
+}
+
+pub fn coerce_timestamps(data: &Value) -> Option<Value> {
+    if !TIMESTAMP_FIELDS
+        .iter()
+        .any(|field| data.get(*field).is_some_and(Value::is_string))
+    {
+        return None;
+    }
+
+    let mut data = data.clone();
+    for field in TIMESTAMP_FIELDS {
+        if let Some(Value::String(text)) = data.get(*field)
+            && let Some(millis) = parse_timestamp(text, &UTC)
+        {
+            data[*field] = json!(millis);
+        }
+    }
+
+    Some(data)
+}
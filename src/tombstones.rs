@@ -0,0 +1,84 @@
+use crate::ROLE_PRIVILEGED;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+const DEFAULT_LIMIT: usize = 500;
+const MAX_LIMIT: usize = 1000;
+
+const ENTITIES: &[(&str, &str, i64)] = &[
+    ("user", "users", 0),
+    ("contract", "contracts", 0),
+    ("sawmill", "sawmills", 0),
+    ("assortment", "assortments", 0),
+    ("crew", "crews", 0),
+    ("tour", "tours", 0),
+    ("location", "locations", 0),
+    ("shipment", "shipments", 0),
+    ("shipment_document", "shipmentDocuments", 0),
+    ("note", "notes", 0),
+    ("photo", "photos", 0),
+    ("review_item", "reviewItems", ROLE_PRIVILEGED),
+    ("sawmill_price", "sawmillPrices", ROLE_PRIVILEGED),
+    ("quota_reservation", "quotaReservations", ROLE_PRIVILEGED),
+];
+
+pub fn deleted_since(
+    data: &Value,
+    role: i64,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let entity = data["entity"].as_str().unwrap_or_default();
+    let (_, table, min_role) = ENTITIES
+        .iter()
+        .find(|(name, _, _)| *name == entity)
+        .ok_or_else(|| invalid(format!("Unknown entity: {}", entity)))?;
+    if role < *min_role {
+        return Err((
+            "PERMISSION_DENIED",
+            format!("Entity {} requires a privileged user", entity),
+        ));
+    }
+
+    let since = match data.get("since") {
+        None | Some(Value::Null) => 0,
+        Some(since) => since
+            .as_i64()
+            .ok_or_else(|| invalid("since must be a timestamp".to_string()))?,
+    };
+    let after_id = data["afterId"].as_str().unwrap_or_default();
+    let limit = match data.get("limit") {
+        None | Some(Value::Null) => DEFAULT_LIMIT,
+        Some(limit) => limit
+            .as_u64()
+            .filter(|limit| *limit > 0)
+            .ok_or_else(|| invalid("limit must be a positive integer".to_string()))?
+            .min(MAX_LIMIT as u64) as usize,
+    };
+
+    let mut tombstones = core_storage
+        .get_tombstones(table, since, after_id, limit + 1)
+        .map_err(|e| ("INTERNAL", format!("Failed to load tombstones: {:?}", e)))?;
+    let has_more = tombstones.len() > limit;
+    tombstones.truncate(limit);
+
+    let next_cursor = match tombstones.last() {
+        Some((id, deleted_at)) => json!({ "since": deleted_at, "afterId": id }),
+        None => json!({ "since": since, "afterId": after_id }),
+    };
+    let tombstones: Vec<Value> = tombstones
+        .into_iter()
+        .map(|(id, deleted_at)| json!({ "id": id, "deletedAt": deleted_at }))
+        .collect();
+
+    Ok(json!({
+        "entity": entity,
+        "tombstones": tombstones,
+        "nextCursor": next_cursor,
+        "hasMore": has_more
+    }))
+}
+
+fn invalid(message: String) -> (&'static str, String) {
+    ("VALIDATION_FAILED", message)
+}
@@ -0,0 +1,259 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::tour::tour_local_storage::TourLocalStorage;
+use crate::rest::{self, ErrorReply};
+use crate::{DbPoolMap, ROLE_PRIVILEGED, with_db_pools};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+pub const STATUS_PLANNED: &str = "planned";
+pub const STATUS_STARTED: &str = "started";
+pub const STATUS_FINISHED: &str = "finished";
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Checks a tour_update. Status changes go through tour_start and
+/// tour_finish, so updates keep the stored status and timestamps.
+pub fn validate_tour(tour: &mut Value, core_storage: Arc<CoreLocalStorage>) -> Result<(), String> {
+    let driver_id = tour["driverId"]
+        .as_str()
+        .filter(|driver_id| !driver_id.is_empty())
+        .ok_or_else(|| "driverId is required".to_string())?;
+    let driver = core_storage
+        .get_existing_by_id("users", driver_id)
+        .map_err(|e| format!("Failed to load driver: {}", e))?;
+    if driver.is_empty() {
+        return Err(format!("Unknown driver: {}", driver_id));
+    }
+    if tour["date"].as_i64().is_none() {
+        return Err("date must be a timestamp in milliseconds".to_string());
+    }
+    match tour.get("vehicle") {
+        None | Some(Value::Null) | Some(Value::String(_)) => {}
+        Some(_) => return Err("vehicle must be a string".to_string()),
+    }
+
+    let id = tour["id"].as_str().unwrap_or_default();
+    let existing = TourLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_tour_by_id(id))
+        .map_err(|e| format!("Failed to load tour: {}", e))?;
+    for field in ["status", "startedAt", "finishedAt"] {
+        tour[field] = existing
+            .as_ref()
+            .map(|existing| existing[field].clone())
+            .unwrap_or(Value::Null);
+    }
+    if tour["status"].is_null() {
+        tour["status"] = json!(STATUS_PLANNED);
+    }
+
+    Ok(())
+}
+
+/// Shipments can only be added to tours that exist and are not finished yet.
+pub fn check_shipment(shipment: &Value, core_storage: Arc<CoreLocalStorage>) -> Result<(), String> {
+    let tour_id = match shipment.get("tourId") {
+        None | Some(Value::Null) => return Ok(()),
+        Some(Value::String(tour_id)) => tour_id,
+        Some(_) => return Err("tourId must be a string".to_string()),
+    };
+
+    let shipment_id = shipment["id"].as_str().unwrap_or_default();
+    let previous = core_storage
+        .get_columns_by_id("shipments", shipment_id, &["tourId"])
+        .map_err(|e| format!("Failed to load shipment: {}", e))?;
+    if previous
+        .first()
+        .is_some_and(|previous| previous["tourId"].as_str() == Some(tour_id.as_str()))
+    {
+        return Ok(());
+    }
+
+    let tour = TourLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_tour_by_id(tour_id))
+        .map_err(|e| format!("Failed to load tour: {}", e))?
+        .ok_or_else(|| format!("Unknown tour: {}", tour_id))?;
+    if tour["status"] == STATUS_FINISHED {
+        return Err(format!("Tour {} is already finished", tour_id));
+    }
+
+    Ok(())
+}
+
+/// Handles tour_start and tour_finish. Drivers can only start and finish
+/// their own tours; privileged users any tour of the tenant.
+pub fn transition(
+    msg_type: &str,
+    data: &Value,
+    user_id: &str,
+    role: i64,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<Value, (&'static str, String)> {
+    let id = data["id"]
+        .as_str()
+        .ok_or(("VALIDATION_FAILED", "id is required".to_string()))?;
+    let storage = TourLocalStorage::new(core_storage)
+        .map_err(|e| ("TOUR_FAILED", format!("Failed to open tours: {:?}", e)))?;
+    let tour = storage
+        .get_tour_by_id(id)
+        .map_err(|e| ("TOUR_FAILED", format!("Failed to load tour: {:?}", e)))?
+        .ok_or(("NOT_FOUND", format!("Tour {} not found", id)))?;
+    if role < ROLE_PRIVILEGED && tour["driverId"].as_str() != Some(user_id) {
+        return Err((
+            "PERMISSION_DENIED",
+            format!("Tour {} is assigned to another driver", id),
+        ));
+    }
+
+    let (from, to, timestamp_column) = if msg_type == "tour_start" {
+        (STATUS_PLANNED, STATUS_STARTED, "startedAt")
+    } else {
+        (STATUS_STARTED, STATUS_FINISHED, "finishedAt")
+    };
+    let at = chrono::Utc::now().timestamp_millis();
+    let changed = storage
+        .transition(id, from, to, timestamp_column, at)
+        .map_err(|e| ("TOUR_FAILED", format!("Failed to update tour: {:?}", e)))?;
+    if !changed {
+        return Err((
+            "VALIDATION_FAILED",
+            format!(
+                "Tour {} is {}, expected {}",
+                id,
+                tour["status"].as_str().unwrap_or_default(),
+                from
+            ),
+        ));
+    }
+
+    storage
+        .get_tour_by_id(id)
+        .map_err(|e| ("TOUR_FAILED", format!("Failed to load tour: {:?}", e)))?
+        .ok_or(("NOT_FOUND", format!("Tour {} not found", id)))
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list = warp::path!("tours" / "summary")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_db_pools(db_pools.clone()))
+        .map(|authorization, query, db_pools: DbPoolMap| {
+            rest::into_reply(list_summaries(authorization, query, &db_pools))
+        });
+
+    let single = warp::path!("tours" / String / "summary")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|tour_id, authorization, db_pools: DbPoolMap| {
+            rest::into_reply(tour_summary(tour_id, authorization, &db_pools))
+        });
+
+    list.or(single).unify()
+}
+
+fn open_tours(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<TourLocalStorage, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_PRIVILEGED)?;
+    rest::open_storage(&user.tenant)
+        .and_then(TourLocalStorage::new)
+        .map_err(|e| rest::internal_error("Failed to open tours", e))
+}
+
+fn with_summary(storage: &TourLocalStorage, mut tour: Value) -> Result<Value, ErrorReply> {
+    let id = tour["id"].as_str().unwrap_or_default();
+    tour["summary"] = storage
+        .get_summary(id)
+        .map_err(|e| rest::internal_error("Failed to summarize tour", e))?;
+    Ok(tour)
+}
+
+fn tour_summary(
+    tour_id: String,
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let storage = open_tours(authorization, db_pools)?;
+    let tour = storage
+        .get_tour_by_id(&tour_id)
+        .map_err(|e| rest::internal_error("Failed to load tour", e))?
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                &format!("Tour {} not found", tour_id),
+            )
+        })?;
+
+    Ok(rest::json_reply(&with_summary(&storage, tour)?))
+}
+
+/// Summaries of the tours dated within `from`..`to` (milliseconds, default
+/// the current day), optionally of a single driver.
+fn list_summaries(
+    authorization: Option<String>,
+    query: HashMap<String, String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let storage = open_tours(authorization, db_pools)?;
+
+    let timestamp = |name: &str| {
+        query
+            .get(name)
+            .map(|value| {
+                value.parse::<i64>().map_err(|_| {
+                    rest::error_reply(
+                        StatusCode::BAD_REQUEST,
+                        "VALIDATION_FAILED",
+                        &format!("{} must be a timestamp in milliseconds", name),
+                    )
+                })
+            })
+            .transpose()
+    };
+    let now = chrono::Utc::now().timestamp_millis();
+    let from = timestamp("from")?.unwrap_or(now - now.rem_euclid(DAY_MILLIS));
+    let to = timestamp("to")?.unwrap_or(from + DAY_MILLIS);
+    if to <= from {
+        return Err(rest::error_reply(
+            StatusCode::BAD_REQUEST,
+            "VALIDATION_FAILED",
+            "to must be after from",
+        ));
+    }
+
+    let tours = storage
+        .get_tours_between(from, to, query.get("driverId").map(String::as_str))
+        .map_err(|e| rest::internal_error("Failed to load tours", e))?
+        .into_iter()
+        .map(|tour| with_summary(&storage, tour))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let total = |field: &str| {
+        tours
+            .iter()
+            .filter_map(|tour| tour["summary"][field].as_f64())
+            .sum::<f64>()
+    };
+    let totals = json!({
+        "tours": tours.len(),
+        "shipments": total("shipments"),
+        "totalQuantity": (total("totalQuantity") * 100.0).round() / 100.0,
+        "stops": total("stops"),
+        "distanceKm": (total("distanceKm") * 100.0).round() / 100.0
+    });
+
+    Ok(rest::json_reply(&json!({
+        "from": from,
+        "to": to,
+        "tours": tours,
+        "totals": totals
+    })))
+}
@@ -0,0 +1,126 @@
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::settings::settings_local_storage::{
+    SettingsLocalStorage, UNIT_FACTORS_KEY,
+};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+
+pub const CANONICAL_UNIT: &str = "fm";
+
+const DEFAULT_FACTORS: &[(&str, f64)] = &[("fm", 1.0), ("rm", 0.7), ("srm", 0.4)];
+
+const QUANTITY_FIELDS: &[(&str, &[&str])] = &[
+    (
+        "location_update",
+        &[
+            "initialQuantity",
+            "initialOversizeQuantity",
+            "currentQuantity",
+            "currentOversizeQuantity",
+        ],
+    ),
+    ("shipment_update", &["quantity", "oversizeQuantity"]),
+];
+
+fn parse_factors(value: &str) -> Result<Map<String, Value>, String> {
+    let factors = serde_json::from_str::<Value>(value)
+        .ok()
+        .and_then(|factors| factors.as_object().cloned())
+        .ok_or_else(|| "Unit factors must be an object of unit names".to_string())?;
+
+    for (unit, factor) in &factors {
+        if unit.is_empty()
+            || unit.len() > 16
+            || !unit
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        {
+            return Err(format!("Invalid unit name: {}", unit));
+        }
+        let factor = factor
+            .as_f64()
+            .filter(|factor| factor.is_finite() && *factor > 0.0)
+            .ok_or_else(|| format!("Factor for {} must be a positive number", unit))?;
+        if unit == CANONICAL_UNIT && factor != 1.0 {
+            return Err(format!("Factor for {} must be 1", CANONICAL_UNIT));
+        }
+    }
+
+    Ok(factors)
+}
+
+pub fn validate_factors(value: &str) -> Result<(), String> {
+    parse_factors(value).map(|_| ())
+}
+
+pub fn factors(core_storage: Arc<CoreLocalStorage>) -> Vec<(String, f64)> {
+    let mut factors: Vec<(String, f64)> = DEFAULT_FACTORS
+        .iter()
+        .map(|(unit, factor)| (unit.to_string(), *factor))
+        .collect();
+
+    let stored = SettingsLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_setting(UNIT_FACTORS_KEY));
+    match stored {
+        Ok(Some(value)) => match parse_factors(&value) {
+            Ok(overrides) => {
+                for (unit, factor) in overrides {
+                    let factor = factor.as_f64().unwrap_or(1.0);
+                    match factors.iter_mut().find(|(known, _)| *known == unit) {
+                        Some(entry) => entry.1 = factor,
+                        None => factors.push((unit, factor)),
+                    }
+                }
+            }
+            Err(e) => println!("Ignoring invalid unit factors: {}", e),
+        },
+        Ok(None) => {}
+        Err(e) => println!("Failed to load unit factors: {:?}", e),
+    }
+
+    factors
+}
+
+pub fn normalize_quantities(
+    msg_type: &str,
+    entity: &mut Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<(), String> {
+    let Some((_, fields)) = QUANTITY_FIELDS
+        .iter()
+        .find(|(message_type, _)| *message_type == msg_type)
+    else {
+        return Ok(());
+    };
+
+    let unit = match entity.get("unit") {
+        None | Some(Value::Null) => CANONICAL_UNIT.to_string(),
+        Some(Value::String(unit)) if unit.trim().is_empty() => CANONICAL_UNIT.to_string(),
+        Some(Value::String(unit)) => unit.trim().to_lowercase(),
+        Some(_) => return Err("unit must be a string".to_string()),
+    };
+    if unit == CANONICAL_UNIT {
+        entity["unit"] = json!(CANONICAL_UNIT);
+        return Ok(());
+    }
+
+    let factors = factors(core_storage);
+    let Some((_, factor)) = factors.iter().find(|(known, _)| *known == unit) else {
+        let known: Vec<&str> = factors.iter().map(|(known, _)| known.as_str()).collect();
+        return Err(format!(
+            "Unknown unit: {}, expected one of {}",
+            unit,
+            known.join(", ")
+        ));
+    };
+
+    for field in fields.iter() {
+        if let Some(quantity) = entity.get(*field).and_then(|v| v.as_f64()) {
+            entity[*field] = json!((quantity * factor * 1e6).round() / 1e6);
+        }
+    }
+    entity["unit"] = json!(CANONICAL_UNIT);
+    entity["enteredUnit"] = json!(unit);
+
+    Ok(())
+}
@@ -0,0 +1,332 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::core_table::Column;
+use crate::local_storage::schema;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+const MESSAGE_TABLES: &[(&str, &str)] = &[
+    ("assortment_update", "assortments"),
+    ("contract_update", "contracts"),
+    ("crew_update", "crews"),
+    ("location_update", "locations"),
+    ("note_update", "notes"),
+    ("photo_update", "photos"),
+    ("sawmill_price_update", "sawmillPrices"),
+    ("sawmill_update", "sawmills"),
+    ("shipment_update", "shipments"),
+    ("tour_update", "tours"),
+    ("user_update", "users"),
+];
+
+pub const SERVER_COLUMNS: &[&str] = &[
+    "arrivalAtServer",
+    "titleSearch",
+    "partieNrSearch",
+    "nameSearch",
+];
+
+const EXTRA_PROPERTIES: &[(&str, &str, Kind)] = &[
+    ("assortment_update", "sawmillIds", Kind::StringList),
+    ("crew_update", "memberIds", Kind::StringList),
+    ("location_update", "sawmillIds", Kind::StringList),
+    ("location_update", "oversizeSawmillIds", Kind::StringList),
+    ("sawmill_update", "assortmentIds", Kind::StringList),
+];
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Integer,
+    Number,
+    String,
+    Bytes,
+    StringList,
+}
+
+impl Kind {
+    fn of(column: &Column) -> Kind {
+        match column.declared_type() {
+            "INTEGER" => Kind::Integer,
+            "REAL" => Kind::Number,
+            "BLOB" => Kind::Bytes,
+            _ => Kind::String,
+        }
+    }
+
+    fn schema(self, nullable: bool) -> Value {
+        let mut schema = match self {
+            Kind::Integer => json!({ "type": ["integer", "boolean"] }),
+            Kind::Number => json!({ "type": ["number"] }),
+            Kind::String => json!({ "type": ["string"] }),
+            Kind::Bytes => json!({
+                "type": ["array"],
+                "items": { "type": "integer", "minimum": 0, "maximum": 255 }
+            }),
+            Kind::StringList => json!({ "type": ["array"], "items": { "type": "string" } }),
+        };
+        if nullable && let Some(types) = schema["type"].as_array_mut() {
+            types.push(json!("null"));
+        }
+        schema
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::Integer => "an integer",
+            Kind::Number => "a number",
+            Kind::String => "a string",
+            Kind::Bytes => "an array of bytes",
+            Kind::StringList => "an array of strings",
+        }
+    }
+
+    fn matches(self, value: &Value) -> Result<(), (Option<usize>, String)> {
+        let is_integer = |value: &Value| {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        };
+        match (self, value) {
+            (Kind::Integer, Value::Bool(_)) => Ok(()),
+            (Kind::Integer, value) if is_integer(value) => Ok(()),
+            (Kind::Number, Value::Number(_)) => Ok(()),
+            (Kind::String, Value::String(_)) => Ok(()),
+            (Kind::Bytes, Value::Array(items)) => {
+                match items
+                    .iter()
+                    .position(|item| item.as_u64().is_none_or(|byte| byte > 255))
+                {
+                    Some(index) => Err((Some(index), "must be a byte from 0 to 255".to_string())),
+                    None => Ok(()),
+                }
+            }
+            (Kind::StringList, Value::Array(items)) => {
+                match items.iter().position(|item| !item.is_string()) {
+                    Some(index) => Err((Some(index), "must be a string".to_string())),
+                    None => Ok(()),
+                }
+            }
+            _ => Err((None, format!("must be {}", self.describe()))),
+        }
+    }
+}
+
+struct Property {
+    name: &'static str,
+    kind: Kind,
+    nullable: bool,
+    required: bool,
+}
+
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(path: String, message: impl Into<String>) -> Self {
+        ValidationError {
+            path,
+            message: message.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "path": self.path, "message": self.message })
+    }
+}
+
+pub fn summarize(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("{}: {}", error.path, error.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+pub fn table_for(msg_type: &str) -> Option<&'static str> {
+    MESSAGE_TABLES
+        .iter()
+        .find(|(message, _)| *message == msg_type)
+        .map(|(_, table)| *table)
+}
+
+fn properties(msg_type: &str) -> Option<Vec<Property>> {
+    let table = table_for(msg_type).and_then(schema::table)?;
+    let defaulted = schema::defaults_for_message(msg_type)
+        .map(|defaults| defaults.fields)
+        .unwrap_or_default();
+
+    let mut properties: Vec<Property> = table
+        .columns
+        .iter()
+        .map(|column| Property {
+            name: column.name,
+            kind: Kind::of(column),
+            nullable: !column.not_null(),
+            required: column.not_null()
+                && !column.definition.contains("DEFAULT")
+                && !SERVER_COLUMNS.contains(&column.name)
+                && !defaulted.iter().any(|(field, _)| *field == column.name),
+        })
+        .collect();
+    properties.extend(
+        EXTRA_PROPERTIES
+            .iter()
+            .filter(|(message, _, _)| *message == msg_type)
+            .map(|(_, name, kind)| Property {
+                name,
+                kind: *kind,
+                nullable: true,
+                required: false,
+            }),
+    );
+
+    Some(properties)
+}
+
+pub fn schema_for(msg_type: &str) -> Option<Value> {
+    let properties = properties(msg_type)?;
+
+    let required: Vec<&str> = properties
+        .iter()
+        .filter(|property| property.required)
+        .map(|property| property.name)
+        .collect();
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|property| {
+            (
+                property.name.to_string(),
+                property.kind.schema(property.nullable),
+            )
+        })
+        .collect();
+
+    Some(json!({
+        "$schema": JSON_SCHEMA_DIALECT,
+        "$id": format!("/schemas/{}", msg_type),
+        "title": msg_type,
+        "$comment": "Required properties apply when creating an entity; updates of existing entities and deletions only need id",
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": !config::get().schema_validation.reject_unknown_fields
+    }))
+}
+
+pub fn validate(
+    msg_type: &str,
+    data: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+) -> Result<(), Vec<ValidationError>> {
+    let settings = &config::get().schema_validation;
+    if !settings.enabled {
+        return Ok(());
+    }
+    let Some(properties) = properties(msg_type) else {
+        return Ok(());
+    };
+    let Some(fields) = data.as_object() else {
+        return Err(vec![ValidationError::new(
+            String::new(),
+            "must be an object",
+        )]);
+    };
+
+    let mut errors = Vec::new();
+    for (name, value) in fields {
+        let path = format!("/{}", name);
+        let Some(property) = properties.iter().find(|property| property.name == name) else {
+            if settings.reject_unknown_fields {
+                errors.push(ValidationError::new(path, "is not a known field"));
+            }
+            continue;
+        };
+
+        if value.is_null() {
+            if !property.nullable {
+                errors.push(ValidationError::new(path, "must not be null"));
+            }
+        } else if let Err((index, message)) = property.kind.matches(value) {
+            let path = match index {
+                Some(index) => format!("{}/{}", path, index),
+                None => path,
+            };
+            errors.push(ValidationError::new(path, message));
+        }
+    }
+
+    let is_deleted = data.get("deleted").and_then(|v| v.as_i64()) == Some(1);
+    let missing: Vec<&Property> = properties
+        .iter()
+        .filter(|property| {
+            (property.required && !is_deleted || property.name == "id")
+                && !fields.contains_key(property.name)
+        })
+        .collect();
+    if !missing.is_empty() && !is_existing(msg_type, data, core_storage) {
+        errors.extend(
+            missing
+                .into_iter()
+                .map(|property| ValidationError::new(format!("/{}", property.name), "is required")),
+        );
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    metrics::increment(&format!(
+        "schema_validation_failed_total{{type=\"{}\"}}",
+        msg_type
+    ));
+    Err(errors)
+}
+
+fn is_existing(msg_type: &str, data: &Value, core_storage: Arc<CoreLocalStorage>) -> bool {
+    let (Some(table), Some(id)) = (table_for(msg_type), data.get("id").and_then(|v| v.as_str()))
+    else {
+        return false;
+    };
+
+    match core_storage.get_columns_by_id(table, id, &["id"]) {
+        Ok(existing) => !existing.is_empty(),
+        Err(e) => {
+            println!("Failed to check existing {}: {:?}", table, e);
+            false
+        }
+    }
+}
+
+pub fn route() -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    let list = warp::path!("schemas").and(warp::get()).map(|| {
+        let schemas: Map<String, Value> = MESSAGE_TABLES
+            .iter()
+            .filter_map(|(msg_type, _)| Some((msg_type.to_string(), schema_for(msg_type)?)))
+            .collect();
+        Box::new(rest::json_reply(&json!({ "schemas": schemas }))) as Box<dyn Reply>
+    });
+
+    let single = warp::path!("schemas" / String)
+        .and(warp::get())
+        .map(|name: String| rest::into_reply(download(&name)));
+
+    list.or(single).unify()
+}
+
+fn download(name: &str) -> Result<ErrorReply, ErrorReply> {
+    let msg_type = name.strip_suffix(".json").unwrap_or(name);
+    schema_for(msg_type)
+        .map(|schema| rest::json_reply(&schema))
+        .ok_or_else(|| {
+            rest::error_reply(
+                StatusCode::NOT_FOUND,
+                "NOT_FOUND",
+                &format!("No schema for {}", msg_type),
+            )
+        })
+}
@@ -0,0 +1,61 @@
+use crate::config;
+use crate::metrics;
+use rusqlite::trace::TraceEvent;
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type StatementSlot = Arc<Mutex<Option<String>>>;
+
+thread_local! {
+    static CURRENT_STATEMENT: RefCell<Option<StatementSlot>> = const { RefCell::new(None) };
+}
+
+pub fn record_statement(event: TraceEvent<'_>) {
+    if let TraceEvent::Stmt(_, sql) = event {
+        CURRENT_STATEMENT.with(|current| {
+            if let Some(slot) = current.borrow().as_ref()
+                && let Ok(mut statement) = slot.lock()
+            {
+                *statement = Some(sql.to_string());
+            }
+        });
+    }
+}
+
+pub async fn run<T: Send + 'static>(
+    operation: &str,
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, &'static str> {
+    let limit = Duration::from_millis(config::get().watchdog.message_timeout_ms);
+    let slot: StatementSlot = Arc::new(Mutex::new(None));
+    let worker_slot = slot.clone();
+
+    let task = tokio::task::spawn_blocking(move || {
+        CURRENT_STATEMENT.with(|current| *current.borrow_mut() = Some(worker_slot));
+        let result = work();
+        CURRENT_STATEMENT.with(|current| *current.borrow_mut() = None);
+        result
+    });
+
+    match tokio::time::timeout(limit, task).await {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(e)) => {
+            println!("Operation {} failed: {:?}", operation, e);
+            Err("INTERNAL")
+        }
+        Err(_) => {
+            metrics::increment("db_watchdog_timeouts_total");
+            let statement = slot
+                .lock()
+                .ok()
+                .and_then(|statement| statement.clone())
+                .unwrap_or_else(|| "<none>".to_string());
+            println!(
+                "Watchdog: {} exceeded {:?}, running statement: {}",
+                operation, limit, statement
+            );
+            Err("TIMEOUT")
+        }
+    }
+}
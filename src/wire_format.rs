@@ -0,0 +1,123 @@
+use crate::config;
+use crate::metrics;
+use crate::ws_compression;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use warp::ws::Message;
+
+pub const JSON: &str = "json";
+pub const MSGPACK: &str = "msgpack";
+
+/// Wire format a client asked for in its authentication_request
+/// (`"format": "msgpack"`). JSON stays the default.
+pub fn negotiate(data: &Value) -> &'static str {
+    if config::get().wire_format.msgpack_enabled
+        && data.get("format").and_then(|v| v.as_str()) == Some(MSGPACK)
+    {
+        MSGPACK
+    } else {
+        JSON
+    }
+}
+
+/// Frame for an outgoing message in the format the client negotiated.
+/// MessagePack maps keep their field names.
+pub fn frame(value: &Value, msgpack: &AtomicBool) -> Message {
+    if !msgpack.load(Ordering::Relaxed) {
+        return Message::text(value.to_string());
+    }
+
+    match rmp_serde::to_vec_named(value) {
+        Ok(encoded) => {
+            metrics::add("ws_msgpack_bytes_out_total", encoded.len() as u64);
+            Message::binary(encoded)
+        }
+        Err(e) => {
+            println!("Failed to encode message as MessagePack: {}", e);
+            Message::text(value.to_string())
+        }
+    }
+}
+
+/// A message sent to many clients. Each format is encoded once, the first
+/// time a client that uses it is sent the message.
+pub struct Shared {
+    value: Value,
+    text: OnceLock<String>,
+    binary: OnceLock<Option<Vec<u8>>>,
+}
+
+impl Shared {
+    pub fn new(value: Value) -> Arc<Self> {
+        Arc::new(Shared {
+            value,
+            text: OnceLock::new(),
+            binary: OnceLock::new(),
+        })
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn frame(&self, msgpack: &AtomicBool) -> Message {
+        if msgpack.load(Ordering::Relaxed)
+            && let Some(encoded) = self.binary.get_or_init(|| {
+                rmp_serde::to_vec_named(&self.value)
+                    .inspect_err(|e| println!("Failed to encode message as MessagePack: {}", e))
+                    .ok()
+            })
+        {
+            metrics::add("ws_msgpack_bytes_out_total", encoded.len() as u64);
+            return Message::binary(encoded.clone());
+        }
+
+        Message::text(self.text.get_or_init(|| self.value.to_string()).clone())
+    }
+}
+
+/// Decodes an incoming frame. Binary frames may be gzip-compressed and hold
+/// either JSON or MessagePack.
+pub fn decode(message: &Message) -> Option<Value> {
+    if let Ok(text) = message.to_str() {
+        return serde_json::from_str(text).ok();
+    }
+    if !message.is_binary() {
+        return None;
+    }
+
+    let bytes = match ws_compression::inflate(message.as_bytes()) {
+        Some(inflated) => Cow::Owned(inflated),
+        None => Cow::Borrowed(message.as_bytes()),
+    };
+    if bytes.first() == Some(&b'{') {
+        return serde_json::from_slice(&bytes).ok();
+    }
+
+    rmp_serde::from_slice(&bytes)
+        .inspect_err(|e| println!("Failed to decode MessagePack message: {}", e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn frames_decode_to_the_sent_value() {
+        let value = json!({"type": "note_update", "data": {"id": "n1", "text": "Stack 4"}});
+
+        for msgpack in [false, true] {
+            let msgpack = AtomicBool::new(msgpack);
+            assert_eq!(decode(&frame(&value, &msgpack)), Some(value.clone()));
+            assert_eq!(
+                decode(&Shared::new(value.clone()).frame(&msgpack)),
+                Some(value.clone())
+            );
+        }
+        assert!(frame(&value, &AtomicBool::new(true)).is_binary());
+    }
+}
@@ -0,0 +1,270 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::metrics;
+use crate::route_distance;
+use serde_json::Value;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+tokio::task_local! {
+    static ACTIVE: Arc<BatchScope>;
+}
+
+type Effect = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct BatchScope {
+    db_path: String,
+    storage: Arc<CoreLocalStorage>,
+    record_failed: AtomicBool,
+    outbox: Mutex<Vec<Effect>>,
+}
+
+/// A record of the batch with the messages and events it produced, which
+/// are held back until the batch is committed.
+struct Record {
+    msg_type: String,
+    id: Option<Value>,
+    failed: bool,
+    effects: Vec<Effect>,
+}
+
+pub struct WriteBatch {
+    scope: Arc<BatchScope>,
+    opened_at: Instant,
+    records: Vec<Record>,
+}
+
+pub fn is_batchable(msg_type: &str) -> bool {
+    let settings = &config::get().write_batch;
+    // Routed distances are fetched over HTTP, which must not happen while the
+    // batch holds the write lock.
+    if msg_type == "shipment_update" && route_distance::is_routed() {
+        return false;
+    }
+    settings.enabled && settings.message_types.iter().any(|t| t == msg_type)
+}
+
+pub fn burst_gap() -> Duration {
+    Duration::from_millis(config::get().write_batch.burst_gap_ms)
+}
+
+pub fn storage_for(db_path: &str) -> Option<Arc<CoreLocalStorage>> {
+    ACTIVE
+        .try_with(|scope| (scope.db_path == db_path).then(|| scope.storage.clone()))
+        .ok()
+        .flatten()
+}
+
+pub fn mark_failed() {
+    let _ = ACTIVE.try_with(|scope| scope.record_failed.store(true, Ordering::Relaxed));
+}
+
+/// Whether the current update runs in a write batch. Its confirmations,
+/// broadcasts and events must then wait for the commit, see `defer`.
+pub fn is_active() -> bool {
+    ACTIVE.try_with(|_| ()).is_ok()
+}
+
+/// Queues `effect` to run once the batch of the current update is
+/// committed. Effects of updates lost to a failed commit are dropped.
+pub fn defer<F: Future<Output = ()> + Send + 'static>(effect: F) {
+    let _ = ACTIVE.try_with(|scope| {
+        scope
+            .outbox
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::pin(effect))
+    });
+}
+
+impl WriteBatch {
+    pub fn begin(db_path: &str) -> rusqlite::Result<Self> {
+        let storage = Arc::new(CoreLocalStorage::new(db_path)?);
+        storage.begin()?;
+        metrics::increment("write_batches_opened_total");
+
+        Ok(WriteBatch {
+            scope: Arc::new(BatchScope {
+                db_path: db_path.to_string(),
+                storage,
+                record_failed: AtomicBool::new(false),
+                outbox: Mutex::new(Vec::new()),
+            }),
+            opened_at: Instant::now(),
+            records: Vec::new(),
+        })
+    }
+
+    pub fn db_path(&self) -> &str {
+        &self.scope.db_path
+    }
+
+    pub async fn run<F: Future<Output = ()>>(
+        &mut self,
+        msg_type: &str,
+        id: Option<Value>,
+        record: F,
+    ) {
+        if let Err(e) = self.scope.storage.savepoint("batch_record") {
+            println!("Failed to open batch savepoint: {:?}", e);
+        }
+        self.scope.record_failed.store(false, Ordering::Relaxed);
+
+        ACTIVE.scope(self.scope.clone(), record).await;
+
+        let failed = self.scope.record_failed.load(Ordering::Relaxed);
+        let effects = mem::take(&mut *self.scope.outbox.lock().unwrap_or_else(|e| e.into_inner()));
+        self.records.push(Record {
+            msg_type: msg_type.to_string(),
+            id,
+            failed,
+            effects,
+        });
+        if failed {
+            metrics::increment("write_batch_records_rolled_back_total");
+            if let Err(e) = self.scope.storage.rollback_to("batch_record") {
+                println!("Failed to roll back batch record: {:?}", e);
+            }
+        }
+        if let Err(e) = self.scope.storage.release("batch_record") {
+            println!("Failed to release batch savepoint: {:?}", e);
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        let settings = &config::get().write_batch;
+        self.records.len() >= settings.max_records
+            || self.opened_at.elapsed() >= Duration::from_millis(settings.max_batch_ms)
+    }
+
+    /// Commits the batch and then sends what its updates produced. If the
+    /// commit fails, only the errors of rejected updates are sent; the
+    /// accepted ones are returned as `(msg_type, id)` so their sender can be
+    /// told they were not saved.
+    pub async fn commit(self) -> Vec<(String, Option<Value>)> {
+        let elapsed = self.opened_at.elapsed();
+        let failed = self.records.iter().filter(|record| record.failed).count();
+        let committed = match self.scope.storage.commit() {
+            Ok(()) => {
+                metrics::add("write_batch_records_total", self.records.len() as u64);
+                println!(
+                    "Committed write batch of {} records ({} rolled back) to {} in {} ms",
+                    self.records.len(),
+                    failed,
+                    self.scope.db_path,
+                    elapsed.as_millis()
+                );
+                true
+            }
+            Err(e) => {
+                metrics::increment("write_batch_commit_failed_total");
+                println!(
+                    "Failed to commit write batch of {} records to {}: {:?}",
+                    self.records.len(),
+                    self.scope.db_path,
+                    e
+                );
+                false
+            }
+        };
+
+        let mut lost = Vec::new();
+        for record in self.records {
+            if committed || record.failed {
+                for effect in record.effects {
+                    effect.await;
+                }
+            } else {
+                lost.push((record.msg_type, record.id));
+            }
+        }
+        lost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::AtomicUsize;
+
+    fn db_path() -> String {
+        std::env::temp_dir()
+            .join(format!("write-batch-{}.db", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn record_sent(sent: &Arc<AtomicUsize>) {
+        let sent = sent.clone();
+        defer(async move {
+            sent.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    #[tokio::test]
+    async fn effects_wait_for_the_commit() {
+        let path = db_path();
+        let sent = Arc::new(AtomicUsize::new(0));
+        let mut batch = WriteBatch::begin(&path).unwrap();
+
+        batch
+            .run("note_update", Some(json!("n1")), async {
+                assert!(is_active());
+                record_sent(&sent);
+            })
+            .await;
+        assert!(!is_active());
+        assert_eq!(sent.load(Ordering::Relaxed), 0);
+
+        assert!(batch.commit().await.is_empty());
+        assert_eq!(sent.load(Ordering::Relaxed), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_commits_report_accepted_records_and_send_only_rejections() {
+        let path = db_path();
+        let sent = Arc::new(AtomicUsize::new(0));
+        let mut batch = WriteBatch::begin(&path).unwrap();
+
+        batch
+            .run("note_update", Some(json!("n1")), async {
+                record_sent(&sent);
+            })
+            .await;
+        batch
+            .run("note_update", Some(json!("n2")), async {
+                mark_failed();
+                record_sent(&sent);
+            })
+            .await;
+        batch
+            .run("note_update", Some(json!("n3")), async {
+                // Lose the transaction so the commit fails.
+                let storage = storage_for(&path).unwrap();
+                storage
+                    .get_connection()
+                    .unwrap()
+                    .execute_batch("ROLLBACK")
+                    .unwrap();
+                record_sent(&sent);
+            })
+            .await;
+
+        let lost = batch.commit().await;
+        assert_eq!(
+            lost,
+            vec![
+                ("note_update".to_string(), Some(json!("n1"))),
+                ("note_update".to_string(), Some(json!("n3")))
+            ]
+        );
+        assert_eq!(sent.load(Ordering::Relaxed), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use crate::config;
+use crate::local_storage::core_local_storage::CoreLocalStorage;
+use crate::local_storage::core_table::Column;
+use crate::local_storage::schema;
+use crate::local_storage::settings::settings_local_storage::{
+    SettingsLocalStorage, WRITE_VERIFICATION_KEY,
+};
+use crate::local_storage::write_verification::write_verification_local_storage::WriteVerificationLocalStorage;
+use crate::metrics;
+use crate::rest::{self, ErrorReply};
+use crate::update_schema;
+use crate::{DbPoolMap, ROLE_ADMIN, with_db_pools};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use uuid::Uuid;
+use warp::{Filter, Rejection, Reply};
+
+const FAILURE_LIMIT: i64 = 500;
+const OUTCOME_MISSING: &str = "missing";
+const OUTCOME_TOMBSTONED: &str = "tombstoned";
+const OUTCOME_SUPERSEDED: &str = "superseded";
+const OUTCOME_MISMATCH: &str = "mismatch";
+
+const SERVER_MANAGED_FIELDS: &[(&str, &str)] = &[
+    ("photo_update", "photoHash"),
+    ("photo_update", "photoFormat"),
+    ("photo_update", "photoPolicy"),
+];
+
+fn parse_settings(value: &str) -> Result<Option<f64>, String> {
+    let settings = serde_json::from_str::<Value>(value)
+        .ok()
+        .filter(Value::is_object)
+        .ok_or_else(|| "Write verification settings must be an object".to_string())?;
+
+    if settings["enabled"].as_bool() != Some(true) {
+        return Ok(None);
+    }
+    match settings.get("sampleRate") {
+        None | Some(Value::Null) => Ok(Some(config::get().write_verification.default_sample_rate)),
+        Some(rate) => rate
+            .as_f64()
+            .filter(|rate| *rate > 0.0 && *rate <= 1.0)
+            .map(Some)
+            .ok_or_else(|| "sampleRate must be above 0 and at most 1".to_string()),
+    }
+}
+
+pub fn validate_settings(value: &str) -> Result<(), String> {
+    parse_settings(value).map(|_| ())
+}
+
+fn sample_rate(core_storage: Arc<CoreLocalStorage>) -> Option<f64> {
+    let stored = SettingsLocalStorage::new(core_storage)
+        .and_then(|storage| storage.get_setting(WRITE_VERIFICATION_KEY));
+    match stored {
+        Ok(Some(value)) => parse_settings(&value).unwrap_or_else(|e| {
+            println!(
+                "Ignoring invalid {} settings: {}",
+                WRITE_VERIFICATION_KEY, e
+            );
+            None
+        }),
+        Ok(None) => None,
+        Err(e) => {
+            println!(
+                "Failed to load {} settings: {:?}",
+                WRITE_VERIFICATION_KEY, e
+            );
+            None
+        }
+    }
+}
+
+fn is_sampled(rate: f64) -> bool {
+    rate >= 1.0 || (Uuid::new_v4().as_u64_pair().0 as f64 / u64::MAX as f64) < rate
+}
+
+fn same_value(sent: &Value, stored: &Value) -> bool {
+    match (sent, stored) {
+        (Value::Bool(flag), other) | (other, Value::Bool(flag)) => {
+            other.as_i64() == Some(*flag as i64) || other.as_bool() == Some(*flag)
+        }
+        (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() <= f64::EPSILON * a.abs().max(b.abs()).max(1.0),
+            _ => a == b,
+        },
+        (sent, stored) => sent == stored,
+    }
+}
+
+fn compared_columns(msg_type: &str, payload: &Value, columns: &[Column]) -> Vec<&'static str> {
+    let is_deleted = payload["deleted"].as_i64() == Some(1);
+    columns
+        .iter()
+        .filter(|column| {
+            if is_deleted {
+                return column.name == "deleted";
+            }
+            payload.get(column.name).is_some()
+                && column.declared_type() != "BLOB"
+                && !update_schema::SERVER_COLUMNS.contains(&column.name)
+                && !SERVER_MANAGED_FIELDS.contains(&(msg_type, column.name))
+        })
+        .map(|column| column.name)
+        .collect()
+}
+
+pub fn verify(
+    msg_type: &str,
+    payload: &Value,
+    core_storage: Arc<CoreLocalStorage>,
+    user_id: Option<&str>,
+) -> Option<Value> {
+    if !config::get().write_verification.enabled {
+        return None;
+    }
+    let table = update_schema::table_for(msg_type).and_then(schema::table)?;
+    let entity_id = payload["id"].as_str()?;
+    let rate = sample_rate(core_storage.clone())?;
+    if !is_sampled(rate) {
+        return None;
+    }
+    metrics::increment(&format!(
+        "write_verification_checks_total{{type=\"{}\"}}",
+        msg_type
+    ));
+
+    let mut columns = compared_columns(msg_type, payload, table.columns);
+    for column in ["lastEdit", "deleted"] {
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    let stored = match core_storage.get_columns_by_id(table.name, entity_id, &columns) {
+        Ok(rows) => rows.into_iter().next(),
+        Err(e) => {
+            println!(
+                "Failed to re-read {} {} for verification: {:?}",
+                table.name, entity_id, e
+            );
+            return None;
+        }
+    };
+
+    let is_deleted = payload["deleted"].as_i64() == Some(1);
+    let payload_last_edit = payload["lastEdit"].as_i64();
+    let (outcome, stored_last_edit, differences) = match stored {
+        None if is_deleted => return None,
+        None => (OUTCOME_MISSING, None, Vec::new()),
+        Some(stored) => {
+            let differences: Vec<Value> = compared_columns(msg_type, payload, table.columns)
+                .into_iter()
+                .filter(|column| !same_value(&payload[*column], &stored[*column]))
+                .map(|column| {
+                    json!({
+                        "field": column,
+                        "sent": payload[column],
+                        "stored": stored[column]
+                    })
+                })
+                .collect();
+            if differences.is_empty() {
+                return None;
+            }
+
+            let stored_last_edit = stored["lastEdit"].as_i64();
+            let outcome = if !is_deleted && stored["deleted"].as_i64() == Some(1) {
+                OUTCOME_TOMBSTONED
+            } else if stored_last_edit
+                .zip(payload_last_edit)
+                .is_some_and(|(s, p)| s > p)
+            {
+                OUTCOME_SUPERSEDED
+            } else {
+                OUTCOME_MISMATCH
+            };
+            (outcome, stored_last_edit, differences)
+        }
+    };
+
+    let failure = json!({
+        "id": Uuid::new_v4().to_string(),
+        "timestamp": chrono::Utc::now().timestamp_millis(),
+        "userId": user_id,
+        "messageType": msg_type,
+        "entityId": entity_id,
+        "outcome": outcome,
+        "payloadLastEdit": payload_last_edit,
+        "storedLastEdit": stored_last_edit,
+        "differences": differences
+    });
+    metrics::increment(&format!(
+        "write_verification_failures_total{{type=\"{}\",outcome=\"{}\"}}",
+        msg_type, outcome
+    ));
+    println!(
+        "Write verification {} for {} {}: {}",
+        outcome, msg_type, entity_id, failure["differences"]
+    );
+    if let Err(e) = WriteVerificationLocalStorage::new(core_storage)
+        .and_then(|storage| storage.record_failure(&failure))
+    {
+        println!("Failed to record write verification failure: {:?}", e);
+    }
+
+    Some(failure)
+}
+
+pub fn route(
+    db_pools: DbPoolMap,
+) -> impl Filter<Extract = (Box<dyn Reply>,), Error = Rejection> + Clone {
+    warp::path!("admin" / "write-verifications")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_db_pools(db_pools))
+        .map(|authorization, db_pools: DbPoolMap| {
+            rest::into_reply(list_failures(authorization, &db_pools))
+        })
+}
+
+fn list_failures(
+    authorization: Option<String>,
+    db_pools: &DbPoolMap,
+) -> Result<ErrorReply, ErrorReply> {
+    let user = rest::authenticate(authorization, db_pools, ROLE_ADMIN)?;
+    let core_storage = rest::open_storage(&user.tenant)
+        .map_err(|e| rest::internal_error("Failed to open tenant storage", e))?;
+    let failures = WriteVerificationLocalStorage::new(core_storage.clone())
+        .and_then(|storage| storage.get_failures(FAILURE_LIMIT))
+        .map_err(|e| rest::internal_error("Failed to load write verifications", e))?;
+
+    Ok(rest::json_reply(&json!({
+        "sampleRate": sample_rate(core_storage),
+        "failures": failures
+    })))
+}
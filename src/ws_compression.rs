@@ -0,0 +1,75 @@
+use crate::config;
+use crate::metrics;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use warp::ws::Message;
+
+pub const GZIP: &str = "gzip";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+/// Compression a client asked for in its authentication_request
+/// (`"compression": "gzip"`). Returns the accepted method, if any.
+pub fn negotiate(data: &Value) -> Option<&'static str> {
+    (config::get().ws_compression.enabled
+        && data.get("compression").and_then(|v| v.as_str()) == Some(GZIP))
+    .then_some(GZIP)
+}
+
+/// Sends frames of at least min_bytes as gzip-compressed binary frames once
+/// the client negotiated compression. Everything else passes through.
+pub fn encode(message: Message, enabled: &AtomicBool) -> Message {
+    let settings = &config::get().ws_compression;
+    if !enabled.load(Ordering::Relaxed)
+        || !(message.is_text() || message.is_binary())
+        || message.as_bytes().len() < settings.min_bytes
+    {
+        return message;
+    }
+
+    let text = message.as_bytes();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(settings.level));
+    match encoder.write_all(text).and_then(|_| encoder.finish()) {
+        Ok(compressed) if compressed.len() < text.len() => {
+            metrics::add("ws_compressed_bytes_in_total", text.len() as u64);
+            metrics::add("ws_compressed_bytes_out_total", compressed.len() as u64);
+            Message::binary(compressed)
+        }
+        Ok(_) => message,
+        Err(e) => {
+            println!("Failed to compress WebSocket message: {}", e);
+            message
+        }
+    }
+}
+
+/// Inflates a gzip-compressed incoming frame. Returns None for frames that
+/// are not compressed or inflate beyond max_inflated_bytes.
+pub fn inflate(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(GZIP_MAGIC) {
+        return None;
+    }
+
+    let max_bytes = config::get().ws_compression.max_inflated_bytes;
+    let mut inflated = Vec::new();
+    match GzDecoder::new(bytes)
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut inflated)
+    {
+        Ok(len) if len <= max_bytes => Some(inflated),
+        Ok(_) => {
+            println!(
+                "Dropped compressed message inflating beyond {} bytes",
+                max_bytes
+            );
+            None
+        }
+        Err(e) => {
+            println!("Failed to inflate compressed message: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use futures_util::{SinkExt, StreamExt};
+use holz_logistik_server::test_fixture;
+use serde_json::Value;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+async fn next_json<S>(socket: &mut S) -> Value
+where
+    S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => return serde_json::from_str(&text).unwrap(),
+            Some(Ok(_)) => continue,
+            other => panic!("connection ended: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn spawns_authenticates_and_shuts_down() {
+    let server = test_fixture::spawn().await.unwrap();
+    let (mut socket, _) = connect_async(server.ws_url.as_str()).await.unwrap();
+
+    let challenge = next_json(&mut socket).await;
+    assert_eq!(challenge["type"], "authentication_challenge");
+    let nonce = challenge["data"]["nonce"].as_str().unwrap();
+
+    let request = server.authentication_request(&server.admin_api_key, nonce);
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+
+    let response = next_json(&mut socket).await;
+    assert_eq!(response["type"], "authentication_response");
+    assert_eq!(response["data"]["authenticated"], 1);
+    assert_eq!(response["dbName"], server.tenant);
+
+    let databases_dir = server.databases_dir.clone();
+    server.shutdown().await;
+    assert!(
+        std::fs::read_dir(&databases_dir)
+            .unwrap()
+            .flatten()
+            .all(|entry| !entry.path().to_string_lossy().ends_with(".db"))
+    );
+}
+
+#[tokio::test]
+async fn basic_users_cannot_raise_their_own_role() {
+    let server = test_fixture::spawn().await.unwrap();
+    let api_key = server
+        .add_user("fixture_basic", "Fixture Basic", 0)
+        .unwrap();
+    let (mut socket, _) = connect_async(server.ws_url.as_str()).await.unwrap();
+
+    let challenge = next_json(&mut socket).await;
+    let nonce = challenge["data"]["nonce"].as_str().unwrap();
+    let request = server.authentication_request(&api_key, nonce);
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    let response = next_json(&mut socket).await;
+    assert_eq!(response["data"]["authenticated"], 1);
+
+    let update = serde_json::json!({
+        "type": "user_update",
+        "data": {
+            "id": "fixture_basic",
+            "name": "Fixture Basic",
+            "role": 2,
+            "lastEdit": chrono::Utc::now().timestamp_millis(),
+            "deleted": 0
+        }
+    });
+    socket
+        .send(Message::Text(update.to_string()))
+        .await
+        .unwrap();
+
+    let error = loop {
+        let message = next_json(&mut socket).await;
+        if message["type"] == "error" {
+            break message;
+        }
+    };
+    assert_eq!(error["data"]["code"], "PERMISSION_DENIED");
+    assert_eq!(error["data"]["refType"], "user_update");
+
+    server.shutdown().await;
+}
+
+async fn authenticate(server: &test_fixture::TestServer, request: impl Fn(&str) -> Value) -> Value {
+    let (mut socket, _) = connect_async(server.ws_url.as_str()).await.unwrap();
+    let challenge = next_json(&mut socket).await;
+    let nonce = challenge["data"]["nonce"].as_str().unwrap();
+    socket
+        .send(Message::Text(request(nonce).to_string()))
+        .await
+        .unwrap();
+    next_json(&mut socket).await
+}
+
+#[tokio::test]
+async fn enrolled_devices_authenticate_with_their_secret_only() {
+    let server = test_fixture::spawn().await.unwrap();
+    let api_key = server
+        .add_user("fixture_driver", "Fixture Driver", 0)
+        .unwrap();
+    let device = Some("fixture-device");
+
+    let enrolment = authenticate(&server, |nonce| {
+        server.device_authentication_request(&api_key, device, &api_key, nonce)
+    })
+    .await;
+    assert_eq!(enrolment["data"]["authenticated"], 1);
+    assert!(enrolment["data"]["apiKey"].is_null());
+    let secret = enrolment["data"]["deviceSecret"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let with_api_key = authenticate(&server, |nonce| {
+        server.device_authentication_request(&api_key, device, &api_key, nonce)
+    })
+    .await;
+    assert_eq!(with_api_key["data"]["authenticated"], 0);
+
+    let with_secret = authenticate(&server, |nonce| {
+        server.device_authentication_request(&api_key, device, &secret, nonce)
+    })
+    .await;
+    assert_eq!(with_secret["data"]["authenticated"], 1);
+    assert!(with_secret["data"]["deviceSecret"].is_null());
+
+    let downgrade = authenticate(&server, |_| {
+        serde_json::json!({
+            "type": "authentication_request",
+            "version": 1,
+            "data": { "apiKey": api_key, "deviceId": device, "protocolVersion": 1 }
+        })
+    })
+    .await;
+    assert_eq!(downgrade["data"]["authenticated"], 0);
+    assert_eq!(
+        downgrade["data"]["error"],
+        "Device requires challenge authentication"
+    );
+
+    server.shutdown().await;
+}